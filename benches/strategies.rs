@@ -0,0 +1,166 @@
+//! Compares parsing strategies across file sizes and thread counts on deterministic sample files
+//! with criterion.
+//!
+//! `cargo bench --bench strategies` runs every benchmark, arguments after `--` are criterion's:
+//!
+//!     cargo bench --bench strategies -- sharded                 # only `sharded` benchmarks
+//!     cargo bench --bench strategies -- --save-baseline main    # keep estimates as `main`
+//!     cargo bench --bench strategies -- --baseline main         # exit with 1 on regressions
+//!     cargo bench --bench strategies -- --quick                 # fewer samples
+//!
+//! A benchmark compared with a baseline regressed if its median got slower than
+//! `BENCH_THRESHOLD` percent (10 by default).
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+use log_parser::parser::{self, ParseOptions, SampleOptions, Strategy};
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime};
+
+/// Number of lines of the generated sample files.
+const SIZES: [u32; 3] = [10_000, 100_000, 1_000_000];
+const THREADS: [u8; 4] = [1, 2, 4, 8];
+const STRATEGIES: [(&str, Strategy); 4] = [
+    ("single", Strategy::Single),
+    ("channel", Strategy::Channel),
+    ("dashmap", Strategy::Dashmap),
+    ("sharded", Strategy::Sharded),
+];
+/// Sampling of a benchmark stops after this time (at least `SAMPLES` are taken).
+const MEASUREMENT_TIME: Duration = Duration::from_secs(3);
+const SAMPLES: usize = 10;
+/// Slowdown of the median reported as regression, in percent.
+const DEFAULT_THRESHOLD: f64 = 10.0;
+
+/// Sample file with the number of lines, generated once with a fixed seed.
+fn sample_file(num_of_lines: u32) -> PathBuf {
+    let dir = target_dir().join("bench-data");
+    fs::create_dir_all(&dir).expect("Can't create bench data directory");
+    let path = dir.join(format!("sample_{}.log", num_of_lines));
+    if !path.exists() {
+        let options = SampleOptions {
+            num_of_lines,
+            num_of_log_types: 10,
+            max_msg_size: 100,
+            timestamps: None,
+            levels: vec![("info".to_string(), 95), ("error".to_string(), 5)],
+            seed: Some(42),
+            template: None,
+            target_size: None,
+        };
+        // written under another name first, so an interrupted run doesn't leave a partial file
+        let partial = path.with_extension("partial");
+        parser::prepare_sample_file(&options, partial.to_str().expect("UTF-8 path"));
+        fs::rename(&partial, &path).expect("Can't move sample file");
+    }
+    path
+}
+
+fn target_dir() -> PathBuf {
+    env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("target"))
+}
+
+/// Every strategy with every number of threads on every sample file, grouped by file size so
+/// criterion reports throughput of the file.
+fn strategies(c: &mut Criterion) {
+    let options = ParseOptions::default();
+    for num_of_lines in SIZES {
+        let path = sample_file(num_of_lines);
+        let size = fs::metadata(&path).expect("Can't read sample file").len();
+        let path = path.to_str().expect("UTF-8 path");
+
+        let mut group = c.benchmark_group(format!("parse/{}", num_of_lines));
+        group
+            .throughput(Throughput::Bytes(size))
+            .sample_size(SAMPLES)
+            .measurement_time(MEASUREMENT_TIME);
+        for (name, strategy) in STRATEGIES {
+            // single thread parser ignores the number of threads
+            let threads = match strategy {
+                Strategy::Single => &THREADS[..1],
+                _ => &THREADS[..],
+            };
+            for threads in threads {
+                group.bench_with_input(BenchmarkId::new(name, threads), threads, |b, threads| {
+                    b.iter(|| parser::parse_file(strategy, *threads, path, &options).unwrap())
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+/// Benchmarks whose median got slower than the threshold against the baseline, read from the
+/// changes criterion wrote since the run started.
+fn regressions(since: SystemTime, threshold: f64) -> Vec<String> {
+    let root = target_dir().join("criterion");
+    let mut regressions = vec![];
+    let mut dirs = vec![root.clone()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let is_change = path.ends_with("change/estimates.json");
+            let is_recent = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified >= since);
+            if !is_change || !is_recent {
+                continue;
+            }
+            let estimates: Value = fs::read_to_string(&path)
+                .ok()
+                .and_then(|text| serde_json::from_str(&text).ok())
+                .unwrap_or_default();
+            let change = estimates["median"]["point_estimate"].as_f64();
+            if change.is_some_and(|change| change * 100.0 > threshold) {
+                let id = dir
+                    .parent()
+                    .and_then(|benchmark| benchmark.strip_prefix(&root).ok())
+                    .map(|id| id.display().to_string())
+                    .unwrap_or_default();
+                regressions.push(id);
+            }
+        }
+    }
+    regressions.sort();
+    regressions
+}
+
+fn main() {
+    let started = SystemTime::now();
+    let threshold = env::var("BENCH_THRESHOLD")
+        .ok()
+        .map(|threshold| {
+            threshold
+                .parse()
+                .expect("BENCH_THRESHOLD should be a percentage, e.g. 10")
+        })
+        .unwrap_or(DEFAULT_THRESHOLD);
+
+    let mut criterion = Criterion::default().configure_from_args();
+    strategies(&mut criterion);
+    criterion.final_summary();
+
+    let regressions = regressions(started, threshold);
+    if !regressions.is_empty() {
+        eprintln!(
+            "Regressions over {}% against the baseline: {}",
+            threshold,
+            regressions.join(", ")
+        );
+        process::exit(1);
+    }
+}
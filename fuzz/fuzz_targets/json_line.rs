@@ -0,0 +1,25 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use log_parser::parser::{self, ParseOptions};
+
+fuzz_target!(|line: &str| {
+    // fast path (type and level only)
+    let _ = parser::parse_line(line, &ParseOptions::default());
+
+    // every extraction enabled
+    let options = ParseOptions {
+        numeric_fields: vec!["duration_ms".to_string()],
+        string_fields: vec!["user_id".to_string()],
+        distinct_fields: vec!["user_id".to_string()],
+        timestamp_field: Some("ts".to_string()),
+        collect_field_names: true,
+        ..ParseOptions::default()
+    };
+    if let Ok(record) = parser::parse_line(line, &options) {
+        assert!(record
+            .numeric_values
+            .iter()
+            .flatten()
+            .all(|value| value.is_finite()));
+    }
+});
@@ -0,0 +1,20 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use log_parser::scanner::{LineError, LineScanner};
+
+// small limit, so long lines are hit often
+const MAX_LINE_LEN: usize = 64;
+
+fuzz_target!(|data: &[u8]| {
+    let mut scanner = LineScanner::with_max_line_len(data, MAX_LINE_LEN);
+    let mut total_bytes = 0;
+    while let Some(line) = scanner.next_line().unwrap() {
+        assert!(line.num_of_bytes > 0);
+        if line.error != Some(LineError::TooLong) {
+            assert!(line.num_of_bytes as usize <= MAX_LINE_LEN);
+        }
+        total_bytes += line.num_of_bytes;
+    }
+    // every byte belongs to exactly one line
+    assert_eq!(total_bytes as usize, data.len());
+});
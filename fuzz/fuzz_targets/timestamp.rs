@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use log_parser::timestamp;
+
+fuzz_target!(|text: &str| {
+    if let Some(time) = timestamp::parse_str(text) {
+        let _ = timestamp::format(time);
+    }
+    if let Ok(value) = serde_json::from_str(text) {
+        let _ = timestamp::parse(&value);
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use log_parser::config::ParseConfig;
+
+fuzz_target!(|text: &str| {
+    let _ = text.parse::<toml::Table>();
+    if let Ok(config) = toml::from_str::<ParseConfig>(text) {
+        let _ = config.args();
+    }
+});
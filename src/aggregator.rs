@@ -0,0 +1,96 @@
+use crate::chunk::{self, FailedRange};
+use crate::group::Registers;
+use crate::parser::{self, LogRegister, ParseOptions, ParseReport, ParsedRecord};
+use crate::platform;
+use crate::quarantine::ChunkSummary;
+use log::debug;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io;
+use std::ops::AddAssign;
+use std::path::Path;
+use std::thread;
+
+/// Custom aggregation of parsed records (e.g. number of 5xx responses per customer) run by the
+/// parsing threads next to the built-in log register, see [`parse_file`].
+///
+/// Every part of the file is aggregated by its own copy, cloned from the aggregator given to
+/// [`parse_file`] - so it should be empty - and the copies are merged once all threads are done.
+/// Records are observed in file order within a part, but parts are merged in any order.
+pub trait Aggregator: Clone + Send {
+    fn observe(&mut self, record: &ParsedRecord);
+
+    fn merge(&mut self, other: Self);
+
+    /// Aggregated result, e.g. for sinks or the CLI output.
+    fn report(&self) -> Value;
+}
+
+/// Parse the file like [`parser::multi_thread_parser_sharded`] with the aggregator observing every
+/// parsed record, returns the report with the merged aggregator. Failed lines are not observed
+/// and a retried part starts over with a new copy of the aggregator. Checkpoints are not
+/// supported (`InvalidInput`), aggregators can't be saved.
+pub fn parse_file<A: Aggregator, P: AsRef<Path>>(
+    num_of_thread: u8,
+    input_file: P,
+    options: &ParseOptions,
+    aggregator: &A,
+) -> io::Result<(ParseReport, A)> {
+    let input_file = input_file.as_ref();
+    if options.checkpoint.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Custom aggregators can't be combined with checkpoints",
+        ));
+    }
+    let num_of_thread = num_of_thread as u64;
+
+    let file = platform::open_shared(input_file)?;
+    let size = file.metadata()?.len();
+    // the input may be a subset of the file, its real size is logged by callers
+    debug!("File size in bytes: {}", size);
+
+    let mut log_register: Registers = HashMap::new();
+    let mut merged = aggregator.clone();
+    let mut results: Vec<Result<ChunkSummary, FailedRange>> = vec![];
+    thread::scope(|scope| {
+        // keep started thread in vector
+        let mut threads = vec![];
+        for (start_idx, bytes_portion) in chunk::byte_ranges(size, num_of_thread) {
+            let end_idx = start_idx + bytes_portion;
+            let aggregator = aggregator.clone();
+
+            let handle = scope.spawn(move || {
+                chunk::run_with_retry(start_idx, end_idx, || {
+                    let mut part_aggregator = aggregator.clone();
+                    parser::read_chunk_records(
+                        start_idx,
+                        bytes_portion,
+                        input_file,
+                        options,
+                        |record| part_aggregator.observe(record),
+                    )
+                    .map(|(chunk_register, summary)| (chunk_register, summary, part_aggregator))
+                })
+            });
+
+            threads.push((start_idx, end_idx, handle));
+        }
+
+        for (start_idx, end_idx, thread) in threads {
+            match chunk::join(thread, start_idx, end_idx) {
+                Ok((chunk_register, summary, part_aggregator)) => {
+                    for (log_type, log_value) in chunk_register {
+                        let value = log_register.entry(log_type).or_insert(LogRegister::zero());
+                        value.add_assign(log_value);
+                    }
+                    merged.merge(part_aggregator);
+                    results.push(Ok(summary));
+                }
+                Err(failed_range) => results.push(Err(failed_range)),
+            }
+        }
+    });
+
+    Ok((ParseReport::new(log_register, results, options), merged))
+}
@@ -0,0 +1,319 @@
+use crate::group;
+use crate::http::{self, Url};
+use crate::parser::{LogRegister, ParseOptions, ParseReport};
+use crate::timestamp;
+use crate::units;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Aggregate a rule compares with its threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    /// `count`: number of logs.
+    Count,
+    /// `errors`: number of logs with error level (or matching `--error-when`).
+    Errors,
+    /// `error_rate`: errors per log, as a fraction or a percentage (`error_rate>5%`).
+    ErrorRate,
+    /// `bytes`: bytes of the logs, with units (`bytes>1GB`).
+    Bytes,
+    /// `window_count`: number of logs in any rate window.
+    WindowCount,
+    /// `window_errors`: number of errors in any rate window.
+    WindowErrors,
+    /// `window_error_rate`: errors per log in any rate window.
+    WindowErrorRate,
+}
+
+impl Metric {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "count" => Some(Metric::Count),
+            "errors" => Some(Metric::Errors),
+            "error_rate" => Some(Metric::ErrorRate),
+            "bytes" => Some(Metric::Bytes),
+            "window_count" => Some(Metric::WindowCount),
+            "window_errors" => Some(Metric::WindowErrors),
+            "window_error_rate" => Some(Metric::WindowErrorRate),
+            _ => None,
+        }
+    }
+
+    /// Whether the metric is evaluated per window of `--rate-window`.
+    pub fn is_windowed(&self) -> bool {
+        matches!(
+            self,
+            Metric::WindowCount | Metric::WindowErrors | Metric::WindowErrorRate
+        )
+    }
+
+    fn threshold(&self, text: &str) -> Result<f64, String> {
+        match self {
+            Metric::Bytes => units::parse_bytes(text).map(|bytes| bytes as f64),
+            Metric::ErrorRate | Metric::WindowErrorRate if text.ends_with('%') => text
+                [..text.len() - 1]
+                .parse::<f64>()
+                .map(|percent| percent / 100.0)
+                .map_err(|err| err.to_string()),
+            _ => text.parse::<f64>().map_err(|err| err.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+}
+
+impl Comparison {
+    /// Operators in the order they are searched for, longer ones first.
+    const OPERATORS: [(&'static str, Comparison); 5] = [
+        (">=", Comparison::GreaterOrEqual),
+        ("<=", Comparison::LessOrEqual),
+        ("==", Comparison::Equal),
+        (">", Comparison::Greater),
+        ("<", Comparison::Less),
+    ];
+
+    fn holds(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Greater => value > threshold,
+            Comparison::GreaterOrEqual => value >= threshold,
+            Comparison::Less => value < threshold,
+            Comparison::LessOrEqual => value <= threshold,
+            Comparison::Equal => value == threshold,
+        }
+    }
+}
+
+/// Alert rule, e.g. `type=error count>1000` or `service=api window_error_rate>=5%`: optional
+/// `<field>=<value>` selectors of log types (or groups) and a condition on the sum of their
+/// aggregates. Without selectors the condition applies to the whole report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    /// The rule as given, it names the alert.
+    pub rule: String,
+    pub selectors: Vec<(String, String)>,
+    pub metric: Metric,
+    pub comparison: Comparison,
+    pub threshold: f64,
+}
+
+impl AlertRule {
+    /// Parse rule in `[<field>=<value> ...] <metric><operator><threshold>` format.
+    pub fn parse(rule: &str) -> Result<Self, String> {
+        let mut tokens: Vec<&str> = rule.split_whitespace().collect();
+        let condition = tokens
+            .pop()
+            .ok_or_else(|| "Alert rule is empty".to_string())?;
+        let (idx, operator, comparison) = Comparison::OPERATORS
+            .iter()
+            .filter_map(|(operator, comparison)| {
+                condition
+                    .find(operator)
+                    .map(|idx| (idx, *operator, *comparison))
+            })
+            .min_by_key(|(idx, _, _)| *idx)
+            .ok_or_else(|| {
+                format!(
+                    "Alert rule should end with <metric><operator><threshold>, e.g. count>1000: [{}]",
+                    rule
+                )
+            })?;
+        let name = &condition[..idx];
+        let metric = Metric::parse(name).ok_or_else(|| {
+            format!(
+                "Unknown alert metric [{}] in [{}], use count, errors, error_rate, bytes, window_count, window_errors or window_error_rate",
+                name, rule
+            )
+        })?;
+        let threshold = metric
+            .threshold(&condition[idx + operator.len()..])
+            .map_err(|err| format!("Invalid alert threshold in [{}]: {}", rule, err))?;
+
+        let selectors = tokens
+            .iter()
+            .map(|selector| match selector.split_once('=') {
+                Some((field, value)) if !field.is_empty() => {
+                    Ok((field.to_string(), value.to_string()))
+                }
+                _ => Err(format!(
+                    "Alert selector should be <field>=<value>: [{}] in [{}]",
+                    selector, rule
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            rule: rule.trim().to_string(),
+            selectors,
+            metric,
+            comparison,
+            threshold,
+        })
+    }
+
+    /// Check the rule can be evaluated with the options: selected fields are the log type
+    /// (`type`) or group-by fields, windowed metrics need rate windows.
+    pub fn validate(&self, options: &ParseOptions) -> Result<(), String> {
+        for (field, _) in &self.selectors {
+            let known = match options.group_by.is_empty() {
+                true => field == "type",
+                false => options.group_by.contains(field),
+            };
+            if !known {
+                return Err(format!(
+                    "Alert [{}] selects by {}, which isn't the log type (type) or a --group-by field",
+                    self.rule, field
+                ));
+            }
+        }
+        if self.metric.is_windowed() && options.rate_window.is_none() {
+            return Err(format!(
+                "Alert [{}] needs --rate-window for its window metric",
+                self.rule
+            ));
+        }
+        Ok(())
+    }
+
+    fn selects(&self, key: &str, options: &ParseOptions) -> bool {
+        if options.group_by.is_empty() {
+            return self.selectors.iter().all(|(_, value)| value == key);
+        }
+        let values = group::values(key, options.group_by.len());
+        self.selectors.iter().all(|(field, value)| {
+            options
+                .group_by
+                .iter()
+                .position(|group_by| group_by == field)
+                .and_then(|idx| values.get(idx))
+                .is_some_and(|group_value| group_value == value)
+        })
+    }
+
+    /// Evaluate the rule against the report, the alert if it fires. Windowed metrics fire on
+    /// the first window satisfying the condition.
+    pub fn evaluate(&self, report: &ParseReport, options: &ParseOptions) -> Option<Alert> {
+        let registers: Vec<&LogRegister> = report
+            .log_register
+            .iter()
+            .filter(|(key, _)| self.selects(key, options))
+            .map(|(_, register)| register)
+            .collect();
+        let rate = |errors: u64, lines: u64| match lines {
+            0 => 0.0,
+            lines => errors as f64 / lines as f64,
+        };
+
+        let (value, window) = if self.metric.is_windowed() {
+            let mut windows: BTreeMap<u64, (u64, u64)> = BTreeMap::new();
+            for register in &registers {
+                for (start, count) in &register.windows {
+                    let total = windows.entry(*start).or_default();
+                    total.0 += count.lines;
+                    total.1 += count.errors;
+                }
+            }
+            windows
+                .into_iter()
+                .map(|(start, (lines, errors))| {
+                    let value = match self.metric {
+                        Metric::WindowCount => lines as f64,
+                        Metric::WindowErrors => errors as f64,
+                        _ => rate(errors, lines),
+                    };
+                    (value, Some(start))
+                })
+                .find(|(value, _)| self.comparison.holds(*value, self.threshold))?
+        } else {
+            let sum = |value: fn(&LogRegister) -> u64| -> u64 {
+                registers.iter().map(|register| value(register)).sum()
+            };
+            let value = match self.metric {
+                Metric::Count => sum(|register| register.counter) as f64,
+                Metric::Errors => sum(|register| register.errors) as f64,
+                Metric::Bytes => sum(|register| register.num_of_bytes) as f64,
+                _ => rate(
+                    sum(|register| register.errors),
+                    sum(|register| register.counter),
+                ),
+            };
+            if !self.comparison.holds(value, self.threshold) {
+                return None;
+            }
+            (value, None)
+        };
+
+        Some(Alert {
+            rule: self.rule.clone(),
+            value,
+            threshold: self.threshold,
+            window: window.map(|start| timestamp::format(UNIX_EPOCH + Duration::from_secs(start))),
+        })
+    }
+}
+
+/// Rule which fired, with the value which fired it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub rule: String,
+    pub value: f64,
+    pub threshold: f64,
+    /// Start of the first window firing a windowed rule.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<String>,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Alert [{}] fired with value {}", self.rule, self.value)?;
+        if let Some(window) = &self.window {
+            write!(f, " in window starting {}", window)?;
+        }
+        Ok(())
+    }
+}
+
+/// Evaluate every rule, alerts of the rules which fired in the order of the rules.
+pub fn evaluate(rules: &[AlertRule], report: &ParseReport, options: &ParseOptions) -> Vec<Alert> {
+    rules
+        .iter()
+        .filter_map(|rule| rule.evaluate(report, options))
+        .collect()
+}
+
+/// POST fired alerts to a webhook as a JSON document with the input and line counts, created
+/// at the time of the clock of the options.
+pub fn notify(
+    url: &str,
+    input: &str,
+    report: &ParseReport,
+    alerts: &[Alert],
+    options: &ParseOptions,
+) -> io::Result<()> {
+    let url = Url::parse(url).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let body = json!({
+        "created_at": timestamp::format(options.clock().now()),
+        "input": input,
+        "lines": report.lines,
+        "failed_lines": report.failed_lines,
+        "alerts": alerts,
+    });
+    let response = http::post(&url, "application/json", body.to_string().as_bytes(), &[])?;
+    if !response.is_success() {
+        return Err(io::Error::other(format!(
+            "Webhook answered with status {}",
+            response.status
+        )));
+    }
+    Ok(())
+}
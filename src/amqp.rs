@@ -0,0 +1,434 @@
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Default port of AMQP brokers.
+pub const DEFAULT_PORT: u16 = 5672;
+
+/// Timeout of the handshake and of writes.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Channel every command is sent on, a consumer needs only one.
+const CHANNEL: u16 = 1;
+
+const FRAME_METHOD: u8 = 1;
+const FRAME_HEADER: u8 = 2;
+const FRAME_BODY: u8 = 3;
+const FRAME_HEARTBEAT: u8 = 8;
+const FRAME_END: u8 = 0xce;
+
+/// Class and method ids of the AMQP 0-9-1 methods used by the consumer.
+const CONNECTION_START: (u16, u16) = (10, 10);
+const CONNECTION_START_OK: (u16, u16) = (10, 11);
+const CONNECTION_TUNE: (u16, u16) = (10, 30);
+const CONNECTION_TUNE_OK: (u16, u16) = (10, 31);
+const CONNECTION_OPEN: (u16, u16) = (10, 40);
+const CONNECTION_OPEN_OK: (u16, u16) = (10, 41);
+const CONNECTION_CLOSE: (u16, u16) = (10, 50);
+const CHANNEL_OPEN: (u16, u16) = (20, 10);
+const CHANNEL_OPEN_OK: (u16, u16) = (20, 11);
+const CHANNEL_CLOSE: (u16, u16) = (20, 40);
+const BASIC_QOS: (u16, u16) = (60, 10);
+const BASIC_QOS_OK: (u16, u16) = (60, 11);
+const BASIC_CONSUME: (u16, u16) = (60, 20);
+const BASIC_CONSUME_OK: (u16, u16) = (60, 21);
+const BASIC_CANCEL: (u16, u16) = (60, 30);
+const BASIC_DELIVER: (u16, u16) = (60, 60);
+const BASIC_ACK: (u16, u16) = (60, 80);
+
+/// `amqp://[user:password@]host[:port][/vhost]` URL, credentials default to `guest:guest` and
+/// the virtual host to `/` (given as `%2f` in the URL).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmqpUrl {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub password: String,
+    pub vhost: String,
+}
+
+impl AmqpUrl {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("amqp://")
+            .ok_or_else(|| format!("URL should start with amqp://: [{}]", url))?;
+        let (authority, vhost) = match rest.split_once('/') {
+            Some((authority, vhost)) if !vhost.is_empty() => {
+                (authority, vhost.replace("%2f", "/").replace("%2F", "/"))
+            }
+            Some((authority, _)) => (authority, "/".to_string()),
+            None => (rest, "/".to_string()),
+        };
+        let (user, password, address) = match authority.rsplit_once('@') {
+            Some((credentials, address)) => match credentials.split_once(':') {
+                Some((user, password)) => (user, password, address),
+                None => (credentials, "", address),
+            },
+            None => ("guest", "guest", authority),
+        };
+        let (host, port) = match address.rfind(':') {
+            Some(idx) => (
+                &address[..idx],
+                address[idx + 1..]
+                    .parse()
+                    .map_err(|_| format!("Invalid port in URL: [{}]", url))?,
+            ),
+            None => (address, DEFAULT_PORT),
+        };
+        if host.is_empty() {
+            return Err(format!("URL without host: [{}]", url));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            user: user.to_string(),
+            password: password.to_string(),
+            vhost,
+        })
+    }
+}
+
+/// Message delivered from the queue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    /// Tag the delivery is acked with.
+    pub delivery_tag: u64,
+    pub routing_key: String,
+    /// Whether the message was delivered before without being acked.
+    pub redelivered: bool,
+    pub body: Vec<u8>,
+}
+
+/// Consumer of a queue speaking AMQP 0-9-1 (no TLS, PLAIN authentication). At most `prefetch`
+/// messages are delivered without being acked, so unacked messages are redelivered to another
+/// consumer when the parser stops. Frames are read by a background thread, which answers
+/// heartbeats too.
+pub struct Consumer {
+    writer: Arc<Mutex<BufWriter<TcpStream>>>,
+    deliveries: Receiver<io::Result<Delivery>>,
+}
+
+impl Consumer {
+    /// Connect and start consuming the existing queue.
+    pub fn connect(url: &AmqpUrl, queue: &str, prefetch: u16) -> io::Result<Self> {
+        let stream = TcpStream::connect((url.host.as_str(), url.port))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream.try_clone()?);
+
+        writer.write_all(b"AMQP\x00\x00\x09\x01")?;
+        writer.flush()?;
+        expect_method(&mut reader, CONNECTION_START)?;
+        let mut response = vec![0];
+        response.extend(url.user.as_bytes());
+        response.push(0);
+        response.extend(url.password.as_bytes());
+        let mut args = vec![];
+        // client properties
+        put_table(&mut args, &[("product", "log-parser")]);
+        put_short_str(&mut args, "PLAIN");
+        put_long_str(&mut args, &response);
+        put_short_str(&mut args, "en_US");
+        write_method(&mut writer, 0, CONNECTION_START_OK, &args)?;
+
+        // broker rejects wrong credentials by closing the connection
+        let mut tune = Args::new(expect_method(&mut reader, CONNECTION_TUNE)?);
+        let channel_max = tune.u16()?;
+        let frame_max = tune.u32()?;
+        let heartbeat = tune.u16()?;
+        let mut args = vec![];
+        args.extend(&channel_max.to_be_bytes());
+        args.extend(&frame_max.to_be_bytes());
+        // heartbeats of the broker are answered, which keeps the connection alive
+        args.extend(&heartbeat.to_be_bytes());
+        write_method(&mut writer, 0, CONNECTION_TUNE_OK, &args)?;
+
+        let mut args = vec![];
+        put_short_str(&mut args, &url.vhost);
+        put_short_str(&mut args, "");
+        args.push(0);
+        write_method(&mut writer, 0, CONNECTION_OPEN, &args)?;
+        expect_method(&mut reader, CONNECTION_OPEN_OK)?;
+
+        let mut args = vec![];
+        put_short_str(&mut args, "");
+        write_method(&mut writer, CHANNEL, CHANNEL_OPEN, &args)?;
+        expect_method(&mut reader, CHANNEL_OPEN_OK)?;
+
+        let mut args = vec![];
+        args.extend(&0u32.to_be_bytes());
+        args.extend(&prefetch.max(1).to_be_bytes());
+        args.push(0);
+        write_method(&mut writer, CHANNEL, BASIC_QOS, &args)?;
+        expect_method(&mut reader, BASIC_QOS_OK)?;
+
+        let mut args = vec![];
+        args.extend(&0u16.to_be_bytes());
+        put_short_str(&mut args, queue);
+        // tag generated by the broker, messages are acked explicitly
+        put_short_str(&mut args, "");
+        args.push(0);
+        put_table(&mut args, &[]);
+        write_method(&mut writer, CHANNEL, BASIC_CONSUME, &args)?;
+        expect_method(&mut reader, BASIC_CONSUME_OK)?;
+        stream.set_read_timeout(None)?;
+
+        let writer = Arc::new(Mutex::new(writer));
+        let (sender, deliveries) = crossbeam_channel::unbounded();
+        let heartbeat_writer = writer.clone();
+        thread::spawn(move || {
+            if let Err(err) = read_deliveries(&mut reader, &heartbeat_writer, &sender) {
+                let _ = sender.send(Err(err));
+            }
+        });
+
+        Ok(Self { writer, deliveries })
+    }
+
+    /// Next delivery, `None` if none arrived within the timeout.
+    pub fn next_delivery(&self, timeout: Duration) -> io::Result<Option<Delivery>> {
+        match self.deliveries.recv_timeout(timeout) {
+            Ok(delivery) => delivery.map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "AMQP connection is closed",
+            )),
+        }
+    }
+
+    /// Acknowledge the delivery, so the message is removed from the queue.
+    pub fn ack(&self, delivery: &Delivery) -> io::Result<()> {
+        let mut args = delivery.delivery_tag.to_be_bytes().to_vec();
+        // not multiple
+        args.push(0);
+        let mut writer = self.writer.lock().expect("AMQP writer lock is poisoned");
+        write_method(&mut *writer, CHANNEL, BASIC_ACK, &args)
+    }
+}
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Arguments of a method, read in order.
+struct Args {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl Args {
+    fn new(data: Vec<u8>) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&[u8]> {
+        if self.pos + len > self.data.len() {
+            return Err(protocol_error("Truncated method arguments".to_string()));
+        }
+        self.pos += len;
+        Ok(&self.data[self.pos - len..self.pos])
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn short_str(&mut self) -> io::Result<String> {
+        let len = self.u8()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+}
+
+fn put_short_str(buf: &mut Vec<u8>, value: &str) {
+    buf.push(value.len().min(255) as u8);
+    buf.extend(&value.as_bytes()[..value.len().min(255)]);
+}
+
+fn put_long_str(buf: &mut Vec<u8>, value: &[u8]) {
+    buf.extend(&(value.len() as u32).to_be_bytes());
+    buf.extend(value);
+}
+
+/// Field table of string values.
+fn put_table(buf: &mut Vec<u8>, fields: &[(&str, &str)]) {
+    let mut table = vec![];
+    for (name, value) in fields {
+        put_short_str(&mut table, name);
+        table.push(b'S');
+        put_long_str(&mut table, value.as_bytes());
+    }
+    put_long_str(buf, &table);
+}
+
+fn write_frame<W: Write>(writer: &mut W, kind: u8, channel: u16, payload: &[u8]) -> io::Result<()> {
+    writer.write_all(&[kind])?;
+    writer.write_all(&channel.to_be_bytes())?;
+    writer.write_all(&(payload.len() as u32).to_be_bytes())?;
+    writer.write_all(payload)?;
+    writer.write_all(&[FRAME_END])?;
+    writer.flush()
+}
+
+fn write_method<W: Write>(
+    writer: &mut W,
+    channel: u16,
+    (class, method): (u16, u16),
+    args: &[u8],
+) -> io::Result<()> {
+    let mut payload = Vec::with_capacity(4 + args.len());
+    payload.extend(&class.to_be_bytes());
+    payload.extend(&method.to_be_bytes());
+    payload.extend(args);
+    write_frame(writer, FRAME_METHOD, channel, &payload)
+}
+
+/// Read frame as its kind and payload, end of stream is an error.
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0; 7];
+    reader
+        .read_exact(&mut header)
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => io::Error::new(err.kind(), "AMQP connection is closed"),
+            _ => err,
+        })?;
+    let size = u32::from_be_bytes([header[3], header[4], header[5], header[6]]) as usize;
+    let mut payload = vec![0; size + 1];
+    reader.read_exact(&mut payload)?;
+    if payload.pop() != Some(FRAME_END) {
+        return Err(protocol_error("Frame without end marker".to_string()));
+    }
+
+    Ok((header[0], payload))
+}
+
+/// Method id and arguments of a method frame payload.
+fn method(payload: Vec<u8>) -> io::Result<((u16, u16), Args)> {
+    let mut args = Args::new(payload);
+    let id = (args.u16()?, args.u16()?);
+    Ok((id, args))
+}
+
+/// Error of a close method sent by the broker, e.g. when the queue doesn't exist.
+fn close_error(mut args: Args) -> io::Error {
+    let code = args.u16().unwrap_or_default();
+    let text = args.short_str().unwrap_or_default();
+    protocol_error(format!("Broker closed the connection: {} {}", code, text))
+}
+
+/// Read until the method of the handshake, returning its arguments.
+fn expect_method<R: Read>(reader: &mut R, expected: (u16, u16)) -> io::Result<Vec<u8>> {
+    loop {
+        let (kind, payload) = read_frame(reader)?;
+        if kind != FRAME_METHOD {
+            continue;
+        }
+        let (id, args) = method(payload)?;
+        if id == expected {
+            return Ok(args.data[args.pos..].to_vec());
+        }
+        if id == CONNECTION_CLOSE || id == CHANNEL_CLOSE {
+            return Err(close_error(args));
+        }
+        return Err(protocol_error(format!(
+            "Expected method {:?}, got {:?}",
+            expected, id
+        )));
+    }
+}
+
+/// Read deliveries (a deliver method, a content header and body frames) until the connection
+/// fails or is closed, answering heartbeats on the way.
+fn read_deliveries<R: Read>(
+    reader: &mut R,
+    writer: &Mutex<BufWriter<TcpStream>>,
+    sender: &Sender<io::Result<Delivery>>,
+) -> io::Result<()> {
+    // delivery and its body size, known once the content header is read
+    let mut pending: Option<(Delivery, Option<usize>)> = None;
+    loop {
+        let (kind, payload) = read_frame(reader)?;
+        match kind {
+            FRAME_HEARTBEAT => {
+                let mut writer = writer.lock().expect("AMQP writer lock is poisoned");
+                write_frame(&mut *writer, FRAME_HEARTBEAT, 0, &[])?;
+            }
+            FRAME_METHOD => {
+                let (id, mut args) = method(payload)?;
+                match id {
+                    BASIC_DELIVER => {
+                        let _consumer_tag = args.short_str()?;
+                        let delivery_tag = args.u64()?;
+                        let redelivered = args.u8()? & 1 == 1;
+                        let _exchange = args.short_str()?;
+                        let routing_key = args.short_str()?;
+                        let delivery = Delivery {
+                            delivery_tag,
+                            routing_key,
+                            redelivered,
+                            body: vec![],
+                        };
+                        pending = Some((delivery, None));
+                    }
+                    CONNECTION_CLOSE | CHANNEL_CLOSE => return Err(close_error(args)),
+                    BASIC_CANCEL => {
+                        return Err(protocol_error(
+                            "Broker cancelled the consumer, e.g. the queue was deleted".to_string(),
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+            // class id, weight, body size and properties (not used)
+            FRAME_HEADER => {
+                let mut args = Args::new(payload);
+                args.take(4)?;
+                let size = args.u64()? as usize;
+                if let Some((delivery, body_size)) = pending.as_mut() {
+                    *body_size = Some(size);
+                    delivery.body.reserve(size);
+                }
+            }
+            FRAME_BODY => {
+                if let Some((delivery, _)) = pending.as_mut() {
+                    delivery.body.extend(payload);
+                }
+            }
+            _ => {}
+        }
+
+        let complete = match &pending {
+            // deliveries with an empty body have no body frames
+            Some((delivery, Some(size))) => delivery.body.len() >= *size,
+            _ => false,
+        };
+        if complete {
+            let (delivery, _) = pending.take().expect("Delivery is pending");
+            if sender.send(Ok(delivery)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use crate::error_log;
+use crate::platform;
+use regex::{Captures, Regex};
+use serde_json::{Error, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+const EMAIL_PATTERN: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}";
+const UUID_PATTERN: &str =
+    r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b";
+const IPV4_PATTERN: &str = r"\b(?:\d{1,3}\.){3}\d{1,3}\b";
+// at least four groups (or `::`) so times like 10:00:01 are not treated as addresses
+const IPV6_PATTERN: &str = r"\b(?:(?:[0-9a-fA-F]{1,4}:){3,7}[0-9a-fA-F]{1,4}|(?:[0-9a-fA-F]{1,4}:){1,6}:[0-9a-fA-F]{1,4}(?::[0-9a-fA-F]{1,4}){0,5})\b";
+
+/// Consistent pseudonyms of a single kind of values, the same value always gets the same
+/// pseudonym, so cardinality is preserved.
+#[derive(Default)]
+struct Pseudonyms {
+    mapping: HashMap<String, u64>,
+}
+
+impl Pseudonyms {
+    /// Sequence number of the value (assigned with the first occurrence).
+    fn index(&mut self, value: &str) -> u64 {
+        let next = self.mapping.len() as u64;
+        *self.mapping.entry(value.to_string()).or_insert(next)
+    }
+}
+
+/// Replaces sensitive values (emails, IP addresses, UUIDs and values of configured fields)
+/// with consistent pseudonyms, keeping structure of logs and the rest of values (timestamps
+/// included) untouched.
+///
+/// Pseudonyms are shaped like the original values (`user1@example.com`, `10.0.0.1`, random
+/// UUIDs, ...) and don't depend on them, the mapping is kept only in memory.
+pub struct Anonymizer {
+    fields: HashSet<String>,
+    email: Regex,
+    uuid: Regex,
+    ipv4: Regex,
+    ipv6: Regex,
+    emails: Pseudonyms,
+    uuids: HashMap<String, String>,
+    ipv4s: Pseudonyms,
+    ipv6s: Pseudonyms,
+    field_values: HashMap<String, Pseudonyms>,
+}
+
+impl Anonymizer {
+    /// Values of given fields are replaced as a whole (at any nesting level).
+    pub fn new(fields: Vec<String>) -> Self {
+        Self {
+            fields: fields.into_iter().collect(),
+            email: Regex::new(EMAIL_PATTERN).expect("Invalid email pattern"),
+            uuid: Regex::new(UUID_PATTERN).expect("Invalid UUID pattern"),
+            ipv4: Regex::new(IPV4_PATTERN).expect("Invalid IPv4 pattern"),
+            ipv6: Regex::new(IPV6_PATTERN).expect("Invalid IPv6 pattern"),
+            emails: Pseudonyms::default(),
+            uuids: HashMap::new(),
+            ipv4s: Pseudonyms::default(),
+            ipv6s: Pseudonyms::default(),
+            field_values: HashMap::new(),
+        }
+    }
+
+    /// Anonymize single JSON line.
+    pub fn anonymize_line(&mut self, line: &str) -> Result<String, Error> {
+        let mut value: Value = serde_json::from_str(line)?;
+        self.anonymize_value(None, &mut value);
+        serde_json::to_string(&value)
+    }
+
+    fn anonymize_value(&mut self, field: Option<&str>, value: &mut Value) {
+        if let Some(field) = field {
+            if self.fields.contains(field) {
+                let pseudonyms = self.field_values.entry(field.to_string()).or_default();
+                match value {
+                    Value::String(text) => {
+                        *text = format!("{}-{}", field, pseudonyms.index(text) + 1)
+                    }
+                    Value::Number(number) => {
+                        *value = Value::from(pseudonyms.index(&number.to_string()) + 1)
+                    }
+                    _ => {}
+                }
+                return;
+            }
+        }
+
+        match value {
+            Value::String(text) => *text = self.anonymize_text(text),
+            Value::Array(values) => values
+                .iter_mut()
+                .for_each(|value| self.anonymize_value(field, value)),
+            Value::Object(object) => object
+                .iter_mut()
+                .for_each(|(field, value)| self.anonymize_value(Some(field), value)),
+            _ => {}
+        }
+    }
+
+    /// Replace sensitive values inside free text.
+    fn anonymize_text(&mut self, text: &str) -> String {
+        let emails = &mut self.emails;
+        let text = self.email.replace_all(text, |caps: &Captures| {
+            format!("user{}@example.com", emails.index(&caps[0]) + 1)
+        });
+
+        let uuids = &mut self.uuids;
+        let text = self.uuid.replace_all(&text, |caps: &Captures| {
+            uuids
+                .entry(caps[0].to_lowercase())
+                .or_insert_with(|| Uuid::new_v4().to_string())
+                .clone()
+        });
+
+        let ipv6s = &mut self.ipv6s;
+        let text = self.ipv6.replace_all(&text, |caps: &Captures| {
+            format!("fd00::{:x}", ipv6s.index(&caps[0].to_lowercase()) + 1)
+        });
+
+        let ipv4s = &mut self.ipv4s;
+        let text = self.ipv4.replace_all(&text, |caps: &Captures| {
+            let address = &caps[0];
+            if address.split('.').any(|octet| octet.parse::<u8>().is_err()) {
+                // not an address, e.g. version number
+                return address.to_string();
+            }
+            let idx = ipv4s.index(address) + 1;
+            format!(
+                "10.{}.{}.{}",
+                (idx >> 16) & 255,
+                (idx >> 8) & 255,
+                idx & 255
+            )
+        });
+
+        text.into_owned()
+    }
+}
+
+/// Summary of anonymized file.
+#[derive(Debug, Default)]
+pub struct AnonymizeSummary {
+    pub lines: u64,
+    pub failed_lines: u64,
+}
+
+/// Anonymize every line of the input file into the output file (in the same order).
+/// Lines which aren't JSON are dropped, they can't be anonymized safely.
+pub fn anonymize_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input_file: P,
+    output_file: Q,
+    anonymizer: &mut Anonymizer,
+) -> io::Result<AnonymizeSummary> {
+    let file = platform::open_shared(input_file)?;
+    let mut buffered = BufReader::new(file);
+    let output = File::create(output_file)?;
+    let mut writer = BufWriter::new(output);
+
+    let mut summary = AnonymizeSummary::default();
+    let mut bytes = vec![];
+    while buffered.read_until(b'\n', &mut bytes)? > 0 {
+        summary.lines += 1;
+        // invalid UTF-8 is replaced, so a bad byte fails the line only
+        let line = String::from_utf8_lossy(&bytes);
+        match anonymizer.anonymize_line(&line) {
+            Ok(anonymized) => {
+                writer.write_all(anonymized.as_bytes())?;
+                writer.write_all(b"\n")?;
+            }
+            Err(err) => {
+                error_log::log(&line, &err);
+                summary.failed_lines += 1;
+            }
+        }
+
+        // clear to reuse the buffer
+        bytes.clear();
+    }
+
+    writer.flush()?;
+    Ok(summary)
+}
@@ -0,0 +1,65 @@
+use crate::parser::{self, ParseOptions, ParseReport, ParsedRecord};
+use std::io;
+use std::panic;
+use std::path::Path;
+use tokio::fs;
+use tokio::sync::mpsc;
+use tokio::task::{self, JoinError};
+
+/// Records buffered by [`stream_async`] before the parser waits for the receiver.
+const STREAM_BUFFER_SIZE: usize = 1024;
+
+/// Async variant of the channel parser on Tokio.
+///
+/// The file is opened with `tokio::fs`, so a missing or unreadable one fails without taking a
+/// blocking thread. Parsing runs on the blocking pool of the runtime
+/// (`tokio::task::spawn_blocking`), so awaiting it never blocks the executor threads. It has to be
+/// awaited within a Tokio runtime.
+pub async fn parse_async<P: AsRef<Path>>(
+    num_of_thread: u8,
+    input_file: P,
+    options: &ParseOptions,
+) -> io::Result<ParseReport> {
+    let input_file = input_file.as_ref().to_path_buf();
+    fs::File::open(&input_file).await?;
+
+    let options = options.clone();
+    task::spawn_blocking(move || {
+        parser::multi_thread_parser_channel(num_of_thread, &input_file, &options)
+    })
+    .await
+    .unwrap_or_else(joined)
+}
+
+/// Records of the file parsed like [`parser::stream`], received from an async channel. Parsing
+/// runs on the blocking pool of the runtime and stops once the receiver is dropped.
+pub async fn stream_async<P: AsRef<Path>>(
+    num_of_thread: u8,
+    input_file: P,
+    options: &ParseOptions,
+) -> io::Result<mpsc::Receiver<io::Result<ParsedRecord>>> {
+    let input_file = input_file.as_ref().to_path_buf();
+    fs::File::open(&input_file).await?;
+
+    let options = options.clone();
+    let records = task::spawn_blocking(move || parser::stream(num_of_thread, input_file, &options))
+        .await
+        .unwrap_or_else(joined)?;
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER_SIZE);
+    task::spawn_blocking(move || {
+        for record in records {
+            if tx.blocking_send(record).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(rx)
+}
+
+/// Result of a blocking task which didn't finish, panics are passed on to the awaiting task.
+fn joined<T>(err: JoinError) -> io::Result<T> {
+    match err.try_into_panic() {
+        Ok(panic) => panic::resume_unwind(panic),
+        Err(err) => Err(io::Error::other(err)),
+    }
+}
@@ -0,0 +1,472 @@
+use crate::args::{
+    dedup, is_local_file, num_of_threads, numeric_arg, open_input, pattern, progress,
+    record_delimiter, values_of,
+};
+use crate::EXIT_TOO_MANY_ERRORS;
+use clap::ArgMatches;
+use log_parser::aggregator::{self, Aggregator};
+use log_parser::cardinality::FieldProfiler;
+use log_parser::drift::{DriftDetector, ExpectedSchema};
+use log_parser::fingerprint::FleetFingerprinter;
+use log_parser::index::{self, Index};
+use log_parser::parser;
+use log_parser::parser::ParseOptions;
+use log_parser::progress::ProgressBar;
+use log_parser::signing;
+use log_parser::slo::{Slo, SloTracker};
+use log_parser::validate::{self, JsonSchema};
+use log_parser::{diagnostics, table, timestamp, units};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
+
+pub(crate) fn drift(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required so we can unwrap
+    let input = open_input(matches.value_of_os("input").unwrap())?;
+    let input = input.path();
+    let num_of_threads = num_of_threads(matches);
+    let schema = ExpectedSchema::load(matches.value_of("schema").unwrap())?;
+    let options = ParseOptions {
+        timestamp_field: matches.value_of("timestamp-field").map(String::from),
+        collect_field_names: true,
+        progress: progress(matches, input)?,
+        ..ParseOptions::default()
+    };
+
+    diagnostics::install(input, matches.value_of("config"));
+
+    info!("Detecting drift...");
+
+    let now = Instant::now();
+
+    let mut detector = DriftDetector::new(schema);
+    let progress_bar = options.progress.clone().map(ProgressBar::start);
+    let records = parser::stream(num_of_threads, input, &options)
+        .map_err(|err| format!("Can't read {}: {}", input.display(), err))?;
+    for record in records {
+        match record {
+            Ok(record) => detector.observe(&record),
+            Err(err) => error!("{}", err),
+        }
+    }
+    if let Some(progress_bar) = progress_bar {
+        progress_bar.finish();
+    }
+
+    let unexpected = detector.report();
+    unexpected.iter().for_each(|field| {
+        info!(
+            "unexpected field: {}, count: {}, log_types: {:?}, first_offset: {}, first_timestamp: {}",
+            field.field,
+            field.count,
+            field.log_types,
+            field.first_offset,
+            field
+                .first_timestamp
+                .map(timestamp::format)
+                .unwrap_or_else(|| "-".to_string())
+        );
+    });
+    if unexpected.is_empty() {
+        info!("No unexpected fields found");
+    }
+
+    info!("Detected in: {}", units::format_duration(now.elapsed()));
+    Ok(())
+}
+
+pub(crate) fn verify_report(matches: &ArgMatches) -> Result<(), String> {
+    // args are required or have default values so we can unwrap
+    let input = Path::new(matches.value_of_os("input").unwrap());
+    let public_key = Path::new(matches.value_of("public-key").unwrap());
+    let signature = matches
+        .value_of("signature")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| signing::signature_path(input));
+
+    match signing::verify(input, public_key, &signature) {
+        Ok(true) => info!("Signature of {} is valid", input.display()),
+        Ok(false) => {
+            error!(
+                "Signature {} doesn't match {} and the public key",
+                signature.display(),
+                input.display()
+            );
+            process::exit(EXIT_TOO_MANY_ERRORS);
+        }
+        Err(err) => return Err(err.to_string()),
+    }
+    Ok(())
+}
+
+pub(crate) fn validate(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required or have default values so we can unwrap
+    let input = open_input(matches.value_of_os("input").unwrap())?;
+    let input = input.path();
+    let num_of_threads = num_of_threads(matches);
+    let schema = matches
+        .value_of("schema")
+        .map(JsonSchema::load)
+        .transpose()?;
+    let max_reported = matches
+        .value_of("max-reported")
+        .unwrap()
+        .parse()
+        .map_err(|_| "Max reported should be a number".to_string())?;
+    let options = ParseOptions {
+        progress: progress(matches, input)?,
+        ..ParseOptions::default()
+    };
+
+    info!("Validating...");
+
+    let now = Instant::now();
+
+    let progress_bar = options.progress.clone().map(ProgressBar::start);
+    let validation = validate::validate_file(
+        num_of_threads,
+        input,
+        schema.as_ref(),
+        max_reported,
+        &options,
+    )
+    .map_err(|err| format!("Can't read {}: {}", input.display(), err))?;
+    if let Some(progress_bar) = progress_bar {
+        progress_bar.finish();
+    }
+
+    // printed like compiler errors, so CI can link them to the file
+    for invalid in &validation.invalid {
+        for error in &invalid.errors {
+            println!("{}:{}: {}", input.display(), invalid.line, error);
+        }
+    }
+    let not_printed = validation.invalid_lines - validation.invalid.len() as u64;
+    if not_printed > 0 {
+        println!("... {} more invalid lines", not_printed);
+    }
+    info!(
+        "Lines: {}, invalid lines: {}",
+        validation.lines, validation.invalid_lines
+    );
+    info!("Validated in: {}", units::format_duration(now.elapsed()));
+
+    if !validation.is_valid() {
+        process::exit(EXIT_TOO_MANY_ERRORS);
+    }
+    Ok(())
+}
+
+pub(crate) fn producers(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required so we can unwrap
+    let input = open_input(matches.value_of_os("input").unwrap())?;
+    let input = input.path();
+    let num_of_threads = num_of_threads(matches);
+    let options = ParseOptions {
+        string_fields: values_of(matches, "producer-field"),
+        collect_field_names: true,
+        progress: progress(matches, input)?,
+        ..ParseOptions::default()
+    };
+
+    diagnostics::install(input, matches.value_of("config"));
+
+    info!("Fingerprinting producers...");
+
+    let now = Instant::now();
+
+    let mut fingerprinter = FleetFingerprinter::new();
+    let progress_bar = options.progress.clone().map(ProgressBar::start);
+    let records = parser::stream(num_of_threads, input, &options)
+        .map_err(|err| format!("Can't read {}: {}", input.display(), err))?;
+    for record in records {
+        match record {
+            Ok(record) => fingerprinter.observe(&record),
+            Err(err) => error!("{}", err),
+        }
+    }
+    if let Some(progress_bar) = progress_bar {
+        progress_bar.finish();
+    }
+
+    let report = fingerprinter.report();
+    info!("Fleet fields: {:?}", report.fleet_fingerprint);
+    report.producers.iter().for_each(|producer| {
+        info!(
+            "producer: {}, counter: {}, number_of_bytes: {}, error_rate: {:.4}, avg_line_size: {:.1}, conformance: {:.4}{}",
+            producer.producer,
+            producer.counter,
+            producer.num_of_bytes,
+            producer.error_rate,
+            producer.avg_line_size,
+            producer.conformance,
+            if producer.outlier_reasons.is_empty() {
+                String::new()
+            } else {
+                format!(", OUTLIER: {}", producer.outlier_reasons.join("; "))
+            }
+        );
+    });
+
+    info!(
+        "Fingerprinted in: {}",
+        units::format_duration(now.elapsed())
+    );
+    Ok(())
+}
+
+pub(crate) fn profile(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required or have default values so we can unwrap
+    let input = open_input(matches.value_of_os("input").unwrap())?;
+    let input = input.path();
+    let num_of_threads = num_of_threads(matches);
+    let fields: Vec<String> = values_of(matches, "field")
+        .into_iter()
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect();
+    if fields.is_empty() {
+        return Err("At least one field should be profiled".to_string());
+    }
+    let num_of_top = numeric_arg(matches, "top")? as usize;
+    let options = ParseOptions {
+        string_fields: fields.clone(),
+        pattern: pattern(matches)?,
+        ..ParseOptions::default()
+    };
+
+    info!("Profiling...");
+
+    let now = Instant::now();
+
+    let (report, profiler) = aggregator::parse_file(
+        num_of_threads,
+        input,
+        &options,
+        &FieldProfiler::new(fields, num_of_top),
+    )
+    .map_err(|err| format!("Can't read {}: {}", input.display(), err))?;
+
+    match matches.value_of("format").unwrap() {
+        "json" => println!(
+            "{}",
+            serde_json::to_string_pretty(&profiler.report()).expect("Can't serialize profile")
+        ),
+        _ => {
+            for profile in profiler.profiles() {
+                println!(
+                    "{}: {} records, {} missing, {}{} distinct values",
+                    profile.field,
+                    profile.records,
+                    profile.missing,
+                    if profile.distinct_exact { "" } else { "~" },
+                    profile.distinct
+                );
+                let header = ["value", "count", "% records", "max error"].map(String::from);
+                let rows: Vec<Vec<String>> = profile
+                    .top
+                    .iter()
+                    .map(|hitter| {
+                        vec![
+                            hitter.value.clone(),
+                            hitter.count.to_string(),
+                            format!(
+                                "{:.1}%",
+                                100.0 * hitter.count as f64 / profile.records.max(1) as f64
+                            ),
+                            hitter.error.to_string(),
+                        ]
+                    })
+                    .collect();
+                println!("{}", table::markdown_table(&header, &rows));
+            }
+        }
+    }
+
+    info!(
+        "Lines: {}, failed lines: {}",
+        report.lines, report.failed_lines
+    );
+    info!("Profiled in: {}", units::format_duration(now.elapsed()));
+    Ok(())
+}
+
+pub(crate) fn index(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required or have default values so we can unwrap
+    let input = open_input(matches.value_of_os("input").unwrap())?;
+    if !is_local_file(&input) {
+        return Err(format!(
+            "Only plain local files can be indexed, {} has to be fetched or decoded first",
+            input.source()
+        ));
+    }
+    let fields: Vec<String> = values_of(matches, "group-by")
+        .into_iter()
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect();
+    if fields.is_empty() {
+        return Err("At least one field should be indexed".to_string());
+    }
+    let block_bytes = units::parse_bytes(matches.value_of("block-size").unwrap())
+        .map_err(|err| format!("Invalid block size: {}", err))?;
+    let output = matches
+        .value_of_os("output")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| index::sidecar_path(input.path()));
+    let options = ParseOptions {
+        pattern: pattern(matches)?,
+        record_delimiter: record_delimiter(matches)?,
+        ..ParseOptions::default()
+    };
+
+    info!("Indexing...");
+
+    let now = Instant::now();
+
+    let index = Index::build(input.path(), fields, block_bytes, &options)
+        .map_err(|err| format!("Can't index {}: {}", input.source(), err))?;
+    index
+        .save(&output)
+        .map_err(|err| format!("Can't write index {}: {}", output.display(), err))?;
+
+    let index_size = fs::metadata(&output).map_or(0, |metadata| metadata.len());
+    info!(
+        "Records: {}, blocks: {}, index: {} ({})",
+        index.records(),
+        index.blocks().len(),
+        output.display(),
+        units::format_binary_bytes(index_size)
+    );
+    info!("Indexed in: {}", units::format_duration(now.elapsed()));
+    Ok(())
+}
+
+pub(crate) fn slo(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required or have default values so we can unwrap
+    let input = open_input(matches.value_of_os("input").unwrap())?;
+    let input = input.path();
+    let num_of_threads = num_of_threads(matches);
+    let objective: f64 = matches
+        .value_of("objective")
+        .unwrap()
+        .parse()
+        .map_err(|_| "Objective should be a number".to_string())?;
+    if !(0.0..=1.0).contains(&objective) {
+        return Err("Objective should be between 0 and 1".to_string());
+    }
+    let period = humantime::parse_duration(matches.value_of("period").unwrap())
+        .map_err(|_| "Period should be a duration, e.g. 30d".to_string())?;
+    let latency_threshold = matches
+        .value_of("latency-threshold")
+        .map(|threshold| {
+            threshold
+                .parse::<f64>()
+                .map_err(|_| "Latency threshold should be a number".to_string())
+        })
+        .transpose()?;
+    let latency_field = matches.value_of("latency-field");
+    let status_field = matches.value_of("status-field");
+    if latency_field.is_none() && status_field.is_none() {
+        return Err("Latency or status field is required".to_string());
+    }
+    let window = matches
+        .value_of("window")
+        .map(|window| {
+            humantime::parse_duration(window)
+                .map_err(|_| "Window should be a duration, e.g. 1h, 1day".to_string())
+        })
+        .transpose()?;
+
+    let (dedup_field, dedup_window) = dedup(matches)?;
+
+    // latency is the first and status the second numeric field
+    let numeric_fields = vec![
+        latency_field.unwrap_or_default().to_string(),
+        status_field.unwrap_or_default().to_string(),
+    ];
+    let options = ParseOptions {
+        numeric_fields,
+        timestamp_field: matches.value_of("timestamp-field").map(String::from),
+        dedup_field,
+        dedup_window,
+        pattern: pattern(matches)?,
+        record_delimiter: record_delimiter(matches)?,
+        progress: progress(matches, input)?,
+        ..ParseOptions::default()
+    };
+
+    diagnostics::install(input, matches.value_of("config"));
+
+    info!("Computing SLO...");
+
+    let now = Instant::now();
+
+    let mut tracker = SloTracker::new(Slo {
+        objective,
+        latency_threshold,
+        period,
+    });
+    let progress_bar = options.progress.clone().map(ProgressBar::start);
+    let records = parser::stream(num_of_threads, input, &options)
+        .map_err(|err| format!("Can't read {}: {}", input.display(), err))?;
+    for record in records {
+        match record {
+            Ok(record) => {
+                let latency = latency_field.and(record.numeric_values[0]);
+                let status = status_field.and(record.numeric_values[1]);
+                tracker.observe(record.timestamp, latency, status);
+            }
+            Err(err) => error!("{}", err),
+        }
+    }
+    if let Some(progress_bar) = progress_bar {
+        progress_bar.finish();
+    }
+
+    let report = tracker.report(window);
+    info!(
+        "total: {}, bad: {}, compliance: {:.5}, objective: {}, window: {}, budget consumed: {:.2}%",
+        report.total,
+        report.bad,
+        report.compliance,
+        objective,
+        humantime::format_duration(Duration::from_secs(report.window_secs)),
+        report.budget_consumed * 100.0
+    );
+    if report.without_timestamp > 0 {
+        info!(
+            "{} events without timestamp are not included in burn rates",
+            report.without_timestamp
+        );
+    }
+    report.burn_rates.iter().for_each(|burn_rate| {
+        info!(
+            "window: {}, total: {}, bad: {}, burn_rate: {:.2}",
+            humantime::format_duration(Duration::from_secs(burn_rate.window_secs)),
+            burn_rate.total,
+            burn_rate.bad,
+            burn_rate.burn_rate
+        );
+    });
+    report
+        .alerts
+        .iter()
+        .for_each(|alert| warn!("Alert {}", alert));
+
+    info!("Computed in: {}", units::format_duration(now.elapsed()));
+    Ok(())
+}
@@ -0,0 +1,756 @@
+use clap::ArgMatches;
+use log_parser::alert::AlertRule;
+use log_parser::budget::ByteBudget;
+use log_parser::checkpoint::Checkpoint;
+use log_parser::clock::SystemClock;
+use log_parser::config::ParseConfig;
+use log_parser::cost::CostEstimator;
+use log_parser::csv::CsvFormat;
+use log_parser::decompress::{Decompressed, Input, Storage};
+use log_parser::dedup::{self, DedupMode, SeenIds};
+use log_parser::encoding::Encoding;
+use log_parser::expr::Derived;
+use log_parser::http::Url;
+use log_parser::index::{self, Index, ReadRanges};
+use log_parser::normalize::Normalization;
+use log_parser::parser;
+use log_parser::parser::ParseOptions;
+use log_parser::pattern::LinePattern;
+use log_parser::progress::Progress;
+use log_parser::projection::Condition;
+use log_parser::quarantine::Quarantine;
+use log_parser::rate::AnomalyDetector;
+use log_parser::scanner::{Delimiter, Multiline, StartPattern};
+use log_parser::subset::Subset;
+use log_parser::throttle::{RateLimit, TokenBucket};
+use log_parser::timestamp::TimeLocale;
+use log_parser::{tune, units};
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// How much of the parse results is printed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ReportLevel {
+    /// Totals only.
+    Summary,
+    /// Rows per log type (or group).
+    Standard,
+    /// Rows with line size histograms, and per-file breakdowns in watch mode.
+    Full,
+}
+
+/// Read number of threads arg (validated by clap), the available parallelism if it's not given.
+pub(crate) fn num_of_threads(matches: &ArgMatches) -> u8 {
+    matches
+        .value_of("num-of-threads")
+        .map_or_else(tune::available_threads, |threads| {
+            threads.parse().expect("Number of threads is validated")
+        })
+}
+
+/// Number of threads parsing the first part of the input the fastest.
+pub(crate) fn auto_tune(
+    matches: &ArgMatches,
+    strategy: parser::Strategy,
+    input: &Path,
+    options: &ParseOptions,
+) -> Result<u8, String> {
+    if strategy == parser::Strategy::Single {
+        warn!("--auto-tune has no effect with the single thread strategy");
+        return Ok(1);
+    }
+    let sample_bytes = units::parse_bytes(matches.value_of("auto-tune-sample").unwrap_or("64MB"))
+        .map_err(|err| format!("Invalid --auto-tune-sample: {}", err))?;
+    let candidates = tune::candidates(tune::available_threads());
+    info!(
+        "Tuning number of threads on the first {}...",
+        units::format_bytes(sample_bytes as f64)
+    );
+    let tuning = tune::tune(strategy, input, sample_bytes, &candidates, options)
+        .map_err(|err| format!("Can't read {}: {}", input.display(), err))?;
+    for (threads, elapsed) in &tuning.timings {
+        info!(
+            "{} threads: {} ({:.1} MB/s)",
+            threads,
+            units::format_duration(*elapsed),
+            tuning.sample_bytes as f64 / 1_000_000.0 / elapsed.as_secs_f64()
+        );
+    }
+    info!("Parsing with {} threads", tuning.threads);
+    Ok(tuning.threads)
+}
+
+/// Read numeric arg with default value, fails if it can't be parsed.
+pub(crate) fn numeric_arg(matches: &ArgMatches, name: &str) -> Result<u32, String> {
+    // args have default values so we can unwrap
+    matches
+        .value_of(name)
+        .unwrap()
+        .parse()
+        .map_err(|_| format!("Argument {} should be a number", name))
+}
+
+/// Read all values of arg which can be used multiple times.
+pub(crate) fn values_of(matches: &ArgMatches, name: &str) -> Vec<String> {
+    matches
+        .values_of(name)
+        .map(|values| values.map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// Paths of the argument as given, they don't have to be UTF-8.
+pub(crate) fn paths_of(matches: &ArgMatches, name: &str) -> Vec<PathBuf> {
+    matches
+        .values_of_os(name)
+        .map(|values| values.map(PathBuf::from).collect())
+        .unwrap_or_default()
+}
+
+/// Read alert rules, fails if any can't be evaluated with the options.
+pub(crate) fn alert_rules(
+    matches: &ArgMatches,
+    options: &ParseOptions,
+) -> Result<Vec<AlertRule>, String> {
+    values_of(matches, "alert")
+        .iter()
+        .map(|rule| {
+            let rule = AlertRule::parse(rule)?;
+            rule.validate(options)?;
+            Ok(rule)
+        })
+        .collect()
+}
+
+/// Read byte budgets, empty if not set.
+pub(crate) fn budgets(matches: &ArgMatches) -> Result<Vec<ByteBudget>, String> {
+    values_of(matches, "budget")
+        .iter()
+        .map(|budget| ByteBudget::parse(budget))
+        .collect()
+}
+
+/// Build cost estimator if price per GB is provided.
+pub(crate) fn cost_estimator(matches: &ArgMatches) -> Result<Option<CostEstimator>, String> {
+    let cost_per_gb: f64 = match matches.value_of("cost-per-gb") {
+        Some(cost_per_gb) => cost_per_gb
+            .parse()
+            .map_err(|_| "Cost per GB should be a number".to_string())?,
+        None => return Ok(None),
+    };
+
+    Ok(Some(CostEstimator::new(cost_per_gb, window(matches)?)))
+}
+
+/// Build progress shared by parsing threads if enabled.
+pub(crate) fn progress(
+    matches: &ArgMatches,
+    input: &Path,
+) -> Result<Option<Arc<Progress>>, String> {
+    // run summary takes throughput of threads from the progress
+    if !matches.is_present("progress") && !matches.is_present("summary-json") {
+        return Ok(None);
+    }
+    let total_bytes = fs::metadata(input)
+        .map_err(|err| format!("Can't read {}: {}", input.display(), err))?
+        .len();
+
+    Ok(Some(Arc::new(Progress::new(total_bytes))))
+}
+
+/// Build options of the `parse` subcommand from flags on top of `[options]` of the config.
+pub(crate) fn parse_options(matches: &ArgMatches) -> Result<ParseOptions, String> {
+    let sum_fields = values_of(matches, "sum-field");
+    let avg_fields = values_of(matches, "avg-field");
+    let base_options = match matches.value_of("config") {
+        Some(config) => ParseConfig::load(config)?.options,
+        None => ParseOptions::default(),
+    };
+    let multiline = match matches.value_of("multiline-start-pattern") {
+        Some(pattern) => Some(Multiline::StartPattern(
+            StartPattern::new(pattern)
+                .map_err(|_| "Multiline start pattern should be a regex".to_string())?,
+        )),
+        None if matches.is_present("multiline-json") => Some(Multiline::Json),
+        None => match matches.value_of("json-mode") {
+            Some("concatenated") => Some(Multiline::ConcatenatedJson),
+            Some(_) => None,
+            None => base_options.multiline.clone(),
+        },
+    };
+
+    Ok(ParseOptions {
+        numeric_fields: match sum_fields
+            .iter()
+            .chain(&avg_fields)
+            .cloned()
+            .collect::<Vec<_>>()
+        {
+            fields if fields.is_empty() => base_options.numeric_fields.clone(),
+            fields => fields,
+        },
+        distinct_fields: match values_of(matches, "count-distinct") {
+            fields if fields.is_empty() => base_options.distinct_fields.clone(),
+            fields => fields,
+        },
+        group_by: match values_of(matches, "group-by") {
+            group_by if group_by.is_empty() => base_options.group_by.clone(),
+            group_by => group_by,
+        },
+        derive: match derive(matches)? {
+            derive if derive.is_empty() => base_options.derive.clone(),
+            derive => derive,
+        },
+        normalize: match normalizations(matches)? {
+            normalize if normalize.is_empty() => base_options.normalize.clone(),
+            normalize => normalize,
+        },
+        dedup_field: matches
+            .value_of("dedup-field")
+            .map(String::from)
+            .or_else(|| base_options.dedup_field.clone()),
+        drop_duplicates: matches.is_present("drop-duplicates") || base_options.drop_duplicates,
+        multiline,
+        pattern: pattern(matches)?.or_else(|| base_options.pattern.clone()),
+        record_delimiter: record_delimiter(matches)?
+            .or_else(|| base_options.record_delimiter.clone()),
+        max_line_bytes: matches
+            .value_of("max-line-bytes")
+            .map(|max| units::parse_bytes(max).map(|max| max as usize))
+            .transpose()?
+            .or(base_options.max_line_bytes),
+        quarantine_oversize: matches.is_present("quarantine-oversize")
+            || base_options.quarantine_oversize,
+        io_retries: matches
+            .value_of("io-retries")
+            .map(|retries| {
+                retries
+                    .parse()
+                    .map_err(|_| "Number of I/O retries should be a number".to_string())
+            })
+            .transpose()?
+            .or(base_options.io_retries),
+        io_retry_delay_ms: matches
+            .value_of("io-retry-delay")
+            .map(|delay| {
+                humantime::parse_duration(delay)
+                    .map(|delay| delay.as_millis() as u64)
+                    .map_err(|_| "I/O retry delay should be a duration, e.g. 100ms, 1s".to_string())
+            })
+            .transpose()?
+            .or(base_options.io_retry_delay_ms),
+        skip_unreadable: matches.is_present("skip-unreadable") || base_options.skip_unreadable,
+        timestamp_field: matches
+            .value_of("timestamp-field")
+            .map(String::from)
+            .or_else(|| base_options.timestamp_field.clone()),
+        timestamp_locales: match values_of(matches, "timestamp-locale") {
+            locales if locales.is_empty() => base_options.timestamp_locales.clone(),
+            locales => locales
+                .iter()
+                .map(|locale| TimeLocale::from_name(locale))
+                .collect::<Result<_, _>>()?,
+        },
+        rate_window: rate_window(matches)?.or(base_options.rate_window),
+        anomaly: anomaly_detector(matches)?.or(base_options.anomaly),
+        size_histogram: matches.is_present("size-histogram") || base_options.size_histogram,
+        error_when: match values_of(matches, "error-when") {
+            conditions if conditions.is_empty() => base_options.error_when.clone(),
+            conditions => conditions
+                .iter()
+                .map(|condition| Condition::parse(condition))
+                .collect::<Result<_, _>>()?,
+        },
+        json_backend: matches
+            .value_of("json-backend")
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or(base_options.json_backend),
+        map_backend: matches
+            .value_of("map-backend")
+            .map(str::parse)
+            .transpose()?
+            .or(base_options.map_backend),
+        ..base_options
+    })
+}
+
+/// Number of leading numeric fields of the options reported as sums: those of `--sum-field`, or
+/// every numeric field of the config's `[options]` without `--sum-field` and `--avg-field`.
+pub(crate) fn sum_fields(matches: &ArgMatches, options: &ParseOptions) -> usize {
+    if matches.is_present("sum-field") || matches.is_present("avg-field") {
+        values_of(matches, "sum-field").len()
+    } else {
+        options.numeric_fields.len()
+    }
+}
+
+/// Length of `--rate-window` in seconds.
+pub(crate) fn rate_window(matches: &ArgMatches) -> Result<Option<u64>, String> {
+    matches
+        .value_of("rate-window")
+        .map(|window| {
+            let window = humantime::parse_duration(window)
+                .map_err(|_| "Rate window should be a duration, e.g. 1m, 1h".to_string())?;
+            if window.as_secs() == 0 {
+                return Err("Rate window should be at least 1s".to_string());
+            }
+            Ok(window.as_secs())
+        })
+        .transpose()
+}
+
+/// Anomaly thresholds of rate windows, if any is given.
+fn anomaly_detector(matches: &ArgMatches) -> Result<Option<AnomalyDetector>, String> {
+    let threshold = |name: &str| {
+        matches
+            .value_of(name)
+            .map(|threshold| {
+                threshold
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|threshold| *threshold >= 0.0)
+                    .ok_or_else(|| format!("--{} should be a positive number", name))
+            })
+            .transpose()
+    };
+    let max_stddevs = threshold("anomaly-stddevs")?;
+    let max_change = threshold("anomaly-percent")?.map(|percent| percent / 100.0);
+    if max_stddevs.is_none() && max_change.is_none() {
+        return Ok(None);
+    }
+    let trailing_windows = matches
+        .value_of("anomaly-trailing")
+        .map(|windows| {
+            windows
+                .parse()
+                .map_err(|_| "Number of trailing windows should be a number".to_string())
+        })
+        .transpose()?
+        .unwrap_or(AnomalyDetector::DEFAULT_TRAILING_WINDOWS);
+
+    Ok(Some(AnomalyDetector {
+        trailing_windows,
+        max_stddevs,
+        max_change,
+    }))
+}
+
+/// Read dedup field and window of streamed records.
+pub(crate) fn dedup(matches: &ArgMatches) -> Result<(Option<String>, Option<usize>), String> {
+    let window = matches
+        .value_of("dedup-window")
+        .map(|window| {
+            window
+                .parse()
+                .map_err(|_| "Dedup window should be a number of records".to_string())
+        })
+        .transpose()?;
+
+    Ok((matches.value_of("dedup-field").map(String::from), window))
+}
+
+/// Open input file which is read at random, compressed input is decompressed to a temp file
+/// first.
+pub(crate) fn open_input<P: AsRef<Path>>(input: P) -> Result<Decompressed, String> {
+    let path = input.as_ref();
+    open_stream(path)?
+        .seekable()
+        .map_err(|err| format!("Can't open {}: {}", path.display(), err))
+}
+
+/// Open input file which is parsed as a stream, compressed input is decompressed and input in
+/// another encoding is transcoded as it's read.
+pub(crate) fn open_stream<P: AsRef<Path>>(input: P) -> Result<Input, String> {
+    let path = input.as_ref();
+    let input = path.display();
+    let file = Input::open(path).map_err(|err| format!("Can't open {}: {}", input, err))?;
+    if let Some(storage) = file.storage() {
+        info!("Fetching {} input {}", storage.feature(), input);
+    }
+    if let Some(codec) = file.codec() {
+        info!("Decompressing {} input {}", codec.feature(), input);
+    }
+    match file.encoding() {
+        Some(Encoding::Utf8) => info!("Skipping byte order mark of input {}", input),
+        Some(encoding) => info!("Transcoding {} input {} to UTF-8", encoding, input),
+        None => {}
+    }
+
+    Ok(file)
+}
+
+/// Input opened for reading, as a stream or at random.
+pub(crate) trait Opened {
+    /// Name of the input as given.
+    fn source(&self) -> &str;
+
+    /// Path of the input if it's read from where it was given, not fetched, decompressed or
+    /// transcoded.
+    fn local_file(&self) -> Option<&Path>;
+
+    /// Reader of the UTF-8 content from its start.
+    fn content(&self) -> io::Result<Box<dyn Read + Send>>;
+}
+
+impl Opened for Decompressed {
+    fn source(&self) -> &str {
+        Decompressed::source(self)
+    }
+
+    fn local_file(&self) -> Option<&Path> {
+        is_local_file(self).then(|| self.path())
+    }
+
+    fn content(&self) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(fs::File::open(self.path())?))
+    }
+}
+
+impl Opened for Input {
+    fn source(&self) -> &str {
+        Input::source(self)
+    }
+
+    fn local_file(&self) -> Option<&Path> {
+        self.file()
+            .filter(|_| self.storage().is_none() && self.codec().is_none())
+            .filter(|_| self.encoding().is_none())
+    }
+
+    fn content(&self) -> io::Result<Box<dyn Read + Send>> {
+        self.reader()
+    }
+}
+
+/// Read pattern parsing unstructured lines.
+pub(crate) fn pattern(matches: &ArgMatches) -> Result<Option<LinePattern>, String> {
+    matches
+        .value_of("pattern")
+        .map(LinePattern::new)
+        .transpose()
+}
+
+/// Set of IDs seen in the inputs if duplicates are counted by `--dedup-field`.
+pub(crate) fn seen_ids(matches: &ArgMatches) -> Result<Option<Arc<SeenIds>>, String> {
+    if !matches.is_present("dedup-field") {
+        return Ok(None);
+    }
+    let mode: DedupMode = matches
+        .value_of("dedup-mode")
+        .map(str::parse)
+        .transpose()?
+        .unwrap_or_default();
+    let capacity = matches
+        .value_of("dedup-capacity")
+        .map(|capacity| {
+            capacity
+                .parse()
+                .map_err(|_| "Dedup capacity should be a number of IDs".to_string())
+        })
+        .transpose()?
+        .unwrap_or(dedup::DEFAULT_CAPACITY);
+    Ok(Some(Arc::new(SeenIds::new(mode, capacity))))
+}
+
+/// Derived fields of `--derive` flags.
+pub(crate) fn derive(matches: &ArgMatches) -> Result<Vec<Derived>, String> {
+    values_of(matches, "derive")
+        .iter()
+        .map(|derived| Derived::parse(derived))
+        .collect()
+}
+
+/// CSV format of the inputs, columns are named by the header of the first one.
+pub(crate) fn csv_format<T: Opened>(
+    matches: &ArgMatches,
+    files: &[T],
+) -> Result<Option<CsvFormat>, String> {
+    let delimiter = match matches.value_of("format") {
+        Some("csv") => matches.value_of("delimiter").unwrap_or(","),
+        Some("tsv") => matches.value_of("delimiter").unwrap_or("\\t"),
+        _ if matches.is_present("delimiter") || matches.is_present("has-header") => {
+            return Err(
+                "--delimiter and --has-header are options of --format csv or tsv".to_string(),
+            )
+        }
+        _ => return Ok(None),
+    };
+    let format = CsvFormat::new(delimiter)?;
+    if !matches.is_present("has-header") {
+        return Ok(Some(format));
+    }
+    let mut headers = files.iter().map(|file| {
+        file.content()
+            .and_then(|content| format.clone().with_header_of(content))
+            .map_err(|err| format!("Can't read CSV header of {}: {}", file.source(), err))
+    });
+    let format = match headers.next() {
+        Some(format) => format?,
+        None => return Ok(None),
+    };
+    for header in headers {
+        if header?.columns != format.columns {
+            return Err("Inputs have different CSV headers, parse them separately".to_string());
+        }
+    }
+    info!("CSV columns: {}", format.columns.join(", "));
+
+    Ok(Some(format))
+}
+
+/// Read bytes separating records.
+pub(crate) fn record_delimiter(matches: &ArgMatches) -> Result<Option<Delimiter>, String> {
+    matches
+        .value_of("record-delimiter")
+        .map(Delimiter::parse)
+        .transpose()
+}
+
+/// Normalizations of `--normalize`, lookup tables are loaded.
+pub(crate) fn normalizations(matches: &ArgMatches) -> Result<Vec<Normalization>, String> {
+    values_of(matches, "normalize")
+        .iter()
+        .map(|normalization| Normalization::parse(normalization))
+        .collect()
+}
+
+/// Token bucket of `--rate-limit` and `--burst`, if the rate is limited.
+pub(crate) fn token_bucket(matches: &ArgMatches) -> Result<Option<TokenBucket>, String> {
+    let records_per_sec = match matches.value_of("rate-limit") {
+        Some(records_per_sec) => records_per_sec
+            .parse()
+            .map_err(|_| "Rate limit should be a number of records per second".to_string())?,
+        None => return Ok(None),
+    };
+    let burst = matches
+        .value_of("burst")
+        .map(|burst| {
+            burst
+                .parse()
+                .map_err(|_| "Burst should be a number of records".to_string())
+        })
+        .transpose()?;
+    let limit = RateLimit::new(records_per_sec, burst)?;
+
+    Ok(Some(TokenBucket::new(limit, Arc::new(SystemClock))))
+}
+
+/// Whether the input is read from where it was given, not fetched, decompressed or transcoded.
+pub(crate) fn is_local_file(file: &Decompressed) -> bool {
+    file.storage().is_none() && file.codec().is_none() && file.encoding().is_none()
+}
+
+/// Conditions of `--where`.
+pub(crate) fn conditions(matches: &ArgMatches) -> Result<Vec<Condition>, String> {
+    values_of(matches, "where")
+        .iter()
+        .map(|condition| Condition::parse(condition))
+        .collect()
+}
+
+/// Ranges of the inputs read with `--use-index`: blocks of their indexes which may have records
+/// matching `--where`, inputs without a current index are read whole.
+pub(crate) fn read_ranges<T: Opened>(
+    matches: &ArgMatches,
+    files: &[T],
+) -> Result<Option<Arc<ReadRanges>>, String> {
+    if !matches.is_present("use-index") {
+        return Ok(None);
+    }
+    let conditions = conditions(matches)?;
+    let mut read_ranges = ReadRanges::default();
+    for file in files {
+        if let Some((path, index)) = load_index(file)? {
+            let skipped = read_ranges.insert(path, &index, &conditions);
+            info!(
+                "Input {}: skipped by index: {}",
+                file.source(),
+                units::format_binary_bytes(skipped)
+            );
+        }
+    }
+    Ok(Some(Arc::new(read_ranges)))
+}
+
+/// Index of the input next to it with the path of the input, if it's of the input as it is now.
+fn load_index<T: Opened>(file: &T) -> Result<Option<(&Path, Index)>, String> {
+    let (file_path, path) = match file.local_file() {
+        Some(file_path) => (file_path, index::sidecar_path(file_path)),
+        None => {
+            warn!("No index of {}, reading all of it", file.source());
+            return Ok(None);
+        }
+    };
+    if !path.exists() {
+        warn!("No index of {}, reading all of it", file.source());
+        return Ok(None);
+    }
+    let index = Index::load(&path)
+        .map_err(|err| format!("Can't read index {}: {}", path.display(), err))?;
+    match index.is_current(file_path) {
+        Ok(true) => Ok(Some((file_path, index))),
+        _ => {
+            warn!(
+                "Index {} is older than {}, reading all of it",
+                path.display(),
+                file.source()
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// Open checkpoint of the run if enabled.
+pub(crate) fn checkpoint(matches: &ArgMatches, input: &Path) -> Result<Option<Checkpoint>, String> {
+    let path = match matches.value_of("checkpoint") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    // checkpoint interval has default value so we can unwrap
+    let interval = humantime::parse_duration(matches.value_of("checkpoint-interval").unwrap())
+        .map_err(|_| "Checkpoint interval should be a duration, e.g. 10s, 1m".to_string())?;
+    let checkpoint = Checkpoint::open(path, &checkpoint_key(input)?, interval)
+        .map_err(|err| format!("Can't read checkpoint {}: {}", path, err))?;
+
+    Ok(Some(checkpoint))
+}
+
+/// Identity of the run for checkpoints: command line with size and modification time of the
+/// input, so a checkpoint is never resumed with other options or a changed file.
+fn checkpoint_key(input: &Path) -> Result<String, String> {
+    if input.to_str().and_then(Storage::detect).is_some() {
+        return Err(format!(
+            "Checkpoints need a local input file, {} is remote",
+            input.display()
+        ));
+    }
+    let metadata =
+        fs::metadata(input).map_err(|err| format!("Can't read {}: {}", input.display(), err))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_nanos())
+        .unwrap_or_default();
+    let args: Vec<String> = env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+
+    Ok(format!(
+        "{} (input: {} bytes, modified {})",
+        args.join(" "),
+        metadata.len(),
+        modified
+    ))
+}
+
+/// Read report verbosity.
+pub(crate) fn report_level(matches: &ArgMatches) -> ReportLevel {
+    // report has default value so we can unwrap
+    match matches.value_of("report").unwrap() {
+        "summary" => ReportLevel::Summary,
+        "full" => ReportLevel::Full,
+        _ => ReportLevel::Standard,
+    }
+}
+
+/// Read time span covered by the input.
+pub(crate) fn window(matches: &ArgMatches) -> Result<Duration, String> {
+    // window has default value so we can unwrap
+    let window = humantime::parse_duration(matches.value_of("window").unwrap())
+        .map_err(|_| "Window should be a duration, e.g. 1h, 1day".to_string())?;
+    if window.as_secs_f64() <= 0.0 {
+        return Err("Window should be greater than 0".to_string());
+    }
+
+    Ok(window)
+}
+
+/// URIs of `--sink` results sinks with the OTLP exporter of `--otlp-endpoint`.
+pub(crate) fn sink_uris(matches: &ArgMatches) -> Result<Vec<String>, String> {
+    let mut uris = values_of(matches, "sink");
+    uris.extend(otlp_uri(matches)?);
+    Ok(uris)
+}
+
+/// Sink URI of the `--otlp-endpoint` collector, e.g. `otlp://localhost:4318`.
+pub(crate) fn otlp_uri(matches: &ArgMatches) -> Result<Option<String>, String> {
+    let endpoint = match matches.value_of("otlp-endpoint") {
+        Some(endpoint) => endpoint,
+        None => return Ok(None),
+    };
+    let url = Url::parse(endpoint)?;
+    let scheme = if url.tls { "otlps" } else { "otlp" };
+    let endpoint = endpoint
+        .split_once("://")
+        .map_or(endpoint, |(_, rest)| rest);
+    Ok(Some(format!("{}://{}", scheme, endpoint)))
+}
+
+/// Part of every input parsed instead of the whole input.
+pub(crate) fn subset(matches: &ArgMatches) -> Result<Option<Subset>, String> {
+    let lines = |flag: &str| {
+        matches
+            .value_of(flag)
+            .map(|lines| {
+                lines
+                    .parse()
+                    .map_err(|_| format!("--{} should be a number of lines", flag))
+            })
+            .transpose()
+    };
+    if let Some(fraction) = matches.value_of("sample") {
+        let fraction: f64 = fraction
+            .parse()
+            .map_err(|_| "Sample should be a number, e.g. 0.01".to_string())?;
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err("Sample should be between 0 and 1".to_string());
+        }
+        return Ok(Some(Subset::Sample(fraction)));
+    }
+    match lines("head")? {
+        Some(head) => Ok(Some(Subset::Head(head))),
+        None => Ok(lines("tail")?.map(Subset::Tail)),
+    }
+}
+
+/// Quarantine file of bad lines with its size caps.
+pub(crate) fn quarantine(matches: &ArgMatches) -> Result<Option<Quarantine>, String> {
+    let mut quarantine = match matches.value_of("quarantine") {
+        Some(path) => Quarantine::new(path),
+        None => return Ok(None),
+    };
+    if let Some(max_size) = matches.value_of("quarantine-max-size") {
+        quarantine = quarantine.max_bytes(units::parse_bytes(max_size)?);
+    }
+    if let Some(max_files) = matches.value_of("quarantine-max-files") {
+        let max_files = max_files
+            .parse()
+            .map_err(|_| "Quarantine max files should be a number".to_string())?;
+        if max_files == 0 {
+            return Err("Quarantine max files should be at least 1".to_string());
+        }
+        quarantine = quarantine.max_files(max_files);
+    }
+    Ok(Some(quarantine))
+}
+
+/// Read maximum number of failed lines, `--strict` allows none.
+pub(crate) fn max_errors(matches: &ArgMatches) -> Result<Option<u64>, String> {
+    if matches.is_present("strict") {
+        return Ok(Some(0));
+    }
+    matches
+        .value_of("max-errors")
+        .map(|max_errors| {
+            max_errors
+                .parse()
+                .map_err(|_| "Max errors should be a number".to_string())
+        })
+        .transpose()
+}
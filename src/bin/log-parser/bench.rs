@@ -0,0 +1,261 @@
+use crate::args::{num_of_threads, numeric_arg, open_input, values_of};
+use clap::ArgMatches;
+use log_parser::extract::JsonBackend;
+use log_parser::map_backend::MapBackend;
+use log_parser::parser;
+use log_parser::parser::{ParseOptions, SampleOptions, SampleTimestamps};
+use log_parser::template::Template;
+use log_parser::{timestamp, units};
+use std::fs;
+use std::io;
+use std::time::Instant;
+
+pub(crate) fn generate(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // output is required and the rest has default values so we can unwrap
+    let output = matches.value_of("output").unwrap();
+    let num_of_lines = numeric_arg(matches, "num-of-lines")?;
+    let num_of_log_types = numeric_arg(matches, "num-of-log-types")?;
+    let max_msg_size = numeric_arg(matches, "max-msg-size")?;
+
+    if num_of_log_types == 0 || max_msg_size == 0 {
+        return Err(
+            "Number of log types and max message size should be greater than 0".to_string(),
+        );
+    }
+
+    let seed = matches
+        .value_of("seed")
+        .map(|seed| {
+            seed.parse()
+                .map_err(|_| "Seed should be a number".to_string())
+        })
+        .transpose()?;
+    let levels = matches
+        .value_of("levels")
+        .map(parse_levels)
+        .transpose()?
+        .unwrap_or_default();
+    let timestamps = matches
+        .value_of("time-range")
+        .map(|range| {
+            Ok::<_, String>(SampleTimestamps {
+                start: timestamp::parse_str(matches.value_of("start").unwrap()).ok_or_else(
+                    || "Start should be RFC 3339 timestamp, e.g. 2024-01-01T00:00:00Z".to_string(),
+                )?,
+                range: humantime::parse_duration(range)
+                    .map_err(|_| "Time range should be a duration, e.g. 1h, 1day".to_string())?,
+                jitter: humantime::parse_duration(matches.value_of("jitter").unwrap())
+                    .map_err(|_| "Jitter should be a duration, e.g. 5s".to_string())?,
+            })
+        })
+        .transpose()?;
+    let options = SampleOptions {
+        num_of_lines,
+        num_of_log_types,
+        max_msg_size,
+        timestamps,
+        levels,
+        seed,
+        template: matches
+            .value_of("template")
+            .map(Template::load)
+            .transpose()?,
+        target_size: matches
+            .value_of("target-size")
+            .map(|size| {
+                units::parse_bytes(size).map_err(|err| format!("Invalid target size: {}", err))
+            })
+            .transpose()?,
+    };
+    let num_of_shards = numeric_arg(matches, "shards")?;
+    if num_of_shards == 0 {
+        return Err("Number of shards should be greater than 0".to_string());
+    }
+
+    info!("Generating...");
+
+    let now = Instant::now();
+
+    let result = parser::prepare_sample_shards(&options, output, num_of_shards);
+    result.iter().for_each(|(log_type, value)| {
+        info!(
+            "log_type: {}, counter: {}, number_of_bytes: {}, errors: {}",
+            log_type, value.counter, value.num_of_bytes, value.errors
+        );
+    });
+
+    info!("Generated in: {}", units::format_duration(now.elapsed()));
+    Ok(())
+}
+
+/// Parse weighted levels, e.g. `info=80,warn=15,error=5`.
+fn parse_levels(text: &str) -> Result<Vec<(String, u32)>, String> {
+    let levels: Vec<(String, u32)> = text
+        .split(',')
+        .map(|level| {
+            let (name, weight) = level
+                .split_once('=')
+                .ok_or_else(|| format!("Level should be <level>=<weight>, got {}", level))?;
+            let weight = weight
+                .trim()
+                .parse()
+                .map_err(|_| format!("Weight of level {} should be a number", name))?;
+            Ok((name.trim().to_string(), weight))
+        })
+        .collect::<Result<_, String>>()?;
+    if levels.iter().all(|(_, weight)| *weight == 0) {
+        return Err("At least one level weight should be greater than 0".to_string());
+    }
+
+    Ok(levels)
+}
+
+/// Parser strategy used by bench, returns number of log types found.
+type Strategy<'a> = Box<dyn Fn() -> io::Result<usize> + 'a>;
+
+pub(crate) fn bench(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required so we can unwrap
+    let input = open_input(matches.value_of_os("input").unwrap())?;
+    let input = input.path();
+    let num_of_threads = num_of_threads(matches);
+
+    let size = fs::metadata(input)
+        .map_err(|err| format!("Can't read {}: {}", input.display(), err))?
+        .len();
+    let size_in_mb = size as f64 / (1024.0 * 1024.0);
+
+    let channel_capacity = matches
+        .value_of("channel-capacity")
+        .map(|capacity| {
+            capacity
+                .parse()
+                .map_err(|_| "Channel capacity should be a number".to_string())
+        })
+        .transpose()?;
+    let channel_batch_size = matches
+        .value_of("channel-batch-size")
+        .map(|batch_size| {
+            batch_size
+                .parse()
+                .map_err(|_| "Channel batch size should be a number".to_string())
+        })
+        .transpose()?;
+    let channel_reducers = matches
+        .value_of("channel-reducers")
+        .map(|reducers| {
+            reducers
+                .parse()
+                .map_err(|_| "Number of channel reducers should be a number".to_string())
+        })
+        .transpose()?;
+
+    let backends = [
+        ("serde", JsonBackend::Serde),
+        ("scan", JsonBackend::Scan),
+        #[cfg(feature = "simd")]
+        ("simd", JsonBackend::Simd),
+    ];
+    let options: Vec<ParseOptions> = backends
+        .iter()
+        .map(|(_, json_backend)| ParseOptions {
+            group_by: values_of(matches, "group-by"),
+            json_backend: *json_backend,
+            channel_capacity,
+            channel_batch_size,
+            channel_reducers,
+            ..ParseOptions::default()
+        })
+        .collect();
+
+    info!("Benchmarking...");
+
+    // number of log types is printed to check that results match
+    let mut strategies: Vec<(String, u8, Strategy)> = vec![];
+    for ((backend, _), options) in backends.iter().zip(&options) {
+        strategies.push((
+            format!("single_thread/{}", backend),
+            1,
+            Box::new(move || {
+                Ok(parser::single_thread_parser(input, options)?
+                    .log_register
+                    .len())
+            }),
+        ));
+        strategies.push((
+            format!("multi_thread_channel/{}", backend),
+            num_of_threads,
+            Box::new(move || {
+                Ok(
+                    parser::multi_thread_parser_channel(num_of_threads, input, options)?
+                        .log_register
+                        .len(),
+                )
+            }),
+        ));
+        strategies.push((
+            format!("multi_thread_dashmap/{}", backend),
+            num_of_threads,
+            Box::new(move || {
+                Ok(
+                    parser::multi_thread_parser_dashmap(num_of_threads, input, options)?
+                        .log_register
+                        .len(),
+                )
+            }),
+        ));
+        strategies.push((
+            format!("multi_thread_sharded/{}", backend),
+            num_of_threads,
+            Box::new(move || {
+                Ok(
+                    parser::multi_thread_parser_sharded(num_of_threads, input, options)?
+                        .log_register
+                        .len(),
+                )
+            }),
+        ));
+        // the dashmap backend is the dashmap strategy
+        for map_backend in MapBackend::ALL.iter().copied().skip(1) {
+            strategies.push((
+                format!("merged_{}/{}", map_backend.name(), backend),
+                num_of_threads,
+                Box::new(move || {
+                    Ok(parser::multi_thread_parser_merged(
+                        num_of_threads,
+                        input,
+                        options,
+                        map_backend,
+                    )?
+                    .log_register
+                    .len())
+                }),
+            ));
+        }
+    }
+
+    println!(
+        "{:<28} {:>8} {:>10} {:>12} {:>10}",
+        "strategy", "threads", "log_types", "time_ms", "MB/s"
+    );
+    for (name, threads, strategy) in strategies {
+        let now = Instant::now();
+        let num_of_log_types =
+            strategy().map_err(|err| format!("Can't read {}: {}", input.display(), err))?;
+        let elapsed = now.elapsed();
+
+        let throughput = size_in_mb / elapsed.as_secs_f64();
+        println!(
+            "{:<28} {:>8} {:>10} {:>12.3} {:>10.2}",
+            name,
+            threads,
+            num_of_log_types,
+            elapsed.as_secs_f64() * 1000.0,
+            throughput
+        );
+    }
+    Ok(())
+}
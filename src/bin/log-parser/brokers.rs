@@ -0,0 +1,242 @@
+use crate::args::{pattern, report_level, values_of};
+use crate::output::write_snapshot;
+use clap::ArgMatches;
+#[cfg(feature = "amqp")]
+use log_parser::amqp::{self, AmqpUrl};
+use log_parser::error_log;
+#[cfg(feature = "mqtt")]
+use log_parser::mqtt::{self, MqttUrl};
+#[cfg(feature = "nats")]
+use log_parser::nats::{self, NatsUrl};
+use log_parser::parser::{ParseOptions, ParseReport};
+#[cfg(any(feature = "nats", feature = "amqp", feature = "mqtt"))]
+use std::mem;
+#[cfg(feature = "nats")]
+use std::time::Duration;
+
+#[cfg(feature = "nats")]
+pub(crate) fn nats(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required or have default values so we can unwrap
+    let url = matches.value_of("url").unwrap();
+    let nats_url = NatsUrl::parse(url)?;
+    let batch = matches
+        .value_of("batch")
+        .unwrap()
+        .parse()
+        .map_err(|_| "Batch should be a number of messages".to_string())?;
+    let interval = humantime::parse_duration(matches.value_of("snapshot-interval").unwrap())
+        .map_err(|_| "Snapshot interval should be a duration, e.g. 10s, 1m".to_string())?;
+    let sinks = values_of(matches, "sink");
+    let level = report_level(matches);
+    let options = ParseOptions {
+        group_by: values_of(matches, "group-by"),
+        pattern: pattern(matches)?,
+        ..ParseOptions::default()
+    };
+
+    let connection = nats::Connection::connect(&nats_url.host, nats_url.port)
+        .map_err(|err| format!("Can't connect to {}: {}", url, err))?;
+    let (name, mut source) = match (matches.value_of("subject"), matches.value_of("consumer")) {
+        (_, Some(consumer)) => {
+            let (stream, durable) = consumer
+                .split_once('/')
+                .ok_or_else(|| "Consumer should be <stream>/<consumer>".to_string())?;
+            let consumer = nats::PullConsumer::new(connection, stream, durable, batch)
+                .map_err(|err| format!("Can't consume from {}: {}", url, err))?;
+            (
+                format!("{}/{}/{}", url, stream, durable),
+                nats::Source::Consumer(consumer),
+            )
+        }
+        (Some(subject), _) => {
+            let mut connection = connection;
+            connection
+                .subscribe(subject)
+                .map_err(|err| format!("Can't subscribe to {}: {}", subject, err))?;
+            (
+                format!("{}/{}", url, subject),
+                nats::Source::Subscription(connection),
+            )
+        }
+        _ => unreachable!("subject or consumer is required"),
+    };
+
+    info!("Consuming {}...", name);
+
+    let mut report = ParseReport::default();
+    // acked once a snapshot with their records is written, so the records of a snapshot which
+    // is not written are redelivered
+    let mut unacked = vec![];
+    let clock = options.clock();
+    let mut snapshot_at = clock.instant() + interval;
+    loop {
+        // pull requests shorter than this would expire before reaching the server
+        let timeout = snapshot_at
+            .saturating_duration_since(clock.instant())
+            .max(Duration::from_millis(100));
+        match source.next_message(timeout) {
+            Ok(Some(mut message)) => {
+                // payloads aren't kept until the snapshot, only what acks them
+                let payload = mem::take(&mut message.payload);
+                add_message(&mut report, &payload, &options);
+                unacked.push(message);
+            }
+            Ok(None) => {}
+            Err(err) => return Err(format!("Can't consume {}: {}", name, err)),
+        }
+
+        if clock.instant() >= snapshot_at {
+            write_snapshot(&name, &report, &options, 0, &sinks, level)?;
+            for message in unacked.drain(..) {
+                source
+                    .ack(&message)
+                    .map_err(|err| format!("Can't ack message: {}", err))?;
+            }
+            snapshot_at = clock.instant() + interval;
+        }
+    }
+}
+
+#[cfg(feature = "amqp")]
+pub(crate) fn amqp(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required or have default values so we can unwrap
+    let url = matches.value_of("url").unwrap();
+    let amqp_url = AmqpUrl::parse(url)?;
+    let queue = matches.value_of("queue").unwrap();
+    let prefetch = matches
+        .value_of("prefetch")
+        .unwrap()
+        .parse()
+        .map_err(|_| "Prefetch should be a number of messages (at most 65535)".to_string())?;
+    let interval = humantime::parse_duration(matches.value_of("snapshot-interval").unwrap())
+        .map_err(|_| "Snapshot interval should be a duration, e.g. 10s, 1m".to_string())?;
+    let sinks = values_of(matches, "sink");
+    let level = report_level(matches);
+    let options = ParseOptions {
+        group_by: values_of(matches, "group-by"),
+        pattern: pattern(matches)?,
+        ..ParseOptions::default()
+    };
+
+    let consumer = amqp::Consumer::connect(&amqp_url, queue, prefetch)
+        .map_err(|err| format!("Can't consume {} from {}: {}", queue, url, err))?;
+    // without credentials, the name is written to sinks
+    let name = format!("amqp://{}:{}/{}", amqp_url.host, amqp_url.port, queue);
+
+    info!("Consuming {}...", name);
+
+    let mut report = ParseReport::default();
+    // acked once a snapshot with their records is written, so the records of a snapshot which
+    // is not written are redelivered
+    let mut unacked = vec![];
+    let clock = options.clock();
+    let mut snapshot_at = clock.instant() + interval;
+    loop {
+        let timeout = snapshot_at.saturating_duration_since(clock.instant());
+        match consumer.next_delivery(timeout) {
+            Ok(Some(mut delivery)) => {
+                // bodies aren't kept until the snapshot, only what acks them
+                let body = mem::take(&mut delivery.body);
+                add_message(&mut report, &body, &options);
+                unacked.push(delivery);
+            }
+            Ok(None) => {}
+            Err(err) => return Err(format!("Can't consume {}: {}", name, err)),
+        }
+
+        if clock.instant() >= snapshot_at {
+            write_snapshot(&name, &report, &options, 0, &sinks, level)?;
+            for delivery in unacked.drain(..) {
+                consumer
+                    .ack(&delivery)
+                    .map_err(|err| format!("Can't ack message: {}", err))?;
+            }
+            snapshot_at = clock.instant() + interval;
+        }
+    }
+}
+
+#[cfg(feature = "mqtt")]
+pub(crate) fn mqtt(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required or have default values so we can unwrap
+    let url = matches.value_of("url").unwrap();
+    let mqtt_url = MqttUrl::parse(url)?;
+    let topic_filters = values_of(matches, "topic");
+    let qos = matches.value_of("qos").unwrap().parse().unwrap();
+    let session = mqtt::Session {
+        client_id: matches.value_of("client-id").map_or_else(
+            || format!("log-parser-{}", uuid::Uuid::new_v4().to_simple()),
+            String::from,
+        ),
+        clean: matches.value_of("client-id").is_none(),
+        keep_alive: humantime::parse_duration(matches.value_of("keep-alive").unwrap())
+            .map_err(|_| "Keep alive should be a duration, e.g. 30s, 1m".to_string())?,
+    };
+    let interval = humantime::parse_duration(matches.value_of("snapshot-interval").unwrap())
+        .map_err(|_| "Snapshot interval should be a duration, e.g. 10s, 1m".to_string())?;
+    let sinks = values_of(matches, "sink");
+    let level = report_level(matches);
+    let options = ParseOptions {
+        group_by: values_of(matches, "group-by"),
+        pattern: pattern(matches)?,
+        ..ParseOptions::default()
+    };
+
+    let subscriber = mqtt::Subscriber::connect(&mqtt_url, &session, &topic_filters, qos)
+        .map_err(|err| format!("Can't subscribe to {:?} on {}: {}", topic_filters, url, err))?;
+    // without credentials, the name is written to sinks
+    let name = format!(
+        "mqtt://{}:{}/{}",
+        mqtt_url.host,
+        mqtt_url.port,
+        topic_filters.join(",")
+    );
+
+    info!("Consuming {} as {}...", name, session.client_id);
+
+    let mut report = ParseReport::default();
+    // acked once a snapshot with their records is written, so the records of a snapshot which
+    // is not written are redelivered
+    let mut unacked = vec![];
+    let clock = options.clock();
+    let mut snapshot_at = clock.instant() + interval;
+    loop {
+        let timeout = snapshot_at.saturating_duration_since(clock.instant());
+        match subscriber.next_message(timeout) {
+            Ok(Some(mut message)) => {
+                // payloads aren't kept until the snapshot, only what acks them
+                let payload = mem::take(&mut message.payload);
+                add_message(&mut report, &payload, &options);
+                unacked.push(message);
+            }
+            Ok(None) => {}
+            Err(err) => return Err(format!("Can't consume {}: {}", name, err)),
+        }
+
+        if clock.instant() >= snapshot_at {
+            write_snapshot(&name, &report, &options, 0, &sinks, level)?;
+            for message in unacked.drain(..) {
+                subscriber
+                    .ack(&message)
+                    .map_err(|err| format!("Can't ack message: {}", err))?;
+            }
+            snapshot_at = clock.instant() + interval;
+        }
+    }
+}
+
+/// Add every line of a message from a broker to the running aggregate.
+fn add_message(report: &mut ParseReport, payload: &[u8], options: &ParseOptions) {
+    let payload = String::from_utf8_lossy(payload);
+    for line in payload.lines().filter(|line| !line.trim().is_empty()) {
+        if let Err(err) = report.add_line(line, options) {
+            error_log::log(line, &err);
+        }
+    }
+}
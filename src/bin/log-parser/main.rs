@@ -0,0 +1,116 @@
+use clap::ArgMatches;
+use env_logger::Env;
+use log_parser::encoding;
+#[cfg(feature = "memory-profile")]
+use log_parser::memory;
+use log_parser::terminal::{self, ColorChoice};
+use log_parser::tls::{self, ClientTls};
+use log_parser::units::{self, Locale};
+use std::path::PathBuf;
+use std::process;
+
+mod analyze;
+mod app;
+mod args;
+mod bench;
+#[cfg(any(feature = "nats", feature = "amqp", feature = "mqtt"))]
+mod brokers;
+mod output;
+mod parse;
+mod records;
+mod reports;
+mod server;
+
+#[macro_use]
+extern crate log;
+
+#[cfg(feature = "memory-profile")]
+#[global_allocator]
+static ALLOCATOR: memory::CountingAllocator = memory::CountingAllocator;
+
+/// Exit code used when number of failed lines exceeds the threshold, or lines are invalid.
+const EXIT_TOO_MANY_ERRORS: i32 = 2;
+/// Exit code of errors of the user, e.g. a missing input or an invalid flag.
+const EXIT_FAILURE: i32 = 1;
+/// Exit code of Rust programs which panicked.
+const EXIT_PANIC: i32 = 101;
+
+fn main() {
+    let env = Env::default()
+        .filter_or("MY_LOG_LEVEL", "info")
+        .write_style_or("MY_LOG_STYLE", "always");
+    env_logger::init_from_env(env);
+
+    if let Err(err) = app::get_matches(app::app()).and_then(|matches| run(&matches)) {
+        error!("{}", err);
+        process::exit(EXIT_FAILURE);
+    }
+}
+
+/// Apply the global flags and run the subcommand.
+fn run(matches: &ArgMatches) -> Result<(), String> {
+    units::set_locale(locale(matches)?);
+    terminal::set_color(
+        global_value(matches, "color")
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or(ColorChoice::Auto),
+    );
+    encoding::set_forced(
+        global_value(matches, "encoding")
+            .map(str::parse)
+            .transpose()?,
+    );
+    tls::set_client(&ClientTls {
+        ca: global_value(matches, "tls-ca").map(PathBuf::from),
+        cert: global_value(matches, "tls-client-cert").map(PathBuf::from),
+        key: global_value(matches, "tls-client-key").map(PathBuf::from),
+    })?;
+
+    match matches.subcommand() {
+        ("parse", Some(sub_matches)) => parse::parse_and_record_exit(sub_matches),
+        ("generate", Some(sub_matches)) => bench::generate(sub_matches),
+        ("drift", Some(sub_matches)) => analyze::drift(sub_matches),
+        ("validate", Some(sub_matches)) => analyze::validate(sub_matches),
+        ("producers", Some(sub_matches)) => analyze::producers(sub_matches),
+        ("profile", Some(sub_matches)) => analyze::profile(sub_matches),
+        ("index", Some(sub_matches)) => analyze::index(sub_matches),
+        ("replay", Some(sub_matches)) => records::replay(sub_matches),
+        ("anonymize", Some(sub_matches)) => records::anonymize(sub_matches),
+        ("select", Some(sub_matches)) => records::select(sub_matches),
+        ("head", Some(sub_matches)) => records::head(sub_matches),
+        ("last", Some(sub_matches)) => records::last(sub_matches),
+        ("seek", Some(sub_matches)) => records::seek(sub_matches),
+        ("slo", Some(sub_matches)) => analyze::slo(sub_matches),
+        ("rollup", Some(sub_matches)) => reports::rollup(sub_matches),
+        ("diff", Some(sub_matches)) => reports::diff(sub_matches),
+        ("verify-report", Some(sub_matches)) => analyze::verify_report(sub_matches),
+        ("merge", Some(sub_matches)) => reports::merge(sub_matches),
+        ("reingest", Some(sub_matches)) => reports::reingest(sub_matches),
+        ("agent", Some(sub_matches)) => server::agent(sub_matches),
+        ("serve", Some(sub_matches)) => server::serve(sub_matches),
+        #[cfg(feature = "nats")]
+        ("nats", Some(sub_matches)) => brokers::nats(sub_matches),
+        #[cfg(feature = "amqp")]
+        ("amqp", Some(sub_matches)) => brokers::amqp(sub_matches),
+        #[cfg(feature = "mqtt")]
+        ("mqtt", Some(sub_matches)) => brokers::mqtt(sub_matches),
+        ("bench", Some(sub_matches)) => bench::bench(sub_matches),
+        _ => unreachable!("subcommand is required"),
+    }
+}
+
+/// Value of a global flag given before or after the subcommand.
+fn global_value<'a>(matches: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    matches
+        .value_of(name)
+        .or_else(|| matches.subcommand().1?.value_of(name))
+}
+
+/// Locale of human output from `--locale` or the environment.
+fn locale(matches: &ArgMatches) -> Result<Locale, String> {
+    match global_value(matches, "locale") {
+        Some(name) => Locale::from_name(name),
+        None => Ok(Locale::from_env()),
+    }
+}
@@ -0,0 +1,610 @@
+use crate::args::ReportLevel;
+use log_parser::cost::CostEstimator;
+use log_parser::error_log;
+use log_parser::parser::{ParseOptions, ParseReport};
+use log_parser::profiling::{PipelineMetrics, Profiler, ThreadProfile, STAGES};
+use log_parser::subset::{Subset, SubsetFile};
+use log_parser::terminal::{self, Align, Style};
+use log_parser::{rate, sink, table, timestamp, units};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Aligned table of every log type (or group) with the most lines first, shares of the total
+/// lines and bytes, and a total row (the only one with the summary level). Groups with errors
+/// are yellow, mostly errors red if output is colored.
+fn register_table(
+    report: &ParseReport,
+    options: &ParseOptions,
+    key_name: &str,
+    sum_fields: usize,
+    cost_estimator: Option<&CostEstimator>,
+    estimates: Option<&Estimates>,
+    level: ReportLevel,
+) -> String {
+    let mut header: Vec<String> = [
+        key_name,
+        "lines",
+        "% lines",
+        "bytes",
+        "% bytes",
+        "errors",
+        "% errors",
+        "line size min/mean/max",
+        "p50/p95/p99",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect();
+    // fields are kept in the same order as numeric fields in options
+    for (idx, field_name) in options.numeric_fields.iter().enumerate() {
+        let function = if idx < sum_fields { "sum" } else { "avg" };
+        header.push(format!("{}({})", function, field_name));
+    }
+    for field_name in &options.distinct_fields {
+        header.push(format!("distinct({})", field_name));
+    }
+    // first/last timestamp columns, filled in the total row too
+    let seen_column = header.len();
+    if options.timestamp_field.is_some() {
+        header.extend(["first seen", "last seen", "active span"].map(String::from));
+    }
+    if options.dedup_field.is_some() {
+        header.push("duplicates".to_string());
+    }
+    let cost_column = header.len();
+    if cost_estimator.is_some() {
+        header.push("monthly cost".to_string());
+    }
+    if estimates.is_some() {
+        header.extend(["est. lines", "est. bytes", "est. errors"].map(String::from));
+    }
+    let mut align = vec![Align::Left];
+    align.resize(header.len(), Align::Right);
+
+    let total_lines: u64 = report.log_register.values().map(|elem| elem.counter).sum();
+    let total_bytes: u64 = report
+        .log_register
+        .values()
+        .map(|elem| elem.num_of_bytes)
+        .sum();
+    let total_errors: u64 = report.log_register.values().map(|elem| elem.errors).sum();
+    let share = |part: u64, total: u64| match total {
+        0 => "-".to_string(),
+        total => format!("{:.1}%", part as f64 * 100.0 / total as f64),
+    };
+
+    let mut log_types: Vec<_> = match level {
+        ReportLevel::Summary => vec![],
+        _ => report.log_register.iter().collect(),
+    };
+    log_types.sort_by(|(a_key, a), (b_key, b)| b.counter.cmp(&a.counter).then(a_key.cmp(b_key)));
+    let mut rows: Vec<(Style, Vec<String>)> = vec![];
+    for (log_type, elem) in log_types {
+        let line_size = &elem.line_size;
+        let mut row = vec![
+            log_type.clone(),
+            units::format_count(elem.counter),
+            share(elem.counter, total_lines),
+            units::format_binary_bytes(elem.num_of_bytes),
+            share(elem.num_of_bytes, total_bytes),
+            units::format_count(elem.errors),
+            share(elem.errors, elem.counter),
+            format!(
+                "{}/{:.1}/{}",
+                line_size.min(),
+                line_size.mean(),
+                line_size.max()
+            ),
+            format!(
+                "{}/{}/{}",
+                line_size.p50(),
+                line_size.p95(),
+                line_size.p99()
+            ),
+        ];
+        for idx in 0..options.numeric_fields.len() {
+            let field = elem.fields.get(idx).copied().unwrap_or_default();
+            row.push(match idx < sum_fields {
+                true => format!("{}", field.sum),
+                false => format!("{:.3}", field.avg()),
+            });
+        }
+        for distinct in &elem.distinct {
+            let approx = if distinct.is_exact() { "" } else { "~" };
+            row.push(format!(
+                "{}{}",
+                approx,
+                units::format_count(distinct.count())
+            ));
+        }
+        if options.timestamp_field.is_some() {
+            row.extend(seen_columns(elem.first_seen, elem.last_seen));
+        }
+        if options.dedup_field.is_some() {
+            row.push(units::format_count(elem.duplicates));
+        }
+        if let Some(estimator) = cost_estimator {
+            row.push(format!("{:.2}", estimator.monthly_cost(elem.num_of_bytes)));
+        }
+        if let Some(estimates) = estimates {
+            row.extend(estimate_columns(estimates.of_group(log_type)));
+        }
+        let style = if elem.errors == 0 {
+            Style::Plain
+        } else if elem.errors * 2 < elem.counter {
+            Style::Warning
+        } else {
+            Style::Error
+        };
+        rows.push((style, row));
+    }
+
+    let mut total = vec![
+        format!(
+            "total ({} {}s)",
+            units::format_count(report.log_register.len()),
+            key_name
+        ),
+        units::format_count(total_lines),
+        share(total_lines, total_lines),
+        units::format_binary_bytes(total_bytes),
+        share(total_bytes, total_bytes),
+        units::format_count(total_errors),
+        share(total_errors, total_lines),
+    ];
+    total.resize(header.len(), String::new());
+    if options.timestamp_field.is_some() {
+        let registers = report.log_register.values();
+        let first = registers.clone().filter_map(|elem| elem.first_seen).min();
+        let last = registers.filter_map(|elem| elem.last_seen).max();
+        total.splice(seen_column..seen_column + 3, seen_columns(first, last));
+    }
+    if let Some(estimator) = cost_estimator {
+        total[cost_column] = format!("{:.2}", estimator.monthly_cost(total_bytes));
+    }
+    if let Some(estimates) = estimates {
+        total.truncate(header.len() - 3);
+        total.extend(estimate_columns(estimates.total()));
+    }
+    rows.push((Style::Bold, total));
+
+    terminal::aligned_table(&header, &rows, &align)
+}
+
+/// Estimated lines, bytes and errors, in the units of the counted ones.
+fn estimate_columns((lines, bytes, errors): (f64, f64, f64)) -> [String; 3] {
+    [
+        format!("~{}", units::format_count(lines.round() as u64)),
+        format!("~{}", units::format_binary_bytes(bytes.round() as u64)),
+        format!("~{}", units::format_count(errors.round() as u64)),
+    ]
+}
+
+/// Counts of every group in subsets of the inputs extrapolated to the whole inputs.
+#[derive(Default)]
+pub(crate) struct Estimates {
+    sampled_lines: u64,
+    sampled_bytes: u64,
+    input_bytes: u64,
+    /// Estimated lines, bytes and errors.
+    groups: BTreeMap<String, (f64, f64, f64)>,
+}
+
+impl Estimates {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Estimates of a single input.
+    pub(crate) fn of(report: &ParseReport, subset_file: &SubsetFile) -> Self {
+        let mut estimates = Self::new();
+        estimates.add(report, subset_file);
+        estimates
+    }
+
+    pub(crate) fn add(&mut self, report: &ParseReport, subset_file: &SubsetFile) {
+        let scale = subset_file.scale();
+        self.sampled_lines += subset_file.lines();
+        self.sampled_bytes += subset_file.bytes();
+        self.input_bytes += subset_file.input_bytes();
+        for (key, elem) in &report.log_register {
+            let group = self.groups.entry(key.clone()).or_default();
+            group.0 += elem.counter as f64 * scale;
+            group.1 += elem.num_of_bytes as f64 * scale;
+            group.2 += elem.errors as f64 * scale;
+        }
+    }
+
+    /// Warn that counts are of a subset, the estimates are extrapolated to the whole inputs.
+    pub(crate) fn warn(&self, subset: Subset) {
+        warn!(
+            "SAMPLE: parsed {} ({} lines, {} of {}), counts are of the sample only, est. columns are estimated for the whole inputs",
+            subset,
+            units::format_count(self.sampled_lines),
+            units::format_binary_bytes(self.sampled_bytes),
+            units::format_binary_bytes(self.input_bytes)
+        );
+    }
+
+    fn of_group(&self, key: &str) -> (f64, f64, f64) {
+        self.groups.get(key).copied().unwrap_or_default()
+    }
+
+    fn total(&self) -> (f64, f64, f64) {
+        self.groups.values().fold((0.0, 0.0, 0.0), |total, group| {
+            (total.0 + group.0, total.1 + group.1, total.2 + group.2)
+        })
+    }
+}
+
+/// First and last timestamp and the span between them, `-` without timestamps.
+fn seen_columns(first: Option<SystemTime>, last: Option<SystemTime>) -> [String; 3] {
+    let time = |time: Option<SystemTime>| time.map_or("-".to_string(), timestamp::format);
+    let span = match (first, last) {
+        (Some(first), Some(last)) => {
+            let span = last.duration_since(first).unwrap_or_default();
+            humantime::format_duration(Duration::from_secs(span.as_secs())).to_string()
+        }
+        _ => "-".to_string(),
+    };
+    [time(first), time(last), span]
+}
+
+/// Log row of every log type (or group) with totals, and print the rate table if rates are
+/// counted. Leading `sum_fields` numeric fields are sums, the rest are averages.
+pub(crate) fn log_registers(
+    report: &ParseReport,
+    options: &ParseOptions,
+    sum_fields: usize,
+    cost_estimator: Option<&CostEstimator>,
+    estimates: Option<&Estimates>,
+    level: ReportLevel,
+) {
+    // registers are per group instead of log type with --group-by
+    let key_name = if options.group_by.is_empty() {
+        "log_type"
+    } else {
+        "group"
+    };
+    println!(
+        "{}",
+        register_table(
+            report,
+            options,
+            key_name,
+            sum_fields,
+            cost_estimator,
+            estimates,
+            level,
+        )
+    );
+    if level == ReportLevel::Full {
+        let mut log_types: Vec<_> = report.log_register.iter().collect();
+        log_types.sort_by_key(|(log_type, _)| *log_type);
+        let rows: Vec<(Style, Vec<String>)> = log_types
+            .into_iter()
+            .map(|(log_type, elem)| {
+                let histogram: Vec<String> = elem
+                    .line_size
+                    .histogram()
+                    .iter()
+                    .map(|(lower, count)| {
+                        format!("{}-{}: {}", lower, (lower * 2).max(1) - 1, count)
+                    })
+                    .collect();
+                (Style::Plain, vec![log_type.clone(), histogram.join(", ")])
+            })
+            .collect();
+        let header = [key_name.to_string(), "line size histogram".to_string()];
+        println!("{}", terminal::aligned_table(&header, &rows, &[]));
+    }
+
+    if let Some(dedup_field) = &options.dedup_field {
+        let duplicates: u64 = report
+            .log_register
+            .values()
+            .map(|elem| elem.duplicates)
+            .sum();
+        let action = if options.drop_duplicates {
+            "dropped"
+        } else {
+            "counted"
+        };
+        info!(
+            "Duplicates by {}: {} ({:.2}% of lines, {})",
+            dedup_field,
+            units::format_count(duplicates),
+            duplicates as f64 * 100.0 / report.lines.max(1) as f64,
+            action
+        );
+    }
+
+    if options.size_histogram && level != ReportLevel::Summary {
+        let (header, rows) = size_histogram_table(report, key_name);
+        println!("{}", table::markdown_table(&header, &rows));
+    }
+
+    if let Some(window) = options.rate_window {
+        let with_windows = level != ReportLevel::Summary;
+        let (header, rows) = rate_table(report, options, key_name, window, with_windows);
+        println!("{}", table::markdown_table(&header, &rows));
+    }
+}
+
+/// Milliseconds every thread spent in each stage with its share of the thread time, and the
+/// totals of all threads.
+pub(crate) fn profile_table(
+    profiles: &[(String, Arc<Profiler>)],
+) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut header = vec!["input".to_string(), "part".to_string(), "lines".to_string()];
+    header.extend(STAGES.iter().map(|stage| format!("{} ms", stage)));
+    header.push("total ms".to_string());
+
+    let row = |input: &str, part: String, thread: &ThreadProfile| {
+        let total = thread.total().as_secs_f64();
+        let mut row = vec![input.to_string(), part, thread.lines.to_string()];
+        for stage in STAGES {
+            let secs = thread.stage(stage).as_secs_f64();
+            let share = if total > 0.0 {
+                secs / total * 100.0
+            } else {
+                0.0
+            };
+            row.push(format!("{:.3} ({:.0}%)", secs * 1000.0, share));
+        }
+        row.push(format!("{:.3}", total * 1000.0));
+        row
+    };
+    let mut rows = vec![];
+    let mut all = ThreadProfile::default();
+    for (input, profiler) in profiles {
+        for thread in profiler.threads() {
+            rows.push(row(input, format!("bytes {}..", thread.start_idx), &thread));
+            all.lines += thread.lines;
+            all.read += thread.read;
+            all.parse += thread.parse;
+            all.aggregate += thread.aggregate;
+        }
+    }
+    rows.push(row("all", "all threads".to_string(), &all));
+
+    (header, rows)
+}
+
+/// Busy time and utilization of every pipeline stage: share of the parsing threads time for
+/// read, parse and aggregate, share of the reducers time merging with depths of their queue,
+/// and wall time of writing sinks.
+pub(crate) fn pipeline_table(metrics: &PipelineMetrics) -> (Vec<String>, Vec<Vec<String>>) {
+    let header = vec![
+        "stage".to_string(),
+        "busy ms".to_string(),
+        "utilization".to_string(),
+        "queue".to_string(),
+    ];
+    let millis = |elapsed: Duration| format!("{:.3}", elapsed.as_secs_f64() * 1000.0);
+    let percent = |share: f64| format!("{:.0}%", share * 100.0);
+
+    let mut rows: Vec<Vec<String>> = STAGES
+        .iter()
+        .map(|stage| {
+            vec![
+                stage.to_string(),
+                millis(metrics.threads.stage(*stage)),
+                percent(metrics.utilization(*stage)),
+                String::new(),
+            ]
+        })
+        .collect();
+    if let Some(utilization) = metrics.reducer_utilization() {
+        let queue = &metrics.queue;
+        rows.push(vec![
+            "reduce".to_string(),
+            millis(metrics.reducers.busy),
+            percent(utilization),
+            format!(
+                "mean {:.1}, max {} of {}, full {}",
+                queue.mean_depth,
+                queue.max_depth,
+                queue.capacity,
+                percent(metrics.queue_full().unwrap_or(0.0))
+            ),
+        ]);
+    }
+    rows.push(vec![
+        "sink".to_string(),
+        millis(metrics.sink),
+        String::new(),
+        String::new(),
+    ]);
+
+    (header, rows)
+}
+
+/// Line size histogram of every group as bars from its smallest to its largest power of two
+/// range of line sizes.
+fn size_histogram_table(report: &ParseReport, key_name: &str) -> (Vec<String>, Vec<Vec<String>>) {
+    let header = vec![
+        key_name.to_string(),
+        "lines".to_string(),
+        "line_size".to_string(),
+        "histogram".to_string(),
+    ];
+    let mut log_types: Vec<_> = report.log_register.iter().collect();
+    log_types.sort_by_key(|(log_type, _)| *log_type);
+    let rows = log_types
+        .into_iter()
+        .map(|(log_type, elem)| {
+            let line_size = &elem.line_size;
+            let histogram = line_size.histogram();
+            let range = match (histogram.first(), histogram.last()) {
+                (Some((first, _)), Some((last, _))) => {
+                    format!("{}-{}", first, (last * 2).max(1) - 1)
+                }
+                _ => String::new(),
+            };
+            vec![
+                log_type.clone(),
+                units::format_count(line_size.count()),
+                range,
+                format!("`{}`", line_size.sparkline()),
+            ]
+        })
+        .collect();
+
+    (header, rows)
+}
+
+/// Lines/s and error rate of every group per time window (if `with_windows`), groups with the
+/// highest rate first and every group starting with its overall rate. With an anomaly detector,
+/// anomalous windows are marked and the overall row tells when the first one started.
+fn rate_table(
+    report: &ParseReport,
+    options: &ParseOptions,
+    key_name: &str,
+    window: u64,
+    with_windows: bool,
+) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut header = vec![
+        key_name.to_string(),
+        "window".to_string(),
+        "lines".to_string(),
+        "lines/s".to_string(),
+        "error rate".to_string(),
+    ];
+    if options.anomaly.is_some() {
+        header.push("anomaly".to_string());
+    }
+    let window_time = |start| timestamp::format(UNIX_EPOCH + Duration::from_secs(start));
+    let mut groups: Vec<(&String, f64)> = report
+        .log_register
+        .iter()
+        .filter(|(_, elem)| !elem.windows.is_empty())
+        .map(|(key, elem)| (key, rate::lines_per_sec(elem, window)))
+        .collect();
+    groups.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+
+    let mut rows = vec![];
+    for (key, lines_per_sec) in groups {
+        let elem = &report.log_register[key];
+        let windowed: u64 = elem.windows.values().map(|count| count.lines).sum();
+        let errors: u64 = elem.windows.values().map(|count| count.errors).sum();
+        let mut series = rate::series(elem, window);
+        let mut all = vec![
+            key.clone(),
+            "all".to_string(),
+            units::format_count(windowed),
+            format!("{:.3}", lines_per_sec),
+            format!("{:.4}", errors as f64 / windowed as f64),
+        ];
+        if let Some(detector) = &options.anomaly {
+            rate::flag_anomalies(&mut series, detector);
+            let flagged = series.iter().filter(|rate| rate.anomaly.is_some());
+            all.push(match flagged.clone().next() {
+                Some(first) => format!(
+                    "{} windows, first at {}",
+                    flagged.count(),
+                    window_time(first.start)
+                ),
+                None => String::new(),
+            });
+        }
+        rows.push(all);
+        if !with_windows {
+            continue;
+        }
+        for window_rate in series {
+            let mut row = vec![
+                key.clone(),
+                window_time(window_rate.start),
+                units::format_count(window_rate.lines),
+                format!("{:.3}", window_rate.lines_per_sec),
+                format!("{:.4}", window_rate.error_rate),
+            ];
+            if options.anomaly.is_some() {
+                row.push(match window_rate.anomaly {
+                    Some(anomaly) => format!(
+                        "**{:+.0}%** ({:+.1} stddevs from {:.1})",
+                        anomaly.change * 100.0,
+                        anomaly.stddevs,
+                        anomaly.trailing_average
+                    ),
+                    None => String::new(),
+                });
+            }
+            rows.push(row);
+        }
+    }
+
+    (header, rows)
+}
+
+/// Log number of failed lines per error class, and per error kind with the most common first.
+pub(crate) fn log_error_kinds(report: &ParseReport) {
+    let classes: Vec<String> = error_log::classes(&report.error_kinds)
+        .iter()
+        .map(|(class, count)| format!("{}: {}", class, count))
+        .collect();
+    if !classes.is_empty() {
+        info!("Failed lines by class: {}", classes.join(", "));
+    }
+    let mut error_kinds: Vec<_> = report.error_kinds.iter().collect();
+    error_kinds.sort_by(|(a_kind, a), (b_kind, b)| b.cmp(a).then(a_kind.cmp(b_kind)));
+    for (kind, count) in error_kinds {
+        info!("Failed lines: {}, error: {}", count, kind);
+    }
+}
+
+/// Warn that the report is incomplete, with the offsets every interrupted part reached.
+pub(crate) fn log_unread_ranges(report: &ParseReport) {
+    if report.is_complete() {
+        return;
+    }
+    let unread: u64 = report
+        .unread_ranges
+        .iter()
+        .map(|unread_range| unread_range.end_idx - unread_range.start_idx)
+        .sum();
+    warn!(
+        "INCOMPLETE: the run was interrupted, the report covers lines parsed so far ({} bytes unread)",
+        unread
+    );
+    for unread_range in &report.unread_ranges {
+        warn!(
+            "Stopped at byte {} of the part ending at byte {}",
+            unread_range.start_idx, unread_range.end_idx
+        );
+    }
+}
+
+/// Print the aggregate of a streaming source and write it to sinks.
+pub(crate) fn write_snapshot(
+    name: &str,
+    report: &ParseReport,
+    options: &ParseOptions,
+    sum_fields: usize,
+    sinks: &[String],
+    level: ReportLevel,
+) -> Result<(), String> {
+    log_registers(report, options, sum_fields, None, None, level);
+    info!(
+        "Lines: {}, failed lines: {}",
+        report.lines, report.failed_lines
+    );
+    write_to_sinks(name, report, options, sinks)
+}
+
+pub(crate) fn write_to_sinks(
+    name: &str,
+    report: &ParseReport,
+    options: &ParseOptions,
+    sinks: &[String],
+) -> Result<(), String> {
+    for uri in sinks {
+        let mut sink = sink::open_sink(uri)?;
+        sink.write_report(name, report, options)
+            .and_then(|_| sink.finish())
+            .map_err(|err| format!("Can't write snapshot to {}: {}", uri, err))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,995 @@
+use crate::args::{
+    alert_rules, auto_tune, budgets, checkpoint, conditions, cost_estimator, csv_format,
+    max_errors, num_of_threads, open_stream, otlp_uri, parse_options, paths_of, progress,
+    quarantine, read_ranges, report_level, seen_ids, sink_uris, subset, sum_fields, token_bucket,
+    values_of, window, ReportLevel,
+};
+#[cfg(feature = "kafka")]
+use crate::output::write_snapshot;
+use crate::output::{
+    log_error_kinds, log_registers, log_unread_ranges, pipeline_table, profile_table, Estimates,
+};
+use crate::EXIT_TOO_MANY_ERRORS;
+use crate::{EXIT_FAILURE, EXIT_PANIC};
+use clap::ArgMatches;
+use log_parser::alert;
+use log_parser::budget;
+use log_parser::config::ParseConfig;
+use log_parser::decompress::Input;
+use log_parser::error_log;
+use log_parser::github::{self, IssueRef};
+use log_parser::interrupt;
+#[cfg(feature = "kafka")]
+use log_parser::kafka;
+use log_parser::memory::{self, Module};
+#[cfg(feature = "otlp")]
+use log_parser::otlp;
+use log_parser::parser;
+use log_parser::parser::{ParseError, ParseOptions, ParseReport};
+use log_parser::plan::{Analysis, Plan, Scan};
+use log_parser::profiling::{PipelineMetrics, Profiler};
+use log_parser::progress::{Progress, ProgressBar};
+use log_parser::retention::RetentionRule;
+use log_parser::scanner;
+use log_parser::signing::{self, SigningKey};
+use log_parser::sink::Sink;
+use log_parser::state::{SavedState, StateOptions};
+use log_parser::subset::{self, SubsetFile};
+use log_parser::summary::{ExitRecord, RunSummary};
+use log_parser::throttle::TokenBucket;
+use log_parser::{diagnostics, group, retention, sampling, sink, summary, table, units, watch};
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Parse files landing in the directory until the process is stopped.
+fn watch_dir(matches: &ArgMatches, dir: &Path) -> Result<(), String> {
+    let num_of_threads = num_of_threads(matches);
+    // poll interval has default value so we can unwrap
+    let poll_interval = humantime::parse_duration(matches.value_of("poll-interval").unwrap())
+        .map_err(|_| "Poll interval should be a duration, e.g. 1s, 500ms".to_string())?;
+    let options = parse_options(matches)?;
+    let level = report_level(matches);
+    // running totals before the last file, for per-file breakdowns
+    let mut previous = (0, 0);
+
+    info!("Watching {}...", dir.display());
+
+    let result = watch::watch(
+        num_of_threads,
+        dir,
+        &options,
+        poll_interval,
+        |file, running| {
+            info!(
+                "Parsed {}, total lines: {}, failed lines: {}",
+                file.display(),
+                running.lines,
+                running.failed_lines
+            );
+            if level == ReportLevel::Full {
+                info!(
+                    "{}: lines: {}, failed lines: {}",
+                    file.display(),
+                    running.lines - previous.0,
+                    running.failed_lines - previous.1
+                );
+            }
+            previous = (running.lines, running.failed_lines);
+            if level == ReportLevel::Summary {
+                return true;
+            }
+            let mut keys: Vec<&String> = running.log_register.keys().collect();
+            keys.sort();
+            for key in keys {
+                let elem = &running.log_register[key];
+                info!(
+                    "{}: {}, counter: {}, number_of_bytes: {}",
+                    if options.group_by.is_empty() {
+                        "log_type"
+                    } else {
+                        "group"
+                    },
+                    key,
+                    elem.counter,
+                    elem.num_of_bytes
+                );
+            }
+            true
+        },
+    );
+    result
+        .map(|_| ())
+        .map_err(|err| format!("Can't watch {}: {}", dir.display(), err))
+}
+
+/// Aggregate the Kafka topic, printing and sinking a report per window.
+#[cfg(feature = "kafka")]
+fn kafka(matches: &ArgMatches, brokers: &str) -> Result<(), String> {
+    // topic is required with brokers, group and window have default values so we can unwrap
+    let topic = matches.value_of("kafka-topic").unwrap();
+    let group = matches.value_of("kafka-group").unwrap();
+    let window = humantime::parse_duration(matches.value_of("kafka-window").unwrap())
+        .map_err(|_| "Kafka window should be a duration, e.g. 1m, 1h".to_string())?;
+    let sinks = sink_uris(matches)?;
+    let level = report_level(matches);
+    let options = parse_options(matches)?;
+    let sum_fields = sum_fields(matches, &options);
+
+    let mut consumer = kafka::Consumer::subscribe(brokers, topic, group)
+        .map_err(|err| format!("Can't consume {}: {}", topic, err))?;
+    let name = format!("kafka://{}/{}", brokers, topic);
+
+    info!("Consuming {} in group {}...", name, group);
+
+    let mut report = ParseReport::default();
+    let clock = options.clock();
+    let mut window_end = clock.instant() + window;
+    loop {
+        let timeout = window_end.saturating_duration_since(clock.instant());
+        match consumer.next_line(timeout) {
+            Ok(Some(line)) if line.trim().is_empty() => {}
+            Ok(Some(line)) => {
+                if let Err(err) = report.add_line(&line, &options) {
+                    error_log::log(&line, &err);
+                }
+            }
+            Ok(None) => {}
+            Err(err) => return Err(format!("Can't consume {}: {}", name, err)),
+        }
+
+        if clock.instant() >= window_end {
+            info!("Report of the last {}:", humantime::format_duration(window));
+            write_snapshot(&name, &report, &options, sum_fields, &sinks, level)?;
+            // records of reported windows aren't consumed again by a restarted group
+            consumer
+                .commit()
+                .map_err(|err| format!("Can't commit offsets of {}: {}", name, err))?;
+            report = ParseReport::default();
+            window_end += window;
+        }
+    }
+}
+
+#[cfg(not(feature = "kafka"))]
+fn kafka(_matches: &ArgMatches, _brokers: &str) -> Result<(), String> {
+    Err("Kafka input isn't supported in this build, build with `--features kafka`".to_string())
+}
+
+/// Parse the inputs, progress of every input is added to `progresses` as it starts.
+fn parse(matches: &ArgMatches, progresses: &mut Vec<Arc<Progress>>) -> Result<(), String> {
+    info!("Reading args...");
+    let started = Instant::now();
+    #[cfg(feature = "otlp")]
+    otlp::start_window(std::time::SystemTime::now());
+
+    if let Some(dir) = matches.value_of_os("watch-dir") {
+        return watch_dir(matches, Path::new(dir));
+    }
+    if let Some(brokers) = matches.value_of("kafka-brokers") {
+        return kafka(matches, brokers);
+    }
+    // input is required unless config is used, where it can be missing
+    let paths = paths_of(matches, "input");
+    if paths.is_empty() {
+        return Err("Input is required (--input or input in config)".to_string());
+    }
+    if matches.is_present("explain") {
+        print!("{}", parse_plan(matches)?);
+        return Ok(());
+    }
+    if paths.len() > 1 {
+        for flag in ["checkpoint", "quarantine", "save-state"] {
+            if matches.is_present(flag) {
+                return Err(format!("--{} supports a single input", flag));
+            }
+        }
+    }
+    let subset = subset(matches)?;
+    let files = paths
+        .iter()
+        .map(open_stream)
+        .collect::<Result<Vec<Input>, _>>()?;
+    // subsets and checkpoints are of parts of the file, so compressed input is copied first
+    let files = match subset.is_some() || matches.is_present("checkpoint") {
+        true => files
+            .into_iter()
+            .map(|file| {
+                let source = file.source().to_string();
+                file.seekable()
+                    .map(Input::from)
+                    .map_err(|err| format!("Can't open {}: {}", source, err))
+            })
+            .collect::<Result<_, _>>()?,
+        false => files,
+    };
+    // inputs as given, e.g. in reports
+    let inputs: Vec<String> = files.iter().map(|file| file.source().to_string()).collect();
+    let num_of_threads = num_of_threads(matches);
+    let strategy: parser::Strategy = matches
+        .value_of("strategy")
+        .map(str::parse)
+        .transpose()?
+        .unwrap_or_default();
+    let cost_estimator = cost_estimator(matches)?;
+    let options = ParseOptions {
+        bad_lines: quarantine(matches)?,
+        checkpoint: checkpoint(matches, &paths[0])?,
+        csv: csv_format(matches, &files)?,
+        filter: conditions(matches)?,
+        read_ranges: read_ranges(matches, &files)?,
+        ..parse_options(matches)?
+    };
+    let sum_fields = sum_fields(matches, &options);
+    if strategy == parser::Strategy::Single && options.checkpoint.is_some() {
+        return Err("Checkpoints are saved by multi thread strategies only".to_string());
+    }
+    let alert_rules = alert_rules(matches, &options)?;
+    if matches!(
+        strategy,
+        parser::Strategy::Single | parser::Strategy::Channel
+    ) && options.map_backend.is_some()
+    {
+        return Err("--map-backend is used by the dashmap and sharded strategies only".to_string());
+    }
+    let per_file = matches.is_present("per-file");
+    if let Some(max_examples) = matches.value_of("max-error-examples") {
+        error_log::set_max_examples(
+            max_examples
+                .parse()
+                .map_err(|_| "Max error examples should be a number".to_string())?,
+        );
+    }
+
+    if subset.is_some() {
+        let flags = [
+            "checkpoint",
+            "quarantine",
+            "multiline-start-pattern",
+            "multiline-json",
+            "json-mode",
+            "record-delimiter",
+            "has-header",
+        ];
+        for flag in flags {
+            if matches.is_present(flag) {
+                return Err(format!(
+                    "--{} can't be combined with --sample, --head or --tail",
+                    flag
+                ));
+            }
+        }
+    }
+    let subset_files: Vec<Option<SubsetFile>> = files
+        .iter()
+        .map(|file| {
+            subset
+                .map(|subset| {
+                    let path = file.file().expect("Subsets are of copied inputs");
+                    subset::extract(path, subset, 0)
+                        .map_err(|err| format!("Can't read {}: {}", file.source(), err))
+                })
+                .transpose()
+        })
+        .collect::<Result<_, _>>()?;
+    let num_of_threads = match matches.is_present("auto-tune") {
+        true => {
+            match files[0].file() {
+                Some(path) => auto_tune(matches, strategy, path, &options)?,
+                None => {
+                    warn!("--auto-tune has no effect on streamed input, it's parsed by a single thread");
+                    num_of_threads
+                }
+            }
+        }
+        false => num_of_threads,
+    };
+    // sinks are opened after parsing, but a wrong one is reported before it
+    let sinks = parse_sinks(matches)?;
+    let bucket = token_bucket(matches)?;
+
+    diagnostics::install(inputs.join(", "), matches.value_of("config"));
+
+    info!("Parsing (Ctrl-C stops it and reports lines parsed so far)...");
+
+    let now = Instant::now();
+
+    interrupt::install();
+    let mut report = ParseReport::default();
+    let mut estimates = Estimates::new();
+    let mut threads = vec![];
+    let profile = matches.is_present("profile") || matches.is_present("profile-folded");
+    let mut profiles = vec![];
+    // duplicates are counted across inputs, e.g. redelivered into the next rotated file
+    let seen_ids = seen_ids(matches)?;
+    let parsed_files = inputs.iter().zip(&files).zip(&subset_files);
+    for ((input, file), subset_file) in parsed_files {
+        let path = match subset_file {
+            Some(subset_file) => Some(subset_file.path()),
+            None => file.file(),
+        };
+        match (subset_file, file.file()) {
+            (Some(subset_file), _) => info!(
+                "File size in bytes: {} ({} sampled)",
+                subset_file.input_bytes(),
+                subset_file.bytes()
+            ),
+            (None, Some(path)) => {
+                if let Ok(metadata) = fs::metadata(path) {
+                    info!("File size in bytes: {}", metadata.len());
+                }
+            }
+            // streamed, the size isn't known
+            (None, None) => {}
+        }
+        if path.is_none() && matches.is_present("progress") {
+            warn!(
+                "No progress of {}, its size isn't known until it's streamed",
+                input
+            );
+        }
+        // lines are counted without --progress too, for the exit record of a failed run
+        let progress = match path.map(|path| progress(matches, path)).transpose()? {
+            Some(Some(progress)) => progress,
+            _ => Arc::new(Progress::new(0)),
+        };
+        progresses.push(progress.clone());
+        let options = ParseOptions {
+            progress: Some(progress),
+            profiler: profile.then(|| Arc::new(Profiler::new())),
+            seen_ids: seen_ids.clone(),
+            ..options.clone()
+        };
+        let progress_bar = options
+            .progress
+            .clone()
+            .filter(|_| path.is_some() && matches.is_present("progress"))
+            .map(ProgressBar::start);
+        let file_report = match subset_file {
+            Some(subset_file) => {
+                parser::parse_file(strategy, num_of_threads, subset_file.path(), &options)
+            }
+            None => parser::parse_input(strategy, num_of_threads, file, &options),
+        }
+        .map_err(|err| format!("Can't read {}: {}", input, err))?;
+        let file_estimates = subset_file.as_ref().map(|subset_file| {
+            estimates.add(&file_report, subset_file);
+            Estimates::of(&file_report, subset_file)
+        });
+        if let Some(progress_bar) = progress_bar {
+            progress_bar.finish();
+        }
+        if let Some(profiler) = &options.profiler {
+            profiles.push((input.clone(), profiler.clone()));
+        }
+        if let Some(progress) = &options.progress {
+            threads.extend(
+                progress
+                    .threads()
+                    .into_iter()
+                    .map(|thread| (input.clone(), thread)),
+            );
+        }
+        if per_file {
+            info!("Input {}:", input);
+            log_registers(
+                &file_report,
+                &options,
+                sum_fields,
+                cost_estimator.as_ref(),
+                file_estimates.as_ref(),
+                report_level(matches),
+            );
+        }
+        report += file_report;
+    }
+    let parse_time = now.elapsed();
+    if per_file {
+        info!("All {} inputs:", inputs.len());
+    }
+    if let Some(subset) = subset {
+        estimates.warn(subset);
+    }
+    log_registers(
+        &report,
+        &options,
+        sum_fields,
+        cost_estimator.as_ref(),
+        subset.map(|_| &estimates),
+        report_level(matches),
+    );
+
+    if matches.is_present("pivot") {
+        if options.group_by.len() < 2 {
+            return Err("Pivot needs at least two --group-by fields".to_string());
+        }
+        let (header, rows) = group::pivot(&report.log_register, &options.group_by[0], |elem| {
+            elem.counter
+        });
+        println!("{}", table::markdown_table(&header, &rows));
+    }
+
+    if let Some(estimator) = &cost_estimator {
+        let total_bytes = report
+            .log_register
+            .values()
+            .map(|elem| elem.num_of_bytes)
+            .sum();
+        info!(
+            "Projected monthly cost: {:.2}",
+            estimator.monthly_cost(total_bytes)
+        );
+    }
+
+    let retention_rules = values_of(matches, "retention")
+        .iter()
+        .map(|rule| RetentionRule::parse(rule))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !retention_rules.is_empty() {
+        let simulation = retention::simulate(
+            report
+                .log_register
+                .iter()
+                .map(|(log_type, elem)| (log_type.as_str(), elem)),
+            window(matches)?,
+            &retention_rules,
+        );
+        simulation.types.iter().for_each(|elem| {
+            let retention = elem
+                .retention_secs
+                .map(|secs| humantime::format_duration(Duration::from_secs(secs)).to_string())
+                .unwrap_or_else(|| "forever".to_string());
+            info!(
+                "log_type: {}, retention: {}, remaining_bytes: {}, saved_bytes: {}, steady_state_bytes: {}",
+                elem.log_type,
+                retention,
+                elem.remaining_bytes,
+                elem.saved_bytes,
+                elem.steady_state_bytes
+                    .map(|bytes| bytes.to_string())
+                    .unwrap_or_else(|| "unbounded".to_string())
+            );
+        });
+        info!(
+            "Retention would keep {} of {} bytes, saving {} bytes",
+            simulation.remaining_bytes, simulation.num_of_bytes, simulation.saved_bytes
+        );
+    }
+
+    let budgets = budgets(matches)?;
+    let mut over_budget = vec![];
+    if !budgets.is_empty() {
+        let statuses = budget::check(
+            report
+                .log_register
+                .iter()
+                .map(|(log_type, elem)| (log_type.as_str(), elem)),
+            window(matches)?,
+            &budgets,
+        );
+        for status in statuses {
+            info!(
+                "log_type: {}, daily_bytes: {}, budget: {}{}",
+                status.log_type,
+                status.daily_bytes,
+                status.budget_daily_bytes,
+                if status.over_budget {
+                    ", OVER BUDGET"
+                } else {
+                    ""
+                }
+            );
+            if status.over_budget {
+                over_budget.push(format!(
+                    "Log type {} daily volume {} exceeds its budget {}",
+                    status.log_type,
+                    units::format_bytes(status.daily_bytes as f64),
+                    units::format_bytes(status.budget_daily_bytes as f64)
+                ));
+            }
+        }
+    }
+
+    if let Some(target) = matches.value_of("target-daily-volume") {
+        let target = units::parse_bytes(target)?;
+        let policy = sampling::recommend(
+            report
+                .log_register
+                .iter()
+                .map(|(log_type, elem)| (log_type.as_str(), elem)),
+            window(matches)?,
+            target,
+        );
+        policy
+            .rates
+            .iter()
+            .for_each(|rate| info!("sample_rate: {:.4}, {}", rate.sample_rate, rate.explanation));
+        if !policy.target_reached {
+            info!("Target daily volume can't be reached without sampling error logs");
+        }
+
+        let policy = serde_json::to_string_pretty(&policy).expect("Can't serialize policy");
+        match matches.value_of("sampling-policy") {
+            Some(path) => fs::write(path, policy)
+                .map_err(|err| format!("Can't write sampling policy {}: {}", path, err))?,
+            None => println!("{}", policy),
+        }
+    }
+
+    let sink_started = Instant::now();
+    {
+        let _memory = memory::scope(Module::Sink);
+        write_sinks(&sinks, &files, &report, &options, num_of_threads, bucket)?;
+    }
+    let sink_time = sink_started.elapsed();
+    if let Some(path) = matches.value_of("save-state") {
+        let state = SavedState::new(
+            &inputs[0],
+            report.clone(),
+            StateOptions::new(&options, sum_fields),
+        );
+        state
+            .save(path)
+            .map_err(|err| format!("Can't save state {}: {}", path, err))?;
+    }
+
+    info!(
+        "Lines: {}, failed lines: {}, failed byte ranges: {}",
+        report.lines,
+        report.failed_lines,
+        report.failed_ranges.len()
+    );
+    let oversize_lines = summary::oversize_lines(&report);
+    if oversize_lines > 0 {
+        info!(
+            "Oversize lines (longer than {} bytes): {}",
+            units::format_count(options.max_line_bytes.unwrap_or(scanner::MAX_LINE_LEN)),
+            oversize_lines
+        );
+    }
+    // opening includes decompression, transcoding, subsets and tuning
+    let stages = [
+        ("open", now.duration_since(started)),
+        ("parse", parse_time),
+        ("output", now.elapsed() - parse_time),
+    ];
+    let wall_time = started.elapsed();
+    info!(
+        "Parsed in: {} ({})",
+        units::format_duration(wall_time),
+        stages
+            .iter()
+            .map(|(stage, elapsed)| format!("{} {}", stage, units::format_duration(*elapsed)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    if let Some(memory) = memory::snapshot() {
+        info!(
+            "Memory: peak {}, allocated by {}",
+            units::format_bytes(memory.peak_bytes as f64),
+            memory
+                .modules
+                .iter()
+                .map(|(module, usage)| format!(
+                    "{} {}",
+                    module,
+                    units::format_bytes(usage.allocated_bytes as f64)
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    if profile {
+        let (header, rows) = profile_table(&profiles);
+        println!("{}", table::markdown_table(&header, &rows));
+        let metrics = PipelineMetrics::new(
+            profiles.iter().map(|(_, profiler)| profiler.as_ref()),
+            parse_time,
+            sink_time,
+        );
+        let (header, rows) = pipeline_table(&metrics);
+        println!("{}", table::markdown_table(&header, &rows));
+        match metrics.bottleneck() {
+            Some(bottleneck) => info!("Bottleneck: {}", bottleneck),
+            None => info!("Bottleneck: none, no stage dominates the run"),
+        }
+    }
+    if let Some(path) = matches.value_of("profile-folded") {
+        let folded: String = profiles
+            .iter()
+            .map(|(input, profiler)| profiler.folded(input))
+            .collect();
+        fs::write(path, folded).map_err(|err| format!("Can't write profile {}: {}", path, err))?;
+        info!("Profile written to {}", path);
+    }
+    log_error_kinds(&report);
+    log_unread_ranges(&report);
+    if let Some(path) = matches.value_of("summary-json") {
+        let summary = RunSummary::new(
+            &inputs,
+            &report,
+            threads,
+            wall_time,
+            &stages,
+            effective_config(matches),
+        );
+        let json = serde_json::to_string_pretty(&summary).expect("Can't serialize run summary");
+        match path {
+            "-" => println!("{}", json),
+            path => fs::write(path, json + "\n")
+                .map_err(|err| format!("Can't write run summary {}: {}", path, err))?,
+        }
+    }
+
+    let mut violations = vec![];
+    if let Some(max_errors) = max_errors(matches)? {
+        if report.failed_lines > max_errors {
+            let violation = format!(
+                "Number of failed lines {} exceeds allowed maximum {}",
+                report.failed_lines, max_errors
+            );
+            error!("{}", violation);
+            violations.push(violation);
+        }
+    }
+    for violation in over_budget {
+        error!("{}", violation);
+        violations.push(violation);
+    }
+    let overflows = report.overflows();
+    if overflows > 0 {
+        let overflow = format!(
+            "Counters overflowed {} times, their totals are saturated at the maximum",
+            overflows
+        );
+        match matches.value_of("on-overflow") {
+            Some("error") => {
+                error!("{}", overflow);
+                violations.push(overflow);
+            }
+            _ => warn!("{}", overflow),
+        }
+    }
+
+    for error in sign_reports(matches) {
+        error!("{}", error);
+        violations.push(error);
+    }
+    let alerts = alert::evaluate(&alert_rules, &report, &options);
+    for fired in &alerts {
+        error!("{}", fired);
+        violations.push(fired.to_string());
+    }
+    if let (Some(url), false) = (matches.value_of("alert-webhook"), alerts.is_empty()) {
+        // like the comment below, a failed notification doesn't change the exit code
+        match alert::notify(url, &inputs.join(","), &report, &alerts, &options) {
+            Ok(()) => info!("{} alerts sent to {}", alerts.len(), url),
+            Err(err) => error!("Can't send alerts to {}: {}", url, err),
+        }
+    }
+
+    if let Some(issue) = matches.value_of("github-comment") {
+        let issue = IssueRef::parse(issue)?;
+        let token = env::var("GITHUB_TOKEN")
+            .map_err(|_| "GITHUB_TOKEN should be set to post comment".to_string())?;
+        let body = summary::markdown(&report, &violations);
+        // don't fail the run because of the comment, the exit code reports violations anyway
+        match github::post_comment(
+            matches.value_of("github-api-url").unwrap(),
+            &issue,
+            &token,
+            &body,
+        ) {
+            Ok(url) => info!("Summary posted: {}", url),
+            Err(err) => error!(
+                "Can't post summary to {}#{}: {}",
+                issue.repo, issue.number, err
+            ),
+        }
+    }
+
+    let (status, exit_code) = if !violations.is_empty() {
+        ("violations", EXIT_TOO_MANY_ERRORS)
+    } else if !report.is_complete() {
+        ("interrupted", interrupt::EXIT_INTERRUPTED)
+    } else {
+        ("ok", 0)
+    };
+    ExitRecord {
+        violations,
+        outputs: outputs(matches)?,
+        ..ExitRecord::new(status, exit_code, &report)
+    }
+    .emit();
+    if exit_code != 0 {
+        process::exit(exit_code);
+    }
+    Ok(())
+}
+
+/// Sign report files written by `parse` with the key of `--sign-key` (or the environment),
+/// errors of the files which couldn't be signed.
+fn sign_reports(matches: &ArgMatches) -> Vec<String> {
+    let key = match matches.value_of("sign-key") {
+        Some(path) => SigningKey::File(PathBuf::from(path)),
+        None => match SigningKey::from_env() {
+            Some(key) => key,
+            None => return vec![],
+        },
+    };
+    let mut reports: Vec<String> = ["output", "output-sqlite", "summary-json"]
+        .iter()
+        .filter_map(|flag| matches.value_of(flag))
+        .filter(|output| *output != "-")
+        .map(String::from)
+        .collect();
+    reports.extend(
+        values_of(matches, "sink")
+            .iter()
+            .filter_map(|uri| uri.strip_prefix("file://"))
+            .map(String::from),
+    );
+
+    let mut errors = vec![];
+    for report in reports {
+        match signing::sign(Path::new(&report), &key) {
+            Ok(signature) => info!("Signed {} into {}", report, signature.display()),
+            Err(err) => errors.push(format!("Report {} isn't signed: {}", report, err)),
+        }
+    }
+    errors
+}
+
+/// Run `parse`, an error or a panic before its exit record is emitted gets a failed one.
+pub(crate) fn parse_and_record_exit(matches: &ArgMatches) -> Result<(), String> {
+    let mut progresses = vec![];
+    let result = panic::catch_unwind(AssertUnwindSafe(|| parse(matches, &mut progresses)));
+    // the report is lost with the failed run, its lines are taken from the progress
+    let lines = || -> u64 {
+        progresses
+            .iter()
+            .map(|progress| progress.consumed().1)
+            .sum()
+    };
+    match result {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => {
+            // logged here, as the exit record is the last line of stderr
+            error!("{}", err);
+            ExitRecord::failed(EXIT_FAILURE, err, lines()).emit();
+            process::exit(EXIT_FAILURE);
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| {
+                    panic
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                })
+                .unwrap_or_default();
+            ExitRecord::failed(EXIT_PANIC, message, lines()).emit();
+            panic::resume_unwind(panic);
+        }
+    }
+}
+
+/// Files and URIs `parse` writes results to, credentials of URIs are left out.
+fn outputs(matches: &ArgMatches) -> Result<Vec<String>, String> {
+    let flags = [
+        "output",
+        "output-sqlite",
+        "quarantine",
+        "save-state",
+        "checkpoint",
+        "summary-json",
+        "profile-folded",
+    ];
+    let mut outputs: Vec<String> = flags
+        .iter()
+        .filter_map(|flag| matches.value_of(flag))
+        .filter(|output| *output != "-")
+        .map(String::from)
+        .collect();
+    outputs.extend(
+        sink_uris(matches)?
+            .iter()
+            .map(|uri| without_credentials(uri)),
+    );
+    Ok(outputs)
+}
+
+/// URI without the `user:password@` part.
+fn without_credentials(uri: &str) -> String {
+    let Some((scheme, rest)) = uri.split_once("://") else {
+        return uri.to_string();
+    };
+    let authority_end = rest.find('/').unwrap_or(rest.len());
+    match rest[..authority_end].rfind('@') {
+        Some(at) => format!("{}://{}", scheme, &rest[at + 1..]),
+        None => uri.to_string(),
+    }
+}
+
+/// Flags of the `parse` run as config keys with their values from the command line, the config
+/// file or defaults.
+fn effective_config(matches: &ArgMatches) -> Value {
+    let keys = serde_json::to_value(ParseConfig::default()).expect("Can't serialize config");
+    let keys = keys.as_object().expect("Config is an object");
+    let mut config = serde_json::Map::new();
+    for (key, default) in keys {
+        let flag = key.replace('_', "-");
+        if !matches.is_present(&flag) {
+            continue;
+        }
+        let value = match default {
+            Value::Bool(_) => Value::Bool(true),
+            Value::Array(_) => json!(values_of(matches, &flag)),
+            _ => json!(matches.value_of(&flag)),
+        };
+        config.insert(key.clone(), value);
+    }
+
+    Value::Object(config)
+}
+
+/// Write results into sinks given by URI with a flag whether they store records too, records
+/// of the inputs are parsed again with given number of threads.
+/// URIs of the sinks of parse, with whether records are written to them after the report.
+fn parse_sinks(matches: &ArgMatches) -> Result<Vec<(String, bool)>, String> {
+    // --output and --output-sqlite are shorthands of file:// and sqlite:// sinks
+    let mut sinks = vec![];
+    if let Some(output) = matches.value_of("output") {
+        if !output.ends_with(".parquet") {
+            return Err(format!(
+                "Unsupported output format: [{}], use .parquet",
+                output
+            ));
+        }
+        sinks.push((format!("file://{}", output), false));
+    }
+    if let Some(output) = matches.value_of("output-sqlite") {
+        sinks.push((
+            format!("sqlite://{}", output),
+            matches.is_present("sqlite-records"),
+        ));
+    }
+    for uri in values_of(matches, "sink") {
+        sinks.push((uri, matches.is_present("sink-records")));
+    }
+    // only the report is exported
+    sinks.extend(otlp_uri(matches)?.map(|uri| (uri, false)));
+    Ok(sinks)
+}
+
+/// Analyses requested by the flags of parse (or its config) and the passes running them.
+fn parse_plan(matches: &ArgMatches) -> Result<Plan, String> {
+    let mut analyses = vec![];
+    if subset(matches)?.is_some() {
+        analyses.push(Analysis::scan("subset of every input", Scan::Subset));
+    }
+    if matches.is_present("auto-tune") {
+        analyses.push(Analysis::scan("thread tuning", Scan::Trial));
+    }
+    analyses.push(Analysis::scan(
+        match matches.is_present("group-by") {
+            true => "report per group",
+            false => "report per log type",
+        },
+        Scan::Full,
+    ));
+    let sharing = [
+        ("count-distinct", "distinct counts"),
+        ("rate-window", "rate windows"),
+        ("dedup-field", "duplicates"),
+        ("quarantine", "quarantine"),
+        ("checkpoint", "checkpoint"),
+        ("profile", "profile"),
+    ];
+    for (flag, name) in sharing {
+        if matches.is_present(flag) {
+            analyses.push(Analysis::scan(name, Scan::Full));
+        }
+    }
+    for (uri, records) in parse_sinks(matches)? {
+        let uri = without_credentials(&uri);
+        analyses.push(Analysis::report(&format!("report to {}", uri)));
+        // sinks write records after the report, so they can't share its pass
+        if records {
+            analyses.push(Analysis::scan_after_report(
+                &format!("records to {}", uri),
+                Scan::Full,
+            ));
+        }
+    }
+    let from_report = [
+        ("pivot", "pivot"),
+        ("cost-per-gb", "cost"),
+        ("retention", "retention"),
+        ("budget", "budgets"),
+        ("target-daily-volume", "sampling policy"),
+        ("alert", "alerts"),
+        ("save-state", "saved state"),
+        ("summary-json", "run summary"),
+        ("github-comment", "GitHub comment"),
+        ("sign-key", "signatures"),
+    ];
+    for (flag, name) in from_report {
+        if matches.is_present(flag) {
+            analyses.push(Analysis::report(name));
+        }
+    }
+    Ok(Plan::new(&analyses))
+}
+
+/// Write the report into the sinks, and records of the inputs into sinks of records.
+fn write_sinks(
+    uris: &[(String, bool)],
+    inputs: &[Input],
+    report: &ParseReport,
+    options: &ParseOptions,
+    num_of_threads: u8,
+    mut bucket: Option<TokenBucket>,
+) -> Result<(), String> {
+    let source = inputs
+        .iter()
+        .map(Input::source)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut sinks: Vec<(&str, bool, Box<dyn Sink>)> = uris
+        .iter()
+        .map(|(uri, records)| {
+            let mut sink = sink::open_sink(uri)?;
+            sink.write_report(&source, report, options)
+                .map_err(|err| format!("Can't write results to {}: {}", uri, err))?;
+            Ok((uri.as_str(), *records, sink))
+        })
+        .collect::<Result<_, String>>()?;
+
+    if sinks.iter().any(|(_, records, _)| *records) {
+        // quarantine and progress are already done by the first pass
+        let options = ParseOptions {
+            bad_lines: None,
+            progress: None,
+            ..options.clone()
+        };
+        for input in inputs {
+            let records = parser::stream_input(num_of_threads, input, &options)
+                .map_err(|err| format!("Can't read {}: {}", input.source(), err))?;
+            for record in records {
+                // failed lines are already reported
+                let record = match record {
+                    Ok(record) => record,
+                    Err(err) if ParseError::of(&err).is_some() => continue,
+                    Err(err) => return Err(format!("Can't read {}: {}", input.source(), err)),
+                };
+                if let Some(bucket) = bucket.as_mut() {
+                    bucket.take();
+                }
+                for (uri, _, sink) in sinks.iter_mut().filter(|(_, records, _)| *records) {
+                    sink.write_record(&record, &options)
+                        .map_err(|err| format!("Can't write records to {}: {}", uri, err))?;
+                }
+            }
+        }
+    }
+
+    if let Some(bucket) = bucket.filter(|bucket| !bucket.waited().is_zero()) {
+        info!(
+            "Records waited for the rate limit: {}",
+            units::format_duration(bucket.waited())
+        );
+    }
+    for (uri, _, sink) in sinks {
+        sink.finish()
+            .map_err(|err| format!("Can't write results to {}: {}", uri, err))?;
+        info!("Results written to {}", uri);
+    }
+    Ok(())
+}
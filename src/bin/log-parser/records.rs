@@ -0,0 +1,437 @@
+use crate::args::{
+    budgets, conditions, csv_format, dedup, derive, num_of_threads, open_input, paths_of, pattern,
+    read_ranges, record_delimiter, token_bucket, values_of,
+};
+use clap::ArgMatches;
+use log_parser::anonymize::{self, Anonymizer};
+use log_parser::budget::BudgetTracker;
+use log_parser::clock::SystemClock;
+use log_parser::decompress::Decompressed;
+use log_parser::parser::ParseOptions;
+use log_parser::preview::{self, PreviewRecord};
+use log_parser::projection::{self, Condition, Projection};
+use log_parser::split::{self, Split};
+use log_parser::terminal;
+use log_parser::throttle::ThrottledWriter;
+use log_parser::{replay, reverse, seek, sink, table, timestamp, units};
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+pub(crate) fn replay(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required or have default values so we can unwrap
+    let input = open_input(matches.value_of_os("input").unwrap())?;
+    let input = input.path();
+    let timestamp_field = matches.value_of("timestamp-field").unwrap();
+    let speed: f64 = matches
+        .value_of("speed")
+        .unwrap()
+        .parse()
+        .map_err(|_| "Speed should be a number".to_string())?;
+    if speed < 0.0 {
+        return Err("Speed can't be negative".to_string());
+    }
+    let mut sink = sink::open(matches.value_of("sink").unwrap())?;
+    if let Some(bucket) = token_bucket(matches)? {
+        sink = sink::throttled(sink, bucket);
+    }
+    let mut budgets = BudgetTracker::new(budgets(matches)?);
+
+    info!("Replaying...");
+
+    let summary = replay::replay(
+        input,
+        sink.as_mut(),
+        timestamp_field,
+        speed,
+        &mut budgets,
+        &SystemClock,
+    )
+    .map_err(|err| format!("Can't send records to the sink: {}", err))?;
+
+    info!(
+        "Replayed {} records ({} skipped, {} budget alerts) in: {}",
+        summary.sent,
+        summary.skipped,
+        summary.alerts,
+        units::format_duration(summary.elapsed)
+    );
+    Ok(())
+}
+
+pub(crate) fn anonymize(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required so we can unwrap
+    let input = open_input(matches.value_of_os("input").unwrap())?;
+    let input = input.path();
+    let output = matches.value_of("output").unwrap();
+    let mut anonymizer = Anonymizer::new(values_of(matches, "field"));
+
+    info!("Anonymizing...");
+
+    let now = Instant::now();
+
+    let summary = anonymize::anonymize_file(input, output, &mut anonymizer)
+        .map_err(|err| format!("Can't anonymize {}: {}", input.display(), err))?;
+
+    info!(
+        "Lines: {}, dropped lines (not JSON): {}",
+        summary.lines, summary.failed_lines
+    );
+    info!("Anonymized in: {}", units::format_duration(now.elapsed()));
+    Ok(())
+}
+
+pub(crate) fn select(matches: &ArgMatches) -> Result<(), String> {
+    if matches.is_present("split-by") {
+        return split(matches);
+    }
+    info!("Reading args...");
+
+    // args are required or have default values so we can unwrap
+    let files = paths_of(matches, "input")
+        .iter()
+        .map(open_input)
+        .collect::<Result<Vec<Decompressed>, _>>()?;
+    let num_of_threads = num_of_threads(matches);
+    let fields: Vec<String> = matches
+        .value_of("select")
+        .unwrap()
+        .split(',')
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect();
+    if fields.is_empty() {
+        return Err("At least one field should be selected".to_string());
+    }
+    let conditions: Vec<Condition> = values_of(matches, "where")
+        .iter()
+        .map(|condition| Condition::parse(condition))
+        .collect::<Result<_, _>>()?;
+    let format = match matches.value_of("format").unwrap() {
+        "csv" => projection::Format::Csv,
+        _ => projection::Format::Ndjson,
+    };
+    let projection = Projection::new(fields, conditions, format);
+    let (dedup_field, dedup_window) = dedup(matches)?;
+    let mut out: Box<dyn Write> = match matches.value_of("output") {
+        Some(output) => Box::new(BufWriter::new(
+            fs::File::create(output).map_err(|err| format!("Can't create {}: {}", output, err))?,
+        )),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    if let Some(bucket) = token_bucket(matches)? {
+        out = Box::new(ThrottledWriter::new(out, bucket));
+    }
+
+    info!("Selecting...");
+
+    let now = Instant::now();
+
+    let options = ParseOptions {
+        dedup_field,
+        dedup_window,
+        pattern: pattern(matches)?,
+        derive: derive(matches)?,
+        record_delimiter: record_delimiter(matches)?,
+        timestamp_field: matches.value_of("timestamp-field").map(str::to_string),
+        read_ranges: read_ranges(matches, &files)?,
+        ..ParseOptions::default()
+    };
+    let inputs: Vec<&Path> = files.iter().map(Decompressed::path).collect();
+    let summary = match matches.is_present("chronological") {
+        true => projection::project_chronological(
+            num_of_threads,
+            &inputs,
+            &options,
+            &projection,
+            out.as_mut(),
+        ),
+        false => projection::project_files(
+            num_of_threads,
+            &inputs,
+            &options,
+            &projection,
+            matches.is_present("ordered"),
+            out.as_mut(),
+        ),
+    };
+    let summary = match summary {
+        Ok(summary) => summary,
+        // output closed early, e.g. piped to head
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+        Err(err) => return Err(format!("Can't write selected records: {}", err)),
+    };
+
+    info!(
+        "Records: {}, matched: {}, failed lines: {}",
+        summary.records, summary.matched, summary.failed_lines
+    );
+    info!("Selected in: {}", units::format_duration(now.elapsed()));
+    Ok(())
+}
+
+fn split(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required or have default values so we can unwrap
+    let files = paths_of(matches, "input")
+        .iter()
+        .map(open_input)
+        .collect::<Result<Vec<Decompressed>, _>>()?;
+    let fields: Vec<String> = values_of(matches, "split-by")
+        .iter()
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect();
+    if fields.is_empty() {
+        return Err("At least one field should be split by".to_string());
+    }
+    let conditions = conditions(matches)?;
+    let max_files: usize = matches
+        .value_of("max-files")
+        .unwrap()
+        .parse()
+        .map_err(|_| "Max files should be a number".to_string())?;
+    let out_dir = PathBuf::from(matches.value_of("out-dir").unwrap());
+    let split = Split::new(fields, conditions, out_dir.clone(), max_files);
+
+    info!("Splitting...");
+
+    let now = Instant::now();
+
+    let options = ParseOptions {
+        pattern: pattern(matches)?,
+        derive: derive(matches)?,
+        record_delimiter: record_delimiter(matches)?,
+        read_ranges: read_ranges(matches, &files)?,
+        ..ParseOptions::default()
+    };
+    let inputs: Vec<&Path> = files.iter().map(Decompressed::path).collect();
+    let summary = split::split_files(&inputs, &options, &split)
+        .map_err(|err| format!("Can't split into {}: {}", out_dir.display(), err))?;
+
+    let rows: Vec<Vec<String>> = summary
+        .files
+        .iter()
+        .map(|file| {
+            vec![
+                file.group.clone(),
+                file.path.display().to_string(),
+                file.lines.to_string(),
+            ]
+        })
+        .collect();
+    let header = ["group".to_string(), "file".to_string(), "lines".to_string()];
+    println!("{}", table::markdown_table(&header, &rows));
+    if summary.overflow_lines > 0 {
+        warn!(
+            "More than {} groups, {} lines of the rest are in {}",
+            max_files,
+            summary.overflow_lines,
+            out_dir
+                .join(format!("{}.{}", split::OVERFLOW, split::EXTENSION))
+                .display()
+        );
+    }
+    info!(
+        "Records: {}, matched: {}, failed lines: {}",
+        summary.records, summary.matched, summary.failed_lines
+    );
+    info!("Split in: {}", units::format_duration(now.elapsed()));
+    Ok(())
+}
+
+pub(crate) fn head(matches: &ArgMatches) -> Result<(), String> {
+    // args are required or have default values so we can unwrap
+    let input = open_input(matches.value_of_os("input").unwrap())?;
+    let num_of_records = matches
+        .value_of("lines")
+        .unwrap()
+        .parse()
+        .map_err(|_| "Number of records should be a number".to_string())?;
+    let options = preview_options(matches, &input)?;
+
+    let records = preview::records(input.path(), num_of_records, &options)
+        .map_err(|err| format!("Can't read {}: {}", input.source(), err))?;
+    let color = terminal::color();
+    let mut out = render_records(&records, color);
+    if let Some(csv) = &options.csv {
+        let mut flags = match csv.delimiter {
+            '\t' => "--format tsv".to_string(),
+            ',' => "--format csv".to_string(),
+            delimiter => format!("--format csv --delimiter '{}'", delimiter),
+        };
+        if csv.has_header {
+            flags.push_str(" --has-header");
+        }
+        out.push_str(&format!("\nParse flags of the format: {}\n", flags));
+    }
+    let detected = preview::detected_fields(&records);
+    if !detected.is_empty() {
+        out.push_str("\nFields parse makes use of:\n");
+        let width = detected
+            .keys()
+            .map(|field| field.len())
+            .max()
+            .unwrap_or_default();
+        for (field, role) in &detected {
+            let hint = match role.flag() {
+                Some(flag) => format!("  {} {}", flag, field),
+                None => String::new(),
+            };
+            let row = format!(
+                "  {:width$}  {:9}{}",
+                field,
+                role.to_string(),
+                hint,
+                width = width
+            );
+            out.push_str(row.trim_end());
+            out.push('\n');
+        }
+    }
+    write_preview(&out)
+}
+
+pub(crate) fn last(matches: &ArgMatches) -> Result<(), String> {
+    // args are required or have default values so we can unwrap
+    let input = open_input(matches.value_of_os("input").unwrap())?;
+    let num_of_records = matches
+        .value_of("lines")
+        .unwrap()
+        .parse()
+        .map_err(|_| "Number of records should be a number".to_string())?;
+    let conditions: Vec<Condition> = values_of(matches, "where")
+        .iter()
+        .map(|condition| Condition::parse(condition))
+        .collect::<Result<_, _>>()?;
+    let options = preview_options(matches, &input)?;
+
+    let records = reverse::last_records(input.path(), num_of_records, &options, &conditions)
+        .map_err(|err| format!("Can't read {}: {}", input.source(), err))?;
+    if records.len() < num_of_records {
+        info!("Found {} records, the input holds no more", records.len());
+    }
+    let color = terminal::color();
+    write_preview(&render_records(&records, color))
+}
+
+pub(crate) fn seek(matches: &ArgMatches) -> Result<(), String> {
+    // args are required or have default values so we can unwrap
+    let input = open_input(matches.value_of_os("input").unwrap())?;
+    let at = timestamp::parse_str(matches.value_of("at").unwrap()).ok_or_else(|| {
+        "Time should be an RFC 3339 timestamp or epoch number, e.g. 2024-03-01T12:00:00Z"
+            .to_string()
+    })?;
+    let timestamp_field = matches.value_of("timestamp-field").unwrap();
+    let context = matches
+        .value_of("lines")
+        .unwrap()
+        .parse()
+        .map_err(|_| "Number of records should be a number".to_string())?;
+    let options = preview_options(matches, &input)?;
+
+    let position = seek::seek(input.path(), at, timestamp_field, context, &options)
+        .map_err(|err| format!("Can't read {}: {}", input.source(), err))?;
+    info!("Searched in {} reads", position.probes);
+    let color = terminal::color();
+    let mut out = String::new();
+    for record in &position.before {
+        let title = format!("record -{}", record.number);
+        render_record(&mut out, &title, record, color);
+    }
+    match position.timestamp {
+        Some(timestamp) => info!(
+            "First record at or after {} is at byte {} ({})",
+            timestamp::format(at),
+            position.offset,
+            timestamp::format(timestamp)
+        ),
+        None => info!(
+            "No record at or after {} with {}, the last records are shown",
+            timestamp::format(at),
+            timestamp_field
+        ),
+    }
+    for record in &position.after {
+        let title = match record.number {
+            0 => format!("record at byte {}", position.offset),
+            number => format!("record +{}", number),
+        };
+        render_record(&mut out, &title, record, color);
+    }
+    write_preview(&out)
+}
+
+/// Parse options of the input format of `head`, `last` and `seek`, detected by the first lines with
+/// `--format auto`.
+fn preview_options(matches: &ArgMatches, input: &Decompressed) -> Result<ParseOptions, String> {
+    let format = matches.value_of("format").unwrap();
+    let mut options = ParseOptions {
+        pattern: pattern(matches)?,
+        derive: derive(matches)?,
+        record_delimiter: record_delimiter(matches)?,
+        ..ParseOptions::default()
+    };
+    if options.pattern.is_some() && format != "auto" {
+        return Err("--pattern can't be combined with --format".to_string());
+    }
+    options.csv = match format {
+        "auto" if options.pattern.is_none() => preview::detect_format(input.path(), &options)
+            .map_err(|err| format!("Can't read {}: {}", input.source(), err))?,
+        _ => csv_format(matches, std::slice::from_ref(input))?,
+    };
+    match (&options.pattern, &options.csv) {
+        (Some(_), _) => info!("Format: lines matched by the pattern"),
+        (_, Some(csv)) if csv.has_header => info!(
+            "Format: CSV delimited by {:?} with header {}",
+            csv.delimiter,
+            csv.columns.join(", ")
+        ),
+        (_, Some(csv)) => info!(
+            "Format: CSV delimited by {:?} without header",
+            csv.delimiter
+        ),
+        (None, None) => info!("Format: JSON lines"),
+    }
+
+    Ok(options)
+}
+
+/// Records of `head` and `last` with the reason of failed lines.
+fn render_records(records: &[PreviewRecord], color: bool) -> String {
+    let mut out = String::new();
+    for record in records {
+        render_record(
+            &mut out,
+            &format!("record {}", record.number),
+            record,
+            color,
+        );
+    }
+    out
+}
+
+/// Append the record with the title and the reason if it's a failed line.
+fn render_record(out: &mut String, title: &str, record: &PreviewRecord, color: bool) {
+    out.push_str(&format!("--- {} ---\n", title));
+    out.push_str(&record.render(color));
+    out.push('\n');
+    if let Some(error) = &record.error {
+        out.push_str(&format!("failed line: {}\n", error));
+    }
+}
+
+/// Print records of `head`, `last` and `seek`.
+fn write_preview(out: &str) -> Result<(), String> {
+    match io::stdout().write_all(out.as_bytes()) {
+        // output closed early, e.g. piped to head
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.map_err(|err| format!("Can't write records: {}", err)),
+    }
+}
@@ -0,0 +1,235 @@
+use crate::args::{num_of_threads, open_input, pattern, record_delimiter, report_level, values_of};
+use crate::output::{log_error_kinds, log_registers};
+use clap::ArgMatches;
+use log_parser::diff;
+use log_parser::parser;
+use log_parser::parser::ParseOptions;
+use log_parser::reingest::{self, Overrides};
+use log_parser::rollup::{self, Format, Period};
+use log_parser::run::{self, ParserOptions};
+use log_parser::state::{self, SavedState};
+use log_parser::units;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::time::Instant;
+
+pub(crate) fn rollup(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // args are required or have default values so we can unwrap
+    let dir = matches.value_of("dir").unwrap();
+    let period = match matches.value_of("period").unwrap() {
+        "month" => Period::Month,
+        _ => Period::Week,
+    };
+    let format = match matches.value_of("format").unwrap() {
+        "html" => Format::Html,
+        _ => Format::Markdown,
+    };
+
+    info!("Rolling up...");
+
+    let now = Instant::now();
+
+    let exports =
+        rollup::load_dir(dir).map_err(|err| format!("Can't read directory {}: {}", dir, err))?;
+    let rollups = rollup::rollup(&exports, period);
+    let report = rollup::render(&rollups, format);
+    match matches.value_of("output") {
+        Some(output) => fs::write(output, report)
+            .map_err(|err| format!("Can't write report {}: {}", output, err))?,
+        None => print!("{}", report),
+    }
+
+    info!(
+        "Rolled up {} daily exports into {} periods in: {}",
+        exports.len(),
+        rollups.len(),
+        units::format_duration(now.elapsed())
+    );
+    Ok(())
+}
+
+pub(crate) fn diff(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    let options = ParseOptions {
+        group_by: values_of(matches, "group-by"),
+        pattern: pattern(matches)?,
+        record_delimiter: record_delimiter(matches)?,
+        ..ParseOptions::default()
+    };
+    // args are required so we can unwrap
+    let run_options = |input| {
+        ParserOptions::builder()
+            .input(input)
+            .threads(num_of_threads(matches))
+            .strategy(parser::Strategy::Dashmap)
+            .parse_options(options.clone())
+            .build()
+            .map_err(|err| err.to_string())
+    };
+    let options_a = run_options(matches.value_of_os("input-a").unwrap())?;
+    let options_b = run_options(matches.value_of_os("input-b").unwrap())?;
+
+    info!("Parsing...");
+
+    let now = Instant::now();
+
+    let report_a = run::run(&options_a).map_err(|err| err.to_string())?;
+    let report_b = run::run(&options_b).map_err(|err| err.to_string())?;
+    let diffs = diff::diff(&report_a, &report_b);
+    let key_name = if options.group_by.is_empty() {
+        "log_type"
+    } else {
+        "group"
+    };
+    print!("{}", diff::markdown_table(&diffs, key_name));
+
+    let count = |change| diffs.iter().filter(|diff| diff.change == change).count();
+    info!(
+        "{}s: {} new, {} disappeared, {} in both",
+        key_name,
+        count(diff::Change::New),
+        count(diff::Change::Disappeared),
+        count(diff::Change::Kept)
+    );
+    info!(
+        "Lines: {} -> {}, failed lines: {} -> {}",
+        report_a.lines, report_b.lines, report_a.failed_lines, report_b.failed_lines
+    );
+    info!("Compared in: {}", units::format_duration(now.elapsed()));
+    Ok(())
+}
+
+pub(crate) fn merge(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    let states = values_of(matches, "states")
+        .iter()
+        .map(SavedState::load)
+        .collect::<Result<_, _>>()?;
+
+    info!("Merging...");
+
+    let now = Instant::now();
+
+    let merged = state::merge(states)?;
+    let report = &merged.report;
+    log_registers(
+        report,
+        &merged.options.parse_options(),
+        merged.options.sum_fields,
+        None,
+        None,
+        report_level(matches),
+    );
+    if let Some(path) = matches.value_of("save-state") {
+        merged
+            .save(path)
+            .map_err(|err| format!("Can't save state {}: {}", path, err))?;
+    }
+
+    info!(
+        "Lines: {}, failed lines: {}, failed byte ranges: {}",
+        report.lines,
+        report.failed_lines,
+        report.failed_ranges.len()
+    );
+    info!(
+        "Merged {} inputs in: {}",
+        merged.inputs.len(),
+        units::format_duration(now.elapsed())
+    );
+    Ok(())
+}
+
+pub(crate) fn reingest(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // quarantine file and state are required so we can unwrap
+    let source = matches.value_of("quarantine").unwrap();
+    let state_path = matches.value_of("state").unwrap();
+    let mut state = SavedState::load(state_path)?;
+    let overrides = Overrides {
+        renames: values_of(matches, "rename")
+            .iter()
+            .map(|rename| Overrides::parse_rename(rename))
+            .collect::<Result<_, _>>()?,
+        default_type: matches.value_of("default-type").map(String::from),
+        coerce_types: matches.is_present("coerce-types"),
+    };
+    let options = ParseOptions {
+        pattern: pattern(matches)?,
+        ..state.options.parse_options()
+    };
+    let quarantine = open_input(source)?;
+    let reader = BufReader::new(
+        File::open(quarantine.path()).map_err(|err| format!("Can't open {}: {}", source, err))?,
+    );
+    let mut remaining = matches
+        .value_of("remaining")
+        .map(|path| {
+            File::create(path)
+                .map(BufWriter::new)
+                .map_err(|err| format!("Can't create {}: {}", path, err))
+        })
+        .transpose()?;
+
+    info!("Reingesting...");
+
+    let now = Instant::now();
+
+    let reingested = reingest::reingest(
+        source,
+        reader,
+        &mut state,
+        &options,
+        &overrides,
+        |quarantined| {
+            if let Some(remaining) = remaining.as_mut() {
+                serde_json::to_writer(&mut *remaining, &quarantined)
+                    .expect("Can't serialize quarantined line");
+                remaining
+                    .write_all(b"\n")
+                    .expect("Can't write remaining lines");
+            }
+        },
+    )?;
+    if let Some(mut remaining) = remaining {
+        remaining
+            .flush()
+            .map_err(|err| format!("Can't write remaining lines: {}", err))?;
+    }
+
+    let report = &state.report;
+    log_registers(
+        report,
+        &options,
+        state.options.sum_fields,
+        None,
+        None,
+        report_level(matches),
+    );
+    log_error_kinds(report);
+    if let Some(path) = matches.value_of("save-state") {
+        state
+            .save(path)
+            .map_err(|err| format!("Can't save state {}: {}", path, err))?;
+    }
+
+    info!(
+        "Lines: {}, failed lines: {}, failed byte ranges: {}",
+        report.lines,
+        report.failed_lines,
+        report.failed_ranges.len()
+    );
+    info!(
+        "Recovered {} of {} quarantined lines, still failing: {}, in: {}",
+        reingested.recovered,
+        reingested.lines,
+        reingested.failing,
+        units::format_duration(now.elapsed())
+    );
+    Ok(())
+}
@@ -0,0 +1,241 @@
+use crate::args::{
+    normalizations, paths_of, pattern, rate_window, report_level, sink_uris, token_bucket,
+    values_of,
+};
+use crate::output::{write_snapshot, write_to_sinks};
+use clap::ArgMatches;
+use log_parser::error_log;
+use log_parser::follow::{self, Followed, FollowedFile};
+use log_parser::interrupt;
+use log_parser::normalize::{self, LOOKUP_RELOAD_INTERVAL};
+#[cfg(feature = "otlp")]
+use log_parser::otlp;
+use log_parser::parser;
+use log_parser::parser::{ParseOptions, ParseReport};
+use log_parser::projection::{self, Condition, Projection};
+use log_parser::serve::{Credentials, Server};
+use log_parser::sink::BatchPolicy;
+use log_parser::spool::{Spool, SpooledSink};
+use log_parser::tls::ServerTls;
+use log_parser::tui::Dashboard;
+use log_parser::{sink, units};
+use std::env;
+use std::io;
+use std::mem;
+use std::path::PathBuf;
+
+pub(crate) fn agent(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // poll and snapshot intervals have default values so we can unwrap
+    let poll_interval = humantime::parse_duration(matches.value_of("poll-interval").unwrap())
+        .map_err(|_| "Poll interval should be a duration, e.g. 1s, 500ms".to_string())?;
+    let interval = humantime::parse_duration(matches.value_of("snapshot-interval").unwrap())
+        .map_err(|_| "Snapshot interval should be a duration, e.g. 10s, 1m".to_string())?;
+    let from_start = matches.is_present("from-start");
+    let tui = matches.is_present("tui");
+    if tui && matches.value_of("forward") == Some("-") {
+        return Err("Lines can't be forwarded to stdout with the dashboard on it".to_string());
+    }
+    let mut files: Vec<FollowedFile> = paths_of(matches, "file")
+        .iter()
+        .map(|path| FollowedFile::new(path, from_start))
+        .collect();
+    let conditions: Vec<Condition> = values_of(matches, "where")
+        .iter()
+        .map(|condition| Condition::parse(condition))
+        .collect::<Result<_, _>>()?;
+    let mut forward = matches
+        .value_of("forward")
+        .map(|uri| match matches.value_of("spool-dir") {
+            Some(dir) => {
+                // spool max size has default value so we can unwrap
+                let max_size = units::parse_bytes(matches.value_of("spool-max-size").unwrap())?;
+                let spool = Spool::open(dir, max_size)
+                    .map_err(|err| format!("Can't open spool {}: {}", dir, err))?;
+                // batches are formed before spooling, so spooled ones are delivered as they are
+                let (uri, policy) = BatchPolicy::from_uri(uri)?;
+                Ok(sink::batched(
+                    Box::new(SpooledSink::new(&uri, spool)),
+                    policy,
+                ))
+            }
+            None => sink::open(uri),
+        })
+        .transpose()?;
+    if let Some(bucket) = token_bucket(matches)? {
+        forward = forward.map(|sink| sink::throttled(sink, bucket));
+    }
+    let sinks = sink_uris(matches)?;
+    let level = report_level(matches);
+    let options = ParseOptions {
+        group_by: values_of(matches, "group-by"),
+        normalize: normalizations(matches)?,
+        pattern: pattern(matches)?,
+        ..ParseOptions::default()
+    };
+    normalize::watch_lookup_tables(&options.normalize, LOOKUP_RELOAD_INTERVAL);
+    // only fields of conditions are extracted for filtering
+    let has_conditions = !conditions.is_empty();
+    let filter = Projection::new(vec![], conditions, projection::Format::Ndjson);
+    let filter_options = filter.parse_options(&options);
+
+    info!("Following {} files...", files.len());
+    #[cfg(feature = "otlp")]
+    otlp::start_window(options.clock().now());
+
+    let mut dashboard = tui
+        .then(|| {
+            // the screen is restored on Ctrl-C instead of exiting right away
+            interrupt::install();
+            Dashboard::start(&format!("log-parser agent, {} files", files.len()))
+                .map_err(|err| format!("Can't start dashboard: {}", err))
+        })
+        .transpose()?;
+    let mut report = ParseReport::default();
+    let mut polled = ParseReport::default();
+    let (mut forwarded, mut dropped) = (0, 0);
+    let followed = follow::follow(&mut files, &options, poll_interval, interval, |event| {
+        match event {
+            Followed::Lines(path, lines) => {
+                for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+                    // lines which can't be parsed are forwarded only if there is no filter
+                    let matching = !has_conditions
+                        || parser::parse_line(line, &filter_options)
+                            .is_ok_and(|record| filter.matches(&record));
+                    if !matching {
+                        dropped += 1;
+                        continue;
+                    }
+                    forwarded += 1;
+                    if let Some(forward) = forward.as_mut() {
+                        forward.send(line).map_err(|err| {
+                            io::Error::other(format!("Can't forward lines: {}", err))
+                        })?;
+                    }
+                    // sizes count the new line character like in files
+                    if let Err(err) = polled.add_line_of_size(line, line.len() as u64 + 1, &options)
+                    {
+                        match dashboard.as_mut() {
+                            Some(dashboard) => {
+                                dashboard.error(&path.display().to_string(), line, &err)
+                            }
+                            None => error_log::log(line, &err),
+                        }
+                    }
+                }
+            }
+            Followed::Polled { snapshot } => {
+                if let Some(forward) = forward.as_mut() {
+                    forward
+                        .flush()
+                        .map_err(|err| io::Error::other(format!("Can't forward lines: {}", err)))?;
+                }
+                if let Some(dashboard) = dashboard.as_mut() {
+                    dashboard.update(polled.clone());
+                    dashboard
+                        .draw(&format!(
+                            "matching lines: {}, dropped lines: {}",
+                            forwarded, dropped
+                        ))
+                        .map_err(|err| {
+                            io::Error::other(format!("Can't draw dashboard: {}", err))
+                        })?;
+                }
+                report += mem::take(&mut polled);
+
+                if snapshot {
+                    match dashboard {
+                        // snapshots are only written to sinks, logs would break the dashboard
+                        Some(_) => write_to_sinks("agent", &report, &options, &sinks),
+                        None => {
+                            info!("Matching lines: {}, dropped lines: {}", forwarded, dropped);
+                            write_snapshot("agent", &report, &options, 0, &sinks, level)
+                        }
+                    }
+                    .map_err(io::Error::other)?;
+                    report = ParseReport::default();
+                }
+            }
+        }
+        Ok(true)
+    });
+
+    drop(dashboard);
+    followed.map_err(|err| err.to_string())?;
+    info!("Matching lines: {}, dropped lines: {}", forwarded, dropped);
+    Ok(())
+}
+
+pub(crate) fn serve(matches: &ArgMatches) -> Result<(), String> {
+    info!("Reading args...");
+
+    // listen has default value so we can unwrap
+    let address = matches.value_of("listen").unwrap();
+    let options = ParseOptions {
+        group_by: values_of(matches, "group-by"),
+        normalize: normalizations(matches)?,
+        pattern: pattern(matches)?,
+        timestamp_field: matches.value_of("timestamp-field").map(String::from),
+        rate_window: rate_window(matches)?,
+        ..ParseOptions::default()
+    };
+    normalize::watch_lookup_tables(&options.normalize, LOOKUP_RELOAD_INTERVAL);
+
+    let credentials = serve_credentials(matches)?;
+
+    let mut server = Server::bind(address, options)
+        .map_err(|err| format!("Can't listen on {}: {}", address, err))?
+        .credentials(&credentials);
+    let scheme = match (matches.value_of("tls-cert"), matches.value_of("tls-key")) {
+        (Some(cert), Some(key)) => {
+            let tls = ServerTls {
+                cert: PathBuf::from(cert),
+                key: PathBuf::from(key),
+                client_ca: matches.value_of("tls-client-ca").map(PathBuf::from),
+            };
+            server = server.tls(&tls)?;
+            "https"
+        }
+        _ => "http",
+    };
+    let local_addr = server.local_addr().expect("Listener has an address");
+    if credentials.is_empty() && !local_addr.ip().is_loopback() {
+        warn!(
+            "No credentials are set, anyone who can connect to {} can read the stats",
+            local_addr
+        );
+    }
+    info!(
+        "Listening on {}://{}, POST NDJSON records to any path, GET /stats for the aggregate, /grafana for Grafana...",
+        scheme, local_addr
+    );
+
+    if let Err(err) = server.run() {
+        return Err(format!("Can't accept connections on {}: {}", address, err));
+    }
+    Ok(())
+}
+
+/// Credentials of serve from flags (or config), environment variables otherwise.
+fn serve_credentials(matches: &ArgMatches) -> Result<Vec<Credentials>, String> {
+    let env_values = |name: &str| env::var(name).map(|value| vec![value]).unwrap_or_default();
+    let mut tokens = values_of(matches, "token");
+    let mut users = values_of(matches, "basic-auth");
+    if tokens.is_empty() && users.is_empty() {
+        tokens = env_values("LOG_PARSER_SERVE_TOKEN");
+        users = env_values("LOG_PARSER_SERVE_BASIC_AUTH");
+    }
+
+    let mut credentials = vec![];
+    for token in tokens {
+        if token.is_empty() {
+            return Err("Token can't be empty".to_string());
+        }
+        credentials.push(Credentials::Bearer(token));
+    }
+    for user in users {
+        credentials.push(Credentials::basic(&user)?);
+    }
+    Ok(credentials)
+}
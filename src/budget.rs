@@ -0,0 +1,177 @@
+use crate::parser::LogRegister;
+use crate::units;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+/// Budget matching every log type without its own budget.
+const DEFAULT_BUDGET: &str = "*";
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Streams are projected only after this much time, the first seconds say nothing about a day.
+const MIN_ELAPSED: Duration = Duration::from_secs(60);
+
+/// Max bytes of a log type per period, e.g. `checkout=2GB/day` or `*=500MB/1h`.
+#[derive(Debug, Clone)]
+pub struct ByteBudget {
+    pub log_type: String,
+    pub num_of_bytes: u64,
+    pub period: Duration,
+}
+
+impl ByteBudget {
+    /// Parse budget in `<log_type>=<size>[/<period>]` format (period defaults to a day),
+    /// `*` matches every other log type.
+    pub fn parse(budget: &str) -> Result<Self, String> {
+        let (log_type, limit) = budget
+            .split_once('=')
+            .ok_or_else(|| format!("Budget should be <type>=<size>/<period>: [{}]", budget))?;
+        let log_type = log_type.trim();
+        if log_type.is_empty() {
+            return Err(format!("Budget without log type: [{}]", budget));
+        }
+        let (size, period) = limit.split_once('/').unwrap_or((limit, "day"));
+        let num_of_bytes = units::parse_bytes(size)?;
+        // `day` is read as `1day`
+        let period = period.trim();
+        let period = if period.starts_with(|c: char| c.is_ascii_digit()) {
+            humantime::parse_duration(period)
+        } else {
+            humantime::parse_duration(&format!("1{}", period))
+        }
+        .map_err(|err| format!("Invalid period in budget [{}]: {}", budget, err))?;
+        if period.as_secs_f64() <= 0.0 {
+            return Err(format!(
+                "Budget period should be greater than 0: [{}]",
+                budget
+            ));
+        }
+
+        Ok(Self {
+            log_type: log_type.to_string(),
+            num_of_bytes,
+            period,
+        })
+    }
+
+    pub fn daily_bytes(&self) -> f64 {
+        self.num_of_bytes as f64 * DAY.as_secs_f64() / self.period.as_secs_f64()
+    }
+}
+
+/// Find budget of the log type, the default budget if it has none.
+fn find<'a>(budgets: &'a [ByteBudget], log_type: &str) -> Option<&'a ByteBudget> {
+    budgets
+        .iter()
+        .find(|budget| budget.log_type == log_type)
+        .or_else(|| {
+            budgets
+                .iter()
+                .find(|budget| budget.log_type == DEFAULT_BUDGET)
+        })
+}
+
+/// Daily volume of a log type compared to its budget.
+#[derive(Serialize, Debug)]
+pub struct BudgetStatus {
+    pub log_type: String,
+    pub budget_daily_bytes: u64,
+    /// Volume seen in the analyzed window extrapolated to a day.
+    pub daily_bytes: u64,
+    pub over_budget: bool,
+}
+
+/// Compare daily volume of log types with their budgets, log types without budget are skipped.
+pub fn check<'a, I>(register: I, window: Duration, budgets: &[ByteBudget]) -> Vec<BudgetStatus>
+where
+    I: IntoIterator<Item = (&'a str, &'a LogRegister)>,
+{
+    let mut statuses: Vec<BudgetStatus> = register
+        .into_iter()
+        .filter_map(|(log_type, value)| {
+            let budget = find(budgets, log_type)?;
+            let daily_bytes = value.num_of_bytes as f64 * DAY.as_secs_f64() / window.as_secs_f64();
+            Some(BudgetStatus {
+                log_type: log_type.to_string(),
+                budget_daily_bytes: budget.daily_bytes() as u64,
+                daily_bytes: daily_bytes as u64,
+                over_budget: daily_bytes > budget.daily_bytes(),
+            })
+        })
+        .collect();
+    statuses.sort_by(|a, b| a.log_type.cmp(&b.log_type));
+
+    statuses
+}
+
+/// Log type whose projected daily volume exceeds its budget.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BudgetAlert {
+    pub log_type: String,
+    pub budget_daily_bytes: u64,
+    pub projected_daily_bytes: u64,
+}
+
+impl fmt::Display for BudgetAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "log type {} will exceed its budget: projected {}/day, budget {}/day",
+            self.log_type,
+            units::format_bytes(self.projected_daily_bytes as f64),
+            units::format_bytes(self.budget_daily_bytes as f64)
+        )
+    }
+}
+
+/// Projects daily volume of log types while records are streamed and alerts (once per log
+/// type) when the projection exceeds the budget.
+pub struct BudgetTracker {
+    budgets: Vec<ByteBudget>,
+    first: Option<SystemTime>,
+    num_of_bytes: HashMap<String, u64>,
+    alerted: HashSet<String>,
+}
+
+impl BudgetTracker {
+    pub fn new(budgets: Vec<ByteBudget>) -> Self {
+        Self {
+            budgets,
+            first: None,
+            num_of_bytes: HashMap::new(),
+            alerted: HashSet::new(),
+        }
+    }
+
+    /// Register record seen at the given time (log timestamp or time of arrival), returns
+    /// alert the first time the log type is projected over its budget.
+    pub fn observe(
+        &mut self,
+        log_type: &str,
+        num_of_bytes: u64,
+        time: SystemTime,
+    ) -> Option<BudgetAlert> {
+        let first = *self.first.get_or_insert(time);
+        let budget = find(&self.budgets, log_type)?;
+        let total = self.num_of_bytes.entry(log_type.to_string()).or_default();
+        *total += num_of_bytes;
+
+        let elapsed = time.duration_since(first).ok()?;
+        if elapsed < MIN_ELAPSED || self.alerted.contains(log_type) {
+            return None;
+        }
+        let projected = *total as f64 * DAY.as_secs_f64() / elapsed.as_secs_f64();
+        if projected <= budget.daily_bytes() {
+            return None;
+        }
+
+        self.alerted.insert(log_type.to_string());
+        Some(BudgetAlert {
+            log_type: log_type.to_string(),
+            budget_daily_bytes: budget.daily_bytes() as u64,
+            projected_daily_bytes: projected as u64,
+        })
+    }
+}
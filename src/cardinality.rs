@@ -0,0 +1,235 @@
+use crate::aggregator::Aggregator;
+use crate::distinct::{self, DistinctCounter};
+use crate::parser::ParsedRecord;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+
+/// Counters kept per wanted top value, more counters make counts of the top values exact
+/// for longer.
+const COUNTERS_PER_TOP_VALUE: usize = 10;
+const MIN_COUNTERS: usize = 100;
+
+/// Counter of a value of [`SpaceSaving`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Counter {
+    count: u64,
+    /// How much the count may be over the real number of the value.
+    error: u64,
+}
+
+/// Most frequent values of a stream in fixed memory (the space-saving algorithm): a value
+/// without a counter takes over the counter of the least frequent one when all are used, so
+/// counts are upper bounds off by at most their error. Every value seen more than
+/// `observed / capacity` times has a counter.
+#[derive(Debug, Clone)]
+pub struct SpaceSaving {
+    capacity: usize,
+    observed: u64,
+    counters: HashMap<String, Counter>,
+    /// Values ordered by their count, the first one is replaced next.
+    by_count: BTreeSet<(u64, String)>,
+}
+
+impl SpaceSaving {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            observed: 0,
+            counters: HashMap::new(),
+            by_count: BTreeSet::new(),
+        }
+    }
+
+    pub fn observe(&mut self, value: &str) {
+        self.observed += 1;
+        if let Some(counter) = self.counters.get_mut(value) {
+            self.by_count.remove(&(counter.count, value.to_string()));
+            counter.count += 1;
+            self.by_count.insert((counter.count, value.to_string()));
+            return;
+        }
+        let counter = match self.counters.len() < self.capacity {
+            true => Counter { count: 1, error: 0 },
+            false => {
+                let (count, replaced) = self.by_count.pop_first().expect("Counters are full");
+                self.counters.remove(&replaced);
+                Counter {
+                    count: count + 1,
+                    error: count,
+                }
+            }
+        };
+        self.counters.insert(value.to_string(), counter);
+        self.by_count.insert((counter.count, value.to_string()));
+    }
+
+    /// Count values without a counter may have had, zero until all counters are used.
+    fn floor(&self) -> u64 {
+        match self.counters.len() < self.capacity {
+            true => 0,
+            false => self.by_count.first().map_or(0, |(count, _)| *count),
+        }
+    }
+
+    /// Merge the summary of another part of the stream, counts stay upper bounds: a value
+    /// missing from one summary may have had up to its floor there.
+    pub fn merge(&mut self, other: Self) {
+        let (floor, other_floor) = (self.floor(), other.floor());
+        let mut merged: HashMap<String, Counter> = HashMap::new();
+        for (value, counter) in self.counters.drain() {
+            let counter = match other.counters.get(&value) {
+                Some(other) => Counter {
+                    count: counter.count + other.count,
+                    error: counter.error + other.error,
+                },
+                None => Counter {
+                    count: counter.count + other_floor,
+                    error: counter.error + other_floor,
+                },
+            };
+            merged.insert(value, counter);
+        }
+        for (value, counter) in other.counters {
+            merged.entry(value).or_insert(Counter {
+                count: counter.count + floor,
+                error: counter.error + floor,
+            });
+        }
+
+        let mut merged: Vec<(String, Counter)> = merged.into_iter().collect();
+        merged.sort_unstable_by(|(value, a), (other, b)| {
+            b.count.cmp(&a.count).then_with(|| value.cmp(other))
+        });
+        merged.truncate(self.capacity);
+        self.observed += other.observed;
+        self.by_count = merged
+            .iter()
+            .map(|(value, counter)| (counter.count, value.clone()))
+            .collect();
+        self.counters = merged.into_iter().collect();
+    }
+
+    /// The most frequent values, most frequent first (ties by value).
+    pub fn top(&self, num_of_values: usize) -> Vec<HeavyHitter> {
+        let mut by_count: Vec<&(u64, String)> = self.by_count.iter().collect();
+        by_count.sort_unstable_by(|(count, value), (other_count, other)| {
+            other_count.cmp(count).then_with(|| value.cmp(other))
+        });
+        by_count
+            .into_iter()
+            .take(num_of_values)
+            .map(|(count, value)| HeavyHitter {
+                value: value.clone(),
+                count: *count,
+                error: self.counters[value].error,
+            })
+            .collect()
+    }
+}
+
+/// Frequent value of a field.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HeavyHitter {
+    pub value: String,
+    /// Upper bound of the number of records with the value.
+    pub count: u64,
+    /// How much the count may be over, the count is exact if it's zero.
+    pub error: u64,
+}
+
+/// Cardinality and heavy hitters of a field.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldProfile {
+    pub field: String,
+    /// Records with the field.
+    pub records: u64,
+    /// Records without the field (or with `null`).
+    pub missing: u64,
+    pub distinct: u64,
+    /// Whether the distinct count is exact, it's a HyperLogLog estimate otherwise.
+    pub distinct_exact: bool,
+    pub top: Vec<HeavyHitter>,
+}
+
+#[derive(Debug, Clone)]
+struct FieldSketch {
+    missing: u64,
+    distinct: DistinctCounter,
+    top: SpaceSaving,
+}
+
+/// Approximate distinct counts and most frequent values of fields, in memory independent of
+/// the number of values. Fields are read from [`ParsedRecord::string_values`], so they have
+/// to be the [`ParseOptions::string_fields`](crate::parser::ParseOptions::string_fields) of
+/// the parse in the same order.
+#[derive(Debug, Clone)]
+pub struct FieldProfiler {
+    fields: Vec<String>,
+    num_of_top: usize,
+    sketches: Vec<FieldSketch>,
+}
+
+impl FieldProfiler {
+    /// Profiler of the fields reporting `num_of_top` most frequent values of each.
+    pub fn new(fields: Vec<String>, num_of_top: usize) -> Self {
+        let capacity = num_of_top
+            .saturating_mul(COUNTERS_PER_TOP_VALUE)
+            .max(MIN_COUNTERS);
+        let sketches = fields
+            .iter()
+            .map(|_| FieldSketch {
+                missing: 0,
+                distinct: DistinctCounter::new(),
+                top: SpaceSaving::new(capacity),
+            })
+            .collect();
+
+        Self {
+            fields,
+            num_of_top,
+            sketches,
+        }
+    }
+
+    pub fn profiles(&self) -> Vec<FieldProfile> {
+        self.fields
+            .iter()
+            .zip(&self.sketches)
+            .map(|(field, sketch)| FieldProfile {
+                field: field.clone(),
+                records: sketch.top.observed,
+                missing: sketch.missing,
+                distinct: sketch.distinct.count(),
+                distinct_exact: sketch.distinct.is_exact(),
+                top: sketch.top.top(self.num_of_top),
+            })
+            .collect()
+    }
+}
+
+impl Aggregator for FieldProfiler {
+    fn observe(&mut self, record: &ParsedRecord) {
+        for (sketch, value) in self.sketches.iter_mut().zip(&record.string_values) {
+            match value {
+                Some(value) => {
+                    sketch.distinct.add(distinct::hash_value(value));
+                    sketch.top.observe(value);
+                }
+                None => sketch.missing += 1,
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        for (sketch, other) in self.sketches.iter_mut().zip(other.sketches) {
+            sketch.missing += other.missing;
+            sketch.distinct += other.distinct;
+            sketch.top.merge(other.top);
+        }
+    }
+
+    fn report(&self) -> Value {
+        json!({ "fields": self.profiles() })
+    }
+}
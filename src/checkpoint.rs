@@ -0,0 +1,123 @@
+use crate::parser::LogRegister;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Progress of a part of the file read by a single thread.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct PartProgress {
+    /// Bytes of the part already read, the next record starts here.
+    pub bytes_read: u64,
+    pub lines: u64,
+    pub bad_lines: u64,
+    /// Number of bad lines per error kind.
+    #[serde(default)]
+    pub error_kinds: BTreeMap<String, u64>,
+    /// Whole part is read.
+    pub done: bool,
+    /// Registers of the records read so far.
+    pub register: HashMap<String, LogRegister>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct State {
+    key: String,
+    /// Progress per start index of the part.
+    parts: BTreeMap<u64, PartProgress>,
+}
+
+/// Checkpoint file with progress and partial registers of every part of the file, saved
+/// periodically by parsing threads. A run with the same key resumes every part from its last
+/// saved record instead of parsing it again, the file is removed once the whole input is parsed.
+///
+/// Parts are given by the number of threads, so the key has to change with it (as well as with
+/// options and input, see [`Checkpoint::open`]).
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    path: PathBuf,
+    interval: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+impl Checkpoint {
+    /// Load checkpoint of the run identified by the key (e.g. arguments and input file size),
+    /// checkpoints of other runs are ignored and replaced.
+    pub fn open<P: Into<PathBuf>>(path: P, key: &str, interval: Duration) -> io::Result<Self> {
+        let path = path.into();
+        let mut state = State {
+            key: key.to_string(),
+            parts: BTreeMap::new(),
+        };
+        if path.exists() {
+            let file = BufReader::new(File::open(&path)?);
+            match serde_json::from_reader::<_, State>(file) {
+                Ok(saved) if saved.key == key => {
+                    info!(
+                        "Resuming from checkpoint {} ({} parts started)",
+                        path.display(),
+                        saved.parts.len()
+                    );
+                    state = saved;
+                }
+                Ok(_) => warn!(
+                    "Checkpoint {} is of a different run, starting over",
+                    path.display()
+                ),
+                Err(err) => warn!(
+                    "Checkpoint {} is corrupted ({}), starting over",
+                    path.display(),
+                    err
+                ),
+            }
+        }
+
+        Ok(Self {
+            path,
+            interval,
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// How often parsing threads save their progress.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Last saved progress of the part starting at given index.
+    pub(crate) fn part(&self, start_idx: u64) -> Option<PartProgress> {
+        let state = self.state.lock().expect("Checkpoint lock is poisoned");
+        state.parts.get(&start_idx).cloned()
+    }
+
+    /// Save progress of the part, the file is replaced at once so a crash while saving keeps
+    /// the previous checkpoint.
+    pub(crate) fn save(&self, start_idx: u64, part: &PartProgress) -> io::Result<()> {
+        let mut state = self.state.lock().expect("Checkpoint lock is poisoned");
+        state.parts.insert(start_idx, part.clone());
+
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer(&mut writer, &*state)?;
+        writer.flush()?;
+        fs::rename(&temp_path, &self.path)
+    }
+
+    /// Remove checkpoint of a finished run.
+    pub(crate) fn remove(&self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                warn!("Can't remove checkpoint {}: {}", self.path.display(), err);
+            }
+        }
+    }
+}
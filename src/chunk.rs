@@ -0,0 +1,185 @@
+use crate::clock::Clock;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::panic::{self, AssertUnwindSafe};
+use std::thread::ScopedJoinHandle;
+use std::time::Duration;
+
+/// Number of attempts to parse a part of the file before it is reported as failed.
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// Number of retries of a failed read by default.
+pub const IO_RETRIES: u32 = 3;
+
+/// Delay before the first retry of a failed read by default, in milliseconds.
+pub const IO_RETRY_DELAY_MS: u64 = 100;
+
+/// Bytes skipped at a time past a read which fails even after retries.
+pub const SKIPPED_BYTES: u64 = 64 * 1024;
+
+/// Part of the file which couldn't be parsed even after retries.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FailedRange {
+    pub start_idx: u64,
+    /// Lines crossing the end belong to the part where they start, so the range is approximate.
+    pub end_idx: u64,
+    pub attempts: u32,
+    /// Error of the last attempt.
+    pub error: String,
+}
+
+impl fmt::Display for FailedRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Bytes {}..{} skipped after {} attempts: {}",
+            self.start_idx, self.end_idx, self.attempts, self.error
+        )
+    }
+}
+
+impl std::error::Error for FailedRange {}
+
+/// Rest of a part of the file which wasn't read because the run was interrupted.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct UnreadRange {
+    /// Offset of the first record which wasn't read.
+    pub start_idx: u64,
+    /// Lines crossing the end belong to the part where they start, so the range is approximate.
+    pub end_idx: u64,
+}
+
+/// Reader retrying reads which fail with an I/O error, e.g. transient errors of network file
+/// systems (NFS, SMB). The delay before the first retry doubles with every further one. A failed
+/// read doesn't move the position in a file, so a retry reads the same bytes.
+pub struct RetryReader<'a, R> {
+    inner: R,
+    retries: u32,
+    delay: Duration,
+    clock: &'a dyn Clock,
+}
+
+impl<'a, R> RetryReader<'a, R> {
+    pub fn new(inner: R, retries: u32, delay: Duration, clock: &'a dyn Clock) -> Self {
+        Self {
+            inner,
+            retries,
+            delay,
+            clock,
+        }
+    }
+}
+
+impl<R: Read> Read for RetryReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut delay = self.delay;
+        let mut retry = 0;
+        loop {
+            match self.inner.read(buf) {
+                Err(err) if err.kind() != io::ErrorKind::Interrupted && retry < self.retries => {
+                    retry += 1;
+                    warn!(
+                        "Read failed, retry {} of {} in {:?}: {}",
+                        retry, self.retries, delay, err
+                    );
+                    self.clock.sleep(delay);
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<R: Seek> Seek for RetryReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// How reading of a part of the file ended.
+#[derive(Debug, Default)]
+pub(crate) struct PartRead {
+    /// Rest of the part if reading was interrupted.
+    pub unread_range: Option<UnreadRange>,
+    /// Bytes which couldn't be read even after retries, see
+    /// [`ParseOptions::skip_unreadable`](crate::parser::ParseOptions::skip_unreadable).
+    pub skipped_ranges: Vec<FailedRange>,
+}
+
+/// Split file into parts read by threads, `(start_idx, num_of_bytes)` of every part. Parts
+/// cover every byte (the last one may be shorter) and there are fewer of them than threads if
+/// the file is smaller than the number of threads, so no two parts start at the same byte.
+pub(crate) fn byte_ranges(size: u64, num_of_parts: u64) -> Vec<(u64, u64)> {
+    let bytes_portion = size.div_ceil(num_of_parts.max(1)).max(1);
+    let ranges: Vec<(u64, u64)> = (0..size)
+        .step_by(bytes_portion as usize)
+        .map(|start_idx| (start_idx, bytes_portion.min(size - start_idx)))
+        .collect();
+    // empty file is still read once, so it's reported as a part
+    if ranges.is_empty() {
+        return vec![(0, 0)];
+    }
+
+    ranges
+}
+
+/// Parse part of the file, retrying it on I/O error or panic. Every attempt starts from
+/// scratch, so the closure should publish its results only once it succeeds.
+pub(crate) fn run_with_retry<T, F>(
+    start_idx: u64,
+    end_idx: u64,
+    mut attempt: F,
+) -> Result<T, FailedRange>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    let mut error = String::new();
+    for attempt_idx in 1..=MAX_ATTEMPTS {
+        error = match panic::catch_unwind(AssertUnwindSafe(&mut attempt)) {
+            Ok(Ok(result)) => return Ok(result),
+            Ok(Err(err)) => err.to_string(),
+            Err(panic) => panic_message(panic),
+        };
+        warn!(
+            "Parsing bytes {}..{} failed (attempt {} of {}): {}",
+            start_idx, end_idx, attempt_idx, MAX_ATTEMPTS, error
+        );
+    }
+
+    Err(FailedRange {
+        start_idx,
+        end_idx,
+        attempts: MAX_ATTEMPTS,
+        error,
+    })
+}
+
+/// Join thread parsing part of the file, panic outside of [`run_with_retry`] fails the part too.
+pub(crate) fn join<T>(
+    handle: ScopedJoinHandle<'_, Result<T, FailedRange>>,
+    start_idx: u64,
+    end_idx: u64,
+) -> Result<T, FailedRange> {
+    handle.join().unwrap_or_else(|panic| {
+        Err(FailedRange {
+            start_idx,
+            end_idx,
+            attempts: 1,
+            error: panic_message(panic),
+        })
+    })
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => panic.downcast_ref::<&str>().map_or_else(
+            || "unknown panic".to_string(),
+            |message| message.to_string(),
+        ),
+    }
+}
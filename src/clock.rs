@@ -0,0 +1,79 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Source of time of streaming features: the current time (e.g. `created_at` of reports, year
+/// of timestamps without one), monotonic time of rates and retries and waiting between polls.
+/// Set with [`ParseOptions::clock`](crate::parser::ParseOptions::clock) so time can be
+/// simulated by embedders and tests.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Current wall-clock time.
+    fn now(&self) -> SystemTime;
+    /// Current monotonic time, intervals are measured with it.
+    fn instant(&self) -> Instant;
+    /// Wait for the duration.
+    fn sleep(&self, duration: Duration);
+}
+
+/// Time of the system, the default clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// Clock which only moves when it's advanced. Sleeping advances it by the duration right away,
+/// so waiting code runs without waiting.
+#[derive(Debug)]
+pub struct ManualClock {
+    start: SystemTime,
+    start_instant: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    /// Clock stopped at the time.
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            start,
+            start_instant: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Move the clock forward.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().expect("Clock poisoned") += duration;
+    }
+
+    /// Time passed since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().expect("Clock poisoned")
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        self.start + self.elapsed()
+    }
+
+    fn instant(&self) -> Instant {
+        self.start_instant + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
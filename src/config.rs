@@ -0,0 +1,312 @@
+use crate::parser::ParseOptions;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Config file of the `parse` subcommand. Top level keys are the command line flags
+/// (with `_` instead of `-`), flags given on the command line override them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParseConfig {
+    pub input: Option<String>,
+    pub watch_dir: Option<String>,
+    pub poll_interval: Option<String>,
+    pub kafka_brokers: Option<String>,
+    pub kafka_topic: Option<String>,
+    pub kafka_group: Option<String>,
+    pub kafka_window: Option<String>,
+    pub num_of_threads: Option<u8>,
+    pub sum_field: Vec<String>,
+    pub avg_field: Vec<String>,
+    pub count_distinct: Vec<String>,
+    pub group_by: Vec<String>,
+    pub normalize: Vec<String>,
+    pub pivot: bool,
+    pub report: Option<String>,
+    pub timestamp_field: Option<String>,
+    pub timestamp_locale: Vec<String>,
+    pub rate_window: Option<String>,
+    pub anomaly_stddevs: Option<f64>,
+    pub anomaly_percent: Option<f64>,
+    pub anomaly_trailing: Option<usize>,
+    pub error_when: Vec<String>,
+    pub derive: Vec<String>,
+    pub dedup_field: Option<String>,
+    pub dedup_mode: Option<String>,
+    pub dedup_capacity: Option<u64>,
+    pub drop_duplicates: bool,
+    pub json_backend: Option<String>,
+    pub strategy: Option<String>,
+    pub map_backend: Option<String>,
+    pub auto_tune: bool,
+    pub auto_tune_sample: Option<String>,
+    pub multiline_start_pattern: Option<String>,
+    pub multiline_json: bool,
+    pub json_mode: Option<String>,
+    pub pattern: Option<String>,
+    pub format: Option<String>,
+    pub delimiter: Option<String>,
+    pub has_header: bool,
+    pub record_delimiter: Option<String>,
+    pub strict: bool,
+    pub max_errors: Option<u64>,
+    pub max_line_bytes: Option<String>,
+    pub io_retries: Option<u32>,
+    pub io_retry_delay: Option<String>,
+    pub skip_unreadable: bool,
+    pub on_overflow: Option<String>,
+    pub max_error_examples: Option<u64>,
+    pub sample: Option<f64>,
+    pub head: Option<u64>,
+    pub tail: Option<u64>,
+    #[serde(rename = "where")]
+    pub conditions: Vec<String>,
+    pub use_index: bool,
+    /// Old name of `quarantine`.
+    pub bad_lines: Option<String>,
+    pub quarantine: Option<String>,
+    pub quarantine_oversize: bool,
+    pub quarantine_max_size: Option<String>,
+    pub quarantine_max_files: Option<u64>,
+    pub save_state: Option<String>,
+    pub checkpoint: Option<String>,
+    pub checkpoint_interval: Option<String>,
+    pub output: Option<String>,
+    pub output_sqlite: Option<String>,
+    pub sqlite_records: bool,
+    pub sink: Vec<String>,
+    pub sink_records: bool,
+    pub rate_limit: Option<f64>,
+    pub burst: Option<u64>,
+    pub otlp_endpoint: Option<String>,
+    pub github_comment: Option<String>,
+    pub github_api_url: Option<String>,
+    pub cost_per_gb: Option<f64>,
+    pub window: Option<String>,
+    pub retention: Vec<String>,
+    pub budget: Vec<String>,
+    pub alert: Vec<String>,
+    pub alert_webhook: Option<String>,
+    pub sign_key: Option<String>,
+    pub target_daily_volume: Option<String>,
+    pub sampling_policy: Option<String>,
+    pub progress: bool,
+    pub size_histogram: bool,
+    pub summary_json: Option<String>,
+    pub profile: bool,
+    pub profile_folded: Option<String>,
+    /// Library options (`[options]` table) used as the base for the options built from flags.
+    #[serde(skip_serializing)]
+    pub options: ParseOptions,
+}
+
+impl ParseConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        load(path.as_ref())
+    }
+
+    /// Config values as command line flags with their values (no values for switches).
+    pub fn args(&self) -> Vec<(&'static str, Vec<String>)> {
+        let mut args = vec![];
+        let mut value = |flag: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                args.push((flag, vec![value]));
+            }
+        };
+        value("input", self.input.clone());
+        value("watch-dir", self.watch_dir.clone());
+        value("poll-interval", self.poll_interval.clone());
+        value("kafka-brokers", self.kafka_brokers.clone());
+        value("kafka-topic", self.kafka_topic.clone());
+        value("kafka-group", self.kafka_group.clone());
+        value("kafka-window", self.kafka_window.clone());
+        value(
+            "num-of-threads",
+            self.num_of_threads.map(|num| num.to_string()),
+        );
+        value("max-errors", self.max_errors.map(|max| max.to_string()));
+        value(
+            "max-error-examples",
+            self.max_error_examples.map(|max| max.to_string()),
+        );
+        value("sample", self.sample.map(|fraction| fraction.to_string()));
+        value("head", self.head.map(|lines| lines.to_string()));
+        value("tail", self.tail.map(|lines| lines.to_string()));
+        value("json-backend", self.json_backend.clone());
+        value("strategy", self.strategy.clone());
+        value("map-backend", self.map_backend.clone());
+        value("auto-tune-sample", self.auto_tune_sample.clone());
+        value(
+            "multiline-start-pattern",
+            self.multiline_start_pattern.clone(),
+        );
+        value("json-mode", self.json_mode.clone());
+        value("pattern", self.pattern.clone());
+        value("format", self.format.clone());
+        value("delimiter", self.delimiter.clone());
+        value("report", self.report.clone());
+        value("timestamp-field", self.timestamp_field.clone());
+        value("rate-window", self.rate_window.clone());
+        value(
+            "anomaly-stddevs",
+            self.anomaly_stddevs.map(|max| max.to_string()),
+        );
+        value(
+            "anomaly-percent",
+            self.anomaly_percent.map(|max| max.to_string()),
+        );
+        value(
+            "anomaly-trailing",
+            self.anomaly_trailing.map(|windows| windows.to_string()),
+        );
+        value("dedup-field", self.dedup_field.clone());
+        value("dedup-mode", self.dedup_mode.clone());
+        value(
+            "dedup-capacity",
+            self.dedup_capacity.map(|capacity| capacity.to_string()),
+        );
+        value("record-delimiter", self.record_delimiter.clone());
+        value(
+            "quarantine",
+            self.quarantine.clone().or(self.bad_lines.clone()),
+        );
+        value("max-line-bytes", self.max_line_bytes.clone());
+        value(
+            "io-retries",
+            self.io_retries.map(|retries| retries.to_string()),
+        );
+        value("io-retry-delay", self.io_retry_delay.clone());
+        value("on-overflow", self.on_overflow.clone());
+        value("alert-webhook", self.alert_webhook.clone());
+        value("sign-key", self.sign_key.clone());
+        value("otlp-endpoint", self.otlp_endpoint.clone());
+        value("rate-limit", self.rate_limit.map(|rate| rate.to_string()));
+        value("burst", self.burst.map(|burst| burst.to_string()));
+        value("quarantine-max-size", self.quarantine_max_size.clone());
+        value(
+            "quarantine-max-files",
+            self.quarantine_max_files.map(|max| max.to_string()),
+        );
+        value("save-state", self.save_state.clone());
+        value("summary-json", self.summary_json.clone());
+        value("profile-folded", self.profile_folded.clone());
+        value("checkpoint", self.checkpoint.clone());
+        value("checkpoint-interval", self.checkpoint_interval.clone());
+        value("output", self.output.clone());
+        value("output-sqlite", self.output_sqlite.clone());
+        value("github-comment", self.github_comment.clone());
+        value("github-api-url", self.github_api_url.clone());
+        value("cost-per-gb", self.cost_per_gb.map(|cost| cost.to_string()));
+        value("window", self.window.clone());
+        value("target-daily-volume", self.target_daily_volume.clone());
+        value("sampling-policy", self.sampling_policy.clone());
+
+        let multiple = [
+            ("sum-field", &self.sum_field),
+            ("avg-field", &self.avg_field),
+            ("count-distinct", &self.count_distinct),
+            ("group-by", &self.group_by),
+            ("normalize", &self.normalize),
+            ("timestamp-locale", &self.timestamp_locale),
+            ("error-when", &self.error_when),
+            ("derive", &self.derive),
+            ("retention", &self.retention),
+            ("budget", &self.budget),
+            ("alert", &self.alert),
+            ("sink", &self.sink),
+            ("where", &self.conditions),
+        ];
+        for (flag, values) in multiple {
+            if !values.is_empty() {
+                args.push((flag, values.clone()));
+            }
+        }
+
+        let switches = [
+            ("pivot", self.pivot),
+            ("multiline-json", self.multiline_json),
+            ("has-header", self.has_header),
+            ("strict", self.strict),
+            ("quarantine-oversize", self.quarantine_oversize),
+            ("skip-unreadable", self.skip_unreadable),
+            ("use-index", self.use_index),
+            ("auto-tune", self.auto_tune),
+            ("sqlite-records", self.sqlite_records),
+            ("sink-records", self.sink_records),
+            ("progress", self.progress),
+            ("size-histogram", self.size_histogram),
+            ("drop-duplicates", self.drop_duplicates),
+            ("profile", self.profile),
+        ];
+        for (flag, enabled) in switches {
+            if enabled {
+                args.push((flag, vec![]));
+            }
+        }
+
+        args
+    }
+}
+
+/// Config file of the `serve` subcommand, keys are the command line flags like in
+/// [`ParseConfig`]. Credentials kept here don't show up in the process list.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServeConfig {
+    pub listen: Option<String>,
+    pub group_by: Vec<String>,
+    pub pattern: Option<String>,
+    pub timestamp_field: Option<String>,
+    pub rate_window: Option<String>,
+    pub token: Vec<String>,
+    pub basic_auth: Vec<String>,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub tls_client_ca: Option<String>,
+}
+
+impl ServeConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        load(path.as_ref())
+    }
+
+    /// Config values as command line flags with their values.
+    pub fn args(&self) -> Vec<(&'static str, Vec<String>)> {
+        let mut args = vec![];
+        if let Some(listen) = &self.listen {
+            args.push(("listen", vec![listen.clone()]));
+        }
+        let single = [
+            ("pattern", &self.pattern),
+            ("timestamp-field", &self.timestamp_field),
+            ("rate-window", &self.rate_window),
+            ("tls-cert", &self.tls_cert),
+            ("tls-key", &self.tls_key),
+            ("tls-client-ca", &self.tls_client_ca),
+        ];
+        for (flag, value) in single {
+            if let Some(value) = value {
+                args.push((flag, vec![value.clone()]));
+            }
+        }
+        let multiple = [
+            ("group-by", &self.group_by),
+            ("token", &self.token),
+            ("basic-auth", &self.basic_auth),
+        ];
+        for (flag, values) in multiple {
+            if !values.is_empty() {
+                args.push((flag, values.clone()));
+            }
+        }
+
+        args
+    }
+}
+
+fn load<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Can't read config {}: {}", path.display(), err))?;
+    toml::from_str(&text).map_err(|err| format!("Invalid config {}: {}", path.display(), err))
+}
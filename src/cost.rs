@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+/// Number of bytes in GB (pricing uses decimal units).
+const BYTES_IN_GB: f64 = 1_000_000_000.0;
+
+/// Length of the month used for projections.
+const MONTH: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Projects monthly ingest/storage cost from the volume seen in the analyzed window.
+pub struct CostEstimator {
+    cost_per_gb: f64,
+    window: Duration,
+}
+
+impl CostEstimator {
+    pub fn new(cost_per_gb: f64, window: Duration) -> Self {
+        if window.as_secs_f64() <= 0.0 {
+            panic!("Analyzed window should be greater than 0")
+        }
+        Self {
+            cost_per_gb,
+            window,
+        }
+    }
+
+    /// Number of bytes extrapolated from the analyzed window to the whole month.
+    pub fn monthly_bytes(&self, num_of_bytes: u64) -> f64 {
+        num_of_bytes as f64 * MONTH.as_secs_f64() / self.window.as_secs_f64()
+    }
+
+    /// Projected monthly cost for number of bytes seen in the analyzed window.
+    pub fn monthly_cost(&self, num_of_bytes: u64) -> f64 {
+        self.monthly_bytes(num_of_bytes) / BYTES_IN_GB * self.cost_per_gb
+    }
+}
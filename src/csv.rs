@@ -0,0 +1,174 @@
+use crate::pattern::DEFAULT_TYPE;
+use crate::platform;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+/// Error of quoted fields without the closing quote.
+pub const UNTERMINATED_QUOTE: &str = "unterminated quoted CSV field";
+
+/// Error of records with more fields than the header.
+pub const EXTRA_FIELDS: &str = "CSV record with more fields than the header";
+
+/// Delimiters tried by [`CsvFormat::detect`].
+const CANDIDATES: [char; 4] = [',', '\t', ';', '|'];
+
+/// CSV (or TSV) records instead of JSON lines. Fields are named after the columns, so they can be
+/// used like JSON fields (group-by, filters, numeric fields), the `type` column is the log type
+/// ([`DEFAULT_TYPE`] without it). Values are strings, empty values are missing fields.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CsvFormat {
+    /// Character separating fields, `,` by default.
+    pub delimiter: char,
+    /// The first line of every input names the columns and isn't a record.
+    pub has_header: bool,
+    /// Names of the columns, columns without name are named by their position from 1.
+    pub columns: Vec<String>,
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        CsvFormat {
+            delimiter: ',',
+            has_header: false,
+            columns: vec![],
+        }
+    }
+}
+
+impl CsvFormat {
+    /// Format with the delimiter: a single ASCII character, `\t` or `tab`.
+    pub fn new(delimiter: &str) -> Result<Self, String> {
+        let delimiter = match delimiter {
+            "\\t" | "tab" => '\t',
+            delimiter if delimiter.len() == 1 && delimiter != "\"" => {
+                delimiter.chars().next().unwrap_or_default()
+            }
+            _ => {
+                return Err(format!(
+                    "Invalid CSV delimiter [{}], expected a single ASCII character or \\t",
+                    delimiter
+                ))
+            }
+        };
+        Ok(CsvFormat {
+            delimiter,
+            ..CsvFormat::default()
+        })
+    }
+
+    /// Format of the first lines of an input, `None` if they aren't CSV (e.g. JSON). The delimiter
+    /// splits most lines into as many fields (2 or more) as the first one, which is the header if
+    /// its fields are distinct names and not numbers.
+    pub fn detect(lines: &[&str]) -> Option<Self> {
+        let first = lines.first()?;
+        if first.trim_start().starts_with(['{', '[']) {
+            return None;
+        }
+        let (format, fields) = CANDIDATES
+            .iter()
+            .filter_map(|delimiter| {
+                let format = CsvFormat {
+                    delimiter: *delimiter,
+                    ..CsvFormat::default()
+                };
+                let fields = format.fields(first).ok()?.len();
+                let same = lines
+                    .iter()
+                    .filter(|line| format.fields(line).is_ok_and(|line| line.len() == fields))
+                    .count();
+                // malformed lines are failed lines, not a sign of another format
+                Some((format, fields)).filter(|_| fields > 1 && same * 5 >= lines.len() * 4)
+            })
+            .max_by_key(|(_, fields)| *fields)?;
+
+        let header = format.fields(first).ok()?;
+        let mut names: Vec<&String> = header.iter().collect();
+        names.sort();
+        names.dedup();
+        let is_header = names.len() == fields
+            && header
+                .iter()
+                .all(|name| !name.trim().is_empty() && name.trim().parse::<f64>().is_err());
+        Some(CsvFormat {
+            has_header: is_header,
+            columns: if is_header { header } else { vec![] },
+            ..format
+        })
+    }
+
+    /// Name the columns after the first line of the file, which parsers skip then.
+    pub fn with_header<P: AsRef<Path>>(self, path: P) -> io::Result<Self> {
+        self.with_header_of(platform::open_shared(path)?)
+    }
+
+    /// Name the columns after the first line read from the reader, e.g. of decompressed content.
+    pub fn with_header_of<R: Read>(self, reader: R) -> io::Result<Self> {
+        let mut header = String::new();
+        BufReader::new(reader).read_line(&mut header)?;
+        let columns = self
+            .fields(&header)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(CsvFormat {
+            has_header: true,
+            columns,
+            ..self
+        })
+    }
+
+    /// Values of the record, quotes are removed from quoted fields (RFC 4180).
+    pub fn fields(&self, line: &str) -> Result<Vec<String>, String> {
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line.is_empty() {
+            return Ok(vec![String::new()]);
+        }
+        let delimiter = Some(self.delimiter)
+            .filter(char::is_ascii)
+            .map(|delimiter| delimiter as u8)
+            .ok_or_else(|| format!("Invalid CSV delimiter [{}]", self.delimiter))?;
+        // a quoted field left open runs to the end of the input, so the record after the line
+        // is read only if its quotes are closed
+        let input = format!("{}\n{}", line, self.delimiter);
+        let mut reader = ::csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(input.as_bytes());
+        let mut records = reader.records();
+        let record = records
+            .next()
+            .transpose()
+            .map_err(|err| err.to_string())?
+            .unwrap_or_default();
+        if records.next().is_none() {
+            return Err(UNTERMINATED_QUOTE.to_string());
+        }
+
+        Ok(record.iter().map(str::to_string).collect())
+    }
+
+    /// Record with fields named after the columns, records with fewer fields than the header
+    /// miss the last ones.
+    pub fn record(&self, line: &str) -> Result<Value, String> {
+        let values = self.fields(line)?;
+        if self.has_header && values.len() > self.columns.len() {
+            return Err(EXTRA_FIELDS.to_string());
+        }
+        let mut fields = Map::new();
+        fields.insert("type".to_string(), Value::from(DEFAULT_TYPE));
+        for (idx, value) in values.into_iter().enumerate() {
+            if value.is_empty() {
+                continue;
+            }
+            let name = match self.columns.get(idx) {
+                Some(name) if !name.is_empty() => name.clone(),
+                _ => (idx + 1).to_string(),
+            };
+            fields.insert(name, Value::from(value));
+        }
+
+        Ok(Value::Object(fields))
+    }
+}
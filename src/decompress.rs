@@ -0,0 +1,556 @@
+use crate::encoding::{self, Encoding, Transcoder};
+use crate::platform;
+#[cfg(any(feature = "s3", feature = "gcs"))]
+use crate::remote;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+static NEXT_TEMP_FILE: AtomicU64 = AtomicU64::new(0);
+
+/// Compression of input files, detected by magic bytes (not by extension).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Codec {
+    /// Codec of content starting with the header, `None` for uncompressed content.
+    pub fn detect(header: &[u8]) -> Option<Codec> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Codec::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Codec::Zstd)
+        } else if header.starts_with(b"BZh") {
+            Some(Codec::Bzip2)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Codec::Xz)
+        } else {
+            None
+        }
+    }
+
+    /// Codec of a file named with its extension, e.g. `rejected.jsonl.zst`.
+    pub fn from_extension<P: AsRef<Path>>(path: P) -> Option<Codec> {
+        match path.as_ref().extension()?.to_str()? {
+            "gz" => Some(Codec::Gzip),
+            "zst" => Some(Codec::Zstd),
+            "bz2" => Some(Codec::Bzip2),
+            "xz" => Some(Codec::Xz),
+            _ => None,
+        }
+    }
+
+    /// Cargo feature enabling the codec, also its name (e.g. in logs).
+    pub fn feature(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gzip",
+            Codec::Zstd => "zstd",
+            Codec::Bzip2 => "bzip2",
+            Codec::Xz => "xz",
+        }
+    }
+
+    /// Whether support of the codec is built in.
+    pub fn is_enabled(self) -> bool {
+        match self {
+            Codec::Gzip => cfg!(feature = "gzip"),
+            Codec::Zstd => cfg!(feature = "zstd"),
+            Codec::Bzip2 => cfg!(feature = "bzip2"),
+            Codec::Xz => cfg!(feature = "xz"),
+        }
+    }
+
+    /// Decompressed content of the source, concatenated streams (e.g. of `cat a.gz b.gz`) are
+    /// read as one. Reads fail with the source in the error, a truncated stream fails at its end.
+    #[cfg_attr(
+        not(all(feature = "gzip", feature = "zstd", feature = "bzip2", feature = "xz")),
+        allow(unused_variables, unreachable_code)
+    )]
+    pub fn decoder(
+        self,
+        content: Box<dyn Read + Send>,
+        source: &str,
+    ) -> io::Result<Box<dyn Read + Send>> {
+        check_enabled(self)?;
+        let decoder: Box<dyn Read + Send> = match self {
+            #[cfg(feature = "gzip")]
+            Codec::Gzip => Box::new(flate2::read::MultiGzDecoder::new(content)),
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => Box::new(zstd::stream::read::Decoder::new(content)?),
+            #[cfg(feature = "bzip2")]
+            Codec::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(content)),
+            #[cfg(feature = "xz")]
+            Codec::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(content)),
+            #[allow(unreachable_patterns)]
+            _ => unreachable!("Codec is enabled"),
+        };
+
+        Ok(Box::new(Decoded {
+            decoder,
+            failure: format!("Can't decompress {} ({})", source, self.feature()),
+        }))
+    }
+}
+
+/// Decompressed content, errors name the source.
+struct Decoded {
+    decoder: Box<dyn Read + Send>,
+    /// Prefix of errors, e.g. `Can't decompress app.log.gz (gzip)`.
+    failure: String,
+}
+
+impl Read for Decoded {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.decoder
+            .read(buf)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", self.failure, err)))
+    }
+}
+
+/// Object storage of remote inputs, detected by the URL scheme.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Storage {
+    /// `s3://bucket/key`
+    S3,
+    /// `gs://bucket/object`
+    Gcs,
+}
+
+impl Storage {
+    /// Storage of the input, `None` for local files.
+    pub fn detect(input: &str) -> Option<Storage> {
+        if input.starts_with("s3://") {
+            Some(Storage::S3)
+        } else if input.starts_with("gs://") {
+            Some(Storage::Gcs)
+        } else {
+            None
+        }
+    }
+
+    /// Cargo feature enabling the storage.
+    pub fn feature(self) -> &'static str {
+        match self {
+            Storage::S3 => "s3",
+            Storage::Gcs => "gcs",
+        }
+    }
+
+    pub fn is_enabled(self) -> bool {
+        match self {
+            Storage::S3 => cfg!(feature = "s3"),
+            Storage::Gcs => cfg!(feature = "gcs"),
+        }
+    }
+}
+
+/// Read up to 6 first bytes, enough to detect any codec.
+fn read_header<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut header = [0; 6];
+    let mut len = 0;
+    while len < header.len() {
+        match reader.read(&mut header[len..])? {
+            0 => break,
+            read => len += read,
+        }
+    }
+
+    Ok(header[..len].to_vec())
+}
+
+/// Detect codec of the file from its first bytes.
+pub fn detect<P: AsRef<Path>>(path: P) -> io::Result<Option<Codec>> {
+    let header = read_header(&mut platform::open_shared(path)?)?;
+
+    Ok(Codec::detect(&header))
+}
+
+/// Input which can be read at random, e.g. in parts by parallel parsers: the file itself, or its
+/// decompressed (or fetched, or transcoded) copy in the temp directory removed when dropped (see
+/// [`Input::seekable`]).
+#[derive(Debug)]
+pub struct Decompressed {
+    source: String,
+    path: PathBuf,
+    codec: Option<Codec>,
+    storage: Option<Storage>,
+    encoding: Option<Encoding>,
+}
+
+impl Decompressed {
+    /// Name of the input file as given (e.g. for reports), non-UTF-8 characters of paths are
+    /// replaced.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Path of the uncompressed content.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn codec(&self) -> Option<Codec> {
+        self.codec
+    }
+
+    /// Storage the input was fetched from, `None` for local files.
+    pub fn storage(&self) -> Option<Storage> {
+        self.storage
+    }
+
+    /// Encoding the content was transcoded from, `None` for UTF-8 without byte order mark.
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.encoding
+    }
+}
+
+impl Drop for Decompressed {
+    fn drop(&mut self) {
+        if self.codec.is_some() || self.storage.is_some() || self.encoding.is_some() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Input ready for parsing as a stream: compressed content is decompressed, remote content is
+/// fetched and content in another encoding is transcoded as it's read, so nothing is copied into the temp directory. Plain UTF-8
+/// files can be read in parts too (see [`Input::file`]).
+#[derive(Debug)]
+pub struct Input {
+    source: String,
+    codec: Option<Codec>,
+    storage: Option<Storage>,
+    encoding: Option<Encoding>,
+    content: Content,
+}
+
+#[derive(Debug)]
+enum Content {
+    /// File read through the decoder of the codec and transcoded from the encoding
+    /// (after its byte order mark of given length) if they're set.
+    File {
+        path: PathBuf,
+        codec: Option<Codec>,
+        encoding: Option<(Encoding, usize)>,
+    },
+    /// Object fetched in ranges from the storage, decompressed if it's compressed and transcoded
+    /// like a file.
+    Remote {
+        url: String,
+        storage: Storage,
+        encoding: Option<(Encoding, usize)>,
+        /// Content fetched when the input was opened, taken by the first reader.
+        fetched: Fetched,
+    },
+    /// UTF-8 copy of the content.
+    Copy(Decompressed),
+}
+
+impl Input {
+    /// Open input file like [`open`], without decompressing or transcoding it yet. Remote input
+    /// is fetched up to its first bytes, so a missing object fails here.
+    pub fn open<P: AsRef<Path>>(input_file: P) -> io::Result<Input> {
+        let input_file = input_file.as_ref();
+        if let Some((url, storage)) = input_file
+            .to_str()
+            .and_then(|url| Storage::detect(url).map(|storage| (url, storage)))
+        {
+            return Input::fetch(url, storage);
+        }
+        let codec = detect(input_file)?;
+        let encoding = match codec {
+            Some(codec) => {
+                let decoder = decode_file(codec, input_file)?;
+                encoding::detect_header(&read_prefix(decoder, 3)?)
+            }
+            None => encoding::detect(input_file)?,
+        };
+
+        Ok(Input {
+            source: input_file.to_string_lossy().into_owned(),
+            codec,
+            storage: None,
+            encoding: encoding.map(|(encoding, _)| encoding),
+            content: Content::File {
+                path: input_file.to_path_buf(),
+                codec,
+                encoding,
+            },
+        })
+    }
+
+    fn fetch(url: &str, storage: Storage) -> io::Result<Input> {
+        if !storage.is_enabled() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "{} input isn't supported, build with `--features {}`",
+                    url,
+                    storage.feature()
+                ),
+            ));
+        }
+        let (codec, mut content) = fetch(url, storage)?;
+        let prefix = read_prefix(&mut content, 3)?;
+        let encoding = encoding::detect_header(&prefix);
+        let fetched: Box<dyn Read + Send> = Box::new(io::Cursor::new(prefix).chain(content));
+
+        Ok(Input {
+            source: url.to_string(),
+            codec,
+            storage: Some(storage),
+            encoding: encoding.map(|(encoding, _)| encoding),
+            content: Content::Remote {
+                url: url.to_string(),
+                storage,
+                encoding,
+                fetched: Fetched(Mutex::new(Some(fetched))),
+            },
+        })
+    }
+
+    /// Name of the input file as given (e.g. for reports), non-UTF-8 characters of paths are
+    /// replaced.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn codec(&self) -> Option<Codec> {
+        self.codec
+    }
+
+    /// Storage the input is fetched from, `None` for local files.
+    pub fn storage(&self) -> Option<Storage> {
+        self.storage
+    }
+
+    /// Encoding the content is transcoded from, `None` for UTF-8 without byte order mark.
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.encoding
+    }
+
+    /// Path of the UTF-8 content if it's a file, which can be read in parts, `None` if the
+    /// content has to be streamed through [`Input::reader`].
+    pub fn file(&self) -> Option<&Path> {
+        match &self.content {
+            Content::File {
+                path,
+                codec: None,
+                encoding: None,
+            } => Some(path),
+            Content::File { .. } | Content::Remote { .. } => None,
+            Content::Copy(copy) => Some(copy.path()),
+        }
+    }
+
+    /// Reader of the UTF-8 content from its start, every reader decompresses (or fetches) the
+    /// input again, except for the first reader of remote input which continues the fetch of
+    /// [`Input::open`]. A read fails once it gets to truncated content or a range which can't be
+    /// fetched.
+    pub fn reader(&self) -> io::Result<Box<dyn Read + Send>> {
+        let (content, encoding): (Box<dyn Read + Send>, _) = match &self.content {
+            Content::File {
+                path,
+                codec: Some(codec),
+                encoding,
+            } => (decode_file(*codec, path)?, encoding),
+            Content::File {
+                path,
+                codec: None,
+                encoding,
+            } => (Box::new(platform::open_shared(path)?), encoding),
+            Content::Remote {
+                url,
+                storage,
+                encoding,
+                fetched,
+            } => match fetched.0.lock().unwrap().take() {
+                Some(content) => (content, encoding),
+                None => (fetch(url, *storage)?.1, encoding),
+            },
+            Content::Copy(copy) => return Ok(Box::new(platform::open_shared(copy.path())?)),
+        };
+
+        Ok(match encoding {
+            Some((encoding, bom)) => Box::new(Transcoder::new(*encoding, *bom, content)),
+            None => content,
+        })
+    }
+
+    /// The input as a file which can be read at random, compressed, remote content or content
+    /// in another encoding is copied into the temp directory.
+    pub fn seekable(self) -> io::Result<Decompressed> {
+        if self.file().is_none() {
+            let copy = Decompressed {
+                source: self.source.clone(),
+                path: temp_path(&self.source),
+                codec: self.codec,
+                storage: self.storage,
+                encoding: self.encoding,
+            };
+            let mut writer = BufWriter::new(File::create(&copy.path)?);
+            io::copy(&mut self.reader()?, &mut writer)?;
+            writer.flush()?;
+            return Ok(copy);
+        }
+        Ok(match self.content {
+            Content::File { path, .. } => Decompressed {
+                source: self.source,
+                path,
+                codec: None,
+                storage: None,
+                encoding: None,
+            },
+            Content::Remote { .. } => unreachable!("Remote content is copied"),
+            Content::Copy(copy) => copy,
+        })
+    }
+}
+
+impl From<Decompressed> for Input {
+    fn from(copy: Decompressed) -> Self {
+        Input {
+            source: copy.source.clone(),
+            codec: copy.codec,
+            storage: copy.storage,
+            encoding: copy.encoding,
+            content: Content::Copy(copy),
+        }
+    }
+}
+
+/// Remote content which is already being fetched.
+struct Fetched(Mutex<Option<Box<dyn Read + Send>>>);
+
+impl fmt::Debug for Fetched {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Fetched")
+    }
+}
+
+/// Read up to `len` first bytes of the content.
+fn read_prefix<R: Read>(content: R, len: u64) -> io::Result<Vec<u8>> {
+    let mut prefix = vec![];
+    content.take(len).read_to_end(&mut prefix)?;
+    Ok(prefix)
+}
+
+/// Decompressed content of the file.
+fn decode_file(codec: Codec, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    let file = Box::new(platform::open_shared(path)?);
+    codec.decoder(file, &path.to_string_lossy())
+}
+
+/// Stream the object in ranges, compressed objects are decompressed on the way. Returns the codec
+/// of the object with its content.
+#[cfg(any(feature = "s3", feature = "gcs"))]
+fn fetch(url: &str, storage: Storage) -> io::Result<(Option<Codec>, Box<dyn Read + Send>)> {
+    let mut object = remote::open(url, storage)?;
+    let header = read_header(&mut object)?;
+    let codec = Codec::detect(&header);
+    let object = Box::new(io::Cursor::new(header).chain(object));
+    let content = match codec {
+        Some(codec) => codec.decoder(object, url)?,
+        None => object,
+    };
+
+    Ok((codec, content))
+}
+
+#[cfg(not(any(feature = "s3", feature = "gcs")))]
+fn fetch(url: &str, storage: Storage) -> io::Result<(Option<Codec>, Box<dyn Read + Send>)> {
+    unreachable!("{} input of {:?} storage is checked", url, storage)
+}
+
+pub fn check_enabled(codec: Codec) -> io::Result<()> {
+    if codec.is_enabled() {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{:?} input isn't supported, build with `--features {}`",
+            codec,
+            codec.feature()
+        ),
+    ))
+}
+
+/// Path of a new temp file named after the input.
+pub(crate) fn temp_path<P: AsRef<Path>>(input: P) -> PathBuf {
+    let file_name = input
+        .as_ref()
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    env::temp_dir().join(format!(
+        "log-parser-{}-{}-{}",
+        process::id(),
+        NEXT_TEMP_FILE.fetch_add(1, Ordering::SeqCst),
+        file_name
+    ))
+}
+
+/// Open input file which can be read at random, decompressing it if it's compressed with a
+/// supported codec and transcoding it to UTF-8 if it's in another encoding. Remote (`s3://` or
+/// `gs://`) input is fetched first. Paths aren't limited to UTF-8, remote inputs are.
+pub fn open<P: AsRef<Path>>(input_file: P) -> io::Result<Decompressed> {
+    Input::open(input_file)?.seekable()
+}
+
+/// Compress the file into the target file with the codec.
+#[cfg_attr(
+    not(all(feature = "gzip", feature = "zstd", feature = "bzip2", feature = "xz")),
+    allow(unused_variables, unused_mut, unreachable_code)
+)]
+pub fn compress<P: AsRef<Path>, Q: AsRef<Path>>(
+    codec: Codec,
+    path: P,
+    target: Q,
+) -> io::Result<()> {
+    check_enabled(codec)?;
+    let mut input = File::open(path)?;
+    let target = BufWriter::new(File::create(target)?);
+    let target: BufWriter<File> = match codec {
+        #[cfg(feature = "gzip")]
+        Codec::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(target, flate2::Compression::default());
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?
+        }
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => {
+            let mut encoder = zstd::stream::write::Encoder::new(target, 0)?;
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?
+        }
+        #[cfg(feature = "bzip2")]
+        Codec::Bzip2 => {
+            let mut encoder = bzip2::write::BzEncoder::new(target, bzip2::Compression::default());
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?
+        }
+        #[cfg(feature = "xz")]
+        Codec::Xz => {
+            let mut encoder = xz2::write::XzEncoder::new(target, 6);
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?
+        }
+        #[allow(unreachable_patterns)]
+        _ => unreachable!("Codec is enabled"),
+    };
+
+    target
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?
+        .sync_all()
+}
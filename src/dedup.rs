@@ -0,0 +1,144 @@
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Number of record IDs remembered if the window is not configured.
+pub const DEFAULT_WINDOW: usize = 100_000;
+
+/// Number of distinct IDs the bloom filter is sized for if the capacity is not configured.
+pub const DEFAULT_CAPACITY: usize = 10_000_000;
+
+/// Fraction of new IDs the bloom filter takes for duplicates at its capacity.
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Number of locks of the exact set and of bloom filter inserts, so parsing threads rarely wait for each other.
+const SHARDS: u64 = 64;
+
+/// Drops redelivered records by their ID within a window of the last IDs, so memory is bounded
+/// and duplicates further apart than the window are counted again.
+#[derive(Debug)]
+pub struct Deduplicator {
+    window: usize,
+    seen: HashSet<u64>,
+    order: VecDeque<u64>,
+}
+
+impl Deduplicator {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Whether the ID (hash of the ID field value) wasn't seen within the window, it is
+    /// remembered then and the oldest ID is forgotten once the window is full.
+    pub fn is_new(&mut self, id: u64) -> bool {
+        if !self.seen.insert(id) {
+            return false;
+        }
+        self.order.push_back(id);
+        if self.order.len() > self.window {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+
+        true
+    }
+}
+
+/// How [`SeenIds`] remembers IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DedupMode {
+    /// Every ID, 8 bytes per distinct ID.
+    #[default]
+    Exact,
+    /// Bloom filter of fixed size (about 1.2 bytes per ID of the capacity), a few new IDs are
+    /// counted as duplicates.
+    Bloom,
+}
+
+impl FromStr for DedupMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "exact" => Ok(DedupMode::Exact),
+            "bloom" => Ok(DedupMode::Bloom),
+            _ => Err(format!(
+                "Unknown dedup mode: [{}], expected exact or bloom",
+                mode
+            )),
+        }
+    }
+}
+
+/// IDs seen anywhere in the input, shared by all parsing threads, so duplicates are counted no
+/// matter how far apart they are (unlike [`Deduplicator`]).
+#[derive(Debug)]
+pub enum SeenIds {
+    Exact(Vec<Mutex<HashSet<u64>>>),
+    Bloom {
+        bits: Vec<AtomicU64>,
+        num_of_hashes: u64,
+        /// Inserts of the same ID are serialized, otherwise threads setting its bits at the same
+        /// time can each find one of them unset and both take the ID for a new one.
+        locks: Vec<Mutex<()>>,
+    },
+}
+
+impl SeenIds {
+    /// IDs remembered the way of the mode, the capacity sizes the bloom filter only.
+    pub fn new(mode: DedupMode, capacity: usize) -> Self {
+        match mode {
+            DedupMode::Exact => {
+                SeenIds::Exact((0..SHARDS).map(|_| Mutex::new(HashSet::new())).collect())
+            }
+            DedupMode::Bloom => {
+                // optimal size and number of hashes of the false positive rate
+                let ln2 = std::f64::consts::LN_2;
+                let num_of_bits =
+                    -(capacity.max(1) as f64) * FALSE_POSITIVE_RATE.ln() / (ln2 * ln2);
+                let words = (num_of_bits / 64.0).ceil() as usize;
+                SeenIds::Bloom {
+                    bits: (0..words.max(1)).map(|_| AtomicU64::new(0)).collect(),
+                    num_of_hashes: (-FALSE_POSITIVE_RATE.log2()).ceil() as u64,
+                    locks: (0..SHARDS).map(|_| Mutex::new(())).collect(),
+                }
+            }
+        }
+    }
+
+    /// Whether the ID (hash of the ID field value) wasn't seen before, it is remembered then.
+    pub fn insert(&self, id: u64) -> bool {
+        match self {
+            SeenIds::Exact(shards) => shards[(id % SHARDS) as usize]
+                .lock()
+                .expect("Seen IDs poisoned")
+                .insert(id),
+            SeenIds::Bloom {
+                bits,
+                num_of_hashes,
+                locks,
+            } => {
+                let _lock = locks[(id % SHARDS) as usize]
+                    .lock()
+                    .expect("Seen IDs poisoned");
+                let num_of_bits = bits.len() as u64 * 64;
+                // double hashing of the ID hash, the step is odd so it's never 0
+                let step = id.rotate_left(32).wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+                let mut new = false;
+                for idx in 0..*num_of_hashes {
+                    let bit = id.wrapping_add(idx.wrapping_mul(step)) % num_of_bits;
+                    let mask = 1 << (bit % 64);
+                    let previous = bits[(bit / 64) as usize].fetch_or(mask, Ordering::Relaxed);
+                    new |= previous & mask == 0;
+                }
+                new
+            }
+        }
+    }
+}
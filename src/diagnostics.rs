@@ -0,0 +1,188 @@
+use serde_json::json;
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io;
+use std::panic::{self, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of last lines kept per worker.
+const LAST_LINES: usize = 5;
+
+/// Lines are cut to this length in the bundle.
+const MAX_LINE_LEN: usize = 4096;
+
+/// Run context copied into the bundle.
+struct Context {
+    input_file: PathBuf,
+    config_file: Option<String>,
+}
+
+static CONTEXT: OnceLock<Context> = OnceLock::new();
+static WORKERS: Mutex<Vec<Arc<WorkerTrace>>> = Mutex::new(Vec::new());
+/// Only the first panic writes a bundle (retried parts of the file can panic again).
+static BUNDLE_WRITTEN: AtomicBool = AtomicBool::new(false);
+
+/// Install panic hook writing diagnostics bundle (config, input metadata, offsets reached and
+/// last lines per worker, backtrace) into a temp directory. Workers are traced only once it
+/// is installed.
+pub fn install<P: AsRef<Path>>(input_file: P, config_file: Option<&str>) {
+    let context = Context {
+        input_file: input_file.as_ref().to_path_buf(),
+        config_file: config_file.map(String::from),
+    };
+    if CONTEXT.set(context).is_err() {
+        return;
+    }
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        if BUNDLE_WRITTEN.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        match write_bundle(info) {
+            Ok(dir) => eprintln!("Diagnostics bundle written to {}", dir.display()),
+            Err(err) => eprintln!("Can't write diagnostics bundle: {}", err),
+        }
+    }));
+}
+
+/// Position and last lines of a single parsing thread.
+pub(crate) struct WorkerTrace {
+    start_idx: u64,
+    offset: AtomicU64,
+    last_lines: Mutex<VecDeque<String>>,
+}
+
+impl WorkerTrace {
+    /// Register line the worker is about to parse.
+    pub(crate) fn record(&self, offset: u64, line: &str) {
+        self.offset.store(offset, Ordering::Relaxed);
+        let mut last_lines = self
+            .last_lines
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        // reuse buffers of the oldest line to avoid allocation per line
+        let mut buffer = if last_lines.len() == LAST_LINES {
+            last_lines.pop_front().unwrap_or_default()
+        } else {
+            String::new()
+        };
+        buffer.clear();
+        let mut end = line.len().min(MAX_LINE_LEN);
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        buffer.push_str(&line[..end]);
+        last_lines.push_back(buffer);
+    }
+}
+
+/// Trace of worker parsing part of the file starting at given index, if diagnostics are installed.
+pub(crate) fn worker(start_idx: u64) -> Option<Arc<WorkerTrace>> {
+    CONTEXT.get()?;
+    let trace = Arc::new(WorkerTrace {
+        start_idx,
+        offset: AtomicU64::new(start_idx),
+        last_lines: Mutex::new(VecDeque::with_capacity(LAST_LINES)),
+    });
+    let mut workers = WORKERS.lock().unwrap_or_else(|err| err.into_inner());
+    // retried part or the next run replaces the previous trace of the same part
+    workers.retain(|worker| worker.start_idx != start_idx);
+    workers.push(trace.clone());
+
+    Some(trace)
+}
+
+fn write_bundle(info: &PanicHookInfo) -> io::Result<PathBuf> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let dir = env::temp_dir().join(format!(
+        "log-parser-diagnostics-{}-{}",
+        now,
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir)?;
+
+    let thread = thread::current();
+    let message = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    let location = info
+        .location()
+        .map(|location| location.to_string())
+        .unwrap_or_default();
+    fs::write(
+        dir.join("panic.txt"),
+        format!(
+            "thread '{}' panicked at {}:\n{}\n\nbacktrace:\n{}\n",
+            thread.name().unwrap_or("<unnamed>"),
+            location,
+            message,
+            Backtrace::force_capture()
+        ),
+    )?;
+
+    let context = CONTEXT.get();
+    let input_file = context.map(|context| context.input_file.as_path());
+    let input_metadata = input_file.and_then(|input_file| fs::metadata(input_file).ok());
+    let input_file = input_file.map(Path::to_string_lossy);
+    let config_file = context.and_then(|context| context.config_file.as_deref());
+    let run = json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "args": env::args().collect::<Vec<_>>(),
+        "input": {
+            "path": input_file,
+            "size": input_metadata.as_ref().map(|metadata| metadata.len()),
+            "modified": input_metadata
+                .and_then(|metadata| metadata.modified().ok())
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|modified| modified.as_secs()),
+        },
+        "config": config_file,
+    });
+    fs::write(dir.join("run.json"), serde_json::to_string_pretty(&run)?)?;
+    if let Some(config_file) = config_file {
+        let file_name = Path::new(config_file)
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_else(|| "config".into());
+        fs::copy(config_file, dir.join(file_name))?;
+    }
+
+    // the panicking thread could hold the lock, don't wait for it
+    let workers: Vec<_> = match WORKERS.try_lock() {
+        Ok(workers) => workers
+            .iter()
+            .map(|worker| {
+                let last_lines: Vec<String> = match worker.last_lines.try_lock() {
+                    Ok(last_lines) => last_lines.iter().cloned().collect(),
+                    Err(_) => vec![],
+                };
+                json!({
+                    "start_idx": worker.start_idx,
+                    "offset_reached": worker.offset.load(Ordering::Relaxed),
+                    "last_lines": last_lines,
+                })
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+    fs::write(
+        dir.join("workers.json"),
+        serde_json::to_string_pretty(&workers)?,
+    )?;
+
+    Ok(dir)
+}
@@ -0,0 +1,116 @@
+use crate::parser::ParseReport;
+use crate::table;
+use crate::units;
+use std::collections::BTreeSet;
+
+/// How a group changed between the two reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    /// Only in the second report.
+    New,
+    /// Only in the first report.
+    Disappeared,
+    /// In both reports.
+    Kept,
+}
+
+/// Number of logs and bytes of a group in both reports (zero where it's missing).
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupDiff {
+    pub key: String,
+    pub change: Change,
+    pub counter_a: u64,
+    pub counter_b: u64,
+    pub bytes_a: u64,
+    pub bytes_b: u64,
+}
+
+impl GroupDiff {
+    pub fn counter_delta(&self) -> i64 {
+        self.counter_b as i64 - self.counter_a as i64
+    }
+
+    pub fn bytes_delta(&self) -> i64 {
+        self.bytes_b as i64 - self.bytes_a as i64
+    }
+}
+
+/// Diff of every group (log type, or group with `group_by`) of both reports: new groups first,
+/// then disappeared ones, then the rest with the largest change of number of logs first.
+pub fn diff(a: &ParseReport, b: &ParseReport) -> Vec<GroupDiff> {
+    let keys: BTreeSet<&String> = a.log_register.keys().chain(b.log_register.keys()).collect();
+    let mut diffs: Vec<GroupDiff> = keys
+        .into_iter()
+        .map(|key| {
+            let elem_a = a.log_register.get(key);
+            let elem_b = b.log_register.get(key);
+            GroupDiff {
+                key: key.clone(),
+                change: match (elem_a, elem_b) {
+                    (None, _) => Change::New,
+                    (_, None) => Change::Disappeared,
+                    _ => Change::Kept,
+                },
+                counter_a: elem_a.map_or(0, |elem| elem.counter),
+                counter_b: elem_b.map_or(0, |elem| elem.counter),
+                bytes_a: elem_a.map_or(0, |elem| elem.num_of_bytes),
+                bytes_b: elem_b.map_or(0, |elem| elem.num_of_bytes),
+            }
+        })
+        .collect();
+    // keys are sorted already, so ties stay sorted by key
+    diffs.sort_by_key(|diff| (diff.change, -diff.counter_delta().abs()));
+
+    diffs
+}
+
+/// Delta with its sign and change relative to the first value, e.g. `+12 (+3.4%)`.
+fn delta(from: u64, delta: i64) -> String {
+    if from == 0 {
+        return units::format_count(format!("{:+}", delta));
+    }
+    format!(
+        "{} ({:+.1}%)",
+        units::format_count(format!("{:+}", delta)),
+        delta as f64 * 100.0 / from as f64
+    )
+}
+
+/// Markdown table of the diff, new and disappeared groups are marked in the first column.
+pub fn markdown_table(diffs: &[GroupDiff], key_name: &str) -> String {
+    let header: Vec<String> = [
+        "change",
+        key_name,
+        "counter a",
+        "counter b",
+        "counter delta",
+        "bytes a",
+        "bytes b",
+        "bytes delta",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect();
+    let rows: Vec<Vec<String>> = diffs
+        .iter()
+        .map(|diff| {
+            vec![
+                match diff.change {
+                    Change::New => "new",
+                    Change::Disappeared => "disappeared",
+                    Change::Kept => "",
+                }
+                .to_string(),
+                diff.key.clone(),
+                units::format_count(diff.counter_a),
+                units::format_count(diff.counter_b),
+                delta(diff.counter_a, diff.counter_delta()),
+                units::format_count(diff.bytes_a),
+                units::format_count(diff.bytes_b),
+                delta(diff.bytes_a, diff.bytes_delta()),
+            ]
+        })
+        .collect();
+
+    table::markdown_table(&header, &rows)
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ops::AddAssign;
+
+/// Values are counted exactly until there are more distinct hashes than this.
+const EXACT_LIMIT: usize = 1024;
+
+/// HyperLogLog precision, 2^14 registers give ~0.8% standard error.
+const PRECISION: u32 = 14;
+const NUM_OF_REGISTERS: usize = 1 << PRECISION;
+
+/// FNV-1a hash of the bytes, stable across builds and Rust releases as hashes are kept in
+/// files (saved states, checkpoints, indexes).
+pub fn fnv1a<I: IntoIterator<Item = u8>>(bytes: I) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Hash of a field value, the same value always gives the same hash, also in other builds.
+/// FNV-1a is mixed (the finalizer of SplitMix64) so all bits of the hash are uniform, which
+/// HyperLogLog registers rely on.
+pub fn hash_value(value: &str) -> u64 {
+    let mut hash = fnv1a(value.bytes());
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// Number of distinct values: exact for small sets, HyperLogLog estimate above [`EXACT_LIMIT`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum DistinctCounter {
+    Exact(HashSet<u64>),
+    HyperLogLog(Vec<u8>),
+}
+
+impl Default for DistinctCounter {
+    fn default() -> Self {
+        DistinctCounter::Exact(HashSet::new())
+    }
+}
+
+impl DistinctCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register hash of a value (see [`hash_value`]).
+    pub fn add(&mut self, hash: u64) {
+        match self {
+            DistinctCounter::Exact(hashes) => {
+                hashes.insert(hash);
+                if hashes.len() > EXACT_LIMIT {
+                    *self = Self::hyper_log_log(hashes.iter().copied());
+                }
+            }
+            DistinctCounter::HyperLogLog(registers) => add_to_registers(registers, hash),
+        }
+    }
+
+    /// Whether the count is exact.
+    pub fn is_exact(&self) -> bool {
+        matches!(self, DistinctCounter::Exact(_))
+    }
+
+    pub fn count(&self) -> u64 {
+        match self {
+            DistinctCounter::Exact(hashes) => hashes.len() as u64,
+            DistinctCounter::HyperLogLog(registers) => estimate(registers),
+        }
+    }
+
+    fn hyper_log_log(hashes: impl Iterator<Item = u64>) -> Self {
+        let mut registers = vec![0; NUM_OF_REGISTERS];
+        hashes.for_each(|hash| add_to_registers(&mut registers, hash));
+        DistinctCounter::HyperLogLog(registers)
+    }
+}
+
+impl AddAssign for DistinctCounter {
+    fn add_assign(&mut self, rhs: Self) {
+        match rhs {
+            DistinctCounter::Exact(hashes) => hashes.into_iter().for_each(|hash| self.add(hash)),
+            DistinctCounter::HyperLogLog(rhs_registers) => {
+                if let DistinctCounter::Exact(hashes) = self {
+                    *self = Self::hyper_log_log(hashes.iter().copied());
+                }
+                if let DistinctCounter::HyperLogLog(registers) = self {
+                    for (register, rhs_register) in registers.iter_mut().zip(rhs_registers) {
+                        *register = (*register).max(rhs_register);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn add_to_registers(registers: &mut [u8], hash: u64) {
+    let idx = (hash >> (64 - PRECISION)) as usize;
+    // position of the first 1 bit in the rest of the hash (sentinel bit limits it)
+    let rest = (hash << PRECISION) | (1 << (PRECISION - 1));
+    let rank = rest.leading_zeros() as u8 + 1;
+    registers[idx] = registers[idx].max(rank);
+}
+
+fn estimate(registers: &[u8]) -> u64 {
+    let m = registers.len() as f64;
+    let alpha = 0.7213 / (1.0 + 1.079 / m);
+    let sum: f64 = registers
+        .iter()
+        .map(|register| 2f64.powi(-(*register as i32)))
+        .sum();
+    let raw = alpha * m * m / sum;
+
+    // linear counting is more accurate for small cardinalities
+    let zeros = registers.iter().filter(|register| **register == 0).count();
+    if raw <= 2.5 * m && zeros > 0 {
+        (m * (m / zeros as f64).ln()).round() as u64
+    } else {
+        raw.round() as u64
+    }
+}
@@ -0,0 +1,131 @@
+use crate::parser::ParsedRecord;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::time::SystemTime;
+
+/// Fields which logs are expected to have.
+pub struct ExpectedSchema {
+    fields: HashSet<String>,
+}
+
+impl ExpectedSchema {
+    pub fn new<I: IntoIterator<Item = String>>(fields: I) -> Self {
+        Self {
+            fields: fields.into_iter().collect(),
+        }
+    }
+
+    /// Load schema from JSON file: array of field names or JSON Schema object with `properties`.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("Can't read schema file [{}]: {}", path, err))?;
+        let schema: Value = serde_json::from_str(&content)
+            .map_err(|err| format!("Can't parse schema file [{}]: {}", path, err))?;
+
+        match &schema {
+            Value::Array(fields) => fields
+                .iter()
+                .map(|field| {
+                    field
+                        .as_str()
+                        .map(String::from)
+                        .ok_or_else(|| format!("Field name should be a string: [{}]", field))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(Self::new),
+            Value::Object(object) => match object.get("properties") {
+                Some(Value::Object(properties)) => Ok(Self::new(properties.keys().cloned())),
+                _ => Err("Schema object should have `properties` object".to_string()),
+            },
+            _ => Err("Schema should be an array of field names or an object".to_string()),
+        }
+    }
+
+    pub fn contains(&self, field: &str) -> bool {
+        self.fields.contains(field)
+    }
+}
+
+/// Field which is not declared in the expected schema.
+#[derive(Serialize, Debug, Clone)]
+pub struct UnexpectedField {
+    pub field: String,
+    /// Number of logs with the field.
+    pub count: u64,
+    pub log_types: BTreeSet<String>,
+    /// Byte offset of the first log with the field.
+    pub first_offset: u64,
+    /// Timestamp of the first log with the field (if timestamp field is configured).
+    #[serde(skip)]
+    pub first_timestamp: Option<SystemTime>,
+}
+
+impl UnexpectedField {
+    /// Whether the record occurred before the first occurrence seen so far.
+    ///
+    /// Records are compared by timestamp if both have it, otherwise by position in the file.
+    fn is_earlier(&self, record: &ParsedRecord) -> bool {
+        match (record.timestamp, self.first_timestamp) {
+            (Some(timestamp), Some(first_timestamp)) => timestamp < first_timestamp,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => record.offset < self.first_offset,
+        }
+    }
+}
+
+/// Detects fields which are not declared in the expected schema.
+pub struct DriftDetector {
+    schema: ExpectedSchema,
+    unexpected: HashMap<String, UnexpectedField>,
+}
+
+impl DriftDetector {
+    pub fn new(schema: ExpectedSchema) -> Self {
+        Self {
+            schema,
+            unexpected: HashMap::new(),
+        }
+    }
+
+    /// Check record fields, record has to be parsed with `collect_field_names` option.
+    pub fn observe(&mut self, record: &ParsedRecord) {
+        for field in &record.field_names {
+            if self.schema.contains(field) {
+                continue;
+            }
+
+            let unexpected =
+                self.unexpected
+                    .entry(field.clone())
+                    .or_insert_with(|| UnexpectedField {
+                        field: field.clone(),
+                        count: 0,
+                        log_types: BTreeSet::new(),
+                        first_offset: record.offset,
+                        first_timestamp: record.timestamp,
+                    });
+            if unexpected.is_earlier(record) {
+                unexpected.first_offset = record.offset;
+                unexpected.first_timestamp = record.timestamp;
+            }
+            unexpected.count += 1;
+            if !unexpected.log_types.contains(&record.log_type) {
+                unexpected.log_types.insert(record.log_type.clone());
+            }
+        }
+    }
+
+    /// Unexpected fields ordered by first occurrence.
+    pub fn report(self) -> Vec<UnexpectedField> {
+        let mut fields: Vec<UnexpectedField> = self.unexpected.into_values().collect();
+        fields.sort_by(|a, b| {
+            a.first_timestamp
+                .cmp(&b.first_timestamp)
+                .then(a.first_offset.cmp(&b.first_offset))
+        });
+        fields
+    }
+}
@@ -0,0 +1,189 @@
+use crate::platform;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// Text encoding of input files. Parsers read UTF-8, other encodings are transcoded as they are
+/// read (see [`crate::decompress::Input`]).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1, every byte is the character with its code.
+    Latin1,
+}
+
+static FORCED: RwLock<Option<Encoding>> = RwLock::new(None);
+
+impl Encoding {
+    /// Encoding with its byte order mark (BOM) starting the content, and the length of the mark.
+    pub fn detect(header: &[u8]) -> Option<(Encoding, usize)> {
+        if header.starts_with(&[0xef, 0xbb, 0xbf]) {
+            Some((Encoding::Utf8, 3))
+        } else if header.starts_with(&[0xff, 0xfe]) {
+            Some((Encoding::Utf16Le, 2))
+        } else if header.starts_with(&[0xfe, 0xff]) {
+            Some((Encoding::Utf16Be, 2))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(encoding: &str) -> Result<Self, Self::Err> {
+        match encoding.to_ascii_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            _ => Err(format!(
+                "Unknown encoding [{}], expected utf-8, utf-16le, utf-16be or latin1",
+                encoding
+            )),
+        }
+    }
+}
+
+/// Read every input in the encoding instead of detecting it by the byte order mark, `None`
+/// restores detection.
+pub fn set_forced(encoding: Option<Encoding>) {
+    *FORCED.write().expect("Encoding poisoned") = encoding;
+}
+
+pub fn forced() -> Option<Encoding> {
+    *FORCED.read().expect("Encoding poisoned")
+}
+
+/// Encoding the file has to be transcoded from with the length of its byte order mark, `None`
+/// for UTF-8 content without the mark which is read as it is.
+pub fn detect<P: AsRef<Path>>(path: P) -> io::Result<Option<(Encoding, usize)>> {
+    let mut header = vec![];
+    platform::open_shared(path)?
+        .take(3)
+        .read_to_end(&mut header)?;
+
+    Ok(detect_header(&header))
+}
+
+/// Encoding of content starting with the header like [`detect`], `--encoding` overrides the
+/// byte order mark.
+pub fn detect_header(header: &[u8]) -> Option<(Encoding, usize)> {
+    match (forced(), Encoding::detect(header)) {
+        // a mark of the forced encoding is skipped, other bytes are content
+        (Some(forced), Some((encoding, bom))) if forced == encoding => Some((encoding, bom)),
+        (Some(Encoding::Utf8), _) => None,
+        (Some(forced), _) => Some((forced, 0)),
+        (None, detected) => detected,
+    }
+}
+
+/// Content of the reader after the byte order mark as UTF-8, transcoded as it's read. Invalid
+/// sequences (unpaired UTF-16 surrogates) are replaced with `U+FFFD`.
+pub struct Transcoder<R> {
+    reader: R,
+    encoding: Encoding,
+    /// Bytes of the byte order mark which are not skipped yet.
+    bom: usize,
+    /// Decoder of UTF-16, it keeps code units and surrogates split between reads.
+    decoder: Option<encoding_rs::Decoder>,
+    buffer: Vec<u8>,
+    text: String,
+    /// Bytes of the text already returned.
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> Transcoder<R> {
+    pub fn new(encoding: Encoding, bom: usize, reader: R) -> Self {
+        let decoder = match encoding {
+            Encoding::Utf16Le => Some(encoding_rs::UTF_16LE.new_decoder_without_bom_handling()),
+            Encoding::Utf16Be => Some(encoding_rs::UTF_16BE.new_decoder_without_bom_handling()),
+            Encoding::Utf8 | Encoding::Latin1 => None,
+        };
+        Self {
+            reader,
+            encoding,
+            bom,
+            decoder,
+            buffer: vec![0; 8192],
+            text: String::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    /// Transcode the next read of the reader into the text.
+    fn fill(&mut self) -> io::Result<()> {
+        self.text.clear();
+        self.pos = 0;
+        let read = self.reader.read(&mut self.buffer)?;
+        self.done = read == 0;
+        match &mut self.decoder {
+            Some(decoder) => {
+                self.text.reserve(
+                    decoder
+                        .max_utf8_buffer_length(read)
+                        .expect("Transcoded length overflow"),
+                );
+                // the last call flushes an odd trailing byte as U+FFFD
+                let (result, _, _) =
+                    decoder.decode_to_string(&self.buffer[..read], &mut self.text, self.done);
+                // the text has room for all of the read
+                debug_assert_eq!(result, encoding_rs::CoderResult::InputEmpty);
+            }
+            // encoding_rs reads the iso-8859-1 label as windows-1252, Latin-1 is every byte as is
+            None => self
+                .text
+                .push_str(&encoding_rs::mem::decode_latin1(&self.buffer[..read])),
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Transcoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.bom > 0 {
+            let skipped = io::copy(
+                &mut (&mut self.reader).take(self.bom as u64),
+                &mut io::sink(),
+            )?;
+            self.bom = match skipped {
+                0 => 0,
+                skipped => self.bom - skipped as usize,
+            };
+        }
+        if self.encoding == Encoding::Utf8 {
+            return self.reader.read(buf);
+        }
+        while self.pos == self.text.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let len = buf.len().min(self.text.len() - self.pos);
+        buf[..len].copy_from_slice(&self.text.as_bytes()[self.pos..self.pos + len]);
+        self.pos += len;
+
+        Ok(len)
+    }
+}
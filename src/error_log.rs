@@ -0,0 +1,161 @@
+use crate::scanner::{self, LineError};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::error::Category;
+use serde_json::Error;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Number of logged examples of every error kind by default.
+pub const DEFAULT_MAX_EXAMPLES: u64 = 10;
+
+/// Lines of logged examples are cut to this length.
+const MAX_EXAMPLE_LEN: usize = 256;
+
+static MAX_EXAMPLES: AtomicU64 = AtomicU64::new(DEFAULT_MAX_EXAMPLES);
+/// Examples logged per error kind by all threads.
+static LOGGED: Mutex<Option<HashMap<String, u64>>> = Mutex::new(None);
+
+/// Set how many examples of every error kind are logged in this process.
+pub fn set_max_examples(max_examples: u64) {
+    MAX_EXAMPLES.store(max_examples, Ordering::Relaxed);
+}
+
+/// Error message without its position and the values which differ line by line, so errors of the
+/// same kind have the same text, e.g. `syntax: expected value` or `data: missing field `type``.
+pub fn kind(err: &Error) -> String {
+    let category = match err.classify() {
+        Category::Io => "io",
+        Category::Syntax => "syntax",
+        Category::Data => "data",
+        Category::Eof => "eof",
+    };
+    let message = err.to_string();
+    let mut message = match message.rfind(" at line ") {
+        Some(idx) => message[..idx].to_string(),
+        None => message,
+    };
+    // e.g. invalid type: string "x", expected u64
+    for prefix in ["invalid type: ", "invalid value: "] {
+        if let Some(rest) = message.strip_prefix(prefix) {
+            let (unexpected, expected) = rest.split_once(", expected ").unwrap_or((rest, ""));
+            let unexpected = unexpected.split([' ', '`']).next().unwrap_or_default();
+            message = format!("{}{}, expected {}", prefix, unexpected, expected);
+        }
+    }
+
+    format!("{}: {}", category, message)
+}
+
+/// Coarse class of an error kind, so data quality issues stand out among the exact messages.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    /// Line isn't JSON at all or is cut in the middle.
+    InvalidJson,
+    /// Line has no `type` field, which groups logs by default.
+    MissingGroupField,
+    /// Line longer than [`crate::parser::ParseOptions::max_line_bytes`].
+    OversizeLine,
+    InvalidUtf8,
+    /// Valid JSON with fields of wrong types.
+    SchemaViolation,
+    /// Line which doesn't match `--pattern`.
+    PatternMismatch,
+}
+
+impl ErrorClass {
+    /// Class of an error kind returned by [`kind`].
+    pub fn of(error_kind: &str) -> Self {
+        let (category, message) = error_kind.split_once(": ").unwrap_or(("", error_kind));
+        match category {
+            "data" if message.starts_with(scanner::TOO_LONG) => ErrorClass::OversizeLine,
+            "data" if message == LineError::InvalidUtf8.to_string() => ErrorClass::InvalidUtf8,
+            "data" if message == "missing field `type`" => ErrorClass::MissingGroupField,
+            "data" if message == PATTERN_MISMATCH => ErrorClass::PatternMismatch,
+            "data" => ErrorClass::SchemaViolation,
+            _ => ErrorClass::InvalidJson,
+        }
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorClass::InvalidJson => "invalid JSON",
+            ErrorClass::MissingGroupField => "missing group field",
+            ErrorClass::OversizeLine => "oversize line",
+            ErrorClass::InvalidUtf8 => "invalid UTF-8",
+            ErrorClass::SchemaViolation => "schema violation",
+            ErrorClass::PatternMismatch => "pattern mismatch",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Error of lines which don't match the line pattern.
+pub const PATTERN_MISMATCH: &str = "line doesn't match the pattern";
+
+/// Number of failed lines per class of their error kinds.
+pub fn classes(error_kinds: &BTreeMap<String, u64>) -> BTreeMap<ErrorClass, u64> {
+    let mut classes = BTreeMap::new();
+    for (error_kind, count) in error_kinds {
+        *classes.entry(ErrorClass::of(error_kind)).or_default() += count;
+    }
+    classes
+}
+
+/// Count the error of the line by its kind and log it, unless enough examples of the kind were
+/// already logged. Counts of a part of the input are kept by its thread, so the shared counts
+/// are only locked until the thread saw enough errors of the kind itself.
+pub fn record(error_kinds: &mut BTreeMap<String, u64>, line: &str, err: &Error) {
+    let error_kind = kind(err);
+    let count = error_kinds.entry(error_kind.clone()).or_default();
+    *count += 1;
+    if *count <= MAX_EXAMPLES.load(Ordering::Relaxed) {
+        log_example(error_kind, line, err);
+    }
+}
+
+/// Log the error of the line unless enough examples of its kind were already logged (for errors
+/// counted elsewhere, e.g. by [`crate::parser::ParseReport::add_line`]).
+pub fn log(line: &str, err: &Error) {
+    log_example(kind(err), line, err);
+}
+
+fn log_example(error_kind: String, line: &str, err: &Error) {
+    let max_examples = MAX_EXAMPLES.load(Ordering::Relaxed);
+    let mut logged = LOGGED.lock().expect("Error log lock is poisoned");
+    let logged = logged
+        .get_or_insert_with(HashMap::new)
+        .entry(error_kind.clone())
+        .or_default();
+    if *logged >= max_examples {
+        return;
+    }
+    *logged += 1;
+    error!(
+        "Problem to parse line: [{}]. Error: [{}]",
+        truncate(line),
+        err
+    );
+    if *logged == max_examples {
+        warn!(
+            "Logged {} examples of [{}], further errors of the kind are only counted",
+            max_examples, error_kind
+        );
+    }
+}
+
+fn truncate(line: &str) -> String {
+    if line.len() <= MAX_EXAMPLE_LEN {
+        return line.to_string();
+    }
+    let mut end = MAX_EXAMPLE_LEN;
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}... ({} bytes)", &line[..end], line.len())
+}
@@ -0,0 +1,426 @@
+use serde::Deserialize;
+use serde_json::{Number, Value};
+use std::convert::TryFrom;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Functions of expressions with their number of arguments (`None` for any number from 1).
+const FUNCTIONS: [(&str, Option<usize>); 8] = [
+    ("split", Some(3)),
+    ("lower", Some(1)),
+    ("upper", Some(1)),
+    ("len", Some(1)),
+    ("substr", Some(3)),
+    ("floor", Some(1)),
+    ("round", Some(1)),
+    ("concat", None),
+];
+
+/// Arithmetic operators, in order of precedence from the lowest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl Op {
+    fn precedence(&self) -> u8 {
+        match self {
+            Op::Add | Op::Sub => 1,
+            Op::Mul | Op::Div | Op::Rem => 2,
+        }
+    }
+}
+
+/// Expression computing a value from fields of a record, e.g. `status / 100` or
+/// `split(path, "/", 1)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// Number or `"text"`.
+    Literal(Value),
+    /// Value of a top-level field, names with other characters than letters, digits, `_`, `.`
+    /// and `@` are quoted with backticks.
+    Field(String),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(Number),
+    Text(String),
+    Ident(String),
+    Op(Op),
+    Open,
+    Close,
+    Comma,
+}
+
+fn tokens(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = expr.chars().peekable();
+    while let Some(&char) = chars.peek() {
+        let token = match char {
+            char if char.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '0'..='9' => {
+                let number = take_while(&mut chars, |char| char.is_ascii_digit() || char == '.');
+                let number = match number.parse::<i64>() {
+                    Ok(number) => Number::from(number),
+                    Err(_) => number
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(Number::from_f64)
+                        .ok_or_else(|| format!("Invalid number [{}]", number))?,
+                };
+                Token::Number(number)
+            }
+            '"' | '`' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') if char == '"' => text.extend(chars.next()),
+                        Some(end) if end == char => break,
+                        Some(other) => text.push(other),
+                        None => return Err(format!("Missing closing {} in [{}]", char, expr)),
+                    }
+                }
+                if char == '"' {
+                    Token::Text(text)
+                } else {
+                    Token::Ident(text)
+                }
+            }
+            char if char.is_alphabetic() || char == '_' || char == '@' => {
+                Token::Ident(take_while(&mut chars, |char| {
+                    char.is_alphanumeric() || matches!(char, '_' | '.' | '@')
+                }))
+            }
+            other => {
+                chars.next();
+                match other {
+                    '+' => Token::Op(Op::Add),
+                    '-' => Token::Op(Op::Sub),
+                    '*' => Token::Op(Op::Mul),
+                    '/' => Token::Op(Op::Div),
+                    '%' => Token::Op(Op::Rem),
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    ',' => Token::Comma,
+                    other => return Err(format!("Unexpected {:?} in [{}]", other, expr)),
+                }
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn take_while(chars: &mut Peekable<Chars>, accept: impl Fn(char) -> bool) -> String {
+    let mut taken = String::new();
+    while let Some(&char) = chars.peek().filter(|char| accept(**char)) {
+        taken.push(char);
+        chars.next();
+    }
+    taken
+}
+
+/// Recursive descent parser of a list of tokens.
+struct Parser<'a> {
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+    expr: &'a str,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{} in [{}]", message, self.expr)
+    }
+
+    /// Expression of operators with at least the precedence.
+    fn binary(&mut self, precedence: u8) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(Token::Op(op)) = self.tokens.peek().cloned() {
+            if op.precedence() < precedence {
+                break;
+            }
+            self.tokens.next();
+            let right = self.binary(op.precedence() + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.tokens.next() {
+            Some(Token::Op(Op::Sub)) => Ok(Expr::Neg(Box::new(self.unary()?))),
+            Some(Token::Number(number)) => Ok(Expr::Literal(Value::Number(number))),
+            Some(Token::Text(text)) => Ok(Expr::Literal(Value::String(text))),
+            Some(Token::Open) => {
+                let expr = self.binary(0)?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err(self.error("Missing )")),
+                }
+            }
+            Some(Token::Ident(name)) if self.tokens.peek() == Some(&Token::Open) => {
+                self.tokens.next();
+                let arity = FUNCTIONS
+                    .iter()
+                    .find(|(function, _)| *function == name)
+                    .map(|(_, arity)| *arity)
+                    .ok_or_else(|| self.error(&format!("Unknown function {}", name)))?;
+                let mut args = vec![];
+                if self.tokens.peek() != Some(&Token::Close) {
+                    args.push(self.binary(0)?);
+                    while self.tokens.peek() == Some(&Token::Comma) {
+                        self.tokens.next();
+                        args.push(self.binary(0)?);
+                    }
+                }
+                if self.tokens.next() != Some(Token::Close) {
+                    return Err(self.error(&format!("Missing ) of {}", name)));
+                }
+                if args.is_empty() || arity.is_some_and(|arity| arity != args.len()) {
+                    return Err(self.error(&format!("Wrong number of arguments of {}", name)));
+                }
+                Ok(Expr::Call(name, args))
+            }
+            Some(Token::Ident(name)) => Ok(Expr::Field(name)),
+            _ => Err(self.error("Expected a field, number, text or (")),
+        }
+    }
+}
+
+/// Number of an expression, integers stay integers unless combined with a float.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Num {
+    Int(i64),
+    Float(f64),
+}
+
+impl Num {
+    /// Number of a number or a number written as text.
+    fn of(value: &Value) -> Option<Num> {
+        match value {
+            Value::Number(number) => match number.as_i64() {
+                Some(int) => Some(Num::Int(int)),
+                None => number.as_f64().map(Num::Float),
+            },
+            Value::String(text) => {
+                let text = text.trim();
+                match text.parse::<i64>() {
+                    Ok(int) => Some(Num::Int(int)),
+                    Err(_) => text.parse::<f64>().ok().map(Num::Float),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn float(self) -> f64 {
+        match self {
+            Num::Int(int) => int as f64,
+            Num::Float(float) => float,
+        }
+    }
+
+    fn value(self) -> Value {
+        match self {
+            Num::Int(int) => Value::from(int),
+            Num::Float(float) => Number::from_f64(float).map_or(Value::Null, Value::Number),
+        }
+    }
+}
+
+/// Text of a value: strings as they are, other values as JSON text.
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(text) => Some(text.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// Item of a list at the index, negative indexes count from the end.
+fn nth<T>(items: Vec<T>, idx: i64) -> Option<T> {
+    let len = items.len() as i64;
+    let idx = if idx < 0 { len + idx } else { idx };
+    if idx < 0 {
+        return None;
+    }
+    items.into_iter().nth(idx as usize)
+}
+
+fn arithmetic(op: Op, left: Num, right: Num) -> Option<Num> {
+    match (left, right) {
+        (Num::Int(left), Num::Int(right)) => match op {
+            Op::Add => left.checked_add(right).map(Num::Int),
+            Op::Sub => left.checked_sub(right).map(Num::Int),
+            Op::Mul => left.checked_mul(right).map(Num::Int),
+            // like integer division of most languages, e.g. status classes of `status / 100`
+            Op::Div => left.checked_div(right).map(Num::Int),
+            Op::Rem => left.checked_rem(right).map(Num::Int),
+        },
+        (left, right) => {
+            let (left, right) = (left.float(), right.float());
+            let result = match op {
+                Op::Add => left + right,
+                Op::Sub => left - right,
+                Op::Mul => left * right,
+                Op::Div => left / right,
+                Op::Rem => left % right,
+            };
+            Some(Num::Float(result)).filter(|_| result.is_finite())
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokens(expr)?.into_iter().peekable(),
+            expr,
+        };
+        let parsed = parser.binary(0)?;
+        match parser.tokens.next() {
+            None => Ok(parsed),
+            Some(_) => Err(parser.error("Unexpected token after the expression")),
+        }
+    }
+
+    /// Value of the expression for the record, `null` if a field is missing or a value doesn't
+    /// fit (e.g. text in arithmetic or division by zero). Numbers written as text are numbers.
+    pub fn eval(&self, record: &Value) -> Value {
+        self.try_eval(record).unwrap_or(Value::Null)
+    }
+
+    fn try_eval(&self, record: &Value) -> Option<Value> {
+        match self {
+            Expr::Literal(value) => Some(value.clone()),
+            Expr::Field(field) => record.get(field).cloned(),
+            Expr::Neg(expr) => {
+                let value = Num::of(&expr.try_eval(record)?)?;
+                arithmetic(Op::Sub, Num::Int(0), value).map(Num::value)
+            }
+            Expr::Binary(op, left, right) => {
+                let left = Num::of(&left.try_eval(record)?)?;
+                let right = Num::of(&right.try_eval(record)?)?;
+                arithmetic(*op, left, right).map(Num::value)
+            }
+            Expr::Call(function, args) => {
+                let values: Vec<Value> = args.iter().map(|arg| arg.eval(record)).collect();
+                let int = |idx: usize| match Num::of(&values[idx])? {
+                    Num::Int(int) => Some(int),
+                    Num::Float(_) => None,
+                };
+                match function.as_str() {
+                    "split" => {
+                        let separator = text(&values[1]).filter(|text| !text.is_empty())?;
+                        let parts = text(&values[0])?;
+                        nth(parts.split(separator.as_str()).collect(), int(2)?).map(Value::from)
+                    }
+                    "lower" => text(&values[0]).map(|text| Value::from(text.to_lowercase())),
+                    "upper" => text(&values[0]).map(|text| Value::from(text.to_uppercase())),
+                    "len" => match &values[0] {
+                        Value::Array(items) => Some(Value::from(items.len())),
+                        value => text(value).map(|text| Value::from(text.chars().count())),
+                    },
+                    "substr" => {
+                        let chars: Vec<char> = text(&values[0])?.chars().collect();
+                        let len = chars.len() as i64;
+                        let start = match int(1)? {
+                            start if start < 0 => (len + start).max(0),
+                            start => start.min(len),
+                        };
+                        let count = int(2)?.max(0);
+                        let end = start.saturating_add(count).min(len);
+                        let text: String = chars[start as usize..end as usize].iter().collect();
+                        Some(Value::from(text))
+                    }
+                    "floor" | "round" => match Num::of(&values[0])? {
+                        Num::Int(int) => Some(Value::from(int)),
+                        Num::Float(float) => {
+                            let float = if function == "floor" {
+                                float.floor()
+                            } else {
+                                float.round()
+                            };
+                            // outside of i64 it's a float without fraction anyway
+                            Some(if float.abs() < i64::MAX as f64 {
+                                Value::from(float as i64)
+                            } else {
+                                Num::Float(float).value()
+                            })
+                        }
+                    },
+                    // missing values are empty
+                    "concat" => Some(Value::from(
+                        values.iter().filter_map(text).collect::<String>(),
+                    )),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Field computed for every record by an expression (`--derive`), e.g.
+/// `status_class = status / 100`. Derived fields are added to records before anything is
+/// extracted, so they can be grouped by, filtered and summed like other fields.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Derived {
+    pub name: String,
+    pub expr: Expr,
+}
+
+impl Derived {
+    /// Parse derived field in `<name> = <expression>` format.
+    pub fn parse(derived: &str) -> Result<Self, String> {
+        let (name, expr) = derived.split_once('=').ok_or_else(|| {
+            format!(
+                "Derived field should be <name> = <expression>: [{}]",
+                derived
+            )
+        })?;
+        let name = name.trim().trim_matches('`');
+        if name.is_empty() {
+            return Err(format!("Derived field without name: [{}]", derived));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            expr: Expr::parse(expr)?,
+        })
+    }
+}
+
+impl TryFrom<String> for Derived {
+    type Error = String;
+
+    fn try_from(derived: String) -> Result<Self, Self::Error> {
+        Self::parse(&derived)
+    }
+}
+
+/// Add derived fields to the record in order, so later ones can use earlier ones. Fields whose
+/// expression has no value are missing (an existing field of the name is removed).
+pub fn apply(derived: &[Derived], record: &mut Value) {
+    for field in derived {
+        let value = field.expr.eval(record);
+        if let Value::Object(fields) = record {
+            if value.is_null() {
+                fields.remove(&field.name);
+            } else {
+                fields.insert(field.name.clone(), value);
+            }
+        }
+    }
+}
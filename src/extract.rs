@@ -0,0 +1,227 @@
+use memchr::memchr2;
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// How JSON lines are parsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonBackend {
+    /// Every line is deserialized by serde_json, which validates the whole line.
+    #[default]
+    Serde,
+    /// Only the top level fields which are needed (`type`, `level` and group-by fields) are
+    /// extracted by scanning the line, values of other fields are skipped after checking that
+    /// their strings and brackets are closed. Lines are deserialized by serde_json if more
+    /// fields are extracted (numeric, timestamp etc.).
+    Scan,
+    /// Every line is deserialized by simd-json into the same fields as serde_json. Lines it
+    /// rejects are left to serde_json, so failed lines and their errors are the same.
+    #[cfg(feature = "simd")]
+    Simd,
+}
+
+impl FromStr for JsonBackend {
+    type Err = String;
+
+    fn from_str(backend: &str) -> Result<Self, Self::Err> {
+        match backend {
+            "serde" => Ok(JsonBackend::Serde),
+            "scan" => Ok(JsonBackend::Scan),
+            #[cfg(feature = "simd")]
+            "simd" => Ok(JsonBackend::Simd),
+            #[cfg(not(feature = "simd"))]
+            "simd" => Err(
+                "simd-json backend isn't supported in this build, build with `--features simd`"
+                    .to_string(),
+            ),
+            _ => Err(format!(
+                "Unknown JSON backend [{}], expected serde, scan or simd",
+                backend
+            )),
+        }
+    }
+}
+
+/// Raw JSON values of top level fields of the object in the line, in the order of the names
+/// (`None` for missing fields). Values of fields with other names are skipped. The last value
+/// of a duplicate field is taken if duplicates are allowed.
+pub fn top_level_fields<'a>(
+    line: &'a str,
+    names: &[&str],
+    allow_duplicates: bool,
+) -> Result<Vec<Option<&'a str>>, String> {
+    let mut cursor = Cursor {
+        bytes: line.as_bytes(),
+        pos: 0,
+    };
+    let mut values = vec![None; names.len()];
+
+    cursor.skip_whitespace();
+    cursor.expect(b'{')?;
+    cursor.skip_whitespace();
+    if cursor.peek() == Some(b'}') {
+        cursor.pos += 1;
+    } else {
+        loop {
+            cursor.skip_whitespace();
+            let (start, end) = cursor.string()?;
+            let key = &line[start + 1..end - 1];
+            // escaped keys are rare, they are compared decoded
+            let idx = match key.contains('\\') {
+                true => {
+                    let key: String =
+                        serde_json::from_str(&line[start..end]).map_err(|err| err.to_string())?;
+                    names.iter().position(|name| *name == key)
+                }
+                false => names.iter().position(|name| *name == key),
+            };
+            cursor.skip_whitespace();
+            cursor.expect(b':')?;
+            cursor.skip_whitespace();
+            let (start, end) = cursor.value()?;
+            if let Some(idx) = idx {
+                if values[idx].is_some() && !allow_duplicates {
+                    return Err(cursor.error(&format!("duplicate field `{}`", names[idx])));
+                }
+                values[idx] = Some(&line[start..end]);
+            }
+            cursor.skip_whitespace();
+            match cursor.next() {
+                Some(b',') => continue,
+                Some(b'}') => break,
+                _ => return Err(cursor.error("expected `,` or `}`")),
+            }
+        }
+    }
+    cursor.skip_whitespace();
+    if cursor.peek().is_some() {
+        return Err(cursor.error("trailing characters"));
+    }
+
+    Ok(values)
+}
+
+/// Text of raw JSON string, `None` if the value is not a string.
+pub fn string_value(raw: &str) -> Result<Option<String>, String> {
+    if !raw.starts_with('"') {
+        return Ok(None);
+    }
+    if !raw.contains('\\') {
+        return Ok(Some(raw[1..raw.len() - 1].to_string()));
+    }
+    serde_json::from_str(raw)
+        .map(Some)
+        .map_err(|err| err.to_string())
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek();
+        self.pos += 1;
+        byte
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\r' | b'\n') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), String> {
+        match self.next() {
+            Some(byte) if byte == expected => Ok(()),
+            _ => Err(self.error(&format!("expected `{}`", expected as char))),
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!(
+            "{} at column {}",
+            message,
+            self.pos.min(self.bytes.len()) + 1
+        )
+    }
+
+    /// Span of the string starting at the cursor, quotes included.
+    fn string(&mut self) -> Result<(usize, usize), String> {
+        let start = self.pos;
+        self.expect(b'"')?;
+        loop {
+            match memchr2(b'"', b'\\', &self.bytes[self.pos..]) {
+                Some(idx) if self.bytes[self.pos + idx] == b'"' => {
+                    self.pos += idx + 1;
+                    return Ok((start, self.pos));
+                }
+                // escaped character is skipped
+                Some(idx) => self.pos += idx + 2,
+                None => {
+                    self.pos = self.bytes.len();
+                    return Err(self.error("EOF while parsing a string"));
+                }
+            }
+            if self.pos > self.bytes.len() {
+                return Err(self.error("EOF while parsing a string"));
+            }
+        }
+    }
+
+    /// Span of the value starting at the cursor. Nested values are only checked to be closed.
+    fn value(&mut self) -> Result<(usize, usize), String> {
+        let start = self.pos;
+        match self.peek() {
+            Some(b'"') => return self.string(),
+            Some(b'{' | b'[') => {
+                let mut closing = vec![];
+                loop {
+                    match self.peek() {
+                        Some(b'"') => {
+                            self.string()?;
+                            continue;
+                        }
+                        Some(b'{') => closing.push(b'}'),
+                        Some(b'[') => closing.push(b']'),
+                        Some(byte @ (b'}' | b']')) => {
+                            if closing.pop() != Some(byte) {
+                                return Err(self.error("mismatched bracket"));
+                            }
+                            if closing.is_empty() {
+                                self.pos += 1;
+                                return Ok((start, self.pos));
+                            }
+                        }
+                        Some(_) => {}
+                        None => return Err(self.error("EOF while parsing a value")),
+                    }
+                    self.pos += 1;
+                }
+            }
+            _ => {
+                while let Some(byte) = self.peek() {
+                    if matches!(byte, b',' | b'}' | b']' | b' ' | b'\t' | b'\r' | b'\n') {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+            }
+        }
+        // numbers aren't parsed, literals are checked
+        let scalar = &self.bytes[start..self.pos];
+        let valid = match scalar.first() {
+            Some(b'-' | b'0'..=b'9') => true,
+            _ => matches!(scalar, b"true" | b"false" | b"null"),
+        };
+        if !valid {
+            return Err(self.error("expected value"));
+        }
+        Ok((start, self.pos))
+    }
+}
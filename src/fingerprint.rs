@@ -0,0 +1,166 @@
+use crate::parser::ParsedRecord;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Placeholder used when producer field is missing.
+const UNKNOWN: &str = "-";
+
+/// Robust z-score above which producer metric is flagged as outlier.
+const OUTLIER_SCORE: f64 = 3.5;
+
+/// Relative deviation from the fleet median flagged when most producers have the same value.
+const OUTLIER_RELATIVE_DEVIATION: f64 = 0.05;
+
+/// Field set of a record (sorted field names).
+type Fingerprint = Vec<String>;
+
+#[derive(Default)]
+struct ProducerStats {
+    counter: u64,
+    num_of_bytes: u64,
+    errors: u64,
+    fingerprints: HashMap<Fingerprint, u64>,
+}
+
+/// Report of a single producer compared to the fleet.
+#[derive(Serialize, Debug)]
+pub struct ProducerReport {
+    pub producer: String,
+    pub counter: u64,
+    pub num_of_bytes: u64,
+    pub error_rate: f64,
+    pub avg_line_size: f64,
+    /// Fraction of logs with the fleet's most common field set.
+    pub conformance: f64,
+    /// Why the producer deviates from the fleet norm (empty if it doesn't).
+    pub outlier_reasons: Vec<String>,
+}
+
+/// Report of all producers.
+#[derive(Serialize, Debug)]
+pub struct FleetReport {
+    /// Most common field set across the fleet.
+    pub fleet_fingerprint: Vec<String>,
+    pub producers: Vec<ProducerReport>,
+}
+
+/// Groups records by producer identity (values of producer fields, e.g. host and service).
+///
+/// Records have to be parsed with producer fields as the only `string_fields` and with
+/// `collect_field_names` enabled.
+#[derive(Default)]
+pub struct FleetFingerprinter {
+    producers: HashMap<String, ProducerStats>,
+}
+
+impl FleetFingerprinter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&mut self, record: &ParsedRecord) {
+        let producer = record
+            .string_values
+            .iter()
+            .map(|value| value.as_deref().unwrap_or(UNKNOWN))
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut fingerprint = record.field_names.clone();
+        fingerprint.sort();
+
+        let stats = self.producers.entry(producer).or_default();
+        stats.counter += 1;
+        stats.num_of_bytes += record.num_of_bytes;
+        stats.errors += record.is_error() as u64;
+        *stats.fingerprints.entry(fingerprint).or_insert(0) += 1;
+    }
+
+    pub fn report(self) -> FleetReport {
+        // fleet norm is the field set used by most logs
+        let mut fleet_fingerprints: HashMap<&Fingerprint, u64> = HashMap::new();
+        for stats in self.producers.values() {
+            for (fingerprint, counter) in &stats.fingerprints {
+                *fleet_fingerprints.entry(fingerprint).or_insert(0) += counter;
+            }
+        }
+        let fleet_fingerprint = fleet_fingerprints
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+            .map(|(fingerprint, _)| fingerprint.clone())
+            .unwrap_or_default();
+
+        let mut producers: Vec<ProducerReport> = self
+            .producers
+            .iter()
+            .map(|(producer, stats)| {
+                let conforming = stats
+                    .fingerprints
+                    .get(&fleet_fingerprint)
+                    .copied()
+                    .unwrap_or(0);
+                ProducerReport {
+                    producer: producer.clone(),
+                    counter: stats.counter,
+                    num_of_bytes: stats.num_of_bytes,
+                    error_rate: stats.errors as f64 / stats.counter as f64,
+                    avg_line_size: stats.num_of_bytes as f64 / stats.counter as f64,
+                    conformance: conforming as f64 / stats.counter as f64,
+                    outlier_reasons: vec![],
+                }
+            })
+            .collect();
+        producers.sort_by(|a, b| a.producer.cmp(&b.producer));
+
+        flag_outliers(&mut producers, "conformance", |p| p.conformance);
+        flag_outliers(&mut producers, "error_rate", |p| p.error_rate);
+        flag_outliers(&mut producers, "avg_line_size", |p| p.avg_line_size);
+        flag_outliers(&mut producers, "volume", |p| p.num_of_bytes as f64);
+
+        FleetReport {
+            fleet_fingerprint,
+            producers,
+        }
+    }
+}
+
+/// Flag producers whose metric deviates from the fleet median (modified z-score based on
+/// median absolute deviation, so a single outlier doesn't hide itself by shifting the mean).
+fn flag_outliers<F>(producers: &mut [ProducerReport], name: &str, metric: F)
+where
+    F: Fn(&ProducerReport) -> f64,
+{
+    // there is no norm with less than three producers
+    if producers.len() < 3 {
+        return;
+    }
+
+    let values: Vec<f64> = producers.iter().map(&metric).collect();
+    let fleet_median = median(values.clone());
+    let mad = median(values.iter().map(|v| (v - fleet_median).abs()).collect());
+
+    for (producer, value) in producers.iter_mut().zip(values) {
+        let deviation = value - fleet_median;
+        let is_outlier = if mad > 0.0 {
+            (0.6745 * deviation / mad).abs() > OUTLIER_SCORE
+        } else {
+            deviation.abs() > OUTLIER_RELATIVE_DEVIATION * fleet_median.abs().max(f64::EPSILON)
+        };
+        if is_outlier {
+            producer.outlier_reasons.push(format!(
+                "{} {:.4} deviates from fleet median {:.4}",
+                name, value, fleet_median
+            ));
+        }
+    }
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
@@ -0,0 +1,165 @@
+use crate::interrupt;
+use crate::parser::ParseOptions;
+use crate::platform;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// File followed like `tail -F`: every poll returns lines appended since the last one. A
+/// truncated file is read from the start again, a replaced (rotated) one is read to its end
+/// first and then the new file at the path is followed from its start.
+#[derive(Debug)]
+pub struct FollowedFile {
+    path: PathBuf,
+    file: Option<File>,
+    offset: u64,
+    /// Lines of a file existing when following starts are skipped unless reading from start.
+    skip_existing: bool,
+    /// Last line without new line character yet.
+    partial: Vec<u8>,
+}
+
+impl FollowedFile {
+    /// Follow the file (it doesn't have to exist yet), `from_start` reads lines it already has.
+    pub fn new<P: AsRef<Path>>(path: P, from_start: bool) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            file: None,
+            offset: 0,
+            skip_existing: !from_start,
+            partial: vec![],
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Complete lines appended since the last poll, without new line characters.
+    pub fn poll(&mut self) -> io::Result<Vec<String>> {
+        if self.file.is_none() {
+            match platform::open_shared(&self.path) {
+                Ok(file) => {
+                    self.offset = match self.skip_existing {
+                        true => file.metadata()?.len(),
+                        false => 0,
+                    };
+                    self.file = Some(file);
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    // a file created later is read from its start
+                    self.skip_existing = false;
+                    return Ok(vec![]);
+                }
+                Err(err) => return Err(err),
+            }
+            self.skip_existing = false;
+        }
+        let file = self.file.as_mut().expect("File is open");
+
+        let metadata = file.metadata()?;
+        if metadata.len() < self.offset {
+            self.offset = 0;
+            self.partial.clear();
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut appended = vec![];
+        self.offset += file.read_to_end(&mut appended)? as u64;
+        let mut lines = self.split_lines(&appended);
+
+        // the old file was read to its end, the rest of the partial line won't come
+        if is_replaced(&self.path, &metadata) {
+            if !self.partial.is_empty() {
+                lines.push(String::from_utf8_lossy(&self.partial).into_owned());
+            }
+            self.file = None;
+            self.offset = 0;
+            self.partial.clear();
+        }
+
+        Ok(lines)
+    }
+
+    fn split_lines(&mut self, appended: &[u8]) -> Vec<String> {
+        self.partial.extend_from_slice(appended);
+        let end = match self.partial.iter().rposition(|byte| *byte == b'\n') {
+            Some(idx) => idx + 1,
+            None => return vec![],
+        };
+        let complete: Vec<u8> = self.partial.drain(..end).collect();
+        String::from_utf8_lossy(&complete)
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+}
+
+/// What [`follow`] hands to its handler.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Followed<'a> {
+    /// Complete lines appended to the file since its last poll.
+    Lines(&'a Path, Vec<String>),
+    /// Every file was polled, `snapshot` is set once the snapshot interval passed since the
+    /// previous one.
+    Polled { snapshot: bool },
+}
+
+/// Poll the files every `poll_interval` until interrupted (Ctrl-C) or the handler returns
+/// `false`. Intervals are measured and waited for by the clock of the options, so following
+/// runs on simulated time with [`ManualClock`](crate::clock::ManualClock).
+pub fn follow<F>(
+    files: &mut [FollowedFile],
+    options: &ParseOptions,
+    poll_interval: Duration,
+    snapshot_interval: Duration,
+    mut handler: F,
+) -> io::Result<()>
+where
+    F: FnMut(Followed) -> io::Result<bool>,
+{
+    let clock = options.clock();
+    let mut snapshot_at = clock.instant() + snapshot_interval;
+    while !interrupt::is_interrupted() {
+        for file in files.iter_mut() {
+            let lines = file.poll().map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Can't read {}: {}", file.path().display(), err),
+                )
+            })?;
+            if !handler(Followed::Lines(file.path(), lines))? {
+                return Ok(());
+            }
+        }
+        let snapshot = clock.instant() >= snapshot_at;
+        if snapshot {
+            snapshot_at = clock.instant() + snapshot_interval;
+        }
+        if !handler(Followed::Polled { snapshot })? {
+            return Ok(());
+        }
+        clock.sleep(poll_interval);
+    }
+    Ok(())
+}
+
+/// Whether the path points to another file than the followed one, or to none.
+fn is_replaced(path: &Path, followed: &Metadata) -> bool {
+    match fs::metadata(path) {
+        Ok(current) => file_id(&current) != file_id(followed),
+        Err(_) => true,
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Rotation can't be detected without file ids, only truncation.
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
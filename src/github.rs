@@ -0,0 +1,86 @@
+use serde_json::json;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Default GitHub REST API endpoint.
+pub const API_URL: &str = "https://api.github.com";
+
+/// Issue or pull request (`owner/repo#123`), PR comments use the issues API too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IssueRef {
+    pub repo: String,
+    pub number: u64,
+}
+
+impl IssueRef {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("Expected owner/repo#number: [{}]", value);
+        let (repo, number) = value.rsplit_once('#').ok_or_else(invalid)?;
+        if repo.split('/').count() != 2 || repo.split('/').any(str::is_empty) {
+            return Err(invalid());
+        }
+        let number = number.parse().map_err(|_| invalid())?;
+
+        Ok(Self {
+            repo: repo.to_string(),
+            number,
+        })
+    }
+}
+
+/// Post comment on issue or pull request, returns URL of the created comment.
+///
+/// HTTPS is handled by `curl`, which has to be installed. The token and the comment are passed
+/// to curl via stdin, so they neither show up in the process list nor are written to files.
+pub fn post_comment(
+    api_url: &str,
+    issue: &IssueRef,
+    token: &str,
+    body: &str,
+) -> io::Result<String> {
+    let url = format!(
+        "{}/repos/{}/issues/{}/comments",
+        api_url.trim_end_matches('/'),
+        issue.repo,
+        issue.number
+    );
+    let payload = json!({ "body": body }).to_string();
+
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail-with-body", "--config", "-"])
+        .arg(&url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut curl| {
+            let config = format!(
+                "header = \"Authorization: Bearer {}\"\nheader = \"Accept: application/vnd.github+json\"\nheader = \"Content-Type: application/json\"\nheader = \"User-Agent: log-parser\"\ndata-binary = \"{}\"\n",
+                token,
+                quote(&payload)
+            );
+            curl.stdin
+                .take()
+                .expect("Stdin is piped")
+                .write_all(config.as_bytes())?;
+            curl.wait_with_output()
+        });
+    let output = output?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "GitHub API request failed: {}{}",
+            String::from_utf8_lossy(&output.stderr).trim(),
+            String::from_utf8_lossy(&output.stdout).trim()
+        )));
+    }
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    Ok(response["html_url"].as_str().unwrap_or(&url).to_string())
+}
+
+/// Value of a quoted string of the curl config, JSON has no raw line breaks to escape.
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,187 @@
+//! Grafana data source endpoints of [`crate::serve::Server`]: the SimpleJSON (JSON data source
+//! plugin) `search` and `query` calls, and flat JSON arrays for the Infinity plugin.
+//!
+//! Targets are log types (or groups). Time series of a target are its lines (or errors with
+//! `{"payload": {"metric": "errors"}}`) per rate window, or a single point with the live counter
+//! at the time of the query if the parser doesn't count rate windows.
+
+use crate::clock::Clock;
+use crate::parser::{LogRegister, ParseReport};
+use crate::timestamp;
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Target of a table query listing every log type.
+const ALL_TARGETS: &str = "*";
+
+/// Names of the log types containing the `target` of the search request, sorted.
+pub fn search(report: &ParseReport, body: &[u8]) -> Result<Value, String> {
+    let request = request(body)?;
+    let filter = request["target"].as_str().unwrap_or_default();
+    let mut names: Vec<&String> = report
+        .log_register
+        .keys()
+        .filter(|name| name.contains(filter))
+        .collect();
+    names.sort();
+    Ok(json!(names))
+}
+
+/// Time series or tables of the targets of the query request. Windows outside of its `range` are
+/// left out.
+pub fn query(report: &ParseReport, body: &[u8], clock: &dyn Clock) -> Result<Value, String> {
+    let request = request(body)?;
+    let range = |bound: &str| {
+        request["range"][bound]
+            .as_str()
+            .and_then(timestamp::parse_str)
+    };
+    let (from, to) = (range("from"), range("to"));
+    let targets = match request["targets"].as_array() {
+        Some(targets) => targets,
+        None => return Err("Query should have targets".to_string()),
+    };
+
+    let mut results = vec![];
+    for target in targets {
+        let name = target["target"].as_str().unwrap_or_default();
+        if target["type"] == "table" {
+            results.push(table(report, name));
+            continue;
+        }
+        let register = match report.log_register.get(name) {
+            Some(register) => register,
+            None => continue,
+        };
+        let payload = target.get("payload").unwrap_or(&target["data"]);
+        let errors = match payload["metric"].as_str() {
+            None | Some("lines") => false,
+            Some("errors") => true,
+            Some(metric) => {
+                return Err(format!(
+                    "Unknown metric {}, should be lines or errors",
+                    metric
+                ))
+            }
+        };
+        let datapoints = datapoints(register, errors, from, to, clock.now());
+        results.push(json!({"target": name, "datapoints": datapoints}));
+    }
+    Ok(Value::Array(results))
+}
+
+/// Counters of every log type as rows for the Infinity plugin.
+pub fn counters(report: &ParseReport) -> Value {
+    let mut rows: Vec<Value> = report
+        .log_register
+        .iter()
+        .map(|(name, register)| {
+            json!({
+                "log_type": name,
+                "counter": register.counter,
+                "num_of_bytes": register.num_of_bytes,
+                "errors": register.errors,
+            })
+        })
+        .collect();
+    rows.sort_by(|a, b| a["log_type"].as_str().cmp(&b["log_type"].as_str()));
+    Value::Array(rows)
+}
+
+/// Lines and errors of every log type per rate window as rows for the Infinity plugin, ordered
+/// by time.
+pub fn buckets(report: &ParseReport) -> Value {
+    let mut rows = vec![];
+    for (name, register) in &report.log_register {
+        for (start, count) in &register.windows {
+            rows.push((*start, name, count));
+        }
+    }
+    rows.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+    let rows = rows
+        .into_iter()
+        .map(|(start, name, count)| {
+            json!({
+                "time": start * 1000,
+                "log_type": name,
+                "counter": count.lines,
+                "errors": count.errors,
+            })
+        })
+        .collect();
+    Value::Array(rows)
+}
+
+fn request(body: &[u8]) -> Result<Value, String> {
+    // Grafana tests the data source with an empty body
+    if body.is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_slice(body).map_err(|err| format!("Invalid JSON request: {}", err))
+}
+
+/// `[value, milliseconds since epoch]` pairs of the windows within the range.
+fn datapoints(
+    register: &LogRegister,
+    errors: bool,
+    from: Option<SystemTime>,
+    to: Option<SystemTime>,
+    now: SystemTime,
+) -> Vec<[u64; 2]> {
+    if register.windows.is_empty() {
+        let value = if errors {
+            register.errors
+        } else {
+            register.counter
+        };
+        return vec![[value, millis(now)]];
+    }
+    let (from, to) = (from.map_or(0, millis), to.map_or(u64::MAX, millis));
+    register
+        .windows
+        .iter()
+        .map(|(start, count)| (start * 1000, count))
+        .filter(|(start, _)| (from..=to).contains(start))
+        .map(|(start, count)| {
+            let value = if errors { count.errors } else { count.lines };
+            [value, start]
+        })
+        .collect()
+}
+
+/// Table of the log type, or of every log type with `*` (or no target).
+fn table(report: &ParseReport, name: &str) -> Value {
+    let mut rows: Vec<(&String, &LogRegister)> = report
+        .log_register
+        .iter()
+        .filter(|(log_type, _)| matches!(name, "" | ALL_TARGETS) || *log_type == name)
+        .collect();
+    rows.sort_by_key(|(log_type, _)| *log_type);
+    let rows: Vec<Value> = rows
+        .into_iter()
+        .map(|(log_type, register)| {
+            json!([
+                log_type,
+                register.counter,
+                register.num_of_bytes,
+                register.errors
+            ])
+        })
+        .collect();
+    json!({
+        "type": "table",
+        "columns": [
+            {"text": "log_type", "type": "string"},
+            {"text": "counter", "type": "number"},
+            {"text": "num_of_bytes", "type": "number"},
+            {"text": "errors", "type": "number"},
+        ],
+        "rows": rows,
+    })
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default()
+}
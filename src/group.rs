@@ -0,0 +1,165 @@
+use crate::parser::LogRegister;
+use crate::units;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::AddAssign;
+use std::sync::{Arc, RwLock};
+
+/// Separator of field values in group keys, e.g. `http / error` for `type` and `level`.
+pub const SEPARATOR: &str = " / ";
+
+/// Group key of missing or null fields.
+pub const MISSING: &str = "-";
+
+/// Group key from values of group-by fields.
+pub fn key<S: AsRef<str>>(values: &[S]) -> String {
+    let values: Vec<&str> = values.iter().map(AsRef::as_ref).collect();
+    values.join(SEPARATOR)
+}
+
+/// Values of group-by fields in the group key, the last value keeps separators of values beyond
+/// `num_of_fields`.
+pub fn values(key: &str, num_of_fields: usize) -> Vec<&str> {
+    key.splitn(num_of_fields.max(1), SEPARATOR).collect()
+}
+
+/// Registers of parsing threads keyed by compact group keys.
+pub type Registers = HashMap<GroupKey, LogRegister>;
+
+/// Group key of registers while parsing: ids of the values of group-by fields (or of the log
+/// type) in [`GroupKeys`], 4 bytes per field instead of the joined values.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GroupKey(Box<[u32]>);
+
+/// Values of group keys interned once for all threads, so high-cardinality composite groups
+/// (e.g. user × endpoint) keep every value once instead of in every key they are part of.
+#[derive(Debug, Default)]
+pub struct GroupKeys {
+    interned: RwLock<Interned>,
+}
+
+#[derive(Debug, Default)]
+struct Interned {
+    ids: HashMap<Arc<str>, u32>,
+    values: Vec<Arc<str>>,
+}
+
+impl GroupKeys {
+    /// Key of the values of group-by fields in their order.
+    pub fn key<S: AsRef<str>>(&self, values: &[S]) -> GroupKey {
+        GroupKey(values.iter().map(|value| self.id(value.as_ref())).collect())
+    }
+
+    fn id(&self, value: &str) -> u32 {
+        if let Some(id) = self.read().ids.get(value) {
+            return *id;
+        }
+        let mut interned = self.interned.write().expect("Group keys poisoned");
+        if let Some(id) = interned.ids.get(value) {
+            return *id;
+        }
+        let id = interned.values.len() as u32;
+        let value: Arc<str> = Arc::from(value);
+        interned.values.push(value.clone());
+        interned.ids.insert(value, id);
+        id
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Interned> {
+        self.interned.read().expect("Group keys poisoned")
+    }
+
+    /// Values of the key, field by field.
+    pub fn values(&self, key: &GroupKey) -> Vec<Arc<str>> {
+        let interned = self.read();
+        key.0
+            .iter()
+            .map(|id| interned.values[*id as usize].clone())
+            .collect()
+    }
+
+    /// Group key of reports, the values joined by [`SEPARATOR`].
+    pub fn name(&self, key: &GroupKey) -> String {
+        let interned = self.read();
+        let values: Vec<&str> = key
+            .0
+            .iter()
+            .map(|id| interned.values[*id as usize].as_ref())
+            .collect();
+        self::key(&values)
+    }
+
+    /// Registers of reports keyed by [`GroupKeys::name`].
+    pub fn named(&self, registers: Registers) -> HashMap<String, LogRegister> {
+        let mut named: HashMap<String, LogRegister> = HashMap::with_capacity(registers.len());
+        for (key, register) in registers {
+            // keys of checkpoints are names already, which may equal a name of values
+            named
+                .entry(self.name(&key))
+                .or_insert(LogRegister::zero())
+                .add_assign(register);
+        }
+        named
+    }
+
+    /// Registers keyed by names of reports (e.g. saved by checkpoints), every name is a key of a
+    /// single value.
+    pub fn unnamed(&self, registers: HashMap<String, LogRegister>) -> Registers {
+        registers
+            .into_iter()
+            .map(|(name, register)| (self.key(&[name]), register))
+            .collect()
+    }
+
+    /// Number of interned values.
+    pub fn len(&self) -> usize {
+        self.read().values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Split group key into the value of the first field and values of the rest.
+fn split(key: &str) -> (&str, &str) {
+    key.split_once(SEPARATOR).unwrap_or((key, MISSING))
+}
+
+/// Matrix of grouped registers: rows are values of the first group-by field, columns values of
+/// the rest (e.g. type × level). Returns header and rows with the cell value of every group
+/// (empty if the combination wasn't seen) and a total column.
+pub fn pivot<F>(
+    register: &HashMap<String, LogRegister>,
+    row_field: &str,
+    cell: F,
+) -> (Vec<String>, Vec<Vec<String>>)
+where
+    F: Fn(&LogRegister) -> u64,
+{
+    let columns: BTreeSet<&str> = register.keys().map(|key| split(key).1).collect();
+    let mut rows: BTreeMap<&str, HashMap<&str, u64>> = BTreeMap::new();
+    for (key, value) in register {
+        let (row, column) = split(key);
+        *rows.entry(row).or_default().entry(column).or_default() += cell(value);
+    }
+
+    let mut header = vec![row_field.to_string()];
+    header.extend(columns.iter().map(|column| column.to_string()));
+    header.push("total".to_string());
+    let rows = rows
+        .into_iter()
+        .map(|(row, cells)| {
+            let mut values = vec![row.to_string()];
+            values.extend(columns.iter().map(|column| {
+                cells
+                    .get(column)
+                    .map(units::format_count)
+                    .unwrap_or_default()
+            }));
+            values.push(units::format_count(cells.values().sum::<u64>()));
+            values
+        })
+        .collect();
+
+    (header, rows)
+}
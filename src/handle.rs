@@ -0,0 +1,64 @@
+use crate::interrupt::CancelToken;
+use crate::parser::ParseReport;
+use crate::run::RunError;
+use std::panic;
+use std::thread::{self, JoinHandle};
+
+/// Parse running on background threads, see [`ParserOptionsBuilder::spawn`]. Dropping the
+/// handle cancels the run and waits for its threads, so none of them outlive it.
+///
+/// [`ParserOptionsBuilder::spawn`]: crate::run::ParserOptionsBuilder::spawn
+pub struct ParseHandle {
+    cancel: CancelToken,
+    thread: Option<JoinHandle<Result<ParseReport, RunError>>>,
+}
+
+/// Start the parse on a background thread, it stops once the token is cancelled.
+pub(crate) fn spawn<F>(cancel: CancelToken, parse: F) -> ParseHandle
+where
+    F: FnOnce() -> Result<ParseReport, RunError> + Send + 'static,
+{
+    let thread = thread::spawn(parse);
+
+    ParseHandle {
+        cancel,
+        thread: Some(thread),
+    }
+}
+
+impl ParseHandle {
+    /// Stop parsing at the next record of every thread, [`ParseHandle::join`] returns the
+    /// partial report.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// Whether the report is ready, so joining doesn't block.
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Wait for the report, a cancelled run has the rest of the file in
+    /// [`ParseReport::unread_ranges`]. Fails if an input can't be read, panics of the parser are
+    /// resumed.
+    pub fn join(mut self) -> Result<ParseReport, RunError> {
+        let thread = self.thread.take().expect("Parse handle is joined once");
+        thread
+            .join()
+            .unwrap_or_else(|panic| panic::resume_unwind(panic))
+    }
+}
+
+impl Drop for ParseHandle {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.cancel.cancel();
+            // the report of an abandoned run is not needed, neither its panic
+            let _ = thread.join();
+        }
+    }
+}
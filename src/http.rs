@@ -0,0 +1,155 @@
+use crate::tls::Stream;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::time::Duration;
+
+/// Timeout of connecting, reading and writing.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// HTTP or HTTPS URL (`http[s]://host[:port][/path]`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+    /// Whether requests are sent over TLS (`https://`), see [`crate::tls`].
+    pub tls: bool,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let (rest, tls) = match (url.strip_prefix("http://"), url.strip_prefix("https://")) {
+            (Some(rest), _) => (rest, false),
+            (_, Some(rest)) => (rest, true),
+            _ => {
+                return Err(format!(
+                    "URL should start with http:// or https://: [{}]",
+                    url
+                ))
+            }
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => (&rest[..idx], &rest[idx..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rfind(':') {
+            Some(idx) => (
+                &authority[..idx],
+                authority[idx + 1..]
+                    .parse()
+                    .map_err(|_| format!("Invalid port in URL: [{}]", url))?,
+            ),
+            None => (authority, if tls { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            return Err(format!("URL without host: [{}]", url));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            tls,
+        })
+    }
+}
+
+/// HTTP response status and body.
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Send POST request (new connection for every request).
+pub fn post(
+    url: &Url,
+    content_type: &str,
+    body: &[u8],
+    headers: &[(&str, &str)],
+) -> io::Result<Response> {
+    request("POST", url, content_type, body, headers)
+}
+
+/// Send request with body (new connection for every request).
+pub fn request(
+    method: &str,
+    url: &Url,
+    content_type: &str,
+    body: &[u8],
+    headers: &[(&str, &str)],
+) -> io::Result<Response> {
+    let mut stream = Stream::connect(&url.host, url.port, url.tls)?;
+    stream.tcp().set_read_timeout(Some(TIMEOUT))?;
+    stream.tcp().set_write_timeout(Some(TIMEOUT))?;
+
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        method,
+        url.path,
+        url.host,
+        url.port,
+        content_type,
+        body.len()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    read_response(stream)
+}
+
+fn read_response(stream: Stream) -> io::Result<Response> {
+    let mut reader = BufReader::new(stream);
+
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid HTTP status line: [{}]", status_line.trim()),
+            )
+        })?;
+
+    let mut content_length: Option<usize> = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let mut body = vec![];
+    match content_length {
+        Some(length) => {
+            body.resize(length, 0);
+            reader.read_exact(&mut body)?;
+        }
+        // connection is closed after the response, by some TLS servers without notifying
+        None => match reader.read_to_end(&mut body) {
+            Err(err) if err.kind() != io::ErrorKind::UnexpectedEof => return Err(err),
+            _ => {}
+        },
+    }
+
+    Ok(Response { status, body })
+}
@@ -0,0 +1,454 @@
+use crate::distinct;
+use crate::parser::{self, ParseOptions};
+use crate::platform;
+use crate::projection::{self, Condition};
+use crate::scanner::RecordScanner;
+use log::error;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Extension of the index, next to the indexed file.
+pub const EXTENSION: &str = "idx";
+
+/// Bytes of records in a block by default, a block ends with the record crossing the size.
+pub const DEFAULT_BLOCK_BYTES: u64 = 1 << 20;
+
+/// First bytes of an index file, the last one is the version of the format.
+const MAGIC: &[u8] = b"LPIDX\x02";
+
+/// Fraction of values missing in a block which its filter takes for present ones.
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Index of `<file>.idx`.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = OsString::from(path.as_os_str());
+    sidecar.push(".");
+    sidecar.push(EXTENSION);
+    PathBuf::from(sidecar)
+}
+
+/// Hash of the field with its value, stable across builds as it's kept in files.
+fn hash(field: &str, value: &str) -> u64 {
+    distinct::fnv1a(field.bytes().chain([0]).chain(value.bytes()))
+}
+
+/// Bloom filter of the field values of a block.
+#[derive(Debug, Clone, PartialEq)]
+struct Bloom {
+    bits: Vec<u64>,
+    num_of_hashes: u64,
+}
+
+impl Bloom {
+    /// Filter of the hashes, sized for the false positive rate.
+    fn new(hashes: &HashSet<u64>) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let num_of_bits = -(hashes.len().max(1) as f64) * FALSE_POSITIVE_RATE.ln() / (ln2 * ln2);
+        let mut bloom = Self {
+            bits: vec![0; ((num_of_bits / 64.0).ceil() as usize).max(1)],
+            num_of_hashes: (-FALSE_POSITIVE_RATE.log2()).ceil() as u64,
+        };
+        for hash in hashes {
+            for bit in bloom.bits_of(*hash) {
+                bloom.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+            }
+        }
+        bloom
+    }
+
+    /// Bits of the hash, double hashing like [`crate::dedup::SeenIds`].
+    fn bits_of(&self, hash: u64) -> impl Iterator<Item = u64> {
+        let num_of_bits = self.bits.len() as u64 * 64;
+        let step = hash.rotate_left(32).wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+        (0..self.num_of_hashes)
+            .map(move |idx| hash.wrapping_add(idx.wrapping_mul(step)) % num_of_bits)
+    }
+
+    fn contains(&self, hash: u64) -> bool {
+        self.bits_of(hash)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+}
+
+/// Records starting in a range of the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// Offset of the first record.
+    pub start: u64,
+    /// Offset of the first record of the next block (size of the file for the last block).
+    pub end: u64,
+    pub records: u64,
+    /// Records which couldn't be parsed, they match no condition but every query reports them.
+    pub failed: u64,
+    values: Bloom,
+    /// Sizes of records as varints.
+    record_sizes: Vec<u8>,
+}
+
+impl Block {
+    /// Offsets of the records of the block.
+    pub fn record_offsets(&self) -> Vec<u64> {
+        let mut sizes = self.record_sizes.as_slice();
+        let mut offset = self.start;
+        (0..self.records)
+            .map(|_| {
+                let record = offset;
+                offset += read_varint(&mut sizes).expect("Record sizes are complete");
+                record
+            })
+            .collect()
+    }
+}
+
+/// Sidecar index of a file: offsets of its records and bloom filters of values of fields per
+/// block of records. Queries with conditions on the fields read only blocks which may have
+/// matching records, so re-querying a huge file with other conditions doesn't scan all of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Index {
+    size: u64,
+    /// Modification time of the file in nanoseconds since epoch, 0 if the platform has none.
+    modified: u64,
+    fields: Vec<String>,
+    blocks: Vec<Block>,
+}
+
+impl Index {
+    /// Index values of the fields of the file in blocks of about `block_bytes`. The file has
+    /// to be queried with the same options of the format (pattern, CSV, delimiter, multiline)
+    /// so records start at the same offsets. Values are compared as text, like in
+    /// [`Condition::matches`].
+    pub fn build<P: AsRef<Path>>(
+        input_file: P,
+        fields: Vec<String>,
+        block_bytes: u64,
+        options: &ParseOptions,
+    ) -> io::Result<Self> {
+        let input_file = input_file.as_ref();
+        let file = platform::open_shared(input_file)?;
+        let metadata = file.metadata()?;
+        let options = ParseOptions {
+            value_fields: fields.clone(),
+            ..options.clone()
+        };
+        let mut scanner = scanner(options.retry_reader(file), &options);
+        let mut index = Index {
+            size: metadata.len(),
+            modified: modified(&metadata),
+            fields,
+            blocks: vec![],
+        };
+
+        let mut offset = header_len(&mut scanner, &options)?;
+        let mut block = BlockBuilder::new(offset);
+        while let Some(record) = scanner.next_record()? {
+            match parser::parse_scanned(offset, &record, &options) {
+                Ok(parsed) => {
+                    for (field, value) in index.fields.iter().zip(&parsed.values) {
+                        if let Some(value) = value.as_ref().filter(|value| !value.is_null()) {
+                            block.values.insert(hash(field, &projection::text(value)));
+                        }
+                    }
+                }
+                Err(err) => {
+                    error!("{}", err);
+                    block.failed += 1;
+                }
+            }
+            block.add(record.num_of_bytes);
+            offset += record.num_of_bytes;
+            if offset - block.start >= block_bytes.max(1) {
+                index.blocks.push(block.finish(offset));
+                block = BlockBuilder::new(offset);
+            }
+        }
+        if block.records > 0 {
+            index.blocks.push(block.finish(offset));
+        }
+
+        Ok(index)
+    }
+
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn records(&self) -> u64 {
+        self.blocks.iter().map(|block| block.records).sum()
+    }
+
+    /// Offset of the record (numbered from 0, a CSV header isn't a record).
+    pub fn record_offset(&self, record: u64) -> Option<u64> {
+        let mut first = 0;
+        for block in &self.blocks {
+            if record < first + block.records {
+                return Some(block.record_offsets()[(record - first) as usize]);
+            }
+            first += block.records;
+        }
+        None
+    }
+
+    /// Whether the index is of the file as it is now: same size and modification time.
+    pub fn is_current<P: AsRef<Path>>(&self, input_file: P) -> io::Result<bool> {
+        let metadata = fs::metadata(input_file)?;
+        Ok(metadata.len() == self.size && modified(&metadata) == self.modified)
+    }
+
+    /// Blocks which may have records matching all conditions, or have records which couldn't be
+    /// parsed. Only conditions requiring a value of an indexed field rule blocks out, a few
+    /// blocks without the value are kept as bloom filters have false positives.
+    pub fn matching_blocks(&self, conditions: &[Condition]) -> Vec<&Block> {
+        let hashes: Vec<u64> = conditions
+            .iter()
+            .filter(|condition| !condition.negated && self.fields.contains(&condition.field))
+            .map(|condition| hash(&condition.field, &condition.value))
+            .collect();
+        self.blocks
+            .iter()
+            .filter(|block| {
+                block.failed > 0 || hashes.iter().all(|hash| block.values.contains(*hash))
+            })
+            .collect()
+    }
+
+    /// Write the index into the file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = vec![];
+        out.extend_from_slice(MAGIC);
+        write_varint(&mut out, self.size);
+        write_varint(&mut out, self.modified);
+        write_varint(&mut out, self.fields.len() as u64);
+        for field in &self.fields {
+            write_varint(&mut out, field.len() as u64);
+            out.extend_from_slice(field.as_bytes());
+        }
+        write_varint(&mut out, self.blocks.len() as u64);
+        for block in &self.blocks {
+            write_varint(&mut out, block.start);
+            write_varint(&mut out, block.end - block.start);
+            write_varint(&mut out, block.records);
+            write_varint(&mut out, block.failed);
+            write_varint(&mut out, block.values.num_of_hashes);
+            write_varint(&mut out, block.values.bits.len() as u64);
+            for word in &block.values.bits {
+                out.extend_from_slice(&word.to_le_bytes());
+            }
+            write_varint(&mut out, block.record_sizes.len() as u64);
+            out.extend_from_slice(&block.record_sizes);
+        }
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&out)?;
+        file.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read(path)?;
+        Self::decode(&content).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid index {}: {}", path.display(), err),
+            )
+        })
+    }
+
+    fn decode(content: &[u8]) -> io::Result<Self> {
+        let mut input = content
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| io::Error::other("not an index of this version"))?;
+        let size = read_varint(&mut input)?;
+        let modified = read_varint(&mut input)?;
+        let fields = (0..read_varint(&mut input)?)
+            .map(|_| {
+                let field = read_bytes(&mut input)?;
+                String::from_utf8(field).map_err(|err| io::Error::other(err.to_string()))
+            })
+            .collect::<io::Result<Vec<String>>>()?;
+        let blocks = (0..read_varint(&mut input)?)
+            .map(|_| {
+                let start = read_varint(&mut input)?;
+                let end = start + read_varint(&mut input)?;
+                let records = read_varint(&mut input)?;
+                let failed = read_varint(&mut input)?;
+                let num_of_hashes = read_varint(&mut input)?;
+                let bits = (0..read_varint(&mut input)?)
+                    .map(|_| {
+                        let mut word = [0; 8];
+                        input.read_exact(&mut word)?;
+                        Ok(u64::from_le_bytes(word))
+                    })
+                    .collect::<io::Result<Vec<u64>>>()?;
+                Ok(Block {
+                    start,
+                    end,
+                    records,
+                    failed,
+                    values: Bloom {
+                        bits,
+                        num_of_hashes,
+                    },
+                    record_sizes: read_bytes(&mut input)?,
+                })
+            })
+            .collect::<io::Result<Vec<Block>>>()?;
+
+        Ok(Self {
+            size,
+            modified,
+            fields,
+            blocks,
+        })
+    }
+}
+
+/// Block being indexed.
+struct BlockBuilder {
+    start: u64,
+    records: u64,
+    failed: u64,
+    values: HashSet<u64>,
+    record_sizes: Vec<u8>,
+}
+
+impl BlockBuilder {
+    fn new(start: u64) -> Self {
+        Self {
+            start,
+            records: 0,
+            failed: 0,
+            values: HashSet::new(),
+            record_sizes: vec![],
+        }
+    }
+
+    fn add(&mut self, num_of_bytes: u64) {
+        self.records += 1;
+        write_varint(&mut self.record_sizes, num_of_bytes);
+    }
+
+    fn finish(self, end: u64) -> Block {
+        Block {
+            start: self.start,
+            end,
+            records: self.records,
+            failed: self.failed,
+            values: Bloom::new(&self.values),
+            record_sizes: self.record_sizes,
+        }
+    }
+}
+
+fn modified(metadata: &Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |modified| modified.as_nanos() as u64)
+}
+
+/// Unsigned LEB128: 7 bits per byte, the high bit set on all bytes but the last one.
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        input.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(io::Error::other("varint longer than 64 bits"))
+}
+
+/// Bytes prefixed by their length.
+fn read_bytes(input: &mut &[u8]) -> io::Result<Vec<u8>> {
+    let len = read_varint(input)? as usize;
+    if len > input.len() {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes.to_vec())
+}
+
+fn scanner<R: Read>(reader: R, options: &ParseOptions) -> RecordScanner<BufReader<R>> {
+    options.scanner(BufReader::new(reader))
+}
+
+/// Skip the CSV header at the start of the scanner (if the options have one), returns its size.
+fn header_len<R: Read>(
+    scanner: &mut RecordScanner<BufReader<R>>,
+    options: &ParseOptions,
+) -> io::Result<u64> {
+    if !options.csv.as_ref().is_some_and(|csv| csv.has_header) {
+        return Ok(0);
+    }
+    Ok(scanner
+        .next_record()?
+        .map_or(0, |header| header.num_of_bytes))
+}
+
+/// Byte ranges of inputs which may have records matching the conditions of a query, by the
+/// indexes of the inputs (see [`Index::matching_blocks`]). Parsers read only these ranges of an
+/// input and skip the rest unread, inputs without ranges are read whole.
+#[derive(Debug, Default)]
+pub struct ReadRanges {
+    ranges: HashMap<PathBuf, Vec<(u64, u64)>>,
+}
+
+impl ReadRanges {
+    /// Read only the blocks of the index of the input which may have matching records, returns
+    /// the number of bytes skipped.
+    pub fn insert<P: AsRef<Path>>(
+        &mut self,
+        input_file: P,
+        index: &Index,
+        conditions: &[Condition],
+    ) -> u64 {
+        let mut ranges: Vec<(u64, u64)> = vec![];
+        for block in index.matching_blocks(conditions) {
+            match ranges.last_mut() {
+                Some((_, end)) if *end == block.start => *end = block.end,
+                _ => ranges.push((block.start, block.end)),
+            }
+        }
+        let read: u64 = ranges.iter().map(|(start, end)| end - start).sum();
+        let indexed: u64 = index
+            .blocks
+            .iter()
+            .map(|block| block.end - block.start)
+            .sum();
+        self.ranges
+            .insert(input_file.as_ref().to_path_buf(), ranges);
+        indexed - read
+    }
+
+    /// Where reading of the input goes on from the offset: the offset if it's in a range of the
+    /// input (or the input has none), the start of the next range otherwise, `None` if no range
+    /// is left.
+    pub(crate) fn next(&self, input_file: &Path, offset: u64) -> Option<u64> {
+        let ranges = match self.ranges.get(input_file) {
+            Some(ranges) => ranges,
+            None => return Some(offset),
+        };
+        let next = ranges.partition_point(|(_, end)| *end <= offset);
+        ranges.get(next).map(|(start, _)| offset.max(*start))
+    }
+}
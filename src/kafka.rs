@@ -0,0 +1,172 @@
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer as _};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::Message;
+use rdkafka::producer::{BaseProducer, BaseRecord, DeliveryResult, Producer as _, ProducerContext};
+use rdkafka::ClientContext;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Time to wait for queued messages to be delivered when a producer is flushed.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `kafka://host:port,host:port/topic` of Kafka sinks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KafkaUrl {
+    pub brokers: String,
+    pub topic: String,
+}
+
+impl KafkaUrl {
+    pub fn parse(uri: &str) -> Result<Self, String> {
+        let invalid = || format!("Kafka URI should be kafka://host:port/topic: [{}]", uri);
+        let (brokers, topic) = uri
+            .strip_prefix("kafka://")
+            .and_then(|rest| rest.split_once('/'))
+            .ok_or_else(invalid)?;
+        if brokers.is_empty() || topic.is_empty() || topic.contains('/') {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            brokers: brokers.to_string(),
+            topic: topic.to_string(),
+        })
+    }
+}
+
+/// Consumer of a Kafka topic in a consumer group. Offsets are committed by [`Consumer::commit`],
+/// so a restarted consumer continues after the last commit of the group; a new group starts at
+/// the end of the topic.
+pub struct Consumer {
+    consumer: BaseConsumer,
+    /// Lines of the last message not taken yet.
+    lines: VecDeque<String>,
+}
+
+impl Consumer {
+    pub fn subscribe(brokers: &str, topic: &str, group: &str) -> io::Result<Self> {
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("group.id", group)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "latest")
+            .create()
+            .map_err(io::Error::other)?;
+        consumer.subscribe(&[topic]).map_err(io::Error::other)?;
+
+        Ok(Self {
+            consumer,
+            lines: VecDeque::new(),
+        })
+    }
+
+    /// Next line of a message (every line of a message is a record), `None` if none arrived
+    /// within the timeout.
+    pub fn next_line(&mut self, timeout: Duration) -> io::Result<Option<String>> {
+        if let Some(line) = self.lines.pop_front() {
+            return Ok(Some(line));
+        }
+        let message = match self.consumer.poll(timeout) {
+            Some(message) => message.map_err(io::Error::other)?,
+            None => return Ok(None),
+        };
+        let payload = String::from_utf8_lossy(message.payload().unwrap_or_default());
+        self.lines.extend(payload.lines().map(String::from));
+        Ok(self.lines.pop_front())
+    }
+
+    /// Commit offsets of the messages consumed so far for the group.
+    pub fn commit(&self) -> io::Result<()> {
+        match self.consumer.commit_consumer_state(CommitMode::Sync) {
+            // nothing was consumed since the last commit
+            Err(KafkaError::ConsumerCommit(RDKafkaErrorCode::NoOffset)) => Ok(()),
+            committed => committed.map_err(io::Error::other),
+        }
+    }
+}
+
+/// Keeps the first failed delivery of a producer until it's flushed.
+#[derive(Default)]
+struct DeliveryContext {
+    failed: Mutex<Option<KafkaError>>,
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: ()) {
+        if let Err((err, _)) = result {
+            let mut failed = self.failed.lock().expect("Delivery lock is poisoned");
+            failed.get_or_insert_with(|| err.clone());
+        }
+    }
+}
+
+/// Producer of messages to a Kafka topic. Messages are queued and sent in the background, a
+/// failed delivery is returned by the next [`Producer::flush`].
+pub struct Producer {
+    producer: BaseProducer<DeliveryContext>,
+    topic: String,
+}
+
+impl Producer {
+    pub fn connect(url: &KafkaUrl) -> io::Result<Self> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", &url.brokers)
+            .create_with_context(DeliveryContext::default())
+            .map_err(io::Error::other)?;
+
+        Ok(Self {
+            producer,
+            topic: url.topic.clone(),
+        })
+    }
+
+    /// Queue the message, waiting for deliveries while the queue is full.
+    pub fn send(&self, payload: &[u8]) -> io::Result<()> {
+        let mut record = BaseRecord::<(), [u8]>::to(&self.topic).payload(payload);
+        loop {
+            match self.producer.send(record) {
+                Ok(()) => break,
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), rejected)) => {
+                    self.producer.poll(Duration::from_millis(100));
+                    record = rejected;
+                }
+                Err((err, _)) => return Err(io::Error::other(err)),
+            }
+        }
+        // serves delivery callbacks of earlier messages
+        self.producer.poll(Duration::ZERO);
+        self.failed()
+    }
+
+    /// Wait until every queued message is delivered.
+    pub fn flush(&self) -> io::Result<()> {
+        self.producer
+            .flush(FLUSH_TIMEOUT)
+            .map_err(io::Error::other)?;
+        self.failed()
+    }
+
+    fn failed(&self) -> io::Result<()> {
+        let failed = self
+            .producer
+            .context()
+            .failed
+            .lock()
+            .expect("Delivery lock is poisoned")
+            .take();
+        match failed {
+            Some(err) => Err(io::Error::other(format!(
+                "Delivery to {} failed: {}",
+                self.topic, err
+            ))),
+            None => Ok(()),
+        }
+    }
+}
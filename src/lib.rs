@@ -1 +1,91 @@
+pub mod aggregator;
+pub mod alert;
+#[cfg(feature = "amqp")]
+pub mod amqp;
+pub mod anonymize;
+#[cfg(feature = "async")]
+pub mod async_parser;
+pub mod budget;
+pub mod cardinality;
+pub mod checkpoint;
+pub mod chunk;
+pub mod clock;
+pub mod config;
+pub mod cost;
+pub mod csv;
+pub mod decompress;
+pub mod dedup;
+pub mod diagnostics;
+pub mod diff;
+pub mod distinct;
+pub mod drift;
+pub mod encoding;
+pub mod error_log;
+pub mod expr;
+pub mod extract;
+pub mod fingerprint;
+pub mod follow;
+pub mod github;
+pub mod grafana;
+pub mod group;
+pub mod handle;
+pub mod http;
+pub mod index;
+pub mod interrupt;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod map_backend;
+pub mod memory;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod normalize;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+pub mod parquet;
 pub mod parser;
+pub mod pattern;
+pub mod plan;
+pub mod platform;
+pub mod preview;
+pub mod profiling;
+pub mod progress;
+pub mod projection;
+pub mod quarantine;
+pub mod rate;
+pub mod redis;
+pub mod reingest;
+#[cfg(any(feature = "s3", feature = "gcs"))]
+pub mod remote;
+pub mod replay;
+pub mod retention;
+pub mod reverse;
+pub mod rollup;
+pub mod run;
+pub mod sampling;
+pub mod scanner;
+pub mod seek;
+pub mod serve;
+pub mod signing;
+pub mod sink;
+pub mod slo;
+pub mod split;
+pub mod spool;
+pub mod sqlite;
+pub mod state;
+pub mod stats;
+pub mod subset;
+pub mod summary;
+pub mod table;
+pub mod template;
+pub mod terminal;
+pub mod testing;
+pub mod throttle;
+pub mod timestamp;
+pub mod tls;
+pub mod tui;
+pub mod tune;
+pub mod units;
+pub mod validate;
+pub mod watch;
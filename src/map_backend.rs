@@ -0,0 +1,180 @@
+use crate::group::{GroupKey, Registers};
+use crate::parser::LogRegister;
+use dashmap::DashMap;
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::ops::AddAssign;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Number of locks of [`MapBackend::ShardMerge`].
+const SHARDS: usize = 16;
+
+/// Map the registers of parsing threads are merged into (see
+/// [`crate::parser::multi_thread_parser_merged`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MapBackend {
+    /// Concurrent hash map every thread merges its registers into.
+    #[default]
+    Dashmap,
+    /// Hash maps of a share of the keys each, locked separately, so threads rarely wait for
+    /// each other with many keys.
+    ShardMerge,
+    /// Registers of every thread are kept in their own hash map (hashbrown of the standard
+    /// library) and merged into the largest one at the end, no locks while parsing.
+    HashbrownLocal,
+    /// Ordered map behind a lock, compact with few keys (e.g. log types).
+    #[serde(rename = "btreemap")]
+    BTreeMap,
+}
+
+impl MapBackend {
+    pub const ALL: [MapBackend; 4] = [
+        MapBackend::Dashmap,
+        MapBackend::ShardMerge,
+        MapBackend::HashbrownLocal,
+        MapBackend::BTreeMap,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MapBackend::Dashmap => "dashmap",
+            MapBackend::ShardMerge => "shard-merge",
+            MapBackend::HashbrownLocal => "hashbrown-local",
+            MapBackend::BTreeMap => "btreemap",
+        }
+    }
+
+    /// Empty map of the backend.
+    pub fn map(&self) -> Box<dyn RegisterMap> {
+        match self {
+            MapBackend::Dashmap => Box::new(DashMap::<GroupKey, LogRegister>::new()),
+            MapBackend::ShardMerge => Box::new(ShardMerge(
+                (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+            )),
+            MapBackend::HashbrownLocal => Box::new(HashbrownLocal(Mutex::new(vec![]))),
+            MapBackend::BTreeMap => Box::new(Mutex::new(BTreeMap::<GroupKey, LogRegister>::new())),
+        }
+    }
+}
+
+impl FromStr for MapBackend {
+    type Err = String;
+
+    fn from_str(backend: &str) -> Result<Self, Self::Err> {
+        MapBackend::ALL
+            .iter()
+            .find(|known| known.name() == backend)
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "Unknown map backend [{}], expected dashmap, shard-merge, hashbrown-local or btreemap",
+                    backend
+                )
+            })
+    }
+}
+
+/// Registers per key shared by parsing threads, every thread merges the registers of the part of
+/// the file it parsed.
+pub trait RegisterMap: Send + Sync {
+    /// Add the register to the register of the key.
+    fn insert(&self, key: GroupKey, register: LogRegister);
+
+    /// Add registers of a part of the file.
+    fn merge(&self, registers: Registers) {
+        for (key, register) in registers {
+            self.insert(key, register);
+        }
+    }
+
+    /// Registers of all parts once all threads are done.
+    fn into_registers(self: Box<Self>) -> Registers;
+}
+
+impl RegisterMap for DashMap<GroupKey, LogRegister> {
+    fn insert(&self, key: GroupKey, register: LogRegister) {
+        self.entry(key)
+            .or_insert(LogRegister::zero())
+            .add_assign(register);
+    }
+
+    fn into_registers(self: Box<Self>) -> Registers {
+        (*self).into_iter().collect()
+    }
+}
+
+struct ShardMerge(Vec<Mutex<Registers>>);
+
+impl RegisterMap for ShardMerge {
+    fn insert(&self, key: GroupKey, register: LogRegister) {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let shard = &self.0[hasher.finish() as usize % SHARDS];
+        shard
+            .lock()
+            .expect("Register shard poisoned")
+            .entry(key)
+            .or_insert(LogRegister::zero())
+            .add_assign(register);
+    }
+
+    fn into_registers(self: Box<Self>) -> Registers {
+        // keys of shards are disjoint
+        self.0
+            .into_iter()
+            .flat_map(|shard| shard.into_inner().expect("Register shard poisoned"))
+            .collect()
+    }
+}
+
+struct HashbrownLocal(Mutex<Vec<Registers>>);
+
+impl RegisterMap for HashbrownLocal {
+    fn insert(&self, key: GroupKey, register: LogRegister) {
+        self.merge(HashMap::from([(key, register)]));
+    }
+
+    fn merge(&self, registers: Registers) {
+        self.0
+            .lock()
+            .expect("Local registers poisoned")
+            .push(registers);
+    }
+
+    fn into_registers(self: Box<Self>) -> Registers {
+        let mut locals = self.0.into_inner().expect("Local registers poisoned");
+        // the largest register is kept, so the fewest entries are merged
+        locals.sort_by_key(|registers| std::cmp::Reverse(registers.len()));
+        let mut locals = locals.into_iter();
+        let mut into = locals.next().unwrap_or_default();
+        for from in locals {
+            for (key, register) in from {
+                into.entry(key)
+                    .or_insert(LogRegister::zero())
+                    .add_assign(register);
+            }
+        }
+        into
+    }
+}
+
+impl RegisterMap for Mutex<BTreeMap<GroupKey, LogRegister>> {
+    fn insert(&self, key: GroupKey, register: LogRegister) {
+        self.lock()
+            .expect("Registers poisoned")
+            .entry(key)
+            .or_insert(LogRegister::zero())
+            .add_assign(register);
+    }
+
+    fn into_registers(self: Box<Self>) -> Registers {
+        self.into_inner()
+            .expect("Registers poisoned")
+            .into_iter()
+            .collect()
+    }
+}
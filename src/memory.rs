@@ -0,0 +1,195 @@
+use crate::profiling::Stage;
+use serde::Serialize;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Part of the program allocations are attributed to, set per thread with [`set_module`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Module {
+    /// Anything outside of the other modules (startup, reports).
+    Other,
+    /// Reading records from inputs.
+    Read,
+    /// Parsing records into logs.
+    Parse,
+    /// Adding logs to registers, aggregators and channels.
+    Aggregate,
+    /// Writing results to sinks.
+    Sink,
+}
+
+pub const MODULES: [Module; 5] = [
+    Module::Other,
+    Module::Read,
+    Module::Parse,
+    Module::Aggregate,
+    Module::Sink,
+];
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Module::Other => "other",
+            Module::Read => "read",
+            Module::Parse => "parse",
+            Module::Aggregate => "aggregate",
+            Module::Sink => "sink",
+        };
+        f.write_str(name)
+    }
+}
+
+thread_local! {
+    static MODULE: Cell<Module> = const { Cell::new(Module::Other) };
+}
+
+/// Allocation counters of a module.
+struct ModuleCounters {
+    allocations: AtomicU64,
+    allocated: AtomicU64,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: ModuleCounters = ModuleCounters {
+    allocations: AtomicU64::new(0),
+    allocated: AtomicU64::new(0),
+};
+
+static MODULE_COUNTERS: [ModuleCounters; MODULES.len()] = [ZERO; MODULES.len()];
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// Attribute allocations of the current thread to the module from now on. Costs a thread local
+/// write, and nothing without the `memory-profile` feature.
+#[inline]
+pub fn set_module(module: Module) {
+    if cfg!(feature = "memory-profile") {
+        // threads being torn down keep their module
+        let _ = MODULE.try_with(|current| current.set(module));
+    }
+}
+
+/// Attribute allocations of the current thread to the stage following the ended one, parsing
+/// threads read, parse and aggregate every record in turn.
+#[inline]
+pub fn stage_ended(stage: Stage) {
+    set_module(match stage {
+        Stage::Read => Module::Parse,
+        Stage::Parse => Module::Aggregate,
+        Stage::Aggregate => Module::Read,
+    });
+}
+
+/// Attribute allocations of the current thread to the module until the guard is dropped.
+pub fn scope(module: Module) -> ModuleScope {
+    let previous = MODULE.try_with(Cell::get).unwrap_or(Module::Other);
+    set_module(module);
+    ModuleScope { previous }
+}
+
+/// Guard of [`scope`], restores the previous module when dropped.
+pub struct ModuleScope {
+    previous: Module,
+}
+
+impl Drop for ModuleScope {
+    fn drop(&mut self) {
+        set_module(self.previous);
+    }
+}
+
+/// Allocator counting live bytes, their peak and bytes allocated by every [`Module`] on top of
+/// the system allocator, registered by the binary with the `memory-profile` feature.
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    fn allocated(&self, size: usize) {
+        let module = MODULE.try_with(Cell::get).unwrap_or(Module::Other);
+        let counters = &MODULE_COUNTERS[module as usize];
+        counters.allocations.fetch_add(1, Ordering::Relaxed);
+        counters.allocated.fetch_add(size as u64, Ordering::Relaxed);
+        let live = LIVE.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(live, Ordering::Relaxed);
+    }
+
+    fn freed(&self, size: usize) {
+        LIVE.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            self.allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        self.freed(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            self.freed(layout.size());
+            self.allocated(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// Memory profile of a run, keys are sorted so snapshots of two runs can be diffed line by line.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MemorySnapshot {
+    /// Most bytes allocated at once.
+    pub peak_bytes: u64,
+    /// Bytes allocated when the snapshot was taken.
+    pub live_bytes: u64,
+    pub modules: BTreeMap<String, ModuleMemory>,
+}
+
+/// Allocations attributed to a module.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ModuleMemory {
+    pub allocations: u64,
+    /// Bytes allocated in total, freed ones included.
+    pub allocated_bytes: u64,
+}
+
+/// Counters of [`CountingAllocator`], none without the `memory-profile` feature.
+pub fn snapshot() -> Option<MemorySnapshot> {
+    if !cfg!(feature = "memory-profile") {
+        return None;
+    }
+    let modules = MODULES
+        .iter()
+        .map(|module| {
+            let counters = &MODULE_COUNTERS[*module as usize];
+            let memory = ModuleMemory {
+                allocations: counters.allocations.load(Ordering::Relaxed),
+                allocated_bytes: counters.allocated.load(Ordering::Relaxed),
+            };
+            (module.to_string(), memory)
+        })
+        .collect();
+
+    Some(MemorySnapshot {
+        peak_bytes: PEAK.load(Ordering::Relaxed) as u64,
+        live_bytes: LIVE.load(Ordering::Relaxed) as u64,
+        modules,
+    })
+}
@@ -0,0 +1,327 @@
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Default port of MQTT brokers.
+pub const DEFAULT_PORT: u16 = 1883;
+
+/// Timeout of the handshake and of writes.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const PUBREC: u8 = 0x50;
+const PUBREL: u8 = 0x60;
+const PUBCOMP: u8 = 0x70;
+const SUBSCRIBE: u8 = 0x80;
+const SUBACK: u8 = 0x90;
+const PINGREQ: u8 = 0xc0;
+
+/// `mqtt://[user[:password]@]host[:port]` URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttUrl {
+    pub host: String,
+    pub port: u16,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+impl MqttUrl {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("mqtt://")
+            .ok_or_else(|| format!("URL should start with mqtt://: [{}]", url))?;
+        let authority = rest.trim_end_matches('/');
+        let (user, password, address) = match authority.rsplit_once('@') {
+            Some((credentials, address)) => match credentials.split_once(':') {
+                Some((user, password)) => (Some(user), Some(password), address),
+                None => (Some(credentials), None, address),
+            },
+            None => (None, None, authority),
+        };
+        let (host, port) = match address.rfind(':') {
+            Some(idx) => (
+                &address[..idx],
+                address[idx + 1..]
+                    .parse()
+                    .map_err(|_| format!("Invalid port in URL: [{}]", url))?,
+            ),
+            None => (address, DEFAULT_PORT),
+        };
+        if host.is_empty() {
+            return Err(format!("URL without host: [{}]", url));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            user: user.map(String::from),
+            password: password.map(String::from),
+        })
+    }
+}
+
+/// Session of the subscriber: a clean one is dropped by the broker on disconnect, a persistent
+/// one keeps QoS 1 and 2 messages published while the subscriber is offline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub client_id: String,
+    pub clean: bool,
+    /// The client pings the broker at least this often.
+    pub keep_alive: Duration,
+}
+
+/// Application message of a subscribed topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub topic: String,
+    pub qos: u8,
+    /// Id the message is acked with (QoS 1 and 2 only).
+    pub packet_id: Option<u16>,
+    pub payload: Vec<u8>,
+}
+
+/// Subscriber speaking MQTT 3.1.1 (no TLS). QoS 1 and 2 messages are acked by
+/// [`Subscriber::ack`], so the broker redelivers unacked messages of persistent sessions.
+/// Packets are read by a background thread, another one pings the broker.
+pub struct Subscriber {
+    writer: Arc<Mutex<BufWriter<TcpStream>>>,
+    messages: Receiver<io::Result<Message>>,
+}
+
+impl Subscriber {
+    /// Connect and subscribe to the topic filters (wildcards `+` and `#` can be used) with the
+    /// maximum QoS of delivered messages.
+    pub fn connect(
+        url: &MqttUrl,
+        session: &Session,
+        topic_filters: &[String],
+        qos: u8,
+    ) -> io::Result<Self> {
+        let stream = TcpStream::connect((url.host.as_str(), url.port))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream.try_clone()?);
+
+        let mut flags = 0;
+        let mut body = vec![];
+        put_str(&mut body, "MQTT");
+        // protocol level of 3.1.1
+        body.push(4);
+        if url.user.is_some() {
+            flags |= 0x80;
+        }
+        if url.password.is_some() {
+            flags |= 0x40;
+        }
+        if session.clean {
+            flags |= 0x02;
+        }
+        body.push(flags);
+        let keep_alive = session.keep_alive.as_secs().min(u16::MAX as u64) as u16;
+        body.extend(&keep_alive.to_be_bytes());
+        put_str(&mut body, &session.client_id);
+        if let Some(user) = &url.user {
+            put_str(&mut body, user);
+        }
+        if let Some(password) = &url.password {
+            put_str(&mut body, password);
+        }
+        write_packet(&mut writer, CONNECT, &body)?;
+
+        let (kind, body) = read_packet(&mut reader)?;
+        if kind & 0xf0 != CONNACK || body.len() < 2 {
+            return Err(protocol_error(format!("Expected CONNACK, got {:#x}", kind)));
+        }
+        if body[1] != 0 {
+            return Err(protocol_error(format!(
+                "Broker refused the connection: {}",
+                match body[1] {
+                    1 => "unacceptable protocol version",
+                    2 => "client id rejected",
+                    3 => "server unavailable",
+                    4 => "bad user name or password",
+                    5 => "not authorized",
+                    _ => "unknown reason",
+                }
+            )));
+        }
+
+        let mut body = 1u16.to_be_bytes().to_vec();
+        for topic_filter in topic_filters {
+            put_str(&mut body, topic_filter);
+            body.push(qos.min(2));
+        }
+        write_packet(&mut writer, SUBSCRIBE | 0x02, &body)?;
+        let (kind, body) = read_packet(&mut reader)?;
+        if kind & 0xf0 != SUBACK {
+            return Err(protocol_error(format!("Expected SUBACK, got {:#x}", kind)));
+        }
+        // return code of every filter follows the packet id
+        for (topic_filter, code) in topic_filters.iter().zip(body.iter().skip(2)) {
+            if *code == 0x80 {
+                return Err(protocol_error(format!(
+                    "Broker rejected subscription to {}",
+                    topic_filter
+                )));
+            }
+        }
+        stream.set_read_timeout(None)?;
+
+        let writer = Arc::new(Mutex::new(writer));
+        let (sender, messages) = crossbeam_channel::unbounded();
+        let reader_writer = writer.clone();
+        thread::spawn(move || {
+            if let Err(err) = read_messages(&mut reader, &reader_writer, &sender) {
+                let _ = sender.send(Err(err));
+            }
+        });
+        if keep_alive > 0 {
+            let ping_writer = writer.clone();
+            let interval = session.keep_alive / 2;
+            thread::spawn(move || loop {
+                thread::sleep(interval);
+                let mut writer = ping_writer.lock().expect("MQTT writer lock is poisoned");
+                if write_packet(&mut *writer, PINGREQ, &[]).is_err() {
+                    break;
+                }
+            });
+        }
+
+        Ok(Self { writer, messages })
+    }
+
+    /// Next message, `None` if none arrived within the timeout.
+    pub fn next_message(&self, timeout: Duration) -> io::Result<Option<Message>> {
+        match self.messages.recv_timeout(timeout) {
+            Ok(message) => message.map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "MQTT connection is closed",
+            )),
+        }
+    }
+
+    /// Acknowledge the message: PUBACK for QoS 1, PUBREC for QoS 2 (the broker's PUBREL is
+    /// completed by the reader thread).
+    pub fn ack(&self, message: &Message) -> io::Result<()> {
+        let kind = match message.qos {
+            1 => PUBACK,
+            2 => PUBREC,
+            _ => return Ok(()),
+        };
+        let packet_id = message
+            .packet_id
+            .expect("QoS 1 and 2 messages have packet id");
+        let mut writer = self.writer.lock().expect("MQTT writer lock is poisoned");
+        write_packet(&mut *writer, kind, &packet_id.to_be_bytes())
+    }
+}
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn put_str(buf: &mut Vec<u8>, value: &str) {
+    buf.extend(&(value.len() as u16).to_be_bytes());
+    buf.extend(value.as_bytes());
+}
+
+fn write_packet<W: Write>(writer: &mut W, kind: u8, body: &[u8]) -> io::Result<()> {
+    let mut header = vec![kind];
+    // remaining length: 7 bits per byte, the high bit marks more bytes
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        header.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    writer.write_all(&header)?;
+    writer.write_all(body)?;
+    writer.flush()
+}
+
+/// Read packet as its first byte (type and flags) and body, end of stream is an error.
+fn read_packet<R: Read>(reader: &mut R) -> io::Result<(u8, Vec<u8>)> {
+    let mut byte = [0];
+    reader
+        .read_exact(&mut byte)
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => io::Error::new(err.kind(), "MQTT connection is closed"),
+            _ => err,
+        })?;
+    let kind = byte[0];
+    let mut len = 0;
+    for shift in 0..4 {
+        reader.read_exact(&mut byte)?;
+        len |= ((byte[0] & 0x7f) as usize) << (7 * shift);
+        if byte[0] & 0x80 == 0 {
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body)?;
+            return Ok((kind, body));
+        }
+    }
+
+    Err(protocol_error("Invalid remaining length".to_string()))
+}
+
+/// Read application messages until the connection fails, completing QoS 2 deliveries.
+fn read_messages<R: Read>(
+    reader: &mut R,
+    writer: &Mutex<BufWriter<TcpStream>>,
+    sender: &Sender<io::Result<Message>>,
+) -> io::Result<()> {
+    loop {
+        let (kind, body) = read_packet(reader)?;
+        match kind & 0xf0 {
+            PUBLISH => {
+                let qos = (kind >> 1) & 0x03;
+                let invalid = || protocol_error("Invalid PUBLISH packet".to_string());
+                let topic_len = u16::from_be_bytes([
+                    *body.first().ok_or_else(invalid)?,
+                    *body.get(1).ok_or_else(invalid)?,
+                ]) as usize;
+                let topic = body.get(2..2 + topic_len).ok_or_else(invalid)?;
+                let mut payload_start = 2 + topic_len;
+                let packet_id = match qos {
+                    0 => None,
+                    _ => {
+                        let id = body
+                            .get(payload_start..payload_start + 2)
+                            .ok_or_else(invalid)?;
+                        payload_start += 2;
+                        Some(u16::from_be_bytes([id[0], id[1]]))
+                    }
+                };
+                let message = Message {
+                    topic: String::from_utf8_lossy(topic).into_owned(),
+                    qos,
+                    packet_id,
+                    payload: body[payload_start..].to_vec(),
+                };
+                if sender.send(Ok(message)).is_err() {
+                    return Ok(());
+                }
+            }
+            PUBREL => {
+                let mut writer = writer.lock().expect("MQTT writer lock is poisoned");
+                write_packet(&mut *writer, PUBCOMP, body.get(..2).unwrap_or_default())?;
+            }
+            // PINGRESP, and acks of packets the subscriber doesn't send
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Default port of NATS servers.
+pub const DEFAULT_PORT: u16 = 4222;
+
+/// Timeout of the handshake and of writes.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Server address and subject of a `nats://host[:port][/subject]` URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NatsUrl {
+    pub host: String,
+    pub port: u16,
+    pub subject: Option<String>,
+}
+
+impl NatsUrl {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("nats://")
+            .ok_or_else(|| format!("URL should start with nats://: [{}]", url))?;
+        let (authority, subject) = match rest.split_once('/') {
+            Some((authority, subject)) if !subject.is_empty() => {
+                (authority, Some(subject.to_string()))
+            }
+            Some((authority, _)) => (authority, None),
+            None => (rest, None),
+        };
+        let (host, port) = match authority.rfind(':') {
+            Some(idx) => (
+                &authority[..idx],
+                authority[idx + 1..]
+                    .parse()
+                    .map_err(|_| format!("Invalid port in URL: [{}]", url))?,
+            ),
+            None => (authority, DEFAULT_PORT),
+        };
+        if host.is_empty() {
+            return Err(format!("URL without host: [{}]", url));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            subject,
+        })
+    }
+}
+
+/// Message delivered to a subscription.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub subject: String,
+    /// Subject replies (and JetStream acks) are published to.
+    pub reply: Option<String>,
+    /// Status code of JetStream control messages (e.g. 404 when there are no messages).
+    pub status: Option<u16>,
+    pub payload: Vec<u8>,
+}
+
+/// Connection speaking the core NATS text protocol (no TLS or authentication). Messages are
+/// read by a background thread, which answers server pings too.
+pub struct Connection {
+    writer: Arc<Mutex<BufWriter<TcpStream>>>,
+    messages: Receiver<io::Result<Message>>,
+    next_sid: u64,
+}
+
+impl Connection {
+    pub fn connect(host: &str, port: u16) -> io::Result<Self> {
+        let stream = TcpStream::connect((host, port))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream.try_clone()?);
+
+        let info = read_line(&mut reader)?;
+        if !info.starts_with("INFO") {
+            return Err(protocol_error(format!("Expected INFO, got [{}]", info)));
+        }
+        writer.write_all(
+            b"CONNECT {\"verbose\":false,\"pedantic\":false,\"name\":\"log-parser\",\"lang\":\"rust\",\"version\":\"0.1.0\",\"protocol\":1,\"headers\":true,\"no_responders\":true}\r\nPING\r\n",
+        )?;
+        writer.flush()?;
+        // server confirms the connection by answering the ping, or rejects it with -ERR
+        loop {
+            let line = read_line(&mut reader)?;
+            if line == "PONG" {
+                break;
+            }
+            if let Some(err) = line.strip_prefix("-ERR") {
+                return Err(protocol_error(format!("Server error:{}", err)));
+            }
+        }
+        stream.set_read_timeout(None)?;
+
+        let writer = Arc::new(Mutex::new(writer));
+        let (sender, messages) = crossbeam_channel::unbounded();
+        let pong_writer = writer.clone();
+        thread::spawn(move || loop {
+            let message = read_message(&mut reader, &pong_writer);
+            let failed = message.is_err();
+            if sender.send(message).is_err() || failed {
+                break;
+            }
+        });
+
+        Ok(Self {
+            writer,
+            messages,
+            next_sid: 1,
+        })
+    }
+
+    pub fn publish(&self, subject: &str, payload: &[u8]) -> io::Result<()> {
+        self.publish_request(subject, None, payload)
+    }
+
+    /// Publish message the receiver answers to the reply subject.
+    pub fn publish_request(
+        &self,
+        subject: &str,
+        reply: Option<&str>,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        let mut writer = self.writer.lock().expect("NATS writer lock is poisoned");
+        match reply {
+            Some(reply) => write!(writer, "PUB {} {} {}\r\n", subject, reply, payload.len())?,
+            None => write!(writer, "PUB {} {}\r\n", subject, payload.len())?,
+        }
+        writer.write_all(payload)?;
+        writer.write_all(b"\r\n")?;
+        writer.flush()
+    }
+
+    /// Subscribe to the subject (wildcards `*` and `>` can be used), messages of all
+    /// subscriptions are returned by [`Connection::next_message`].
+    pub fn subscribe(&mut self, subject: &str) -> io::Result<()> {
+        let sid = self.next_sid;
+        self.next_sid += 1;
+        let mut writer = self.writer.lock().expect("NATS writer lock is poisoned");
+        write!(writer, "SUB {} {}\r\n", subject, sid)?;
+        writer.flush()
+    }
+
+    /// Next message, `None` if none arrived within the timeout.
+    pub fn next_message(&self, timeout: Duration) -> io::Result<Option<Message>> {
+        match self.messages.recv_timeout(timeout) {
+            Ok(message) => message.map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "NATS connection is closed",
+            )),
+        }
+    }
+}
+
+/// JetStream pull consumer: messages are fetched in batches and stay in the stream until they
+/// are acked, so records are not lost when the parser stops.
+pub struct PullConsumer {
+    connection: Connection,
+    next_subject: String,
+    inbox: String,
+    batch: usize,
+    /// Messages requested and not delivered yet.
+    pending: usize,
+}
+
+impl PullConsumer {
+    /// Consume messages of an existing durable consumer of the stream.
+    pub fn new(
+        mut connection: Connection,
+        stream: &str,
+        consumer: &str,
+        batch: usize,
+    ) -> io::Result<Self> {
+        let inbox = format!("_INBOX.log-parser.{}", uuid::Uuid::new_v4().to_simple());
+        connection.subscribe(&inbox)?;
+
+        Ok(Self {
+            connection,
+            next_subject: format!("$JS.API.CONSUMER.MSG.NEXT.{}.{}", stream, consumer),
+            inbox,
+            batch: batch.max(1),
+            pending: 0,
+        })
+    }
+
+    /// Next message of the stream, `None` if none arrived within the timeout.
+    pub fn next_message(&mut self, timeout: Duration) -> io::Result<Option<Message>> {
+        if self.pending == 0 {
+            // server ends the request with a 408 status once it expires
+            let request = format!(
+                "{{\"batch\":{},\"expires\":{}}}",
+                self.batch,
+                timeout.as_nanos()
+            );
+            self.connection.publish_request(
+                &self.next_subject,
+                Some(&self.inbox),
+                request.as_bytes(),
+            )?;
+            self.pending = self.batch;
+        }
+
+        // wait a bit longer than the request, so its expiration arrives
+        match self
+            .connection
+            .next_message(timeout + Duration::from_secs(1))?
+        {
+            Some(message) => match message.status {
+                // no messages (404), request expired (408) or consumer changed (409)
+                Some(status) if status >= 400 => {
+                    self.pending = 0;
+                    if status == 503 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::NotFound,
+                            "JetStream is not enabled or the consumer doesn't exist",
+                        ));
+                    }
+                    Ok(None)
+                }
+                Some(_) => Ok(None),
+                None => {
+                    self.pending -= 1;
+                    Ok(Some(message))
+                }
+            },
+            None => {
+                self.pending = 0;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Acknowledge the message, so it's not redelivered.
+    pub fn ack(&self, message: &Message) -> io::Result<()> {
+        match &message.reply {
+            Some(reply) => self.connection.publish(reply, b"+ACK"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Source of records: plain subscription (messages published while not subscribed are lost) or
+/// JetStream pull consumer.
+pub enum Source {
+    Subscription(Connection),
+    Consumer(PullConsumer),
+}
+
+impl Source {
+    /// Next message, `None` if none arrived within the timeout.
+    pub fn next_message(&mut self, timeout: Duration) -> io::Result<Option<Message>> {
+        match self {
+            Source::Subscription(connection) => connection.next_message(timeout),
+            Source::Consumer(consumer) => consumer.next_message(timeout),
+        }
+    }
+
+    /// Acknowledge the message if it comes from JetStream.
+    pub fn ack(&self, message: &Message) -> io::Result<()> {
+        match self {
+            Source::Subscription(_) => Ok(()),
+            Source::Consumer(consumer) => consumer.ack(message),
+        }
+    }
+}
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Read protocol line without `\r\n`, end of stream is an error.
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "NATS connection is closed",
+        ));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Read until the next message, answering pings on the way.
+fn read_message<R: BufRead>(
+    reader: &mut R,
+    writer: &Mutex<BufWriter<TcpStream>>,
+) -> io::Result<Message> {
+    loop {
+        let line = read_line(reader)?;
+        let mut parts = line.split_whitespace();
+        match parts.next() {
+            Some("PING") => {
+                let mut writer = writer.lock().expect("NATS writer lock is poisoned");
+                writer.write_all(b"PONG\r\n")?;
+                writer.flush()?;
+            }
+            // MSG <subject> <sid> [reply] <size>
+            Some("MSG") => {
+                let args: Vec<&str> = parts.collect();
+                let (subject, reply, size) = match args.as_slice() {
+                    [subject, _sid, size] => (subject, None, size),
+                    [subject, _sid, reply, size] => (subject, Some(reply), size),
+                    _ => return Err(protocol_error(format!("Invalid message [{}]", line))),
+                };
+                let size = parse_size(size, &line)?;
+                return Ok(Message {
+                    subject: subject.to_string(),
+                    reply: reply.map(|reply| reply.to_string()),
+                    status: None,
+                    payload: read_payload(reader, size)?,
+                });
+            }
+            // HMSG <subject> <sid> [reply] <header size> <total size>
+            Some("HMSG") => {
+                let args: Vec<&str> = parts.collect();
+                let (subject, reply, header_size, size) = match args.as_slice() {
+                    [subject, _sid, header_size, size] => (subject, None, header_size, size),
+                    [subject, _sid, reply, header_size, size] => {
+                        (subject, Some(reply), header_size, size)
+                    }
+                    _ => return Err(protocol_error(format!("Invalid message [{}]", line))),
+                };
+                let header_size = parse_size(header_size, &line)?;
+                let mut payload = read_payload(reader, parse_size(size, &line)?)?;
+                if header_size > payload.len() {
+                    return Err(protocol_error(format!("Invalid message [{}]", line)));
+                }
+                // status line of headers: NATS/1.0 404 No Messages
+                let status = String::from_utf8_lossy(&payload[..header_size])
+                    .lines()
+                    .next()
+                    .and_then(|status_line| status_line.split_whitespace().nth(1))
+                    .and_then(|status| status.parse().ok());
+                return Ok(Message {
+                    subject: subject.to_string(),
+                    reply: reply.map(|reply| reply.to_string()),
+                    status,
+                    payload: payload.split_off(header_size),
+                });
+            }
+            Some("-ERR") => return Err(protocol_error(format!("Server error: {}", line))),
+            // +OK, PONG and INFO updates
+            _ => {}
+        }
+    }
+}
+
+fn parse_size(size: &str, line: &str) -> io::Result<usize> {
+    size.parse()
+        .map_err(|_| protocol_error(format!("Invalid message [{}]", line)))
+}
+
+/// Read payload of the given size followed by `\r\n`.
+fn read_payload<R: Read>(reader: &mut R, size: usize) -> io::Result<Vec<u8>> {
+    let mut payload = vec![0; size + 2];
+    reader.read_exact(&mut payload)?;
+    payload.truncate(size);
+    Ok(payload)
+}
@@ -0,0 +1,282 @@
+use log::{info, warn};
+use regex::Regex;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::sync::{Arc, RwLock, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often long running modes check files of lookup tables for changes.
+pub const LOOKUP_RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Placeholder of path segments replaced by [`Rule::PathIds`].
+pub const ID_PLACEHOLDER: &str = "{id}";
+
+const UUID_PATTERN: &str =
+    r"^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$";
+
+/// Rewrite of field values before they form group keys.
+#[derive(Debug, Clone)]
+pub enum Rule {
+    /// `lower`: lowercase the value.
+    Lowercase,
+    /// `trim`: strip leading and trailing whitespace.
+    Trim,
+    /// `path-ids`: replace numbers and UUIDs between slashes with [`ID_PLACEHOLDER`], e.g.
+    /// `/users/123/orders` becomes `/users/{id}/orders`. Query strings are kept.
+    PathIds(Regex),
+    /// `lookup:<file>`: map values through a table of `<value>,<mapped value>` lines, values not
+    /// in the table are kept. Clones share the table, so a reload applies to all of them.
+    Lookup(Arc<LookupTable>),
+}
+
+/// Table of a `lookup:<file>` rule, swapped for the content of the file when
+/// [`LookupTable::reload`] finds it modified.
+#[derive(Debug)]
+pub struct LookupTable {
+    path: String,
+    /// Modification time and size of the file the table was loaded from.
+    version: RwLock<Option<(SystemTime, u64)>>,
+    table: RwLock<Arc<HashMap<String, String>>>,
+}
+
+impl LookupTable {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let version = file_version(path);
+        Ok(Self {
+            path: path.to_string(),
+            version: RwLock::new(version),
+            table: RwLock::new(Arc::new(load_table(path)?)),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Table in use, a reload doesn't change tables taken before.
+    pub fn current(&self) -> Arc<HashMap<String, String>> {
+        self.table
+            .read()
+            .expect("Lookup table lock is poisoned")
+            .clone()
+    }
+
+    /// Load the file again if it changed since the table was loaded and swap the table, lookups
+    /// keep the previous table while it's read. Returns whether the table was swapped, a file
+    /// which can't be loaded leaves the previous table in use.
+    pub fn reload(&self) -> Result<bool, String> {
+        let version = file_version(&self.path);
+        if version == *self.version.read().expect("Lookup table lock is poisoned") {
+            return Ok(false);
+        }
+        // the version is taken before reading, so changes during the read are loaded next time
+        *self.version.write().expect("Lookup table lock is poisoned") = version;
+        let table = Arc::new(load_table(&self.path)?);
+        *self.table.write().expect("Lookup table lock is poisoned") = table;
+        Ok(true)
+    }
+}
+
+fn file_version(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+impl PartialEq for Rule {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Rule::Lowercase, Rule::Lowercase)
+            | (Rule::Trim, Rule::Trim)
+            | (Rule::PathIds(_), Rule::PathIds(_)) => true,
+            (Rule::Lookup(table), Rule::Lookup(other)) => {
+                table.path == other.path && table.current() == other.current()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Rule {
+    pub fn parse(rule: &str) -> Result<Self, String> {
+        let rule = rule.trim();
+        match rule {
+            "lower" => return Ok(Rule::Lowercase),
+            "trim" => return Ok(Rule::Trim),
+            "path-ids" => {
+                let uuid = Regex::new(UUID_PATTERN).expect("Invalid UUID pattern");
+                return Ok(Rule::PathIds(uuid));
+            }
+            _ => {}
+        }
+        match rule.strip_prefix("lookup:") {
+            Some(path) => LookupTable::load(path).map(|table| Rule::Lookup(Arc::new(table))),
+            None => Err(format!(
+                "Unknown normalization rule [{}], use lower, trim, path-ids or lookup:<file>",
+                rule
+            )),
+        }
+    }
+
+    fn apply<'a>(&self, value: Cow<'a, str>) -> Cow<'a, str> {
+        match self {
+            Rule::Lowercase if value.chars().any(char::is_uppercase) => {
+                Cow::Owned(value.to_lowercase())
+            }
+            Rule::Trim if value.trim().len() != value.len() => Cow::Owned(value.trim().to_string()),
+            Rule::PathIds(uuid) => {
+                let (path, query) = match value.find('?') {
+                    Some(idx) => value.split_at(idx),
+                    None => (value.as_ref(), ""),
+                };
+                let is_id = |segment: &str| {
+                    (!segment.is_empty() && segment.bytes().all(|byte| byte.is_ascii_digit()))
+                        || uuid.is_match(segment)
+                };
+                if !path.split('/').any(is_id) {
+                    return value;
+                }
+                let path: Vec<&str> = path
+                    .split('/')
+                    .map(|segment| {
+                        if is_id(segment) {
+                            ID_PLACEHOLDER
+                        } else {
+                            segment
+                        }
+                    })
+                    .collect();
+                Cow::Owned(format!("{}{}", path.join("/"), query))
+            }
+            Rule::Lookup(table) => match table.current().get(value.as_ref()) {
+                Some(mapped) => Cow::Owned(mapped.clone()),
+                None => value,
+            },
+            _ => value,
+        }
+    }
+}
+
+/// Lookup table of `<value>,<mapped value>` lines, blank lines and `#` comments are skipped.
+fn load_table(path: &str) -> Result<HashMap<String, String>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Can't read lookup table {}: {}", path, err))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(idx, line)| {
+            line.split_once(',')
+                .map(|(value, mapped)| (value.trim().to_string(), mapped.trim().to_string()))
+                .ok_or_else(|| {
+                    format!(
+                        "Line {} of lookup table {} should be <value>,<mapped value>: [{}]",
+                        idx + 1,
+                        path,
+                        line
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Rules applied in order to values of a field before they form group keys (`--normalize`),
+/// e.g. `path=lower,path-ids` groups `/Users/123` and `/users/456` as `/users/{id}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Normalization {
+    pub field: String,
+    pub rules: Vec<Rule>,
+}
+
+impl Normalization {
+    /// Parse normalization in `<field>=<rule>[,<rule>...]` format.
+    pub fn parse(normalization: &str) -> Result<Self, String> {
+        let (field, rules) = normalization.split_once('=').ok_or_else(|| {
+            format!(
+                "Normalization should be <field>=<rule>[,<rule>...]: [{}]",
+                normalization
+            )
+        })?;
+        let field = field.trim();
+        if field.is_empty() {
+            return Err(format!("Normalization without field: [{}]", normalization));
+        }
+
+        Ok(Self {
+            field: field.to_string(),
+            rules: rules
+                .split(',')
+                .map(Rule::parse)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn apply<'a>(&self, value: Cow<'a, str>) -> Cow<'a, str> {
+        self.rules
+            .iter()
+            .fold(value, |value, rule| rule.apply(value))
+    }
+}
+
+impl TryFrom<String> for Normalization {
+    type Error = String;
+
+    fn try_from(normalization: String) -> Result<Self, Self::Error> {
+        Self::parse(&normalization)
+    }
+}
+
+/// Value of the field normalized by every normalization of the field in order.
+pub fn apply<'a>(
+    normalizations: &[Normalization],
+    field: &str,
+    value: Cow<'a, str>,
+) -> Cow<'a, str> {
+    normalizations
+        .iter()
+        .filter(|normalization| normalization.field == field)
+        .fold(value, |value, normalization| normalization.apply(value))
+}
+
+/// Lookup tables of the normalizations.
+pub fn lookup_tables(normalizations: &[Normalization]) -> Vec<Arc<LookupTable>> {
+    normalizations
+        .iter()
+        .flat_map(|normalization| &normalization.rules)
+        .filter_map(|rule| match rule {
+            Rule::Lookup(table) => Some(table.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Reload lookup tables of the normalizations in a background thread when their files change,
+/// checked every interval, so long running modes don't group by stale mappings. The thread ends
+/// once the normalizations (and their clones) are dropped.
+pub fn watch_lookup_tables(normalizations: &[Normalization], interval: Duration) {
+    let tables: Vec<Weak<LookupTable>> = lookup_tables(normalizations)
+        .iter()
+        .map(Arc::downgrade)
+        .collect();
+    if tables.is_empty() {
+        return;
+    }
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let mut watched = 0;
+        for table in tables.iter().filter_map(Weak::upgrade) {
+            watched += 1;
+            match table.reload() {
+                Ok(true) => info!("Reloaded lookup table {}", table.path()),
+                Ok(false) => {}
+                Err(err) => warn!("{}, the previous table is kept", err),
+            }
+        }
+        if watched == 0 {
+            break;
+        }
+    });
+}
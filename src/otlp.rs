@@ -0,0 +1,156 @@
+use crate::http::{self, Url};
+use crate::parser::{LogRegister, ParseReport};
+use serde_json::{json, Value};
+use std::io;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Path of the metrics endpoint of OTLP/HTTP collectors.
+pub const METRICS_PATH: &str = "/v1/metrics";
+
+/// `AGGREGATION_TEMPORALITY_DELTA`: every export holds what was parsed since the previous one.
+const DELTA: u8 = 1;
+
+/// Start of the window of the next export, the previous export ends it.
+static WINDOW_START: Mutex<Option<SystemTime>> = Mutex::new(None);
+
+/// Start the window of the first export, e.g. when the run starts (the first export itself
+/// otherwise).
+pub fn start_window(start: SystemTime) {
+    *WINDOW_START.lock().expect("OTLP window poisoned") = Some(start);
+}
+
+/// Collector endpoint of an `otlp://host[:port][/path]` URI (`otlps://` over TLS),
+/// [`METRICS_PATH`] if the path is missing.
+pub fn endpoint(uri: &str) -> Result<Url, String> {
+    let url = match (uri.strip_prefix("otlp://"), uri.strip_prefix("otlps://")) {
+        (Some(rest), _) => format!("http://{}", rest),
+        (_, Some(rest)) => format!("https://{}", rest),
+        _ => {
+            return Err(format!(
+                "URI should start with otlp:// or otlps://: [{}]",
+                uri
+            ))
+        }
+    };
+    let mut url = Url::parse(&url)?;
+    if url.path == "/" {
+        url.path = METRICS_PATH.to_string();
+    }
+    Ok(url)
+}
+
+/// Metrics of the report in the OTLP/HTTP JSON encoding (`ExportMetricsServiceRequest`): sums
+/// `log_parser.logs`, `log_parser.bytes` and `log_parser.errors` with a data point per log type
+/// (or group, `group` attribute) and `log_parser.lines` and `log_parser.failed_lines` of the
+/// whole input.
+pub fn metrics(source: &str, report: &ParseReport, start: SystemTime, end: SystemTime) -> Value {
+    let nanos = |time: SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+            .to_string()
+    };
+    let (start, end) = (nanos(start), nanos(end));
+    let point = |group: Option<&str>, value: u64| {
+        let attributes: Vec<Value> = group
+            .map(|group| json!({"key": "group", "value": {"stringValue": group}}))
+            .into_iter()
+            .collect();
+        json!({
+            "attributes": attributes,
+            "startTimeUnixNano": start,
+            "timeUnixNano": end,
+            // 64-bit integers are strings in JSON encoded protobuf
+            "asInt": value.to_string(),
+        })
+    };
+    let sum = |name: &str, unit: &str, description: &str, points: Vec<Value>| {
+        json!({
+            "name": name,
+            "unit": unit,
+            "description": description,
+            "sum": {
+                "dataPoints": points,
+                "aggregationTemporality": DELTA,
+                "isMonotonic": true,
+            },
+        })
+    };
+
+    let mut groups: Vec<_> = report.log_register.iter().collect();
+    groups.sort_by_key(|(group, _)| *group);
+    let per_group = |value: &dyn Fn(&LogRegister) -> u64| {
+        groups
+            .iter()
+            .map(|(group, register)| point(Some(group), value(register)))
+            .collect()
+    };
+    let metrics = vec![
+        sum(
+            "log_parser.logs",
+            "{log}",
+            "Logs per log type or group",
+            per_group(&|register| register.counter),
+        ),
+        sum(
+            "log_parser.bytes",
+            "By",
+            "Bytes of logs per log type or group",
+            per_group(&|register| register.num_of_bytes),
+        ),
+        sum(
+            "log_parser.errors",
+            "{log}",
+            "Error logs per log type or group",
+            per_group(&|register| register.errors),
+        ),
+        sum(
+            "log_parser.lines",
+            "{line}",
+            "Lines read",
+            vec![point(None, report.lines)],
+        ),
+        sum(
+            "log_parser.failed_lines",
+            "{line}",
+            "Lines which couldn't be parsed",
+            vec![point(None, report.failed_lines)],
+        ),
+    ];
+
+    json!({
+        "resourceMetrics": [{
+            "resource": {
+                "attributes": [
+                    {"key": "service.name", "value": {"stringValue": "log-parser"}},
+                    {"key": "log_parser.source", "value": {"stringValue": source}},
+                ],
+            },
+            "scopeMetrics": [{
+                "scope": {"name": "log-parser", "version": env!("CARGO_PKG_VERSION")},
+                "metrics": metrics,
+            }],
+        }],
+    })
+}
+
+/// POST metrics of the report to the collector, the window of the export ends now.
+pub fn export(url: &Url, source: &str, report: &ParseReport) -> io::Result<()> {
+    let end = SystemTime::now();
+    let start = WINDOW_START
+        .lock()
+        .expect("OTLP window poisoned")
+        .replace(end)
+        .unwrap_or(end);
+    let body = metrics(source, report, start, end).to_string();
+    let response = http::post(url, "application/json", body.as_bytes(), &[])?;
+    if !response.is_success() {
+        return Err(io::Error::other(format!(
+            "OTLP collector responded with status {}: {}",
+            response.status,
+            String::from_utf8_lossy(&response.body)
+        )));
+    }
+    Ok(())
+}
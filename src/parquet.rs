@@ -0,0 +1,390 @@
+//! Parquet files of aggregated stats and parsed records, written and read by the `parquet`
+//! crate (Arrow arrays, Snappy compressed), ready to be loaded by Spark, DuckDB, pandas etc.
+
+use crate::parser::{LogRegister, ParseOptions, ParseReport, ParsedRecord};
+use crate::sink::Sink;
+use crate::timestamp;
+use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::basic::Compression;
+use ::parquet::file::properties::WriterProperties;
+use arrow_array::{
+    Array, ArrayRef, Float64Array, Int64Array, RecordBatch, RecordBatchReader, StringArray,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Records are buffered and written as a batch of this many rows.
+const RECORD_BATCH_ROWS: usize = 8192;
+
+/// Rows of a row group, bounds the memory of records held by the writer.
+const MAX_ROW_GROUP_ROWS: usize = 64 * 1024;
+
+/// Values of a single column.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Int64(Vec<i64>),
+    Double(Vec<f64>),
+    Utf8(Vec<String>),
+    /// Text with missing values (stored as nulls).
+    OptionalUtf8(Vec<Option<String>>),
+}
+
+impl Column {
+    fn len(&self) -> usize {
+        match self {
+            Column::Int64(values) => values.len(),
+            Column::Double(values) => values.len(),
+            Column::Utf8(values) => values.len(),
+            Column::OptionalUtf8(values) => values.len(),
+        }
+    }
+
+    fn field(&self, name: &str) -> Field {
+        match self {
+            Column::Int64(_) => Field::new(name, DataType::Int64, false),
+            Column::Double(_) => Field::new(name, DataType::Float64, false),
+            Column::Utf8(_) => Field::new(name, DataType::Utf8, false),
+            Column::OptionalUtf8(_) => Field::new(name, DataType::Utf8, true),
+        }
+    }
+
+    fn array(&self) -> ArrayRef {
+        match self {
+            Column::Int64(values) => Arc::new(Int64Array::from(values.clone())),
+            Column::Double(values) => Arc::new(Float64Array::from(values.clone())),
+            Column::Utf8(values) => Arc::new(StringArray::from_iter_values(values)),
+            Column::OptionalUtf8(values) => Arc::new(values.iter().collect::<StringArray>()),
+        }
+    }
+
+    /// Column of the array of the field, `None` for types not written by this module.
+    fn of_array(field: &Field, array: &dyn Array) -> Option<Self> {
+        let any = array.as_any();
+        let column = match field.data_type() {
+            DataType::Int64 => {
+                Column::Int64(any.downcast_ref::<Int64Array>()?.iter().flatten().collect())
+            }
+            DataType::Float64 => Column::Double(
+                any.downcast_ref::<Float64Array>()?
+                    .iter()
+                    .flatten()
+                    .collect(),
+            ),
+            DataType::Utf8 if field.is_nullable() => Column::OptionalUtf8(
+                any.downcast_ref::<StringArray>()?
+                    .iter()
+                    .map(|value| value.map(String::from))
+                    .collect(),
+            ),
+            DataType::Utf8 => Column::Utf8(
+                any.downcast_ref::<StringArray>()?
+                    .iter()
+                    .flatten()
+                    .map(String::from)
+                    .collect(),
+            ),
+            _ => return None,
+        };
+        Some(column)
+    }
+
+    fn append(&mut self, other: Column) -> io::Result<()> {
+        match (self, other) {
+            (Column::Int64(values), Column::Int64(other)) => values.extend(other),
+            (Column::Double(values), Column::Double(other)) => values.extend(other),
+            (Column::Utf8(values), Column::Utf8(other)) => values.extend(other),
+            (Column::OptionalUtf8(values), Column::OptionalUtf8(other)) => values.extend(other),
+            _ => return Err(invalid_data("Column type differs between batches")),
+        }
+        Ok(())
+    }
+}
+
+/// Write columns as a Parquet file, all columns have to be of the same length.
+pub fn write<P: AsRef<Path>>(path: P, columns: &[(String, Column)]) -> io::Result<()> {
+    let num_rows = columns.first().map_or(0, |(_, column)| column.len());
+    if columns.iter().any(|(_, column)| column.len() != num_rows) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "All columns should have the same length",
+        ));
+    }
+
+    let schema = schema(columns);
+    let mut writer = writer(File::create(path)?, &schema)?;
+    writer
+        .write(&batch(&schema, columns)?)
+        .map_err(io::Error::other)?;
+    writer.close().map_err(io::Error::other)?;
+    Ok(())
+}
+
+/// Read all columns of a Parquet file of integer, double and text columns (e.g. written by
+/// [`write`]).
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<(String, Column)>> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)
+        .map_err(|err| invalid_data(&err.to_string()))?
+        .build()
+        .map_err(|err| invalid_data(&err.to_string()))?;
+    let schema = reader.schema();
+
+    let mut columns: Vec<(String, Column)> = vec![];
+    for batch in reader {
+        let batch = batch.map_err(|err| invalid_data(&err.to_string()))?;
+        for (idx, field) in schema.fields().iter().enumerate() {
+            let column = Column::of_array(field, batch.column(idx).as_ref()).ok_or_else(|| {
+                invalid_data(&format!(
+                    "Unsupported type {} of column {}",
+                    field.data_type(),
+                    field.name()
+                ))
+            })?;
+            // every batch continues columns of the previous one
+            match columns.get_mut(idx) {
+                Some((_, existing)) => existing.append(column)?,
+                None => columns.push((field.name().to_string(), column)),
+            }
+        }
+    }
+    if columns.is_empty() {
+        for field in schema.fields() {
+            let empty = arrow_array::new_empty_array(field.data_type());
+            if let Some(column) = Column::of_array(field, empty.as_ref()) {
+                columns.push((field.name().to_string(), column));
+            }
+        }
+    }
+
+    Ok(columns)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn schema(columns: &[(String, Column)]) -> SchemaRef {
+    let fields: Vec<Field> = columns
+        .iter()
+        .map(|(name, column)| column.field(name))
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+fn batch(schema: &SchemaRef, columns: &[(String, Column)]) -> io::Result<RecordBatch> {
+    let arrays = columns.iter().map(|(_, column)| column.array()).collect();
+    RecordBatch::try_new(schema.clone(), arrays).map_err(io::Error::other)
+}
+
+fn writer(file: File, schema: &SchemaRef) -> io::Result<ArrowWriter<File>> {
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_max_row_group_size(MAX_ROW_GROUP_ROWS)
+        .build();
+    ArrowWriter::try_new(file, schema.clone(), Some(properties)).map_err(io::Error::other)
+}
+
+/// Write aggregated per log type stats as a Parquet file.
+pub fn write_report<P: AsRef<Path>>(
+    path: P,
+    report: &ParseReport,
+    options: &ParseOptions,
+) -> io::Result<()> {
+    let mut log_types: Vec<_> = report.log_register.iter().collect();
+    log_types.sort_by_key(|(log_type, _)| *log_type);
+
+    let int_column = |name: &str, value: &dyn Fn(&LogRegister) -> i64| {
+        (
+            name.to_string(),
+            Column::Int64(log_types.iter().map(|(_, elem)| value(elem)).collect()),
+        )
+    };
+    let mut columns = vec![
+        (
+            "log_type".to_string(),
+            Column::Utf8(
+                log_types
+                    .iter()
+                    .map(|(log_type, _)| log_type.to_string())
+                    .collect(),
+            ),
+        ),
+        int_column("counter", &|elem| elem.counter as i64),
+        int_column("num_of_bytes", &|elem| elem.num_of_bytes as i64),
+        int_column("errors", &|elem| elem.errors as i64),
+        int_column("line_size_min", &|elem| elem.line_size.min() as i64),
+        (
+            "line_size_mean".to_string(),
+            Column::Double(
+                log_types
+                    .iter()
+                    .map(|(_, elem)| elem.line_size.mean())
+                    .collect(),
+            ),
+        ),
+        int_column("line_size_max", &|elem| elem.line_size.max() as i64),
+        int_column("line_size_p50", &|elem| elem.line_size.p50() as i64),
+        int_column("line_size_p95", &|elem| elem.line_size.p95() as i64),
+        int_column("line_size_p99", &|elem| elem.line_size.p99() as i64),
+    ];
+    // fields are kept in the same order as numeric fields in options
+    for (idx, field_name) in options.numeric_fields.iter().enumerate() {
+        let field = |elem: &LogRegister| elem.fields.get(idx).copied().unwrap_or_default();
+        columns.push((
+            format!("{}_sum", field_name),
+            Column::Double(log_types.iter().map(|(_, elem)| field(elem).sum).collect()),
+        ));
+        columns.push(int_column(&format!("{}_count", field_name), &|elem| {
+            field(elem).count as i64
+        }));
+    }
+    for (idx, field_name) in options.distinct_fields.iter().enumerate() {
+        columns.push(int_column(&format!("{}_distinct", field_name), &|elem| {
+            elem.distinct
+                .get(idx)
+                .map_or(0, |distinct| distinct.count()) as i64
+        }));
+    }
+
+    write(path, &columns)
+}
+
+/// File records of the stats file are written to, `results.parquet` keeps them in
+/// `results.records.parquet`.
+pub fn records_path(path: &str) -> String {
+    format!(
+        "{}.records.parquet",
+        path.strip_suffix(".parquet").unwrap_or(path)
+    )
+}
+
+/// Columns of buffered records, the same as the records table of SQLite sinks.
+#[derive(Default)]
+struct RecordColumns {
+    offsets: Vec<i64>,
+    log_types: Vec<String>,
+    levels: Vec<Option<String>>,
+    num_of_bytes: Vec<i64>,
+    timestamps: Vec<Option<String>>,
+    fields: Vec<String>,
+}
+
+impl RecordColumns {
+    fn push(&mut self, record: &ParsedRecord, options: &ParseOptions) {
+        self.offsets.push(record.offset as i64);
+        self.log_types.push(record.log_type.clone());
+        self.levels.push(record.level.clone());
+        self.num_of_bytes.push(record.num_of_bytes as i64);
+        self.timestamps
+            .push(record.timestamp.map(timestamp::format));
+        self.fields
+            .push(serde_json::Value::Object(record.fields(options)).to_string());
+    }
+
+    fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Buffered records as columns, the buffer is emptied.
+    fn take(&mut self) -> Vec<(String, Column)> {
+        let records = mem::take(self);
+        vec![
+            ("offset".to_string(), Column::Int64(records.offsets)),
+            ("log_type".to_string(), Column::Utf8(records.log_types)),
+            ("level".to_string(), Column::OptionalUtf8(records.levels)),
+            (
+                "num_of_bytes".to_string(),
+                Column::Int64(records.num_of_bytes),
+            ),
+            (
+                "timestamp".to_string(),
+                Column::OptionalUtf8(records.timestamps),
+            ),
+            ("fields".to_string(), Column::Utf8(records.fields)),
+        ]
+    }
+}
+
+/// Parquet file records are streamed to in batches.
+struct RecordsFile {
+    writer: ArrowWriter<File>,
+    schema: SchemaRef,
+    buffered: RecordColumns,
+}
+
+impl RecordsFile {
+    fn create(path: &str) -> io::Result<Self> {
+        let schema = schema(&RecordColumns::default().take());
+        Ok(Self {
+            writer: writer(File::create(path)?, &schema)?,
+            schema,
+            buffered: RecordColumns::default(),
+        })
+    }
+
+    fn write(&mut self, record: &ParsedRecord, options: &ParseOptions) -> io::Result<()> {
+        self.buffered.push(record, options);
+        if self.buffered.len() >= RECORD_BATCH_ROWS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let batch = batch(&self.schema, &self.buffered.take())?;
+        self.writer.write(&batch).map_err(io::Error::other)
+    }
+
+    fn close(mut self) -> io::Result<()> {
+        if self.buffered.len() > 0 {
+            self.flush()?;
+        }
+        self.writer.close().map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+/// Results sink writing aggregated stats into a Parquet file and records, if any, into a
+/// Parquet file of their own next to it (see [`records_path`]).
+pub struct ParquetSink {
+    path: String,
+    records: Option<RecordsFile>,
+}
+
+impl ParquetSink {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            records: None,
+        }
+    }
+}
+
+impl Sink for ParquetSink {
+    fn write_report(
+        &mut self,
+        _input_file: &str,
+        report: &ParseReport,
+        options: &ParseOptions,
+    ) -> io::Result<()> {
+        write_report(&self.path, report, options)
+    }
+
+    fn write_record(&mut self, record: &ParsedRecord, options: &ParseOptions) -> io::Result<()> {
+        let records = match &mut self.records {
+            Some(records) => records,
+            None => self
+                .records
+                .insert(RecordsFile::create(&records_path(&self.path))?),
+        };
+        records.write(record, options)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.records.map_or(Ok(()), RecordsFile::close)
+    }
+}
@@ -1,268 +1,2486 @@
-use dashmap::DashMap;
-use log::{error, info};
-use rand::distributions::Alphanumeric;
-use rand::Rng;
+use crate::checkpoint::Checkpoint;
+use crate::chunk::{self, FailedRange, PartRead, RetryReader, UnreadRange};
+use crate::clock::{Clock, SystemClock};
+use crate::csv::CsvFormat;
+use crate::decompress::Input;
+use crate::dedup::{self, Deduplicator, SeenIds};
+use crate::diagnostics;
+use crate::distinct::{self, DistinctCounter};
+use crate::error_log::{self, ErrorClass};
+use crate::expr::{self, Derived};
+use crate::extract::{self, JsonBackend};
+use crate::group::{self, GroupKey, GroupKeys, Registers};
+use crate::index::ReadRanges;
+use crate::interrupt::{self, CancelToken};
+use crate::map_backend::MapBackend;
+use crate::memory::{self, Module};
+use crate::normalize::{self, Normalization};
+use crate::pattern::LinePattern;
+use crate::platform;
+use crate::profiling::{Profiler, ReducerProfile, Stage};
+use crate::progress::Progress;
+use crate::projection::Condition;
+use crate::quarantine::{ChunkSummary, Quarantine, QuarantineChunk};
+use crate::rate::{self, AnomalyDetector, WindowCount};
+use crate::scanner::{self, Delimiter, Multiline, RecordScanner, ScannedLine};
+use crate::stats::Stats;
+use crate::template::{self, Template};
+use crate::timestamp::{self, TimeLocale};
+use log::{debug, error, info, warn};
+use rand::distributions::{Alphanumeric, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::de::{DeserializeOwned, IgnoredAny};
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Error};
-use std::collections::HashMap;
+use serde_json::{json, Error, Map, Value};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::mem;
 use std::ops::AddAssign;
-use std::sync::mpsc::{Receiver, Sender};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, SyncSender};
 use std::sync::{mpsc, Arc};
 use std::thread;
-use uuid::Uuid;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Severity levels treated as errors.
+const ERROR_LEVELS: [&str; 7] = [
+    "error", "err", "fatal", "critical", "crit", "alert", "emerg",
+];
+
+/// Parser configuration shared by all strategies, can be loaded from config files too
+/// (see [`crate::config`]).
+#[derive(Clone, Default, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParseOptions {
+    /// Numeric fields aggregated per log type (see [`LogRegister::fields`]).
+    pub numeric_fields: Vec<String>,
+    /// Fields extracted as text (see [`ParsedRecord::string_values`]).
+    pub string_fields: Vec<String>,
+    /// Fields extracted as JSON values (see [`ParsedRecord::values`]).
+    pub value_fields: Vec<String>,
+    /// Fields with distinct values counted per log type (see [`LogRegister::distinct`]).
+    pub distinct_fields: Vec<String>,
+    /// Field with log timestamp (RFC 3339, epoch number or date with month name).
+    pub timestamp_field: Option<String>,
+    /// Languages of month names in timestamps besides English, e.g. German syslog dates.
+    pub timestamp_locales: Vec<TimeLocale>,
+    /// Length of time windows in seconds, lines and errors are counted per window of the log
+    /// timestamp if set (see [`LogRegister::windows`]).
+    pub rate_window: Option<u64>,
+    /// Windows of rate series deviating from their trailing average are flagged if set.
+    pub anomaly: Option<AnomalyDetector>,
+    /// Logs matching any of the conditions are errors instead of logs with error severity level.
+    pub error_when: Vec<Condition>,
+    /// Only logs matching all of the conditions are aggregated (and streamed), the rest are
+    /// counted as lines only. Lines which can't be parsed are failed lines either way.
+    pub filter: Vec<Condition>,
+    /// Field with record ID, [`stream`] drops records with an ID seen within the dedup window
+    /// (redeliveries and replays).
+    pub dedup_field: Option<String>,
+    /// Number of the last record IDs remembered, [`dedup::DEFAULT_WINDOW`] if not set.
+    pub dedup_window: Option<usize>,
+    /// Leave logs whose ID was seen before out of registers, they are only counted as
+    /// [`LogRegister::duplicates`].
+    pub drop_duplicates: bool,
+    /// Number of update batches buffered between threads of [`multi_thread_parser_channel`] and
+    /// its consumer, [`CHANNEL_CAPACITY`] if not set. Threads wait while the buffer is full.
+    pub channel_capacity: Option<usize>,
+    /// Number of register updates sent at once by threads of [`multi_thread_parser_channel`],
+    /// [`CHANNEL_BATCH_SIZE`] if not set.
+    pub channel_batch_size: Option<usize>,
+    /// Number of consumers of [`multi_thread_parser_channel`], each merging the keys of its hash
+    /// partition on its own thread, one if not set.
+    pub channel_reducers: Option<usize>,
+    /// Collect names of all top level fields of every log.
+    pub collect_field_names: bool,
+    /// Report histogram of line sizes per log type (see [`Stats::histogram`]).
+    pub size_histogram: bool,
+    /// Fields computed by expressions and added to every record before anything is extracted
+    /// (see [`expr::apply`]).
+    pub derive: Vec<Derived>,
+    /// Fields whose values form register keys instead of the log type, e.g. `type` and `level`
+    /// give `http / error` keys (see [`crate::group`]).
+    pub group_by: Vec<String>,
+    /// Rules rewriting values of group-by fields before they form group keys, e.g. lowercasing
+    /// or replacing IDs in URL paths (see [`Normalization`]).
+    pub normalize: Vec<Normalization>,
+    /// Assemble records spanning multiple lines (pretty-printed JSON, stack traces), every line
+    /// is a record if not set. Line counts and quarantine line numbers count records then.
+    pub multiline: Option<Multiline>,
+    /// Bytes separating records (e.g. NUL), new line if not set.
+    pub record_delimiter: Option<Delimiter>,
+    /// Longer lines (and records) are failed lines of the oversize class, only this many bytes
+    /// of them are kept in memory. [`scanner::MAX_LINE_LEN`] if not set.
+    pub max_line_bytes: Option<usize>,
+    /// Regex with named groups parsing unstructured lines instead of JSON, fields are named
+    /// after the groups and lines which don't match are errors.
+    pub pattern: Option<LinePattern>,
+    /// CSV (or TSV) records instead of JSON lines, can't be combined with the pattern.
+    pub csv: Option<CsvFormat>,
+    /// How JSON lines are parsed, scanning is faster but doesn't validate skipped values.
+    pub json_backend: JsonBackend,
+    /// Map the registers of parsing threads are merged into by [`parse_file`], the map of the
+    /// strategy if not set.
+    pub map_backend: Option<MapBackend>,
+    /// Quarantine file for lines which couldn't be parsed.
+    pub bad_lines: Option<Quarantine>,
+    /// Write the kept part of oversize lines into the quarantine file too, they are left out by
+    /// default as they can't be fixed and parsed from it.
+    pub quarantine_oversize: bool,
+    /// Retries of a read failing with an I/O error before the part of the file fails (and is
+    /// parsed again from its start), [`chunk::IO_RETRIES`] if not set.
+    pub io_retries: Option<u32>,
+    /// Delay before the first retry of a failed read in milliseconds, doubling with every
+    /// further retry, [`chunk::IO_RETRY_DELAY_MS`] if not set.
+    pub io_retry_delay_ms: Option<u64>,
+    /// Skip bytes which still can't be read after the retries instead of failing the part of
+    /// the file, the part goes on with the first record after them. Skipped bytes are in
+    /// [`ParseReport::failed_ranges`].
+    pub skip_unreadable: bool,
+    /// Progress updated by parsing threads.
+    #[serde(skip)]
+    pub progress: Option<Arc<Progress>>,
+    /// Checkpoint saved by parsing threads and resumed by the next run, can't be combined with
+    /// [`ParseOptions::bad_lines`].
+    #[serde(skip)]
+    pub checkpoint: Option<Checkpoint>,
+    /// Profiler timing read, parse and aggregate stages of every thread.
+    #[serde(skip)]
+    pub profiler: Option<Arc<Profiler>>,
+    /// IDs of [`ParseOptions::dedup_field`] seen in the whole input by all threads, logs with an
+    /// ID seen before are counted as [`LogRegister::duplicates`].
+    #[serde(skip)]
+    pub seen_ids: Option<Arc<SeenIds>>,
+    /// Ranges of the inputs which are read, the rest can't have logs matching the filter (see
+    /// [`ReadRanges`]). Inputs are read whole if not set.
+    #[serde(skip)]
+    pub read_ranges: Option<Arc<ReadRanges>>,
+    /// Token stopping the run like an interrupt: parsers return what they parsed so far with the
+    /// rest in [`ParseReport::unread_ranges`].
+    #[serde(skip)]
+    pub cancel: Option<CancelToken>,
+    /// Values of group keys of parsing threads, shared by clones of the options.
+    #[serde(skip)]
+    pub group_keys: Arc<GroupKeys>,
+    /// Clock of the current time and of waiting, the system clock if not set.
+    #[serde(skip)]
+    pub clock: Option<Arc<dyn Clock>>,
+}
+
+impl ParseOptions {
+    /// Clock of the options, [`SystemClock`] unless set.
+    pub fn clock(&self) -> &dyn Clock {
+        match &self.clock {
+            Some(clock) => clock.as_ref(),
+            None => &SystemClock,
+        }
+    }
+
+    /// Reader of the input retrying failed reads (see [`ParseOptions::io_retries`]).
+    pub(crate) fn retry_reader<R>(&self, inner: R) -> RetryReader<'_, R> {
+        RetryReader::new(
+            inner,
+            self.io_retries.unwrap_or(chunk::IO_RETRIES),
+            Duration::from_millis(self.io_retry_delay_ms.unwrap_or(chunk::IO_RETRY_DELAY_MS)),
+            self.clock(),
+        )
+    }
+
+    /// Scanner of records of the file from its current position.
+    fn file_scanner(&self, file: File) -> FileScanner<'_> {
+        self.scanner(BufReader::new(self.retry_reader(file)))
+    }
+
+    /// Scanner of records of the reader. Quoted fields of CSV records can contain new lines
+    /// unless records are assembled otherwise.
+    pub(crate) fn scanner<R: BufRead>(&self, reader: R) -> RecordScanner<R> {
+        let multiline = self
+            .multiline
+            .clone()
+            .or_else(|| self.csv.clone().map(Multiline::Csv));
+        RecordScanner::new(reader, multiline)
+            .delimiter(self.record_delimiter.clone().unwrap_or_default())
+            .max_record_len(self.max_line_bytes.unwrap_or(scanner::MAX_LINE_LEN))
+    }
+
+    /// Scanner of records of the file from the offset, which has to be the start of a record.
+    pub(crate) fn file_scanner_at(
+        &self,
+        input_file: &Path,
+        offset: u64,
+    ) -> io::Result<FileScanner<'_>> {
+        let mut file = platform::open_shared(input_file)?;
+        file.seek(SeekFrom::Start(offset))?;
+        Ok(self.file_scanner(file))
+    }
+
+    /// Where reading of the file goes on from the record at the offset (see
+    /// [`ParseOptions::read_ranges`]), `None` if the rest of the file is skipped.
+    pub(crate) fn next_read(&self, input_file: &Path, offset: u64) -> Option<u64> {
+        match &self.read_ranges {
+            Some(read_ranges) => read_ranges.next(input_file, offset),
+            None => Some(offset),
+        }
+    }
+
+    /// Whether the run was interrupted or cancelled.
+    fn is_stopped(&self) -> bool {
+        interrupt::is_interrupted() || self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Whether the record at the offset of the file is the CSV header, which isn't a line.
+    fn is_header(&self, offset: u64) -> bool {
+        offset == 0 && self.csv.as_ref().is_some_and(|csv| csv.has_header)
+    }
+
+    /// Whether the failed line goes into the quarantine file (see
+    /// [`ParseOptions::quarantine_oversize`]).
+    fn quarantines(&self, err: &Error) -> bool {
+        self.quarantine_oversize
+            || ErrorClass::of(&error_log::kind(err)) != ErrorClass::OversizeLine
+    }
+
+    /// Whether logs have to be parsed into generic JSON value to extract more than type/level.
+    fn needs_value(&self) -> bool {
+        self.needs_more_than_group()
+            || self
+                .group_by
+                .iter()
+                .any(|field| field != "type" && field != "level")
+    }
+
+    /// Whether fields other than type, level and group-by fields are extracted.
+    fn needs_more_than_group(&self) -> bool {
+        !self.numeric_fields.is_empty()
+            || !self.string_fields.is_empty()
+            || !self.value_fields.is_empty()
+            || !self.distinct_fields.is_empty()
+            || self.timestamp_field.is_some()
+            || !self.error_when.is_empty()
+            || !self.filter.is_empty()
+            || self.dedup_field.is_some()
+            || self.collect_field_names
+            || !self.derive.is_empty()
+    }
+}
+
+/// Whether severity level is treated as error.
+fn is_error_level(level: Option<&str>) -> bool {
+    match level {
+        Some(level) => ERROR_LEVELS
+            .iter()
+            .any(|error_level| level.eq_ignore_ascii_case(error_level)),
+        None => false,
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 struct Log {
     #[serde(rename = "type")]
     log_type: String,
+    #[serde(default)]
+    level: Option<String>,
+    /// Values of numeric fields in the same order as in [`ParseOptions::numeric_fields`].
+    #[serde(skip)]
+    numeric_values: Vec<Option<f64>>,
+    /// Values of string fields in the same order as in [`ParseOptions::string_fields`].
+    #[serde(skip)]
+    string_values: Vec<Option<String>>,
+    /// Values of value fields in the same order as in [`ParseOptions::value_fields`].
+    #[serde(skip)]
+    values: Vec<Option<Value>>,
+    /// Hashes of distinct fields in the same order as in [`ParseOptions::distinct_fields`].
+    #[serde(skip)]
+    distinct_hashes: Vec<Option<u64>>,
+    #[serde(skip)]
+    timestamp: Option<SystemTime>,
+    #[serde(skip)]
+    id: Option<u64>,
+    /// Set only if [`ParseOptions::error_when`] is configured.
+    #[serde(skip)]
+    error: Option<bool>,
+    /// The ID was seen before, set only if [`ParseOptions::seen_ids`] is configured.
+    #[serde(skip)]
+    duplicate: bool,
+    /// Duplicate left out of registers (see [`ParseOptions::drop_duplicates`]).
+    #[serde(skip)]
+    dropped: bool,
+    /// Doesn't match [`ParseOptions::filter`], so it's left out of registers and streams.
+    #[serde(skip)]
+    unmatched: bool,
+    /// Start of the rate window, set only if [`ParseOptions::rate_window`] is configured.
+    #[serde(skip)]
+    window: Option<u64>,
+    #[serde(skip)]
+    field_names: Vec<String>,
+    /// Set only if [`ParseOptions::group_by`] is configured.
+    #[serde(skip)]
+    group: Option<GroupKey>,
     // the rest can be omitted
 }
 
-#[derive(Hash, Eq, PartialEq, Debug)]
+impl Log {
+    /// Parse JSON line (or line matched by the pattern, or CSV record), numeric fields are
+    /// extracted only if requested.
+    fn parse(line: &str, options: &ParseOptions) -> Result<Log, Error> {
+        if options.csv.is_some() || options.pattern.is_some() {
+            return Log::from_value(parse_record(line, options)?, options);
+        }
+        // serde reads other values (e.g. arrays) as logs too, they are left to it
+        if options.json_backend == JsonBackend::Scan
+            && !options.needs_more_than_group()
+            && line.trim_start().starts_with('{')
+        {
+            // lines which can't be scanned fail in serde too, so errors (and their kinds) are
+            // the same for both backends
+            if let Ok(log) = Log::scan(line, options) {
+                return Ok(log);
+            }
+        }
+        if !options.needs_value() {
+            let mut log: Log = from_json(line, options)?;
+            log.group = log.group_key(options, &Value::Null);
+            return Ok(log);
+        }
+
+        Log::from_value(from_json(line, options)?, options)
+    }
+
+    /// Extract type, level and group-by fields by scanning the line (see [`JsonBackend::Scan`]).
+    fn scan(line: &str, options: &ParseOptions) -> Result<Log, Error> {
+        let mut names = vec!["type", "level"];
+        names.extend(
+            options
+                .group_by
+                .iter()
+                .map(String::as_str)
+                .filter(|field| *field != "type" && *field != "level"),
+        );
+        let custom = serde::de::Error::custom;
+        // like serde_json: a generic value keeps the last duplicate, the log rejects them
+        let values =
+            extract::top_level_fields(line, &names, options.needs_value()).map_err(custom)?;
+        let string = |idx: usize| match values[idx] {
+            Some(raw) => extract::string_value(raw).map_err(custom),
+            None => Ok(None),
+        };
+
+        let log_type = match (values[0], string(0)?) {
+            (_, Some(log_type)) => log_type,
+            (Some(raw), None) => return Err(custom(format!("invalid type of `type`: {}", raw))),
+            (None, None) => return Err(custom("missing field `type`".to_string())),
+        };
+        let level = match (values[1], string(1)?) {
+            (Some("null"), _) | (None, _) => None,
+            (_, Some(level)) => Some(level),
+            (Some(raw), None) => return Err(custom(format!("invalid type of `level`: {}", raw))),
+        };
+        let mut log = Log {
+            log_type,
+            level,
+            numeric_values: vec![],
+            string_values: vec![],
+            values: vec![],
+            distinct_hashes: vec![],
+            timestamp: None,
+            id: None,
+            error: None,
+            duplicate: false,
+            dropped: false,
+            unmatched: false,
+            window: None,
+            field_names: vec![],
+            group: None,
+        };
+        if !options.group_by.is_empty() {
+            // group values are formatted like values of parsed fields
+            let mut group = Map::new();
+            for (idx, name) in names.iter().enumerate().skip(2) {
+                let value = match (values[idx], string(idx)?) {
+                    (_, Some(text)) => Value::String(text),
+                    (Some(raw), None) => serde_json::from_str(raw)?,
+                    (None, None) => Value::Null,
+                };
+                group.insert(name.to_string(), value);
+            }
+            log.group = log.group_key(options, &Value::Object(group));
+        }
+
+        Ok(log)
+    }
+
+    fn from_value(mut value: Value, options: &ParseOptions) -> Result<Log, Error> {
+        expr::apply(&options.derive, &mut value);
+        let mut log = Log::deserialize(&value)?;
+        log.numeric_values = options
+            .numeric_fields
+            .iter()
+            .map(|field| match value.get(field) {
+                Some(Value::Number(number)) => number.as_f64(),
+                Some(Value::String(text)) => text.trim().parse().ok(),
+                _ => None,
+            })
+            // giant numbers written as text would turn sums into infinity
+            .map(|value| value.filter(|value| value.is_finite()))
+            .collect();
+        log.string_values = options
+            .string_fields
+            .iter()
+            .map(|field| match value.get(field) {
+                Some(Value::String(text)) => Some(text.clone()),
+                Some(Value::Null) | None => None,
+                Some(other) => Some(other.to_string()),
+            })
+            .collect();
+        log.values = options
+            .value_fields
+            .iter()
+            .map(|field| value.get(field).filter(|value| !value.is_null()).cloned())
+            .collect();
+        log.distinct_hashes = options
+            .distinct_fields
+            .iter()
+            .map(|field| hash_field(value.get(field)))
+            .collect();
+        if let Some(timestamp_field) = &options.timestamp_field {
+            log.timestamp = value.get(timestamp_field).and_then(|value| {
+                timestamp::parse_with(value, &options.timestamp_locales, options.clock())
+            });
+        }
+        if let (Some(timestamp), Some(window)) = (log.timestamp, options.rate_window) {
+            log.window = Some(rate::window_start(timestamp, window));
+        }
+        if !options.error_when.is_empty() {
+            let error = options
+                .error_when
+                .iter()
+                .any(|condition| condition.matches(value.get(&condition.field)));
+            log.error = Some(error);
+        }
+        log.unmatched = !options
+            .filter
+            .iter()
+            .all(|condition| condition.matches(value.get(&condition.field)));
+        if let Some(dedup_field) = &options.dedup_field {
+            log.id = hash_field(value.get(dedup_field));
+        }
+        if let (Some(seen_ids), Some(id)) = (&options.seen_ids, log.id) {
+            log.duplicate = !seen_ids.insert(id);
+            log.dropped = log.duplicate && options.drop_duplicates;
+        }
+        if options.collect_field_names {
+            if let Value::Object(fields) = &value {
+                log.field_names = fields.keys().cloned().collect();
+            }
+        }
+        log.group = log.group_key(options, &value);
+
+        Ok(log)
+    }
+
+    /// Key from normalized values of group-by fields, `type` and `level` are taken from the log.
+    fn group_key(&self, options: &ParseOptions, value: &Value) -> Option<GroupKey> {
+        if options.group_by.is_empty() {
+            return None;
+        }
+        let values: Vec<Cow<str>> = options
+            .group_by
+            .iter()
+            .map(|field| {
+                let value = match (field.as_str(), value.get(field)) {
+                    ("type", _) => Cow::Borrowed(self.log_type.as_str()),
+                    ("level", _) => Cow::Borrowed(self.level.as_deref().unwrap_or(group::MISSING)),
+                    (_, Some(Value::String(text))) => Cow::Borrowed(text.as_str()),
+                    (_, Some(Value::Null)) | (_, None) => Cow::Borrowed(group::MISSING),
+                    (_, Some(other)) => Cow::Owned(other.to_string()),
+                };
+                normalize::apply(&options.normalize, field, value)
+            })
+            .collect();
+
+        Some(options.group_keys.key(&values))
+    }
+
+    /// Register key: the group key or the log type.
+    fn key(&self, options: &ParseOptions) -> GroupKey {
+        match &self.group {
+            Some(group) => group.clone(),
+            None => options.group_keys.key(&[&self.log_type]),
+        }
+    }
+
+    /// Register key of reports (see [`GroupKeys::name`]).
+    fn name(&self, options: &ParseOptions) -> String {
+        match &self.group {
+            Some(group) => options.group_keys.name(group),
+            None => self.log_type.clone(),
+        }
+    }
+
+    /// Parse line returned by the scanner, lines which are too long or not UTF-8 are errors.
+    /// Nesting depth is limited by serde_json (128 levels), so deeply nested JSON is an error too.
+    fn parse_scanned(line: &ScannedLine, options: &ParseOptions) -> Result<Log, Error> {
+        if let Some(error) = line.error {
+            return Err(serde::de::Error::custom(error));
+        }
+        // quoted CSV fields can span the lines of a record
+        if options.multiline.is_none() || options.csv.is_some() {
+            return Log::parse(&line.text, options);
+        }
+        if options.pattern.is_some() {
+            // the pattern matches the first line, the rest is e.g. a stack trace
+            let first = line.text.split('\n').next().unwrap_or_default();
+            return Log::parse(first, options);
+        }
+
+        // lines after the JSON value of a multi-line record (e.g. stack trace) are not parsed
+        let mut values = serde_json::Deserializer::from_str(&line.text).into_iter::<IgnoredAny>();
+        let end = match values.next() {
+            Some(Ok(_)) => values.byte_offset(),
+            _ => line.text.len(),
+        };
+        Log::parse(&line.text[..end], options)
+    }
+
+    fn is_error(&self) -> bool {
+        self.error
+            .unwrap_or_else(|| is_error_level(self.level.as_deref()))
+    }
+}
+
+/// Hash of field value: strings are hashed as they are, other values as JSON text.
+fn hash_field(value: Option<&Value>) -> Option<u64> {
+    match value {
+        Some(Value::String(text)) => Some(distinct::hash_value(text)),
+        Some(Value::Null) | None => None,
+        Some(other) => Some(distinct::hash_value(&other.to_string())),
+    }
+}
+
+/// Sum of numeric field values and number of logs where the field was present.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub struct FieldSum {
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl FieldSum {
+    pub fn avg(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum / self.count as f64
+    }
+}
+
+/// Add the value to the total, a sum which doesn't fit saturates at the maximum and counts as
+/// an overflow (see [`LogRegister::overflows`]).
+fn accumulate(total: &mut u64, value: u64, overflows: &mut u64) {
+    match total.checked_add(value) {
+        Some(sum) => *total = sum,
+        None => {
+            *total = u64::MAX;
+            *overflows += 1;
+        }
+    }
+}
+
+/// Add the value to the sum of a numeric field like [`accumulate`], a sum which doesn't fit
+/// (would be infinite) saturates at the largest finite value.
+fn accumulate_f64(total: &mut f64, value: f64, overflows: &mut u64) {
+    let sum = *total + value;
+    if sum.is_finite() {
+        *total = sum;
+    } else {
+        *total = if sum > 0.0 { f64::MAX } else { f64::MIN };
+        *overflows += 1;
+    }
+}
+
+/// Widen the first/last timestamps to include the range, ends are merged with min/max so any
+/// merge order of registers gives the same range.
+fn widen(
+    first_seen: &mut Option<SystemTime>,
+    last_seen: &mut Option<SystemTime>,
+    first: Option<SystemTime>,
+    last: Option<SystemTime>,
+) {
+    *first_seen = match (*first_seen, first) {
+        (Some(current), Some(first)) => Some(current.min(first)),
+        (current, first) => current.or(first),
+    };
+    *last_seen = match (*last_seen, last) {
+        (Some(current), Some(last)) => Some(current.max(last)),
+        (current, last) => current.or(last),
+    };
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct LogRegister {
-    pub counter: u32,
+    pub counter: u64,
     pub num_of_bytes: u64,
+    /// Number of logs with error severity level.
+    pub errors: u64,
+    /// Line size statistics.
+    pub line_size: Stats,
+    /// Numeric field sums in the same order as in [`ParseOptions::numeric_fields`].
+    pub fields: Vec<FieldSum>,
+    /// Distinct values in the same order as in [`ParseOptions::distinct_fields`].
+    pub distinct: Vec<DistinctCounter>,
+    /// Lines and errors per window start (seconds since epoch), see [`crate::rate`]. Filled only
+    /// if [`ParseOptions::rate_window`] is set, logs without timestamp are not counted.
+    pub windows: BTreeMap<u64, WindowCount>,
+    /// Logs with an ID seen before (see [`ParseOptions::seen_ids`]), they are counted in the rest
+    /// of the register too unless [`ParseOptions::drop_duplicates`] is set.
+    #[serde(default)]
+    pub duplicates: u64,
+    /// Additions to counters and sums of the register which overflowed, their totals are
+    /// saturated at the maximum instead of wrapping.
+    #[serde(default)]
+    pub overflows: u64,
+    /// Earliest timestamp of the logs, set only if [`ParseOptions::timestamp_field`] is.
+    #[serde(default)]
+    pub first_seen: Option<SystemTime>,
+    /// Latest timestamp of the logs, set only if [`ParseOptions::timestamp_field`] is.
+    #[serde(default)]
+    pub last_seen: Option<SystemTime>,
 }
 
 impl LogRegister {
-    fn new(num_of_bytes: u64) -> Self {
+    pub(crate) fn zero() -> Self {
         Self {
-            counter: 1,
-            num_of_bytes,
+            counter: 0,
+            num_of_bytes: 0,
+            errors: 0,
+            line_size: Stats::new(),
+            fields: vec![],
+            distinct: vec![],
+            windows: BTreeMap::new(),
+            duplicates: 0,
+            overflows: 0,
+            first_seen: None,
+            last_seen: None,
         }
     }
 
-    fn zero() -> Self {
-        Self {
-            counter: 0,
-            num_of_bytes: 0,
+    /// Time between the first and the last timestamp of the logs, `None` without timestamps.
+    pub fn active_span(&self) -> Option<Duration> {
+        let (first, last) = (self.first_seen?, self.last_seen?);
+        Some(last.duration_since(first).unwrap_or_default())
+    }
+
+    /// Register single log in place (avoids allocating new register for every line).
+    fn add(&mut self, num_of_bytes: u64, log: &Log) {
+        let overflows = &mut self.overflows;
+        accumulate(&mut self.duplicates, log.duplicate as u64, overflows);
+        if log.dropped {
+            return;
         }
+        accumulate(&mut self.counter, 1, overflows);
+        accumulate(&mut self.num_of_bytes, num_of_bytes, overflows);
+        accumulate(&mut self.errors, log.is_error() as u64, overflows);
+        self.line_size.add(num_of_bytes);
+        widen(
+            &mut self.first_seen,
+            &mut self.last_seen,
+            log.timestamp,
+            log.timestamp,
+        );
+        if let Some(window) = log.window {
+            let count = self.windows.entry(window).or_default();
+            accumulate(&mut count.lines, 1, overflows);
+            accumulate(&mut count.errors, log.is_error() as u64, overflows);
+        }
+
+        if self.fields.len() < log.numeric_values.len() {
+            self.fields
+                .resize(log.numeric_values.len(), FieldSum::default());
+        }
+        for (field, value) in self.fields.iter_mut().zip(&log.numeric_values) {
+            if let Some(value) = value {
+                accumulate_f64(&mut field.sum, *value, overflows);
+                accumulate(&mut field.count, 1, overflows);
+            }
+        }
+
+        if self.distinct.len() < log.distinct_hashes.len() {
+            self.distinct
+                .resize(log.distinct_hashes.len(), DistinctCounter::new());
+        }
+        for (distinct, hash) in self.distinct.iter_mut().zip(&log.distinct_hashes) {
+            if let Some(hash) = hash {
+                distinct.add(*hash);
+            }
+        }
+    }
+
+    /// Fraction of logs with error severity level.
+    pub fn error_ratio(&self) -> f64 {
+        if self.counter == 0 {
+            return 0.0;
+        }
+        self.errors as f64 / self.counter as f64
     }
 }
 
 impl AddAssign for LogRegister {
     fn add_assign(&mut self, rhs: Self) {
-        *self = Self {
-            counter: self.counter + rhs.counter,
-            num_of_bytes: self.num_of_bytes + rhs.num_of_bytes,
+        let overflows = &mut self.overflows;
+        accumulate(overflows, rhs.overflows, &mut 0);
+        accumulate(&mut self.counter, rhs.counter, overflows);
+        accumulate(&mut self.num_of_bytes, rhs.num_of_bytes, overflows);
+        accumulate(&mut self.errors, rhs.errors, overflows);
+        accumulate(&mut self.duplicates, rhs.duplicates, overflows);
+        self.line_size += rhs.line_size;
+        widen(
+            &mut self.first_seen,
+            &mut self.last_seen,
+            rhs.first_seen,
+            rhs.last_seen,
+        );
+
+        if self.fields.len() < rhs.fields.len() {
+            self.fields.resize(rhs.fields.len(), FieldSum::default());
+        }
+        for (field, rhs_field) in self.fields.iter_mut().zip(rhs.fields) {
+            accumulate_f64(&mut field.sum, rhs_field.sum, overflows);
+            accumulate(&mut field.count, rhs_field.count, overflows);
+        }
+
+        if self.distinct.len() < rhs.distinct.len() {
+            self.distinct
+                .resize(rhs.distinct.len(), DistinctCounter::new());
+        }
+        for (distinct, rhs_distinct) in self.distinct.iter_mut().zip(rhs.distinct) {
+            *distinct += rhs_distinct;
+        }
+        for (window, count) in rhs.windows {
+            let total = self.windows.entry(window).or_default();
+            accumulate(&mut total.lines, count.lines, overflows);
+            accumulate(&mut total.errors, count.errors, overflows);
+        }
+    }
+}
+
+impl AddAssign for ParseReport {
+    fn add_assign(&mut self, rhs: Self) {
+        for (key, value) in rhs.log_register {
+            let register = self.log_register.entry(key).or_insert(LogRegister::zero());
+            register.add_assign(value);
+        }
+        self.lines += rhs.lines;
+        self.failed_lines += rhs.failed_lines;
+        self.failed_ranges.extend(rhs.failed_ranges);
+        self.unread_ranges.extend(rhs.unread_ranges);
+        for (kind, count) in rhs.error_kinds {
+            *self.error_kinds.entry(kind).or_default() += count;
+        }
+    }
+}
+
+/// Aggregated result of parsing, the same for every strategy.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ParseReport {
+    /// Registers per log type, or per group if [`ParseOptions::group_by`] is set.
+    pub log_register: HashMap<String, LogRegister>,
+    /// Number of lines read.
+    pub lines: u64,
+    /// Number of lines which couldn't be parsed.
+    pub failed_lines: u64,
+    /// Parts of the file which failed even after retries, their logs are missing in the report.
+    pub failed_ranges: Vec<FailedRange>,
+    /// Parts of the file left unread because the run was interrupted (see [`interrupt`]) or
+    /// cancelled.
+    #[serde(default)]
+    pub unread_ranges: Vec<UnreadRange>,
+    /// Number of failed lines per error kind (see [`error_log::kind`]).
+    #[serde(default)]
+    pub error_kinds: BTreeMap<String, u64>,
+}
+
+impl ParseReport {
+    /// Register a single line which doesn't come from a file (e.g. a message of a broker), its
+    /// size is the length of the line as given.
+    pub fn add_line(&mut self, line: &str, options: &ParseOptions) -> Result<(), Error> {
+        self.add_line_of_size(line, line.len() as u64, options)
+    }
+
+    /// Register a single line received with its delimiter, which counts to its size like in
+    /// files.
+    pub fn add_line_of_size(
+        &mut self,
+        line: &str,
+        num_of_bytes: u64,
+        options: &ParseOptions,
+    ) -> Result<(), Error> {
+        self.lines += 1;
+        let log = Log::parse(line, options).inspect_err(|err| {
+            self.failed_lines += 1;
+            *self.error_kinds.entry(error_log::kind(err)).or_default() += 1;
+        })?;
+        if log.unmatched {
+            return Ok(());
+        }
+        self.log_register
+            .entry(log.name(options))
+            .or_insert(LogRegister::zero())
+            .add(num_of_bytes, &log);
+        Ok(())
+    }
+
+    /// Number of additions to counters and sums of all registers which overflowed (see
+    /// [`LogRegister::overflows`]).
+    pub fn overflows(&self) -> u64 {
+        self.log_register
+            .values()
+            .fold(0, |overflows: u64, register| {
+                overflows.saturating_add(register.overflows)
+            })
+    }
+
+    /// Whether the whole input was read, i.e. the run wasn't interrupted.
+    pub fn is_complete(&self) -> bool {
+        self.unread_ranges.is_empty()
+    }
+
+    /// Build report from results of all parts of the file (writes quarantine file if configured).
+    pub(crate) fn new(
+        log_register: Registers,
+        results: Vec<Result<ChunkSummary, FailedRange>>,
+        options: &ParseOptions,
+    ) -> Self {
+        let mut chunks = vec![];
+        let mut failed_ranges = vec![];
+        for result in results {
+            match result {
+                Ok(chunk) => chunks.push(chunk),
+                Err(failed_range) => {
+                    error!(
+                        "Bytes {}..{} skipped after {} attempts: {}",
+                        failed_range.start_idx,
+                        failed_range.end_idx,
+                        failed_range.attempts,
+                        failed_range.error
+                    );
+                    failed_ranges.push(failed_range);
+                }
+            }
+        }
+
+        if let Some(quarantine) = &options.bad_lines {
+            failed_ranges
+                .iter()
+                .for_each(|failed_range| quarantine.discard(failed_range.start_idx));
+            let bad_lines = quarantine.merge(&chunks);
+            info!(
+                "Bad lines: {} (written to {})",
+                bad_lines,
+                quarantine.path().display()
+            );
+        }
+
+        // bytes skipped in parts which were read, the lines around them are in the report
+        for chunk in &chunks {
+            for skipped in &chunk.skipped_ranges {
+                error!(
+                    "Bytes {}..{} skipped after {} attempts: {}",
+                    skipped.start_idx, skipped.end_idx, skipped.attempts, skipped.error
+                );
+                failed_ranges.push(skipped.clone());
+            }
+        }
+        failed_ranges.sort_by_key(|failed_range| failed_range.start_idx);
+
+        let mut unread_ranges: Vec<UnreadRange> = chunks
+            .iter()
+            .filter_map(|chunk| chunk.unread_range)
+            .collect();
+        unread_ranges.sort_by_key(|unread_range| unread_range.start_idx);
+        let mut error_kinds: BTreeMap<String, u64> = BTreeMap::new();
+        for chunk in &chunks {
+            for (kind, count) in &chunk.error_kinds {
+                *error_kinds.entry(kind.clone()).or_default() += count;
+            }
+        }
+
+        if let Some(progress) = &options.progress {
+            progress.finish();
+        }
+        if let Some(checkpoint) = &options.checkpoint {
+            // failed and unread parts are parsed by the next run
+            if failed_ranges.is_empty() && unread_ranges.is_empty() {
+                checkpoint.remove();
+            }
+        }
+
+        Self {
+            log_register: options.group_keys.named(log_register),
+            lines: chunks.iter().map(|chunk| chunk.lines).sum(),
+            failed_lines: chunks.iter().map(|chunk| chunk.bad_lines).sum(),
+            failed_ranges,
+            unread_ranges,
+            error_kinds,
         }
     }
 }
 
 /// Multi thread parser with concurrent hash map.
-pub fn multi_thread_parser_dashmap(
+pub fn multi_thread_parser_dashmap<P: AsRef<Path>>(
     num_of_thread: u8,
-    input_file: &str,
-) -> Arc<DashMap<String, LogRegister>> {
+    input_file: P,
+    options: &ParseOptions,
+) -> io::Result<ParseReport> {
+    let input_file = input_file.as_ref();
+    multi_thread_parser_merged(num_of_thread, input_file, options, MapBackend::Dashmap)
+}
+
+/// Multi thread parser merging the register of every part of the file into a map of the backend
+/// as soon as the part is parsed.
+pub fn multi_thread_parser_merged<P: AsRef<Path>>(
+    num_of_thread: u8,
+    input_file: P,
+    options: &ParseOptions,
+    backend: MapBackend,
+) -> io::Result<ParseReport> {
+    let input_file = input_file.as_ref();
     let num_of_thread = num_of_thread as u64;
 
-    let file = File::open(input_file).expect("Can't open file");
-    let size = file.metadata().expect("Can't read file metadata").len();
-    info!("File size in bytes: {}", size);
-
-    // calculate number of bytes to be parsed by every thread
-    let bytes_portion = size / num_of_thread;
-
-    // use concurrent HashMap
-    let log_register: DashMap<String, LogRegister> = DashMap::new();
-    let log_register_arc = Arc::new(log_register);
-    // keep started thread in vector
-    let mut threads = vec![];
-    for idx in 0..num_of_thread {
-        let start_idx = idx * bytes_portion;
-
-        let log_reg = log_register_arc.clone();
-
-        // fail fast if can't open the file - stop the program
-        let file = File::open(input_file).expect("Can't open file");
-        let handle = thread::spawn(move || {
-            let log_hasher = |bytes_read: u64, log_type: String| {
-                let mut value = log_reg.entry(log_type).or_insert(LogRegister::zero());
-                value.add_assign(LogRegister::new(bytes_read));
-            };
-            partially_read_file(start_idx, bytes_portion, file, log_hasher)
-        });
+    let file = platform::open_shared(input_file)?;
+    let size = file.metadata()?.len();
+    // the input may be a subset of the file, its real size is logged by callers
+    debug!("File size in bytes: {}", size);
+
+    let log_register = backend.map();
+    let results: Vec<Result<ChunkSummary, FailedRange>> = thread::scope(|scope| {
+        // keep started thread in vector
+        let mut threads = vec![];
+        for (start_idx, bytes_portion) in chunk::byte_ranges(size, num_of_thread) {
+            let end_idx = start_idx + bytes_portion;
+            let log_register = &log_register;
+
+            let handle = scope.spawn(move || {
+                chunk::run_with_retry(start_idx, end_idx, || {
+                    read_chunk_registers(start_idx, bytes_portion, input_file, options)
+                })
+                .map(|(chunk_register, summary)| {
+                    log_register.merge(chunk_register);
+                    summary
+                })
+            });
+
+            threads.push((start_idx, end_idx, handle));
+        }
+
+        threads
+            .into_iter()
+            .map(|(start_idx, end_idx, thread)| chunk::join(thread, start_idx, end_idx))
+            .collect()
+    });
+
+    Ok(ParseReport::new(
+        log_register.into_registers(),
+        results,
+        options,
+    ))
+}
+
+/// Multi thread parser without shared state: every thread parses its part into its own register
+/// and the registers are merged once all threads are done.
+pub fn multi_thread_parser_sharded<P: AsRef<Path>>(
+    num_of_thread: u8,
+    input_file: P,
+    options: &ParseOptions,
+) -> io::Result<ParseReport> {
+    let input_file = input_file.as_ref();
+    let num_of_thread = num_of_thread as u64;
+
+    let file = platform::open_shared(input_file)?;
+    let size = file.metadata()?.len();
+    // the input may be a subset of the file, its real size is logged by callers
+    debug!("File size in bytes: {}", size);
+
+    let mut log_register: Registers = HashMap::new();
+    let mut results: Vec<Result<ChunkSummary, FailedRange>> = vec![];
+    thread::scope(|scope| {
+        // keep started thread in vector
+        let mut threads = vec![];
+        for (start_idx, bytes_portion) in chunk::byte_ranges(size, num_of_thread) {
+            let end_idx = start_idx + bytes_portion;
+
+            let handle = scope.spawn(move || {
+                chunk::run_with_retry(start_idx, end_idx, || {
+                    read_chunk_registers(start_idx, bytes_portion, input_file, options)
+                })
+            });
+
+            threads.push((start_idx, end_idx, handle));
+        }
+
+        for (start_idx, end_idx, thread) in threads {
+            match chunk::join(thread, start_idx, end_idx) {
+                Ok((chunk_register, summary)) => {
+                    // the largest register is kept, so the fewest entries are merged
+                    let registers = mem::take(&mut log_register);
+                    let (mut into, from) = match chunk_register.len() > registers.len() {
+                        true => (chunk_register, registers),
+                        false => (registers, chunk_register),
+                    };
+                    for (log_type, log_value) in from {
+                        let value = into.entry(log_type).or_insert(LogRegister::zero());
+                        value.add_assign(log_value);
+                    }
+                    log_register = into;
+                    results.push(Ok(summary));
+                }
+                Err(failed_range) => results.push(Err(failed_range)),
+            }
+        }
+    });
+
+    Ok(ParseReport::new(log_register, results, options))
+}
+
+/// How a file is parsed by [`parse_file`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// [`single_thread_parser`], the number of threads is ignored.
+    Single,
+    /// [`multi_thread_parser_channel`].
+    Channel,
+    /// [`multi_thread_parser_dashmap`].
+    Dashmap,
+    /// [`multi_thread_parser_sharded`], fastest with many log types or groups.
+    #[default]
+    Sharded,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(strategy: &str) -> Result<Self, Self::Err> {
+        match strategy {
+            "single" => Ok(Strategy::Single),
+            "channel" => Ok(Strategy::Channel),
+            "dashmap" => Ok(Strategy::Dashmap),
+            "sharded" => Ok(Strategy::Sharded),
+            _ => Err(format!(
+                "Unknown strategy [{}], expected single, channel, dashmap or sharded",
+                strategy
+            )),
+        }
+    }
+}
+
+/// Parse the file with the strategy, every strategy gives the same report (or the error of
+/// opening the file). Threads of the dashmap and sharded strategies merge their registers into
+/// [`ParseOptions::map_backend`] if set.
+pub fn parse_file<P: AsRef<Path>>(
+    strategy: Strategy,
+    num_of_thread: u8,
+    input_file: P,
+    options: &ParseOptions,
+) -> io::Result<ParseReport> {
+    let input_file = input_file.as_ref();
+    if let (Strategy::Dashmap | Strategy::Sharded, Some(backend)) = (strategy, options.map_backend)
+    {
+        return multi_thread_parser_merged(num_of_thread, input_file, options, backend);
+    }
+    match strategy {
+        Strategy::Single => single_thread_parser(input_file, options),
+        Strategy::Channel => multi_thread_parser_channel(num_of_thread, input_file, options),
+        Strategy::Dashmap => multi_thread_parser_dashmap(num_of_thread, input_file, options),
+        Strategy::Sharded => multi_thread_parser_sharded(num_of_thread, input_file, options),
+    }
+}
 
-        threads.push(handle);
+/// Parse the input with the strategy if it's a file which can be read in parts, compressed or
+/// transcoded content is streamed into a single thread instead (see [`parse_reader`]).
+pub fn parse_input(
+    strategy: Strategy,
+    num_of_thread: u8,
+    input: &Input,
+    options: &ParseOptions,
+) -> io::Result<ParseReport> {
+    match input.file() {
+        Some(path) => parse_file(strategy, num_of_thread, path, options),
+        None => parse_reader(input.reader()?, input.source(), options),
     }
+}
 
-    for thread in threads {
-        thread
-            .join()
-            .expect("Couldn't join on the associated thread");
+/// Read part of the file into its own register, so a failed attempt can be retried without
+/// leaving partial results in the shared state.
+fn read_chunk_registers(
+    start_idx: u64,
+    num_of_bytes: u64,
+    input_file: &Path,
+    options: &ParseOptions,
+) -> io::Result<(Registers, ChunkSummary)> {
+    if let Some(checkpoint) = &options.checkpoint {
+        return read_chunk_checkpointed(start_idx, num_of_bytes, input_file, options, checkpoint);
     }
+    let mut chunk_register: Registers = HashMap::new();
+    let file = platform::open_shared(input_file)?;
+    let summary = partially_read_file(
+        start_idx,
+        num_of_bytes,
+        file,
+        input_file,
+        options,
+        |_offset, bytes_read, log| {
+            let value = chunk_register
+                .entry(log.key(options))
+                .or_insert(LogRegister::zero());
+            value.add(bytes_read, &log);
+        },
+    )?;
 
-    log_register_arc
+    Ok((chunk_register, summary))
+}
+
+/// Read part of the file into its own register like [`read_chunk_registers`], passing every
+/// parsed record to the observer too (checkpoints are not supported).
+pub(crate) fn read_chunk_records<F>(
+    start_idx: u64,
+    num_of_bytes: u64,
+    input_file: &Path,
+    options: &ParseOptions,
+    mut observer: F,
+) -> io::Result<(Registers, ChunkSummary)>
+where
+    F: FnMut(&ParsedRecord),
+{
+    let mut chunk_register: Registers = HashMap::new();
+    let file = platform::open_shared(input_file)?;
+    let summary = partially_read_file(
+        start_idx,
+        num_of_bytes,
+        file,
+        input_file,
+        options,
+        |offset, bytes_read, log| {
+            let value = chunk_register
+                .entry(log.key(options))
+                .or_insert(LogRegister::zero());
+            value.add(bytes_read, &log);
+            observer(&ParsedRecord::new(offset, bytes_read, log));
+        },
+    )?;
+
+    Ok((chunk_register, summary))
+}
+
+/// Read part of the file like [`read_chunk_registers`], resuming from the last saved progress of
+/// the part and saving it every [`Checkpoint::interval`] (bad lines are not quarantined).
+fn read_chunk_checkpointed(
+    start_idx: u64,
+    num_of_bytes: u64,
+    input_file: &Path,
+    options: &ParseOptions,
+    checkpoint: &Checkpoint,
+) -> io::Result<(Registers, ChunkSummary)> {
+    let mut part = checkpoint.part(start_idx).unwrap_or_default();
+    let mut read = PartRead::default();
+    if !part.done {
+        if part.bytes_read > 0 {
+            info!(
+                "Resuming bytes {}..{} at {}",
+                start_idx,
+                start_idx + num_of_bytes,
+                start_idx + part.bytes_read
+            );
+        }
+        let file = platform::open_shared(input_file)?;
+        let mut saved = Instant::now();
+        let mut save_error = None;
+        read = partially_read_lines(
+            start_idx,
+            num_of_bytes,
+            part.bytes_read,
+            input_file,
+            file,
+            options,
+            |offset, bytes_read, line, log_result| {
+                part.lines += 1;
+                match log_result {
+                    Ok(log) if log.unmatched => {}
+                    Ok(log) => {
+                        // saved registers are keyed by names, ids are of this run only
+                        let value = part
+                            .register
+                            .entry(log.name(options))
+                            .or_insert(LogRegister::zero());
+                        value.add(bytes_read, &log);
+                    }
+                    Err(err) => {
+                        error_log::record(&mut part.error_kinds, line, &err);
+                        part.bad_lines += 1;
+                    }
+                }
+                part.bytes_read = offset + bytes_read - start_idx;
+                if saved.elapsed() >= checkpoint.interval() {
+                    saved = Instant::now();
+                    if let Err(err) = checkpoint.save(start_idx, &part) {
+                        save_error = Some(err);
+                        return false;
+                    }
+                }
+                true
+            },
+        )?;
+        if let Some(err) = save_error {
+            return Err(err);
+        }
+        // interrupted part is resumed by the next run
+        part.done = read.unread_range.is_none();
+        checkpoint.save(start_idx, &part)?;
+    }
+
+    let summary = ChunkSummary {
+        start_idx,
+        lines: part.lines,
+        bad_lines: part.bad_lines,
+        error_kinds: part.error_kinds,
+        unread_range: read.unread_range,
+        skipped_ranges: read.skipped_ranges,
+    };
+    Ok((options.group_keys.unnamed(part.register), summary))
 }
 
 /// Read part of the file from index until number of bytes consumed.
-fn partially_read_file<F>(start_idx: u64, num_of_bytes: u64, file: File, log_handler: F)
-    where
-        F: Fn(u64, String) -> (),
+fn partially_read_file<F>(
+    start_idx: u64,
+    num_of_bytes: u64,
+    file: File,
+    input_file: &Path,
+    options: &ParseOptions,
+    mut log_handler: F,
+) -> io::Result<ChunkSummary>
+where
+    F: FnMut(u64, u64, Log),
+{
+    let mut quarantine = options
+        .bad_lines
+        .as_ref()
+        .map(|quarantine| quarantine.chunk(input_file, start_idx));
+    let mut summary = ChunkSummary {
+        start_idx,
+        ..ChunkSummary::default()
+    };
+
+    let read = partially_read_lines(
+        start_idx,
+        num_of_bytes,
+        0,
+        input_file,
+        file,
+        options,
+        |offset, bytes_read, line, log_result| {
+            summary.lines += 1;
+            match log_result {
+                Ok(log) if log.unmatched => {}
+                Ok(log) => {
+                    log_handler(offset, bytes_read, log);
+                }
+                Err(err) => {
+                    error_log::record(&mut summary.error_kinds, line, &err);
+                    summary.bad_lines += 1;
+                    if let Some(quarantine) =
+                        quarantine.as_mut().filter(|_| options.quarantines(&err))
+                    {
+                        quarantine.write(summary.lines, line, &err);
+                    }
+                }
+            }
+            true
+        },
+    )?;
+    summary.unread_range = read.unread_range;
+    summary.skipped_ranges = read.skipped_ranges;
+
+    Ok(summary)
+}
+
+/// Read part of the file from index until number of bytes consumed, passing every parsed line
+/// (with its byte offset in the file) to the handler. Reading stops early if the handler
+/// returns false. Reading of a resumed part starts at the record `resume` bytes from its start.
+/// Returns the rest of the part if reading was interrupted and the bytes skipped as unreadable.
+fn partially_read_lines<F>(
+    start_idx: u64,
+    num_of_bytes: u64,
+    resume: u64,
+    input_file: &Path,
+    file: File,
+    options: &ParseOptions,
+    mut line_handler: F,
+) -> io::Result<PartRead>
+where
+    F: FnMut(u64, u64, &str, Result<Log, Error>) -> bool,
 {
-    let mut buffered = BufReader::new(file);
+    let mut profiler = options
+        .profiler
+        .as_ref()
+        .map(|profiler| profiler.thread(start_idx));
+    let _memory = memory::scope(Module::Read);
+    let mut lap = |stage| {
+        if let Some(profiler) = profiler.as_mut() {
+            profiler.lap(stage);
+        }
+        memory::stage_ended(stage);
+    };
+    partially_scan_records(
+        start_idx,
+        num_of_bytes,
+        resume,
+        input_file,
+        file,
+        options,
+        |offset, line| {
+            lap(Stage::Read);
+            let log_result = Log::parse_scanned(line, options);
+            lap(Stage::Parse);
+            let more = line_handler(offset, line.num_of_bytes, &line.text, log_result);
+            lap(Stage::Aggregate);
+            more
+        },
+    )
+}
+
+/// Read part of the file like [`partially_read_lines`], passing records to the handler
+/// without parsing them.
+pub(crate) fn partially_scan_records<F>(
+    start_idx: u64,
+    num_of_bytes: u64,
+    resume: u64,
+    input_file: &Path,
+    file: File,
+    options: &ParseOptions,
+    mut record_handler: F,
+) -> io::Result<PartRead>
+where
+    F: FnMut(u64, &ScannedLine) -> bool,
+{
+    let progress = options.progress.as_ref().map(|progress| progress.counter());
+    let trace = diagnostics::worker(start_idx);
+    // delimiter which started before the part ends the record being skipped, so its search
+    // starts earlier (resumed part starts right at a record)
+    let back = if resume > 0 {
+        0
+    } else {
+        search_back(&file, start_idx, options)?
+    };
+    // unreadable bytes are skipped by reading on with another handle of the file
+    let unreadable = match options.skip_unreadable {
+        true => Some(file.try_clone()?),
+        false => None,
+    };
+    let mut file = file;
     // go to position where we need to start consuming
-    buffered
-        .seek(SeekFrom::Start(start_idx))
-        .expect(&format!("Can't seek to position: {}", start_idx));
+    file.seek(SeekFrom::Start(start_idx + resume - back))?;
+    let mut scanner = options.file_scanner(file);
 
-    let mut line = String::new();
-    if start_idx > 0 {
-        // move cursor to the beginning of the next line (only if we are in the middle of the file)
-        buffered.read_line(&mut line).expect("Unexpected I/O error");
+    let mut offset = resume;
+    if start_idx > 0 && resume == 0 {
+        // move cursor to the beginning of the next record (only if we are in the middle of the file)
+        offset = scanner.skip_to_record_start(back)?.saturating_sub(back);
     }
-    let offset = line.len() as u64;
-    line.clear();
 
+    let mut read = PartRead::default();
     // we need to take into account how many bytes we moved to find the next line
     let mut total_bytes_read = offset;
-    // panic if we face I/O error - we can't recover
-    while buffered.read_line(&mut line).expect("Unexpected I/O error") > 0 {
-        let bytes_read = line.len() as u64;
-        let log_result: Result<Log, Error> = serde_json::from_str(line.as_str());
-        match log_result {
-            Ok(log) => {
-                log_handler(bytes_read, log.log_type);
+    // records starting in this part belong to it, the skipped part can already be longer than
+    // the whole part - the next record is then read by the next thread (unless the next thread
+    // can't start at it, see `RecordScanner::next_starts_part`)
+    while total_bytes_read <= num_of_bytes || !scanner.next_starts_part()? {
+        if options.is_stopped() {
+            read.unread_range = Some(UnreadRange {
+                start_idx: start_idx + total_bytes_read,
+                end_idx: start_idx + num_of_bytes,
+            });
+            return Ok(read);
+        }
+        let position = start_idx + total_bytes_read;
+        let next = options.next_read(input_file, position).unwrap_or(u64::MAX);
+        if next > position {
+            // records before the next range can't match the filter, they're skipped unread
+            let end_idx = start_idx + num_of_bytes;
+            if let Some(progress) = &progress {
+                progress.add(next.min(end_idx).saturating_sub(position));
+            }
+            if next > end_idx {
+                break;
             }
+            scanner = options.file_scanner_at(input_file, next)?;
+            total_bytes_read = next - start_idx;
+            continue;
+        }
+        let line = match scanner.next_record() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
             Err(err) => {
-                error!("Problem to parse line: [{}]. Error: [{}]", line, err);
+                let file = match &unreadable {
+                    Some(file) => file,
+                    None => return Err(err),
+                };
+                let skipped_idx = start_idx + total_bytes_read;
+                let (skipped, resumed) =
+                    skip_unreadable(file, skipped_idx, start_idx + num_of_bytes, err, options)?;
+                if let Some(progress) = &progress {
+                    progress.add(skipped.end_idx - skipped.start_idx);
+                }
+                total_bytes_read = skipped.end_idx - start_idx;
+                read.skipped_ranges.push(skipped);
+                match resumed {
+                    Some(resumed) => {
+                        scanner = resumed;
+                        continue;
+                    }
+                    None => break,
+                }
             }
+        };
+        let bytes_read = line.num_of_bytes;
+        if let Some(progress) = &progress {
+            progress.add(bytes_read);
+        }
+        let offset = start_idx + total_bytes_read;
+        if let Some(trace) = &trace {
+            trace.record(offset, &line.text);
+        }
+        if options.is_header(offset) {
+            total_bytes_read += bytes_read;
+            continue;
+        }
+        if !record_handler(offset, &line) {
+            break;
         }
 
         // add bytes read in line (error as well to be correctly sum at the end)
         total_bytes_read += bytes_read;
+    }
 
-        // clear to reuse the buffer
-        line.clear();
+    Ok(read)
+}
 
-        if total_bytes_read > num_of_bytes {
-            break;
+/// Scanner of records of the file retrying failed reads.
+type FileScanner<'a> = RecordScanner<BufReader<RetryReader<'a, File>>>;
+
+/// Number of bytes before `start_idx` the search for the delimiter ending the record in
+/// progress starts: the delimiter can start up to its length earlier. Runs of a delimiter
+/// overlapping itself (e.g. `@@@@` of `@@`) are searched from their start, as they split into
+/// delimiters from there, not from the middle of the run.
+fn search_back(file: &File, start_idx: u64, options: &ParseOptions) -> io::Result<u64> {
+    let delimiter = match &options.record_delimiter {
+        Some(delimiter) => delimiter,
+        None => return Ok(0),
+    };
+    let mut back = start_idx.min(delimiter.as_bytes().len() as u64 - 1);
+    let period = delimiter.period();
+    if period == delimiter.as_bytes().len() {
+        return Ok(back);
+    }
+
+    // bytes continuing the run are the same as `period` bytes after them
+    let mut file = file.try_clone()?;
+    let mut block = vec![];
+    loop {
+        let end = start_idx - back + period as u64;
+        let begin = end.saturating_sub(SEARCH_BACK_BLOCK).min(start_idx - back);
+        if begin == end - period as u64 {
+            return Ok(back);
+        }
+        block.resize((end - begin) as usize, 0);
+        file.seek(SeekFrom::Start(begin))?;
+        file.read_exact(&mut block)?;
+        let continued = block
+            .windows(period + 1)
+            .rev()
+            .take_while(|window| window[0] == window[period])
+            .count() as u64;
+        back += continued;
+        if continued < (block.len() - period) as u64 || start_idx == back {
+            return Ok(back);
+        }
+    }
+}
+
+/// Bytes read at once by [`search_back`].
+const SEARCH_BACK_BLOCK: u64 = 4096;
+
+/// Skip bytes from `start_idx` which can't be read even after retries (failed with the error):
+/// they're skipped [`chunk::SKIPPED_BYTES`] at a time until a record after them can be read.
+/// Returns the skipped bytes with the scanner of the file after them, none if they reach
+/// `end_idx`.
+fn skip_unreadable<'a>(
+    file: &File,
+    start_idx: u64,
+    end_idx: u64,
+    err: io::Error,
+    options: &'a ParseOptions,
+) -> io::Result<(FailedRange, Option<FileScanner<'a>>)> {
+    let mut skipped = FailedRange {
+        start_idx,
+        end_idx,
+        attempts: options.io_retries.unwrap_or(chunk::IO_RETRIES) + 1,
+        error: err.to_string(),
+    };
+    let mut position = start_idx;
+    loop {
+        position += chunk::SKIPPED_BYTES;
+        if position >= end_idx {
+            return Ok((skipped, None));
+        }
+        let resumed = search_back(file, position, options).and_then(|back| {
+            let mut file = file.try_clone()?;
+            file.seek(SeekFrom::Start(position - back))?;
+            let mut scanner = options.file_scanner(file);
+            let bytes = scanner.skip_to_record_start(back)?;
+            Ok((bytes.saturating_sub(back), scanner))
+        });
+        match resumed {
+            Ok((bytes, scanner)) => {
+                skipped.end_idx = position + bytes;
+                return Ok((skipped, Some(scanner)));
+            }
+            Err(err) => warn!("Bytes after {} can't be read either: {}", position, err),
         }
     }
 }
 
-/// Multi thread parser with channels.
-pub fn multi_thread_parser_channel(
+/// Number of records buffered between parsing threads and the [`stream`] consumer.
+const STREAM_BUFFER_SIZE: usize = 1024;
+
+/// Number of update batches buffered by [`multi_thread_parser_channel`] by default.
+pub const CHANNEL_CAPACITY: usize = 64;
+
+/// Number of register updates in a batch of [`multi_thread_parser_channel`] by default.
+pub const CHANNEL_BATCH_SIZE: usize = 256;
+
+/// Update sent from parsing threads to the consumer.
+type LogUpdate = (GroupKey, LogRegister);
+type Batch = Vec<LogUpdate>;
+
+/// Multi thread parser with channels: every thread parses its part into its own register and
+/// sends it to the consumers in batches, keys are partitioned by hash so every consumer
+/// (reducer) merges its own keys (see [`ParseOptions::channel_reducers`]). Channels are bounded,
+/// threads wait while a consumer merges earlier batches (see [`ParseOptions::channel_capacity`]).
+pub fn multi_thread_parser_channel<P: AsRef<Path>>(
     num_of_thread: u8,
-    input_file: &str,
-) -> HashMap<String, LogRegister> {
+    input_file: P,
+    options: &ParseOptions,
+) -> io::Result<ParseReport> {
+    let input_file = input_file.as_ref();
     let num_of_thread = num_of_thread as u64;
 
-    let file = File::open(input_file).expect("Can't open file");
-    let size = file.metadata().expect("Can't read file metadata").len();
-    info!("File size in bytes: {}", size);
+    let file = platform::open_shared(input_file)?;
+    let size = file.metadata()?.len();
+    // the input may be a subset of the file, its real size is logged by callers
+    debug!("File size in bytes: {}", size);
+
+    // channels for communication between parsing threads and consumers updating their hashmaps
+    let capacity = options.channel_capacity.unwrap_or(CHANNEL_CAPACITY);
+    let batch_size = options
+        .channel_batch_size
+        .unwrap_or(CHANNEL_BATCH_SIZE)
+        .max(1);
+    let num_of_reducers = options.channel_reducers.unwrap_or(1).max(1);
+    let (senders, receivers): (Vec<SyncSender<Batch>>, Vec<Receiver<Batch>>) = (0..num_of_reducers)
+        .map(|_| mpsc::sync_channel(capacity))
+        .unzip();
+    let profiler = options.profiler.as_deref();
+    if let Some(profiler) = profiler {
+        profiler.queue().set_capacity(capacity * num_of_reducers);
+    }
+
+    let mut log_register: Registers = HashMap::new();
+    let results: Vec<Result<ChunkSummary, FailedRange>> = thread::scope(|scope| {
+        let reducers: Vec<_> = receivers
+            .into_iter()
+            .map(|rx| {
+                scope.spawn(move || {
+                    let _memory = memory::scope(Module::Aggregate);
+                    let mut log_register: Registers = HashMap::new();
+                    let mut profile = ReducerProfile::default();
+                    let mut lap = Instant::now();
+                    for batch in rx {
+                        let received = Instant::now();
+                        profile.idle += received - lap;
+                        if let Some(profiler) = profiler {
+                            profiler.queue().received();
+                        }
+                        for (log_type, log_value) in batch {
+                            let value = log_register.entry(log_type).or_insert(LogRegister::zero());
+                            value.add_assign(log_value);
+                        }
+                        lap = Instant::now();
+                        profile.busy += lap - received;
+                    }
+                    if let Some(profiler) = profiler {
+                        profiler.reducer(profile);
+                    }
+                    log_register
+                })
+            })
+            .collect();
+
+        // keep started thread in vector
+        let mut threads = vec![];
+        for (start_idx, bytes_portion) in chunk::byte_ranges(size, num_of_thread) {
+            let end_idx = start_idx + bytes_portion;
+            let senders = senders.clone();
 
-    // calculate number of bytes to be parsed by every thread
-    let bytes_portion = size / num_of_thread;
+            let handle = scope.spawn(move || {
+                chunk::run_with_retry(start_idx, end_idx, || {
+                    read_chunk_registers(start_idx, bytes_portion, input_file, options)
+                })
+                .map(|(chunk_register, summary)| {
+                    let send = |tx: &SyncSender<Batch>, batch| {
+                        if let Some(profiler) = profiler {
+                            profiler.queue().sent();
+                        }
+                        if tx.send(batch).is_err() {
+                            error!("Can't send via channel");
+                        }
+                    };
+                    let mut batches: Vec<Batch> = vec![vec![]; num_of_reducers];
+                    for (log_type, log_value) in chunk_register {
+                        let mut hasher = DefaultHasher::new();
+                        log_type.hash(&mut hasher);
+                        let reducer = hasher.finish() as usize % num_of_reducers;
+                        batches[reducer].push((log_type, log_value));
+                        if batches[reducer].len() == batch_size {
+                            send(&senders[reducer], mem::take(&mut batches[reducer]));
+                        }
+                    }
+                    for (tx, batch) in senders.iter().zip(batches) {
+                        if !batch.is_empty() {
+                            send(tx, batch);
+                        }
+                    }
+                    summary
+                })
+            });
 
-    // channels for communication between parsing threads and main consumer to update hashmap
-    let (tx, rx): (
-        Sender<(String, LogRegister)>,
-        Receiver<(String, LogRegister)>,
-    ) = mpsc::channel();
+            threads.push((start_idx, end_idx, handle));
+        }
+        // channels are closed once all threads are done
+        drop(senders);
+
+        let results = threads
+            .into_iter()
+            .map(|(start_idx, end_idx, thread)| chunk::join(thread, start_idx, end_idx))
+            .collect();
+        // keys of reducers are disjoint
+        for reducer in reducers {
+            log_register.extend(reducer.join().expect("Reducer thread panicked"));
+        }
+        results
+    });
+    Ok(ParseReport::new(log_register, results, options))
+}
+
+/// Parsed log passed to library users by [`stream`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedRecord {
+    pub log_type: String,
+    pub level: Option<String>,
+    /// Byte offset of the line in the file.
+    pub offset: u64,
+    /// Number of bytes of the line including new line character.
+    pub num_of_bytes: u64,
+    /// Values of numeric fields in the same order as in [`ParseOptions::numeric_fields`].
+    pub numeric_values: Vec<Option<f64>>,
+    /// Values of string fields in the same order as in [`ParseOptions::string_fields`]
+    /// (non-string values are kept as JSON text).
+    pub string_values: Vec<Option<String>>,
+    /// Values of value fields in the same order as in [`ParseOptions::value_fields`].
+    pub values: Vec<Option<Value>>,
+    /// Set only if [`ParseOptions::timestamp_field`] is configured.
+    pub timestamp: Option<SystemTime>,
+    /// Hash of the ID field value, set only if [`ParseOptions::dedup_field`] is configured.
+    pub id: Option<u64>,
+    /// Set only if [`ParseOptions::collect_field_names`] is enabled.
+    pub field_names: Vec<String>,
+    error: bool,
+}
+
+impl ParsedRecord {
+    /// Whether the record matches [`ParseOptions::error_when`], or has error severity level if
+    /// there are no conditions.
+    pub fn is_error(&self) -> bool {
+        self.error
+    }
+
+    /// Extracted numeric, string and value fields as JSON object, missing fields are skipped.
+    pub fn fields(&self, options: &ParseOptions) -> Map<String, Value> {
+        let mut fields = Map::new();
+        for (field_name, value) in options.numeric_fields.iter().zip(&self.numeric_values) {
+            if let Some(value) = value {
+                fields.insert(field_name.clone(), Value::from(*value));
+            }
+        }
+        for (field_name, value) in options.string_fields.iter().zip(&self.string_values) {
+            if let Some(value) = value {
+                fields.insert(field_name.clone(), Value::from(value.as_str()));
+            }
+        }
+        for (field_name, value) in options.value_fields.iter().zip(&self.values) {
+            if let Some(value) = value {
+                fields.insert(field_name.clone(), value.clone());
+            }
+        }
+
+        fields
+    }
+
+    fn new(offset: u64, num_of_bytes: u64, log: Log) -> Self {
+        Self {
+            error: log.is_error(),
+            log_type: log.log_type,
+            level: log.level,
+            offset,
+            num_of_bytes,
+            numeric_values: log.numeric_values,
+            string_values: log.string_values,
+            values: log.values,
+            timestamp: log.timestamp,
+            id: log.id,
+            field_names: log.field_names,
+        }
+    }
+}
+
+/// Line which couldn't be parsed.
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: String,
+    pub error: Error,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Problem to parse line: [{}]. Error: [{}]",
+            self.line, self.error
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl ParseError {
+    /// Line which couldn't be parsed of an error of [`stream`], `None` if the file couldn't be
+    /// read.
+    pub fn of(err: &io::Error) -> Option<&ParseError> {
+        err.get_ref()?.downcast_ref()
+    }
+}
+
+impl From<ParseError> for io::Error {
+    fn from(err: ParseError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Parse a single line the same way lines of a file are parsed (offset of the record is 0).
+pub fn parse_line(line: &str, options: &ParseOptions) -> Result<ParsedRecord, Error> {
+    Log::parse(line, options).map(|log| ParsedRecord::new(0, line.len() as u64, log))
+}
 
-    // keep started thread in vector
-    let mut threads = vec![];
-    for idx in 0..num_of_thread {
-        let start_idx = idx * bytes_portion;
+/// Parse a record read by [`RecordScanner`](crate::scanner::RecordScanner) at the offset the
+/// way records of a file are parsed: errors of the scanner fail it and only the first line of
+/// a multiline record matches the pattern.
+pub fn parse_scanned(
+    offset: u64,
+    line: &ScannedLine,
+    options: &ParseOptions,
+) -> Result<ParsedRecord, Error> {
+    Log::parse_scanned(line, options).map(|log| ParsedRecord::new(offset, line.num_of_bytes, log))
+}
 
-        let tx = tx.clone();
+/// The whole record of a single line as JSON value (fields of the CSV columns or pattern groups
+/// for such inputs) with derived fields, unlike [`parse_line`] fields of logs aren't checked.
+pub fn parse_value(line: &str, options: &ParseOptions) -> Result<Value, Error> {
+    let mut value = parse_record(line, options)?;
+    expr::apply(&options.derive, &mut value);
+    Ok(value)
+}
 
-        // fail fast if can't open the file - stop the program
-        let file = File::open(input_file).expect("Can't open file");
-        let handle = thread::spawn(move || {
-            let log_hasher = |bytes_read: u64, log_type: String| {
-                if tx.send((log_type, LogRegister::new(bytes_read))).is_err() {
-                    error!("Can't send via channel");
+/// JSON line deserialized by the backend of the options.
+#[cfg_attr(not(feature = "simd"), allow(unused_variables))]
+fn from_json<T: DeserializeOwned>(line: &str, options: &ParseOptions) -> Result<T, Error> {
+    #[cfg(feature = "simd")]
+    if options.json_backend == JsonBackend::Simd {
+        // simd-json parses in place, lines it rejects fail in serde_json too, so errors (and
+        // their kinds) are the same for every backend
+        if let Ok(value) = simd_json::serde::from_slice(&mut line.as_bytes().to_vec()) {
+            return Ok(value);
+        }
+    }
+    serde_json::from_str(line)
+}
+
+/// The record of a single line as it is.
+fn parse_record(line: &str, options: &ParseOptions) -> Result<Value, Error> {
+    if let Some(csv) = &options.csv {
+        return csv.record(line).map_err(serde::de::Error::custom);
+    }
+    if let Some(pattern) = &options.pattern {
+        return pattern
+            .record(line)
+            .ok_or_else(|| serde::de::Error::custom(error_log::PATTERN_MISMATCH));
+    }
+    from_json(line, options)
+}
+
+/// Stream records as they are parsed (built on the channel parser).
+///
+/// Every thread parses its part of the file, so records come in file order only within a part.
+/// Buffer between parsing threads and the consumer is bounded - threads wait if the consumer
+/// is slow and stop once the iterator is dropped. Records with an ID seen within the dedup
+/// window are dropped if [`ParseOptions::dedup_field`] is set.
+///
+/// Lines which can't be parsed are `InvalidData` errors of the [`ParseError`] (see
+/// [`ParseError::of`]). Errors reading the file are streamed too: bytes skipped as unreadable
+/// (see [`ParseOptions::skip_unreadable`]) as errors of their [`FailedRange`] once the part is
+/// read, and a read of the part failing even after retries, which ends the records of the part.
+pub fn stream<P: AsRef<Path>>(
+    num_of_thread: u8,
+    input_file: P,
+    options: &ParseOptions,
+) -> io::Result<impl Iterator<Item = io::Result<ParsedRecord>>> {
+    let input_file = input_file.as_ref();
+    let file = platform::open_shared(input_file)?;
+    let size = file.metadata()?.len();
+    // the input may be a subset of the file, its real size is logged by callers
+    debug!("File size in bytes: {}", size);
+
+    let (tx, rx) = mpsc::sync_channel(STREAM_BUFFER_SIZE);
+    for (start_idx, bytes_portion) in chunk::byte_ranges(size, num_of_thread as u64) {
+        stream_part(tx.clone(), start_idx, bytes_portion, input_file, options)?;
+    }
+
+    Ok(deduplicated(rx, options))
+}
+
+/// Stream records of the file like [`stream`], in file order: every part is parsed into its own
+/// bounded buffer, which is read once the parts before it are, so threads of later parts wait
+/// for the consumer instead of their records piling up in memory.
+pub fn stream_ordered<P: AsRef<Path>>(
+    num_of_thread: u8,
+    input_file: P,
+    options: &ParseOptions,
+) -> io::Result<impl Iterator<Item = io::Result<ParsedRecord>>> {
+    let input_file = input_file.as_ref();
+    let file = platform::open_shared(input_file)?;
+    let size = file.metadata()?.len();
+    // the input may be a subset of the file, its real size is logged by callers
+    debug!("File size in bytes: {}", size);
+
+    let mut parts = vec![];
+    for (start_idx, bytes_portion) in chunk::byte_ranges(size, num_of_thread as u64) {
+        let (tx, rx) = mpsc::sync_channel(STREAM_BUFFER_SIZE);
+        stream_part(tx, start_idx, bytes_portion, input_file, options)?;
+        parts.push(rx);
+    }
+
+    Ok(deduplicated(parts.into_iter().flatten(), options))
+}
+
+/// Parse the part of the file in a new thread, sending its records (and errors) into the channel.
+fn stream_part(
+    tx: SyncSender<io::Result<ParsedRecord>>,
+    start_idx: u64,
+    bytes_portion: u64,
+    input_file: &Path,
+    options: &ParseOptions,
+) -> io::Result<()> {
+    let options = options.clone();
+    let file = platform::open_shared(input_file)?;
+    let input_file = input_file.to_path_buf();
+    thread::spawn(move || {
+        let record_sender =
+            |offset: u64, bytes_read: u64, line: &str, log_result: Result<Log, Error>| {
+                match streamed_record(offset, bytes_read, line, log_result) {
+                    // receiver is dropped - nobody is interested in the rest of records
+                    Some(record) => tx.send(record).is_ok(),
+                    None => true,
                 }
             };
-            partially_read_file(start_idx, bytes_portion, file, log_hasher)
-        });
+        // records already sent can't be taken back, so the part is not retried, the stream of
+        // an interrupted run just ends
+        let read = partially_read_lines(
+            start_idx,
+            bytes_portion,
+            0,
+            &input_file,
+            file,
+            &options,
+            record_sender,
+        );
+        match read {
+            Ok(read) => {
+                for skipped in read.skipped_ranges {
+                    error!("{}", skipped);
+                    if tx.send(Err(io::Error::other(skipped))).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(err) => {
+                let end_idx = start_idx + bytes_portion;
+                let message = format!("Reading bytes {}..{} failed: {}", start_idx, end_idx, err);
+                error!("{}", message);
+                let _ = tx.send(Err(io::Error::new(err.kind(), message)));
+            }
+        }
+    });
+    Ok(())
+}
 
-        threads.push(handle);
-    }
-    drop(tx);
+/// Records of the content of the reader like [`stream`], parsed by a single thread as the content
+/// is read (see [`parse_reader`]). A failed read ends the records.
+pub fn stream_reader<R: Read + Send + 'static>(
+    reader: R,
+    options: &ParseOptions,
+) -> impl Iterator<Item = io::Result<ParsedRecord>> {
+    let (tx, rx) = mpsc::sync_channel(STREAM_BUFFER_SIZE);
+    let thread_options = options.clone();
+    thread::spawn(move || {
+        let options = thread_options;
+        let mut scanner = options.scanner(BufReader::new(reader));
+        let mut offset = 0;
+        // the stream of an interrupted run just ends
+        while !options.is_stopped() {
+            let line = match scanner.next_record() {
+                Ok(Some(line)) => line,
+                Ok(None) => return,
+                Err(err) => {
+                    let message = format!("Reading after byte {} failed: {}", offset, err);
+                    error!("{}", message);
+                    let _ = tx.send(Err(io::Error::new(err.kind(), message)));
+                    return;
+                }
+            };
+            let bytes_read = line.num_of_bytes;
+            if !options.is_header(offset) {
+                let log_result = Log::parse_scanned(&line, &options);
+                if let Some(record) = streamed_record(offset, bytes_read, &line.text, log_result) {
+                    if tx.send(record).is_err() {
+                        return;
+                    }
+                }
+            }
+            offset += bytes_read;
+        }
+    });
 
-    let mut log_register: HashMap<String, LogRegister> = HashMap::new();
-    for (log_type, log_value) in rx {
-        let value = log_register.entry(log_type).or_insert(LogRegister::zero());
-        value.add_assign(log_value);
+    deduplicated(rx, options)
+}
+
+/// Records of the input like [`stream`], compressed or transcoded content is streamed into a
+/// single thread instead (see [`stream_reader`]).
+pub fn stream_input(
+    num_of_thread: u8,
+    input: &Input,
+    options: &ParseOptions,
+) -> io::Result<Box<dyn Iterator<Item = io::Result<ParsedRecord>>>> {
+    Ok(match input.file() {
+        Some(path) => Box::new(stream(num_of_thread, path.to_path_buf(), options)?),
+        None => Box::new(stream_reader(input.reader()?, options)),
+    })
+}
+
+/// Record of the parsed line at the offset for a stream, `None` if it doesn't match the filter.
+fn streamed_record(
+    offset: u64,
+    bytes_read: u64,
+    line: &str,
+    log_result: Result<Log, Error>,
+) -> Option<io::Result<ParsedRecord>> {
+    if log_result.as_ref().is_ok_and(|log| log.unmatched) {
+        return None;
     }
+    let record = log_result
+        .map(|log| ParsedRecord::new(offset, bytes_read, log))
+        .map_err(|error| {
+            io::Error::from(ParseError {
+                line: line.to_string(),
+                error,
+            })
+        });
+    Some(record)
+}
 
-    log_register
+/// Streamed records without those with IDs seen in the dedup window if
+/// [`ParseOptions::dedup_field`] is set.
+fn deduplicated<I>(
+    records: I,
+    options: &ParseOptions,
+) -> impl Iterator<Item = io::Result<ParsedRecord>>
+where
+    I: IntoIterator<Item = io::Result<ParsedRecord>>,
+{
+    let mut deduplicator = options
+        .dedup_field
+        .as_ref()
+        .map(|_| Deduplicator::new(options.dedup_window.unwrap_or(dedup::DEFAULT_WINDOW)));
+    records
+        .into_iter()
+        .filter(move |record| match (&mut deduplicator, record) {
+            (Some(deduplicator), Ok(record)) => record.id.is_none_or(|id| deduplicator.is_new(id)),
+            _ => true,
+        })
 }
 
 /// Single thread parser.
-pub fn single_thread_parser(input_file: &str) -> HashMap<String, LogRegister> {
-    let mut log_register: HashMap<String, LogRegister> = HashMap::new();
+pub fn single_thread_parser<P: AsRef<Path>>(
+    input_file: P,
+    options: &ParseOptions,
+) -> io::Result<ParseReport> {
+    let input_file = input_file.as_ref();
+    let file = platform::open_shared(input_file)?;
+    let size = file.metadata()?.len();
+
+    let result = chunk::run_with_retry(0, size, || read_whole_file(input_file, options));
+    match result {
+        Ok((log_register, summary)) => {
+            Ok(ParseReport::new(log_register, vec![Ok(summary)], options))
+        }
+        Err(failed_range) => Ok(ParseReport::new(
+            HashMap::new(),
+            vec![Err(failed_range)],
+            options,
+        )),
+    }
+}
+
+fn read_whole_file(
+    input_file: &Path,
+    options: &ParseOptions,
+) -> io::Result<(Registers, ChunkSummary)> {
+    let mut log_register: Registers = HashMap::new();
+
+    let file = platform::open_shared(input_file)?;
+    let size = file.metadata()?.len();
+    let unreadable = match options.skip_unreadable {
+        true => Some(file.try_clone()?),
+        false => None,
+    };
+    let mut scanner = options.file_scanner(file);
 
-    // fail fast - panic if can't open the file
-    let file = File::open(input_file).expect("Can't open file");
-    let mut buffered = BufReader::new(file);
-
-    let mut line = String::new();
-    while buffered.read_line(&mut line).expect("Unexpected I/O error") > 0 {
-        let bytes_read = line.len();
-        let log_result: Result<Log, Error> = serde_json::from_str(line.as_str());
-        match log_result {
-            Ok(log) => {
-                let value = log_register
-                    .entry(log.log_type)
-                    .or_insert(LogRegister::zero());
-                value.add_assign(LogRegister::new(bytes_read as u64));
+    let mut quarantine = options
+        .bad_lines
+        .as_ref()
+        .map(|quarantine| quarantine.chunk(input_file, 0));
+    let mut summary = ChunkSummary::default();
+    let progress = options.progress.as_ref().map(|progress| progress.counter());
+    let trace = diagnostics::worker(0);
+
+    let mut profiler = options.profiler.as_ref().map(|profiler| profiler.thread(0));
+    let _memory = memory::scope(Module::Read);
+    let mut lap = |stage| {
+        if let Some(profiler) = profiler.as_mut() {
+            profiler.lap(stage);
+        }
+        memory::stage_ended(stage);
+    };
+    let mut offset = 0;
+    loop {
+        if options.is_stopped() && offset < size {
+            summary.unread_range = Some(UnreadRange {
+                start_idx: offset,
+                end_idx: size,
+            });
+            break;
+        }
+        let next = options
+            .next_read(input_file, offset)
+            .unwrap_or(size)
+            .max(offset);
+        if next > offset {
+            // records before the next range can't match the filter, they're skipped unread
+            if let Some(progress) = &progress {
+                progress.add(next.min(size) - offset);
             }
+            if next >= size {
+                break;
+            }
+            scanner = options.file_scanner_at(input_file, next)?;
+            offset = next;
+            continue;
+        }
+        let line = match scanner.next_record() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
             Err(err) => {
-                error!("Problem to parse line: [{}]. Error: [{}]", line, err);
+                let file = match &unreadable {
+                    Some(file) => file,
+                    None => return Err(err),
+                };
+                let (skipped, resumed) = skip_unreadable(file, offset, size, err, options)?;
+                if let Some(progress) = &progress {
+                    progress.add(skipped.end_idx - skipped.start_idx);
+                }
+                offset = skipped.end_idx;
+                summary.skipped_ranges.push(skipped);
+                match resumed {
+                    Some(resumed) => {
+                        scanner = resumed;
+                        continue;
+                    }
+                    None => break,
+                }
             }
+        };
+        let bytes_read = line.num_of_bytes;
+        if let Some(progress) = &progress {
+            progress.add(bytes_read);
+        }
+        if let Some(trace) = &trace {
+            trace.record(offset, &line.text);
+        }
+        if options.is_header(offset) {
+            offset += bytes_read;
+            continue;
         }
+        offset += bytes_read;
+        add_record(
+            &line,
+            &mut log_register,
+            &mut summary,
+            quarantine.as_mut(),
+            options,
+            &mut lap,
+        );
+    }
+
+    Ok((log_register, summary))
+}
 
-        // clear to reuse the buffer
-        line.clear();
+/// Count the record into the summary and its log into the registers, a line which can't be
+/// parsed goes into the quarantine instead.
+fn add_record(
+    line: &ScannedLine,
+    log_register: &mut Registers,
+    summary: &mut ChunkSummary,
+    quarantine: Option<&mut QuarantineChunk>,
+    options: &ParseOptions,
+    lap: &mut dyn FnMut(Stage),
+) {
+    summary.lines += 1;
+    lap(Stage::Read);
+    let log_result = Log::parse_scanned(line, options);
+    lap(Stage::Parse);
+    match log_result {
+        Ok(log) if log.unmatched => {}
+        Ok(log) => {
+            let value = log_register
+                .entry(log.key(options))
+                .or_insert(LogRegister::zero());
+            value.add(line.num_of_bytes, &log);
+        }
+        Err(err) => {
+            error_log::record(&mut summary.error_kinds, &line.text, &err);
+            summary.bad_lines += 1;
+            if let Some(quarantine) = quarantine.filter(|_| options.quarantines(&err)) {
+                quarantine.write(summary.lines, &line.text, &err);
+            }
+        }
     }
+    lap(Stage::Aggregate);
+}
 
-    log_register
+/// Parse the content of the reader (e.g. the output of a decompression tool) by a single thread
+/// as it's read, for input which can't be read in parts. Lines are quarantined as lines of the
+/// source. Read ranges of an index don't apply, and failed reads end the parsing as the content
+/// can't be read again.
+pub fn parse_reader<R: Read, P: AsRef<Path>>(
+    reader: R,
+    source: P,
+    options: &ParseOptions,
+) -> io::Result<ParseReport> {
+    let mut log_register: Registers = HashMap::new();
+    let mut scanner = options.scanner(BufReader::new(reader));
+
+    let mut quarantine = options
+        .bad_lines
+        .as_ref()
+        .map(|quarantine| quarantine.chunk(source.as_ref(), 0));
+    let mut summary = ChunkSummary::default();
+    let progress = options.progress.as_ref().map(|progress| progress.counter());
+    let trace = diagnostics::worker(0);
+
+    let mut profiler = options.profiler.as_ref().map(|profiler| profiler.thread(0));
+    let _memory = memory::scope(Module::Read);
+    let mut lap = |stage| {
+        if let Some(profiler) = profiler.as_mut() {
+            profiler.lap(stage);
+        }
+        memory::stage_ended(stage);
+    };
+    let mut offset = 0;
+    loop {
+        if options.is_stopped() {
+            // size of the rest isn't known before it's read
+            summary.unread_range = Some(UnreadRange {
+                start_idx: offset,
+                end_idx: offset,
+            });
+            break;
+        }
+        let line = match scanner.next_record()? {
+            Some(line) => line,
+            None => break,
+        };
+        let bytes_read = line.num_of_bytes;
+        if let Some(progress) = &progress {
+            progress.add(bytes_read);
+        }
+        if let Some(trace) = &trace {
+            trace.record(offset, &line.text);
+        }
+        if options.is_header(offset) {
+            offset += bytes_read;
+            continue;
+        }
+        offset += bytes_read;
+        add_record(
+            &line,
+            &mut log_register,
+            &mut summary,
+            quarantine.as_mut(),
+            options,
+            &mut lap,
+        );
+    }
+
+    Ok(ParseReport::new(log_register, vec![Ok(summary)], options))
+}
+
+/// Timestamps of generated logs: evenly spread over the range, optionally jittered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleTimestamps {
+    pub start: SystemTime,
+    pub range: Duration,
+    /// Max random shift of every timestamp in both directions, logs are out of order if it's
+    /// bigger than the gap between logs. Zero gives monotonic timestamps.
+    pub jitter: Duration,
+}
+
+/// Options of generated sample file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleOptions {
+    pub num_of_lines: u32,
+    pub num_of_log_types: u32,
+    pub max_msg_size: u32,
+    /// `timestamp` field is written only if set.
+    pub timestamps: Option<SampleTimestamps>,
+    /// Severity levels with their weights, `level` field is written only if not empty.
+    pub levels: Vec<(String, u32)>,
+    /// Seed of the random generator, the same seed and options always give the same file.
+    pub seed: Option<u64>,
+    /// Template of generated logs, number of log types, message size and levels are not used
+    /// if set (see [`Template`]).
+    pub template: Option<Template>,
+    /// Size of the file in bytes, lines are generated until it's reached instead of
+    /// `num_of_lines` if set. Timestamps are spread over the range by the bytes written.
+    pub target_size: Option<u64>,
+}
+
+impl SampleTimestamps {
+    /// Timestamp of the log with given index.
+    fn at<R: Rng>(&self, idx: u32, num_of_lines: u32, rng: &mut R) -> SystemTime {
+        let step = self.range.as_secs_f64() / num_of_lines.max(1) as f64;
+        self.shifted(idx as f64 * step, rng)
+    }
+
+    /// Timestamp of the log written after the fraction of the file size.
+    fn at_fraction<R: Rng>(&self, fraction: f64, rng: &mut R) -> SystemTime {
+        self.shifted(fraction * self.range.as_secs_f64(), rng)
+    }
+
+    /// Time of the offset from the start in seconds, jittered.
+    fn shifted<R: Rng>(&self, offset: f64, rng: &mut R) -> SystemTime {
+        let jitter = self.jitter.as_secs_f64();
+        let shift = if jitter > 0.0 {
+            rng.gen_range(-jitter, jitter)
+        } else {
+            0.0
+        };
+        let offset = offset + shift;
+        if offset >= 0.0 {
+            self.start + Duration::from_secs_f64(offset)
+        } else {
+            self.start
+                .checked_sub(Duration::from_secs_f64(-offset))
+                .unwrap_or(UNIX_EPOCH)
+        }
+    }
+}
+
+impl Default for SampleOptions {
+    fn default() -> Self {
+        Self {
+            num_of_lines: 1000,
+            num_of_log_types: 2,
+            max_msg_size: 100,
+            timestamps: None,
+            levels: vec![],
+            seed: None,
+            template: None,
+            target_size: None,
+        }
+    }
 }
 
 /// Use it to generate sample file.
-pub fn prepare_sample_file(
-    num_of_lines: u32,
-    num_of_log_types: u32,
-    max_msg_size: u32,
-    file: &str,
-) -> HashMap<String, LogRegister> {
+pub fn prepare_sample_file(options: &SampleOptions, file: &str) -> HashMap<String, LogRegister> {
     let mut log_register: HashMap<String, LogRegister> = HashMap::new();
 
-    let mut file = File::create(file).expect("Can't create sample file");
+    let mut file = BufWriter::new(File::create(file).expect("Can't create sample file"));
 
-    let mut rng = rand::thread_rng();
-    for _ in 0..num_of_lines {
-        let log_type = rng.gen_range(0, num_of_log_types).to_string();
+    let mut rng = match options.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let levels = if options.levels.is_empty() {
+        None
+    } else {
+        let weights = options.levels.iter().map(|(_, weight)| *weight);
+        Some(WeightedIndex::new(weights).expect("Level weights should be positive"))
+    };
+    let (mut idx, mut written) = (0, 0);
+    while match options.target_size {
+        Some(target_size) => written < target_size,
+        None => idx < options.num_of_lines,
+    } {
+        let time = |rng: &mut StdRng, timestamps: &SampleTimestamps| match options.target_size {
+            Some(target_size) => timestamps.at_fraction(written as f64 / target_size as f64, rng),
+            None => timestamps.at(idx, options.num_of_lines, rng),
+        };
+        let log = match &options.template {
+            Some(template) => {
+                // without time range `{ts}` is the time of generation
+                let time = match &options.timestamps {
+                    Some(timestamps) => time(&mut rng, timestamps),
+                    None => SystemTime::now(),
+                };
+                template.fill(&mut rng, time)
+            }
+            None => {
+                let log_type = rng.gen_range(0, options.num_of_log_types).to_string();
 
-        let id = Uuid::new_v4();
+                // built from the seeded generator, so ids are reproducible too
+                let id = template::random_uuid(&mut rng);
 
-        let msg_size = rng.gen_range(1, max_msg_size + 1);
-        let msg: String = rng
-            .sample_iter(&Alphanumeric)
-            .take(msg_size as usize)
-            .collect();
+                let msg_size = rng.gen_range(1, options.max_msg_size + 1);
+                let msg: String = (&mut rng)
+                    .sample_iter(&Alphanumeric)
+                    .take(msg_size as usize)
+                    .collect();
 
-        let log = json!({"type" : log_type.clone(), "id" : id, "message" : msg});
-        let log = log.to_string();
-        let log_as_bytes = log.as_bytes();
+                let mut log = json!({"type" : log_type, "id" : id, "message" : msg});
+                if let Some(levels) = &levels {
+                    log["level"] = json!(options.levels[rng.sample(levels)].0);
+                }
+                if let Some(timestamps) = &options.timestamps {
+                    let time = time(&mut rng, timestamps);
+                    log["timestamp"] = json!(timestamp::format(time));
+                }
+                log
+            }
+        };
+        let log_as_text = log.to_string();
+        let log_as_bytes = log_as_text.as_bytes();
 
-        file.write(log_as_bytes).unwrap();
-        file.write(b"\n").unwrap();
+        file.write_all(log_as_bytes).unwrap();
+        file.write_all(b"\n").unwrap();
 
         let num_of_bytes: u64 = log_as_bytes.len() as u64 + 1;
+        idx = idx.saturating_add(1);
+        written += num_of_bytes;
 
         // register inserted logs
-        let value = log_register.entry(log_type).or_insert(LogRegister::zero());
-        value.add_assign(LogRegister::new(num_of_bytes));
+        let log = Log::deserialize(&log).expect("Generated log should have string type and level");
+        let value = log_register
+            .entry(log.log_type.clone())
+            .or_insert(LogRegister::zero());
+        value.add(num_of_bytes, &log);
     }
 
     file.flush().unwrap();
 
     log_register
 }
+
+/// Generate the sample in shards written in parallel, the registers of all shards are returned.
+/// Lines (or the target size) are split evenly between the shards, each of them spans the whole
+/// time range like logs of separate hosts. Shard `idx` is generated with seed `seed + idx`, so
+/// the same seed still gives the same files. A single shard is written to the file itself,
+/// others to [`shard_path`] files.
+pub fn prepare_sample_shards(
+    options: &SampleOptions,
+    file: &str,
+    num_of_shards: u32,
+) -> HashMap<String, LogRegister> {
+    let num_of_shards = num_of_shards.max(1);
+    let share = |total: u64, idx: u32| {
+        total / num_of_shards as u64 + u64::from((idx as u64) < total % num_of_shards as u64)
+    };
+    let registers: Vec<HashMap<String, LogRegister>> = thread::scope(|scope| {
+        let shards: Vec<_> = (0..num_of_shards)
+            .map(|idx| {
+                let path = match num_of_shards {
+                    1 => file.to_string(),
+                    _ => shard_path(file, idx, num_of_shards),
+                };
+                let options = SampleOptions {
+                    num_of_lines: share(options.num_of_lines as u64, idx) as u32,
+                    target_size: options.target_size.map(|size| share(size, idx)),
+                    seed: options.seed.map(|seed| seed.wrapping_add(idx as u64)),
+                    ..options.clone()
+                };
+                scope.spawn(move || prepare_sample_file(&options, &path))
+            })
+            .collect();
+        shards
+            .into_iter()
+            .map(|shard| shard.join().expect("Sample shard thread panicked"))
+            .collect()
+    });
+
+    let mut log_register: HashMap<String, LogRegister> = HashMap::new();
+    for register in registers {
+        for (log_type, value) in register {
+            *log_register.entry(log_type).or_insert(LogRegister::zero()) += value;
+        }
+    }
+    log_register
+}
+
+/// File of the shard of the sample: the index (padded so shards sort by name) before the
+/// extension, e.g. `sample-03.log`.
+pub fn shard_path(file: &str, idx: u32, num_of_shards: u32) -> String {
+    let width = (num_of_shards.max(1) - 1).to_string().len();
+    let path = Path::new(file);
+    let name = format!(
+        "{}-{:0width$}",
+        path.file_stem().unwrap_or_default().to_string_lossy(),
+        idx,
+        width = width
+    );
+    let name = match path.extension() {
+        Some(extension) => format!("{}.{}", name, extension.to_string_lossy()),
+        None => name,
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
@@ -0,0 +1,48 @@
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+
+/// Log type of records from patterns without `type` group.
+pub const DEFAULT_TYPE: &str = "-";
+
+/// Regex with named groups turning unstructured lines into records, e.g.
+/// `^(?P<ts>\S+) (?P<level>\w+) \[(?P<type>\w+)\] (?P<msg>.*)$`. Fields are named after the
+/// groups, so they can be used like JSON fields (group-by, filters, numeric fields).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct LinePattern(Regex);
+
+impl LinePattern {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let regex = Regex::new(pattern).map_err(|err| format!("Invalid pattern: {}", err))?;
+        if !regex.capture_names().any(|name| name.is_some()) {
+            return Err(format!("Pattern without named groups: [{}]", pattern));
+        }
+        Ok(LinePattern(regex))
+    }
+
+    /// Record with fields from groups which matched (as strings), `None` if the line doesn't
+    /// match. Log type is [`DEFAULT_TYPE`] if the pattern has no `type` group.
+    pub fn record(&self, line: &str) -> Option<Value> {
+        let line = line.trim_end_matches(['\n', '\r']);
+        let captures = self.0.captures(line)?;
+        let mut fields = Map::new();
+        fields.insert("type".to_string(), Value::from(DEFAULT_TYPE));
+        for name in self.0.capture_names().flatten() {
+            if let Some(value) = captures.name(name) {
+                fields.insert(name.to_string(), Value::from(value.as_str()));
+            }
+        }
+
+        Some(Value::Object(fields))
+    }
+}
+
+impl TryFrom<String> for LinePattern {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        Self::new(&pattern)
+    }
+}
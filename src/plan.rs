@@ -0,0 +1,149 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// How much of the inputs an analysis reads, in the order scans of a stage run.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Scan {
+    /// Part of every input is copied out (`--head`, `--tail`, `--sample`).
+    Subset,
+    /// The beginning of the first input is parsed a few times (`--auto-tune`).
+    Trial,
+    /// Inputs are parsed from start to end.
+    Full,
+}
+
+impl fmt::Display for Scan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Scan::Subset => "subset scan",
+            Scan::Trial => "trial scan",
+            Scan::Full => "full scan",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Analysis requested by the options, with what it needs from a pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub name: String,
+    /// Inputs read by the analysis, none if it's computed from the report alone.
+    pub scan: Option<Scan>,
+    /// Whether the analysis needs the complete report before it reads the inputs, e.g. sinks
+    /// writing records after the report.
+    pub after_report: bool,
+}
+
+impl Analysis {
+    /// Analysis reading the inputs.
+    pub fn scan(name: &str, scan: Scan) -> Self {
+        Self {
+            name: name.to_string(),
+            scan: Some(scan),
+            after_report: false,
+        }
+    }
+
+    /// Analysis reading the inputs once the report is complete.
+    pub fn scan_after_report(name: &str, scan: Scan) -> Self {
+        Self {
+            after_report: true,
+            ..Self::scan(name, scan)
+        }
+    }
+
+    /// Analysis of the report, it doesn't read the inputs.
+    pub fn report(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            scan: None,
+            after_report: true,
+        }
+    }
+}
+
+/// Pass over the inputs shared by analyses.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Pass {
+    pub scan: Scan,
+    pub after_report: bool,
+    pub analyses: Vec<String>,
+}
+
+/// Passes running the analyses, in order.
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct Plan {
+    pub passes: Vec<Pass>,
+    /// Analyses computed from the report without reading the inputs.
+    pub from_report: Vec<String>,
+}
+
+impl Plan {
+    /// Plan the fewest passes: analyses share a pass if they read the same part of the inputs
+    /// and neither needs the report the other one completes. Passes before the report run by
+    /// their scan (subsets are copied before trials parse them), passes after it follow.
+    pub fn new(analyses: &[Analysis]) -> Self {
+        let mut passes: BTreeMap<(bool, Scan), Vec<String>> = BTreeMap::new();
+        let mut from_report = vec![];
+        for analysis in analyses {
+            match analysis.scan {
+                Some(scan) => passes
+                    .entry((analysis.after_report, scan))
+                    .or_default()
+                    .push(analysis.name.clone()),
+                None => from_report.push(analysis.name.clone()),
+            }
+        }
+
+        Self {
+            passes: passes
+                .into_iter()
+                .map(|((after_report, scan), analyses)| Pass {
+                    scan,
+                    after_report,
+                    analyses,
+                })
+                .collect(),
+            from_report,
+        }
+    }
+
+    /// Number of passes reading the inputs from start to end.
+    pub fn full_scans(&self) -> usize {
+        self.passes
+            .iter()
+            .filter(|pass| pass.scan == Scan::Full)
+            .count()
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} passes, {} full scans",
+            self.passes.len(),
+            self.full_scans()
+        )?;
+        for (idx, pass) in self.passes.iter().enumerate() {
+            writeln!(
+                f,
+                "pass {}: {}{}: {}",
+                idx + 1,
+                pass.scan,
+                if pass.after_report {
+                    " after the report"
+                } else {
+                    ""
+                },
+                pass.analyses.join(", ")
+            )?;
+        }
+        if !self.from_report.is_empty() {
+            writeln!(f, "from the report: {}", self.from_report.join(", "))?;
+        }
+        Ok(())
+    }
+}
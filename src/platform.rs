@@ -0,0 +1,67 @@
+use std::env;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// `FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE`
+#[cfg(windows)]
+const SHARE_ALL: u32 = 0x1 | 0x2 | 0x4;
+
+/// Open the input file for reading while other processes keep writing, renaming or deleting it
+/// (log rotation). On Windows the sharing mode is set explicitly, long and UNC paths get the
+/// `\\?\` prefix from std, so neither is limited to `MAX_PATH`.
+pub fn open_shared<P: AsRef<Path>>(path: P) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.read(true);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        options.share_mode(SHARE_ALL);
+    }
+    options.open(path)
+}
+
+/// Directory only the user can enter, for files other users mustn't read or replace (e.g. keys
+/// handed to tools). It has a random name and is created anew, so nothing can be planted in it
+/// beforehand. It's removed with its files on drop.
+#[derive(Debug)]
+pub struct PrivateDir(PathBuf);
+
+impl PrivateDir {
+    pub fn new() -> io::Result<Self> {
+        let path = env::temp_dir().join(format!("log-parser-{}", uuid::Uuid::new_v4().to_simple()));
+        let mut builder = DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        // fails if the path exists, even as a symlink
+        builder.create(&path)?;
+        Ok(Self(path))
+    }
+
+    /// New file of the directory, it fails if the file exists.
+    pub fn create(&self, name: &str) -> io::Result<(PathBuf, File)> {
+        let path = self.0.join(name);
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(&path)?;
+        Ok((path, file))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for PrivateDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
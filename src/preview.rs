@@ -0,0 +1,224 @@
+use crate::csv::CsvFormat;
+use crate::parser::{self, ParseOptions};
+use crate::platform;
+use crate::scanner::{self, LineError};
+use crate::timestamp;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+/// Number of lines [`detect_format`] looks at.
+const DETECT_LINES: usize = 20;
+
+/// What parse makes of a top-level field of a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FieldRole {
+    /// Groups logs by default.
+    LogType,
+    /// Severity, error levels make logs errors.
+    Level,
+    /// RFC 3339 date, e.g. for `--timestamp-field`.
+    Timestamp,
+    /// Number (or number as text), e.g. for `--sum-field`.
+    Number,
+}
+
+impl FieldRole {
+    /// Role of the field with the value, `None` for other text and nested values.
+    pub fn of(field: &str, value: &Value) -> Option<Self> {
+        match (field, value) {
+            ("type", _) => Some(FieldRole::LogType),
+            ("level", _) => Some(FieldRole::Level),
+            (_, Value::Number(_)) => Some(FieldRole::Number),
+            (_, Value::String(text)) if text.trim().parse::<f64>().is_ok() => {
+                Some(FieldRole::Number)
+            }
+            // epoch numbers are numbers above
+            (_, Value::String(text)) if timestamp::parse_str(text).is_some() => {
+                Some(FieldRole::Timestamp)
+            }
+            _ => None,
+        }
+    }
+
+    /// Parse flag using the field, if it isn't used by default.
+    pub fn flag(&self) -> Option<&'static str> {
+        match self {
+            FieldRole::LogType | FieldRole::Level => None,
+            FieldRole::Timestamp => Some("--timestamp-field"),
+            FieldRole::Number => Some("--sum-field"),
+        }
+    }
+
+    /// ANSI color of the field name.
+    fn color(&self) -> &'static str {
+        match self {
+            FieldRole::LogType => "\x1b[1;36m",
+            FieldRole::Level => "\x1b[1;33m",
+            FieldRole::Timestamp => "\x1b[1;35m",
+            FieldRole::Number => "\x1b[1;32m",
+        }
+    }
+}
+
+impl fmt::Display for FieldRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FieldRole::LogType => "log type",
+            FieldRole::Level => "level",
+            FieldRole::Timestamp => "timestamp",
+            FieldRole::Number => "number",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A record of the input head.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviewRecord {
+    /// Number of the record from 1 (the CSV header isn't a record).
+    pub number: u64,
+    pub line: String,
+    /// The whole record, `None` if it can't be parsed at all.
+    pub value: Option<Value>,
+    /// Why parse would count the record as a failed line.
+    pub error: Option<String>,
+}
+
+impl PreviewRecord {
+    /// Record of a line read without the scanner (new line character removed), `None` for blank
+    /// lines.
+    pub fn from_line(number: u64, line: Vec<u8>, options: &ParseOptions) -> Option<Self> {
+        let max_line_len = options.max_line_bytes.unwrap_or(scanner::MAX_LINE_LEN);
+        // the limit of the scanner includes the new line character
+        let (line, error) = match String::from_utf8(line) {
+            Ok(line) if line.len() >= max_line_len => {
+                (String::new(), Some(LineError::TooLong(max_line_len)))
+            }
+            Ok(line) => (line, None),
+            Err(err) => (
+                String::from_utf8_lossy(err.as_bytes()).into_owned(),
+                Some(LineError::InvalidUtf8),
+            ),
+        };
+        if line.trim().is_empty() && error.is_none() {
+            return None;
+        }
+        let (value, error) = match error {
+            Some(error) => (None, Some(error.to_string())),
+            None => (
+                parser::parse_value(&line, options).ok(),
+                parser::parse_line(&line, options)
+                    .err()
+                    .map(|err| err.to_string()),
+            ),
+        };
+        Some(PreviewRecord {
+            number,
+            line,
+            value,
+            error,
+        })
+    }
+
+    /// Top-level fields of the record parse makes use of.
+    pub fn fields(&self) -> Vec<(&str, FieldRole)> {
+        match &self.value {
+            Some(Value::Object(fields)) => fields
+                .iter()
+                .filter_map(|(field, value)| Some((field.as_str(), FieldRole::of(field, value)?)))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// The record as pretty-printed JSON (the line as it is if it can't be parsed), with names of
+    /// fields parse makes use of colored if enabled.
+    pub fn render(&self, color: bool) -> String {
+        let fields = match &self.value {
+            Some(Value::Object(fields)) => fields,
+            Some(value) => return serde_json::to_string_pretty(value).unwrap_or_default(),
+            None => return self.line.trim_end_matches(['\n', '\r']).to_string(),
+        };
+        let mut lines = vec!["{".to_string()];
+        for (idx, (field, value)) in fields.iter().enumerate() {
+            let name = serde_json::to_string(field).unwrap_or_default();
+            let name = match FieldRole::of(field, value) {
+                Some(role) if color => format!("{}{}\x1b[0m", role.color(), name),
+                _ => name,
+            };
+            let value = serde_json::to_string_pretty(value)
+                .unwrap_or_default()
+                .replace('\n', "\n  ");
+            let comma = if idx + 1 < fields.len() { "," } else { "" };
+            lines.push(format!("  {}: {}{}", name, value, comma));
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+}
+
+/// CSV format of the input by its first lines (see [`CsvFormat::detect`]), `None` for JSON lines.
+pub fn detect_format<P: AsRef<Path>>(
+    path: P,
+    options: &ParseOptions,
+) -> io::Result<Option<CsvFormat>> {
+    let records = records(path, DETECT_LINES, options)?;
+    let lines: Vec<&str> = records
+        .iter()
+        .map(|record| record.line.trim_end_matches(['\n', '\r']))
+        .filter(|line| !line.is_empty())
+        .collect();
+    Ok(CsvFormat::detect(&lines))
+}
+
+/// The first records of the input parsed like parse does (the CSV header is skipped).
+pub fn records<P: AsRef<Path>>(
+    path: P,
+    num_of_records: usize,
+    options: &ParseOptions,
+) -> io::Result<Vec<PreviewRecord>> {
+    let file = platform::open_shared(path)?;
+    let mut scanner = options.scanner(BufReader::new(file));
+    if options.csv.as_ref().is_some_and(|csv| csv.has_header) {
+        scanner.next_record()?;
+    }
+
+    let mut records = vec![];
+    while records.len() < num_of_records {
+        let line = match scanner.next_record()? {
+            Some(line) => line,
+            None => break,
+        };
+        let (value, error) = match line.error {
+            Some(error) => (None, Some(error.to_string())),
+            None => (
+                parser::parse_value(&line.text, options).ok(),
+                parser::parse_line(&line.text, options)
+                    .err()
+                    .map(|err| err.to_string()),
+            ),
+        };
+        records.push(PreviewRecord {
+            number: records.len() as u64 + 1,
+            line: line.text.into_owned(),
+            value,
+            error,
+        });
+    }
+
+    Ok(records)
+}
+
+/// Fields parse makes use of in any of the records, with the role of their first value.
+pub fn detected_fields(records: &[PreviewRecord]) -> BTreeMap<String, FieldRole> {
+    let mut detected = BTreeMap::new();
+    for record in records {
+        for (field, role) in record.fields() {
+            detected.entry(field.to_string()).or_insert(role);
+        }
+    }
+    detected
+}
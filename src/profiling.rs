@@ -0,0 +1,361 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Phase of handling a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Reading the record from the file (I/O, finding its end, skipping to a part start).
+    Read,
+    /// Parsing the record into a log.
+    Parse,
+    /// Adding the log to registers or passing it on (channel, stream, checkpoint).
+    Aggregate,
+}
+
+pub const STAGES: [Stage; 3] = [Stage::Read, Stage::Parse, Stage::Aggregate];
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Stage::Read => "read",
+            Stage::Parse => "parse",
+            Stage::Aggregate => "aggregate",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Time spent in every stage by the parsing threads of a run, see
+/// [`crate::parser::ParseOptions::profiler`]. Threads time stages on their own and publish the
+/// totals once they are done, so profiling costs only reading the clock between stages.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    threads: Mutex<Vec<ThreadProfile>>,
+    /// Queue between parsing threads and reducers of the channel strategy.
+    queue: QueueGauge,
+    /// Time reducers spent merging and waiting for batches.
+    reducers: Mutex<Vec<ReducerProfile>>,
+}
+
+/// Stage durations of a parsing thread (a retried part of the file is another thread).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ThreadProfile {
+    /// Start of the part of the file read by the thread.
+    pub start_idx: u64,
+    pub lines: u64,
+    pub read: Duration,
+    pub parse: Duration,
+    pub aggregate: Duration,
+}
+
+impl ThreadProfile {
+    pub fn stage(&self, stage: Stage) -> Duration {
+        match stage {
+            Stage::Read => self.read,
+            Stage::Parse => self.parse,
+            Stage::Aggregate => self.aggregate,
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        self.read + self.parse + self.aggregate
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start timing a thread reading the part of the file, the first stage starts now.
+    pub fn thread(&self, start_idx: u64) -> ThreadProfiler<'_> {
+        ThreadProfiler {
+            profiler: self,
+            profile: ThreadProfile {
+                start_idx,
+                ..ThreadProfile::default()
+            },
+            lap: Instant::now(),
+        }
+    }
+
+    /// Profiles of finished threads ordered by their part of the file.
+    pub fn threads(&self) -> Vec<ThreadProfile> {
+        let mut threads = self.threads.lock().expect("Profiler poisoned").clone();
+        threads.sort_by_key(|thread| thread.start_idx);
+        threads
+    }
+
+    pub fn queue(&self) -> &QueueGauge {
+        &self.queue
+    }
+
+    /// Publish the time a reducer spent merging batches and waiting for them.
+    pub fn reducer(&self, profile: ReducerProfile) {
+        self.reducers
+            .lock()
+            .expect("Profiler poisoned")
+            .push(profile);
+    }
+
+    pub fn reducers(&self) -> Vec<ReducerProfile> {
+        self.reducers.lock().expect("Profiler poisoned").clone()
+    }
+
+    /// Profiles in the folded stacks format of flamegraph tools (`inferno-flamegraph`,
+    /// `flamegraph.pl`): a `<root>;part <start>;<stage> <microseconds>` line per thread and stage.
+    pub fn folded(&self, root: &str) -> String {
+        let mut folded = String::new();
+        for thread in self.threads() {
+            for stage in STAGES {
+                let micros = thread.stage(stage).as_micros();
+                if micros > 0 {
+                    folded.push_str(&format!(
+                        "{};part {};{} {}\n",
+                        root, thread.start_idx, stage, micros
+                    ));
+                }
+            }
+        }
+        folded
+    }
+}
+
+/// Stage timer of a single thread, its profile is published when dropped.
+pub struct ThreadProfiler<'a> {
+    profiler: &'a Profiler,
+    profile: ThreadProfile,
+    /// End of the previous stage.
+    lap: Instant,
+}
+
+impl ThreadProfiler<'_> {
+    /// End the stage, time since the previous stage ended is spent in it.
+    pub fn lap(&mut self, stage: Stage) {
+        let now = Instant::now();
+        let elapsed = now - self.lap;
+        self.lap = now;
+        match stage {
+            Stage::Read => {
+                self.profile.read += elapsed;
+                self.profile.lines += 1;
+            }
+            Stage::Parse => self.profile.parse += elapsed,
+            Stage::Aggregate => self.profile.aggregate += elapsed,
+        }
+    }
+}
+
+impl Drop for ThreadProfiler<'_> {
+    fn drop(&mut self) {
+        self.profiler
+            .threads
+            .lock()
+            .expect("Profiler poisoned")
+            .push(self.profile.clone());
+    }
+}
+
+/// Time a reducer of the channel strategy spent merging batches and waiting for them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReducerProfile {
+    pub busy: Duration,
+    pub idle: Duration,
+}
+
+/// Depth of a bounded queue, sampled whenever an item is sent. The depth counts items sent and
+/// not received yet, including the one being sent, so a send finding the queue at its capacity
+/// waits for the receiver.
+#[derive(Debug, Default)]
+pub struct QueueGauge {
+    capacity: AtomicUsize,
+    depth: AtomicUsize,
+    sends: AtomicU64,
+    full_sends: AtomicU64,
+    depth_sum: AtomicU64,
+    max_depth: AtomicUsize,
+}
+
+/// Depths sampled by [`QueueGauge`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueueStats {
+    pub capacity: usize,
+    pub sends: u64,
+    /// Sends which found the queue full and waited.
+    pub full_sends: u64,
+    pub mean_depth: f64,
+    pub max_depth: usize,
+}
+
+impl QueueGauge {
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    /// Count an item about to be sent.
+    pub fn sent(&self) {
+        let depth = self.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        self.sends.fetch_add(1, Ordering::Relaxed);
+        self.depth_sum.fetch_add(depth as u64, Ordering::Relaxed);
+        self.max_depth.fetch_max(depth, Ordering::Relaxed);
+        if depth > self.capacity.load(Ordering::Relaxed) {
+            self.full_sends.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count an item taken off the queue.
+    pub fn received(&self) {
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let sends = self.sends.load(Ordering::Relaxed);
+        let depth_sum = self.depth_sum.load(Ordering::Relaxed);
+        QueueStats {
+            capacity: self.capacity.load(Ordering::Relaxed),
+            sends,
+            full_sends: self.full_sends.load(Ordering::Relaxed),
+            mean_depth: if sends > 0 {
+                depth_sum as f64 / sends as f64
+            } else {
+                0.0
+            },
+            max_depth: self.max_depth.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Stage of the whole pipeline of a run a [`Bottleneck`] can be found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bottleneck {
+    Read,
+    Parse,
+    Aggregate,
+    /// Reducers of the channel strategy, parsing threads wait for them.
+    Reduce,
+    /// Writing results to sinks once parsing is done.
+    Sink,
+}
+
+impl fmt::Display for Bottleneck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hint = match self {
+            Bottleneck::Read => {
+                "reader-bound: consider more --num-of-threads, uncompressed inputs or a faster disk"
+            }
+            Bottleneck::Parse => "parser-bound: consider --json-backend scan",
+            Bottleneck::Aggregate => {
+                "aggregator-bound: consider --strategy sharded or fewer --group-by fields"
+            }
+            Bottleneck::Reduce => {
+                "reducer-bound: parsing threads wait for the channel, consider --strategy sharded"
+            }
+            Bottleneck::Sink => {
+                "sink-bound: consider fewer --sink destinations or writing them without --sink-records"
+            }
+        };
+        f.write_str(hint)
+    }
+}
+
+/// Share of the busy time above which a stage is reported as the bottleneck.
+const BOTTLENECK_SHARE: f64 = 0.5;
+
+/// Time spent in every stage of the pipeline by all parsing threads, reducers and sinks of a run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PipelineMetrics {
+    /// Totals of all parsing threads.
+    pub threads: ThreadProfile,
+    pub reducers: ReducerProfile,
+    /// Queue of the channel strategy, sends of all inputs.
+    pub queue: QueueStats,
+    /// Wall time of parsing all inputs.
+    pub parse: Duration,
+    /// Wall time of writing sinks.
+    pub sink: Duration,
+}
+
+impl PipelineMetrics {
+    /// Metrics of the profiled inputs with wall times of parsing them and writing sinks.
+    pub fn new<'a, I>(profilers: I, parse: Duration, sink: Duration) -> Self
+    where
+        I: IntoIterator<Item = &'a Profiler>,
+    {
+        let mut metrics = PipelineMetrics {
+            parse,
+            sink,
+            ..PipelineMetrics::default()
+        };
+        let mut depth_sum = 0.0;
+        for profiler in profilers {
+            for thread in profiler.threads() {
+                metrics.threads.lines += thread.lines;
+                metrics.threads.read += thread.read;
+                metrics.threads.parse += thread.parse;
+                metrics.threads.aggregate += thread.aggregate;
+            }
+            for reducer in profiler.reducers() {
+                metrics.reducers.busy += reducer.busy;
+                metrics.reducers.idle += reducer.idle;
+            }
+            let queue = profiler.queue().stats();
+            depth_sum += queue.mean_depth * queue.sends as f64;
+            metrics.queue.capacity = metrics.queue.capacity.max(queue.capacity);
+            metrics.queue.sends += queue.sends;
+            metrics.queue.full_sends += queue.full_sends;
+            metrics.queue.max_depth = metrics.queue.max_depth.max(queue.max_depth);
+        }
+        if metrics.queue.sends > 0 {
+            metrics.queue.mean_depth = depth_sum / metrics.queue.sends as f64;
+        }
+        metrics
+    }
+
+    /// Share of the parsing threads time spent in the stage.
+    pub fn utilization(&self, stage: Stage) -> f64 {
+        share(self.threads.stage(stage), self.threads.total())
+    }
+
+    /// Share of the reducers time spent merging, none without reducers.
+    pub fn reducer_utilization(&self) -> Option<f64> {
+        let total = self.reducers.busy + self.reducers.idle;
+        (total > Duration::default()).then(|| share(self.reducers.busy, total))
+    }
+
+    /// Share of the sends which waited for a full queue, none without a queue.
+    pub fn queue_full(&self) -> Option<f64> {
+        (self.queue.sends > 0).then(|| self.queue.full_sends as f64 / self.queue.sends as f64)
+    }
+
+    /// Stage dominating the run: sinks if writing them took longer than parsing, reducers if
+    /// most sends waited for them, otherwise the stage taking most of the parsing threads time.
+    /// None if no stage dominates.
+    pub fn bottleneck(&self) -> Option<Bottleneck> {
+        if self.sink > self.parse {
+            return Some(Bottleneck::Sink);
+        }
+        if self.queue_full().unwrap_or(0.0) > BOTTLENECK_SHARE {
+            return Some(Bottleneck::Reduce);
+        }
+        STAGES
+            .iter()
+            .map(|stage| (*stage, self.utilization(*stage)))
+            .filter(|(_, utilization)| *utilization > BOTTLENECK_SHARE)
+            .map(|(stage, _)| match stage {
+                Stage::Read => Bottleneck::Read,
+                Stage::Parse => Bottleneck::Parse,
+                Stage::Aggregate => Bottleneck::Aggregate,
+            })
+            .next()
+    }
+}
+
+fn share(part: Duration, total: Duration) -> f64 {
+    if total > Duration::default() {
+        part.as_secs_f64() / total.as_secs_f64()
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,425 @@
+use crate::clock::{Clock, SystemClock};
+use crate::units;
+use indicatif::{MultiProgress, ProgressDrawTarget, ProgressStyle};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the progress line is redrawn.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Width of the bar in characters.
+const BAR_WIDTH: usize = 30;
+
+/// Bytes and lines consumed by a single parsing thread.
+#[derive(Debug)]
+pub struct ThreadCounter {
+    bytes: AtomicU64,
+    lines: AtomicU64,
+    started: Instant,
+    /// Time the thread took, 0 while it's running.
+    elapsed_micros: AtomicU64,
+    clock: Arc<dyn Clock>,
+}
+
+impl ThreadCounter {
+    fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            bytes: AtomicU64::new(0),
+            lines: AtomicU64::new(0),
+            started: clock.instant(),
+            elapsed_micros: AtomicU64::new(0),
+            clock,
+        }
+    }
+
+    /// Register consumed line, only the owning thread writes so relaxed ordering is enough.
+    pub fn add(&self, num_of_bytes: u64) {
+        self.bytes.fetch_add(num_of_bytes, Ordering::Relaxed);
+        self.lines.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn elapsed(&self) -> Duration {
+        match self.elapsed_micros.load(Ordering::Relaxed) {
+            0 => self.running(),
+            micros => Duration::from_micros(micros),
+        }
+    }
+
+    fn running(&self) -> Duration {
+        self.clock.instant().saturating_duration_since(self.started)
+    }
+}
+
+/// Counter of a parsing thread, its time is taken when the thread drops it.
+#[derive(Debug)]
+pub struct ThreadProgress(Arc<ThreadCounter>);
+
+impl ThreadProgress {
+    pub fn add(&self, num_of_bytes: u64) {
+        self.0.add(num_of_bytes);
+    }
+}
+
+impl Drop for ThreadProgress {
+    fn drop(&mut self) {
+        let micros = self.0.running().as_micros().max(1) as u64;
+        self.0.elapsed_micros.store(micros, Ordering::Relaxed);
+    }
+}
+
+/// Bytes and lines consumed by a parsing thread with its (running) time, see
+/// [`Progress::threads`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThreadStats {
+    pub bytes: u64,
+    pub lines: u64,
+    pub elapsed: Duration,
+}
+
+/// Progress of a parser run, aggregated from per-thread counters.
+#[derive(Debug)]
+pub struct Progress {
+    total_bytes: u64,
+    started: Instant,
+    counters: Mutex<Vec<Arc<ThreadCounter>>>,
+    finished: AtomicBool,
+    clock: Arc<dyn Clock>,
+}
+
+/// Point in time view of a run, see [`Progress::snapshot`] and [`ProgressHandle::poll`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressSnapshot {
+    /// Bytes consumed, at most the total.
+    pub bytes: u64,
+    pub total_bytes: u64,
+    pub lines: u64,
+    pub elapsed: Duration,
+    /// Share of the input consumed (0.0 - 1.0).
+    pub fraction: f64,
+    /// Average throughput since the start.
+    pub bytes_per_sec: f64,
+    pub lines_per_sec: f64,
+    /// Throughput since the previous poll of a [`ProgressHandle`], the average otherwise.
+    pub current_bytes_per_sec: f64,
+    pub current_lines_per_sec: f64,
+    /// Time left at the average throughput, `None` until the first bytes are consumed (zero once
+    /// all of them are, e.g. of an empty file).
+    pub eta: Option<Duration>,
+    /// Whether the parser returned its report.
+    pub finished: bool,
+}
+
+impl Progress {
+    pub fn new(total_bytes: u64) -> Self {
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        Self {
+            total_bytes,
+            started: clock.instant(),
+            counters: Mutex::new(Vec::new()),
+            finished: AtomicBool::new(false),
+            clock,
+        }
+    }
+
+    /// Measure throughput and pace the progress bar by the clock instead of the system one.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.started = clock.instant();
+        self.clock = clock;
+        self
+    }
+
+    /// Mark the run as finished, called by parsers once the report is built.
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::Relaxed);
+    }
+
+    /// Register counter for a new parsing thread.
+    pub fn counter(&self) -> ThreadProgress {
+        let counter = Arc::new(ThreadCounter::new(self.clock.clone()));
+        self.counters
+            .lock()
+            .expect("Progress counters poisoned")
+            .push(counter.clone());
+        ThreadProgress(counter)
+    }
+
+    /// Bytes, lines and time of every thread in the order they started (retried parts of the
+    /// file are new threads).
+    pub fn threads(&self) -> Vec<ThreadStats> {
+        self.counters
+            .lock()
+            .expect("Progress counters poisoned")
+            .iter()
+            .map(|counter| ThreadStats {
+                bytes: counter.bytes.load(Ordering::Relaxed),
+                lines: counter.lines.load(Ordering::Relaxed),
+                elapsed: counter.elapsed(),
+            })
+            .collect()
+    }
+
+    /// Total bytes and lines consumed by all threads.
+    pub fn consumed(&self) -> (u64, u64) {
+        self.counters
+            .lock()
+            .expect("Progress counters poisoned")
+            .iter()
+            .fold((0, 0), |(bytes, lines), counter| {
+                (
+                    bytes + counter.bytes.load(Ordering::Relaxed),
+                    lines + counter.lines.load(Ordering::Relaxed),
+                )
+            })
+    }
+
+    /// Bytes and lines consumed so far with average throughput and ETA.
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        let (bytes, lines) = self.consumed();
+        // chunk boundaries can be read twice so don't go over the total
+        let bytes = bytes.min(self.total_bytes);
+        let elapsed = self.clock.instant().saturating_duration_since(self.started);
+        let secs = elapsed.as_secs_f64();
+        let fraction = if self.total_bytes == 0 {
+            1.0
+        } else {
+            bytes as f64 / self.total_bytes as f64
+        };
+        let (bytes_per_sec, lines_per_sec) = if secs > 0.0 {
+            (bytes as f64 / secs, lines as f64 / secs)
+        } else {
+            (0.0, 0.0)
+        };
+        let eta = if bytes == self.total_bytes {
+            Some(Duration::ZERO)
+        } else if bytes > 0 {
+            let remaining = secs * (self.total_bytes - bytes) as f64 / bytes as f64;
+            Some(Duration::from_secs(remaining.ceil() as u64))
+        } else {
+            None
+        };
+
+        ProgressSnapshot {
+            bytes,
+            total_bytes: self.total_bytes,
+            lines,
+            elapsed,
+            fraction,
+            bytes_per_sec,
+            lines_per_sec,
+            current_bytes_per_sec: bytes_per_sec,
+            current_lines_per_sec: lines_per_sec,
+            eta,
+            finished: self.finished.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Progress line with bytes processed, lines/sec, MB/sec and ETA.
+    pub fn render(&self) -> String {
+        let snapshot = self.snapshot();
+        let filled = (snapshot.fraction * BAR_WIDTH as f64) as usize;
+
+        format!(
+            "[{}{}] {}",
+            "=".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            stats(&snapshot)
+        )
+    }
+}
+
+/// Progress line after the bar.
+fn stats(snapshot: &ProgressSnapshot) -> String {
+    let eta = match snapshot.eta {
+        Some(eta) => humantime::format_duration(eta).to_string(),
+        None => "-".to_string(),
+    };
+
+    format!(
+        "{:>3.0}% {}/{}, {:.0} lines/s, {:.1} MB/s, ETA {}",
+        snapshot.fraction * 100.0,
+        units::format_bytes(snapshot.bytes as f64),
+        units::format_bytes(snapshot.total_bytes as f64),
+        snapshot.lines_per_sec,
+        snapshot.bytes_per_sec / 1_000_000.0,
+        eta
+    )
+}
+
+/// Line of a parsing thread under the bar.
+fn thread_stats(thread: &ThreadStats) -> String {
+    let secs = thread.elapsed.as_secs_f64();
+    let mb_per_sec = if secs > 0.0 {
+        thread.bytes as f64 / secs / 1_000_000.0
+    } else {
+        0.0
+    };
+
+    format!(
+        "{}, {} lines, {:.1} MB/s",
+        units::format_bytes(thread.bytes as f64),
+        thread.lines,
+        mb_per_sec
+    )
+}
+
+/// Progress of a run polled by embedders, e.g. for their own progress reporting: the progress
+/// is passed to the parser with [`crate::parser::ParseOptions::progress`] and the handle is
+/// polled from another thread. Clones share the progress and the previous poll.
+#[derive(Debug, Clone)]
+pub struct ProgressHandle {
+    progress: Arc<Progress>,
+    /// Time, bytes and lines of the previous poll.
+    previous: Arc<Mutex<Option<(Duration, u64, u64)>>>,
+}
+
+impl ProgressHandle {
+    pub fn new(total_bytes: u64) -> Self {
+        Self {
+            progress: Arc::new(Progress::new(total_bytes)),
+            previous: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Handle of a progress measured by the clock, see [`Progress::with_clock`].
+    pub fn with_clock(total_bytes: u64, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            progress: Arc::new(Progress::new(total_bytes).with_clock(clock)),
+            previous: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Handle of a run parsing the whole file.
+    pub fn for_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(fs::metadata(path)?.len()))
+    }
+
+    /// Progress to pass to the parser.
+    pub fn progress(&self) -> Arc<Progress> {
+        self.progress.clone()
+    }
+
+    /// Snapshot of the progress with current throughput since the previous poll.
+    pub fn poll(&self) -> ProgressSnapshot {
+        let mut snapshot = self.progress.snapshot();
+        let mut previous = self.previous.lock().expect("Progress handle poisoned");
+        if let Some((elapsed, bytes, lines)) = *previous {
+            let secs = (snapshot.elapsed - elapsed).as_secs_f64();
+            if secs > 0.0 {
+                snapshot.current_bytes_per_sec = (snapshot.bytes - bytes) as f64 / secs;
+                snapshot.current_lines_per_sec = (snapshot.lines - lines) as f64 / secs;
+            }
+        }
+        *previous = Some((snapshot.elapsed, snapshot.bytes, snapshot.lines));
+        snapshot
+    }
+}
+
+/// Background thread redrawing the progress on stderr (if it's a terminal) until finished: the
+/// bar of the run with a line per parsing thread under it.
+pub struct ProgressBar {
+    progress: Arc<Progress>,
+    bars: Arc<Bars>,
+    done: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Bars of the run and of its threads, in the order the threads started.
+struct Bars {
+    multi: MultiProgress,
+    total: indicatif::ProgressBar,
+    threads: Mutex<Vec<indicatif::ProgressBar>>,
+}
+
+impl Bars {
+    fn new(total_bytes: u64) -> Self {
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::stderr());
+        let style = ProgressStyle::with_template(&format!("[{{bar:{}}}] {{msg}}", BAR_WIDTH))
+            .expect("Progress template is valid")
+            .progress_chars("= ");
+        let total = multi.add(indicatif::ProgressBar::new(total_bytes).with_style(style));
+
+        Self {
+            multi,
+            total,
+            threads: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn update(&self, progress: &Progress) {
+        let snapshot = progress.snapshot();
+        self.total.set_position(snapshot.bytes);
+        self.total.set_message(stats(&snapshot));
+
+        let mut bars = self.threads.lock().expect("Progress bars poisoned");
+        for (idx, thread) in progress.threads().iter().enumerate() {
+            if idx == bars.len() {
+                let style = ProgressStyle::with_template("  {prefix} {msg}")
+                    .expect("Progress template is valid");
+                let bar = self
+                    .multi
+                    .add(indicatif::ProgressBar::new_spinner().with_style(style));
+                bar.set_prefix(format!("thread {}", idx + 1));
+                bars.push(bar);
+            }
+            bars[idx].set_message(thread_stats(thread));
+        }
+    }
+
+    /// Leave the bar of the run on screen, without the lines of threads.
+    fn finish(&self) {
+        for bar in self.threads.lock().expect("Progress bars poisoned").iter() {
+            bar.finish_and_clear();
+        }
+        self.total.finish();
+    }
+}
+
+impl ProgressBar {
+    pub fn start(progress: Arc<Progress>) -> Self {
+        let bars = Arc::new(Bars::new(progress.total_bytes));
+        let done = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let progress = progress.clone();
+            let bars = bars.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    bars.update(&progress);
+                    progress.clock.sleep(REFRESH_INTERVAL);
+                }
+            })
+        };
+
+        Self {
+            progress,
+            bars,
+            done,
+            handle: Some(handle),
+        }
+    }
+
+    /// Stop redrawing and leave the final progress line on screen.
+    pub fn finish(mut self) {
+        self.stop();
+        self.bars.update(&self.progress);
+        self.bars.finish();
+    }
+
+    fn stop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.join().expect("Couldn't join on the progress thread");
+        }
+    }
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,450 @@
+use crate::parser::{self, ParseError, ParseOptions, ParsedRecord};
+use log::error;
+use serde::Deserialize;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::mem;
+use std::ops::AddAssign;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Output format of selected fields.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Ndjson,
+    Csv,
+}
+
+/// Filter on field value, `type=error` or `level!=info`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Condition {
+    pub field: String,
+    pub value: String,
+    pub negated: bool,
+}
+
+impl Condition {
+    /// Parse condition in `<field>=<value>` or `<field>!=<value>` format, values are compared
+    /// as text (non-string values as JSON text).
+    pub fn parse(condition: &str) -> Result<Self, String> {
+        let (field, value, negated) = match condition.split_once("!=") {
+            Some((field, value)) => (field, value, true),
+            None => match condition.split_once('=') {
+                Some((field, value)) => (field, value, false),
+                None => {
+                    return Err(format!(
+                        "Condition should be <field>=<value> or <field>!=<value>: [{}]",
+                        condition
+                    ))
+                }
+            },
+        };
+        let field = field.trim();
+        if field.is_empty() {
+            return Err(format!("Condition without field: [{}]", condition));
+        }
+
+        Ok(Self {
+            field: field.to_string(),
+            value: value.trim().to_string(),
+            negated,
+        })
+    }
+
+    /// Whether the value of the field (`None` if missing) matches the condition.
+    pub fn matches(&self, value: Option<&Value>) -> bool {
+        let equal = value
+            .filter(|value| !value.is_null())
+            .is_some_and(|value| text(value) == self.value);
+        equal != self.negated
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = String;
+
+    fn try_from(condition: String) -> Result<Self, Self::Error> {
+        Self::parse(&condition)
+    }
+}
+
+/// Fields extracted from every record matching all conditions.
+#[derive(Debug, Clone)]
+pub struct Projection {
+    fields: Vec<String>,
+    conditions: Vec<Condition>,
+    format: Format,
+    /// Fields extracted by the parser: selected fields followed by fields of conditions.
+    value_fields: Vec<String>,
+}
+
+impl Projection {
+    pub fn new(fields: Vec<String>, conditions: Vec<Condition>, format: Format) -> Self {
+        let mut value_fields = fields.clone();
+        for condition in &conditions {
+            if !value_fields.contains(&condition.field) {
+                value_fields.push(condition.field.clone());
+            }
+        }
+
+        Self {
+            fields,
+            conditions,
+            format,
+            value_fields,
+        }
+    }
+
+    /// Parser options extracting selected fields and fields of conditions.
+    pub fn parse_options(&self, options: &ParseOptions) -> ParseOptions {
+        ParseOptions {
+            value_fields: self.value_fields.clone(),
+            ..options.clone()
+        }
+    }
+
+    fn value<'a>(&self, record: &'a ParsedRecord, field: &str) -> Option<&'a Value> {
+        let idx = self.value_fields.iter().position(|name| name == field)?;
+        record.values.get(idx)?.as_ref()
+    }
+
+    pub fn matches(&self, record: &ParsedRecord) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(self.value(record, &condition.field)))
+    }
+
+    /// Header line (CSV only).
+    pub fn header(&self) -> Option<String> {
+        match self.format {
+            Format::Ndjson => None,
+            Format::Csv => Some(
+                self.fields
+                    .iter()
+                    .map(|field| csv_cell(field))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+        }
+    }
+
+    /// Selected fields of the record as a single line (without new line character), missing
+    /// fields are `null` in NDJSON and empty in CSV.
+    pub fn format(&self, record: &ParsedRecord) -> String {
+        match self.format {
+            Format::Ndjson => {
+                // written by hand to keep fields in the selected order
+                let fields: Vec<String> = self
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        let value = self.value(record, field).unwrap_or(&Value::Null);
+                        format!("{}:{}", Value::from(field.as_str()), value)
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(","))
+            }
+            Format::Csv => self
+                .fields
+                .iter()
+                .map(|field| {
+                    let value = self.value(record, field).map(text).unwrap_or_default();
+                    csv_cell(&value)
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+        }
+    }
+}
+
+/// Value as text, strings without quotes and other values as JSON.
+pub(crate) fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// CSV cell, quoted if it contains separator, quotes or new lines.
+fn csv_cell(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Summary of projected records.
+#[derive(Debug, Default)]
+pub struct ProjectionSummary {
+    pub records: u64,
+    pub matched: u64,
+    pub failed_lines: u64,
+}
+
+impl AddAssign for ProjectionSummary {
+    fn add_assign(&mut self, rhs: Self) {
+        self.records += rhs.records;
+        self.matched += rhs.matched;
+        self.failed_lines += rhs.failed_lines;
+    }
+}
+
+/// Number of lines of a file [`project_chronological`] reads ahead to put them in time order.
+pub const REORDER_WINDOW: usize = 4096;
+
+/// Write selected fields of matching records, parsed by the multi-threaded reader.
+///
+/// Records are written as they are parsed (in file order only within a part of the file read
+/// by a single thread) unless `ordered` is set, then they're written in file order: every part
+/// is parsed into its own bounded buffer, which is written once the parts before it are (see
+/// [`parser::stream_ordered`]).
+pub fn project<P: AsRef<Path>>(
+    num_of_thread: u8,
+    input_file: P,
+    options: &ParseOptions,
+    projection: &Projection,
+    ordered: bool,
+    out: &mut dyn Write,
+) -> io::Result<ProjectionSummary> {
+    project_files(
+        num_of_thread,
+        &[input_file],
+        options,
+        projection,
+        ordered,
+        out,
+    )
+}
+
+/// Write selected fields of matching records of the files like [`project`], one file after
+/// another (e.g. rotated files from the oldest).
+pub fn project_files<P: AsRef<Path>>(
+    num_of_thread: u8,
+    input_files: &[P],
+    options: &ParseOptions,
+    projection: &Projection,
+    ordered: bool,
+    out: &mut dyn Write,
+) -> io::Result<ProjectionSummary> {
+    let options = projection.parse_options(options);
+    let mut summary = ProjectionSummary::default();
+    if let Some(header) = projection.header() {
+        writeln!(out, "{}", header)?;
+    }
+
+    for input_file in input_files {
+        let mut matching = matching(
+            num_of_thread,
+            input_file.as_ref(),
+            &options,
+            projection,
+            ordered,
+        )?;
+        for line in matching.by_ref() {
+            writeln!(out, "{}", line?.line)?;
+        }
+        summary += matching.summary;
+    }
+    out.flush()?;
+
+    Ok(summary)
+}
+
+/// Write selected fields of matching records of the files in the order of their timestamps
+/// ([`ParseOptions::timestamp_field`]), e.g. of rotated files of several hosts interleaved.
+///
+/// Files are read at once, each in file order, and merged as they're read, so only
+/// [`REORDER_WINDOW`] lines of every file are kept in memory: a line is put in time order among
+/// the lines read ahead, so lines out of order by less than the window (e.g. logs of threads
+/// written a bit late) are still sorted. Records without timestamp stay after the record
+/// preceding them in their file (the first records before the following one), so do records of
+/// files without any timestamp, and records with the same time are written in file order. The
+/// threads are shared by the files.
+pub fn project_chronological<P: AsRef<Path>>(
+    num_of_thread: u8,
+    input_files: &[P],
+    options: &ParseOptions,
+    projection: &Projection,
+    out: &mut dyn Write,
+) -> io::Result<ProjectionSummary> {
+    if options.timestamp_field.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Records are ordered by time of the timestamp field, which isn't set",
+        ));
+    }
+    let options = projection.parse_options(options);
+    let mut summary = ProjectionSummary::default();
+    if let Some(header) = projection.header() {
+        writeln!(out, "{}", header)?;
+    }
+
+    let threads = (num_of_thread as usize / input_files.len().max(1)).max(1) as u8;
+    let mut runs = vec![];
+    for input_file in input_files {
+        let matching = matching(threads, input_file.as_ref(), &options, projection, true)?;
+        runs.push(Timed::new(matching));
+    }
+
+    // k-way merge of the runs, ties go to the earlier file
+    let mut heads = BinaryHeap::new();
+    for (idx, run) in runs.iter_mut().enumerate() {
+        if let Some((time, line)) = run.next().transpose()? {
+            heads.push(Reverse((time, idx, line)));
+        }
+    }
+    while let Some(Reverse((_, idx, line))) = heads.pop() {
+        writeln!(out, "{}", line)?;
+        if let Some((time, line)) = runs[idx].next().transpose()? {
+            heads.push(Reverse((time, idx, line)));
+        }
+    }
+    out.flush()?;
+    for run in runs {
+        summary += run.lines.summary;
+    }
+
+    Ok(summary)
+}
+
+/// Selected fields of a matching record.
+struct MatchedLine {
+    timestamp: Option<SystemTime>,
+    line: String,
+}
+
+/// Records of a file.
+type Records = Box<dyn Iterator<Item = io::Result<ParsedRecord>>>;
+
+/// Matching records of a file with their selected fields, counted into the summary as they're
+/// read.
+struct Matching<'a> {
+    records: Records,
+    projection: &'a Projection,
+    summary: ProjectionSummary,
+}
+
+/// Parse the file, in file order if `ordered` is set.
+fn matching<'a>(
+    num_of_thread: u8,
+    input_file: &Path,
+    options: &ParseOptions,
+    projection: &'a Projection,
+    ordered: bool,
+) -> io::Result<Matching<'a>> {
+    let records: Records = match ordered {
+        true => Box::new(parser::stream_ordered(
+            num_of_thread,
+            input_file.to_path_buf(),
+            options,
+        )?),
+        false => Box::new(parser::stream(
+            num_of_thread,
+            input_file.to_path_buf(),
+            options,
+        )?),
+    };
+    Ok(Matching {
+        records,
+        projection,
+        summary: ProjectionSummary::default(),
+    })
+}
+
+impl Iterator for Matching<'_> {
+    type Item = io::Result<MatchedLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for record in self.records.by_ref() {
+            let record = match record {
+                Ok(record) => record,
+                Err(err) if ParseError::of(&err).is_some() => {
+                    error!("{}", err);
+                    self.summary.records += 1;
+                    self.summary.failed_lines += 1;
+                    continue;
+                }
+                Err(err) => return Some(Err(err)),
+            };
+            self.summary.records += 1;
+            if !self.projection.matches(&record) {
+                continue;
+            }
+            self.summary.matched += 1;
+            return Some(Ok(MatchedLine {
+                timestamp: record.timestamp,
+                line: self.projection.format(&record),
+            }));
+        }
+        None
+    }
+}
+
+/// Lines of a file in time order of the [`REORDER_WINDOW`] read ahead, lines without timestamp
+/// take the time of the line before them in the file (the first ones of the first line with
+/// timestamp).
+struct Timed<'a> {
+    lines: Matching<'a>,
+    /// Lines read ahead by time, then by their number in the file.
+    window: BinaryHeap<Reverse<(SystemTime, u64, String)>>,
+    /// First lines of the file without timestamp, until one with timestamp is read.
+    leading: Vec<String>,
+    previous: Option<SystemTime>,
+    read: u64,
+}
+
+impl<'a> Timed<'a> {
+    fn new(lines: Matching<'a>) -> Self {
+        Timed {
+            lines,
+            window: BinaryHeap::new(),
+            leading: vec![],
+            previous: None,
+            read: 0,
+        }
+    }
+
+    fn push(&mut self, time: SystemTime, line: String) {
+        self.window.push(Reverse((time, self.read, line)));
+        self.read += 1;
+    }
+}
+
+impl Iterator for Timed<'_> {
+    type Item = io::Result<(SystemTime, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.window.len() + self.leading.len() < REORDER_WINDOW {
+            let line = match self.lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(err)) => return Some(Err(err)),
+                None => break,
+            };
+            match line.timestamp.or(self.previous) {
+                Some(time) => {
+                    for leading in mem::take(&mut self.leading) {
+                        self.push(time, leading);
+                    }
+                    self.previous = Some(time);
+                    self.push(time, line.line);
+                }
+                None => self.leading.push(line.line),
+            }
+        }
+        // lines of a file without timestamp (in the window)
+        if !self.leading.is_empty() {
+            self.previous = Some(UNIX_EPOCH);
+            for leading in mem::take(&mut self.leading) {
+                self.push(UNIX_EPOCH, leading);
+            }
+        }
+        self.window
+            .pop()
+            .map(|Reverse((time, _, line))| Ok((time, line)))
+    }
+}
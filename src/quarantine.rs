@@ -0,0 +1,308 @@
+use crate::chunk::{FailedRange, UnreadRange};
+use crate::decompress::{self, Codec};
+use crate::error_log::{self, ErrorClass};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Line which couldn't be parsed, written as JSON line into the quarantine file.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuarantinedLine {
+    pub source: String,
+    /// Line number in the source file (starting from 1).
+    pub line_number: u64,
+    /// Class of the error, e.g. to pick lines to reprocess once the producer is fixed.
+    pub reason: ErrorClass,
+    pub error: String,
+    /// Raw line without new line character.
+    pub line: String,
+}
+
+/// Summary of a part of the file parsed by a single thread.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkSummary {
+    pub start_idx: u64,
+    pub lines: u64,
+    pub bad_lines: u64,
+    /// Number of bad lines per error kind.
+    pub error_kinds: BTreeMap<String, u64>,
+    /// Rest of the part if reading was interrupted.
+    pub unread_range: Option<UnreadRange>,
+    /// Bytes of the part which couldn't be read even after retries.
+    pub skipped_ranges: Vec<FailedRange>,
+}
+
+/// Quarantine file for lines which couldn't be parsed, so they can be reprocessed later.
+///
+/// Threads don't know line numbers of their parts of the file, so every thread writes into its
+/// own temporary file with line numbers relative to the part and [`Quarantine::merge`] fixes
+/// them once number of lines in every part is known.
+///
+/// Files ending with an extension of a [`Codec`] (e.g. `rejected.jsonl.zst`) are compressed with
+/// its tool. With a size cap, lines go into numbered files once a file is full
+/// (`rejected.1.jsonl.zst`, `rejected.2.jsonl.zst`, ...).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "PathBuf")]
+pub struct Quarantine {
+    path: PathBuf,
+    max_bytes: Option<u64>,
+    max_files: Option<usize>,
+}
+
+impl From<PathBuf> for Quarantine {
+    fn from(path: PathBuf) -> Self {
+        Self::new(path)
+    }
+}
+
+impl Quarantine {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes: None,
+            max_files: None,
+        }
+    }
+
+    /// Start a new file once the file reaches the size (uncompressed).
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Keep at most this many files, further lines are only counted once they are full.
+    pub fn max_files(mut self, max_files: usize) -> Self {
+        self.max_files = Some(max_files);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writer for the part of the file starting at given index.
+    pub(crate) fn chunk(&self, source: &Path, start_idx: u64) -> QuarantineChunk {
+        // leftover of a failed attempt of the same part
+        self.discard(start_idx);
+        QuarantineChunk {
+            path: self.chunk_path(start_idx),
+            source: source.to_string_lossy().into_owned(),
+            writer: None,
+        }
+    }
+
+    /// Merge temporary files of all parts into the quarantine files, returns number of bad
+    /// lines written.
+    pub(crate) fn merge(&self, chunks: &[ChunkSummary]) -> u64 {
+        let mut chunks = chunks.to_vec();
+        chunks.sort_by_key(|chunk| chunk.start_idx);
+
+        let codec = Codec::from_extension(&self.path);
+        if let Some(codec) = codec {
+            decompress::check_enabled(codec).unwrap_or_else(|err| panic!("{}", err));
+        }
+        // numbered files of a previous run would look like a part of this one
+        (1..)
+            .map(|idx| self.file_path(idx))
+            .take_while(|path| path.exists())
+            .for_each(|path| fs::remove_file(path).expect("Can't remove old quarantine file"));
+        let mut writer = RotatingWriter::new(self, codec);
+
+        // number of lines in all previous parts
+        let mut lines_before = 0;
+        let mut bad_lines = 0;
+        for chunk in chunks {
+            let chunk_path = self.chunk_path(chunk.start_idx);
+            // oversize lines are failed lines which aren't always quarantined
+            if chunk.bad_lines > 0 && chunk_path.exists() {
+                let chunk_file = File::open(&chunk_path).expect("Can't open quarantine part");
+                for entry in BufReader::new(chunk_file).lines() {
+                    let entry = entry.expect("Unexpected I/O error");
+                    let mut quarantined: QuarantinedLine =
+                        serde_json::from_str(&entry).expect("Corrupted quarantine part");
+                    quarantined.line_number += lines_before;
+                    if writer.write(&quarantined) {
+                        bad_lines += 1;
+                    }
+                }
+                fs::remove_file(&chunk_path).expect("Can't remove quarantine part");
+            }
+            lines_before += chunk.lines;
+        }
+
+        writer.finish();
+        bad_lines
+    }
+
+    /// Remove temporary file of a part which won't be merged.
+    pub(crate) fn discard(&self, start_idx: u64) {
+        // the file exists only if the part had quarantined lines
+        let _ = fs::remove_file(self.chunk_path(start_idx));
+    }
+
+    /// Path of the file with given number, the first one is the quarantine path itself.
+    fn file_path(&self, idx: usize) -> PathBuf {
+        if idx == 0 {
+            return self.path.clone();
+        }
+        let file_name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let file_name = match file_name.split_once('.') {
+            Some((stem, extensions)) => format!("{}.{}.{}", stem, idx, extensions),
+            None => format!("{}.{}", file_name, idx),
+        };
+        self.path.with_file_name(file_name)
+    }
+
+    fn chunk_path(&self, start_idx: u64) -> PathBuf {
+        let mut file_name = self.path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(format!(".{}.part", start_idx));
+        self.path.with_file_name(file_name)
+    }
+}
+
+/// Quarantine writer of a single thread (file is created with the first bad line).
+pub(crate) struct QuarantineChunk {
+    path: PathBuf,
+    source: String,
+    writer: Option<BufWriter<File>>,
+}
+
+impl QuarantineChunk {
+    /// Write bad line, line number is relative to the part of the file.
+    pub(crate) fn write(&mut self, line_number: u64, line: &str, error: &serde_json::Error) {
+        let path = &self.path;
+        let writer = self.writer.get_or_insert_with(|| {
+            BufWriter::new(File::create(path).expect("Can't create quarantine part"))
+        });
+        let quarantined = QuarantinedLine {
+            source: self.source.clone(),
+            line_number,
+            reason: ErrorClass::of(&error_log::kind(error)),
+            error: error.to_string(),
+            line: line.trim_end_matches(['\n', '\r']).to_string(),
+        };
+        write_line(writer, &quarantined);
+    }
+}
+
+impl Drop for QuarantineChunk {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush().expect("Can't write quarantine part");
+        }
+    }
+}
+
+/// Writer of quarantine files which starts a new file once the current one is full. Files are
+/// written uncompressed and compressed once complete.
+struct RotatingWriter<'a> {
+    quarantine: &'a Quarantine,
+    codec: Option<Codec>,
+    /// Number of the current file.
+    idx: usize,
+    writer: Option<BufWriter<File>>,
+    file_bytes: u64,
+    dropped: u64,
+}
+
+impl<'a> RotatingWriter<'a> {
+    fn new(quarantine: &'a Quarantine, codec: Option<Codec>) -> Self {
+        let mut writer = Self {
+            quarantine,
+            codec,
+            idx: 0,
+            writer: None,
+            file_bytes: 0,
+            dropped: 0,
+        };
+        // the quarantine file exists even without bad lines
+        writer.open();
+        writer
+    }
+
+    /// Write the line, returns false if it's dropped because all files are full.
+    fn write(&mut self, quarantined: &QuarantinedLine) -> bool {
+        let mut line = serde_json::to_vec(quarantined).expect("Can't serialize quarantined line");
+        line.push(b'\n');
+        let is_full = match self.quarantine.max_bytes {
+            Some(max_bytes) => {
+                self.file_bytes > 0 && self.file_bytes + line.len() as u64 > max_bytes
+            }
+            None => false,
+        };
+        if is_full {
+            self.close();
+            if self
+                .quarantine
+                .max_files
+                .is_some_and(|max| self.idx + 1 >= max)
+            {
+                self.dropped += 1;
+                return false;
+            }
+            self.idx += 1;
+            self.open();
+        }
+
+        let writer = self.writer.as_mut().expect("Quarantine file is closed");
+        writer
+            .write_all(&line)
+            .expect("Can't write quarantine file");
+        self.file_bytes += line.len() as u64;
+        true
+    }
+
+    fn finish(mut self) {
+        self.close();
+        if self.dropped > 0 {
+            warn!(
+                "Quarantine files are full, {} bad lines were only counted",
+                self.dropped
+            );
+        }
+    }
+
+    /// Path the current file is written to, the compressed file is created next to it.
+    fn plain_path(&self) -> PathBuf {
+        let path = self.quarantine.file_path(self.idx);
+        match self.codec {
+            Some(_) => {
+                let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+                file_name.push(".uncompressed");
+                path.with_file_name(file_name)
+            }
+            None => path,
+        }
+    }
+
+    fn open(&mut self) {
+        let file = File::create(self.plain_path()).expect("Can't create quarantine file");
+        self.writer = Some(BufWriter::new(file));
+        self.file_bytes = 0;
+    }
+
+    fn close(&mut self) {
+        let mut writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => return,
+        };
+        writer.flush().expect("Can't write quarantine file");
+        drop(writer);
+        if let Some(codec) = self.codec {
+            let plain_path = self.plain_path();
+            decompress::compress(codec, &plain_path, self.quarantine.file_path(self.idx))
+                .unwrap_or_else(|err| panic!("Can't compress quarantine file: {}", err));
+            fs::remove_file(plain_path).expect("Can't remove uncompressed quarantine file");
+        }
+    }
+}
+
+fn write_line<W: Write>(writer: &mut W, quarantined: &QuarantinedLine) {
+    serde_json::to_writer(&mut *writer, quarantined).expect("Can't serialize quarantined line");
+    writer
+        .write_all(b"\n")
+        .expect("Can't write quarantine file");
+}
@@ -0,0 +1,148 @@
+use crate::parser::LogRegister;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of lines and errors in a time window.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct WindowCount {
+    pub lines: u64,
+    pub errors: u64,
+}
+
+/// Start of the window the timestamp falls into, in seconds since epoch.
+pub fn window_start(timestamp: SystemTime, window_secs: u64) -> u64 {
+    let secs = timestamp
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+    secs - secs % window_secs.max(1)
+}
+
+/// Rates of a single time window.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WindowRate {
+    /// Start of the window in seconds since epoch.
+    pub start: u64,
+    pub lines: u64,
+    pub lines_per_sec: f64,
+    /// Fraction of lines which are errors.
+    pub error_rate: f64,
+    /// Set by [`flag_anomalies`] if the lines deviate from the trailing average.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anomaly: Option<Anomaly>,
+}
+
+/// Flags windows whose lines deviate from the average of the previous windows by more than
+/// `max_stddevs` standard deviations or by more than `max_change` of the average (any of the set
+/// thresholds).
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AnomalyDetector {
+    /// Number of previous windows averaged, windows with fewer than two previous windows are
+    /// never flagged.
+    pub trailing_windows: usize,
+    pub max_stddevs: Option<f64>,
+    /// Fraction of the trailing average, e.g. 0.5 flags windows with 50% more or fewer lines.
+    pub max_change: Option<f64>,
+}
+
+impl AnomalyDetector {
+    pub const DEFAULT_TRAILING_WINDOWS: usize = 6;
+}
+
+/// Deviation of a window from the previous windows.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Anomaly {
+    /// Average lines of the trailing windows.
+    pub trailing_average: f64,
+    /// Standard deviations from the trailing average, with at least the square root of the
+    /// average as deviation so steady counts aren't flagged for a few lines more.
+    pub stddevs: f64,
+    /// Change relative to the trailing average, e.g. 2.0 for three times the lines.
+    pub change: f64,
+}
+
+/// Rate series of a register, from the first to the last window with logs. Windows without logs
+/// in between are included with zero rates, so gaps are visible as well as spikes.
+pub fn series(register: &LogRegister, window_secs: u64) -> Vec<WindowRate> {
+    let window_secs = window_secs.max(1);
+    let (first, last) = match (
+        register.windows.keys().next(),
+        register.windows.keys().next_back(),
+    ) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return vec![],
+    };
+
+    (first..=last)
+        .step_by(window_secs as usize)
+        .map(|start| {
+            let count = register.windows.get(&start).copied().unwrap_or_default();
+            WindowRate {
+                start,
+                lines: count.lines,
+                lines_per_sec: count.lines as f64 / window_secs as f64,
+                error_rate: if count.lines == 0 {
+                    0.0
+                } else {
+                    count.errors as f64 / count.lines as f64
+                },
+                anomaly: None,
+            }
+        })
+        .collect()
+}
+
+/// Average lines per second over the windows from the first to the last one with logs.
+pub fn lines_per_sec(register: &LogRegister, window_secs: u64) -> f64 {
+    let window_secs = window_secs.max(1);
+    let span = match (
+        register.windows.keys().next(),
+        register.windows.keys().next_back(),
+    ) {
+        (Some(first), Some(last)) => Duration::from_secs(last - first + window_secs),
+        _ => return 0.0,
+    };
+    let lines: u64 = register.windows.values().map(|count| count.lines).sum();
+
+    lines as f64 / span.as_secs_f64()
+}
+
+/// Flag windows of the series which are anomalies, so the first flagged window shows when a spike
+/// or a drop started.
+pub fn flag_anomalies(series: &mut [WindowRate], detector: &AnomalyDetector) {
+    let trailing = detector.trailing_windows.max(2);
+    for idx in 2..series.len() {
+        let previous = &series[idx.saturating_sub(trailing)..idx];
+        let average =
+            previous.iter().map(|rate| rate.lines as f64).sum::<f64>() / previous.len() as f64;
+        let variance = previous
+            .iter()
+            .map(|rate| (rate.lines as f64 - average).powi(2))
+            .sum::<f64>()
+            / previous.len() as f64;
+        let deviation = series[idx].lines as f64 - average;
+        let ratio = |deviation: f64, of: f64| {
+            if deviation == 0.0 {
+                0.0
+            } else {
+                deviation / of
+            }
+        };
+        let anomaly = Anomaly {
+            trailing_average: average,
+            // counts of random events vary by the square root of their average
+            stddevs: ratio(deviation, variance.sqrt().max(average.sqrt())),
+            change: ratio(deviation, average),
+        };
+
+        let beyond = |threshold: Option<f64>, value: f64| {
+            threshold.is_some_and(|threshold| value.abs() > threshold)
+        };
+        if beyond(detector.max_stddevs, anomaly.stddevs)
+            || beyond(detector.max_change, anomaly.change)
+        {
+            series[idx].anomaly = Some(anomaly);
+        }
+    }
+}
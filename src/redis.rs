@@ -0,0 +1,305 @@
+use crate::parser::{ParseOptions, ParseReport, ParsedRecord};
+use crate::sink::Sink;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// Default port of Redis servers.
+pub const DEFAULT_PORT: u16 = 6379;
+
+/// Prefix of keys if not set in the URL.
+pub const DEFAULT_PREFIX: &str = "log-parser";
+
+/// Timeout of connecting, reading and writing.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `redis://[:password@]host[:port][/db][?prefix=<prefix>&ttl=<duration>]` URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedisUrl {
+    pub host: String,
+    pub port: u16,
+    pub password: Option<String>,
+    pub db: Option<u32>,
+    pub prefix: String,
+    /// Keys expire after this time since the last update.
+    pub ttl: Option<Duration>,
+}
+
+impl RedisUrl {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("redis://")
+            .ok_or_else(|| format!("URL should start with redis://: [{}]", url))?;
+        let (rest, query) = match rest.split_once('?') {
+            Some((rest, query)) => (rest, query),
+            None => (rest, ""),
+        };
+        let (authority, db) = match rest.split_once('/') {
+            Some((authority, "")) => (authority, None),
+            Some((authority, db)) => (
+                authority,
+                Some(
+                    db.parse()
+                        .map_err(|_| format!("Invalid database in URL: [{}]", url))?,
+                ),
+            ),
+            None => (rest, None),
+        };
+        let (password, address) = match authority.rsplit_once('@') {
+            // user names are not supported, only `:password@`
+            Some((credentials, address)) => (
+                Some(credentials.trim_start_matches(':').to_string()),
+                address,
+            ),
+            None => (None, authority),
+        };
+        let (host, port) = match address.rfind(':') {
+            Some(idx) => (
+                &address[..idx],
+                address[idx + 1..]
+                    .parse()
+                    .map_err(|_| format!("Invalid port in URL: [{}]", url))?,
+            ),
+            None => (address, DEFAULT_PORT),
+        };
+        if host.is_empty() {
+            return Err(format!("URL without host: [{}]", url));
+        }
+
+        let mut prefix = DEFAULT_PREFIX.to_string();
+        let mut ttl = None;
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            match param.split_once('=') {
+                Some(("prefix", value)) if !value.is_empty() => prefix = value.to_string(),
+                Some(("ttl", value)) => {
+                    let value = humantime::parse_duration(value)
+                        .map_err(|err| format!("Invalid ttl in URL [{}]: {}", url, err))?;
+                    if value.as_secs() == 0 {
+                        return Err(format!("TTL should be at least 1s: [{}]", url));
+                    }
+                    ttl = Some(value);
+                }
+                _ => return Err(format!("Unknown parameter [{}] in URL: [{}]", param, url)),
+            }
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            password: password.filter(|password| !password.is_empty()),
+            db,
+            prefix,
+            ttl,
+        })
+    }
+}
+
+/// Connection sending commands in pipelines (RESP protocol, no TLS).
+pub struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    /// Connect, authenticate and select the database of the URL.
+    pub fn connect(url: &RedisUrl) -> io::Result<Self> {
+        let stream = TcpStream::connect((url.host.as_str(), url.port))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut connection = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        };
+
+        let mut commands = vec![];
+        if let Some(password) = &url.password {
+            commands.push(vec!["AUTH".to_string(), password.clone()]);
+        }
+        if let Some(db) = url.db {
+            commands.push(vec!["SELECT".to_string(), db.to_string()]);
+        }
+        connection.pipeline(&commands)?;
+
+        Ok(connection)
+    }
+
+    /// Send commands at once and read their replies, the first error reply fails the pipeline
+    /// (commands are not a transaction, the other ones are applied).
+    pub fn pipeline(&mut self, commands: &[Vec<String>]) -> io::Result<Vec<Reply>> {
+        for command in commands {
+            write!(self.writer, "*{}\r\n", command.len())?;
+            for arg in command {
+                write!(self.writer, "${}\r\n{}\r\n", arg.len(), arg)?;
+            }
+        }
+        self.writer.flush()?;
+
+        let mut replies = Vec::with_capacity(commands.len());
+        let mut error = None;
+        for _ in commands {
+            match read_reply(&mut self.reader)? {
+                Reply::Error(message) => {
+                    error.get_or_insert(message);
+                }
+                reply => replies.push(reply),
+            }
+        }
+        match error {
+            Some(message) => Err(io::Error::other(format!("Redis error: {}", message))),
+            None => Ok(replies),
+        }
+    }
+}
+
+/// Reply of a command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Status(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Reply>>),
+}
+
+fn read_reply<R: BufRead>(reader: &mut R) -> io::Result<Reply> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Redis connection is closed",
+        ));
+    }
+    let line = line.trim_end_matches(['\r', '\n']);
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid reply [{}]", line),
+        )
+    };
+    let (kind, value) = line.split_at(line.len().min(1));
+    match kind {
+        "+" => Ok(Reply::Status(value.to_string())),
+        "-" => Ok(Reply::Error(value.to_string())),
+        ":" => value.parse().map(Reply::Integer).map_err(|_| invalid()),
+        "$" => {
+            let size: i64 = value.parse().map_err(|_| invalid())?;
+            if size < 0 {
+                return Ok(Reply::Bulk(None));
+            }
+            let mut bulk = vec![0; size as usize + 2];
+            reader.read_exact(&mut bulk)?;
+            bulk.truncate(size as usize);
+            Ok(Reply::Bulk(Some(bulk)))
+        }
+        "*" => {
+            let size: i64 = value.parse().map_err(|_| invalid())?;
+            if size < 0 {
+                return Ok(Reply::Array(None));
+            }
+            let items = (0..size)
+                .map(|_| read_reply(reader))
+                .collect::<io::Result<_>>()?;
+            Ok(Reply::Array(Some(items)))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Increments per log type counters of every report, so other services can read near real time
+/// stats. Hashes `<prefix>:counter`, `<prefix>:bytes` and `<prefix>:errors` map log types (or
+/// groups) to their totals, sorted sets `<prefix>:by_counter` and `<prefix>:by_bytes` rank them,
+/// and hash `<prefix>:totals` counts `lines` and `failed_lines`. Every report is added to the
+/// totals, records are not written (stats only).
+pub struct RedisSink {
+    connection: Connection,
+    prefix: String,
+    ttl: Option<Duration>,
+}
+
+impl RedisSink {
+    pub fn open(url: &RedisUrl) -> io::Result<Self> {
+        Ok(Self {
+            connection: Connection::connect(url)?,
+            prefix: url.prefix.clone(),
+            ttl: url.ttl,
+        })
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}:{}", self.prefix, name)
+    }
+}
+
+impl Sink for RedisSink {
+    fn write_report(
+        &mut self,
+        _input_file: &str,
+        report: &ParseReport,
+        _options: &ParseOptions,
+    ) -> io::Result<()> {
+        let command =
+            |args: &[&str]| -> Vec<String> { args.iter().map(|arg| arg.to_string()).collect() };
+        let totals = self.key("totals");
+        let mut commands = vec![
+            command(&["HINCRBY", &totals, "lines", &report.lines.to_string()]),
+            command(&[
+                "HINCRBY",
+                &totals,
+                "failed_lines",
+                &report.failed_lines.to_string(),
+            ]),
+        ];
+        for (log_type, elem) in &report.log_register {
+            let counter = elem.counter.to_string();
+            let bytes = elem.num_of_bytes.to_string();
+            commands.push(command(&[
+                "HINCRBY",
+                &self.key("counter"),
+                log_type,
+                &counter,
+            ]));
+            commands.push(command(&["HINCRBY", &self.key("bytes"), log_type, &bytes]));
+            commands.push(command(&[
+                "HINCRBY",
+                &self.key("errors"),
+                log_type,
+                &elem.errors.to_string(),
+            ]));
+            commands.push(command(&[
+                "ZINCRBY",
+                &self.key("by_counter"),
+                &counter,
+                log_type,
+            ]));
+            commands.push(command(&[
+                "ZINCRBY",
+                &self.key("by_bytes"),
+                &bytes,
+                log_type,
+            ]));
+        }
+        if let Some(ttl) = self.ttl {
+            let ttl = ttl.as_secs().to_string();
+            for name in [
+                "totals",
+                "counter",
+                "bytes",
+                "errors",
+                "by_counter",
+                "by_bytes",
+            ] {
+                commands.push(command(&["EXPIRE", &self.key(name), &ttl]));
+            }
+        }
+
+        self.connection.pipeline(&commands).map(|_| ())
+    }
+
+    fn write_record(&mut self, _record: &ParsedRecord, _options: &ParseOptions) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+use crate::error_log::{self, ErrorClass};
+use crate::parser::{self, ParseOptions, ParseReport};
+use crate::quarantine::QuarantinedLine;
+use crate::state::SavedState;
+use log::warn;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::io::BufRead;
+
+/// Rules fixing quarantined lines before they are parsed again, e.g. once it's known the producer
+/// sent `kind` instead of `type`. Lines which aren't JSON objects are left as they are (they can
+/// be parsed with a line pattern instead).
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    /// Top level fields renamed as `(from, to)`, replacing fields with the new name.
+    pub renames: Vec<(String, String)>,
+    /// Type of logs without `type` field.
+    pub default_type: Option<String>,
+    /// Non-string `type` and `level` values are taken as their JSON text, e.g. `5` for `"type": 5`.
+    pub coerce_types: bool,
+}
+
+impl Overrides {
+    /// Parse rename written as `<from>=<to>`.
+    pub fn parse_rename(rename: &str) -> Result<(String, String), String> {
+        match rename.split_once('=') {
+            Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+                Ok((from.to_string(), to.to_string()))
+            }
+            _ => Err(format!(
+                "Invalid rename [{}], expected <from>=<to>, e.g. kind=type",
+                rename
+            )),
+        }
+    }
+
+    /// Line with the overrides applied.
+    pub fn apply<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let mut object = match serde_json::from_str(line) {
+            Ok(Value::Object(object)) => object,
+            _ => return Cow::Borrowed(line),
+        };
+        for (from, to) in &self.renames {
+            if let Some(value) = object.remove(from) {
+                object.insert(to.clone(), value);
+            }
+        }
+        if self.coerce_types {
+            for field in ["type", "level"] {
+                if let Some(value) = object.get_mut(field) {
+                    if !value.is_string() && !value.is_null() {
+                        *value = Value::String(value.to_string());
+                    }
+                }
+            }
+        }
+        if let Some(default_type) = &self.default_type {
+            if object.get("type").is_none_or(Value::is_null) {
+                object.insert("type".to_string(), Value::String(default_type.clone()));
+            }
+        }
+
+        Cow::Owned(Value::Object(object).to_string())
+    }
+}
+
+/// Counts of a re-ingested quarantine file.
+#[derive(Debug, Default, PartialEq)]
+pub struct Reingested {
+    pub lines: u64,
+    /// Lines parsed with the overrides and merged into the state.
+    pub recovered: u64,
+    /// Lines which still can't be parsed.
+    pub failing: u64,
+}
+
+/// Parse lines of the quarantine file with the overrides and merge the recovered ones into the
+/// state the file was quarantined with: they are added to the registers and no longer counted as
+/// failed lines (or under their error kind), lines of the state stay the same. Lines which still
+/// fail are passed to `failing` with their new error. The quarantine file is recorded as an input
+/// of the state, so it can't be merged twice.
+///
+/// `options` are parse options of the state, with a line pattern for lines in another format.
+pub fn reingest<R: BufRead, F: FnMut(QuarantinedLine)>(
+    source: &str,
+    reader: R,
+    state: &mut SavedState,
+    options: &ParseOptions,
+    overrides: &Overrides,
+    mut failing: F,
+) -> Result<Reingested, String> {
+    let input = format!("{} (reingested)", source);
+    if state.inputs.contains(&input) {
+        return Err(format!("{} was already reingested into the state", source));
+    }
+    // options the lines were quarantined with, to find the error kinds they were counted under
+    let state_options = state.options.parse_options();
+    let mut unknown_sources = HashSet::new();
+
+    let mut reingested = Reingested::default();
+    let mut recovered = ParseReport::default();
+    let mut recovered_kinds: BTreeMap<String, u64> = BTreeMap::new();
+    for entry in reader.lines() {
+        let entry = entry.map_err(|err| format!("Can't read {}: {}", source, err))?;
+        let quarantined: QuarantinedLine = serde_json::from_str(&entry)
+            .map_err(|err| format!("Invalid quarantined line in {}: {}", source, err))?;
+        reingested.lines += 1;
+        if !state.inputs.contains(&quarantined.source)
+            && unknown_sources.insert(quarantined.source.clone())
+        {
+            warn!(
+                "Lines of {} weren't parsed into the state, they are counted as failed lines \
+                 of other inputs",
+                quarantined.source
+            );
+        }
+
+        let line = overrides.apply(&quarantined.line);
+        // the line was read with its new line character
+        let num_of_bytes = quarantined.line.len() as u64 + 1;
+        match recovered.add_line_of_size(&line, num_of_bytes, options) {
+            Ok(()) => {
+                reingested.recovered += 1;
+                if let Err(err) = parser::parse_line(&quarantined.line, &state_options) {
+                    *recovered_kinds.entry(error_log::kind(&err)).or_default() += 1;
+                }
+            }
+            Err(err) => {
+                reingested.failing += 1;
+                failing(QuarantinedLine {
+                    reason: ErrorClass::of(&error_log::kind(&err)),
+                    error: err.to_string(),
+                    ..quarantined
+                });
+            }
+        }
+    }
+
+    // lines (failed ones included) are already counted by the state
+    recovered.lines = 0;
+    recovered.failed_lines = 0;
+    recovered.error_kinds.clear();
+    state.report += recovered;
+    state.report.failed_lines = state
+        .report
+        .failed_lines
+        .saturating_sub(reingested.recovered);
+    for (kind, count) in recovered_kinds {
+        if let Some(kind_count) = state.report.error_kinds.get_mut(&kind) {
+            *kind_count = kind_count.saturating_sub(count);
+            if *kind_count == 0 {
+                state.report.error_kinds.remove(&kind);
+            }
+        }
+    }
+    state.inputs.push(input);
+
+    Ok(reingested)
+}
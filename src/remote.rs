@@ -0,0 +1,116 @@
+//! Objects of `s3://` and `gs://` inputs on object_store. Credentials come from its chain (e.g.
+//! `AWS_*` variables, web identity or instance metadata for S3, `GOOGLE_*` variables or
+//! application default credentials for GCS) and the object is read in ranges, so parsing starts
+//! with the first range while the next ones are fetched.
+
+use crate::decompress::Storage;
+use bytes::Bytes;
+use object_store::path::Path;
+use object_store::{GetOptions, GetRange, ObjectStore};
+use std::io::{self, Read};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use tokio::runtime::{Builder, Runtime};
+
+/// Size of ranges the object is fetched in.
+pub const RANGE_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Ranges fetched ahead of the reader.
+const PREFETCHED_RANGES: usize = 2;
+
+/// Content of the object, the read of a range which couldn't be fetched fails.
+pub struct ObjectReader {
+    ranges: Receiver<io::Result<Bytes>>,
+    range: Bytes,
+}
+
+/// Open the object of the URL, a missing object (or bucket, or credentials) fails here.
+pub fn open(url: &str, storage: Storage) -> io::Result<ObjectReader> {
+    let failure =
+        |err: &dyn std::fmt::Display| io::Error::other(format!("Can't fetch {}: {}", url, err));
+    let (bucket, key) = url
+        .split_once("://")
+        .and_then(|(_, object)| object.split_once('/'))
+        .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+        .ok_or_else(|| {
+            let format = match storage {
+                Storage::S3 => "s3://bucket/key",
+                Storage::Gcs => "gs://bucket/object",
+            };
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} should be {}", url, format),
+            )
+        })?;
+    let store = object_store(storage, bucket).map_err(|err| failure(&err))?;
+    let path = Path::from(key);
+    let runtime = runtime()?;
+    let meta = runtime
+        .block_on(store.head(&path))
+        .map_err(|err| failure(&err))?;
+
+    let (sender, ranges) = mpsc::sync_channel(PREFETCHED_RANGES);
+    let url = url.to_string();
+    thread::spawn(move || {
+        for start in (0..meta.size).step_by(RANGE_SIZE as usize) {
+            let options = GetOptions {
+                range: Some(GetRange::Bounded(start..meta.size.min(start + RANGE_SIZE))),
+                // the object can't change between the ranges
+                if_match: meta.e_tag.clone(),
+                ..GetOptions::default()
+            };
+            let range = runtime
+                .block_on(async { store.get_opts(&path, options).await?.bytes().await })
+                .map_err(|err| io::Error::other(format!("Can't fetch {}: {}", url, err)));
+            let failed = range.is_err();
+            // the reader is dropped before the end
+            if sender.send(range).is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    Ok(ObjectReader {
+        ranges,
+        range: Bytes::new(),
+    })
+}
+
+fn object_store(storage: Storage, bucket: &str) -> object_store::Result<Arc<dyn ObjectStore>> {
+    Ok(match storage {
+        #[cfg(feature = "s3")]
+        Storage::S3 => Arc::new(
+            object_store::aws::AmazonS3Builder::from_env()
+                .with_bucket_name(bucket)
+                .build()?,
+        ),
+        #[cfg(feature = "gcs")]
+        Storage::Gcs => Arc::new(
+            object_store::gcp::GoogleCloudStorageBuilder::from_env()
+                .with_bucket_name(bucket)
+                .build()?,
+        ),
+        #[allow(unreachable_patterns)]
+        _ => unreachable!("Storage is enabled"),
+    })
+}
+
+fn runtime() -> io::Result<Runtime> {
+    Builder::new_current_thread().enable_all().build()
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.range.is_empty() {
+            match self.ranges.recv() {
+                Ok(range) => self.range = range?,
+                // all ranges were read
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.range.len());
+        buf[..len].copy_from_slice(&self.range.split_to(len));
+        Ok(len)
+    }
+}
@@ -0,0 +1,94 @@
+use crate::budget::BudgetTracker;
+use crate::clock::Clock;
+use crate::error_log;
+use crate::platform;
+use crate::sink::LineSink;
+use crate::timestamp;
+use log::error;
+use serde_json::Value;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Summary of replayed records.
+#[derive(Debug, Default)]
+pub struct ReplaySummary {
+    pub sent: u64,
+    pub skipped: u64,
+    /// Budget alerts raised while replaying.
+    pub alerts: u64,
+    pub elapsed: Duration,
+}
+
+/// Re-emit parsed records to the sink keeping original pacing of the timestamp field.
+///
+/// Delays between records are divided by `speed` (e.g. 2.0 replays twice as fast), speed 0
+/// sends records as fast as possible. Records without timestamp are sent right after the
+/// previous record and lines which aren't JSON are skipped.
+///
+/// Budget alerts are logged as soon as the projected daily volume of a log type exceeds its
+/// budget, records are projected by their timestamp (time of sending if they have none).
+///
+/// The clock paces the records, so a [`ManualClock`](crate::clock::ManualClock) replays them
+/// right away with the same summary.
+pub fn replay<P: AsRef<Path>>(
+    input_file: P,
+    sink: &mut dyn LineSink,
+    timestamp_field: &str,
+    speed: f64,
+    budgets: &mut BudgetTracker,
+    clock: &dyn Clock,
+) -> io::Result<ReplaySummary> {
+    let file = platform::open_shared(input_file)?;
+    let mut buffered = BufReader::new(file);
+
+    let started = clock.instant();
+    let mut first_timestamp: Option<SystemTime> = None;
+    let mut summary = ReplaySummary::default();
+
+    let mut bytes = vec![];
+    while buffered.read_until(b'\n', &mut bytes)? > 0 {
+        let line = String::from_utf8_lossy(&bytes);
+        let record = line.trim_end_matches(['\n', '\r']);
+        match serde_json::from_str::<Value>(record) {
+            Ok(value) => {
+                let record_timestamp = value
+                    .get(timestamp_field)
+                    .and_then(|value| timestamp::parse_with(value, &[], clock));
+                if let (Some(record_timestamp), true) = (record_timestamp, speed > 0.0) {
+                    let first_timestamp = *first_timestamp.get_or_insert(record_timestamp);
+                    // records older than the first one are sent right away
+                    if let Ok(offset) = record_timestamp.duration_since(first_timestamp) {
+                        let due = offset.div_f64(speed);
+                        let elapsed = clock.instant().duration_since(started);
+                        if due > elapsed {
+                            clock.sleep(due - elapsed);
+                        }
+                    }
+                }
+
+                sink.send(record)?;
+                summary.sent += 1;
+
+                if let Some(log_type) = value.get("type").and_then(Value::as_str) {
+                    let time = record_timestamp.unwrap_or_else(|| clock.now());
+                    if let Some(alert) = budgets.observe(log_type, line.len() as u64, time) {
+                        error!("Budget alert: {}", alert);
+                        summary.alerts += 1;
+                    }
+                }
+            }
+            Err(err) => {
+                error_log::log(record, &err);
+                summary.skipped += 1;
+            }
+        }
+
+        // clear to reuse the buffer
+        bytes.clear();
+    }
+
+    sink.close()?;
+    summary.elapsed = clock.instant().duration_since(started);
+    Ok(summary)
+}
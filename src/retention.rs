@@ -0,0 +1,115 @@
+use crate::parser::LogRegister;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Rule matching every log type without its own rule.
+const DEFAULT_RULE: &str = "*";
+
+/// How long logs of given type are kept, e.g. `debug=7d` or `*=30d`.
+#[derive(Debug, Clone)]
+pub struct RetentionRule {
+    pub log_type: String,
+    pub retention: Duration,
+}
+
+impl RetentionRule {
+    /// Parse rule in `<log_type>=<duration>` format, `*` matches every other log type.
+    pub fn parse(rule: &str) -> Result<Self, String> {
+        let mut parts = rule.splitn(2, '=');
+        let log_type = parts.next().unwrap_or_default().trim();
+        let retention = parts
+            .next()
+            .ok_or_else(|| format!("Retention rule should be <type>=<duration>: [{}]", rule))?;
+        if log_type.is_empty() {
+            return Err(format!("Retention rule without log type: [{}]", rule));
+        }
+        let retention = humantime::parse_duration(retention.trim())
+            .map_err(|err| format!("Invalid retention in rule [{}]: {}", rule, err))?;
+
+        Ok(Self {
+            log_type: log_type.to_string(),
+            retention,
+        })
+    }
+}
+
+/// Retention simulation result of a single log type.
+#[derive(Serialize, Debug)]
+pub struct TypeRetention {
+    pub log_type: String,
+    /// Retention applied, `None` if no rule matches and logs are kept forever.
+    pub retention_secs: Option<u64>,
+    pub num_of_bytes: u64,
+    pub remaining_bytes: u64,
+    pub saved_bytes: u64,
+    /// Bytes stored at any time once retention is in effect (daily volume * retention).
+    pub steady_state_bytes: Option<u64>,
+}
+
+/// Retention simulation result for all log types.
+#[derive(Serialize, Debug)]
+pub struct RetentionSimulation {
+    pub num_of_bytes: u64,
+    pub remaining_bytes: u64,
+    pub saved_bytes: u64,
+    pub types: Vec<TypeRetention>,
+}
+
+/// Simulate how much of the analyzed data would remain if retention rules were applied.
+///
+/// Logs are assumed to be spread evenly over the analyzed window, so a log type with retention
+/// shorter than the window keeps only the newest `retention / window` part of its bytes.
+pub fn simulate<'a, I>(
+    register: I,
+    window: Duration,
+    rules: &[RetentionRule],
+) -> RetentionSimulation
+where
+    I: IntoIterator<Item = (&'a str, &'a LogRegister)>,
+{
+    let default_rule = rules.iter().find(|rule| rule.log_type == DEFAULT_RULE);
+
+    let types: Vec<TypeRetention> = register
+        .into_iter()
+        .map(|(log_type, value)| {
+            let rule = rules
+                .iter()
+                .find(|rule| rule.log_type == log_type)
+                .or(default_rule);
+            let num_of_bytes = value.num_of_bytes;
+            let bytes_per_sec = num_of_bytes as f64 / window.as_secs_f64();
+
+            match rule {
+                Some(rule) => {
+                    let kept = (rule.retention.as_secs_f64() / window.as_secs_f64()).min(1.0);
+                    let remaining_bytes = (num_of_bytes as f64 * kept) as u64;
+                    TypeRetention {
+                        log_type: log_type.to_string(),
+                        retention_secs: Some(rule.retention.as_secs()),
+                        num_of_bytes,
+                        remaining_bytes,
+                        saved_bytes: num_of_bytes - remaining_bytes,
+                        steady_state_bytes: Some(
+                            (bytes_per_sec * rule.retention.as_secs_f64()) as u64,
+                        ),
+                    }
+                }
+                None => TypeRetention {
+                    log_type: log_type.to_string(),
+                    retention_secs: None,
+                    num_of_bytes,
+                    remaining_bytes: num_of_bytes,
+                    saved_bytes: 0,
+                    steady_state_bytes: None,
+                },
+            }
+        })
+        .collect();
+
+    RetentionSimulation {
+        num_of_bytes: types.iter().map(|elem| elem.num_of_bytes).sum(),
+        remaining_bytes: types.iter().map(|elem| elem.remaining_bytes).sum(),
+        saved_bytes: types.iter().map(|elem| elem.saved_bytes).sum(),
+        types,
+    }
+}
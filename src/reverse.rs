@@ -0,0 +1,106 @@
+use crate::parser::ParseOptions;
+use crate::platform;
+use crate::preview::PreviewRecord;
+use crate::projection::Condition;
+use memchr::memrchr;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem;
+use std::path::Path;
+
+/// Bytes read at once from the end of the input.
+const BLOCK_LEN: u64 = 64 * 1024;
+
+/// Lines of the input from the last one to the first, read backwards in blocks so only the end of
+/// the input is read for the newest lines. Lines come with the offset of their start and without
+/// the new line character; the empty line after the last new line isn't a line.
+pub struct ReverseLines<R> {
+    reader: R,
+    /// Offset of the start of `buffer`, everything before it is unread.
+    start: u64,
+    /// Unread end of the line started before `start` (and of the input at first).
+    buffer: Vec<u8>,
+    /// No line ended at the end of the input yet.
+    at_end: bool,
+}
+
+impl<R: Read + Seek> ReverseLines<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let size = reader.seek(SeekFrom::End(0))?;
+        Ok(Self::before(reader, size))
+    }
+
+    /// Lines before the offset, which should be the start of a line.
+    pub fn before(reader: R, offset: u64) -> Self {
+        Self {
+            reader,
+            start: offset,
+            buffer: vec![],
+            at_end: true,
+        }
+    }
+
+    /// Previous line with the offset of its start, `None` before the first one.
+    pub fn prev_line(&mut self) -> io::Result<Option<(u64, Vec<u8>)>> {
+        loop {
+            if let Some(idx) = memrchr(b'\n', &self.buffer) {
+                let line = self.buffer.split_off(idx + 1);
+                self.buffer.truncate(idx);
+                if mem::take(&mut self.at_end) && line.is_empty() {
+                    continue;
+                }
+                return Ok(Some((self.start + idx as u64 + 1, line)));
+            }
+            if self.start == 0 {
+                self.at_end = false;
+                return Ok(Some(mem::take(&mut self.buffer))
+                    .filter(|line| !line.is_empty())
+                    .map(|line| (0, line)));
+            }
+
+            let block_start = self.start.saturating_sub(BLOCK_LEN);
+            let mut block = vec![0; (self.start - block_start) as usize];
+            self.reader.seek(SeekFrom::Start(block_start))?;
+            self.reader.read_exact(&mut block)?;
+            block.append(&mut self.buffer);
+            self.buffer = block;
+            self.start = block_start;
+        }
+    }
+}
+
+/// The newest `num_of_records` records of the input matching all conditions, newest first, read
+/// from the end of the input. Records are numbered from the end, blank lines and the CSV header are
+/// skipped and lines which can't be parsed only match without conditions.
+pub fn last_records<P: AsRef<Path>>(
+    path: P,
+    num_of_records: usize,
+    options: &ParseOptions,
+    conditions: &[Condition],
+) -> io::Result<Vec<PreviewRecord>> {
+    let has_header = options.csv.as_ref().is_some_and(|csv| csv.has_header);
+    let mut lines = ReverseLines::new(platform::open_shared(path)?)?;
+
+    let mut records = vec![];
+    while records.len() < num_of_records {
+        let (offset, line) = match lines.prev_line()? {
+            Some(line) => line,
+            None => break,
+        };
+        if offset == 0 && has_header {
+            break;
+        }
+        let record = match PreviewRecord::from_line(records.len() as u64 + 1, line, options) {
+            Some(record) => record,
+            None => continue,
+        };
+        let matches = conditions.iter().all(|condition| match &record.value {
+            Some(value) if record.error.is_none() => condition.matches(value.get(&condition.field)),
+            _ => false,
+        });
+        if matches {
+            records.push(record);
+        }
+    }
+
+    Ok(records)
+}
@@ -0,0 +1,336 @@
+use crate::parquet::{self, Column};
+use crate::table::{html_table, markdown_table};
+use crate::{timestamp, units};
+use log::warn;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Number of log types listed in the top growth column.
+pub const TOP_GROWTH: usize = 3;
+
+/// Totals of a single log type.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TypeTotals {
+    pub counter: u64,
+    pub num_of_bytes: u64,
+    pub errors: u64,
+}
+
+impl TypeTotals {
+    fn add(&mut self, other: &TypeTotals) {
+        self.counter += other.counter;
+        self.num_of_bytes += other.num_of_bytes;
+        self.errors += other.errors;
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        if self.counter == 0 {
+            return 0.0;
+        }
+        self.errors as f64 / self.counter as f64
+    }
+}
+
+/// Stats exported by `parse --output` for a single day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyExport {
+    /// Days since epoch.
+    pub day: u64,
+    pub types: BTreeMap<String, TypeTotals>,
+}
+
+impl DailyExport {
+    /// Load Parquet export, the day is taken from `YYYY-MM-DD` in the file name.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let day = path
+            .file_name()
+            .and_then(|name| day_from_name(&name.to_string_lossy()))
+            .ok_or_else(|| invalid(format!("No YYYY-MM-DD date in {}", path.display())))?;
+
+        let columns = parquet::read(path)?;
+        let column = |name: &str| {
+            columns
+                .iter()
+                .find(|(column_name, _)| column_name == name)
+                .map(|(_, column)| column)
+                .ok_or_else(|| invalid(format!("Missing column {} in {}", name, path.display())))
+        };
+        let int_column = |name: &str| match column(name)? {
+            Column::Int64(values) => Ok(values),
+            _ => Err(invalid(format!("Column {} should be integer", name))),
+        };
+        let log_types = match column("log_type")? {
+            Column::Utf8(values) => values,
+            _ => return Err(invalid("Column log_type should be text".to_string())),
+        };
+        let counters = int_column("counter")?;
+        let num_of_bytes = int_column("num_of_bytes")?;
+        let errors = int_column("errors")?;
+
+        let types = log_types
+            .iter()
+            .enumerate()
+            .map(|(idx, log_type)| {
+                (
+                    log_type.clone(),
+                    TypeTotals {
+                        counter: counters[idx] as u64,
+                        num_of_bytes: num_of_bytes[idx] as u64,
+                        errors: errors[idx] as u64,
+                    },
+                )
+            })
+            .collect();
+
+        Ok(Self { day, types })
+    }
+}
+
+/// Load all `.parquet` exports from directory, sorted by day.
+pub fn load_dir<P: AsRef<Path>>(dir: P) -> io::Result<Vec<DailyExport>> {
+    let mut exports = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_none_or(|extension| extension != "parquet")
+        {
+            continue;
+        }
+        match DailyExport::load(&path) {
+            Ok(export) => exports.push(export),
+            Err(err) => warn!("Skipping {}: {}", path.display(), err),
+        }
+    }
+    exports.sort_by_key(|export| export.day);
+
+    Ok(exports)
+}
+
+/// Find `YYYY-MM-DD` in the file name and convert it to days since epoch.
+fn day_from_name(name: &str) -> Option<u64> {
+    let date = Regex::new(r"\d{4}-\d{2}-\d{2}").unwrap();
+    let date = date.find(name)?.as_str();
+    let time = humantime::parse_rfc3339_weak(&format!("{} 00:00:00", date)).ok()?;
+
+    Some(time.duration_since(UNIX_EPOCH).ok()?.as_secs() / SECS_PER_DAY)
+}
+
+/// Format days since epoch as `YYYY-MM-DD`.
+fn format_day(day: u64) -> String {
+    let time = UNIX_EPOCH + Duration::from_secs(day * SECS_PER_DAY);
+    timestamp::format(time)[.."YYYY-MM-DD".len()].to_string()
+}
+
+/// Period daily exports are rolled up into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    /// Weeks starting on Monday.
+    Week,
+    Month,
+}
+
+impl Period {
+    /// Label of the period the day belongs to.
+    fn label(&self, day: u64) -> String {
+        match self {
+            // 1970-01-01 was Thursday
+            Period::Week => format_day(day - (day + 3) % 7),
+            Period::Month => format_day(day)[.."YYYY-MM".len()].to_string(),
+        }
+    }
+}
+
+/// Totals of a single week or month.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeriodRollup {
+    /// First day of the week or `YYYY-MM`.
+    pub label: String,
+    /// Number of daily exports in the period (the first and the last period can be partial).
+    pub days: u64,
+    pub totals: TypeTotals,
+    pub types: BTreeMap<String, TypeTotals>,
+}
+
+impl PeriodRollup {
+    /// Average daily volume, comparable between partial and full periods.
+    pub fn daily_bytes(&self) -> f64 {
+        self.totals.num_of_bytes as f64 / self.days.max(1) as f64
+    }
+
+    fn type_daily_bytes(&self, log_type: &str) -> f64 {
+        self.types.get(log_type).map_or(0.0, |totals| {
+            totals.num_of_bytes as f64 / self.days.max(1) as f64
+        })
+    }
+}
+
+/// Roll daily exports (sorted by day) up into weeks or months.
+pub fn rollup(exports: &[DailyExport], period: Period) -> Vec<PeriodRollup> {
+    let mut rollups: Vec<PeriodRollup> = Vec::new();
+    for export in exports {
+        let label = period.label(export.day);
+        if rollups.last().is_none_or(|last| last.label != label) {
+            rollups.push(PeriodRollup {
+                label,
+                days: 0,
+                totals: TypeTotals::default(),
+                types: BTreeMap::new(),
+            });
+        }
+        let current = rollups.last_mut().expect("Period was just added");
+        current.days += 1;
+        for (log_type, totals) in &export.types {
+            current.totals.add(totals);
+            current
+                .types
+                .entry(log_type.clone())
+                .or_default()
+                .add(totals);
+        }
+    }
+
+    rollups
+}
+
+/// Change of average daily volume of a log type between two periods.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Growth {
+    pub log_type: String,
+    pub previous_daily_bytes: f64,
+    pub daily_bytes: f64,
+}
+
+impl Growth {
+    fn describe(&self) -> String {
+        let change = self.daily_bytes - self.previous_daily_bytes;
+        let relative = if self.previous_daily_bytes > 0.0 {
+            format!(" ({:+.0}%)", change / self.previous_daily_bytes * 100.0)
+        } else {
+            " (new)".to_string()
+        };
+        format!(
+            "{} +{}/day{}",
+            self.log_type,
+            units::format_bytes(change),
+            relative
+        )
+    }
+}
+
+/// Log types with the biggest increase of average daily volume.
+pub fn top_growth(previous: &PeriodRollup, current: &PeriodRollup, limit: usize) -> Vec<Growth> {
+    let mut growth: Vec<Growth> = current
+        .types
+        .keys()
+        .map(|log_type| Growth {
+            log_type: log_type.clone(),
+            previous_daily_bytes: previous.type_daily_bytes(log_type),
+            daily_bytes: current.type_daily_bytes(log_type),
+        })
+        .filter(|growth| growth.daily_bytes > growth.previous_daily_bytes)
+        .collect();
+    growth.sort_by(|a, b| {
+        (b.daily_bytes - b.previous_daily_bytes)
+            .total_cmp(&(a.daily_bytes - a.previous_daily_bytes))
+    });
+    growth.truncate(limit);
+
+    growth
+}
+
+/// Report format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+/// Trend table per period and volume table per log type.
+pub fn render(rollups: &[PeriodRollup], format: Format) -> String {
+    let mut trend = vec![];
+    for (idx, current) in rollups.iter().enumerate() {
+        let previous = idx.checked_sub(1).map(|idx| &rollups[idx]);
+        let change = match previous {
+            Some(previous) if previous.daily_bytes() > 0.0 => format!(
+                "{:+.1}%",
+                (current.daily_bytes() / previous.daily_bytes() - 1.0) * 100.0
+            ),
+            _ => "-".to_string(),
+        };
+        let growth = previous
+            .map(|previous| {
+                top_growth(previous, current, TOP_GROWTH)
+                    .iter()
+                    .map(Growth::describe)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .filter(|growth| !growth.is_empty())
+            .unwrap_or_else(|| "-".to_string());
+        trend.push(vec![
+            current.label.clone(),
+            current.days.to_string(),
+            units::format_count(current.totals.counter),
+            units::format_bytes(current.totals.num_of_bytes as f64),
+            units::format_bytes(current.daily_bytes()),
+            change,
+            format!("{:.2}%", current.totals.error_rate() * 100.0),
+            growth,
+        ]);
+    }
+
+    let log_types: BTreeSet<&String> = rollups
+        .iter()
+        .flat_map(|rollup| rollup.types.keys())
+        .collect();
+    let volume: Vec<Vec<String>> = log_types
+        .iter()
+        .map(|log_type| {
+            let mut row = vec![log_type.to_string()];
+            row.extend(
+                rollups
+                    .iter()
+                    .map(|rollup| units::format_bytes(rollup.type_daily_bytes(log_type))),
+            );
+            row
+        })
+        .collect();
+    let mut volume_header = vec!["Log type".to_string()];
+    volume_header.extend(rollups.iter().map(|rollup| rollup.label.clone()));
+
+    let trend_header: Vec<String> = [
+        "Period",
+        "Days",
+        "Logs",
+        "Volume",
+        "Daily volume",
+        "Change",
+        "Error rate",
+        "Top growth",
+    ]
+    .iter()
+    .map(|column| column.to_string())
+    .collect();
+
+    match format {
+        Format::Markdown => format!(
+            "# Log volume trends\n\n{}\n## Daily volume per log type\n\n{}",
+            markdown_table(&trend_header, &trend),
+            markdown_table(&volume_header, &volume)
+        ),
+        Format::Html => format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Log volume trends</title></head>\n<body>\n<h1>Log volume trends</h1>\n{}<h2>Daily volume per log type</h2>\n{}</body>\n</html>\n",
+            html_table(&trend_header, &trend),
+            html_table(&volume_header, &volume)
+        ),
+    }
+}
@@ -0,0 +1,292 @@
+use crate::clock::Clock;
+use crate::decompress::Input;
+use crate::handle::{self, ParseHandle};
+use crate::interrupt::CancelToken;
+use crate::map_backend::MapBackend;
+use crate::normalize::Normalization;
+use crate::parser::{self, ParseOptions, ParseReport, Strategy};
+use crate::pattern::LinePattern;
+use crate::projection::Condition;
+use crate::timestamp::TimeLocale;
+use crate::tune;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Error of [`run`] and [`ParserOptionsBuilder::build`].
+#[derive(Debug)]
+pub enum RunError {
+    /// No input was given.
+    NoInput,
+    /// Option which can't be used, with the reason.
+    InvalidOption(String),
+    /// Input which can't be opened (or fetched, or decompressed), non-UTF-8 characters of its
+    /// path are replaced.
+    Open { input: String, source: io::Error },
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::NoInput => write!(f, "No input given"),
+            RunError::InvalidOption(reason) => write!(f, "Invalid option: {}", reason),
+            RunError::Open { input, source } => write!(f, "Can't open {}: {}", input, source),
+        }
+    }
+}
+
+impl Error for RunError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RunError::Open { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Inputs, threads and strategy of a [`run`] with the [`ParseOptions`] of every input, built with
+/// [`ParserOptions::builder`].
+#[derive(Debug, Clone)]
+pub struct ParserOptions {
+    inputs: Vec<PathBuf>,
+    num_of_threads: u8,
+    strategy: Strategy,
+    parse: ParseOptions,
+}
+
+impl ParserOptions {
+    pub fn builder() -> ParserOptionsBuilder {
+        ParserOptionsBuilder::default()
+    }
+
+    pub fn inputs(&self) -> &[PathBuf] {
+        &self.inputs
+    }
+
+    pub fn num_of_threads(&self) -> u8 {
+        self.num_of_threads
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    pub fn parse_options(&self) -> &ParseOptions {
+        &self.parse
+    }
+}
+
+/// Builder of [`ParserOptions`], the first invalid value is returned by
+/// [`ParserOptionsBuilder::build`]. Options without a method are set on the [`ParseOptions`]
+/// given to [`ParserOptionsBuilder::parse_options`].
+#[derive(Debug, Default)]
+pub struct ParserOptionsBuilder {
+    inputs: Vec<PathBuf>,
+    num_of_threads: Option<u8>,
+    strategy: Strategy,
+    parse: ParseOptions,
+    error: Option<RunError>,
+}
+
+impl ParserOptionsBuilder {
+    /// Input file (or URL of remote storage, compressed ones are decompressed like in the CLI),
+    /// can be called multiple times.
+    pub fn input<P: AsRef<Path>>(mut self, input: P) -> Self {
+        self.inputs.push(input.as_ref().to_path_buf());
+        self
+    }
+
+    /// Number of threads, the available parallelism by default.
+    pub fn threads(mut self, num_of_threads: u8) -> Self {
+        if num_of_threads == 0 {
+            self.fail("number of threads should be at least 1".to_string());
+        }
+        self.num_of_threads = Some(num_of_threads);
+        self
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Base options, set before the other options of the builder as it replaces them.
+    pub fn parse_options(mut self, options: ParseOptions) -> Self {
+        self.parse = options;
+        self
+    }
+
+    /// Field whose values group logs instead of the log type, can be called multiple times.
+    pub fn group_by<S: Into<String>>(mut self, field: S) -> Self {
+        self.parse.group_by.push(field.into());
+        self
+    }
+
+    /// Rules `<field>=<rule>[,<rule>...]` rewriting values of a group-by field before grouping,
+    /// can be called multiple times.
+    pub fn normalize(mut self, normalization: &str) -> Self {
+        match Normalization::parse(normalization) {
+            Ok(normalization) => self.parse.normalize.push(normalization),
+            Err(err) => self.fail(err),
+        }
+        self
+    }
+
+    /// Numeric field summed per log type (or group), can be called multiple times.
+    pub fn numeric_field<S: Into<String>>(mut self, field: S) -> Self {
+        self.parse.numeric_fields.push(field.into());
+        self
+    }
+
+    /// Field with distinct values counted per log type (or group), can be called multiple times.
+    pub fn count_distinct<S: Into<String>>(mut self, field: S) -> Self {
+        self.parse.distinct_fields.push(field.into());
+        self
+    }
+
+    pub fn timestamp_field<S: Into<String>>(mut self, field: S) -> Self {
+        self.parse.timestamp_field = Some(field.into());
+        self
+    }
+
+    /// Language of month names in timestamps besides English, e.g. `de`, can be called multiple
+    /// times.
+    pub fn timestamp_locale(mut self, locale: &str) -> Self {
+        match TimeLocale::from_name(locale) {
+            Ok(locale) => self.parse.timestamp_locales.push(locale),
+            Err(err) => self.fail(err),
+        }
+        self
+    }
+
+    /// Count lines and errors per window of the timestamp, requires a timestamp field.
+    pub fn rate_window(mut self, window: Duration) -> Self {
+        if window.as_secs() == 0 {
+            self.fail("rate window should be at least 1s".to_string());
+        }
+        self.parse.rate_window = Some(window.as_secs());
+        self
+    }
+
+    /// Condition `<field>=<value>` or `<field>!=<value>` making a log an error, can be called
+    /// multiple times (any has to match).
+    pub fn error_when(mut self, condition: &str) -> Self {
+        match Condition::parse(condition) {
+            Ok(condition) => self.parse.error_when.push(condition),
+            Err(err) => self.fail(err),
+        }
+        self
+    }
+
+    /// Regex with named groups parsing unstructured lines instead of JSON.
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        match LinePattern::new(pattern) {
+            Ok(pattern) => self.parse.pattern = Some(pattern),
+            Err(err) => self.fail(err),
+        }
+        self
+    }
+
+    pub fn map_backend(mut self, backend: MapBackend) -> Self {
+        self.parse.map_backend = Some(backend);
+        self
+    }
+
+    /// Clock of the current time and of waiting instead of the system one, e.g. a
+    /// [`ManualClock`](crate::clock::ManualClock) simulating time.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.parse.clock = Some(clock);
+        self
+    }
+
+    fn fail(&mut self, reason: String) {
+        self.error.get_or_insert(RunError::InvalidOption(reason));
+    }
+
+    pub fn build(self) -> Result<ParserOptions, RunError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if self.inputs.is_empty() {
+            return Err(RunError::NoInput);
+        }
+        let invalid = |reason: &str| Err(RunError::InvalidOption(reason.to_string()));
+        if self.parse.rate_window.is_some() && self.parse.timestamp_field.is_none() {
+            return invalid("rate window requires a timestamp field");
+        }
+        if self.strategy == Strategy::Single && self.parse.checkpoint.is_some() {
+            return invalid("checkpoints are saved by multi thread strategies only");
+        }
+        if matches!(self.strategy, Strategy::Single | Strategy::Channel)
+            && self.parse.map_backend.is_some()
+        {
+            return invalid("map backend is used by the dashmap and sharded strategies only");
+        }
+        let num_of_threads = self.num_of_threads.unwrap_or_else(tune::available_threads);
+
+        Ok(ParserOptions {
+            inputs: self.inputs,
+            num_of_threads,
+            strategy: self.strategy,
+            parse: self.parse,
+        })
+    }
+
+    /// Build the options and start the [`run`] on a background thread without waiting for the
+    /// report, e.g. in GUI or server embedders which may abort the run. Inputs are opened before
+    /// it starts, so errors are returned right away. Cancels with the token of the parse options
+    /// if they have one.
+    pub fn spawn(self) -> Result<ParseHandle, RunError> {
+        let mut options = self.build()?;
+        let inputs = open_inputs(&options)?;
+        let cancel = options
+            .parse
+            .cancel
+            .get_or_insert_with(CancelToken::new)
+            .clone();
+        Ok(handle::spawn(cancel, move || {
+            parse_inputs(&inputs, &options)
+        }))
+    }
+}
+
+/// Parse every input with the strategy and sum up their reports. Inputs are opened (fetched)
+/// before any is parsed, so a missing one fails the run without parsing the rest. Compressed ones
+/// are decompressed as they're parsed.
+pub fn run(options: &ParserOptions) -> Result<ParseReport, RunError> {
+    let inputs = open_inputs(options)?;
+    parse_inputs(&inputs, options)
+}
+
+fn open_inputs(options: &ParserOptions) -> Result<Vec<Input>, RunError> {
+    options
+        .inputs
+        .iter()
+        .map(|input| {
+            Input::open(input).map_err(|source| RunError::Open {
+                input: input.display().to_string(),
+                source,
+            })
+        })
+        .collect()
+}
+
+fn parse_inputs(inputs: &[Input], options: &ParserOptions) -> Result<ParseReport, RunError> {
+    let mut report = ParseReport::default();
+    for input in inputs {
+        report += parser::parse_input(
+            options.strategy,
+            options.num_of_threads,
+            input,
+            &options.parse,
+        )
+        .map_err(|source| RunError::Open {
+            input: input.source().to_string(),
+            source,
+        })?;
+    }
+    Ok(report)
+}
@@ -0,0 +1,134 @@
+use crate::parser::LogRegister;
+use crate::units::format_bytes;
+use serde::Serialize;
+use std::time::Duration;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Number of bisection steps used to find the per-type volume cap.
+const SEARCH_STEPS: u32 = 100;
+
+/// Suggested sampling rates per log type to hit the target daily volume.
+#[derive(Serialize, Debug)]
+pub struct SamplingPolicy {
+    pub target_daily_bytes: u64,
+    pub observed_daily_bytes: u64,
+    pub sampled_daily_bytes: u64,
+    pub target_reached: bool,
+    pub rates: Vec<SamplingRate>,
+}
+
+/// Sampling rate of a single log type with explanation why it was chosen.
+#[derive(Serialize, Debug)]
+pub struct SamplingRate {
+    pub log_type: String,
+    pub sample_rate: f64,
+    pub daily_bytes: u64,
+    pub error_ratio: f64,
+    pub explanation: String,
+}
+
+/// Recommend sampling rates from observed volumes.
+///
+/// Every log type gets at most the same daily volume cap (the biggest types are sampled first),
+/// but its rate never goes below its error ratio so error logs are not sampled away.
+pub fn recommend<'a, I>(register: I, window: Duration, target_daily_bytes: u64) -> SamplingPolicy
+where
+    I: IntoIterator<Item = (&'a str, &'a LogRegister)>,
+{
+    let scale = DAY.as_secs_f64() / window.as_secs_f64();
+    let types: Vec<(&str, f64, f64)> = register
+        .into_iter()
+        .map(|(log_type, value)| {
+            (
+                log_type,
+                value.num_of_bytes as f64 * scale,
+                value.error_ratio(),
+            )
+        })
+        .collect();
+
+    let target = target_daily_bytes as f64;
+    let observed: f64 = types.iter().map(|(_, daily_bytes, _)| daily_bytes).sum();
+    let rate = |daily_bytes: f64, error_ratio: f64, cap: f64| {
+        if daily_bytes <= cap {
+            1.0
+        } else {
+            (cap / daily_bytes).max(error_ratio)
+        }
+    };
+    let sampled_with_cap = |cap: f64| -> f64 {
+        types
+            .iter()
+            .map(|(_, daily_bytes, error_ratio)| {
+                daily_bytes * rate(*daily_bytes, *error_ratio, cap)
+            })
+            .sum()
+    };
+
+    // find the biggest per-type cap which fits into the target
+    let mut low = 0.0;
+    let mut high = types
+        .iter()
+        .map(|(_, daily_bytes, _)| *daily_bytes)
+        .fold(0.0, f64::max);
+    if observed > target {
+        for _ in 0..SEARCH_STEPS {
+            let mid = (low + high) / 2.0;
+            if sampled_with_cap(mid) > target {
+                high = mid;
+            } else {
+                low = mid;
+            }
+        }
+    }
+    let cap = if observed > target { low } else { high };
+
+    let rates: Vec<SamplingRate> = types
+        .iter()
+        .map(|(log_type, daily_bytes, error_ratio)| {
+            let sample_rate = rate(*daily_bytes, *error_ratio, cap);
+            let share = if observed > 0.0 {
+                daily_bytes / observed * 100.0
+            } else {
+                0.0
+            };
+            let reason = if sample_rate >= 1.0 {
+                "kept in full, it is below the per-type volume cap".to_string()
+            } else if sample_rate <= *error_ratio {
+                format!(
+                    "sampled at its error ratio {:.4} so error logs are not lost",
+                    error_ratio
+                )
+            } else {
+                format!(
+                    "sampled down to the per-type volume cap of {}/day",
+                    format_bytes(cap)
+                )
+            };
+            SamplingRate {
+                log_type: log_type.to_string(),
+                sample_rate,
+                daily_bytes: *daily_bytes as u64,
+                error_ratio: *error_ratio,
+                explanation: format!(
+                    "log type [{}] produces {}/day ({:.1}% of total, {:.1}% errors): {}",
+                    log_type,
+                    format_bytes(*daily_bytes),
+                    share,
+                    error_ratio * 100.0,
+                    reason
+                ),
+            }
+        })
+        .collect();
+
+    let sampled = sampled_with_cap(cap);
+    SamplingPolicy {
+        target_daily_bytes,
+        observed_daily_bytes: observed as u64,
+        sampled_daily_bytes: sampled as u64,
+        target_reached: sampled <= target * (1.0 + f64::EPSILON) + 1.0,
+        rates,
+    }
+}
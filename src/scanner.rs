@@ -0,0 +1,625 @@
+use crate::csv::CsvFormat;
+use regex::Regex;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, BufRead};
+
+/// Lines (including new line character) longer than this are skipped and reported as too long by
+/// default, only the first part is kept in memory. The same limit applies to multi-line records.
+pub const MAX_LINE_LEN: usize = 1 << 20;
+
+/// Start of the message of [`LineError::TooLong`].
+pub const TOO_LONG: &str = "line longer than";
+
+/// Why a line can't be parsed before even looking at its content.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineError {
+    /// Line longer than the limit of the scanner.
+    TooLong(usize),
+    InvalidUtf8,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineError::TooLong(max_line_len) => {
+                write!(f, "{} {} bytes", TOO_LONG, max_line_len)
+            }
+            LineError::InvalidUtf8 => write!(f, "line is not valid UTF-8"),
+        }
+    }
+}
+
+/// Bytes separating records, new line by default. Written with escapes, e.g. `\0`, `\r\n`,
+/// `\x1e` or `\t`, other characters are taken as they are.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Delimiter(Vec<u8>);
+
+impl Delimiter {
+    pub fn parse(delimiter: &str) -> Result<Self, String> {
+        let mut bytes = vec![];
+        let mut chars = delimiter.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                let mut utf8 = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                continue;
+            }
+            let byte = match chars.next() {
+                Some('0') => 0,
+                Some('n') => b'\n',
+                Some('r') => b'\r',
+                Some('t') => b'\t',
+                Some('\\') => b'\\',
+                Some('x') => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    u8::from_str_radix(&hex, 16)
+                        .map_err(|_| format!("Invalid \\x escape in delimiter: [{}]", delimiter))?
+                }
+                _ => return Err(format!("Invalid escape in delimiter: [{}]", delimiter)),
+            };
+            bytes.push(byte);
+        }
+        if bytes.is_empty() {
+            return Err("Delimiter can't be empty".to_string());
+        }
+
+        Ok(Delimiter(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn is_new_line(&self) -> bool {
+        self.0 == b"\n"
+    }
+
+    /// Smallest shift of the delimiter onto itself, its length unless the delimiter overlaps
+    /// itself (e.g. 1 for `@@`). Runs of an overlapping delimiter split differently depending
+    /// on where they are read from.
+    pub fn period(&self) -> usize {
+        (1..self.0.len())
+            .find(|shift| self.0[*shift..] == self.0[..self.0.len() - shift])
+            .unwrap_or(self.0.len())
+    }
+
+    /// End of the delimiter in `available` (index after it), the delimiter can start in `tail`
+    /// (the last bytes consumed before).
+    fn find(&self, tail: &[u8], available: &[u8]) -> Option<usize> {
+        let last = self.0[self.0.len() - 1];
+        let mut from = 0;
+        while let Some(idx) = available[from..].iter().position(|byte| *byte == last) {
+            let end = from + idx + 1;
+            let chunk = &available[..end];
+            let found = if chunk.len() >= self.0.len() {
+                chunk.ends_with(&self.0)
+            } else {
+                let (head, rest) = self.0.split_at(self.0.len() - chunk.len());
+                rest == chunk && tail.ends_with(head)
+            };
+            if found {
+                return Some(end);
+            }
+            from = end;
+        }
+
+        None
+    }
+}
+
+impl Default for Delimiter {
+    fn default() -> Self {
+        Delimiter(b"\n".to_vec())
+    }
+}
+
+impl TryFrom<String> for Delimiter {
+    type Error = String;
+
+    fn try_from(delimiter: String) -> Result<Self, Self::Error> {
+        Self::parse(&delimiter)
+    }
+}
+
+/// Line returned by [`LineScanner::next_line`].
+#[derive(Debug)]
+pub struct ScannedLine<'a> {
+    /// Bytes consumed including the delimiter (and the part over the limit).
+    pub num_of_bytes: u64,
+    /// Line content cut to the limit, invalid UTF-8 is replaced. The new line character is kept,
+    /// other delimiters are not.
+    pub text: Cow<'a, str>,
+    pub error: Option<LineError>,
+}
+
+/// Splits input into lines with bounded memory: unlike `BufRead::read_line` it doesn't fail on
+/// invalid UTF-8 and doesn't buffer lines longer than the limit.
+pub struct LineScanner<R> {
+    reader: R,
+    max_line_len: usize,
+    delimiter: Delimiter,
+    buffer: Vec<u8>,
+    /// The last bytes of the line consumed so far, a delimiter can span reads.
+    tail: Vec<u8>,
+    /// Whether the last byte consumed by [`LineScanner::next_json_value`] ended a line.
+    line_start: bool,
+}
+
+impl<R: BufRead> LineScanner<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_max_line_len(reader, MAX_LINE_LEN)
+    }
+
+    pub fn with_max_line_len(reader: R, max_line_len: usize) -> Self {
+        Self {
+            reader,
+            max_line_len,
+            delimiter: Delimiter::default(),
+            buffer: Vec::new(),
+            tail: Vec::new(),
+            line_start: true,
+        }
+    }
+
+    /// Split lines on the delimiter instead of new line character.
+    pub fn delimiter(mut self, delimiter: Delimiter) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Read the next line, `None` at the end of input.
+    pub fn next_line(&mut self) -> io::Result<Option<ScannedLine<'_>>> {
+        self.buffer.clear();
+        self.tail.clear();
+        let mut num_of_bytes = 0;
+        let mut too_long = false;
+        loop {
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            if available.is_empty() {
+                break;
+            }
+            let (len, done) = match self.delimiter.find(&self.tail, available) {
+                Some(end) => (end, true),
+                None => (available.len(), false),
+            };
+            let room = self.max_line_len.saturating_sub(self.buffer.len());
+            too_long |= len > room;
+            self.buffer.extend_from_slice(&available[..len.min(room)]);
+            let keep = self.delimiter.as_bytes().len() - 1;
+            if keep > 0 && !done {
+                self.tail.extend_from_slice(&available[..len]);
+                let excess = self.tail.len().saturating_sub(keep);
+                self.tail.drain(..excess);
+            }
+            self.reader.consume(len);
+            num_of_bytes += len as u64;
+            if done {
+                break;
+            }
+        }
+        if num_of_bytes == 0 {
+            return Ok(None);
+        }
+        if !self.delimiter.is_new_line() && self.buffer.ends_with(self.delimiter.as_bytes()) {
+            let len = self.buffer.len() - self.delimiter.as_bytes().len();
+            self.buffer.truncate(len);
+        }
+
+        let (text, error) = match std::str::from_utf8(&self.buffer) {
+            Ok(text) => (Cow::Borrowed(text), None),
+            Err(_) => (
+                String::from_utf8_lossy(&self.buffer),
+                Some(LineError::InvalidUtf8),
+            ),
+        };
+        // line cut in the middle of a character is reported as too long, not as invalid
+        let error = if too_long {
+            Some(LineError::TooLong(self.max_line_len))
+        } else {
+            error
+        };
+
+        Ok(Some(ScannedLine {
+            num_of_bytes,
+            text,
+            error,
+        }))
+    }
+}
+
+impl<R: BufRead> LineScanner<R> {
+    /// The next byte without consuming it, `None` at the end of input.
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            match self.reader.fill_buf() {
+                Ok(available) => return Ok(available.first().copied()),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Read the next top level JSON value of concatenated values (see
+    /// [`Multiline::ConcatenatedJson`]), with whitespace following it. Values are split on
+    /// balanced braces and brackets outside of strings wherever they end, other text up to the
+    /// end of its line is a record too (which doesn't parse). `None` at the end of input.
+    pub fn next_json_value(&mut self) -> io::Result<Option<ScannedLine<'_>>> {
+        self.buffer.clear();
+        let mut num_of_bytes = 0;
+        let mut too_long = false;
+        let mut depth = JsonDepth::default();
+        let mut phase = ValuePhase::Leading;
+        loop {
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            if available.is_empty() {
+                break;
+            }
+            let mut len = 0;
+            let mut done = false;
+            for byte in available {
+                phase = match phase {
+                    ValuePhase::Leading if byte.is_ascii_whitespace() => ValuePhase::Leading,
+                    ValuePhase::Leading if *byte != b'{' && *byte != b'[' => ValuePhase::Text,
+                    ValuePhase::Leading | ValuePhase::Value => {
+                        depth.update_byte(*byte);
+                        if depth.is_balanced() {
+                            ValuePhase::Trailing
+                        } else {
+                            ValuePhase::Value
+                        }
+                    }
+                    ValuePhase::Text if *byte == b'\n' => ValuePhase::Trailing,
+                    ValuePhase::Text => ValuePhase::Text,
+                    ValuePhase::Trailing if byte.is_ascii_whitespace() => ValuePhase::Trailing,
+                    ValuePhase::Trailing => {
+                        done = true;
+                        break;
+                    }
+                };
+                len += 1;
+            }
+            if len > 0 {
+                self.line_start = available[len - 1] == b'\n';
+            }
+            let room = self.max_line_len.saturating_sub(self.buffer.len());
+            too_long |= len > room;
+            self.buffer.extend_from_slice(&available[..len.min(room)]);
+            self.reader.consume(len);
+            num_of_bytes += len as u64;
+            if done {
+                break;
+            }
+        }
+        if num_of_bytes == 0 {
+            return Ok(None);
+        }
+
+        let (text, error) = match std::str::from_utf8(&self.buffer) {
+            Ok(text) => (Cow::Borrowed(text), None),
+            Err(_) => (
+                String::from_utf8_lossy(&self.buffer),
+                Some(LineError::InvalidUtf8),
+            ),
+        };
+        let error = if too_long {
+            Some(LineError::TooLong(self.max_line_len))
+        } else {
+            error
+        };
+
+        Ok(Some(ScannedLine {
+            num_of_bytes,
+            text,
+            error,
+        }))
+    }
+}
+
+/// Part of a concatenated JSON value read by [`LineScanner::next_json_value`].
+#[derive(Clone, Copy)]
+enum ValuePhase {
+    /// Whitespace before the value (only at the start of the input).
+    Leading,
+    /// Inside of braces or brackets.
+    Value,
+    /// Text which isn't an object or array, up to the end of its line.
+    Text,
+    /// Whitespace after the value.
+    Trailing,
+}
+
+/// How lines are assembled into multi-line records.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Multiline {
+    /// Record starts with a line matching the pattern (e.g. a timestamp), the following lines
+    /// (e.g. a stack trace) belong to it.
+    StartPattern(StartPattern),
+    /// Pretty-printed JSON: record starts with `{` at the beginning of a line once braces of the
+    /// previous record are balanced.
+    Json,
+    /// Concatenated JSON: records are top level JSON values wherever they end, e.g.
+    /// `{"a":1}{"b":2}` on a single line or pretty-printed values. Parts of the file start at a
+    /// line starting with `{` like with [`Multiline::Json`].
+    ConcatenatedJson,
+    /// CSV records (RFC 4180): a record goes on while a quoted field is open, so quoted fields
+    /// can contain new lines. Parts of the file start at a line which is a whole record, with as
+    /// many fields as the header if it has one, the part before reads records up to it. Set by
+    /// the parser for CSV input.
+    #[serde(skip)]
+    Csv(CsvFormat),
+}
+
+impl Multiline {
+    fn is_start(&self, line: &str, balanced: bool) -> bool {
+        match self {
+            Multiline::StartPattern(pattern) => {
+                pattern.0.is_match(line.trim_end_matches(['\n', '\r']))
+            }
+            Multiline::Json | Multiline::ConcatenatedJson => balanced && line.starts_with('{'),
+            Multiline::Csv(_) => balanced,
+        }
+    }
+
+    /// Whether a part of the file starts at the line, when it isn't known whether the lines
+    /// before it are balanced.
+    fn starts_part(&self, line: &str) -> bool {
+        match self {
+            // a line continuing a quoted field rarely closes it and has the fields of a record
+            Multiline::Csv(csv) => {
+                let mut quotes = CsvQuotes::default();
+                quotes.update(line);
+                !quotes.open
+                    && csv
+                        .fields(line)
+                        .is_ok_and(|fields| !csv.has_header || fields.len() == csv.columns.len())
+            }
+            multiline => multiline.is_start(line, true),
+        }
+    }
+}
+
+/// Regex matching the first line of a record.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct StartPattern(Regex);
+
+impl StartPattern {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(StartPattern)
+    }
+}
+
+impl TryFrom<String> for StartPattern {
+    type Error = regex::Error;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        Self::new(&pattern)
+    }
+}
+
+/// Nesting depth of JSON braces outside of strings.
+#[derive(Default)]
+struct JsonDepth {
+    depth: i64,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonDepth {
+    fn update(&mut self, text: &str) {
+        for byte in text.bytes() {
+            self.update_byte(byte);
+        }
+    }
+
+    fn update_byte(&mut self, byte: u8) {
+        if self.in_string {
+            match byte {
+                _ if self.escaped => self.escaped = false,
+                b'\\' => self.escaped = true,
+                b'"' => self.in_string = false,
+                _ => {}
+            }
+        } else {
+            match byte {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => self.depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    fn is_balanced(&self) -> bool {
+        self.depth <= 0 && !self.in_string
+    }
+}
+
+/// Whether a quoted CSV field is open, doubled quotes inside of it close and open it again.
+#[derive(Default)]
+struct CsvQuotes {
+    open: bool,
+}
+
+impl CsvQuotes {
+    fn update(&mut self, text: &str) {
+        let quotes = text.bytes().filter(|byte| *byte == b'"').count();
+        self.open ^= quotes % 2 == 1;
+    }
+}
+
+/// Line kept after the end of a record, it starts the next one.
+struct NextLine {
+    num_of_bytes: u64,
+    text: String,
+    error: Option<LineError>,
+}
+
+impl From<ScannedLine<'_>> for NextLine {
+    fn from(line: ScannedLine) -> Self {
+        Self {
+            num_of_bytes: line.num_of_bytes,
+            text: line.text.into_owned(),
+            error: line.error,
+        }
+    }
+}
+
+/// Assembles lines into records: every line is a record unless [`Multiline`] mode is set.
+///
+/// A record belongs to the part of the file where its first line starts and can continue past
+/// the end of the part, so a thread starting in the middle of the file has to skip to the next
+/// record start first (see [`skip_to_record_start`]).
+///
+/// [`skip_to_record_start`]: RecordScanner::skip_to_record_start
+pub struct RecordScanner<R> {
+    lines: LineScanner<R>,
+    max_record_len: usize,
+    multiline: Option<Multiline>,
+    record: String,
+    next: Option<NextLine>,
+}
+
+impl<R: BufRead> RecordScanner<R> {
+    pub fn new(reader: R, multiline: Option<Multiline>) -> Self {
+        Self {
+            lines: LineScanner::new(reader),
+            max_record_len: MAX_LINE_LEN,
+            multiline,
+            record: String::new(),
+            next: None,
+        }
+    }
+
+    /// Split records on the delimiter instead of new line character.
+    pub fn delimiter(mut self, delimiter: Delimiter) -> Self {
+        self.lines = self.lines.delimiter(delimiter);
+        self
+    }
+
+    /// Report lines and records longer than the limit as too long instead of [`MAX_LINE_LEN`].
+    pub fn max_record_len(mut self, max_record_len: usize) -> Self {
+        self.lines.max_line_len = max_record_len;
+        self.max_record_len = max_record_len;
+        self
+    }
+
+    /// Skip the rest of the current line and lines which continue a record started before,
+    /// returns number of skipped bytes. Lines are skipped until more than `back` bytes are,
+    /// when the scanner starts that many bytes before the part to find the delimiter ending
+    /// the line.
+    pub fn skip_to_record_start(&mut self, back: u64) -> io::Result<u64> {
+        let mut skipped = 0;
+        while skipped <= back {
+            match self.lines.next_line()? {
+                Some(line) => skipped += line.num_of_bytes,
+                None => return Ok(skipped),
+            }
+        }
+        if let Some(Multiline::ConcatenatedJson) = &self.multiline {
+            // values are read from the start of the line, so it isn't consumed
+            while !matches!(self.lines.peek()?, Some(b'{') | None) {
+                skipped += self.lines.next_line()?.map_or(0, |line| line.num_of_bytes);
+            }
+            return Ok(skipped);
+        }
+        if let Some(multiline) = &self.multiline {
+            while let Some(line) = self.lines.next_line()? {
+                if multiline.starts_part(&line.text) {
+                    self.next = Some(line.into());
+                    break;
+                }
+                skipped += line.num_of_bytes;
+            }
+        }
+
+        Ok(skipped)
+    }
+
+    /// Whether the next record starts where [`RecordScanner::skip_to_record_start`] stops, so a
+    /// part of the file ends before it. Concatenated JSON values can start in the middle of a
+    /// line, the part of the file they start in reads them until a value starts a line. CSV
+    /// records are read until one can start a part.
+    pub fn next_starts_part(&mut self) -> io::Result<bool> {
+        match &self.multiline {
+            Some(Multiline::ConcatenatedJson) => {
+                Ok(self.lines.line_start && matches!(self.lines.peek()?, Some(b'{') | None))
+            }
+            Some(multiline @ Multiline::Csv(_)) => {
+                if self.next.is_none() {
+                    self.next = self.lines.next_line()?.map(NextLine::from);
+                }
+                Ok(self
+                    .next
+                    .as_ref()
+                    .is_none_or(|line| multiline.starts_part(&line.text)))
+            }
+            _ => Ok(true),
+        }
+    }
+
+    /// Read the next record, `None` at the end of input. Records are cut to the maximum record
+    /// length.
+    pub fn next_record(&mut self) -> io::Result<Option<ScannedLine<'_>>> {
+        let multiline = match &self.multiline {
+            Some(Multiline::ConcatenatedJson) => return self.lines.next_json_value(),
+            Some(multiline) => multiline,
+            None => return self.lines.next_line(),
+        };
+        let first = match self.next.take() {
+            Some(line) => line,
+            None => match self.lines.next_line()? {
+                Some(line) => line.into(),
+                None => return Ok(None),
+            },
+        };
+
+        self.record.clear();
+        self.record.push_str(&first.text);
+        let mut num_of_bytes = first.num_of_bytes;
+        let mut error = first.error;
+        let mut depth = JsonDepth::default();
+        let mut quotes = CsvQuotes::default();
+        depth.update(&first.text);
+        quotes.update(&first.text);
+        // the end of the record is known only once the next record starts
+        while let Some(line) = self.lines.next_line()? {
+            let balanced = match multiline {
+                Multiline::Csv(_) => !quotes.open,
+                _ => depth.is_balanced(),
+            };
+            if multiline.is_start(&line.text, balanced) {
+                self.next = Some(line.into());
+                break;
+            }
+            num_of_bytes += line.num_of_bytes;
+            error = error.or(line.error);
+            quotes.update(&line.text);
+            if self.record.len() + line.text.len() > self.max_record_len {
+                error = Some(LineError::TooLong(self.max_record_len));
+            } else {
+                self.record.push_str(&line.text);
+                depth.update(&line.text);
+            }
+        }
+
+        Ok(Some(ScannedLine {
+            num_of_bytes,
+            text: Cow::Borrowed(&self.record),
+            error,
+        }))
+    }
+}
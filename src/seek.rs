@@ -0,0 +1,157 @@
+use crate::parser::{self, ParseOptions};
+use crate::platform;
+use crate::preview::PreviewRecord;
+use crate::reverse::ReverseLines;
+use crate::timestamp;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Records around the first record at or after a time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    /// Offset of the first record at or after the time, size of the input if there is none.
+    pub offset: u64,
+    /// Timestamp of the record at the offset.
+    pub timestamp: Option<SystemTime>,
+    /// Records before the offset, oldest first and numbered from the offset.
+    pub before: Vec<PreviewRecord>,
+    /// Records from the offset on, the first one is the record at the offset.
+    pub after: Vec<PreviewRecord>,
+    /// Number of times the input was read at another offset.
+    pub probes: u32,
+}
+
+/// Lines of the input read forward from any offset.
+struct Lines<'a> {
+    reader: BufReader<File>,
+    options: &'a ParseOptions,
+    timestamp_field: &'a str,
+}
+
+impl Lines<'_> {
+    /// Start of the first line starting at or after the offset.
+    fn line_start(&mut self, offset: u64) -> io::Result<u64> {
+        if offset == 0 {
+            self.reader.seek(SeekFrom::Start(0))?;
+            return Ok(0);
+        }
+        self.reader.seek(SeekFrom::Start(offset - 1))?;
+        let mut rest = vec![];
+        let read = self.reader.read_until(b'\n', &mut rest)?;
+        Ok(offset - 1 + read as u64)
+    }
+
+    /// Next line without the new line character and the number of bytes read.
+    fn next_line(&mut self) -> io::Result<Option<(Vec<u8>, u64)>> {
+        let mut line = vec![];
+        let read = self.reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        Ok(Some((line, read as u64)))
+    }
+
+    /// Start, end and timestamp of the first line with a timestamp starting at or after the
+    /// offset and before `end`.
+    fn next_timestamp(
+        &mut self,
+        offset: u64,
+        end: u64,
+    ) -> io::Result<Option<(u64, u64, SystemTime)>> {
+        let mut start = self.line_start(offset)?;
+        while start < end {
+            let (line, read) = match self.next_line()? {
+                Some(line) => line,
+                None => break,
+            };
+            let timestamp = std::str::from_utf8(&line)
+                .ok()
+                .and_then(|line| parser::parse_value(line, self.options).ok())
+                .and_then(|value| value.get(self.timestamp_field).and_then(timestamp::parse));
+            if let Some(timestamp) = timestamp {
+                return Ok(Some((start, start + read, timestamp)));
+            }
+            start += read;
+        }
+        Ok(None)
+    }
+}
+
+/// Binary search of the first record with the timestamp field at or after the time in an input
+/// ordered by time, with `context` records before it and after it. Records without the
+/// timestamp (e.g. lines of stack traces) are skipped by the search, only a few lines are read at
+/// each of the `log2(size)` probes.
+pub fn seek<P: AsRef<Path>>(
+    path: P,
+    at: SystemTime,
+    timestamp_field: &str,
+    context: usize,
+    options: &ParseOptions,
+) -> io::Result<Position> {
+    let file = platform::open_shared(&path)?;
+    let size = file.metadata()?.len();
+    let mut lines = Lines {
+        reader: BufReader::new(file),
+        options,
+        timestamp_field,
+    };
+    let first = match &options.csv {
+        Some(csv) if csv.has_header => lines.line_start(1)?,
+        _ => 0,
+    };
+
+    // the first record at or after the time starts in [lo, hi)
+    let (mut lo, mut hi) = (first, size);
+    let mut probes = 0;
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        probes += 1;
+        match lines.next_timestamp(mid, hi)? {
+            Some((_, end, timestamp)) if timestamp < at => lo = end,
+            Some((start, _, _)) => hi = start,
+            // no timestamp after the middle
+            None => hi = mid,
+        }
+    }
+    let (offset, timestamp) = match lines.next_timestamp(lo, size)? {
+        Some((start, _, timestamp)) => (start, Some(timestamp)),
+        None => (size, None),
+    };
+
+    let mut after = vec![];
+    lines.line_start(offset)?;
+    while after.len() <= context {
+        let line = match lines.next_line()? {
+            Some((line, _)) => line,
+            None => break,
+        };
+        after.extend(PreviewRecord::from_line(after.len() as u64, line, options));
+    }
+    let mut before = vec![];
+    let mut reverse = ReverseLines::before(platform::open_shared(&path)?, offset);
+    while before.len() < context {
+        let line = match reverse.prev_line()? {
+            Some((start, line)) if start >= first => line,
+            _ => break,
+        };
+        before.extend(PreviewRecord::from_line(
+            before.len() as u64 + 1,
+            line,
+            options,
+        ));
+    }
+    before.reverse();
+
+    Ok(Position {
+        offset,
+        timestamp,
+        before,
+        after,
+        probes,
+    })
+}
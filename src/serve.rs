@@ -0,0 +1,500 @@
+use crate::error_log;
+use crate::grafana;
+use crate::parser::{ParseOptions, ParseReport};
+use crate::sink;
+use crate::tls::{ServerTls, Stream};
+use log::error;
+use rustls::ServerConfig;
+use serde_json::{json, Value};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Largest accepted request body, larger batches are rejected with 413.
+pub const MAX_BODY_SIZE: usize = 64 * 1024 * 1024;
+
+/// Idle connections are closed after this time.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Page served at `/`, renders tables and a rate chart of the aggregate polled from `/stats`.
+const UI_PAGE: &str = include_str!("serve_ui.html");
+
+const JSON: &str = "application/json";
+const HTML: &str = "text/html; charset=utf-8";
+
+/// Credentials accepted by [`Server`] in the `Authorization` header.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Credentials {
+    /// `Authorization: Bearer <token>`.
+    Bearer(String),
+    /// `Authorization: Basic <base64 of user:password>`.
+    Basic { user: String, password: String },
+}
+
+impl Credentials {
+    /// Parse `user:password` of basic auth.
+    pub fn basic(user_password: &str) -> Result<Self, String> {
+        match user_password.split_once(':') {
+            Some((user, password)) if !user.is_empty() => Ok(Credentials::Basic {
+                user: user.to_string(),
+                password: password.to_string(),
+            }),
+            _ => Err("Basic auth should be user:password".to_string()),
+        }
+    }
+
+    /// Value of the `Authorization` header.
+    pub fn header(&self) -> String {
+        match self {
+            Credentials::Bearer(token) => format!("Bearer {}", token),
+            Credentials::Basic { user, password } => {
+                format!(
+                    "Basic {}",
+                    base64(format!("{}:{}", user, password).as_bytes())
+                )
+            }
+        }
+    }
+}
+
+/// HTTP ingestion endpoint: NDJSON records POSTed to any path are added to the live aggregate,
+/// which is returned as JSON by `GET /stats`, shown by the page at `GET /` and served to Grafana
+/// under `/grafana` (see [`grafana`]). Every connection is served by its own thread and kept
+/// alive, so log forwarders (e.g. fluent-bit or vector) can reuse it. With credentials, requests
+/// without any of them are rejected with 401. With TLS, connections are served over HTTPS, and
+/// only clients with certificates of the client CA are served if it's set.
+pub struct Server {
+    listener: TcpListener,
+    report: Arc<Mutex<ParseReport>>,
+    options: Arc<ParseOptions>,
+    /// Accepted `Authorization` headers, anyone is served if empty.
+    authorizations: Arc<Vec<String>>,
+    tls: Option<Arc<ServerConfig>>,
+}
+
+impl Server {
+    pub fn bind(address: &str, options: ParseOptions) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(address)?,
+            report: Arc::new(Mutex::new(ParseReport::default())),
+            options: Arc::new(options),
+            authorizations: Arc::new(vec![]),
+            tls: None,
+        })
+    }
+
+    /// Serve connections over TLS.
+    pub fn tls(mut self, tls: &ServerTls) -> Result<Self, String> {
+        self.tls = Some(tls.config()?);
+        Ok(self)
+    }
+
+    /// Serve only requests with any of the credentials.
+    pub fn credentials(mut self, credentials: &[Credentials]) -> Self {
+        self.authorizations = Arc::new(credentials.iter().map(Credentials::header).collect());
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Snapshot of the live aggregate.
+    pub fn report(&self) -> ParseReport {
+        self.report.lock().expect("Report lock is poisoned").clone()
+    }
+
+    /// Serve connections until accepting fails.
+    pub fn run(&self) -> io::Result<()> {
+        loop {
+            let (stream, peer) = self.listener.accept()?;
+            let report = self.report.clone();
+            let options = self.options.clone();
+            let authorizations = self.authorizations.clone();
+            let scheme = if self.tls.is_some() { "https" } else { "http" };
+            let name = format!("{}://{}", scheme, self.local_addr()?);
+            let stream = Stream::accept(stream, self.tls.as_ref())?;
+            thread::spawn(move || {
+                let served = serve_connection(stream, &report, &options, &authorizations, &name);
+                if let Err(err) = served {
+                    error!("Connection of {} failed: {}", peer, err);
+                }
+            });
+        }
+    }
+}
+
+/// Request with its body, header names are lower case.
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn is_chunked(&self) -> bool {
+        self.header("transfer-encoding")
+            .is_some_and(|value| value.eq_ignore_ascii_case("chunked"))
+    }
+
+    /// Whether a body follows the head.
+    fn has_body(&self) -> bool {
+        self.is_chunked()
+            || self
+                .header("content-length")
+                .is_some_and(|length| length != "0")
+    }
+}
+
+fn serve_connection(
+    stream: Stream,
+    report: &Mutex<ParseReport>,
+    options: &ParseOptions,
+    authorizations: &[String],
+    name: &str,
+) -> io::Result<()> {
+    stream.tcp().set_read_timeout(Some(TIMEOUT))?;
+    stream.tcp().set_write_timeout(Some(TIMEOUT))?;
+    // responses are written to the stream under the reader, a TLS stream can't be split
+    let mut reader = BufReader::new(stream);
+
+    loop {
+        let mut request = match read_head(&mut reader) {
+            Ok(Some(request)) => request,
+            // closed by the client, or idle for too long
+            Ok(None) => return Ok(()),
+            Err(err) => return failed(reader.get_mut(), err),
+        };
+        let close = request
+            .header("connection")
+            .is_some_and(|value| value.eq_ignore_ascii_case("close"));
+
+        // checked before the body is read, so unauthorized clients can't make it allocate bodies
+        if !is_authorized(&request, authorizations) {
+            // the unread body would be taken for the next request
+            let close = close || request.has_body();
+            let body = json!({"error": "Missing or invalid credentials"}).to_string();
+            let challenge = ("WWW-Authenticate", "Basic realm=\"log-parser\", Bearer");
+            write_response(
+                reader.get_mut(),
+                "401 Unauthorized",
+                JSON,
+                &[challenge],
+                &body,
+                close,
+            )?;
+            if close {
+                return Ok(());
+            }
+            continue;
+        }
+        if let Err(err) = read_body(&mut reader, &mut request) {
+            return failed(reader.get_mut(), err);
+        }
+
+        let (status, body) = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/" | "/index.html") => {
+                write_response(reader.get_mut(), "200 OK", HTML, &[], UI_PAGE, close)?;
+                if close {
+                    return Ok(());
+                }
+                continue;
+            }
+            ("GET", "/stats") => {
+                let report = report.lock().expect("Report lock is poisoned").clone();
+                (
+                    "200 OK",
+                    sink::report_document(name, &report, options).to_string(),
+                )
+            }
+            (_, "/stats") => (
+                "405 Method Not Allowed",
+                json!({"error": "Use GET /stats"}).to_string(),
+            ),
+            ("GET", "/grafana" | "/grafana/") => ("200 OK", json!({"status": "ok"}).to_string()),
+            ("POST", "/grafana/search") => {
+                let report = report.lock().expect("Report lock is poisoned").clone();
+                grafana_response(grafana::search(&report, &request.body))
+            }
+            ("POST", "/grafana/query") => {
+                let report = report.lock().expect("Report lock is poisoned").clone();
+                grafana_response(grafana::query(&report, &request.body, options.clock()))
+            }
+            ("GET", "/grafana/counters") => {
+                let report = report.lock().expect("Report lock is poisoned");
+                ("200 OK", grafana::counters(&report).to_string())
+            }
+            ("GET", "/grafana/buckets") => {
+                let report = report.lock().expect("Report lock is poisoned");
+                ("200 OK", grafana::buckets(&report).to_string())
+            }
+            ("POST", _) if request.header("content-encoding").is_some() => (
+                "415 Unsupported Media Type",
+                json!({"error": "Compressed bodies are not supported"}).to_string(),
+            ),
+            ("POST", _) => {
+                // parsed without holding the lock, then merged into the live aggregate
+                let batch = parse_batch(&request.body, options);
+                let body = json!({"lines": batch.lines, "failed_lines": batch.failed_lines});
+                *report.lock().expect("Report lock is poisoned") += batch;
+                ("200 OK", body.to_string())
+            }
+            _ => (
+                "404 Not Found",
+                json!({"error": "POST NDJSON records, GET /stats, /grafana or / for the page"})
+                    .to_string(),
+            ),
+        };
+        write_response(reader.get_mut(), status, JSON, &[], &body, close)?;
+        if close {
+            return Ok(());
+        }
+    }
+}
+
+/// Status and body of a Grafana data source call, invalid requests are answered with 400.
+fn grafana_response(result: Result<Value, String>) -> (&'static str, String) {
+    match result {
+        Ok(body) => ("200 OK", body.to_string()),
+        Err(err) => ("400 Bad Request", json!({ "error": err }).to_string()),
+    }
+}
+
+/// Whether the request has any of the accepted `Authorization` headers (any request if there are
+/// none). Headers are compared in constant time, so timing doesn't reveal matching prefixes.
+fn is_authorized(request: &Request, authorizations: &[String]) -> bool {
+    if authorizations.is_empty() {
+        return true;
+    }
+    let given = match request.header("authorization") {
+        Some(given) => given.as_bytes(),
+        None => return false,
+    };
+    authorizations.iter().any(|expected| {
+        let expected = expected.as_bytes();
+        expected.len() == given.len()
+            && expected
+                .iter()
+                .zip(given)
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    })
+}
+
+/// Standard base64 with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let triple = chunk.iter().enumerate().fold(0u32, |triple, (idx, byte)| {
+            triple | (*byte as u32) << (16 - idx * 8)
+        });
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                encoded.push(ALPHABET[(triple >> (18 - idx * 6)) as usize & 63] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Report of NDJSON records of a request body, new lines count to record sizes like in files.
+fn parse_batch(body: &[u8], options: &ParseOptions) -> ParseReport {
+    let mut batch = ParseReport::default();
+    let body = String::from_utf8_lossy(body);
+    for record in body.split_inclusive('\n') {
+        let line = record.trim_end_matches(['\r', '\n']);
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Err(err) = batch.add_line_of_size(line, record.len() as u64, options) {
+            error_log::log(line, &err);
+        }
+    }
+    batch
+}
+
+/// Request which can't be served, answered with the status before the connection is closed.
+#[derive(Debug)]
+struct Rejected {
+    status: &'static str,
+    message: String,
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+fn invalid_request(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        Rejected {
+            status: "400 Bad Request",
+            message: message.to_string(),
+        },
+    )
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        Rejected {
+            status: "413 Payload Too Large",
+            message: format!("Request body is larger than {} bytes", MAX_BODY_SIZE),
+        },
+    )
+}
+
+/// End of a connection whose request couldn't be read, rejected requests are answered with
+/// their status.
+fn failed<W: Write>(writer: &mut W, err: io::Error) -> io::Result<()> {
+    match err.kind() {
+        // idle for too long
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Ok(()),
+        _ => match err.get_ref().and_then(|err| err.downcast_ref::<Rejected>()) {
+            Some(rejected) => {
+                let body = json!({ "error": rejected.message }).to_string();
+                write_response(writer, rejected.status, JSON, &[], &body, true)
+            }
+            None => Err(err),
+        },
+    }
+}
+
+/// Read the request line and headers of the next request of the connection, `None` if the client
+/// closed it. The body is read by [`read_body`].
+fn read_head<R: BufRead>(reader: &mut R) -> io::Result<Option<Request>> {
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Err(invalid_request("Invalid request line")),
+    };
+    // query is not used
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let mut headers = vec![];
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    Ok(Some(Request {
+        method,
+        path,
+        headers,
+        body: vec![],
+    }))
+}
+
+/// Read the body of the request after its head.
+fn read_body<S: Read + Write>(reader: &mut BufReader<S>, request: &mut Request) -> io::Result<()> {
+    // curl waits for it before sending larger bodies
+    if request
+        .header("expect")
+        .is_some_and(|value| value.eq_ignore_ascii_case("100-continue"))
+    {
+        let writer = reader.get_mut();
+        writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        writer.flush()?;
+    }
+    if request.is_chunked() {
+        request.body = read_chunked(reader)?;
+    } else if let Some(length) = request.header("content-length") {
+        let length: usize = length
+            .parse()
+            .map_err(|_| invalid_request("Invalid Content-Length"))?;
+        if length > MAX_BODY_SIZE {
+            return Err(too_large());
+        }
+        request.body.resize(length, 0);
+        reader.read_exact(&mut request.body)?;
+    }
+    Ok(())
+}
+
+/// Read body sent in chunks, each one prefixed by its hexadecimal size.
+fn read_chunked<R: BufRead>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        let mut size_line = String::new();
+        reader.read_line(&mut size_line)?;
+        // chunk extensions follow `;`
+        let size = size_line.trim().split(';').next().unwrap_or_default();
+        let size =
+            usize::from_str_radix(size, 16).map_err(|_| invalid_request("Invalid chunk size"))?;
+        if body.len() + size > MAX_BODY_SIZE {
+            return Err(too_large());
+        }
+        if size == 0 {
+            break;
+        }
+        let start = body.len();
+        body.resize(start + size + 2, 0);
+        reader.read_exact(&mut body[start..])?;
+        // CRLF after the chunk
+        body.truncate(start + size);
+    }
+    // trailer headers until an empty line
+    loop {
+        let mut trailer = String::new();
+        if reader.read_line(&mut trailer)? == 0 || trailer.trim().is_empty() {
+            return Ok(body);
+        }
+    }
+}
+
+fn write_response<W: Write>(
+    writer: &mut W,
+    status: &str,
+    content_type: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+    close: bool,
+) -> io::Result<()> {
+    // written at once, so a response is a single TLS record or TCP segment where it fits
+    let mut response = vec![];
+    write!(
+        response,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    for (name, value) in headers {
+        write!(response, "{}: {}\r\n", name, value)?;
+    }
+    write!(
+        response,
+        "{}\r\n{}",
+        if close { "Connection: close\r\n" } else { "" },
+        body
+    )?;
+    writer.write_all(&response)?;
+    writer.flush()
+}
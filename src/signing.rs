@@ -0,0 +1,95 @@
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use ed25519_dalek::{Signature, Signer, Verifier, VerifyingKey};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Environment variable with the PEM private key, used if no key file is given.
+pub const KEY_ENV: &str = "LOG_PARSER_SIGNING_KEY";
+
+/// Extension of detached signatures, next to the signed file.
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+/// Ed25519 private key signing reports, in PEM format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SigningKey {
+    /// Key file, e.g. made by `openssl genpkey -algorithm ed25519 -out report.key`.
+    File(PathBuf),
+    /// Key itself, from [`KEY_ENV`].
+    Pem(String),
+}
+
+impl SigningKey {
+    /// Key from [`KEY_ENV`], if it's set.
+    pub fn from_env() -> Option<Self> {
+        env::var(KEY_ENV)
+            .ok()
+            .filter(|pem| !pem.trim().is_empty())
+            .map(SigningKey::Pem)
+    }
+}
+
+/// Detached signature of the file, `<file>.sig`.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut signature = OsString::from(path.as_os_str());
+    signature.push(".");
+    signature.push(SIGNATURE_EXTENSION);
+    PathBuf::from(signature)
+}
+
+/// Sign the file with the Ed25519 key, the raw 64 byte signature is written to
+/// [`signature_path`]. It verifies with `openssl pkeyutl -verify -rawin` as well as with
+/// [`verify`].
+pub fn sign(path: &Path, key: &SigningKey) -> io::Result<PathBuf> {
+    let pem = match key {
+        SigningKey::File(file) => fs::read_to_string(file)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", file.display(), err)))?,
+        SigningKey::Pem(pem) => pem.clone(),
+    };
+    let key = ed25519_dalek::SigningKey::from_pkcs8_pem(&pem).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Can't read Ed25519 private key: {}", err),
+        )
+    })?;
+    let content = fs::read(path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("Can't sign {}: {}", path.display(), err),
+        )
+    })?;
+
+    let signature = signature_path(path);
+    fs::write(&signature, key.sign(&content).to_bytes())?;
+    Ok(signature)
+}
+
+/// Whether the detached signature of the file was made by the private key of the public key
+/// (PEM). Errors are kept for files and keys which can't be read, not for invalid signatures.
+pub fn verify(path: &Path, public_key: &Path, signature: &Path) -> io::Result<bool> {
+    let read = |file: &Path| {
+        fs::read(file)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", file.display(), err)))
+    };
+    let (content, pem, signature) = (read(path)?, read(public_key)?, read(signature)?);
+    let key = std::str::from_utf8(&pem)
+        .map_err(|err| err.to_string())
+        .and_then(|pem| VerifyingKey::from_public_key_pem(pem).map_err(|err| err.to_string()))
+        .map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Can't read Ed25519 public key {}: {}",
+                    public_key.display(),
+                    err
+                ),
+            )
+        })?;
+
+    // signatures of another length are invalid too
+    Ok(Signature::from_slice(&signature)
+        .and_then(|signature| key.verify(&content, &signature))
+        .is_ok())
+}
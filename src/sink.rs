@@ -0,0 +1,843 @@
+use crate::error_log;
+use crate::group;
+use crate::http::{self, Url};
+#[cfg(feature = "kafka")]
+use crate::kafka::{self, KafkaUrl};
+#[cfg(feature = "nats")]
+use crate::nats::{self, NatsUrl};
+#[cfg(feature = "otlp")]
+use crate::otlp;
+use crate::parquet::ParquetSink;
+use crate::parser::{ParseOptions, ParseReport, ParsedRecord};
+use crate::rate;
+use crate::redis::{RedisSink, RedisUrl};
+use crate::sqlite::SqliteWriter;
+use crate::throttle::TokenBucket;
+use crate::timestamp;
+use crate::tls::Stream;
+use crate::units;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::time::{Duration, Instant};
+
+/// HTTP sink sends records in batches of about this size unless its batch policy says otherwise.
+const HTTP_BATCH_SIZE: u64 = 1 << 20;
+
+/// When buffered records of a sink are sent: once the batch has `max_records` records or
+/// `max_bytes` bytes, or its first record is `max_latency` old. Set per sink by URI query
+/// parameters, e.g. `http://host:port/path?batch_records=500&batch_bytes=256KB&batch_latency=5s`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchPolicy {
+    pub max_records: Option<usize>,
+    pub max_bytes: Option<u64>,
+    pub max_latency: Option<Duration>,
+}
+
+impl BatchPolicy {
+    /// Split batch parameters off the URI, the URI is returned with the rest of its query.
+    pub fn from_uri(uri: &str) -> Result<(String, Self), String> {
+        let (base, query) = match uri.split_once('?') {
+            Some((base, query)) => (base, query),
+            None => return Ok((uri.to_string(), Self::default())),
+        };
+        let mut policy = Self::default();
+        let mut rest = vec![];
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            match param.split_once('=') {
+                Some(("batch_records", value)) => {
+                    let max_records = value
+                        .parse()
+                        .ok()
+                        .filter(|max_records| *max_records > 0)
+                        .ok_or_else(|| format!("Invalid batch_records in URI: [{}]", uri))?;
+                    policy.max_records = Some(max_records);
+                }
+                Some(("batch_bytes", value)) => {
+                    let max_bytes = units::parse_bytes(value)
+                        .map_err(|err| format!("Invalid batch_bytes in URI [{}]: {}", uri, err))?;
+                    policy.max_bytes = Some(max_bytes);
+                }
+                Some(("batch_latency", value)) => {
+                    let max_latency = humantime::parse_duration(value).map_err(|err| {
+                        format!("Invalid batch_latency in URI [{}]: {}", uri, err)
+                    })?;
+                    policy.max_latency = Some(max_latency);
+                }
+                _ => rest.push(param),
+            }
+        }
+        let uri = match rest.is_empty() {
+            true => base.to_string(),
+            false => format!("{}?{}", base, rest.join("&")),
+        };
+
+        Ok((uri, policy))
+    }
+
+    pub fn is_set(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// Whether the batch reached its size.
+    fn is_full(&self, records: usize, bytes: u64) -> bool {
+        self.max_records.is_some_and(|max| records >= max)
+            || self.max_bytes.is_some_and(|max| bytes >= max)
+    }
+
+    /// Whether the batch started at the time waited long enough.
+    fn is_due(&self, started: Option<Instant>) -> bool {
+        match (self.max_latency, started) {
+            (Some(max_latency), Some(started)) => started.elapsed() >= max_latency,
+            _ => false,
+        }
+    }
+}
+
+/// Destination of raw log lines.
+pub trait LineSink {
+    /// Send single line (without new line character).
+    fn send(&mut self, line: &str) -> io::Result<()>;
+
+    /// Send lines at once.
+    fn send_batch(&mut self, lines: &[String]) -> io::Result<()> {
+        for line in lines {
+            self.send(line)?;
+        }
+        self.flush()
+    }
+
+    /// Send lines which are due, called periodically by streaming modes.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Send every line, called once at the end.
+    fn close(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+/// Open sink by URI scheme: `tcp://host:port`, `tls://host:port`, `http://host:port/path`,
+/// `https://host:port/path`, `kafka://host:port/topic` (`kafka` feature) or `-` for stdout. Lines
+/// are batched if the URI has batch parameters (see [`BatchPolicy`]).
+pub fn open(uri: &str) -> Result<Box<dyn LineSink>, String> {
+    let (uri, policy) = BatchPolicy::from_uri(uri)?;
+    let sink = open_unbatched(&uri)?;
+    Ok(batched(sink, policy))
+}
+
+/// Buffer lines of the sink in batches sent by the policy, the sink as it is without policy.
+pub fn batched(sink: Box<dyn LineSink>, policy: BatchPolicy) -> Box<dyn LineSink> {
+    if !policy.is_set() {
+        return sink;
+    }
+    Box::new(BatchingSink {
+        sink,
+        policy,
+        batch: vec![],
+        bytes: 0,
+        started: None,
+    })
+}
+
+/// Send lines of the sink no faster than the rate limit of the bucket, waiting for tokens.
+pub fn throttled(sink: Box<dyn LineSink>, bucket: TokenBucket) -> Box<dyn LineSink> {
+    Box::new(ThrottlingSink { sink, bucket })
+}
+
+fn open_unbatched(uri: &str) -> Result<Box<dyn LineSink>, String> {
+    if uri == "-" {
+        return Ok(Box::new(StdoutSink {
+            stdout: io::stdout(),
+        }));
+    }
+    let tcp = uri.strip_prefix("tcp://").map(|address| (address, false));
+    let tls = uri.strip_prefix("tls://").map(|address| (address, true));
+    if let Some((address, tls)) = tcp.or(tls) {
+        let (host, port) = address
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+            .ok_or_else(|| format!("Sink [{}] should have host:port", uri))?;
+        let stream = Stream::connect(host.trim_matches(&['[', ']'][..]), port, tls)
+            .map_err(|err| format!("Can't connect to [{}]: {}", uri, err))?;
+        return Ok(Box::new(TcpSink {
+            writer: BufWriter::new(stream),
+        }));
+    }
+    if uri.starts_with("http://") || uri.starts_with("https://") {
+        return Ok(Box::new(HttpSink {
+            url: Url::parse(uri)?,
+        }));
+    }
+    if uri.starts_with("kafka://") {
+        return open_kafka_line_sink(uri);
+    }
+
+    Err(format!(
+        "Unknown sink [{}], expected tcp://, tls://, http://, https://, kafka:// or -",
+        uri
+    ))
+}
+
+#[cfg(feature = "kafka")]
+fn open_kafka_line_sink(uri: &str) -> Result<Box<dyn LineSink>, String> {
+    let url = KafkaUrl::parse(uri)?;
+    let producer = kafka::Producer::connect(&url)
+        .map_err(|err| format!("Can't connect to [{}]: {}", uri, err))?;
+    Ok(Box::new(KafkaLineSink { producer }))
+}
+
+#[cfg(not(feature = "kafka"))]
+fn open_kafka_line_sink(_uri: &str) -> Result<Box<dyn LineSink>, String> {
+    Err("Kafka sink is not supported in this build, build with `--features kafka`".to_string())
+}
+
+/// Writes lines to the standard output.
+struct StdoutSink {
+    stdout: io::Stdout,
+}
+
+impl LineSink for StdoutSink {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        let mut stdout = self.stdout.lock();
+        stdout.write_all(line.as_bytes())?;
+        stdout.write_all(b"\n")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+/// Writes new line delimited lines to TCP connection, over TLS for `tls://`.
+struct TcpSink {
+    writer: BufWriter<Stream>,
+}
+
+impl LineSink for TcpSink {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        // lines are paced, so they have to leave the buffer right away
+        self.writer.flush()
+    }
+
+    fn send_batch(&mut self, lines: &[String]) -> io::Result<()> {
+        for line in lines {
+            self.writer.write_all(line.as_bytes())?;
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_mut().close()
+    }
+}
+
+/// Produces every line as a message of its own, deliveries are awaited when flushed.
+#[cfg(feature = "kafka")]
+struct KafkaLineSink {
+    producer: kafka::Producer,
+}
+
+#[cfg(feature = "kafka")]
+impl LineSink for KafkaLineSink {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        self.producer.send(line.as_bytes())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.producer.flush()
+    }
+}
+
+/// Sends every line as NDJSON body of POST request, batches as a single request.
+struct HttpSink {
+    url: Url,
+}
+
+impl HttpSink {
+    fn post(&self, body: &[u8]) -> io::Result<()> {
+        let response = http::post(&self.url, "application/x-ndjson", body, &[])?;
+        if !response.is_success() {
+            return Err(io::Error::other(format!(
+                "HTTP sink responded with status {}",
+                response.status
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl LineSink for HttpSink {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        let mut body = line.as_bytes().to_vec();
+        body.push(b'\n');
+        self.post(&body)
+    }
+
+    fn send_batch(&mut self, lines: &[String]) -> io::Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let mut body = vec![];
+        for line in lines {
+            body.extend_from_slice(line.as_bytes());
+            body.push(b'\n');
+        }
+        self.post(&body)
+    }
+}
+
+/// Buffers lines of the sink and sends them in batches by the policy.
+struct BatchingSink {
+    sink: Box<dyn LineSink>,
+    policy: BatchPolicy,
+    batch: Vec<String>,
+    bytes: u64,
+    /// When the first line of the batch was buffered.
+    started: Option<Instant>,
+}
+
+impl BatchingSink {
+    fn send_buffered(&mut self) -> io::Result<()> {
+        let batch = mem::take(&mut self.batch);
+        self.bytes = 0;
+        self.started = None;
+        self.sink.send_batch(&batch)
+    }
+}
+
+impl LineSink for BatchingSink {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        self.started.get_or_insert_with(Instant::now);
+        self.batch.push(line.to_string());
+        self.bytes += line.len() as u64 + 1;
+        if self.policy.is_full(self.batch.len(), self.bytes) || self.policy.is_due(self.started) {
+            self.send_buffered()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.batch.is_empty() && self.policy.is_due(self.started) {
+            self.send_buffered()?;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        if !self.batch.is_empty() {
+            self.send_buffered()?;
+        }
+        self.sink.close()
+    }
+}
+
+/// Takes a token of the bucket for every line before passing it on.
+struct ThrottlingSink {
+    sink: Box<dyn LineSink>,
+    bucket: TokenBucket,
+}
+
+impl LineSink for ThrottlingSink {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        if !self.bucket.try_take() {
+            // lines buffered by the sink leave before waiting
+            self.sink.flush()?;
+            self.bucket.take();
+        }
+        self.sink.send(line)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.sink.close()
+    }
+}
+
+/// Destination of parse results: aggregated stats of a run and optionally every parsed record.
+/// Built-in sinks are opened by URI scheme with [`open_sink`], embedders can implement their own.
+pub trait Sink {
+    /// Write aggregated stats of the run, records written afterwards belong to it.
+    fn write_report(
+        &mut self,
+        input_file: &str,
+        report: &ParseReport,
+        options: &ParseOptions,
+    ) -> io::Result<()>;
+
+    /// Write single parsed record (called after [`Sink::write_report`]).
+    fn write_record(&mut self, record: &ParsedRecord, options: &ParseOptions) -> io::Result<()>;
+
+    /// Flush everything written, called once at the end.
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+/// Open results sink by URI scheme: `file://results.parquet` (stats only), `file://results.json`
+/// (pretty-printed document), `file://results.jsonl`, `sqlite://results.db`,
+/// `http://host:port/path` (NDJSON POST), `redis://host:port` (counters, see [`RedisSink`]),
+/// `nats://host:port/subject` (`nats` feature), `kafka://host:port/topic` (`kafka` feature),
+/// `otlp://host:port` or `otlps://host:port` (metrics, `otlp` feature) or
+/// `-` for NDJSON on stdout. Records of the HTTP sink are batched by the batch parameters of the
+/// URI (see [`BatchPolicy`]).
+pub fn open_sink(uri: &str) -> Result<Box<dyn Sink>, String> {
+    let (uri, policy) = BatchPolicy::from_uri(uri)?;
+    let uri = uri.as_str();
+    if policy.is_set() && !(uri.starts_with("http://") || uri.starts_with("https://")) {
+        return Err(format!(
+            "Batch parameters are supported only by http:// results sinks: [{}]",
+            uri
+        ));
+    }
+    if uri == "-" {
+        return Ok(Box::new(NdjsonSink {
+            writer: Box::new(BufWriter::new(io::stdout())),
+        }));
+    }
+    if let Some(path) = uri.strip_prefix("file://") {
+        if path.ends_with(".parquet") {
+            return Ok(Box::new(ParquetSink::new(path)));
+        }
+        let file = File::create(path).map_err(|err| format!("Can't create [{}]: {}", path, err))?;
+        if path.ends_with(".json") {
+            return Ok(Box::new(PrettyJsonSink {
+                writer: Box::new(BufWriter::new(file)),
+                records: 0,
+            }));
+        }
+        return Ok(Box::new(NdjsonSink {
+            writer: Box::new(BufWriter::new(file)),
+        }));
+    }
+    if let Some(path) = uri.strip_prefix("sqlite://") {
+        let writer =
+            SqliteWriter::open(path).map_err(|err| format!("Can't open [{}]: {}", uri, err))?;
+        return Ok(Box::new(writer));
+    }
+    if uri.starts_with("http://") || uri.starts_with("https://") {
+        return Ok(Box::new(HttpResultsSink {
+            url: Url::parse(uri)?,
+            policy,
+            batch: vec![],
+            records: 0,
+            started: None,
+        }));
+    }
+    if uri.starts_with("redis://") {
+        let url = RedisUrl::parse(uri)?;
+        let sink = RedisSink::open(&url).map_err(|err| format!("Can't open [{}]: {}", uri, err))?;
+        return Ok(Box::new(sink));
+    }
+    if uri.starts_with("nats://") {
+        return open_nats_sink(uri);
+    }
+    if uri.starts_with("otlp://") || uri.starts_with("otlps://") {
+        return open_otlp_sink(uri);
+    }
+    if uri.starts_with("kafka://") {
+        return open_kafka_sink(uri);
+    }
+
+    Err(format!(
+        "Unknown sink [{}], expected file://, sqlite://, http://, https://, redis://, nats://, kafka://, otlp://, otlps:// or -",
+        uri
+    ))
+}
+
+/// NDJSON lines of the run: `run` line followed by a `log_type` line for every log type.
+fn report_lines(input_file: &str, report: &ParseReport, options: &ParseOptions) -> Vec<Value> {
+    let mut lines = vec![json!({
+        "kind": "run",
+        "created_at": timestamp::format(options.clock().now()),
+        "input": input_file,
+        "lines": report.lines,
+        "failed_lines": report.failed_lines,
+        "complete": report.is_complete(),
+        "error_classes": error_log::classes(&report.error_kinds),
+        "error_kinds": report.error_kinds,
+    })];
+
+    let mut log_types: Vec<_> = report.log_register.iter().collect();
+    log_types.sort_by_key(|(log_type, _)| *log_type);
+    for (log_type, elem) in log_types {
+        let line_size = &elem.line_size;
+        let mut fields = serde_json::Map::new();
+        for (field_name, field) in options.numeric_fields.iter().zip(&elem.fields) {
+            fields.insert(
+                field_name.clone(),
+                json!({"sum": field.sum, "count": field.count}),
+            );
+        }
+        let mut distinct = serde_json::Map::new();
+        for (field_name, counter) in options.distinct_fields.iter().zip(&elem.distinct) {
+            distinct.insert(field_name.clone(), json!(counter.count()));
+        }
+        let mut line = json!({
+            "kind": "log_type",
+            "log_type": log_type,
+            "counter": elem.counter,
+            "num_of_bytes": elem.num_of_bytes,
+            "errors": elem.errors,
+            "line_size": {
+                "min": line_size.min(),
+                "mean": line_size.mean(),
+                "max": line_size.max(),
+                "p50": line_size.p50(),
+                "p95": line_size.p95(),
+                "p99": line_size.p99(),
+            },
+            "fields": fields,
+            "distinct": distinct,
+        });
+        if !options.group_by.is_empty() {
+            let values = group::values(log_type, options.group_by.len());
+            let group: serde_json::Map<String, Value> = options
+                .group_by
+                .iter()
+                .zip(values)
+                .map(|(field, value)| (field.clone(), json!(value)))
+                .collect();
+            line["group"] = Value::Object(group);
+        }
+        if options.size_histogram {
+            line["line_size"]["histogram"] = line_size
+                .histogram()
+                .into_iter()
+                .map(|(lower, count)| {
+                    json!({"lower": lower, "upper": (lower * 2).max(1) - 1, "count": count})
+                })
+                .collect();
+        }
+        if let (Some(first), Some(last)) = (elem.first_seen, elem.last_seen) {
+            line["first_seen"] = json!(timestamp::format(first));
+            line["last_seen"] = json!(timestamp::format(last));
+            line["active_span_secs"] = json!(elem.active_span().unwrap_or_default().as_secs_f64());
+        }
+        if let Some(window) = options.rate_window {
+            line["lines_per_sec"] = json!(rate::lines_per_sec(elem, window));
+            let mut series = rate::series(elem, window);
+            if let Some(detector) = &options.anomaly {
+                rate::flag_anomalies(&mut series, detector);
+            }
+            line["rates"] = json!(series);
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// NDJSON line of a record.
+fn record_line(record: &ParsedRecord, options: &ParseOptions) -> Value {
+    json!({
+        "kind": "record",
+        "offset": record.offset,
+        "log_type": record.log_type,
+        "level": record.level,
+        "num_of_bytes": record.num_of_bytes,
+        "timestamp": record.timestamp.map(timestamp::format),
+        "fields": record.fields(options),
+    })
+}
+
+/// Writes results as NDJSON lines (see [`report_lines`]).
+struct NdjsonSink {
+    writer: Box<dyn Write>,
+}
+
+impl Sink for NdjsonSink {
+    fn write_report(
+        &mut self,
+        input_file: &str,
+        report: &ParseReport,
+        options: &ParseOptions,
+    ) -> io::Result<()> {
+        for line in report_lines(input_file, report, options) {
+            writeln!(self.writer, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &ParsedRecord, options: &ParseOptions) -> io::Result<()> {
+        writeln!(self.writer, "{}", record_line(record, options))
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes results as a single pretty-printed JSON document: the `run` line with its `log_types`
+/// and `records` (if any) without the `kind` field. Records are streamed into the document, so
+/// they don't have to fit in memory.
+struct PrettyJsonSink {
+    writer: Box<dyn Write>,
+    /// Number of records written.
+    records: u64,
+}
+
+/// Line without the `kind` field, which is given by its place in the document.
+fn without_kind(mut line: Value) -> Value {
+    if let Some(fields) = line.as_object_mut() {
+        fields.remove("kind");
+    }
+    line
+}
+
+/// JSON document of the report: the `run` line with its `log_types` (see [`report_lines`]).
+pub(crate) fn report_document(
+    input_file: &str,
+    report: &ParseReport,
+    options: &ParseOptions,
+) -> Value {
+    let mut lines = report_lines(input_file, report, options).into_iter();
+    let mut document = without_kind(lines.next().expect("Report starts with run line"));
+    document["log_types"] = Value::Array(lines.map(without_kind).collect());
+    document
+}
+
+impl Sink for PrettyJsonSink {
+    fn write_report(
+        &mut self,
+        input_file: &str,
+        report: &ParseReport,
+        options: &ParseOptions,
+    ) -> io::Result<()> {
+        let document = report_document(input_file, report, options);
+        let document = serde_json::to_string_pretty(&document)?;
+        // records are added before the closing brace
+        let document = document
+            .strip_suffix("\n}")
+            .expect("Document is an object with fields");
+        write!(self.writer, "{}", document)
+    }
+
+    fn write_record(&mut self, record: &ParsedRecord, options: &ParseOptions) -> io::Result<()> {
+        if self.records == 0 {
+            write!(self.writer, ",\n  \"records\": [\n    ")?;
+        } else {
+            write!(self.writer, ",\n    ")?;
+        }
+        self.records += 1;
+        let record = serde_json::to_string_pretty(&without_kind(record_line(record, options)))?;
+        // strings are escaped, so new lines are only between values
+        write!(self.writer, "{}", record.replace('\n', "\n    "))
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        if self.records > 0 {
+            write!(self.writer, "\n  ]")?;
+        }
+        writeln!(self.writer, "\n}}")?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(feature = "nats")]
+fn open_nats_sink(uri: &str) -> Result<Box<dyn Sink>, String> {
+    let url = NatsUrl::parse(uri)?;
+    let subject = url
+        .subject
+        .ok_or_else(|| format!("NATS sink needs a subject: [{}]", uri))?;
+    let connection = nats::Connection::connect(&url.host, url.port)
+        .map_err(|err| format!("Can't connect to [{}]: {}", uri, err))?;
+    Ok(Box::new(NatsSink {
+        connection,
+        subject,
+    }))
+}
+
+#[cfg(not(feature = "nats"))]
+fn open_nats_sink(_uri: &str) -> Result<Box<dyn Sink>, String> {
+    Err("NATS sink is not supported in this build, build with `--features nats`".to_string())
+}
+
+#[cfg(feature = "kafka")]
+fn open_kafka_sink(uri: &str) -> Result<Box<dyn Sink>, String> {
+    let url = KafkaUrl::parse(uri)?;
+    let producer = kafka::Producer::connect(&url)
+        .map_err(|err| format!("Can't connect to [{}]: {}", uri, err))?;
+    Ok(Box::new(KafkaSink { producer }))
+}
+
+#[cfg(not(feature = "kafka"))]
+fn open_kafka_sink(_uri: &str) -> Result<Box<dyn Sink>, String> {
+    Err("Kafka sink is not supported in this build, build with `--features kafka`".to_string())
+}
+
+#[cfg(feature = "otlp")]
+fn open_otlp_sink(uri: &str) -> Result<Box<dyn Sink>, String> {
+    Ok(Box::new(OtlpSink {
+        url: otlp::endpoint(uri)?,
+    }))
+}
+
+#[cfg(not(feature = "otlp"))]
+fn open_otlp_sink(_uri: &str) -> Result<Box<dyn Sink>, String> {
+    Err("OTLP export is not supported in this build, build with `--features otlp`".to_string())
+}
+
+/// Exports the report as OTLP metrics (see [`otlp::metrics`]), records aren't exported.
+#[cfg(feature = "otlp")]
+struct OtlpSink {
+    url: Url,
+}
+
+#[cfg(feature = "otlp")]
+impl Sink for OtlpSink {
+    fn write_report(
+        &mut self,
+        input_file: &str,
+        report: &ParseReport,
+        _options: &ParseOptions,
+    ) -> io::Result<()> {
+        otlp::export(&self.url, input_file, report)
+    }
+
+    fn write_record(&mut self, _record: &ParsedRecord, _options: &ParseOptions) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Publishes the report as a single NDJSON message and every record as a message of its own,
+/// streams capturing the subject keep them in JetStream.
+#[cfg(feature = "nats")]
+struct NatsSink {
+    connection: nats::Connection,
+    subject: String,
+}
+
+#[cfg(feature = "nats")]
+impl Sink for NatsSink {
+    fn write_report(
+        &mut self,
+        input_file: &str,
+        report: &ParseReport,
+        options: &ParseOptions,
+    ) -> io::Result<()> {
+        let mut message = vec![];
+        for line in report_lines(input_file, report, options) {
+            writeln!(message, "{}", line)?;
+        }
+        self.connection.publish(&self.subject, &message)
+    }
+
+    fn write_record(&mut self, record: &ParsedRecord, options: &ParseOptions) -> io::Result<()> {
+        let line = record_line(record, options).to_string();
+        self.connection.publish(&self.subject, line.as_bytes())
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Produces the report as a single NDJSON message and every record as a message of its own, a
+/// failed delivery fails the next write or the end of the run.
+#[cfg(feature = "kafka")]
+struct KafkaSink {
+    producer: kafka::Producer,
+}
+
+#[cfg(feature = "kafka")]
+impl Sink for KafkaSink {
+    fn write_report(
+        &mut self,
+        input_file: &str,
+        report: &ParseReport,
+        options: &ParseOptions,
+    ) -> io::Result<()> {
+        let mut message = vec![];
+        for line in report_lines(input_file, report, options) {
+            writeln!(message, "{}", line)?;
+        }
+        self.producer.send(&message)
+    }
+
+    fn write_record(&mut self, record: &ParsedRecord, options: &ParseOptions) -> io::Result<()> {
+        let line = record_line(record, options).to_string();
+        self.producer.send(line.as_bytes())
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.producer.flush()
+    }
+}
+
+/// POSTs results as NDJSON: the report right away and records in batches of about
+/// [`HTTP_BATCH_SIZE`] bytes unless the batch policy says otherwise.
+struct HttpResultsSink {
+    url: Url,
+    policy: BatchPolicy,
+    batch: Vec<u8>,
+    records: usize,
+    /// When the first record of the batch was written.
+    started: Option<Instant>,
+}
+
+impl HttpResultsSink {
+    fn post_batch(&mut self) -> io::Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let response = http::post(&self.url, "application/x-ndjson", &self.batch, &[])?;
+        self.batch.clear();
+        self.records = 0;
+        self.started = None;
+        if !response.is_success() {
+            return Err(io::Error::other(format!(
+                "HTTP sink responded with status {}",
+                response.status
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Sink for HttpResultsSink {
+    fn write_report(
+        &mut self,
+        input_file: &str,
+        report: &ParseReport,
+        options: &ParseOptions,
+    ) -> io::Result<()> {
+        for line in report_lines(input_file, report, options) {
+            writeln!(self.batch, "{}", line)?;
+        }
+        self.post_batch()
+    }
+
+    fn write_record(&mut self, record: &ParsedRecord, options: &ParseOptions) -> io::Result<()> {
+        writeln!(self.batch, "{}", record_line(record, options))?;
+        self.records += 1;
+        let started = *self.started.get_or_insert_with(Instant::now);
+        let max_bytes = self.policy.max_bytes.unwrap_or(HTTP_BATCH_SIZE);
+        if self.policy.is_full(self.records, self.batch.len() as u64)
+            || self.batch.len() as u64 >= max_bytes
+            || self.policy.is_due(Some(started))
+        {
+            self.post_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.post_batch()
+    }
+}
@@ -0,0 +1,205 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Size of the time bucket events are counted in.
+const BUCKET_SECS: u64 = 60;
+
+/// Status codes from this value are treated as failed requests.
+const SERVER_ERROR_STATUS: f64 = 500.0;
+
+/// Multi-window burn rate alerts (long window, short window, burn rate threshold, severity)
+/// as recommended by the Google SRE workbook for a 30 day period.
+const ALERT_WINDOWS: [(u64, u64, f64, &str); 4] = [
+    (60 * 60, 5 * 60, 14.4, "page"),
+    (6 * 60 * 60, 30 * 60, 6.0, "page"),
+    (24 * 60 * 60, 2 * 60 * 60, 3.0, "ticket"),
+    (3 * 24 * 60 * 60, 6 * 60 * 60, 1.0, "ticket"),
+];
+
+/// Service level objective, e.g. 99% of requests under 300 ms over 30 days.
+#[derive(Debug, Clone)]
+pub struct Slo {
+    /// Fraction of good events, e.g. 0.99.
+    pub objective: f64,
+    /// Requests slower than this are bad.
+    pub latency_threshold: Option<f64>,
+    pub period: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Counts {
+    total: u64,
+    bad: u64,
+}
+
+/// Burn rate within a window ending at the newest event.
+#[derive(Serialize, Debug)]
+pub struct BurnRate {
+    pub window_secs: u64,
+    pub total: u64,
+    pub bad: u64,
+    /// How many times faster than allowed the error budget is spent (1.0 spends exactly
+    /// the whole budget over the SLO period).
+    pub burn_rate: f64,
+}
+
+/// SLO compliance within the analyzed window.
+#[derive(Serialize, Debug)]
+pub struct SloReport {
+    pub total: u64,
+    pub bad: u64,
+    /// Events without timestamp are counted in totals but not in burn rate windows.
+    pub without_timestamp: u64,
+    pub compliance: f64,
+    pub window_secs: u64,
+    /// Fraction of the error budget of the whole SLO period spent within the analyzed window.
+    pub budget_consumed: f64,
+    pub burn_rates: Vec<BurnRate>,
+    /// Multi-window burn rate alerts which would fire at the end of the analyzed window.
+    pub alerts: Vec<String>,
+}
+
+/// Counts good and bad events in time buckets to compute error budget and burn rates.
+pub struct SloTracker {
+    slo: Slo,
+    buckets: BTreeMap<u64, Counts>,
+    counts: Counts,
+    without_timestamp: u64,
+}
+
+impl SloTracker {
+    pub fn new(slo: Slo) -> Self {
+        Self {
+            slo,
+            buckets: BTreeMap::new(),
+            counts: Counts::default(),
+            without_timestamp: 0,
+        }
+    }
+
+    /// Register event, events without latency and status are not SLI events and are skipped.
+    pub fn observe(
+        &mut self,
+        timestamp: Option<SystemTime>,
+        latency: Option<f64>,
+        status: Option<f64>,
+    ) {
+        if latency.is_none() && status.is_none() {
+            return;
+        }
+
+        let slow = match (latency, self.slo.latency_threshold) {
+            (Some(latency), Some(threshold)) => latency > threshold,
+            _ => false,
+        };
+        let failed = status.is_some_and(|status| status >= SERVER_ERROR_STATUS);
+        let bad = (slow || failed) as u64;
+
+        self.counts.total += 1;
+        self.counts.bad += bad;
+
+        match timestamp.and_then(|timestamp| timestamp.duration_since(UNIX_EPOCH).ok()) {
+            Some(since_epoch) => {
+                let bucket = self
+                    .buckets
+                    .entry(since_epoch.as_secs() / BUCKET_SECS)
+                    .or_default();
+                bucket.total += 1;
+                bucket.bad += bad;
+            }
+            None => self.without_timestamp += 1,
+        }
+    }
+
+    /// Build report, `window` overrides span between the first and the last event.
+    pub fn report(&self, window: Option<Duration>) -> SloReport {
+        let allowed_bad_ratio = 1.0 - self.slo.objective;
+        let ratio = |counts: Counts| {
+            if counts.total == 0 {
+                0.0
+            } else {
+                counts.bad as f64 / counts.total as f64
+            }
+        };
+        let burn_rate = |counts: Counts| {
+            if allowed_bad_ratio <= 0.0 {
+                return if counts.bad > 0 { f64::INFINITY } else { 0.0 };
+            }
+            ratio(counts) / allowed_bad_ratio
+        };
+
+        let first = self.buckets.keys().next().copied();
+        let last = self.buckets.keys().next_back().copied();
+        let window = window.unwrap_or_else(|| match (first, last) {
+            (Some(first), Some(last)) => Duration::from_secs((last - first + 1) * BUCKET_SECS),
+            _ => Duration::from_secs(0),
+        });
+
+        let in_window = |window_secs: u64| -> Counts {
+            let last = match last {
+                Some(last) => last,
+                None => return Counts::default(),
+            };
+            let from = (last + 1).saturating_sub(window_secs / BUCKET_SECS);
+            self.buckets
+                .range(from..=last)
+                .fold(Counts::default(), |acc, (_, counts)| Counts {
+                    total: acc.total + counts.total,
+                    bad: acc.bad + counts.bad,
+                })
+        };
+
+        let mut window_lengths: Vec<u64> = ALERT_WINDOWS
+            .iter()
+            .flat_map(|(long, short, _, _)| vec![*long, *short])
+            .collect();
+        window_lengths.sort_unstable();
+        window_lengths.dedup();
+        let burn_rates = window_lengths
+            .into_iter()
+            .map(|window_secs| {
+                let counts = in_window(window_secs);
+                BurnRate {
+                    window_secs,
+                    total: counts.total,
+                    bad: counts.bad,
+                    burn_rate: burn_rate(counts),
+                }
+            })
+            .collect();
+
+        let alerts = ALERT_WINDOWS
+            .iter()
+            .filter(|(long, short, threshold, _)| {
+                burn_rate(in_window(*long)) > *threshold
+                    && burn_rate(in_window(*short)) > *threshold
+            })
+            .map(|(long, short, threshold, severity)| {
+                format!(
+                    "{}: burn rate above {} over {} and {}",
+                    severity,
+                    threshold,
+                    humantime::format_duration(Duration::from_secs(*long)),
+                    humantime::format_duration(Duration::from_secs(*short))
+                )
+            })
+            .collect();
+
+        let period_secs = self.slo.period.as_secs_f64();
+        SloReport {
+            total: self.counts.total,
+            bad: self.counts.bad,
+            without_timestamp: self.without_timestamp,
+            compliance: 1.0 - ratio(self.counts),
+            window_secs: window.as_secs(),
+            budget_consumed: if period_secs > 0.0 {
+                burn_rate(self.counts) * window.as_secs_f64() / period_secs
+            } else {
+                0.0
+            },
+            burn_rates,
+            alerts,
+        }
+    }
+}
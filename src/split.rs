@@ -0,0 +1,293 @@
+use crate::group;
+use crate::parser::{self, ParseOptions, ParsedRecord};
+use crate::projection::{self, Condition};
+use log::error;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Extension of the files of groups.
+pub const EXTENSION: &str = "log";
+
+/// File name (without extension) of lines of groups beyond the limit of files.
+pub const OVERFLOW: &str = "_overflow";
+
+/// Longest file name of a group (without extension), longer values are cut.
+const MAX_NAME_LEN: usize = 100;
+
+/// Raw lines of records matching all conditions, routed to a file per value of the split-by
+/// fields, e.g. to hand just the error lines or lines of a single service to another team.
+#[derive(Debug, Clone)]
+pub struct Split {
+    fields: Vec<String>,
+    conditions: Vec<Condition>,
+    out_dir: PathBuf,
+    max_files: usize,
+    /// Fields extracted by the parser: split-by fields followed by fields of conditions.
+    value_fields: Vec<String>,
+}
+
+impl Split {
+    /// Split by the fields into files in the directory, lines of groups beyond `max_files` go
+    /// to a single [`OVERFLOW`] file.
+    pub fn new(
+        fields: Vec<String>,
+        conditions: Vec<Condition>,
+        out_dir: PathBuf,
+        max_files: usize,
+    ) -> Self {
+        let mut value_fields = fields.clone();
+        for condition in &conditions {
+            if !value_fields.contains(&condition.field) {
+                value_fields.push(condition.field.clone());
+            }
+        }
+
+        Self {
+            fields,
+            conditions,
+            out_dir,
+            max_files,
+            value_fields,
+        }
+    }
+
+    /// Parser options extracting split-by fields and fields of conditions.
+    pub fn parse_options(&self, options: &ParseOptions) -> ParseOptions {
+        ParseOptions {
+            value_fields: self.value_fields.clone(),
+            ..options.clone()
+        }
+    }
+
+    fn value<'a>(&self, record: &'a ParsedRecord, field: &str) -> Option<&'a Value> {
+        let idx = self.value_fields.iter().position(|name| name == field)?;
+        record.values.get(idx)?.as_ref()
+    }
+
+    pub fn matches(&self, record: &ParsedRecord) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(self.value(record, &condition.field)))
+    }
+
+    /// Values of the split-by fields of the record, missing fields are [`group::MISSING`].
+    fn group_values(&self, record: &ParsedRecord) -> Vec<String> {
+        self.fields
+            .iter()
+            .map(|field| {
+                self.value(record, field)
+                    .filter(|value| !value.is_null())
+                    .map(projection::text)
+                    .unwrap_or_else(|| group::MISSING.to_string())
+            })
+            .collect()
+    }
+}
+
+/// File of a group written by [`split_files`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitFile {
+    /// Values of the split-by fields joined by [`group::SEPARATOR`].
+    pub group: String,
+    pub path: PathBuf,
+    pub lines: u64,
+}
+
+/// Summary of split records.
+#[derive(Debug, Default)]
+pub struct SplitSummary {
+    pub records: u64,
+    pub matched: u64,
+    pub failed_lines: u64,
+    /// Files of groups in the order they were created.
+    pub files: Vec<SplitFile>,
+    /// Lines of groups beyond the limit of files, written to the [`OVERFLOW`] file.
+    pub overflow_lines: u64,
+}
+
+/// File name of the group values, characters other than ASCII letters, digits, `.`, `-` and
+/// `_` are replaced by `_` (so are leading dots and dashes), values of several fields are
+/// joined by `_`.
+pub fn file_name(values: &[String]) -> String {
+    let name: String = values
+        .iter()
+        .map(|value| {
+            value
+                .chars()
+                .map(|c| match c {
+                    'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+                    _ => '_',
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("_");
+    let name: String = name
+        .chars()
+        .enumerate()
+        .map(|(idx, c)| match (idx, c) {
+            (0, '.') | (0, '-') => '_',
+            _ => c,
+        })
+        .take(MAX_NAME_LEN)
+        .collect();
+    match name.is_empty() {
+        true => "_".to_string(),
+        false => name,
+    }
+}
+
+/// Open files of groups, file names of different groups stay different after sanitizing.
+struct Outputs<'a> {
+    split: &'a Split,
+    files: HashMap<String, (usize, BufWriter<File>)>,
+    names: HashSet<String>,
+    overflow: Option<BufWriter<File>>,
+    /// CSV header of the first input, every file starts with it.
+    header: Option<String>,
+    summary: SplitSummary,
+}
+
+impl<'a> Outputs<'a> {
+    fn new(split: &'a Split) -> Self {
+        Self {
+            split,
+            files: HashMap::new(),
+            // groups named like the overflow file get a suffix
+            names: HashSet::from([OVERFLOW.to_string()]),
+            overflow: None,
+            header: None,
+            summary: SplitSummary::default(),
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.split.out_dir.join(format!("{}.{}", name, EXTENSION))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<BufWriter<File>> {
+        let mut file = BufWriter::new(File::create(path)?);
+        if let Some(header) = &self.header {
+            write_line(&mut file, header)?;
+        }
+        Ok(file)
+    }
+
+    fn write(&mut self, values: Vec<String>, line: &str) -> io::Result<()> {
+        let group = group::key(&values);
+        if !self.files.contains_key(&group) && self.files.len() >= self.split.max_files {
+            if self.overflow.is_none() {
+                self.overflow = Some(self.create(&self.path(OVERFLOW))?);
+            }
+            self.summary.overflow_lines += 1;
+            return write_line(self.overflow.as_mut().expect("Overflow is open"), line);
+        }
+
+        if !self.files.contains_key(&group) {
+            let name = file_name(&values);
+            let mut unique = name.clone();
+            let mut suffix = 1;
+            while !self.names.insert(unique.clone()) {
+                suffix += 1;
+                unique = format!("{}-{}", name, suffix);
+            }
+            let path = self.path(&unique);
+            let file = self.create(&path)?;
+            self.files
+                .insert(group.clone(), (self.summary.files.len(), file));
+            self.summary.files.push(SplitFile {
+                group: group.clone(),
+                path,
+                lines: 0,
+            });
+        }
+        let (idx, file) = self.files.get_mut(&group).expect("File is open");
+        self.summary.files[*idx].lines += 1;
+        write_line(file, line)
+    }
+
+    fn finish(mut self) -> io::Result<SplitSummary> {
+        for (_, file) in self.files.values_mut() {
+            file.flush()?;
+        }
+        if let Some(overflow) = self.overflow.as_mut() {
+            overflow.flush()?;
+        }
+        Ok(self.summary)
+    }
+}
+
+/// Raw line of the record, ended by a new line character (records split on other delimiters
+/// don't keep them).
+fn write_line(out: &mut dyn Write, line: &str) -> io::Result<()> {
+    out.write_all(line.as_bytes())?;
+    if !line.ends_with('\n') {
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Write raw lines of matching records of the files to the file of their group in the output
+/// directory (created if missing), one file after another. Files are read by a single thread,
+/// so lines keep the order of the inputs in every group file. Files of CSV inputs with header
+/// start with the header of the first input.
+pub fn split_files<P: AsRef<Path>>(
+    input_files: &[P],
+    options: &ParseOptions,
+    split: &Split,
+) -> io::Result<SplitSummary> {
+    let options = split.parse_options(options);
+    fs::create_dir_all(&split.out_dir)?;
+    let mut outputs = Outputs::new(split);
+
+    for input_file in input_files {
+        split_file(input_file.as_ref(), &options, &mut outputs)?;
+    }
+
+    outputs.finish()
+}
+
+fn split_file(path: &Path, options: &ParseOptions, outputs: &mut Outputs) -> io::Result<()> {
+    let mut scanner = options.file_scanner_at(path, 0)?;
+    let mut offset = 0;
+    if options.csv.as_ref().is_some_and(|csv| csv.has_header) {
+        if let Some(header) = scanner.next_record()? {
+            offset = header.num_of_bytes;
+            if outputs.header.is_none() {
+                outputs.header = Some(header.text.into_owned());
+            }
+        }
+    }
+
+    // records outside of the read ranges can't match, they're skipped unread
+    while let Some(next) = options.next_read(path, offset) {
+        if next > offset {
+            scanner = options.file_scanner_at(path, next)?;
+            offset = next;
+        }
+        let Some(line) = scanner.next_record()? else {
+            break;
+        };
+        outputs.summary.records += 1;
+        let record = parser::parse_scanned(offset, &line, options);
+        offset += line.num_of_bytes;
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                error!("{}", err);
+                outputs.summary.failed_lines += 1;
+                continue;
+            }
+        };
+        if !outputs.split.matches(&record) {
+            continue;
+        }
+        outputs.summary.matched += 1;
+        let values = outputs.split.group_values(&record);
+        outputs.write(values, &line.text)?;
+    }
+    Ok(())
+}
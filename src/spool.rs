@@ -0,0 +1,268 @@
+use crate::clock::{Clock, SystemClock};
+use crate::sink::{self, LineSink};
+use log::{error, info, warn};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Delay of the first retry after the sink fails, it doubles with every failed retry.
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Batch of lines stored in a segment file named `<sequence>-<number of lines>.ndjson`.
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    seq: u64,
+    lines: u64,
+    size: u64,
+}
+
+impl Segment {
+    fn file_name(&self) -> String {
+        format!("{:020}-{}.ndjson", self.seq, self.lines)
+    }
+
+    fn parse(file_name: &str, size: u64) -> Option<Self> {
+        let (seq, lines) = file_name.strip_suffix(".ndjson")?.split_once('-')?;
+        Some(Self {
+            seq: seq.parse().ok()?,
+            lines: lines.parse().ok()?,
+            size,
+        })
+    }
+}
+
+/// Bounded on-disk queue of line batches. Batches are kept in files of the directory, so the
+/// queue survives restarts; once it's larger than the maximum size, the oldest batches are
+/// dropped.
+#[derive(Debug)]
+pub struct Spool {
+    dir: PathBuf,
+    max_size: u64,
+    segments: VecDeque<Segment>,
+    size: u64,
+}
+
+impl Spool {
+    /// Open spool in the directory (it's created if missing) with batches left by earlier runs.
+    pub fn open<P: AsRef<Path>>(dir: P, max_size: u64) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut segments = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            // unfinished writes have another extension
+            if let Some(segment) =
+                Segment::parse(&file_name.to_string_lossy(), entry.metadata()?.len())
+            {
+                segments.push(segment);
+            }
+        }
+        segments.sort_by_key(|segment| segment.seq);
+
+        Ok(Self {
+            dir,
+            max_size,
+            size: segments.iter().map(|segment| segment.size).sum(),
+            segments: segments.into(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Number of spooled lines.
+    pub fn lines(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.lines).sum()
+    }
+
+    /// Spooled bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Append the batch, returns number of lines of the oldest batches dropped to stay within
+    /// the maximum size (the new batch is always kept).
+    pub fn push(&mut self, lines: &[String]) -> io::Result<u64> {
+        let mut segment = Segment {
+            seq: self.segments.back().map_or(0, |segment| segment.seq + 1),
+            lines: lines.len() as u64,
+            size: 0,
+        };
+        // written under a temporary name, so a crash doesn't leave half of the batch
+        let path = self.dir.join(segment.file_name());
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        for line in lines {
+            writer.write_all(line.as_bytes())?;
+            writer.write_all(b"\n")?;
+            segment.size += line.len() as u64 + 1;
+        }
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        fs::rename(&tmp_path, &path)?;
+        self.size += segment.size;
+        self.segments.push_back(segment);
+
+        let mut dropped = 0;
+        while self.size > self.max_size && self.segments.len() > 1 {
+            dropped += self.segments.front().map_or(0, |segment| segment.lines);
+            self.pop()?;
+        }
+        Ok(dropped)
+    }
+
+    /// The oldest batch.
+    pub fn front(&self) -> io::Result<Option<Vec<String>>> {
+        let segment = match self.segments.front() {
+            Some(segment) => segment,
+            None => return Ok(None),
+        };
+        let file = File::open(self.dir.join(segment.file_name()))?;
+        BufReader::new(file)
+            .lines()
+            .collect::<io::Result<_>>()
+            .map(Some)
+    }
+
+    /// Remove the oldest batch.
+    pub fn pop(&mut self) -> io::Result<()> {
+        if let Some(segment) = self.segments.pop_front() {
+            self.size -= segment.size;
+            fs::remove_file(self.dir.join(segment.file_name()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Line sink which doesn't block on a slow or unavailable sink: lines are sent in batches on
+/// flush, a batch which can't be delivered is spooled and the sink is retried with growing
+/// delay. Spooled batches are delivered first, in order, once the sink recovers. A batch
+/// failed in the middle is sent again as a whole, so lines are delivered at least once.
+pub struct SpooledSink {
+    uri: String,
+    sink: Option<Box<dyn LineSink>>,
+    spool: Spool,
+    batch: Vec<String>,
+    retry_at: Instant,
+    retry_delay: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl SpooledSink {
+    /// Sink is opened by URI like [`sink::open`], and opened again after every failure.
+    pub fn new(uri: &str, spool: Spool) -> Self {
+        if !spool.is_empty() {
+            info!("Spool of {} has {} lines to deliver", uri, spool.lines());
+        }
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        Self {
+            uri: uri.to_string(),
+            sink: None,
+            spool,
+            batch: vec![],
+            retry_at: clock.instant(),
+            retry_delay: MIN_RETRY_DELAY,
+            clock,
+        }
+    }
+
+    /// Schedule retries of the failed sink by the clock instead of the system one.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.retry_at = clock.instant();
+        self.clock = clock;
+        self
+    }
+
+    pub fn spool(&self) -> &Spool {
+        &self.spool
+    }
+
+    /// Deliver spooled batches, oldest first, until the sink fails.
+    fn drain(&mut self) -> io::Result<()> {
+        let mut delivered = 0;
+        while let Some(batch) = self.spool.front()? {
+            if !self.deliver(&batch) {
+                break;
+            }
+            delivered += batch.len();
+            self.spool.pop()?;
+        }
+        if delivered > 0 {
+            info!(
+                "Delivered {} spooled lines to {}, {} lines left",
+                delivered,
+                self.uri,
+                self.spool.lines()
+            );
+        }
+        Ok(())
+    }
+
+    /// Send the batch, whether it was delivered. Failed sink is retried later.
+    fn deliver(&mut self, batch: &[String]) -> bool {
+        match self.send_batch(batch) {
+            Ok(()) => {
+                self.retry_delay = MIN_RETRY_DELAY;
+                true
+            }
+            Err(err) => {
+                warn!(
+                    "Can't forward lines to {}: {}, spooling them and retrying in {:?}",
+                    self.uri, err, self.retry_delay
+                );
+                self.sink = None;
+                self.retry_at = self.clock.instant() + self.retry_delay;
+                self.retry_delay = (self.retry_delay * 2).min(MAX_RETRY_DELAY);
+                false
+            }
+        }
+    }
+
+    fn send_batch(&mut self, batch: &[String]) -> io::Result<()> {
+        if self.sink.is_none() {
+            self.sink = Some(sink::open(&self.uri).map_err(io::Error::other)?);
+        }
+        self.sink.as_mut().expect("Sink is open").send_batch(batch)
+    }
+}
+
+impl LineSink for SpooledSink {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        self.batch.push(line.to_string());
+        Ok(())
+    }
+
+    /// Deliver the batch unless the sink failed recently, spool it otherwise. Errors are only
+    /// returned if the spool can't be written.
+    fn flush(&mut self) -> io::Result<()> {
+        let batch = mem::take(&mut self.batch);
+        let available = self.clock.instant() >= self.retry_at;
+        if available {
+            self.drain()?;
+        }
+        if batch.is_empty() {
+            return Ok(());
+        }
+        // spooled lines go first, so the batch waits behind them
+        if available && self.spool.is_empty() && self.deliver(&batch) {
+            return Ok(());
+        }
+        let dropped = self.spool.push(&batch)?;
+        if dropped > 0 {
+            error!(
+                "Spool of {} is over {} bytes, dropped {} oldest lines",
+                self.uri, self.spool.max_size, dropped
+            );
+        }
+        Ok(())
+    }
+}
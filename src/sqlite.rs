@@ -0,0 +1,202 @@
+use crate::parser::{ParseOptions, ParseReport, ParsedRecord};
+use crate::sink::Sink;
+use crate::timestamp;
+use rusqlite::{params, Connection};
+use serde_json::Value;
+use std::io;
+use std::path::Path;
+
+/// Tables are created on first use, so every run is appended to the same database.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    created_at TEXT NOT NULL,
+    input TEXT NOT NULL,
+    lines INTEGER NOT NULL,
+    failed_lines INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS log_types (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    log_type TEXT NOT NULL,
+    counter INTEGER NOT NULL,
+    num_of_bytes INTEGER NOT NULL,
+    errors INTEGER NOT NULL,
+    line_size_min INTEGER NOT NULL,
+    line_size_mean REAL NOT NULL,
+    line_size_max INTEGER NOT NULL,
+    line_size_p50 INTEGER NOT NULL,
+    line_size_p95 INTEGER NOT NULL,
+    line_size_p99 INTEGER NOT NULL,
+    PRIMARY KEY (run_id, log_type)
+);
+CREATE TABLE IF NOT EXISTS fields (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    log_type TEXT NOT NULL,
+    field TEXT NOT NULL,
+    sum REAL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (run_id, log_type, field)
+);
+CREATE TABLE IF NOT EXISTS records (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    offset INTEGER NOT NULL,
+    log_type TEXT NOT NULL,
+    level TEXT,
+    num_of_bytes INTEGER NOT NULL,
+    timestamp TEXT,
+    fields TEXT NOT NULL,
+    PRIMARY KEY (run_id, offset)
+);
+";
+
+/// Writes results into SQLite database (SQLite is built in). Rows are inserted with prepared
+/// statements in a single transaction committed by [`finish`], a writer dropped before is rolled
+/// back.
+///
+/// [`finish`]: SqliteWriter::finish
+pub struct SqliteWriter {
+    connection: Connection,
+    /// Run the records written belong to, the last one written.
+    run_id: Option<i64>,
+}
+
+impl SqliteWriter {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let connection = Connection::open(path).map_err(sqlite_error)?;
+        connection
+            .execute_batch(&format!("BEGIN;{}", SCHEMA))
+            .map_err(sqlite_error)?;
+
+        Ok(Self {
+            connection,
+            run_id: None,
+        })
+    }
+
+    /// Insert run with aggregated stats, records written afterwards belong to this run.
+    pub fn write_report(
+        &mut self,
+        input_file: &str,
+        report: &ParseReport,
+        options: &ParseOptions,
+    ) -> io::Result<()> {
+        self.connection
+            .prepare_cached(
+                "INSERT INTO runs (created_at, input, lines, failed_lines) VALUES (?1, ?2, ?3, ?4)",
+            )
+            .and_then(|mut statement| {
+                statement.execute(params![
+                    timestamp::format(options.clock().now()),
+                    input_file,
+                    report.lines,
+                    report.failed_lines
+                ])
+            })
+            .map_err(sqlite_error)?;
+        let run_id = self.connection.last_insert_rowid();
+        self.run_id = Some(run_id);
+
+        let mut log_types = self
+            .connection
+            .prepare_cached(
+                "INSERT INTO log_types VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )
+            .map_err(sqlite_error)?;
+        let mut fields = self
+            .connection
+            .prepare_cached("INSERT INTO fields VALUES (?1, ?2, ?3, ?4, ?5)")
+            .map_err(sqlite_error)?;
+        for (log_type, elem) in &report.log_register {
+            let line_size = &elem.line_size;
+            log_types
+                .execute(params![
+                    run_id,
+                    log_type,
+                    elem.counter,
+                    elem.num_of_bytes,
+                    elem.errors,
+                    line_size.min(),
+                    line_size.mean(),
+                    line_size.max(),
+                    line_size.p50(),
+                    line_size.p95(),
+                    line_size.p99()
+                ])
+                .map_err(sqlite_error)?;
+            // fields are kept in the same order as numeric fields in options
+            for (field_name, field) in options.numeric_fields.iter().zip(&elem.fields) {
+                // SQLite stores infinite sums as they are, NaN ones as NULL
+                let sum = Some(field.sum).filter(|sum| !sum.is_nan());
+                fields
+                    .execute(params![run_id, log_type, field_name, sum, field.count])
+                    .map_err(sqlite_error)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Insert single record with its extracted fields stored as JSON object.
+    pub fn write_record(
+        &mut self,
+        record: &ParsedRecord,
+        options: &ParseOptions,
+    ) -> io::Result<()> {
+        let run_id = self.run_id.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Records are written after the report of their run",
+            )
+        })?;
+        let fields = record.fields(options);
+
+        self.connection
+            .prepare_cached("INSERT INTO records VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")
+            .and_then(|mut statement| {
+                statement.execute(params![
+                    run_id,
+                    record.offset,
+                    record.log_type,
+                    record.level,
+                    record.num_of_bytes,
+                    record.timestamp.map(timestamp::format),
+                    Value::Object(fields).to_string()
+                ])
+            })
+            .map(|_| ())
+            .map_err(sqlite_error)
+    }
+
+    /// Commit transaction and close the database.
+    pub fn finish(self) -> io::Result<()> {
+        self.connection
+            .execute_batch("COMMIT;")
+            .map_err(sqlite_error)?;
+        self.connection
+            .close()
+            .map_err(|(_, err)| sqlite_error(err))
+    }
+}
+
+impl Sink for SqliteWriter {
+    fn write_report(
+        &mut self,
+        input_file: &str,
+        report: &ParseReport,
+        options: &ParseOptions,
+    ) -> io::Result<()> {
+        SqliteWriter::write_report(self, input_file, report, options)
+    }
+
+    fn write_record(&mut self, record: &ParsedRecord, options: &ParseOptions) -> io::Result<()> {
+        SqliteWriter::write_record(self, record, options)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        SqliteWriter::finish(*self)
+    }
+}
+
+fn sqlite_error(err: rusqlite::Error) -> io::Error {
+    io::Error::other(format!("SQLite failed: {}", err))
+}
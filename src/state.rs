@@ -0,0 +1,107 @@
+use crate::parser::{ParseOptions, ParseReport};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Version of the saved state format, states of other versions can't be loaded. Version 2
+/// hashes distinct values with the stable [`crate::distinct::hash_value`].
+const VERSION: u32 = 2;
+
+/// Options the registers depend on, states can be merged only if they are the same.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StateOptions {
+    pub numeric_fields: Vec<String>,
+    /// Number of leading numeric fields reported as sums, the rest is reported as averages.
+    pub sum_fields: usize,
+    pub distinct_fields: Vec<String>,
+    pub group_by: Vec<String>,
+    pub rate_window: Option<u64>,
+}
+
+impl StateOptions {
+    pub fn new(options: &ParseOptions, sum_fields: usize) -> Self {
+        Self {
+            numeric_fields: options.numeric_fields.clone(),
+            sum_fields,
+            distinct_fields: options.distinct_fields.clone(),
+            group_by: options.group_by.clone(),
+            rate_window: options.rate_window,
+        }
+    }
+
+    /// Parse options giving registers of the state.
+    pub fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            numeric_fields: self.numeric_fields.clone(),
+            distinct_fields: self.distinct_fields.clone(),
+            group_by: self.group_by.clone(),
+            rate_window: self.rate_window,
+            ..ParseOptions::default()
+        }
+    }
+}
+
+/// Aggregation result saved to a file, so shards parsed on different machines can be merged
+/// into a single report afterwards (see [`merge`]).
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SavedState {
+    version: u32,
+    /// Input files the state was built from.
+    pub inputs: Vec<String>,
+    pub options: StateOptions,
+    pub report: ParseReport,
+}
+
+impl SavedState {
+    pub fn new(input: &str, report: ParseReport, options: StateOptions) -> Self {
+        Self {
+            version: VERSION,
+            inputs: vec![input.to_string()],
+            options,
+            report,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|err| format!("Can't open state {}: {}", path.display(), err))?;
+        let state: SavedState = serde_json::from_reader(BufReader::new(file))
+            .map_err(|err| format!("Invalid state {}: {}", path.display(), err))?;
+        if state.version != VERSION {
+            return Err(format!(
+                "State {} has version {}, expected {}",
+                path.display(),
+                state.version,
+                VERSION
+            ));
+        }
+
+        Ok(state)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()
+    }
+}
+
+/// Merge states into one, they have to be built with the same options.
+pub fn merge(states: Vec<SavedState>) -> Result<SavedState, String> {
+    let mut states = states.into_iter();
+    let mut merged = states.next().ok_or("No states to merge")?;
+    for state in states {
+        if state.options != merged.options {
+            return Err(format!(
+                "State of {:?} was built with other options ({:?}) than state of {:?} ({:?})",
+                state.inputs, state.options, merged.inputs, merged.options
+            ));
+        }
+        merged.inputs.extend(state.inputs);
+        merged.report += state.report;
+    }
+
+    Ok(merged)
+}
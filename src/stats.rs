@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+
+/// Relative accuracy of approximated percentiles.
+const RELATIVE_ACCURACY: f64 = 0.01;
+
+/// Line size statistics: exact min/max/mean and approximate percentiles.
+///
+/// Percentiles are computed with DDSketch - values are counted in logarithmic buckets, so every
+/// percentile is within [`RELATIVE_ACCURACY`] of the real value and sketches can be merged.
+#[derive(Serialize, Deserialize, Hash, Eq, PartialEq, Debug, Clone, Default)]
+pub struct Stats {
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+    zero_count: u64,
+    buckets: BTreeMap<i32, u64>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stats with a single value.
+    pub fn of(value: u64) -> Self {
+        let mut stats = Self::new();
+        stats.add(value);
+        stats
+    }
+
+    pub fn add(&mut self, value: u64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        // the mean of sizes beyond u64::MAX in total stays approximate
+        self.sum = self.sum.saturating_add(value);
+
+        if value == 0 {
+            self.zero_count += 1;
+        } else {
+            *self.buckets.entry(bucket_index(value)).or_insert(0) += 1;
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn min(&self) -> u64 {
+        self.min
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum as f64 / self.count as f64
+    }
+
+    /// Approximate value at given quantile (0.0 - 1.0).
+    pub fn percentile(&self, quantile: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+
+        let rank = (quantile.clamp(0.0, 1.0) * (self.count - 1) as f64).round() as u64;
+        if rank < self.zero_count {
+            return 0;
+        }
+
+        let mut seen = self.zero_count;
+        for (idx, bucket_count) in &self.buckets {
+            seen += bucket_count;
+            if seen > rank {
+                return bucket_value(*idx).clamp(self.min, self.max);
+            }
+        }
+
+        self.max
+    }
+
+    pub fn p50(&self) -> u64 {
+        self.percentile(0.50)
+    }
+
+    pub fn p95(&self) -> u64 {
+        self.percentile(0.95)
+    }
+
+    pub fn p99(&self) -> u64 {
+        self.percentile(0.99)
+    }
+
+    /// Approximate number of values per power of two range: `(lower bound, count)` of values in
+    /// `lower..2 * lower` (zeros are counted under `0`), only non-empty ranges.
+    pub fn histogram(&self) -> Vec<(u64, u64)> {
+        let mut ranges: BTreeMap<u64, u64> = BTreeMap::new();
+        if self.zero_count > 0 {
+            ranges.insert(0, self.zero_count);
+        }
+        for (idx, bucket_count) in &self.buckets {
+            let value = bucket_value(*idx).clamp(self.min, self.max).max(1);
+            let lower = 1 << (u64::BITS - 1 - value.leading_zeros());
+            *ranges.entry(lower).or_insert(0) += bucket_count;
+        }
+
+        ranges.into_iter().collect()
+    }
+
+    /// [`Stats::histogram`] as a bar per power of two range from the smallest to the largest
+    /// value, heights are relative to the fullest range and empty ranges are blank.
+    pub fn sparkline(&self) -> String {
+        let histogram = self.histogram();
+        let (first, last) = match (histogram.first(), histogram.last()) {
+            (Some((first, _)), Some((last, _))) => (*first, *last),
+            _ => return String::new(),
+        };
+        let highest = histogram.iter().map(|(_, count)| *count).max().unwrap_or(1);
+        let counts: BTreeMap<u64, u64> = histogram.into_iter().collect();
+
+        let mut lower = first;
+        let mut sparkline = String::new();
+        loop {
+            sparkline.push(match counts.get(&lower) {
+                Some(count) => {
+                    let level = count * (BARS.len() as u64 - 1) / highest;
+                    BARS[level as usize]
+                }
+                None => ' ',
+            });
+            if lower >= last {
+                return sparkline;
+            }
+            lower = (lower * 2).max(1);
+        }
+    }
+}
+
+/// Bars of [`Stats::sparkline`] from the lowest to the highest.
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, rhs: Self) {
+        if rhs.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = rhs;
+            return;
+        }
+
+        // saturated like the counters of `add`
+        self.count = self.count.saturating_add(rhs.count);
+        self.sum = self.sum.saturating_add(rhs.sum);
+        self.min = self.min.min(rhs.min);
+        self.max = self.max.max(rhs.max);
+        self.zero_count = self.zero_count.saturating_add(rhs.zero_count);
+        for (idx, bucket_count) in rhs.buckets {
+            let count = self.buckets.entry(idx).or_insert(0);
+            *count = count.saturating_add(bucket_count);
+        }
+    }
+}
+
+fn gamma() -> f64 {
+    (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY)
+}
+
+fn bucket_index(value: u64) -> i32 {
+    ((value as f64).ln() / gamma().ln()).ceil() as i32
+}
+
+fn bucket_value(idx: i32) -> u64 {
+    let gamma = gamma();
+    (2.0 * gamma.powi(idx) / (gamma + 1.0)).round() as u64
+}
@@ -0,0 +1,205 @@
+use crate::decompress;
+use crate::platform;
+use crate::units;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Sampled blocks are at most this long, smaller files are split into about 1000 blocks.
+pub const MAX_BLOCK_LEN: u64 = 1 << 20;
+const MIN_BLOCK_LEN: u64 = 4096;
+
+/// Part of the input parsed for a quick estimate instead of the whole file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subset {
+    /// Random blocks with this share of the file (0.0 - 1.0), lines starting in a block belong to
+    /// it. Only the blocks are read, so a sample of a huge file takes seconds.
+    Sample(f64),
+    /// First lines.
+    Head(u64),
+    /// Lines starting in the first bytes.
+    HeadBytes(u64),
+    /// Last lines.
+    Tail(u64),
+}
+
+impl fmt::Display for Subset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Subset::Sample(fraction) => write!(f, "random {}% of the file", fraction * 100.0),
+            Subset::Head(lines) => write!(f, "first {} lines", lines),
+            Subset::HeadBytes(bytes) => write!(f, "first {}", units::format_binary_bytes(*bytes)),
+            Subset::Tail(lines) => write!(f, "last {} lines", lines),
+        }
+    }
+}
+
+/// Lines of the subset copied into a temp file, removed when dropped.
+#[derive(Debug)]
+pub struct SubsetFile {
+    path: PathBuf,
+    input_bytes: u64,
+    bytes: u64,
+    lines: u64,
+}
+
+impl SubsetFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Size of the whole input.
+    pub fn input_bytes(&self) -> u64 {
+        self.input_bytes
+    }
+
+    /// Size of the lines in the subset.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn lines(&self) -> u64 {
+        self.lines
+    }
+
+    /// Factor extrapolating counts of the subset to the whole input (by size).
+    pub fn scale(&self) -> f64 {
+        if self.bytes == 0 {
+            return 0.0;
+        }
+        self.input_bytes as f64 / self.bytes as f64
+    }
+}
+
+impl Drop for SubsetFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Copy the subset of new line separated input into a temp file. Random blocks are picked with
+/// the seed, so the same seed samples the same lines.
+pub fn extract<P: AsRef<Path>>(input_file: P, subset: Subset, seed: u64) -> io::Result<SubsetFile> {
+    let input_file = input_file.as_ref();
+    let mut file = platform::open_shared(input_file)?;
+    let input_bytes = file.metadata()?.len();
+    let mut subset_file = SubsetFile {
+        path: decompress::temp_path(input_file),
+        input_bytes,
+        bytes: 0,
+        lines: 0,
+    };
+    let mut writer = BufWriter::new(File::create(&subset_file.path)?);
+
+    let mut copy = |reader: &mut BufReader<&File>, start: u64, end: Option<u64>, max: u64| {
+        let mut position = start;
+        let mut line = vec![];
+        let mut lines = 0;
+        while end.is_none_or(|end| position < end) && lines < max {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 {
+                break;
+            }
+            writer.write_all(&line)?;
+            position += read as u64;
+            lines += 1;
+            subset_file.bytes += read as u64;
+            subset_file.lines += 1;
+        }
+        io::Result::Ok(())
+    };
+
+    match subset {
+        Subset::Sample(fraction) => {
+            if !(fraction > 0.0 && fraction <= 1.0) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Sample should be between 0 and 1, got {}", fraction),
+                ));
+            }
+            let block_len = (input_bytes / 1000).clamp(MIN_BLOCK_LEN, MAX_BLOCK_LEN);
+            let num_of_blocks = input_bytes.div_ceil(block_len);
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut blocks: Vec<u64> = (0..num_of_blocks)
+                .filter(|_| rng.gen_bool(fraction))
+                .collect();
+            if blocks.is_empty() && num_of_blocks > 0 {
+                blocks.push(rng.gen_range(0, num_of_blocks));
+            }
+            for block in blocks {
+                let start = block * block_len;
+                let mut reader = BufReader::new(&file);
+                reader.seek(SeekFrom::Start(start))?;
+                let line_start = skip_to_line_start(&mut reader, start)?;
+                copy(&mut reader, line_start, Some(start + block_len), u64::MAX)?;
+            }
+        }
+        Subset::Head(lines) => {
+            copy(&mut BufReader::new(&file), 0, None, lines)?;
+        }
+        Subset::HeadBytes(bytes) => {
+            copy(&mut BufReader::new(&file), 0, Some(bytes), u64::MAX)?;
+        }
+        Subset::Tail(lines) => {
+            let start = tail_start(&mut file, input_bytes, lines)?;
+            let mut reader = BufReader::new(&file);
+            reader.seek(SeekFrom::Start(start))?;
+            copy(&mut reader, start, None, u64::MAX)?;
+        }
+    }
+
+    writer.flush()?;
+    drop(writer);
+    Ok(subset_file)
+}
+
+/// Skip the end of the line started before `start`, returns offset of the next line.
+fn skip_to_line_start(reader: &mut BufReader<&File>, start: u64) -> io::Result<u64> {
+    if start == 0 {
+        return Ok(0);
+    }
+    reader.seek(SeekFrom::Start(start - 1))?;
+    let mut rest = vec![];
+    let read = reader.read_until(b'\n', &mut rest)?;
+
+    Ok(start - 1 + read as u64)
+}
+
+/// Offset of the first of the last `lines` lines, read backwards in blocks.
+fn tail_start(file: &mut File, size: u64, lines: u64) -> io::Result<u64> {
+    if lines == 0 {
+        return Ok(size);
+    }
+    let mut buffer = vec![0; MAX_BLOCK_LEN as usize];
+    let mut end = size;
+    // new line ending the last line doesn't start another one
+    let mut new_lines = 0;
+    let mut skip_last = true;
+    while end > 0 {
+        let start = end.saturating_sub(MAX_BLOCK_LEN);
+        let block = &mut buffer[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(block)?;
+        for (idx, byte) in block.iter().enumerate().rev() {
+            if *byte != b'\n' {
+                skip_last = false;
+                continue;
+            }
+            if skip_last {
+                skip_last = false;
+                continue;
+            }
+            new_lines += 1;
+            if new_lines == lines {
+                return Ok(start + idx as u64 + 1);
+            }
+        }
+        end = start;
+    }
+
+    Ok(0)
+}
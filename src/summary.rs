@@ -0,0 +1,250 @@
+use crate::error_log::{self, ErrorClass};
+use crate::memory::{self, MemorySnapshot};
+use crate::parser::ParseReport;
+use crate::progress::ThreadStats;
+use crate::table::markdown_table;
+use crate::units;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Machine-readable summary of a run with its timing and configuration.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    pub inputs: Vec<String>,
+    pub lines: u64,
+    pub parsed_lines: u64,
+    pub failed_lines: u64,
+    /// Failed lines longer than the maximum line length.
+    pub oversize_lines: u64,
+    /// Counter additions which overflowed and were saturated.
+    pub overflows: u64,
+    /// Bytes consumed by parsing threads.
+    pub bytes: u64,
+    pub wall_time_secs: f64,
+    /// Wall time as ISO 8601 duration, e.g. `PT2M13.400S`.
+    pub wall_time: String,
+    /// Seconds spent in every stage of the run.
+    pub stage_secs: BTreeMap<String, f64>,
+    pub lines_per_sec: f64,
+    pub bytes_per_sec: f64,
+    /// Whether the whole input was read, see [`ParseReport::is_complete`].
+    pub complete: bool,
+    pub threads: Vec<ThreadSummary>,
+    /// Peak memory and allocations per module, only with the `memory-profile` feature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemorySnapshot>,
+    /// Effective configuration, e.g. flags merged with the config file.
+    pub config: Value,
+}
+
+/// Single-line completion record of a run written to stderr at exit (see [`ExitRecord::emit`]),
+/// so wrappers get the outcome without parsing the human report.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ExitRecord {
+    /// `ok`, `violations` (thresholds exceeded), `interrupted` or `failed`.
+    pub status: &'static str,
+    pub exit_code: i32,
+    pub lines: u64,
+    pub failed_lines: u64,
+    pub failed_ranges: usize,
+    /// Counter additions which overflowed and were saturated.
+    pub overflows: u64,
+    pub error_classes: BTreeMap<ErrorClass, u64>,
+    pub violations: Vec<String>,
+    /// Files and URIs results were written to.
+    pub outputs: Vec<String>,
+    /// Why the run failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ExitRecord {
+    pub fn new(status: &'static str, exit_code: i32, report: &ParseReport) -> Self {
+        Self {
+            status,
+            exit_code,
+            lines: report.lines,
+            failed_lines: report.failed_lines,
+            failed_ranges: report.failed_ranges.len(),
+            overflows: report.overflows(),
+            error_classes: error_log::classes(&report.error_kinds),
+            violations: vec![],
+            outputs: vec![],
+            error: None,
+        }
+    }
+
+    /// Record of a run which failed before reporting, with the lines read until then.
+    pub fn failed(exit_code: i32, error: String, lines: u64) -> Self {
+        Self {
+            lines,
+            error: Some(error),
+            ..Self::new("failed", exit_code, &ParseReport::default())
+        }
+    }
+
+    /// Write the record as a JSON line to stderr.
+    pub fn emit(&self) {
+        let json = serde_json::to_string(self).expect("Can't serialize exit record");
+        eprintln!("{}", json);
+    }
+}
+
+/// Throughput of a single parsing thread.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ThreadSummary {
+    pub input: String,
+    pub bytes: u64,
+    pub lines: u64,
+    pub secs: f64,
+    pub bytes_per_sec: f64,
+    pub lines_per_sec: f64,
+}
+
+impl RunSummary {
+    /// Summary of the report parsed in `wall_time` (split into stages) by the threads of every
+    /// input.
+    pub fn new(
+        inputs: &[String],
+        report: &ParseReport,
+        threads: Vec<(String, ThreadStats)>,
+        wall_time: Duration,
+        stages: &[(&str, Duration)],
+        config: Value,
+    ) -> Self {
+        let secs = wall_time.as_secs_f64();
+        let per_sec = |count: u64, secs: f64| if secs > 0.0 { count as f64 / secs } else { 0.0 };
+        let bytes = threads.iter().map(|(_, thread)| thread.bytes).sum();
+
+        Self {
+            inputs: inputs.to_vec(),
+            lines: report.lines,
+            parsed_lines: report.lines - report.failed_lines,
+            failed_lines: report.failed_lines,
+            oversize_lines: oversize_lines(report),
+            overflows: report.overflows(),
+            bytes,
+            wall_time_secs: secs,
+            wall_time: units::iso_duration(wall_time),
+            stage_secs: stages
+                .iter()
+                .map(|(stage, elapsed)| (stage.to_string(), elapsed.as_secs_f64()))
+                .collect(),
+            lines_per_sec: per_sec(report.lines, secs),
+            bytes_per_sec: per_sec(bytes, secs),
+            complete: report.is_complete(),
+            threads: threads
+                .into_iter()
+                .map(|(input, thread)| {
+                    let secs = thread.elapsed.as_secs_f64();
+                    ThreadSummary {
+                        input,
+                        bytes: thread.bytes,
+                        lines: thread.lines,
+                        secs,
+                        bytes_per_sec: per_sec(thread.bytes, secs),
+                        lines_per_sec: per_sec(thread.lines, secs),
+                    }
+                })
+                .collect(),
+            memory: memory::snapshot(),
+            config,
+        }
+    }
+}
+
+/// Number of failed lines longer than the maximum line length.
+pub fn oversize_lines(report: &ParseReport) -> u64 {
+    error_log::classes(&report.error_kinds)
+        .get(&ErrorClass::OversizeLine)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Markdown summary of parse results with threshold violations, e.g. for review comments.
+pub fn markdown(report: &ParseReport, violations: &[String]) -> String {
+    let mut log_types: Vec<_> = report.log_register.iter().collect();
+    log_types.sort_by_key(|(log_type, _)| *log_type);
+
+    let header: Vec<String> = [
+        "Log type",
+        "Logs",
+        "Volume",
+        "Error rate",
+        "Line size p50",
+        "Line size p99",
+    ]
+    .iter()
+    .map(|column| column.to_string())
+    .collect();
+    let rows: Vec<Vec<String>> = log_types
+        .iter()
+        .map(|(log_type, elem)| {
+            vec![
+                log_type.to_string(),
+                units::format_count(elem.counter),
+                units::format_bytes(elem.num_of_bytes as f64),
+                format!("{:.2}%", elem.error_ratio() * 100.0),
+                elem.line_size.p50().to_string(),
+                elem.line_size.p99().to_string(),
+            ]
+        })
+        .collect();
+
+    let mut summary = format!(
+        "### Log parser summary\n\nLines: {}, failed lines: {}\n\n{}",
+        units::format_count(report.lines),
+        units::format_count(report.failed_lines),
+        markdown_table(&header, &rows)
+    );
+    if !report.error_kinds.is_empty() {
+        let header = vec![
+            "Class".to_string(),
+            "Error".to_string(),
+            "Failed lines".to_string(),
+        ];
+        let mut error_kinds: Vec<_> = report.error_kinds.iter().collect();
+        error_kinds.sort_by(|(a_kind, a), (b_kind, b)| b.cmp(a).then(a_kind.cmp(b_kind)));
+        let rows: Vec<Vec<String>> = error_kinds
+            .into_iter()
+            .map(|(kind, count)| {
+                vec![
+                    ErrorClass::of(kind).to_string(),
+                    kind.clone(),
+                    units::format_count(count),
+                ]
+            })
+            .collect();
+        summary.push_str("\n**Failed lines by error:**\n\n");
+        summary.push_str(&markdown_table(&header, &rows));
+    }
+    if !report.is_complete() {
+        summary
+            .push_str("\n**Incomplete: the run was interrupted, unread parts of the file:**\n\n");
+        report.unread_ranges.iter().for_each(|unread_range| {
+            summary.push_str(&format!(
+                "- bytes {}..{}\n",
+                unread_range.start_idx, unread_range.end_idx
+            ))
+        });
+    }
+    if !report.failed_ranges.is_empty() {
+        summary.push_str("\n**Skipped parts of the file:**\n\n");
+        report.failed_ranges.iter().for_each(|failed_range| {
+            summary.push_str(&format!(
+                "- bytes {}..{}: {}\n",
+                failed_range.start_idx, failed_range.end_idx, failed_range.error
+            ))
+        });
+    }
+    if !violations.is_empty() {
+        summary.push_str("\n**Threshold violations:**\n\n");
+        violations
+            .iter()
+            .for_each(|violation| summary.push_str(&format!("- :x: {}\n", violation)));
+    }
+
+    summary
+}
@@ -0,0 +1,35 @@
+/// Markdown table, `|` in cells is escaped.
+pub fn markdown_table(header: &[String], rows: &[Vec<String>]) -> String {
+    let row = |cells: &[String]| {
+        let cells: Vec<String> = cells.iter().map(|cell| cell.replace('|', "\\|")).collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+    let mut table = row(header);
+    table.push_str(&format!("|{}\n", "---|".repeat(header.len())));
+    rows.iter().for_each(|cells| table.push_str(&row(cells)));
+    table
+}
+
+/// HTML table, cells are escaped.
+pub fn html_table(header: &[String], rows: &[Vec<String>]) -> String {
+    let row = |cells: &[String], tag: &str| {
+        let cells: String = cells
+            .iter()
+            .map(|cell| format!("<{}>{}</{}>", tag, escape_html(cell), tag))
+            .collect();
+        format!("<tr>{}</tr>\n", cells)
+    };
+    let mut table = format!("<table>\n{}", row(header, "th"));
+    rows.iter()
+        .for_each(|cells| table.push_str(&row(cells, "td")));
+    table.push_str("</table>\n");
+    table
+}
+
+/// Escape text to be placed in HTML.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,228 @@
+use crate::timestamp;
+use rand::Rng;
+use serde_json::{Map, Value};
+use std::fs;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// Value generated for every line.
+#[derive(Debug, Clone, PartialEq)]
+enum Placeholder {
+    /// `{uuid}`
+    Uuid,
+    /// `{ts}`
+    Timestamp,
+    /// `{choice:error,warn,info}`
+    Choice(Vec<String>),
+    /// `{int:100-599}`, both ends included.
+    Int(i64, i64),
+}
+
+impl Placeholder {
+    /// Parse placeholder content (without braces), `None` if it's not a placeholder.
+    fn parse(content: &str) -> Result<Option<Self>, String> {
+        let (name, args) = match content.split_once(':') {
+            Some((name, args)) => (name, Some(args)),
+            None => (content, None),
+        };
+        let placeholder = match (name, args) {
+            ("uuid", None) => Placeholder::Uuid,
+            ("ts", None) => Placeholder::Timestamp,
+            ("choice", Some(args)) => {
+                let choices: Vec<String> = args.split(',').map(String::from).collect();
+                Placeholder::Choice(choices)
+            }
+            ("int", Some(args)) => {
+                let invalid = || format!("Invalid range in {{{}}}, e.g. {{int:100-599}}", content);
+                // the first '-' after the first character, so negative ranges work too
+                let (split, _) = args
+                    .char_indices()
+                    .skip(1)
+                    .find(|(_, c)| *c == '-')
+                    .ok_or_else(invalid)?;
+                let min: i64 = args[..split].trim().parse().map_err(|_| invalid())?;
+                let max: i64 = args[split + 1..].trim().parse().map_err(|_| invalid())?;
+                if min > max {
+                    return Err(invalid());
+                }
+                Placeholder::Int(min, max)
+            }
+            ("uuid", Some(_)) | ("ts", Some(_)) | ("choice", None) | ("int", None) => {
+                return Err(format!("Invalid placeholder {{{}}}", content))
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(placeholder))
+    }
+
+    fn fill<R: Rng>(&self, rng: &mut R, time: SystemTime) -> Value {
+        match self {
+            Placeholder::Uuid => Value::from(random_uuid(rng).to_string()),
+            Placeholder::Timestamp => Value::from(timestamp::format(time)),
+            Placeholder::Choice(choices) => {
+                Value::from(choices[rng.gen_range(0, choices.len())].as_str())
+            }
+            Placeholder::Int(min, max) => Value::from(rng.gen_range(*min, *max + 1)),
+        }
+    }
+}
+
+/// Part of a string value.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+/// Template value with placeholders parsed.
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    /// Value without placeholders.
+    Fixed(Value),
+    /// String which is a single placeholder, replaced by a typed value (`{int:..}` gives number).
+    Placeholder(Placeholder),
+    /// String with placeholders in the text, always gives string.
+    Text(Vec<Segment>),
+    Array(Vec<Node>),
+    Object(Vec<(String, Node)>),
+}
+
+impl Node {
+    fn parse(value: &Value) -> Result<Self, String> {
+        let node = match value {
+            Value::String(text) => {
+                let segments = segments(text)?;
+                match segments.as_slice() {
+                    [Segment::Placeholder(placeholder)] => Node::Placeholder(placeholder.clone()),
+                    [] | [Segment::Text(_)] => Node::Fixed(value.clone()),
+                    _ => Node::Text(segments),
+                }
+            }
+            Value::Array(values) => {
+                Node::Array(values.iter().map(Node::parse).collect::<Result<_, _>>()?)
+            }
+            Value::Object(fields) => Node::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| Ok((name.clone(), Node::parse(value)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            _ => Node::Fixed(value.clone()),
+        };
+
+        Ok(node)
+    }
+
+    fn fill<R: Rng>(&self, rng: &mut R, time: SystemTime) -> Value {
+        match self {
+            Node::Fixed(value) => value.clone(),
+            Node::Placeholder(placeholder) => placeholder.fill(rng, time),
+            Node::Text(segments) => {
+                let mut text = String::new();
+                for segment in segments {
+                    match segment {
+                        Segment::Text(part) => text.push_str(part),
+                        Segment::Placeholder(placeholder) => match placeholder.fill(rng, time) {
+                            Value::String(value) => text.push_str(&value),
+                            value => text.push_str(&value.to_string()),
+                        },
+                    }
+                }
+                Value::from(text)
+            }
+            Node::Array(nodes) => {
+                Value::Array(nodes.iter().map(|node| node.fill(rng, time)).collect())
+            }
+            Node::Object(fields) => {
+                let mut map = Map::new();
+                for (name, node) in fields {
+                    map.insert(name.clone(), node.fill(rng, time));
+                }
+                Value::Object(map)
+            }
+        }
+    }
+}
+
+/// Split text into fixed text and placeholders, braces which are not placeholders are kept.
+fn segments(text: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = vec![];
+    let mut literal = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        match Placeholder::parse(&rest[start + 1..end])? {
+            Some(placeholder) => {
+                literal.push_str(&rest[..start]);
+                if !literal.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Placeholder(placeholder));
+                rest = &rest[end + 1..];
+            }
+            None => {
+                literal.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        segments.push(Segment::Text(literal));
+    }
+
+    Ok(segments)
+}
+
+/// JSON log template with placeholders in string values filled for every generated line:
+/// `{uuid}`, `{ts}` (timestamp), `{choice:error,warn,info}` and `{int:100-599}`. A string which
+/// is a single `{int:..}` placeholder becomes a number, other braces are kept as they are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    root: Node,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let value: Value =
+            serde_json::from_str(text).map_err(|err| format!("Template is not JSON: {}", err))?;
+        let root = Node::parse(&value)?;
+        // `{int:..}` type would be a number
+        let int_type = match &root {
+            Node::Object(fields) => fields.iter().any(|(name, node)| {
+                name == "type" && matches!(node, Node::Placeholder(Placeholder::Int(..)))
+            }),
+            _ => false,
+        };
+        if !value.get("type").is_some_and(Value::is_string) || int_type {
+            return Err(
+                "Template should have string type field, logs are parsed by it".to_string(),
+            );
+        }
+
+        Ok(Self { root })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Can't read template file [{}]: {}", path, err))?;
+        Self::parse(&text).map_err(|err| format!("Invalid template [{}]: {}", path, err))
+    }
+
+    /// Log with placeholders filled, `{ts}` is replaced by the given time.
+    pub fn fill<R: Rng>(&self, rng: &mut R, time: SystemTime) -> Value {
+        self.root.fill(rng, time)
+    }
+}
+
+/// Random v4 UUID from the given generator (reproducible unlike `Uuid::new_v4`).
+pub(crate) fn random_uuid<R: Rng>(rng: &mut R) -> Uuid {
+    uuid::Builder::from_bytes(rng.gen())
+        .set_variant(uuid::Variant::RFC4122)
+        .set_version(uuid::Version::Random)
+        .build()
+}
@@ -0,0 +1,122 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// When human output is colored.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ColorChoice {
+    /// Colored if stdout is a terminal and `NO_COLOR` isn't set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(&self) -> bool {
+        match self {
+            ColorChoice::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(choice: &str) -> Result<Self, Self::Err> {
+        match choice {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!(
+                "Unknown color choice [{}], expected auto, always or never",
+                choice
+            )),
+        }
+    }
+}
+
+/// Set whether human output is colored, it isn't by default.
+pub fn set_color(choice: ColorChoice) {
+    COLOR.store(choice.enabled(), Ordering::Relaxed);
+}
+
+pub fn color() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// Style of a table row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Plain,
+    Bold,
+    /// Some errors, yellow.
+    Warning,
+    /// Mostly errors, red.
+    Error,
+}
+
+impl Style {
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            Style::Plain => None,
+            Style::Bold => Some("\x1b[1m"),
+            Style::Warning => Some("\x1b[33m"),
+            Style::Error => Some("\x1b[1;31m"),
+        }
+    }
+}
+
+/// Text in the style if output is colored.
+pub fn paint(text: &str, style: Style) -> String {
+    match style.code() {
+        Some(code) if color() => format!("{}{}\x1b[0m", code, text),
+        _ => text.to_string(),
+    }
+}
+
+/// Alignment of a table column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// Table with columns padded to their widest cell and rows painted in their style. Columns
+/// without an alignment are left aligned.
+pub fn aligned_table(header: &[String], rows: &[(Style, Vec<String>)], align: &[Align]) -> String {
+    let width = |cell: &String| cell.chars().count();
+    let mut widths: Vec<usize> = header.iter().map(width).collect();
+    for (_, cells) in rows {
+        for (idx, cell) in cells.iter().enumerate() {
+            if idx < widths.len() {
+                widths[idx] = widths[idx].max(width(cell));
+            }
+        }
+    }
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(idx, (cell, width))| match align.get(idx) {
+                Some(Align::Right) => format!("{:>width$}", cell, width = width),
+                _ => format!("{:<width$}", cell, width = width),
+            })
+            .collect();
+        cells.join("  ").trim_end().to_string()
+    };
+
+    let mut table = paint(&line(header), Style::Bold);
+    table.push('\n');
+    for (style, cells) in rows {
+        table.push_str(&paint(&line(cells), *style));
+        table.push('\n');
+    }
+    table
+}
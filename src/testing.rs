@@ -0,0 +1,234 @@
+//! Test support for integration tests of new input and output formats: fixture files, log line
+//! builder and golden file comparison. Golden files are rewritten instead of compared when
+//! `UPDATE_GOLDEN=1` is set.
+
+use crate::error_log;
+use crate::parser::{self, ParseOptions, ParseReport, SampleOptions};
+use crate::rate;
+use serde_json::{Map, Value};
+use std::env;
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Environment variable which makes [`assert_golden`] write actual output into golden files.
+pub const UPDATE_GOLDEN: &str = "UPDATE_GOLDEN";
+
+static NEXT_FIXTURE: AtomicUsize = AtomicUsize::new(0);
+
+/// Temp directory with input files of a single test, removed when dropped.
+pub struct Fixture {
+    dir: PathBuf,
+}
+
+impl Fixture {
+    pub fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!(
+            "log-parser-fixture-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT_FIXTURE.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&dir).expect("Can't create fixture directory");
+
+        Self { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of a file in the fixture directory as text, e.g. for command line arguments.
+    pub fn path(&self, file_name: &str) -> String {
+        self.dir.join(file_name).to_string_lossy().into_owned()
+    }
+
+    /// Write file with given lines, every line ends with new line character.
+    pub fn lines<I, S>(&self, file_name: &str, lines: I) -> String
+    where
+        I: IntoIterator<Item = S>,
+        S: fmt::Display,
+    {
+        let mut content = String::new();
+        for line in lines {
+            writeln!(content, "{}", line).expect("Writing to string can't fail");
+        }
+        self.bytes(file_name, content.as_bytes())
+    }
+
+    /// Write file with exact content, e.g. invalid UTF-8 or missing final new line.
+    pub fn bytes(&self, file_name: &str, content: &[u8]) -> String {
+        let path = self.path(file_name);
+        fs::write(&path, content).expect("Can't write fixture file");
+        path
+    }
+
+    /// Write sample file with the generator (use a seed to get the same file every time).
+    pub fn generated(&self, file_name: &str, options: &SampleOptions) -> String {
+        let path = self.path(file_name);
+        parser::prepare_sample_file(options, &path);
+        path
+    }
+
+    /// Replace fixture directory with `<fixture>`, so output with paths can be compared.
+    pub fn normalize(&self, text: &str) -> String {
+        text.replace(self.dir.to_string_lossy().as_ref(), "<fixture>")
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// JSON log line builder, e.g. `LogLine::new("http").level("error").field("status", 500)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    fields: Map<String, Value>,
+}
+
+impl LogLine {
+    pub fn new(log_type: &str) -> Self {
+        let mut fields = Map::new();
+        fields.insert("type".to_string(), Value::from(log_type));
+        Self { fields }
+    }
+
+    pub fn level(self, level: &str) -> Self {
+        self.field("level", level)
+    }
+
+    pub fn field<V: Into<Value>>(mut self, name: &str, value: V) -> Self {
+        self.fields.insert(name.to_string(), value.into());
+        self
+    }
+}
+
+impl fmt::Display for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Value::Object(self.fields.clone()))
+    }
+}
+
+/// Stable text form of a report (log types sorted, field names from options) for golden files.
+pub fn report_snapshot(report: &ParseReport, options: &ParseOptions) -> String {
+    let mut snapshot = String::new();
+    // writing to string can't fail
+    let _ = write_report(&mut snapshot, report, options);
+    snapshot
+}
+
+fn write_report(out: &mut String, report: &ParseReport, options: &ParseOptions) -> fmt::Result {
+    writeln!(out, "lines: {}", report.lines)?;
+    writeln!(out, "failed lines: {}", report.failed_lines)?;
+    for (class, count) in error_log::classes(&report.error_kinds) {
+        writeln!(out, "error class: {}: {}", class, count)?;
+    }
+    for (kind, count) in &report.error_kinds {
+        writeln!(out, "error kind: {}: {}", kind, count)?;
+    }
+    for range in &report.failed_ranges {
+        writeln!(
+            out,
+            "failed range: {}..{} after {} attempts: {}",
+            range.start_idx, range.end_idx, range.attempts, range.error
+        )?;
+    }
+
+    let mut log_types: Vec<_> = report.log_register.iter().collect();
+    log_types.sort_by_key(|(log_type, _)| *log_type);
+    for (log_type, elem) in log_types {
+        let line_size = &elem.line_size;
+        writeln!(out, "\n[{}]", log_type)?;
+        writeln!(out, "counter: {}", elem.counter)?;
+        writeln!(out, "num_of_bytes: {}", elem.num_of_bytes)?;
+        writeln!(out, "errors: {}", elem.errors)?;
+        writeln!(
+            out,
+            "line_size min/mean/max: {}/{:.1}/{}, p50/p95/p99: {}/{}/{}",
+            line_size.min(),
+            line_size.mean(),
+            line_size.max(),
+            line_size.p50(),
+            line_size.p95(),
+            line_size.p99()
+        )?;
+        for (idx, field_name) in options.numeric_fields.iter().enumerate() {
+            let field = elem.fields.get(idx).copied().unwrap_or_default();
+            writeln!(
+                out,
+                "field {}: sum {}, count {}",
+                field_name, field.sum, field.count
+            )?;
+        }
+        for (field_name, distinct) in options.distinct_fields.iter().zip(&elem.distinct) {
+            let approx = if distinct.is_exact() { "" } else { "~" };
+            writeln!(
+                out,
+                "distinct {}: {}{}",
+                field_name,
+                approx,
+                distinct.count()
+            )?;
+        }
+        if let Some(window) = options.rate_window {
+            writeln!(out, "lines/s: {:.3}", rate::lines_per_sec(elem, window))?;
+            for window_rate in rate::series(elem, window) {
+                writeln!(
+                    out,
+                    "window {}: lines {}, lines/s {:.3}, error rate {:.4}",
+                    window_rate.start,
+                    window_rate.lines,
+                    window_rate.lines_per_sec,
+                    window_rate.error_rate
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare output with golden file, or write the golden file if `UPDATE_GOLDEN=1` is set.
+/// Panics with the first differing line otherwise.
+pub fn assert_golden<P: AsRef<Path>>(golden_file: P, actual: &str) {
+    let golden_file = golden_file.as_ref();
+    if env::var(UPDATE_GOLDEN).is_ok_and(|update| update == "1") {
+        if let Some(dir) = golden_file.parent() {
+            fs::create_dir_all(dir).expect("Can't create golden files directory");
+        }
+        fs::write(golden_file, actual).expect("Can't write golden file");
+        return;
+    }
+
+    let expected = fs::read_to_string(golden_file).unwrap_or_else(|err| {
+        panic!(
+            "Can't read golden file {} ({}), run with {}=1 to create it",
+            golden_file.display(),
+            err,
+            UPDATE_GOLDEN
+        )
+    });
+    if expected == actual {
+        return;
+    }
+
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line_number = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(expected), Some(actual)) if expected == actual => line_number += 1,
+            (expected, actual) => panic!(
+                "Output differs from golden file {} at line {}:\n  expected: {}\n    actual: {}\nrun with {}=1 to update it",
+                golden_file.display(),
+                line_number,
+                expected.unwrap_or("<end of file>"),
+                actual.unwrap_or("<end of file>"),
+                UPDATE_GOLDEN
+            ),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::clock::Clock;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Most records per second sent downstream, with bursts of up to `burst` records at once after
+/// a pause.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub records_per_sec: f64,
+    pub burst: u64,
+}
+
+impl RateLimit {
+    /// Limit of the rate, bursts default to a second of records (at least one).
+    pub fn new(records_per_sec: f64, burst: Option<u64>) -> Result<Self, String> {
+        if !(records_per_sec.is_finite() && records_per_sec > 0.0) {
+            return Err(format!(
+                "Rate limit should be a positive number of records per second: [{}]",
+                records_per_sec
+            ));
+        }
+        let burst = burst.unwrap_or(records_per_sec.ceil() as u64);
+        if burst == 0 {
+            return Err("Burst should be at least 1 record".to_string());
+        }
+
+        Ok(Self {
+            records_per_sec,
+            burst,
+        })
+    }
+}
+
+/// Token bucket enforcing a [`RateLimit`]: a record takes a token, tokens are added at the
+/// rate up to the burst, and a record without a token waits (by the clock) until one is added.
+/// The bucket starts full.
+#[derive(Debug)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+    clock: Arc<dyn Clock>,
+    /// Time records waited for tokens.
+    waited: Duration,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            refilled_at: clock.instant(),
+            clock,
+            waited: Duration::ZERO,
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = self.clock.instant();
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.limit.records_per_sec)
+            .min(self.limit.burst as f64);
+        self.refilled_at = now;
+    }
+
+    /// Take a token if there is one, without waiting.
+    pub fn try_take(&mut self) -> bool {
+        self.refill();
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    /// Take a token, waiting for it if there is none.
+    pub fn take(&mut self) {
+        if self.try_take() {
+            return;
+        }
+        let wait = Duration::from_secs_f64((1.0 - self.tokens) / self.limit.records_per_sec);
+        self.clock.sleep(wait);
+        self.waited += wait;
+        self.refill();
+        // the token is due after the wait, even if the clock rounded it short
+        self.tokens = self.tokens.max(1.0) - 1.0;
+    }
+
+    /// Total time records waited for tokens.
+    pub fn waited(&self) -> Duration {
+        self.waited
+    }
+}
+
+/// Writer of new line delimited records taking a token of the bucket for every line. Lines
+/// written so far are flushed before waiting, so they don't stay in buffers of the writer.
+pub struct ThrottledWriter<W: Write> {
+    inner: W,
+    bucket: TokenBucket,
+}
+
+impl<W: Write> ThrottledWriter<W> {
+    pub fn new(inner: W, bucket: TokenBucket) -> Self {
+        Self { inner, bucket }
+    }
+
+    pub fn bucket(&self) -> &TokenBucket {
+        &self.bucket
+    }
+}
+
+impl<W: Write> Write for ThrottledWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for _ in memchr::memchr_iter(b'\n', buf) {
+            if !self.bucket.try_take() {
+                self.inner.flush()?;
+                self.bucket.take();
+            }
+        }
+        self.inner.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,451 @@
+use crate::clock::{Clock, SystemClock};
+use serde::Deserialize;
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Epoch numbers above these limits are treated as milli/micro/nanoseconds.
+const MILLIS_LIMIT: f64 = 1e11;
+const MICROS_LIMIT: f64 = 1e14;
+const NANOS_LIMIT: f64 = 1e17;
+
+/// Parse timestamp field value: RFC 3339 string (with `Z` or `+hh:mm` offset) or number
+/// of seconds/milliseconds/microseconds/nanoseconds since epoch.
+pub fn parse(value: &Value) -> Option<SystemTime> {
+    parse_with(value, &[], &SystemClock)
+}
+
+/// Parse timestamp field value like [`parse`], month names of dates with them can be in the
+/// languages of the locales too (see [`parse_str_with`]), dates without year are placed by the
+/// current time of the clock.
+pub fn parse_with(value: &Value, locales: &[TimeLocale], clock: &dyn Clock) -> Option<SystemTime> {
+    match value {
+        Value::String(text) => parse_str_with_clock(text, locales, clock),
+        Value::Number(number) => number.as_f64().and_then(from_epoch),
+        _ => None,
+    }
+}
+
+/// Parse RFC 3339 timestamp or epoch number written as text.
+pub fn parse_str(text: &str) -> Option<SystemTime> {
+    parse_str_with(text, &[])
+}
+
+/// Parse RFC 3339 timestamp, epoch number written as text or date with month name, e.g.
+/// `Feb 14 00:28:07` (syslog), `14/Feb/2018:00:28:07 +0100` (access logs) or
+/// `14. Feb. 2018 00:28:07`. English month names are always known, names in the languages of the
+/// locales are tried too (`Mär 14 00:28:07` with German).
+pub fn parse_str_with(text: &str, locales: &[TimeLocale]) -> Option<SystemTime> {
+    parse_str_with_clock(text, locales, &SystemClock)
+}
+
+/// Parse timestamp like [`parse_str_with`], dates without year are placed by the current time
+/// of the clock instead of the system.
+pub fn parse_str_with_clock(
+    text: &str,
+    locales: &[TimeLocale],
+    clock: &dyn Clock,
+) -> Option<SystemTime> {
+    let text = text.trim();
+    if let Ok(epoch) = text.parse::<f64>() {
+        return from_epoch(epoch);
+    }
+
+    // humantime supports UTC only, so numeric offset is applied manually
+    let (date_time, offset_secs) = split_offset(text);
+    match humantime::parse_rfc3339_weak(date_time) {
+        Ok(time) => apply_offset(time, offset_secs),
+        Err(_) => parse_month_name(text, locales, clock),
+    }
+}
+
+fn apply_offset(time: SystemTime, offset_secs: i64) -> Option<SystemTime> {
+    if offset_secs >= 0 {
+        time.checked_sub(Duration::from_secs(offset_secs as u64))
+    } else {
+        time.checked_add(Duration::from_secs((-offset_secs) as u64))
+    }
+}
+
+/// Format timestamp as RFC 3339 with millisecond precision.
+pub fn format(time: SystemTime) -> String {
+    humantime::format_rfc3339_millis(time).to_string()
+}
+
+/// Split `+hh:mm` / `-hh:mm` / `+hhmm` offset from the date time, offset is returned in seconds.
+fn split_offset(text: &str) -> (&str, i64) {
+    // date part contains '-' too, so offset is searched only after the time
+    const DATE_TIME_LEN: usize = "2018-02-14T00:28:07".len();
+    if text.len() <= DATE_TIME_LEN || !text.is_char_boundary(DATE_TIME_LEN) {
+        return (text, 0);
+    }
+
+    let rest = &text[DATE_TIME_LEN..];
+    match rest.find(['+', '-']) {
+        Some(idx) => {
+            let sign = if rest[idx..].starts_with('-') { -1 } else { 1 };
+            let digits: String = rest[idx + 1..]
+                .chars()
+                .filter(|c| c.is_ascii_digit())
+                .collect();
+            if digits.len() != 4 {
+                return (text, 0);
+            }
+            let hours: i64 = digits[..2].parse().unwrap_or(0);
+            let minutes: i64 = digits[2..].parse().unwrap_or(0);
+            (
+                &text[..DATE_TIME_LEN + idx],
+                sign * (hours * 3600 + minutes * 60),
+            )
+        }
+        None => (text, 0),
+    }
+}
+
+fn from_epoch(epoch: f64) -> Option<SystemTime> {
+    if !epoch.is_finite() || epoch < 0.0 {
+        return None;
+    }
+
+    let secs = if epoch >= NANOS_LIMIT {
+        epoch / 1e9
+    } else if epoch >= MICROS_LIMIT {
+        epoch / 1e6
+    } else if epoch >= MILLIS_LIMIT {
+        epoch / 1e3
+    } else {
+        epoch
+    };
+
+    // giant numbers don't fit into duration
+    UNIX_EPOCH.checked_add(Duration::try_from_secs_f64(secs).ok()?)
+}
+
+/// Language of month names in timestamps (`--timestamp-locale`), English names are always known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum TimeLocale {
+    English,
+    German,
+    French,
+    Spanish,
+    Italian,
+    Dutch,
+    Portuguese,
+}
+
+impl TimeLocale {
+    /// Locale by its language or POSIX name, e.g. `de`, `de_DE.UTF-8` or `german`.
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        let language = name
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        match language.as_str() {
+            "en" | "english" | "c" | "posix" => Ok(TimeLocale::English),
+            "de" | "german" => Ok(TimeLocale::German),
+            "fr" | "french" => Ok(TimeLocale::French),
+            "es" | "spanish" => Ok(TimeLocale::Spanish),
+            "it" | "italian" => Ok(TimeLocale::Italian),
+            "nl" | "dutch" => Ok(TimeLocale::Dutch),
+            "pt" | "portuguese" => Ok(TimeLocale::Portuguese),
+            _ => Err(format!(
+                "Unknown timestamp locale [{}], use en, de, fr, es, it, nl or pt",
+                name
+            )),
+        }
+    }
+
+    /// Full month names, abbreviations are their prefixes (see [`TimeLocale::abbreviations`]).
+    fn months(&self) -> [&'static str; 12] {
+        match self {
+            TimeLocale::English => [
+                "january",
+                "february",
+                "march",
+                "april",
+                "may",
+                "june",
+                "july",
+                "august",
+                "september",
+                "october",
+                "november",
+                "december",
+            ],
+            TimeLocale::German => [
+                "januar",
+                "februar",
+                "märz",
+                "april",
+                "mai",
+                "juni",
+                "juli",
+                "august",
+                "september",
+                "oktober",
+                "november",
+                "dezember",
+            ],
+            TimeLocale::French => [
+                "janvier",
+                "février",
+                "mars",
+                "avril",
+                "mai",
+                "juin",
+                "juillet",
+                "août",
+                "septembre",
+                "octobre",
+                "novembre",
+                "décembre",
+            ],
+            TimeLocale::Spanish => [
+                "enero",
+                "febrero",
+                "marzo",
+                "abril",
+                "mayo",
+                "junio",
+                "julio",
+                "agosto",
+                "septiembre",
+                "octubre",
+                "noviembre",
+                "diciembre",
+            ],
+            TimeLocale::Italian => [
+                "gennaio",
+                "febbraio",
+                "marzo",
+                "aprile",
+                "maggio",
+                "giugno",
+                "luglio",
+                "agosto",
+                "settembre",
+                "ottobre",
+                "novembre",
+                "dicembre",
+            ],
+            TimeLocale::Dutch => [
+                "januari",
+                "februari",
+                "maart",
+                "april",
+                "mei",
+                "juni",
+                "juli",
+                "augustus",
+                "september",
+                "oktober",
+                "november",
+                "december",
+            ],
+            TimeLocale::Portuguese => [
+                "janeiro",
+                "fevereiro",
+                "março",
+                "abril",
+                "maio",
+                "junho",
+                "julho",
+                "agosto",
+                "setembro",
+                "outubro",
+                "novembro",
+                "dezembro",
+            ],
+        }
+    }
+
+    /// Abbreviations which aren't prefixes of the full names, with their month number.
+    fn abbreviations(&self) -> &'static [(&'static str, u32)] {
+        match self {
+            TimeLocale::German => &[("mrz", 3), ("maer", 3), ("maerz", 3)],
+            TimeLocale::French => &[("fevr", 2), ("aout", 8), ("dec", 12)],
+            TimeLocale::Spanish => &[("set", 9), ("setiembre", 9)],
+            TimeLocale::Dutch => &[("mrt", 3)],
+            TimeLocale::Portuguese => &[("marco", 3)],
+            TimeLocale::English | TimeLocale::Italian => &[],
+        }
+    }
+
+    /// Month number (1-12) of a name or abbreviation (lowercase), at least 3 characters long.
+    fn month(&self, name: &str) -> Option<u32> {
+        if let Some((_, month)) = self.abbreviations().iter().find(|(abbr, _)| *abbr == name) {
+            return Some(*month);
+        }
+        if name.chars().count() < 3 {
+            return None;
+        }
+        let months = self.months();
+        let mut matching = months
+            .iter()
+            .enumerate()
+            .filter(|(_, month)| month.starts_with(name));
+        match (matching.next(), matching.next()) {
+            (Some((idx, _)), None) => Some(idx as u32 + 1),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<String> for TimeLocale {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Self::from_name(&name)
+    }
+}
+
+/// Month of the name in English or any of the locales, `None` if they disagree.
+fn month(name: &str, locales: &[TimeLocale]) -> Option<u32> {
+    let name = name.to_lowercase();
+    let mut months = std::iter::once(&TimeLocale::English)
+        .chain(locales)
+        .filter_map(|locale| locale.month(&name));
+    let month = months.next()?;
+    months.all(|other| other == month).then_some(month)
+}
+
+/// Parse date with month name: day, month, optional year in any order of the usual formats,
+/// time `hh:mm[:ss[.fff]]` and optional `Z`/`UTC`/`GMT` or `+hhmm` offset. A leading week day
+/// followed by a comma is skipped (`Wed, 14 Feb 2018 00:28:07 +0000`). Dates without year
+/// (syslog) are in the current year of the clock, or the previous one if they would be more
+/// than a day ahead.
+fn parse_month_name(text: &str, locales: &[TimeLocale], clock: &dyn Clock) -> Option<SystemTime> {
+    let text = match text.split_once(',') {
+        Some((week_day, rest)) if week_day.chars().all(char::is_alphabetic) => rest,
+        _ => text,
+    };
+    let (mut year, mut month_num, mut day, mut time, mut offset) = (None, None, None, None, None);
+    for token in text
+        .split(|c: char| c.is_whitespace() || c == '/' || c == ',')
+        .map(|token| token.trim_end_matches('.'))
+        .filter(|token| !token.is_empty())
+    {
+        let digits = token.bytes().all(|byte| byte.is_ascii_digit());
+        if digits && token.len() == 4 && year.is_none() {
+            year = Some(token.parse::<i64>().ok()?);
+        } else if digits && token.len() <= 2 && day.is_none() {
+            day = Some(token.parse::<u32>().ok()?);
+        } else if token.contains(':') && time.is_none() {
+            // access logs join the year and time: 14/Feb/2018:00:28:07
+            let time_text = match token.split_once(':') {
+                Some((first, rest)) if first.len() == 4 && year.is_none() => {
+                    year = Some(first.parse::<i64>().ok()?);
+                    rest
+                }
+                _ => token,
+            };
+            time = Some(parse_time(time_text)?);
+        } else if matches!(token, "Z" | "UTC" | "GMT") && offset.is_none() {
+            offset = Some(0);
+        } else if token.starts_with(['+', '-']) && offset.is_none() {
+            offset = Some(parse_offset(token)?);
+        } else if token.chars().all(char::is_alphabetic) && month_num.is_none() {
+            month_num = Some(month(token, locales)?);
+        } else {
+            return None;
+        }
+    }
+
+    let (month_num, day, time) = (month_num?, day?, time?);
+    if !(1..=days_in_month(year.unwrap_or(2000), month_num)).contains(&day) {
+        return None;
+    }
+    let at = |year| {
+        let days = days_from_civil(year, month_num, day);
+        let secs = u64::try_from(days * 86_400).ok()?;
+        apply_offset(
+            UNIX_EPOCH + Duration::from_secs(secs) + time,
+            offset.unwrap_or(0),
+        )
+    };
+    match year {
+        Some(year) => at(year),
+        None => {
+            let now = clock.now();
+            let current = civil_year(now.duration_since(UNIX_EPOCH).ok()?.as_secs() / 86_400);
+            let time = at(current)?;
+            match time.duration_since(now) {
+                Ok(ahead) if ahead > Duration::from_secs(86_400) => at(current - 1),
+                _ => Some(time),
+            }
+        }
+    }
+}
+
+/// Time of day `hh:mm[:ss[.fff]]` (`,` separates fraction too).
+fn parse_time(text: &str) -> Option<Duration> {
+    let (text, fraction) = match text.split_once(['.', ',']) {
+        Some((text, fraction)) => (text, Some(fraction)),
+        None => (text, None),
+    };
+    let mut parts = text.split(':').map(|part| {
+        (!part.is_empty() && part.len() <= 2)
+            .then(|| part.parse::<u64>().ok())
+            .flatten()
+    });
+    let hours = parts.next()??;
+    let minutes = parts.next()??;
+    let seconds = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    let nanos = match fraction {
+        Some(fraction) if fraction.bytes().all(|byte| byte.is_ascii_digit()) => {
+            format!("0.{}", fraction).parse::<f64>().ok()? * 1e9
+        }
+        Some(_) => return None,
+        None => 0.0,
+    };
+    Some(Duration::new(
+        hours * 3600 + minutes * 60 + seconds,
+        nanos as u32,
+    ))
+}
+
+/// Offset `+hhmm` or `+hh:mm` in seconds.
+fn parse_offset(text: &str) -> Option<i64> {
+    let sign = if text.starts_with('-') { -1 } else { 1 };
+    let digits = text[1..].replace(':', "");
+    if digits.len() != 4 || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since epoch of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Year of the day since epoch.
+fn civil_year(days: u64) -> i64 {
+    let mut year = 1970 + (days / 366) as i64;
+    while days_from_civil(year + 1, 1, 1) <= days as i64 {
+        year += 1;
+    }
+    year
+}
@@ -0,0 +1,225 @@
+//! TLS of network inputs and outputs (rustls): `https://` and `tls://` sinks, webhooks and
+//! collectors as a client, `serve` as a server. Certificates and keys are read from PEM files.
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection};
+use rustls::{ConnectionCommon, SideData, StreamOwned, DEFAULT_VERSIONS};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::ops::DerefMut;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Client config set by [`set_client`], the public roots are trusted by default.
+static CLIENT: RwLock<Option<Arc<ClientConfig>>> = RwLock::new(None);
+
+/// Certificates of outgoing connections: CA trusted besides the public roots (e.g. of an
+/// internal collector) and the certificate with its key the client authenticates with.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientTls {
+    pub ca: Option<PathBuf>,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+}
+
+impl ClientTls {
+    pub fn config(&self) -> Result<Arc<ClientConfig>, String> {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        if let Some(ca) = &self.ca {
+            for cert in certs(ca)? {
+                roots
+                    .add(cert)
+                    .map_err(|err| format!("Invalid CA certificate {}: {}", ca.display(), err))?;
+            }
+        }
+        let builder = ClientConfig::builder_with_protocol_versions(DEFAULT_VERSIONS)
+            .with_root_certificates(roots);
+        let config = match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(certs(cert)?, private_key(key)?)
+                .map_err(|err| format!("Invalid client certificate {}: {}", cert.display(), err))?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => return Err("Client certificate and key should be given together".to_string()),
+        };
+        Ok(Arc::new(config))
+    }
+}
+
+/// Use the certificates for every outgoing TLS connection from now on.
+pub fn set_client(tls: &ClientTls) -> Result<(), String> {
+    *CLIENT.write().expect("TLS config poisoned") = Some(tls.config()?);
+    Ok(())
+}
+
+fn client() -> Result<Arc<ClientConfig>, String> {
+    if let Some(config) = CLIENT.read().expect("TLS config poisoned").as_ref() {
+        return Ok(config.clone());
+    }
+    let config = ClientTls::default().config()?;
+    Ok(CLIENT
+        .write()
+        .expect("TLS config poisoned")
+        .get_or_insert(config)
+        .clone())
+}
+
+/// Certificate with its key the server authenticates with, and CA whose certificates clients
+/// have to authenticate with if it's set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerTls {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub client_ca: Option<PathBuf>,
+}
+
+impl ServerTls {
+    pub fn config(&self) -> Result<Arc<ServerConfig>, String> {
+        let builder = ServerConfig::builder_with_protocol_versions(DEFAULT_VERSIONS);
+        let builder = match &self.client_ca {
+            Some(ca) => {
+                let mut roots = RootCertStore::empty();
+                for cert in certs(ca)? {
+                    roots.add(cert).map_err(|err| {
+                        format!("Invalid client CA certificate {}: {}", ca.display(), err)
+                    })?;
+                }
+                let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                    .build()
+                    .map_err(|err| format!("Invalid client CA {}: {}", ca.display(), err))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let config = builder
+            .with_single_cert(certs(&self.cert)?, private_key(&self.key)?)
+            .map_err(|err| format!("Invalid certificate {}: {}", self.cert.display(), err))?;
+        Ok(Arc::new(config))
+    }
+}
+
+fn certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| format!("Can't read certificates {}: {}", path.display(), err))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+fn private_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    PrivateKeyDer::from_pem_file(path)
+        .map_err(|err| format!("Can't read private key {}: {}", path.display(), err))
+}
+
+/// Plain TCP or TLS connection.
+#[allow(clippy::large_enum_variant)]
+pub enum Stream {
+    Plain(TcpStream),
+    Client(StreamOwned<ClientConnection, TcpStream>),
+    Server(StreamOwned<ServerConnection, TcpStream>),
+}
+
+impl Stream {
+    /// Connect to the host, over TLS verifying that the certificate is of the host if `tls` is
+    /// set.
+    pub fn connect(host: &str, port: u16, tls: bool) -> io::Result<Self> {
+        let stream = TcpStream::connect((host, port))?;
+        if !tls {
+            return Ok(Stream::Plain(stream));
+        }
+        let name = ServerName::try_from(host.to_string())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let config = client().map_err(io::Error::other)?;
+        let connection = ClientConnection::new(config, name).map_err(io::Error::other)?;
+        Ok(Stream::Client(StreamOwned::new(connection, stream)))
+    }
+
+    /// Accepted connection, the handshake is done by the first read.
+    pub fn accept(stream: TcpStream, config: Option<&Arc<ServerConfig>>) -> io::Result<Self> {
+        match config {
+            Some(config) => {
+                let connection = ServerConnection::new(config.clone()).map_err(io::Error::other)?;
+                Ok(Stream::Server(StreamOwned::new(connection, stream)))
+            }
+            None => Ok(Stream::Plain(stream)),
+        }
+    }
+
+    /// Tell the other end that nothing more is sent (TLS `close_notify`), so it can tell the end
+    /// of the data from a dropped connection.
+    pub fn close(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(_) => Ok(()),
+            Stream::Client(stream) => close(&mut stream.conn, &mut stream.sock),
+            Stream::Server(stream) => close(&mut stream.conn, &mut stream.sock),
+        }
+    }
+
+    pub fn tcp(&self) -> &TcpStream {
+        match self {
+            Stream::Plain(stream) => stream,
+            Stream::Client(stream) => stream.get_ref(),
+            Stream::Server(stream) => stream.get_ref(),
+        }
+    }
+}
+
+/// Send `close_notify` after taking in what the other end already sent (e.g. session tickets),
+/// as closing a socket with unread data resets the connection, which can drop data not read by
+/// the other end yet.
+fn close<C, S>(conn: &mut C, sock: &mut TcpStream) -> io::Result<()>
+where
+    C: DerefMut<Target = ConnectionCommon<S>>,
+    S: SideData,
+{
+    sock.set_nonblocking(true)?;
+    let pending = loop {
+        match conn.read_tls(sock) {
+            Ok(0) => break Ok(()),
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+            Err(err) => break Err(err),
+        }
+    };
+    sock.set_nonblocking(false)?;
+    pending?;
+    conn.process_new_packets().map_err(io::Error::other)?;
+    conn.send_close_notify();
+    while conn.wants_write() {
+        conn.write_tls(sock)?;
+    }
+    sock.flush()
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buf),
+            Stream::Client(stream) => stream.read(buf),
+            Stream::Server(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            Stream::Client(stream) => stream.write(buf),
+            Stream::Server(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            Stream::Client(stream) => stream.flush(),
+            Stream::Server(stream) => stream.flush(),
+        }
+    }
+}
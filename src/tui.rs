@@ -0,0 +1,287 @@
+use crate::clock::{Clock, SystemClock};
+use crate::error_log;
+use crate::parser::ParseReport;
+use crate::units;
+use ratatui::backend::{Backend, CrosstermBackend};
+use ratatui::crossterm::cursor::Hide;
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Alignment, Constraint, Layout, Position, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{Block, Cell, List, Paragraph, Row, Table};
+use ratatui::{Terminal, TerminalOptions, Viewport};
+use serde_json::Error;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Stdout};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Rates are lines over this last period.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+/// Error lines kept, only the ones fitting the terminal are shown.
+const MAX_ERRORS: usize = 100;
+
+/// Live dashboard of followed files drawn with ratatui on the alternate screen of the terminal:
+/// counters and rates per log type (or group) since the start and a scrolling view of the recent
+/// lines which couldn't be parsed. The screen is restored when dropped.
+pub struct Dashboard<B: Backend = CrosstermBackend<Stdout>> {
+    terminal: Terminal<B>,
+    /// Whether the dashboard is on the alternate screen of stdout, left when dropped.
+    alternate_screen: bool,
+    title: String,
+    started: Instant,
+    total: ParseReport,
+    /// Counters per log type at past updates, oldest first.
+    history: VecDeque<(Instant, BTreeMap<String, u64>)>,
+    errors: VecDeque<String>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Dashboard {
+    /// Switch to the alternate screen, `title` heads the dashboard.
+    pub fn start(title: &str) -> io::Result<Self> {
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen, Hide)?;
+        let mut dashboard = Dashboard::with_backend(title, CrosstermBackend::new(stdout))?;
+        dashboard.alternate_screen = true;
+        Ok(dashboard)
+    }
+}
+
+impl<B: Backend> Dashboard<B> {
+    /// Dashboard drawn by the backend (e.g. `TestBackend`) in its whole area, without switching
+    /// screens.
+    pub fn with_backend(title: &str, backend: B) -> io::Result<Self> {
+        let area = Rect::from((Position::ORIGIN, backend.size()?));
+        let terminal = Terminal::with_options(
+            backend,
+            TerminalOptions {
+                viewport: Viewport::Fixed(area),
+            },
+        )?;
+
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        Ok(Self {
+            terminal,
+            alternate_screen: false,
+            title: title.to_string(),
+            started: clock.instant(),
+            total: ParseReport::default(),
+            history: VecDeque::new(),
+            errors: VecDeque::new(),
+            clock,
+        })
+    }
+
+    /// Take rates and times of errors by the clock instead of the system one.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.started = clock.instant();
+        self.clock = clock;
+        self
+    }
+
+    /// Backend the dashboard is drawn by.
+    pub fn backend(&self) -> &B {
+        self.terminal.backend()
+    }
+
+    /// Add lines registered since the last update.
+    pub fn update(&mut self, report: ParseReport) {
+        self.total += report;
+        let now = self.clock.instant();
+        while self
+            .history
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > RATE_WINDOW)
+        {
+            self.history.pop_front();
+        }
+        self.history.push_back((now, self.counters()));
+    }
+
+    /// Record a line of the source which couldn't be parsed.
+    pub fn error(&mut self, source: &str, line: &str, err: &Error) {
+        let time = humantime::format_rfc3339_seconds(self.clock.now()).to_string();
+        self.errors.push_back(format!(
+            "{} {} [{}] {}",
+            &time[11..19],
+            source,
+            error_log::kind(err),
+            line
+        ));
+        if self.errors.len() > MAX_ERRORS {
+            self.errors.pop_front();
+        }
+    }
+
+    /// Redraw the dashboard, `status` is shown under the title (e.g. forwarded lines).
+    pub fn draw(&mut self, status: &str) -> io::Result<()> {
+        if self.alternate_screen {
+            let (width, height) = terminal_size();
+            let area = Rect::new(0, 0, width, height);
+            if self.terminal.get_frame().area() != area {
+                self.terminal.resize(area)?;
+            }
+        }
+        let header = Text::from(vec![
+            Line::from(format!(
+                "{} - up {}, lines: {}, failed lines: {}, {:.1} lines/s",
+                self.title,
+                humantime::format_duration(Duration::from_secs(
+                    self.clock
+                        .instant()
+                        .saturating_duration_since(self.started)
+                        .as_secs(),
+                )),
+                self.total.lines,
+                self.total.failed_lines,
+                self.rates().values().fold(0.0, |sum, rate| sum + rate)
+            )),
+            Line::from(status.to_string()),
+        ]);
+        let types = self.types_table();
+        let errors = self.errors_list();
+
+        self.terminal.draw(|frame| {
+            // half of the screen at most for types, the rest for errors
+            let [header_area, types_area, errors_area] = Layout::vertical([
+                Constraint::Length(2),
+                Constraint::Max(frame.area().height / 2),
+                Constraint::Fill(1),
+            ])
+            .areas(frame.area());
+            frame.render_widget(Paragraph::new(header), header_area);
+            frame.render_widget(types(types_area.height), types_area);
+            frame.render_widget(errors(errors_area.height), errors_area);
+        })?;
+        Ok(())
+    }
+
+    /// Table of log types by lines, the ones which don't fit the height are summed up in a row.
+    fn types_table(&self) -> impl FnOnce(u16) -> Table<'static> {
+        let rates = self.rates();
+        let mut types: Vec<(String, u64, u64)> = self
+            .total
+            .log_register
+            .iter()
+            .map(|(key, register)| (key.clone(), register.counter, register.num_of_bytes))
+            .collect();
+        types.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        move |height| {
+            // borders and the header take 3 rows
+            let max_types = (height as usize).saturating_sub(3).max(1);
+            let mut rows: Vec<Row> = types
+                .iter()
+                .take(max_types)
+                .map(|(key, lines, bytes)| {
+                    Row::new([
+                        Cell::from(key.clone()),
+                        right(units::format_count(lines)),
+                        right(format!(
+                            "{:.1}",
+                            rates.get(key).copied().unwrap_or_default()
+                        )),
+                        right(units::format_bytes(*bytes as f64)),
+                    ])
+                })
+                .collect();
+            if types.len() > max_types {
+                rows.pop();
+                rows.push(Row::new([Cell::from(format!(
+                    "... {} more",
+                    types.len() - max_types + 1
+                ))]));
+            }
+            Table::new(
+                rows,
+                [
+                    Constraint::Fill(1),
+                    Constraint::Length(12),
+                    Constraint::Length(10),
+                    Constraint::Length(12),
+                ],
+            )
+            .header(
+                Row::new([
+                    Cell::from("log type"),
+                    right("lines".to_string()),
+                    right("lines/s".to_string()),
+                    right("bytes".to_string()),
+                ])
+                .style(Style::new().bold()),
+            )
+            .block(Block::bordered().title(" log types "))
+        }
+    }
+
+    /// Most recent errors fitting the height, the newest at the bottom.
+    fn errors_list(&self) -> impl FnOnce(u16) -> List<'static> {
+        let errors = self.errors.clone();
+        let title = format!(" recent errors ({} total) ", self.total.failed_lines);
+
+        move |height| {
+            // borders take 2 rows
+            let max_errors = (height as usize).saturating_sub(2);
+            let skip = errors.len().saturating_sub(max_errors);
+            List::new(errors.into_iter().skip(skip)).block(Block::bordered().title(title))
+        }
+    }
+
+    fn counters(&self) -> BTreeMap<String, u64> {
+        self.total
+            .log_register
+            .iter()
+            .map(|(key, register)| (key.clone(), register.counter))
+            .collect()
+    }
+
+    /// Lines per second of every log type over the rate window.
+    fn rates(&self) -> BTreeMap<String, f64> {
+        let (Some((first_at, first)), Some((last_at, last))) =
+            (self.history.front(), self.history.back())
+        else {
+            return BTreeMap::new();
+        };
+        let secs = last_at.duration_since(*first_at).as_secs_f64();
+        if secs == 0.0 {
+            return BTreeMap::new();
+        }
+        last.iter()
+            .map(|(key, lines)| {
+                let before = first.get(key).copied().unwrap_or_default();
+                (key.clone(), (lines - before) as f64 / secs)
+            })
+            .collect()
+    }
+}
+
+impl<B: Backend> Drop for Dashboard<B> {
+    fn drop(&mut self) {
+        // the screen is restored even on panic, ignore write errors
+        if self.alternate_screen {
+            let _ = self.terminal.show_cursor();
+            let _ = execute!(io::stdout(), LeaveAlternateScreen);
+        }
+    }
+}
+
+/// Right aligned cell of a number.
+fn right(text: String) -> Cell<'static> {
+    Cell::from(Line::from(text).alignment(Alignment::Right))
+}
+
+/// Columns and rows of the terminal on stdout, 80x24 if it's not a terminal.
+fn terminal_size() -> (u16, u16) {
+    #[cfg(unix)]
+    {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        // TIOCGWINSZ only fills the struct
+        let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+        if result == 0 && size.ws_col > 0 && size.ws_row > 0 {
+            return (size.ws_col, size.ws_row);
+        }
+    }
+    (80, 24)
+}
@@ -0,0 +1,74 @@
+use crate::parser::{self, ParseOptions, Strategy};
+use crate::subset::{self, Subset};
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Threads used when the number isn't given: the available parallelism of the machine.
+pub fn available_threads() -> u8 {
+    thread::available_parallelism()
+        .map_or(1, |threads| threads.get())
+        .min(u8::MAX as usize) as u8
+}
+
+/// Powers of two below `max` and `max` itself, the counts tried by [`tune`].
+pub fn candidates(max: u8) -> Vec<u8> {
+    let mut candidates: Vec<u8> = (0..8)
+        .map(|power| 1u8 << power)
+        .take_while(|threads| *threads < max)
+        .collect();
+    candidates.push(max.max(1));
+    candidates
+}
+
+/// Result of [`tune`].
+#[derive(Debug, Clone)]
+pub struct Tuning {
+    /// The fastest number of threads.
+    pub threads: u8,
+    /// Parsing time of the sample for every tried number of threads.
+    pub timings: Vec<(u8, Duration)>,
+    /// Size of the sample, whole lines of the first bytes of the input.
+    pub sample_bytes: u64,
+}
+
+/// Time parsing the first `sample_bytes` of the file with every candidate number of threads
+/// and pick the fastest. The sample is parsed once before, so every run reads it from the page
+/// cache. Options with side effects (progress, checkpoint, quarantine) are not used.
+pub fn tune<P: AsRef<Path>>(
+    strategy: Strategy,
+    input_file: P,
+    sample_bytes: u64,
+    candidates: &[u8],
+    options: &ParseOptions,
+) -> io::Result<Tuning> {
+    let sample = subset::extract(input_file, Subset::HeadBytes(sample_bytes), 0)?;
+    let options = ParseOptions {
+        bad_lines: None,
+        progress: None,
+        checkpoint: None,
+        profiler: None,
+        ..options.clone()
+    };
+    parser::parse_file(strategy, 1, sample.path(), &options)?;
+
+    let timings: Vec<(u8, Duration)> = candidates
+        .iter()
+        .map(|threads| {
+            let now = Instant::now();
+            parser::parse_file(strategy, *threads, sample.path(), &options)?;
+            Ok((*threads, now.elapsed()))
+        })
+        .collect::<io::Result<_>>()?;
+    let threads = timings
+        .iter()
+        .min_by_key(|(_, elapsed)| *elapsed)
+        .map_or(1, |(threads, _)| *threads);
+
+    Ok(Tuning {
+        threads,
+        timings,
+        sample_bytes: sample.bytes(),
+    })
+}
@@ -0,0 +1,189 @@
+use std::env;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Parse human readable byte size, e.g. `500`, `10KB`, `1.5GB`, `2GiB`.
+pub fn parse_bytes(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split_idx = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split_idx);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid byte size: [{}]", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" | "k" => 1_000,
+        "mb" | "m" => 1_000_000,
+        "gb" | "g" => 1_000_000_000,
+        "tb" | "t" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return Err(format!("Unknown byte size unit: [{}]", unit)),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Format number of bytes with decimal unit, e.g. `1.50 GB`.
+pub fn format_bytes(num_of_bytes: f64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = num_of_bytes;
+    let mut unit_idx = 0;
+    while value >= 1000.0 && unit_idx < units.len() - 1 {
+        value /= 1000.0;
+        unit_idx += 1;
+    }
+    let value = locale().group(&format!("{:.2}", value));
+    format!("{} {}", value, units[unit_idx])
+}
+
+/// Format number of bytes with binary unit, e.g. `1.50 MiB` (bytes without fraction).
+pub fn format_binary_bytes(num_of_bytes: u64) -> String {
+    let units = ["KiB", "MiB", "GiB", "TiB"];
+    if num_of_bytes < 1024 {
+        return format!("{} B", num_of_bytes);
+    }
+    let mut value = num_of_bytes as f64 / 1024.0;
+    let mut unit_idx = 0;
+    while value >= 1024.0 && unit_idx < units.len() - 1 {
+        value /= 1024.0;
+        unit_idx += 1;
+    }
+    let value = locale().group(&format!("{:.2}", value));
+    format!("{} {}", value, units[unit_idx])
+}
+
+/// Format duration for humans with sub-second precision, e.g. `900ms`, `13.4s` or `2m 13.4s`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs_f64();
+    if secs < 0.01 {
+        return format!("{:.2}ms", secs * 1000.0);
+    }
+    if secs < 1.0 {
+        return format!("{:.0}ms", secs * 1000.0);
+    }
+    // rounded first, so 59.96 sec is `1m 0.0s`
+    let tenths = (secs * 10.0).round() as u64;
+    let (hours, minutes, tenths) = (tenths / 36_000, tenths / 600 % 60, tenths % 600);
+    let seconds = format!("{}.{}s", tenths / 10, tenths % 10);
+    match (hours, minutes) {
+        (0, 0) => seconds,
+        (0, _) => format!("{}m {}", minutes, seconds),
+        _ => format!("{}h {}m {}", hours, minutes, seconds),
+    }
+}
+
+/// Format duration as ISO 8601 with millisecond precision, e.g. `PT2M13.400S`.
+pub fn iso_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    let (hours, minutes, millis) = (millis / 3_600_000, millis / 60_000 % 60, millis % 60_000);
+    let mut iso = "PT".to_string();
+    if hours > 0 {
+        iso.push_str(&format!("{}H", hours));
+    }
+    if minutes > 0 {
+        iso.push_str(&format!("{}M", minutes));
+    }
+    if millis > 0 || iso.len() == 2 {
+        iso.push_str(&format!("{}.{:03}S", millis / 1000, millis % 1000));
+    }
+    iso
+}
+
+/// Separators of numbers in human output (tables, HTML reports, the dashboard), machine formats
+/// (JSON, CSV, Parquet, SQLite) are locale-free.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Locale {
+    /// Separator of thousands, numbers are not grouped without it.
+    pub grouping: Option<char>,
+    pub decimal: char,
+}
+
+static LOCALE: RwLock<Locale> = RwLock::new(Locale::PLAIN);
+
+impl Locale {
+    /// `C` locale: no grouping, `.` decimal separator.
+    pub const PLAIN: Locale = Locale {
+        grouping: None,
+        decimal: '.',
+    };
+
+    /// Locale by its POSIX name, e.g. `en_US.UTF-8`, `de_DE` or `fr`, separators depend on the
+    /// language (and the country for Swiss and Brazilian ones).
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        let name = name.split(['.', '@']).next().unwrap_or_default();
+        let (language, country) = name.split_once(['_', '-']).unwrap_or((name, ""));
+        let (grouping, decimal) = match (language, country) {
+            ("C" | "POSIX" | "", _) => (None, '.'),
+            ("de" | "it" | "fr", "CH") => (Some('\''), '.'),
+            ("pt", "BR") => (Some('.'), ','),
+            ("en" | "ja" | "zh" | "ko" | "he" | "th" | "ga", _) => (Some(','), '.'),
+            (
+                "de" | "nl" | "it" | "es" | "pt" | "da" | "id" | "tr" | "el" | "ro" | "hr" | "sl",
+                _,
+            ) => (Some('.'), ','),
+            (
+                "fr" | "pl" | "ru" | "uk" | "cs" | "sk" | "sv" | "fi" | "nb" | "nn" | "no" | "hu"
+                | "bg" | "et" | "lt" | "lv",
+                _,
+            ) => (Some('\u{a0}'), ','),
+            _ => return Err(format!("Unknown locale: [{}]", name)),
+        };
+        Ok(Self { grouping, decimal })
+    }
+
+    /// Locale of numbers from `LC_ALL`, `LC_NUMERIC` or `LANG` (the first one set), plain if
+    /// none is set or it's unknown.
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::from_name(&value).ok())
+            .unwrap_or(Self::PLAIN)
+    }
+
+    /// Number formatted in the `C` locale (optional sign, digits, `.` and fraction) with the
+    /// separators of this one.
+    pub fn group(&self, number: &str) -> String {
+        let (sign, number) = match number.strip_prefix(['-', '+']) {
+            Some(rest) => number.split_at(number.len() - rest.len()),
+            None => ("", number),
+        };
+        let (integer, fraction) = match number.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (number, None),
+        };
+        let mut grouped = sign.to_string();
+        for (idx, digit) in integer.chars().enumerate() {
+            if idx > 0 && (integer.len() - idx) % 3 == 0 {
+                grouped.extend(self.grouping);
+            }
+            grouped.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            grouped.push(self.decimal);
+            grouped.push_str(fraction);
+        }
+        grouped
+    }
+}
+
+/// Set the locale of human output, plain by default.
+pub fn set_locale(locale: Locale) {
+    *LOCALE.write().expect("Locale poisoned") = locale;
+}
+
+pub fn locale() -> Locale {
+    *LOCALE.read().expect("Locale poisoned")
+}
+
+/// Counter with the separators of the locale, e.g. `1,234,567`.
+pub fn format_count<T: ToString>(count: T) -> String {
+    locale().group(&count.to_string())
+}
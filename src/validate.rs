@@ -0,0 +1,424 @@
+use crate::chunk;
+use crate::parser::{self, ParseOptions};
+use crate::platform;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::thread;
+
+/// Nesting of schemas (e.g. through `$ref`) deeper than this is reported as an error.
+const MAX_DEPTH: usize = 64;
+
+/// JSON Schema records are checked against. Supported keywords are `type`, `enum`, `const`,
+/// `properties`, `required`, `additionalProperties`, `items`, `minItems`, `maxItems`,
+/// `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum`, `minLength`, `maxLength`,
+/// `pattern`, `allOf`, `anyOf`, `oneOf`, `not` and local `$ref`; other keywords are ignored.
+#[derive(Debug, Clone)]
+pub struct JsonSchema {
+    root: Value,
+    patterns: HashMap<String, Regex>,
+}
+
+impl JsonSchema {
+    pub fn new(root: Value) -> Result<Self, String> {
+        let mut patterns = HashMap::new();
+        compile_patterns(&root, &mut patterns)?;
+        Ok(Self { root, patterns })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("Can't read schema file [{}]: {}", path, err))?;
+        let root = serde_json::from_str(&content)
+            .map_err(|err| format!("Can't parse schema file [{}]: {}", path, err))?;
+        Self::new(root)
+    }
+
+    /// Violations of the schema, each one prefixed by JSON pointer of the value.
+    pub fn validate(&self, value: &Value) -> Vec<String> {
+        let mut errors = vec![];
+        self.check(&self.root, value, "", 0, &mut errors);
+        errors
+    }
+
+    fn check(
+        &self,
+        schema: &Value,
+        value: &Value,
+        path: &str,
+        depth: usize,
+        errors: &mut Vec<String>,
+    ) {
+        let at = |message: String| format!("{}: {}", display_path(path), message);
+        if depth > MAX_DEPTH {
+            errors.push(at("schema is nested too deep (recursive $ref?)".to_string()));
+            return;
+        }
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => return errors.push(at("no value is allowed".to_string())),
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+
+        if let Some(Value::String(reference)) = schema.get("$ref") {
+            match reference
+                .strip_prefix('#')
+                .and_then(|pointer| self.root.pointer(pointer))
+            {
+                Some(referenced) => self.check(referenced, value, path, depth + 1, errors),
+                None => errors.push(at(format!("can't resolve $ref `{}`", reference))),
+            }
+            return;
+        }
+
+        if let Some(expected) = schema.get("type") {
+            let types: Vec<&str> = match expected {
+                Value::String(name) => vec![name],
+                Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+                _ => vec![],
+            };
+            if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+                return errors.push(at(format!(
+                    "expected {}, got {}",
+                    types.join(" or "),
+                    type_name(value)
+                )));
+            }
+        }
+        if let Some(Value::Array(allowed)) = schema.get("enum") {
+            if !allowed.contains(value) {
+                errors.push(at(format!(
+                    "{} is not one of {}",
+                    value,
+                    Value::Array(allowed.clone())
+                )));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if value != expected {
+                errors.push(at(format!("{} should be {}", value, expected)));
+            }
+        }
+
+        match value {
+            Value::Number(number) => {
+                let number = number.as_f64().unwrap_or_default();
+                let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+                // draft 4 makes exclusive bounds flags of minimum and maximum
+                let flag = |keyword: &str| schema.get(keyword) == Some(&Value::Bool(true));
+                if let Some(minimum) = bound("minimum") {
+                    if number < minimum || (flag("exclusiveMinimum") && number == minimum) {
+                        errors.push(at(format!("{} is less than minimum {}", number, minimum)));
+                    }
+                }
+                if let Some(maximum) = bound("maximum") {
+                    if number > maximum || (flag("exclusiveMaximum") && number == maximum) {
+                        errors.push(at(format!(
+                            "{} is greater than maximum {}",
+                            number, maximum
+                        )));
+                    }
+                }
+                if let Some(minimum) = bound("exclusiveMinimum") {
+                    if number <= minimum {
+                        errors.push(at(format!("{} is not greater than {}", number, minimum)));
+                    }
+                }
+                if let Some(maximum) = bound("exclusiveMaximum") {
+                    if number >= maximum {
+                        errors.push(at(format!("{} is not less than {}", number, maximum)));
+                    }
+                }
+            }
+            Value::String(text) => {
+                let len = text.chars().count() as u64;
+                if let Some(min_len) = schema.get("minLength").and_then(Value::as_u64) {
+                    if len < min_len {
+                        errors.push(at(format!("string is shorter than {} characters", min_len)));
+                    }
+                }
+                if let Some(max_len) = schema.get("maxLength").and_then(Value::as_u64) {
+                    if len > max_len {
+                        errors.push(at(format!("string is longer than {} characters", max_len)));
+                    }
+                }
+                if let Some(Value::String(pattern)) = schema.get("pattern") {
+                    if !self.patterns[pattern].is_match(text) {
+                        errors.push(at(format!("{} doesn't match pattern `{}`", value, pattern)));
+                    }
+                }
+            }
+            Value::Array(items) => {
+                let len = items.len() as u64;
+                if let Some(min_items) = schema.get("minItems").and_then(Value::as_u64) {
+                    if len < min_items {
+                        errors.push(at(format!("array has fewer than {} items", min_items)));
+                    }
+                }
+                if let Some(max_items) = schema.get("maxItems").and_then(Value::as_u64) {
+                    if len > max_items {
+                        errors.push(at(format!("array has more than {} items", max_items)));
+                    }
+                }
+                match schema.get("items") {
+                    // tuple of schemas, one per position
+                    Some(Value::Array(item_schemas)) => {
+                        for (idx, (item_schema, item)) in item_schemas.iter().zip(items).enumerate()
+                        {
+                            let item_path = format!("{}/{}", path, idx);
+                            self.check(item_schema, item, &item_path, depth + 1, errors);
+                        }
+                    }
+                    Some(item_schema) => {
+                        for (idx, item) in items.iter().enumerate() {
+                            let item_path = format!("{}/{}", path, idx);
+                            self.check(item_schema, item, &item_path, depth + 1, errors);
+                        }
+                    }
+                    None => {}
+                }
+            }
+            Value::Object(fields) => {
+                if let Some(Value::Array(required)) = schema.get("required") {
+                    for field in required.iter().filter_map(Value::as_str) {
+                        if !fields.contains_key(field) {
+                            errors.push(at(format!("missing required field `{}`", field)));
+                        }
+                    }
+                }
+                let properties = schema.get("properties").and_then(Value::as_object);
+                for (field, field_value) in fields {
+                    let field_path = format!("{}/{}", path, escape_pointer(field));
+                    match properties.and_then(|properties| properties.get(field)) {
+                        Some(field_schema) => {
+                            self.check(field_schema, field_value, &field_path, depth + 1, errors)
+                        }
+                        None => match schema.get("additionalProperties") {
+                            Some(Value::Bool(false)) => {
+                                errors.push(at(format!("unexpected field `{}`", field)))
+                            }
+                            Some(additional) => {
+                                self.check(additional, field_value, &field_path, depth + 1, errors)
+                            }
+                            None => {}
+                        },
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(Value::Array(schemas)) = schema.get("allOf") {
+            for schema in schemas {
+                self.check(schema, value, path, depth + 1, errors);
+            }
+        }
+        let matching = |schemas: &[Value]| {
+            schemas
+                .iter()
+                .filter(|schema| {
+                    let mut errors = vec![];
+                    self.check(schema, value, path, depth + 1, &mut errors);
+                    errors.is_empty()
+                })
+                .count()
+        };
+        if let Some(Value::Array(schemas)) = schema.get("anyOf") {
+            if matching(schemas) == 0 {
+                errors.push(at("matches no schema of anyOf".to_string()));
+            }
+        }
+        if let Some(Value::Array(schemas)) = schema.get("oneOf") {
+            let count = matching(schemas);
+            if count != 1 {
+                errors.push(at(format!(
+                    "matches {} schemas of oneOf instead of one",
+                    count
+                )));
+            }
+        }
+        if let Some(not) = schema.get("not") {
+            if matching(std::slice::from_ref(not)) == 1 {
+                errors.push(at("matches schema of not".to_string()));
+            }
+        }
+    }
+}
+
+/// Compile regular expressions of `pattern` keywords, values of keywords which aren't schemas
+/// are skipped.
+fn compile_patterns(schema: &Value, patterns: &mut HashMap<String, Regex>) -> Result<(), String> {
+    match schema {
+        Value::Object(object) => {
+            for (keyword, value) in object {
+                match (keyword.as_str(), value) {
+                    ("pattern", Value::String(pattern)) => {
+                        let regex = Regex::new(pattern)
+                            .map_err(|err| format!("Invalid pattern [{}]: {}", pattern, err))?;
+                        patterns.insert(pattern.clone(), regex);
+                    }
+                    ("enum" | "const" | "default" | "examples", _) => {}
+                    _ => compile_patterns(value, patterns)?,
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                compile_patterns(item, patterns)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match (name, value) {
+        ("integer", Value::Number(number)) => {
+            number.is_i64() || number.is_u64() || number.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        _ => type_name(value) == name || (name == "number" && value.is_number()),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_i64() || number.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn escape_pointer(field: &str) -> String {
+    field.replace('~', "~0").replace('/', "~1")
+}
+
+fn display_path(path: &str) -> &str {
+    match path {
+        "" => "(root)",
+        path => path,
+    }
+}
+
+/// Line which is not valid JSON or doesn't conform to the schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidLine {
+    /// Line number starting at 1.
+    pub line: u64,
+    /// Byte offset of the line in the file.
+    pub offset: u64,
+    pub errors: Vec<String>,
+}
+
+/// Result of validating a file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Validation {
+    pub lines: u64,
+    pub invalid_lines: u64,
+    /// The first invalid lines in file order, at most as many as requested.
+    pub invalid: Vec<InvalidLine>,
+}
+
+impl Validation {
+    pub fn is_valid(&self) -> bool {
+        self.invalid_lines == 0
+    }
+}
+
+/// Check that every line of the NDJSON file is a JSON value conforming to the schema (if
+/// given). Parts of the file are checked by separate threads; empty lines are allowed.
+pub fn validate_file<P: AsRef<Path>>(
+    num_of_thread: u8,
+    input_file: P,
+    schema: Option<&JsonSchema>,
+    max_reported: usize,
+    options: &ParseOptions,
+) -> io::Result<Validation> {
+    let input_file = input_file.as_ref();
+    // every line of a valid file is checked, bytes which can't be read fail the validation
+    let options = &ParseOptions {
+        skip_unreadable: false,
+        ..options.clone()
+    };
+    let size = platform::open_shared(input_file)?.metadata()?.len();
+    let ranges = chunk::byte_ranges(size, num_of_thread as u64);
+
+    let parts = thread::scope(|scope| {
+        let threads: Vec<_> = ranges
+            .into_iter()
+            .map(|(start_idx, bytes_portion)| {
+                scope.spawn(move || {
+                    let mut part = Validation::default();
+                    let file = platform::open_shared(input_file)?;
+                    parser::partially_scan_records(
+                        start_idx,
+                        bytes_portion,
+                        0,
+                        input_file,
+                        file,
+                        options,
+                        |offset, line| {
+                            part.lines += 1;
+                            let errors = match &line.error {
+                                Some(error) => vec![error.to_string()],
+                                None => validate_line(&line.text, schema),
+                            };
+                            if !errors.is_empty() {
+                                part.invalid_lines += 1;
+                                // line numbers are relative to the part until parts are joined
+                                if part.invalid.len() < max_reported {
+                                    part.invalid.push(InvalidLine {
+                                        line: part.lines,
+                                        offset,
+                                        errors,
+                                    });
+                                }
+                            }
+                            true
+                        },
+                    )?;
+                    Ok(part)
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().expect("Validating thread panicked"))
+            .collect::<io::Result<Vec<Validation>>>()
+    })?;
+
+    let mut validation = Validation::default();
+    for part in parts {
+        for mut invalid in part.invalid {
+            if validation.invalid.len() < max_reported {
+                invalid.line += validation.lines;
+                validation.invalid.push(invalid);
+            }
+        }
+        validation.lines += part.lines;
+        validation.invalid_lines += part.invalid_lines;
+    }
+    Ok(validation)
+}
+
+/// Errors of a single line, none if it's valid.
+pub fn validate_line(line: &str, schema: Option<&JsonSchema>) -> Vec<String> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.trim().is_empty() {
+        return vec![];
+    }
+    match serde_json::from_str::<Value>(line) {
+        Ok(value) => schema
+            .map(|schema| schema.validate(&value))
+            .unwrap_or_default(),
+        Err(err) => vec![format!("invalid JSON: {}", err)],
+    }
+}
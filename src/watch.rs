@@ -0,0 +1,248 @@
+use crate::decompress::Input;
+use crate::parser::{self, ParseOptions, ParseReport, Strategy};
+use log::{error, warn};
+use notify_debouncer_full::notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
+use notify_debouncer_full::notify::{EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{DebounceEventResult, Debouncer, RecommendedCache};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Time without events of a file before they're reported, e.g. a close right before a rename.
+pub const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Finds files landing in a drop folder by polling its listing, the fallback of [`DirEvents`] for
+/// folders which can't be watched. A file is ready once its size didn't change between two
+/// polls, so files still being copied are not parsed half-way. Hidden files (e.g. `.part` files
+/// of rsync) and subdirectories are skipped.
+pub struct DirWatcher {
+    dir: PathBuf,
+    /// Sizes of files seen by the last poll which are not ready yet.
+    sizes: HashMap<PathBuf, u64>,
+    done: HashSet<PathBuf>,
+}
+
+impl DirWatcher {
+    /// Watch the directory, files already in it are reported as well.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            sizes: HashMap::new(),
+            done: HashSet::new(),
+        }
+    }
+
+    /// Files which became ready since the last poll (sorted by name), every file is returned
+    /// only once.
+    pub fn poll(&mut self) -> io::Result<Vec<PathBuf>> {
+        let mut ready = vec![];
+        let mut sizes = HashMap::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if is_hidden(&entry.file_name()) || self.done.contains(&path) {
+                continue;
+            }
+            let metadata = match entry.metadata() {
+                Ok(metadata) if metadata.is_file() => metadata,
+                // removed since listed or not a file
+                _ => continue,
+            };
+            if self.sizes.get(&path) == Some(&metadata.len()) {
+                ready.push(path);
+            } else {
+                sizes.insert(path, metadata.len());
+            }
+        }
+        ready.sort();
+        self.done.extend(ready.iter().cloned());
+        self.sizes = sizes;
+
+        Ok(ready)
+    }
+}
+
+/// Finds files landing in a drop folder from file system notifications. A file is ready once it's
+/// closed after writing or renamed into the folder, so files still being copied are not parsed
+/// half-way (backends which don't report closes, e.g. FSEvents, report files once their writes
+/// are debounced). Files already in the folder are ready right away, hidden files and
+/// subdirectories are skipped like by [`DirWatcher`].
+pub struct DirEvents {
+    dir: PathBuf,
+    /// Watches the folder until it's dropped.
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+    events: Receiver<DebounceEventResult>,
+    /// Files in the folder when it started to be watched, not reported yet.
+    existing: Vec<PathBuf>,
+    done: HashSet<PathBuf>,
+}
+
+impl DirEvents {
+    /// Watch the directory, fails if it can't be watched (e.g. it's missing, or there are no
+    /// inotify watches left).
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let (sender, events) = mpsc::channel();
+        let mut debouncer = notify_debouncer_full::new_debouncer(DEBOUNCE, None, sender)
+            .map_err(io::Error::other)?;
+        debouncer
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(io::Error::other)?;
+        // listed once watched, so no file lands in between
+        let existing = list_files(&dir)?;
+
+        Ok(Self {
+            dir,
+            _debouncer: debouncer,
+            events,
+            existing,
+            done: HashSet::new(),
+        })
+    }
+
+    /// Files which became ready (sorted by name), waiting up to the timeout for the first of
+    /// them. Every file is returned only once.
+    pub fn wait(&mut self, timeout: Duration) -> io::Result<Vec<PathBuf>> {
+        let deadline = Instant::now() + timeout;
+        let mut ready = mem::take(&mut self.existing);
+        while ready.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let events = match self.events.recv_timeout(remaining) {
+                Ok(events) => events.map_err(|errors| {
+                    let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+                    io::Error::other(format!(
+                        "Can't watch {}: {}",
+                        self.dir.display(),
+                        errors.join(", ")
+                    ))
+                })?,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::other(format!(
+                        "Watcher of {} stopped",
+                        self.dir.display()
+                    )))
+                }
+            };
+            for event in events {
+                if event.need_rescan() {
+                    // events were dropped, e.g. the queue overflowed
+                    ready.extend(list_files(&self.dir)?);
+                } else if is_ready(&event.kind) {
+                    ready.extend(event.paths.last().filter(|path| is_file(path)).cloned());
+                }
+            }
+            ready.retain(|path| !self.done.contains(path));
+        }
+        ready.sort();
+        ready.dedup();
+        self.done.extend(ready.iter().cloned());
+
+        Ok(ready)
+    }
+}
+
+/// Whether the event ends the writes of its file: closes after writing and renames (to the last
+/// path of the event) with inotify, any creation or write with backends which don't report
+/// closes.
+fn is_ready(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Access(AccessKind::Close(AccessMode::Write))
+        | EventKind::Modify(ModifyKind::Name(
+            RenameMode::To | RenameMode::Both | RenameMode::Any,
+        )) => true,
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any) => {
+            !cfg!(any(target_os = "linux", target_os = "android"))
+        }
+        _ => false,
+    }
+}
+
+/// Files of the directory, except for hidden ones.
+fn list_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if is_file(&path) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Whether the path is a file which isn't hidden.
+fn is_file(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| !is_hidden(name)) && path.is_file()
+}
+
+fn is_hidden(file_name: &OsStr) -> bool {
+    file_name.to_string_lossy().starts_with('.')
+}
+
+/// Where watched files come from.
+enum Source {
+    Events(DirEvents),
+    Polling(DirWatcher),
+}
+
+/// Parse files landing in the directory (compressed ones are decompressed as they're read) and
+/// merge them into a running report. The directory is watched for notifications, or listed every
+/// poll interval if it can't be. The handler gets every parsed file with the running report,
+/// watching stops when it returns `false`.
+pub fn watch<F>(
+    num_of_thread: u8,
+    dir: &Path,
+    options: &ParseOptions,
+    poll_interval: Duration,
+    mut handler: F,
+) -> io::Result<ParseReport>
+where
+    F: FnMut(&Path, &ParseReport) -> bool,
+{
+    let mut source = match DirEvents::new(dir) {
+        Ok(events) => Source::Events(events),
+        Err(err) => {
+            warn!(
+                "Can't watch {} ({}), listing it every {:?} instead",
+                dir.display(),
+                err,
+                poll_interval
+            );
+            Source::Polling(DirWatcher::new(dir))
+        }
+    };
+    let mut running = ParseReport::default();
+    loop {
+        let ready = match &mut source {
+            Source::Events(events) => events.wait(poll_interval)?,
+            Source::Polling(watcher) => watcher.poll()?,
+        };
+        for file in ready {
+            let input = match Input::open(&file) {
+                Ok(input) => input,
+                Err(err) => {
+                    error!("Can't open {}: {}", file.display(), err);
+                    continue;
+                }
+            };
+            match parser::parse_input(Strategy::Dashmap, num_of_thread, &input, options) {
+                Ok(report) => running += report,
+                Err(err) => {
+                    error!("Can't parse {}: {}", file.display(), err);
+                    continue;
+                }
+            }
+            if !handler(&file, &running) {
+                return Ok(running);
+            }
+        }
+        if let Source::Polling(_) = source {
+            options.clock().sleep(poll_interval);
+        }
+    }
+}
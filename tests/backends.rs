@@ -0,0 +1,6549 @@
+//! Property tests: every parsing backend gives the same report for random corpora drawn by
+//! proptest strategies, whatever the number of threads. A failing case is shrunk and printed.
+
+use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
+use ed25519_dalek::pkcs8::{EncodePrivateKey, EncodePublicKey};
+use log_parser::aggregator::{self, Aggregator};
+use log_parser::alert::{self, AlertRule};
+#[cfg(feature = "amqp")]
+use log_parser::amqp::{self, AmqpUrl};
+use log_parser::anonymize::{self, Anonymizer};
+#[cfg(feature = "async")]
+use log_parser::async_parser;
+use log_parser::budget::{self, BudgetTracker, ByteBudget};
+use log_parser::cardinality::FieldProfiler;
+use log_parser::checkpoint::Checkpoint;
+use log_parser::chunk::{self, FailedRange, RetryReader};
+use log_parser::clock::ManualClock;
+use log_parser::config::ParseConfig;
+use log_parser::cost::CostEstimator;
+use log_parser::csv::CsvFormat;
+use log_parser::decompress::{self, Input};
+use log_parser::dedup::{DedupMode, SeenIds};
+use log_parser::distinct::{self, DistinctCounter};
+use log_parser::drift::{DriftDetector, ExpectedSchema};
+use log_parser::encoding::{Encoding, Transcoder};
+use log_parser::expr::Derived;
+use log_parser::extract::JsonBackend;
+use log_parser::fingerprint::FleetFingerprinter;
+use log_parser::follow::{self, Followed, FollowedFile};
+use log_parser::github::{self, IssueRef};
+use log_parser::group::{self, GroupKeys};
+use log_parser::http;
+use log_parser::index::{self, Index, ReadRanges};
+use log_parser::interrupt::CancelToken;
+use log_parser::map_backend::MapBackend;
+use log_parser::memory;
+#[cfg(feature = "mqtt")]
+use log_parser::mqtt::{self, MqttUrl};
+#[cfg(feature = "nats")]
+use log_parser::nats;
+use log_parser::normalize::{self, Normalization};
+#[cfg(feature = "otlp")]
+use log_parser::otlp;
+use log_parser::parquet::{self, Column};
+use log_parser::parser::{
+    self, ParseError, ParseOptions, ParseReport, ParsedRecord, SampleOptions, SampleTimestamps,
+    Strategy as ParseStrategy,
+};
+use log_parser::pattern::LinePattern;
+use log_parser::plan::{Analysis, Plan, Scan};
+use log_parser::platform::PrivateDir;
+use log_parser::preview::{self, FieldRole};
+use log_parser::profiling::{Bottleneck, PipelineMetrics, Profiler};
+use log_parser::progress::{Progress, ProgressHandle};
+use log_parser::projection::{self, Condition, Projection};
+use log_parser::quarantine::Quarantine;
+use log_parser::rate::{self, AnomalyDetector, WindowRate};
+use log_parser::redis::{self, RedisUrl, Reply};
+use log_parser::reingest::{self, Overrides};
+#[cfg(feature = "s3")]
+use log_parser::remote;
+use log_parser::replay;
+use log_parser::retention::{self, RetentionRule};
+use log_parser::reverse;
+use log_parser::run::{self, ParserOptions, RunError};
+use log_parser::sampling;
+use log_parser::scanner::{Delimiter, LineError, LineScanner, Multiline};
+use log_parser::seek;
+use log_parser::serve::{self, Credentials, Server};
+use log_parser::signing::{self, SigningKey};
+use log_parser::sink::{self, BatchPolicy, LineSink};
+use log_parser::slo::{Slo, SloTracker};
+use log_parser::split::{self, Split};
+use log_parser::spool::Spool;
+use log_parser::state::{self, SavedState, StateOptions};
+use log_parser::stats::Stats;
+use log_parser::subset::{self, Subset};
+use log_parser::summary;
+use log_parser::testing::Fixture;
+use log_parser::throttle::{RateLimit, ThrottledWriter, TokenBucket};
+use log_parser::timestamp::{self, TimeLocale};
+use log_parser::tls::{self, ClientTls, ServerTls, Stream};
+use log_parser::tui::Dashboard;
+use log_parser::tune;
+use log_parser::units::{self, Locale};
+use proptest::prelude::{any, prop, prop_assume, prop_oneof, BoxedStrategy, Just};
+use proptest::strategy::Strategy;
+use proptest::test_runner::{Config, TestCaseResult, TestRunner};
+use ratatui::backend::TestBackend;
+use serde_json::{json, Value};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::iter;
+use std::net::{TcpListener, TcpStream};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Number of random cases checked by every property.
+const CASES: u32 = 64;
+const MAX_THREADS: u8 = 8;
+/// Most lines of a random corpus.
+const MAX_LINES: usize = 300;
+
+#[cfg(feature = "memory-profile")]
+#[global_allocator]
+static ALLOCATOR: memory::CountingAllocator = memory::CountingAllocator;
+
+/// Check the property with `CASES` cases drawn by the strategy.
+fn check<S: Strategy>(strategy: S, property: impl Fn(S::Value) -> TestCaseResult) {
+    check_cases(CASES, strategy, property);
+}
+
+/// Check the property with given number of cases, a failing case is shrunk to the simplest one
+/// and printed with the error.
+fn check_cases<S: Strategy>(
+    cases: u32,
+    strategy: S,
+    property: impl Fn(S::Value) -> TestCaseResult,
+) {
+    let config = Config {
+        cases,
+        failure_persistence: None,
+        ..Config::default()
+    };
+    if let Err(err) = TestRunner::new(config).run(&strategy, property) {
+        panic!("{}", err);
+    }
+}
+
+/// Generated file in its own fixture directory, removed when dropped.
+struct Corpus {
+    _fixture: Fixture,
+    path: String,
+}
+
+impl Corpus {
+    fn generate(name: &str, options: &SampleOptions) -> Self {
+        let fixture = Fixture::new(name);
+        let path = fixture.generated("corpus.log", options);
+        Corpus {
+            _fixture: fixture,
+            path,
+        }
+    }
+
+    fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// Generator options of tests of a single corpus.
+fn sample(seed: u64) -> SampleOptions {
+    SampleOptions {
+        num_of_lines: 200,
+        num_of_log_types: 5,
+        max_msg_size: 100,
+        timestamps: Some(SampleTimestamps {
+            start: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            range: Duration::from_secs(3600),
+            jitter: Duration::from_secs(5),
+        }),
+        levels: vec![
+            ("info".to_string(), 80),
+            ("warn".to_string(), 15),
+            ("error".to_string(), 5),
+        ],
+        seed: Some(seed),
+        template: None,
+        target_size: None,
+    }
+}
+
+/// Random generator options, sizes are small so lines are often longer than parts of the file.
+fn sample_options() -> BoxedStrategy<SampleOptions> {
+    (
+        0..MAX_LINES as u32,
+        1..6u32,
+        1..200u32,
+        0..10u64,
+        any::<u64>(),
+    )
+        .prop_map(
+            |(num_of_lines, num_of_log_types, max_msg_size, jitter, seed)| {
+                let sample = sample(seed);
+                SampleOptions {
+                    num_of_lines,
+                    num_of_log_types,
+                    max_msg_size,
+                    timestamps: sample.timestamps.map(|timestamps| SampleTimestamps {
+                        jitter: Duration::from_secs(jitter),
+                        ..timestamps
+                    }),
+                    ..sample
+                }
+            },
+        )
+        .boxed()
+}
+
+/// Number of threads of a parser.
+fn threads() -> RangeInclusive<u8> {
+    1..=MAX_THREADS
+}
+
+fn strategies() -> impl Strategy<Value = ParseStrategy> {
+    prop::sample::select(vec![
+        ParseStrategy::Single,
+        ParseStrategy::Channel,
+        ParseStrategy::Dashmap,
+        ParseStrategy::Sharded,
+    ])
+}
+
+fn parse_options() -> ParseOptions {
+    ParseOptions {
+        string_fields: vec!["level".to_string()],
+        distinct_fields: vec!["id".to_string(), "level".to_string()],
+        timestamp_field: Some("timestamp".to_string()),
+        ..ParseOptions::default()
+    }
+}
+
+fn assert_same_report(expected: &ParseReport, actual: &ParseReport, backend: &str) {
+    assert_eq!(
+        expected, actual,
+        "{} report differs from single thread report",
+        backend
+    );
+}
+
+#[test]
+fn backends_give_the_same_report() {
+    let options = parse_options();
+    // tiny channel buffers make threads wait for the consumer
+    let channels = (0..4usize, 1..4usize, 1..5usize);
+    check(
+        (sample_options(), threads(), channels),
+        |(sample_options, num_of_threads, (capacity, batch_size, reducers))| {
+            let corpus = Corpus::generate("backends", &sample_options);
+            let channel_options = ParseOptions {
+                channel_capacity: Some(capacity),
+                channel_batch_size: Some(batch_size),
+                channel_reducers: Some(reducers),
+                ..options.clone()
+            };
+
+            let expected = parser::single_thread_parser(corpus.path(), &options).unwrap();
+            let channel = parser::multi_thread_parser_channel(
+                num_of_threads,
+                corpus.path(),
+                &channel_options,
+            )
+            .unwrap();
+            let dashmap =
+                parser::multi_thread_parser_dashmap(num_of_threads, corpus.path(), &options)
+                    .unwrap();
+            let sharded =
+                parser::multi_thread_parser_sharded(num_of_threads, corpus.path(), &options)
+                    .unwrap();
+
+            assert_same_report(&expected, &channel, "channel");
+            assert_same_report(&expected, &dashmap, "dashmap");
+            assert_same_report(&expected, &sharded, "sharded");
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn groups_span_their_first_and_last_timestamp() {
+    let options = parse_options();
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("span", &sample_options);
+
+            let mut expected: HashMap<String, (SystemTime, SystemTime)> = HashMap::new();
+            for line in fs::read_to_string(corpus.path()).unwrap().lines() {
+                let value: Value = serde_json::from_str(line).unwrap();
+                let time = timestamp::parse(&value["timestamp"]).unwrap();
+                let log_type = value["type"].as_str().unwrap().to_string();
+                let span = expected.entry(log_type).or_insert((time, time));
+                *span = (span.0.min(time), span.1.max(time));
+            }
+
+            let report =
+                parser::multi_thread_parser_dashmap(num_of_threads, corpus.path(), &options)
+                    .unwrap();
+            assert_eq!(report.log_register.len(), expected.len());
+            for (log_type, (first, last)) in expected {
+                let register = &report.log_register[&log_type];
+                assert_eq!(register.first_seen, Some(first));
+                assert_eq!(register.last_seen, Some(last));
+                assert_eq!(register.active_span(), last.duration_since(first).ok());
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn split_files_keep_lines_of_every_group_in_order() {
+    let options = parse_options();
+    check(
+        (sample_options(), 1..6usize),
+        |(sample_options, max_files)| {
+            let corpus = Corpus::generate("split", &sample_options);
+            let output = Fixture::new("split");
+            let dir = output.dir().join("split");
+
+            let mut expected: Vec<(String, Vec<String>)> = vec![];
+            for line in fs::read_to_string(corpus.path()).unwrap().lines() {
+                let value: Value = serde_json::from_str(line).unwrap();
+                let log_type = value["type"].as_str().unwrap().to_string();
+                match expected.iter_mut().find(|(group, _)| *group == log_type) {
+                    Some((_, lines)) => lines.push(line.to_string()),
+                    None => expected.push((log_type, vec![line.to_string()])),
+                }
+            }
+
+            let split = Split::new(vec!["type".to_string()], vec![], dir.clone(), max_files);
+            let summary = split::split_files(&[corpus.path()], &options, &split).unwrap();
+            let files: Vec<(String, Vec<String>)> = summary
+                .files
+                .iter()
+                .map(|file| {
+                    let lines = fs::read_to_string(&file.path).unwrap();
+                    (
+                        file.group.clone(),
+                        lines.lines().map(str::to_string).collect(),
+                    )
+                })
+                .collect();
+            let num_of_files = expected.len().min(max_files);
+            let overflow: Vec<String> = expected[num_of_files..]
+                .iter()
+                .flat_map(|(_, lines)| lines.clone())
+                .collect();
+            assert_eq!(files, expected[..num_of_files].to_vec());
+            assert_eq!(summary.overflow_lines, overflow.len() as u64);
+            if !overflow.is_empty() {
+                let mut lines: Vec<String> = fs::read_to_string(dir.join("_overflow.log"))
+                    .unwrap()
+                    .lines()
+                    .map(str::to_string)
+                    .collect();
+                let mut overflow = overflow;
+                lines.sort();
+                overflow.sort();
+                assert_eq!(lines, overflow);
+            }
+            Ok(())
+        },
+    );
+
+    assert_eq!(split::file_name(&["a/b".to_string()]), "a_b");
+    assert_eq!(split::file_name(&["..".to_string()]), "_.");
+    assert_eq!(
+        split::file_name(&["api".to_string(), "error".to_string()]),
+        "api_error"
+    );
+    assert_eq!(split::file_name(&["".to_string()]), "_");
+}
+
+#[test]
+fn index_reads_only_blocks_which_may_match() {
+    let options = parse_options();
+    let skipped_bytes = Cell::new(0);
+    let broken = prop::collection::vec(any::<prop::sample::Index>(), 0..3);
+    let condition = (0..6u8, prop::bool::weighted(0.2), any::<bool>());
+    let index_options = (any::<bool>(), 1..2000u64, condition);
+    let parser = (strategies(), threads());
+    check(
+        (sample_options(), broken, index_options, parser),
+        |(sample_options, broken, (one_field, block_bytes, condition), (strategy, threads))| {
+            let (log_type, negated, error_level) = condition;
+            let corpus = Corpus::generate("index", &sample_options);
+            // records grouped by type, like in files of a single service per hour
+            let content = fs::read_to_string(corpus.path()).unwrap();
+            let mut lines: Vec<(String, &str)> = content
+                .lines()
+                .map(|line| {
+                    let value: Value = serde_json::from_str(line).unwrap();
+                    (value["type"].as_str().unwrap().to_string(), line)
+                })
+                .collect();
+            lines.sort_by(|(type_a, _), (type_b, _)| type_a.cmp(type_b));
+            for idx in broken {
+                let idx = idx.index(lines.len() + 1);
+                lines.insert(idx, (String::new(), "{\"type\": broken"));
+            }
+            let sorted: String = lines
+                .iter()
+                .map(|(_, line)| format!("{}\n", line))
+                .collect();
+            fs::write(corpus.path(), &sorted).unwrap();
+
+            let fields = match one_field {
+                true => vec!["type".to_string()],
+                false => vec!["type".to_string(), "level".to_string()],
+            };
+            let index = Index::build(corpus.path(), fields, block_bytes, &options).unwrap();
+            let offsets: Vec<u64> = iter::once(0)
+                .chain(lines.iter().scan(0, |offset, (_, line)| {
+                    *offset += line.len() as u64 + 1;
+                    Some(*offset)
+                }))
+                .take(lines.len())
+                .collect();
+            let indexed: Vec<u64> = (0..index.records())
+                .map(|record| index.record_offset(record).unwrap())
+                .collect();
+            assert_eq!(indexed, offsets);
+            assert_eq!(index.record_offset(index.records()), None);
+
+            let sidecar = index::sidecar_path(Path::new(corpus.path()));
+            index.save(&sidecar).unwrap();
+            assert_eq!(Index::load(&sidecar).unwrap(), index);
+            fs::remove_file(&sidecar).unwrap();
+
+            let mut conditions = vec![Condition {
+                field: "type".to_string(),
+                value: log_type.to_string(),
+                negated,
+            }];
+            if error_level {
+                conditions.push(Condition {
+                    field: "level".to_string(),
+                    value: "error".to_string(),
+                    negated: false,
+                });
+            }
+            let filtered = ParseOptions {
+                filter: conditions.clone(),
+                ..options.clone()
+            };
+            let all = parser::single_thread_parser(corpus.path(), &filtered).unwrap();
+            let unfiltered = parser::single_thread_parser(corpus.path(), &options).unwrap();
+            // failed lines are reported whatever the filter
+            assert_eq!(all.failed_lines, unfiltered.failed_lines);
+            assert_eq!(all.lines, unfiltered.lines);
+            let condition = &conditions[0];
+            for log_type in all.log_register.keys() {
+                assert_eq!(*log_type == condition.value, !condition.negated);
+            }
+
+            let mut read_ranges = ReadRanges::default();
+            skipped_bytes
+                .set(skipped_bytes.get() + read_ranges.insert(corpus.path(), &index, &conditions));
+            let indexed = ParseOptions {
+                read_ranges: Some(Arc::new(read_ranges)),
+                ..filtered
+            };
+            let report = parser::parse_file(strategy, threads, corpus.path(), &indexed).unwrap();
+            assert_eq!(report.log_register, all.log_register);
+            assert_eq!(report.failed_lines, all.failed_lines);
+            assert_eq!(report.error_kinds, all.error_kinds);
+            let streamed = parser::stream(threads, corpus.path(), &indexed)
+                .unwrap()
+                .count() as u64;
+            let counted: u64 = all
+                .log_register
+                .values()
+                .map(|register| register.counter)
+                .sum();
+            assert_eq!(streamed, counted + all.failed_lines);
+
+            assert!(index.is_current(corpus.path()).unwrap());
+            OpenOptions::new()
+                .append(true)
+                .open(corpus.path())
+                .unwrap()
+                .write_all(b"{}\n")
+                .unwrap();
+            assert!(!index.is_current(corpus.path()).unwrap());
+            Ok(())
+        },
+    );
+    assert!(skipped_bytes.get() > 0, "no block was skipped");
+}
+
+#[test]
+fn ordered_projection_writes_records_in_file_order() {
+    let options = parse_options();
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("ordered", &sample_options);
+            let projection =
+                Projection::new(vec!["id".to_string()], vec![], projection::Format::Ndjson);
+            let expected: Vec<String> = fs::read_to_string(corpus.path())
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    let value: Value = serde_json::from_str(line).unwrap();
+                    json!({ "id": value["id"] }).to_string()
+                })
+                .collect();
+
+            let mut out = vec![];
+            let summary = projection::project(
+                num_of_threads,
+                corpus.path(),
+                &options,
+                &projection,
+                true,
+                &mut out,
+            )
+            .unwrap();
+            let lines: Vec<String> = String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .map(str::to_string)
+                .collect();
+            assert_eq!(summary.matched, expected.len() as u64);
+            assert_eq!(lines, expected);
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn chronological_projection_merges_files_by_time() {
+    let options = parse_options();
+    let files = prop::collection::vec(sample_options(), 1..4);
+    check((files, threads()), |(files, num_of_threads)| {
+        let corpora: Vec<Corpus> = files
+            .iter()
+            .enumerate()
+            .map(|(idx, sample_options)| {
+                let name = format!("chronological_{}", idx);
+                Corpus::generate(&name, sample_options)
+            })
+            .collect();
+        let inputs: Vec<&str> = corpora.iter().map(Corpus::path).collect();
+
+        let mut expected = vec![];
+        for input in &inputs {
+            for line in fs::read_to_string(input).unwrap().lines() {
+                let value: Value = serde_json::from_str(line).unwrap();
+                expected.push(timestamp::parse(&value["timestamp"]).unwrap());
+            }
+        }
+        expected.sort();
+
+        let projection = Projection::new(
+            vec!["timestamp".to_string(), "type".to_string()],
+            vec![],
+            projection::Format::Ndjson,
+        );
+        let mut out = vec![];
+        let summary = projection::project_chronological(
+            num_of_threads,
+            &inputs,
+            &options,
+            &projection,
+            &mut out,
+        )
+        .unwrap();
+        let times: Vec<SystemTime> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let value: Value = serde_json::from_str(line).unwrap();
+                timestamp::parse(&value["timestamp"]).unwrap()
+            })
+            .collect();
+        assert_eq!(summary.matched, expected.len() as u64);
+        assert_eq!(times, expected);
+        Ok(())
+    });
+
+    let without_timestamp = ParseOptions::default();
+    let projection = Projection::new(vec![], vec![], projection::Format::Ndjson);
+    assert!(projection::project_chronological(
+        1,
+        &[] as &[&str],
+        &without_timestamp,
+        &projection,
+        &mut vec![]
+    )
+    .is_err());
+}
+
+#[test]
+fn chronological_projection_sorts_late_records_within_the_window() {
+    let fixture = Fixture::new("chronological-window");
+    let lines = 2 * projection::REORDER_WINDOW;
+    // even seconds in one file and odd in the other, every 100th record written 50 records late
+    let times = |first: usize| {
+        let mut times: Vec<usize> = (0..lines).map(|idx| first + 2 * idx).collect();
+        for idx in (0..lines - 50).step_by(100) {
+            times[idx..=idx + 50].rotate_left(1);
+        }
+        times
+    };
+    let inputs: Vec<String> = [0, 1]
+        .iter()
+        .map(|first| {
+            let lines = times(*first)
+                .into_iter()
+                .map(|time| format!(r#"{{"type":"a","ts":{}}}"#, 1_700_000_000 + time));
+            fixture.lines(&format!("{}.log", first), lines)
+        })
+        .collect();
+    let options = ParseOptions {
+        timestamp_field: Some("ts".to_string()),
+        ..ParseOptions::default()
+    };
+    let projection = Projection::new(vec!["ts".to_string()], vec![], projection::Format::Ndjson);
+
+    let mut out = vec![];
+    let summary =
+        projection::project_chronological(4, &inputs, &options, &projection, &mut out).unwrap();
+    let times: Vec<u64> = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<Value>(line).unwrap()["ts"]
+                .as_u64()
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(summary.matched, 2 * lines as u64);
+    let expected: Vec<u64> = (0..2 * lines as u64)
+        .map(|time| 1_700_000_000 + time)
+        .collect();
+    assert_eq!(times, expected);
+}
+
+#[test]
+fn sample_shards_split_the_target_size() {
+    let shards = (1..6u32, 0..20_000u64);
+    check(
+        (sample_options(), shards),
+        |(sample_options, (num_of_shards, target_size))| {
+            let options = SampleOptions {
+                target_size: Some(target_size),
+                ..sample_options
+            };
+            let fixture = Fixture::new("shards");
+            let output = fixture.path("shards.log");
+
+            let registers = parser::prepare_sample_shards(&options, &output, num_of_shards);
+            let shards: Vec<String> = (0..num_of_shards)
+                .map(|idx| match num_of_shards {
+                    1 => output.clone(),
+                    _ => parser::shard_path(&output, idx, num_of_shards),
+                })
+                .collect();
+            let mut total = ParseReport::default();
+            for (idx, shard) in shards.iter().enumerate() {
+                let size = fs::metadata(shard).unwrap().len();
+                let share = target_size / num_of_shards as u64
+                    + u64::from((idx as u64) < target_size % num_of_shards as u64);
+                let last_line = fs::read_to_string(shard)
+                    .unwrap()
+                    .lines()
+                    .last()
+                    .map_or(0, |line| line.len() as u64 + 1);
+                // shards stop with the line reaching their share
+                assert!(size >= share && size - last_line < share.max(1));
+                total += parser::single_thread_parser(shard, &ParseOptions::default()).unwrap();
+            }
+            let counter: u64 = registers.values().map(|register| register.counter).sum();
+            let bytes: u64 = registers
+                .values()
+                .map(|register| register.num_of_bytes)
+                .sum();
+            assert_eq!(counter, total.lines);
+            assert_eq!(
+                bytes,
+                total
+                    .log_register
+                    .values()
+                    .map(|register| register.num_of_bytes)
+                    .sum::<u64>()
+            );
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn plan_shares_passes_of_compatible_analyses() {
+    let plan = Plan::new(&[
+        Analysis::scan_after_report("records to file://records.json", Scan::Full),
+        Analysis::scan("report per log type", Scan::Full),
+        Analysis::report("alerts"),
+        Analysis::scan("thread tuning", Scan::Trial),
+        Analysis::scan("duplicates", Scan::Full),
+        Analysis::scan_after_report("records to sqlite://results.db", Scan::Full),
+        Analysis::scan("subset of every input", Scan::Subset),
+    ]);
+
+    let passes: Vec<(Scan, bool, Vec<&str>)> = plan
+        .passes
+        .iter()
+        .map(|pass| {
+            let analyses = pass.analyses.iter().map(String::as_str).collect();
+            (pass.scan, pass.after_report, analyses)
+        })
+        .collect();
+    assert_eq!(
+        passes,
+        vec![
+            (Scan::Subset, false, vec!["subset of every input"]),
+            (Scan::Trial, false, vec!["thread tuning"]),
+            (Scan::Full, false, vec!["report per log type", "duplicates"]),
+            (
+                Scan::Full,
+                true,
+                vec![
+                    "records to file://records.json",
+                    "records to sqlite://results.db"
+                ]
+            ),
+        ]
+    );
+    assert_eq!(plan.full_scans(), 2);
+    assert_eq!(plan.from_report, vec!["alerts"]);
+    assert!(plan
+        .to_string()
+        .starts_with("4 passes, 2 full scans\npass 1: subset scan: subset of every input\n"));
+
+    let single = Plan::new(&[Analysis::scan("report per log type", Scan::Full)]);
+    assert_eq!(single.passes.len(), 1);
+}
+
+#[test]
+fn field_profiles_bound_the_exact_counts() {
+    let fields = ["type", "level", "id"].map(String::from).to_vec();
+    let options = ParseOptions {
+        string_fields: fields.clone(),
+        ..ParseOptions::default()
+    };
+    check(
+        (sample_options(), threads(), 1..20usize),
+        |(sample_options, num_of_threads, num_of_top)| {
+            let corpus = Corpus::generate("profile", &sample_options);
+
+            let mut exact: Vec<HashMap<String, u64>> = vec![HashMap::new(); fields.len()];
+            for line in fs::read_to_string(corpus.path()).unwrap().lines() {
+                let value: Value = serde_json::from_str(line).unwrap();
+                for (field, counts) in fields.iter().zip(&mut exact) {
+                    *counts
+                        .entry(value[field].as_str().unwrap().to_string())
+                        .or_default() += 1;
+                }
+            }
+
+            let (report, profiler) = aggregator::parse_file(
+                num_of_threads,
+                corpus.path(),
+                &options,
+                &FieldProfiler::new(fields.clone(), num_of_top),
+            )
+            .unwrap();
+            for (profile, counts) in profiler.profiles().iter().zip(&exact) {
+                assert_eq!(profile.records, report.lines);
+                assert_eq!(profile.missing, 0);
+                assert_eq!(profile.distinct, counts.len() as u64);
+                assert!(profile.top.len() <= num_of_top);
+                for hitter in &profile.top {
+                    let count = counts[&hitter.value];
+                    assert!(
+                        hitter.count - hitter.error <= count && count <= hitter.count,
+                        "{} of {}",
+                        hitter.value,
+                        profile.field
+                    );
+                }
+                // few values fit the counters, so their counts are exact
+                if counts.len() <= num_of_top {
+                    let mut expected: Vec<(&String, &u64)> = counts.iter().collect();
+                    expected
+                        .sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+                    let top: Vec<(&String, &u64)> = profile
+                        .top
+                        .iter()
+                        .map(|hitter| (&hitter.value, &hitter.count))
+                        .collect();
+                    assert_eq!(top, expected, "{}", profile.field);
+                }
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn distinct_hashes_are_stable_and_estimate_counts() {
+    // hashes are saved with reports and states, so they can't change between builds
+    assert_eq!(distinct::fnv1a(iter::empty()), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(distinct::fnv1a(*b"a"), 0xaf63_dc4c_8601_ec8c);
+    assert_eq!(distinct::hash_value(""), 0xf52a_15e9_a9b5_e89b);
+    assert_eq!(distinct::hash_value("user-1"), 0xc499_b5f8_a721_df58);
+
+    check(2_000..50_000u64, |num_of_values| {
+        let mut counter = DistinctCounter::new();
+        for value in 0..num_of_values {
+            counter.add(distinct::hash_value(&format!("user-{}", value)));
+        }
+        assert!(!counter.is_exact());
+        let error = (counter.count() as f64 - num_of_values as f64).abs() / num_of_values as f64;
+        assert!(error < 0.05, "{} of {}", counter.count(), num_of_values);
+        Ok(())
+    });
+}
+
+#[test]
+fn configs_are_read_with_the_full_toml_grammar() {
+    let fixture = Fixture::new("config");
+    let path = fixture.bytes(
+        "parse.toml",
+        br#"# literal, multi-line and escaped strings, arrays over lines and an inline table
+input = 'C:\logs\app.log'
+group_by = [
+    "type",   # trailing comments and commas
+    "level",
+]
+error_when = ["""status >= 500"""]
+pattern = "\u0041{level} {message}"
+num_of_threads = 0x4
+options = { timestamp_field = "ts", size_histogram = true }
+"#,
+    );
+    let config = ParseConfig::load(&path).unwrap();
+    assert_eq!(config.input.as_deref(), Some(r"C:\logs\app.log"));
+    assert_eq!(config.group_by, ["type", "level"]);
+    assert_eq!(config.error_when, ["status >= 500"]);
+    assert_eq!(config.pattern.as_deref(), Some("A{level} {message}"));
+    assert_eq!(config.num_of_threads, Some(4));
+    assert_eq!(config.options.timestamp_field.as_deref(), Some("ts"));
+    assert!(config.options.size_histogram);
+
+    let invalid = fixture.bytes(
+        "invalid.toml",
+        b"input = \"app.log\"\ninput = \"other.log\"\n",
+    );
+    let err = ParseConfig::load(&invalid).unwrap_err();
+    assert!(
+        err.starts_with(&format!("Invalid config {}", invalid)),
+        "{}",
+        err
+    );
+    assert!(err.contains("line 2"), "{}", err);
+}
+
+#[test]
+fn fields_of_config_options_are_reported_like_flags() {
+    let fixture = Fixture::new("config_fields");
+    let input = fixture.lines(
+        "input.log",
+        [
+            r#"{"type":"a","bytes":10,"user":"x"}"#,
+            r#"{"type":"a","bytes":5,"user":"y"}"#,
+            r#"{"type":"b","bytes":1,"user":"x"}"#,
+        ],
+    );
+    let config = fixture.bytes(
+        "parse.toml",
+        b"[options]\nnumeric_fields = [\"bytes\"]\ndistinct_fields = [\"user\"]\n",
+    );
+    let run = |args: &[&str]| {
+        let output = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+            .args(["parse", "-i", &input])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let configured = run(&["--config", &config]);
+    assert!(configured.contains("sum(bytes)"), "{}", configured);
+    assert!(configured.contains("distinct(user)"), "{}", configured);
+    assert_eq!(
+        configured,
+        run(&["--sum-field", "bytes", "--count-distinct", "user"])
+    );
+}
+
+#[test]
+fn map_backends_give_the_same_report() {
+    check(
+        (sample_options(), threads(), any::<bool>()),
+        |(sample_options, num_of_threads, by_id)| {
+            let corpus = Corpus::generate("map_backends", &sample_options);
+            // many keys with ids, a few with log types
+            let options = ParseOptions {
+                group_by: match by_id {
+                    true => vec!["id".to_string()],
+                    false => vec![],
+                },
+                ..parse_options()
+            };
+
+            let expected = parser::single_thread_parser(corpus.path(), &options).unwrap();
+            for backend in MapBackend::ALL.iter().copied() {
+                let report = parser::multi_thread_parser_merged(
+                    num_of_threads,
+                    corpus.path(),
+                    &options,
+                    backend,
+                )
+                .unwrap();
+                assert_same_report(&expected, &report, backend.name());
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn report_matches_generated_logs() {
+    let options = parse_options();
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("generated", &sample_options);
+
+            let generated = parser::prepare_sample_file(&sample_options, corpus.path());
+            let report =
+                parser::multi_thread_parser_dashmap(num_of_threads, corpus.path(), &options)
+                    .unwrap();
+
+            assert_eq!(report.lines, sample_options.num_of_lines as u64);
+            assert_eq!(report.failed_lines, 0);
+            assert_eq!(report.log_register.len(), generated.len());
+            for (log_type, expected) in &generated {
+                let actual = &report.log_register[log_type];
+                assert_eq!(actual.counter, expected.counter);
+                assert_eq!(actual.num_of_bytes, expected.num_of_bytes);
+                assert_eq!(actual.errors, expected.errors);
+
+                // every line falls into one power of two range, and the sparkline has a bar per
+                // range between the smallest and the largest line
+                let histogram = actual.line_size.histogram();
+                let in_ranges: u64 = histogram.iter().map(|(_, count)| count).sum();
+                assert_eq!(in_ranges, expected.counter);
+                let (first, last) = (histogram[0].0.max(1), histogram[histogram.len() - 1].0);
+                let ranges = (last.trailing_zeros() - first.trailing_zeros() + 1) as usize;
+                assert_eq!(actual.line_size.sparkline().chars().count(), ranges);
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn subsets_are_whole_lines_of_the_input() {
+    let subsets = (any::<prop::sample::Index>(), 0.01..1.0, any::<u64>());
+    check(
+        (sample_options(), subsets),
+        |(sample_options, (num_of_lines, fraction, seed))| {
+            let corpus = Corpus::generate("subset", &sample_options);
+            let content = fs::read_to_string(corpus.path()).unwrap();
+            let lines: Vec<&str> = content.split_inclusive('\n').collect();
+            let num_of_lines = num_of_lines.index(lines.len() + 2) as u64;
+
+            let read = |subset| {
+                fs::read_to_string(subset::extract(corpus.path(), subset, seed).unwrap().path())
+                    .unwrap()
+            };
+
+            let head = lines
+                .iter()
+                .take(num_of_lines as usize)
+                .copied()
+                .collect::<String>();
+            assert_eq!(read(Subset::Head(num_of_lines)), head);
+            let skipped = lines.len().saturating_sub(num_of_lines as usize);
+            let tail = lines[skipped..].concat();
+            assert_eq!(read(Subset::Tail(num_of_lines)), tail);
+            assert_eq!(read(Subset::Sample(1.0)), content);
+
+            // sampled blocks keep lines in order and never cut them
+            let sample = read(Subset::Sample(fraction));
+            let mut remaining = lines.iter();
+            for line in sample.split_inclusive('\n') {
+                assert!(
+                    remaining.any(|input_line| *input_line == line),
+                    "sampled line [{}] isn't a line of the input in order",
+                    line
+                );
+            }
+            Ok(())
+        },
+    );
+}
+
+/// Lines per value of the first string field.
+#[derive(Clone, Default)]
+struct LinesPerValue(BTreeMap<Option<String>, u64>);
+
+impl Aggregator for LinesPerValue {
+    fn observe(&mut self, record: &ParsedRecord) {
+        *self.0.entry(record.string_values[0].clone()).or_default() += 1;
+    }
+
+    fn merge(&mut self, other: Self) {
+        for (value, lines) in other.0 {
+            *self.0.entry(value).or_default() += lines;
+        }
+    }
+
+    fn report(&self) -> Value {
+        json!(self.0.iter().collect::<Vec<_>>())
+    }
+}
+
+#[test]
+fn custom_aggregator_sees_every_record_once() {
+    let options = parse_options();
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("aggregator", &sample_options);
+
+            let mut expected = LinesPerValue::default();
+            parser::stream(1, corpus.path(), &options)
+                .unwrap()
+                .flatten()
+                .for_each(|record| expected.observe(&record));
+            let (report, aggregated) = aggregator::parse_file(
+                num_of_threads,
+                corpus.path(),
+                &options,
+                &LinesPerValue::default(),
+            )
+            .unwrap();
+
+            assert_eq!(aggregated.report(), expected.report());
+            let sharded =
+                parser::multi_thread_parser_sharded(num_of_threads, corpus.path(), &options)
+                    .unwrap();
+            assert_same_report(&sharded, &report, "aggregated");
+            Ok(())
+        },
+    );
+}
+
+/// Lines with one of the levels, borrowed from the caller.
+#[derive(Clone)]
+struct LinesOfLevels<'a> {
+    levels: &'a BTreeSet<String>,
+    lines: u64,
+}
+
+impl Aggregator for LinesOfLevels<'_> {
+    fn observe(&mut self, record: &ParsedRecord) {
+        if record
+            .level
+            .as_ref()
+            .is_some_and(|level| self.levels.contains(level))
+        {
+            self.lines += 1;
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.lines += other.lines;
+    }
+
+    fn report(&self) -> Value {
+        json!(self.lines)
+    }
+}
+
+#[test]
+fn aggregators_borrow_from_the_caller() {
+    let options = parse_options();
+    let levels: BTreeSet<String> = ["warn", "error"]
+        .iter()
+        .map(|level| level.to_string())
+        .collect();
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("borrowing-aggregator", &sample_options);
+
+            let expected = parser::stream(1, corpus.path(), &options)
+                .unwrap()
+                .flatten()
+                .filter(|record| {
+                    record
+                        .level
+                        .as_ref()
+                        .is_some_and(|level| levels.contains(level))
+                })
+                .count();
+            let aggregator = LinesOfLevels {
+                levels: &levels,
+                lines: 0,
+            };
+            let (_, aggregated) =
+                aggregator::parse_file(num_of_threads, corpus.path(), &options, &aggregator)
+                    .unwrap();
+            assert_eq!(aggregated.lines, expected as u64);
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn seeded_samples_are_reproducible_with_weighted_levels_and_timestamps() {
+    check(
+        (sample_options(), 0..3u32),
+        |(sample_options, warn_weight)| {
+            let sample_options = SampleOptions {
+                levels: vec![("info".to_string(), 1), ("warn".to_string(), warn_weight)],
+                ..sample_options
+            };
+            let first = Corpus::generate("seeded", &sample_options);
+            let second = Corpus::generate("seeded", &sample_options);
+            assert_eq!(
+                fs::read(first.path()).unwrap(),
+                fs::read(second.path()).unwrap()
+            );
+
+            let options = ParseOptions {
+                string_fields: vec!["level".to_string()],
+                timestamp_field: Some("timestamp".to_string()),
+                ..ParseOptions::default()
+            };
+            let timestamps = sample_options.timestamps.clone().unwrap();
+            let earliest = timestamps.start - timestamps.jitter;
+            let latest = timestamps.start + timestamps.range + timestamps.jitter;
+            let mut previous = UNIX_EPOCH;
+            for record in parser::stream(1, first.path(), &options).unwrap() {
+                let record = record.unwrap();
+                let level = record.string_values[0].as_deref().unwrap();
+                assert!(level == "info" || (level == "warn" && warn_weight > 0));
+                let timestamp = record.timestamp.unwrap();
+                assert!((earliest..=latest).contains(&timestamp));
+                if timestamps.jitter.is_zero() {
+                    assert!(timestamp >= previous);
+                }
+                previous = timestamp;
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn polled_progress_ends_with_every_line_consumed() {
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("progress", &sample_options);
+            let handle = ProgressHandle::for_file(corpus.path()).expect("Can't read corpus");
+            let options = ParseOptions {
+                progress: Some(handle.progress()),
+                ..parse_options()
+            };
+
+            let path = corpus.path().to_string();
+            let parsing = thread::spawn(move || {
+                parser::multi_thread_parser_sharded(num_of_threads, &path, &options).unwrap()
+            });
+            let mut previous = handle.poll();
+            while !previous.finished {
+                let snapshot = handle.poll();
+                assert!(snapshot.bytes >= previous.bytes);
+                assert!(snapshot.fraction <= 1.0);
+                previous = snapshot;
+                thread::sleep(Duration::from_millis(1));
+            }
+            let report = parsing.join().expect("Parser panicked");
+
+            let snapshot = handle.poll();
+            assert_eq!(snapshot.fraction, 1.0);
+            assert_eq!(snapshot.bytes, snapshot.total_bytes);
+            assert_eq!(snapshot.lines, report.lines);
+            assert_eq!(snapshot.eta, Some(Duration::ZERO));
+            let threads = handle.progress().threads();
+            let thread_lines: u64 = threads.iter().map(|thread| thread.lines).sum();
+            assert_eq!(thread_lines, report.lines);
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn profiled_threads_time_every_line() {
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("profile", &sample_options);
+            for strategy in [ParseStrategy::Single, ParseStrategy::Sharded] {
+                let profiler = Arc::new(Profiler::new());
+                let options = ParseOptions {
+                    profiler: Some(profiler.clone()),
+                    ..parse_options()
+                };
+                let report =
+                    parser::parse_file(strategy, num_of_threads, corpus.path(), &options).unwrap();
+
+                let threads = profiler.threads();
+                let lines: u64 = threads.iter().map(|thread| thread.lines).sum();
+                assert_eq!(lines, report.lines, "{:?}", strategy);
+                let folded = profiler.folded("run");
+                assert!(
+                    folded.lines().all(|line| line.starts_with("run;part ")),
+                    "{:?}",
+                    strategy
+                );
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn profiled_channel_drains_its_queue() {
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("pipeline", &sample_options);
+            let profiler = Arc::new(Profiler::new());
+            let options = ParseOptions {
+                profiler: Some(profiler.clone()),
+                channel_capacity: Some(1),
+                channel_batch_size: Some(1),
+                channel_reducers: Some(2),
+                ..parse_options()
+            };
+            let report = parser::parse_file(
+                ParseStrategy::Channel,
+                num_of_threads,
+                corpus.path(),
+                &options,
+            )
+            .unwrap();
+
+            let metrics = PipelineMetrics::new(
+                iter::once(profiler.as_ref()),
+                Duration::from_secs(1),
+                Duration::default(),
+            );
+            assert_eq!(metrics.threads.lines, report.lines);
+            let queue = profiler.queue().stats();
+            assert_eq!(queue.capacity, 2);
+            assert!(queue.max_depth <= queue.sends as usize);
+            assert_eq!(profiler.reducers().len(), 2);
+            // reducers of an empty input may never be timed
+            assert!(report.lines == 0 || metrics.reducer_utilization().is_some());
+            // the run is not dominated by writing sinks
+            assert_ne!(metrics.bottleneck(), Some(Bottleneck::Sink));
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn memory_profile_attributes_allocations_of_parsing_threads() {
+    let corpus = Corpus::generate("memory", &sample(0));
+    let options = ParseOptions {
+        group_by: vec!["type".to_string(), "level".to_string()],
+        ..parse_options()
+    };
+    let before = memory::snapshot();
+    let report = parser::multi_thread_parser_sharded(4, corpus.path(), &options).unwrap();
+
+    let after = memory::snapshot();
+    if !cfg!(feature = "memory-profile") {
+        assert_eq!(after, None);
+        return;
+    }
+    let (before, after) = (before.unwrap(), after.unwrap());
+    assert!(after.peak_bytes >= after.live_bytes);
+    let allocations =
+        |snapshot: &memory::MemorySnapshot, module: &str| snapshot.modules[module].allocations;
+    // tests run in parallel, so only counters of this run's modules are known to grow
+    if report.lines > 0 {
+        assert!(allocations(&after, "aggregate") > allocations(&before, "aggregate"));
+    }
+    assert_eq!(
+        after.modules.keys().collect::<Vec<_>>(),
+        ["aggregate", "other", "parse", "read", "sink"]
+    );
+}
+
+#[test]
+fn cancelled_handles_return_partial_reports() {
+    let options = parse_options();
+    check(
+        (sample_options(), threads(), strategies()),
+        |(sample_options, num_of_threads, strategy)| {
+            let corpus = Corpus::generate("handle", &sample_options);
+            let expected =
+                parser::parse_file(strategy, num_of_threads, corpus.path(), &options).unwrap();
+            let spawn = |options: &ParseOptions| {
+                ParserOptions::builder()
+                    .input(corpus.path())
+                    .threads(num_of_threads)
+                    .strategy(strategy)
+                    .parse_options(options.clone())
+                    .spawn()
+                    .unwrap()
+            };
+
+            let report = spawn(&options).join().unwrap();
+            assert_same_report(&expected, &report, "spawned");
+
+            let cancel = CancelToken::new();
+            cancel.cancel();
+            let cancelled_options = ParseOptions {
+                cancel: Some(cancel),
+                ..options.clone()
+            };
+            let report = spawn(&cancelled_options).join().unwrap();
+            assert_eq!(report.lines, 0);
+            // an empty input may have nothing left unread
+            assert!(!report.is_complete() || expected.lines == 0);
+
+            let handle = spawn(&options);
+            handle.cancel();
+            let report = handle.join().unwrap();
+            assert!(report.lines <= expected.lines);
+            if report.is_complete() {
+                assert_same_report(&expected, &report, "cancelled too late");
+            }
+            Ok(())
+        },
+    );
+
+    let missing = ParserOptions::builder().input("missing.log").spawn();
+    assert!(matches!(missing, Err(RunError::Open { .. })));
+}
+
+#[test]
+fn scan_and_simd_backends_give_the_same_report() {
+    // tricky lines: escapes, brackets in strings, wrong types and invalid JSON
+    let lines = [
+        r#"{"type":"a\"b","level":null,"host":"x}"}"#,
+        r#"{"ty\u0070e":"a","nested":{"type":"b","list":[1,{"a":"]"}]},"host":["h",1]}"#,
+        r#"{ "level" : "ERROR" , "type" : "a" , "host" : 12.50 }"#,
+        r#"{"type":1}"#,
+        r#"{"type":"a","level":3}"#,
+        r#"{"level":"info"}"#,
+        r#"{"type":"a","type":"b"}"#,
+        r#"{"type":"a"} trailing"#,
+        r#"{"type":"a","host":tru}"#,
+        r#"{"type":"a","x":[1,2}"#,
+        r#"["type","a"]"#,
+        r#"{"type":"a","x":"unterminated}"#,
+        r#"{"type":"a","host":0.1e-2,"n":18446744073709551616}"#,
+        r#"{"type":"a","host":1e400}"#,
+        r#"{"type":"a","host":"😀"}"#,
+    ];
+    let tricky = prop::collection::vec(prop::sample::select(lines.to_vec()), 0..20);
+    check(
+        (sample_options(), tricky, 0..3u8),
+        |(sample_options, tricky, group_by)| {
+            let corpus = Corpus::generate("scan", &sample_options);
+            let mut file = OpenOptions::new().append(true).open(corpus.path()).unwrap();
+            for line in tricky {
+                writeln!(file, "{}", line).unwrap();
+            }
+            let group_by = match group_by {
+                0 => vec![],
+                1 => vec!["level".to_string(), "type".to_string()],
+                _ => vec!["host".to_string(), "level".to_string()],
+            };
+            let options = |json_backend| ParseOptions {
+                group_by: group_by.clone(),
+                json_backend,
+                ..ParseOptions::default()
+            };
+
+            let expected =
+                parser::single_thread_parser(corpus.path(), &options(JsonBackend::Serde)).unwrap();
+            let scanned =
+                parser::single_thread_parser(corpus.path(), &options(JsonBackend::Scan)).unwrap();
+
+            assert_same_report(&expected, &scanned, "scan");
+
+            #[cfg(feature = "simd")]
+            {
+                let simd = parser::single_thread_parser(corpus.path(), &options(JsonBackend::Simd))
+                    .unwrap();
+                assert_same_report(&expected, &simd, "simd");
+                // whole values are deserialized for more fields
+                let options = |json_backend| ParseOptions {
+                    group_by: group_by.clone(),
+                    numeric_fields: vec!["host".to_string()],
+                    json_backend,
+                    ..parse_options()
+                };
+                let expected =
+                    parser::single_thread_parser(corpus.path(), &options(JsonBackend::Serde))
+                        .unwrap();
+                let simd = parser::single_thread_parser(corpus.path(), &options(JsonBackend::Simd))
+                    .unwrap();
+                assert_same_report(&expected, &simd, "simd");
+            }
+            Ok(())
+        },
+    );
+    #[cfg(feature = "simd")]
+    assert_eq!("simd".parse(), Ok(JsonBackend::Simd));
+    #[cfg(not(feature = "simd"))]
+    assert!("simd"
+        .parse::<JsonBackend>()
+        .unwrap_err()
+        .contains("--features simd"));
+}
+
+#[test]
+fn stream_yields_every_line_once() {
+    let options = parse_options();
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("stream", &sample_options);
+
+            let expected = parser::single_thread_parser(corpus.path(), &options).unwrap();
+            let mut counters: HashMap<String, u64> = HashMap::new();
+            let mut offsets = vec![];
+            for record in parser::stream(num_of_threads, corpus.path(), &options).unwrap() {
+                let record = record.expect("Generated lines are valid");
+                *counters.entry(record.log_type).or_default() += 1;
+                offsets.push(record.offset);
+            }
+
+            offsets.sort_unstable();
+            assert!(
+                offsets.windows(2).all(|pair| pair[0] != pair[1]),
+                "line streamed twice"
+            );
+            assert_eq!(offsets.len() as u64, sample_options.num_of_lines as u64);
+            for (log_type, register) in &expected.log_register {
+                assert_eq!(counters[log_type], register.counter);
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn stream_drops_redelivered_records() {
+    let options = ParseOptions {
+        dedup_field: Some("id".to_string()),
+        ..parse_options()
+    };
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("dedup", &sample_options);
+            // the whole corpus is delivered again
+            let content = fs::read(corpus.path()).unwrap();
+            let mut file = OpenOptions::new().append(true).open(corpus.path()).unwrap();
+            file.write_all(&content).unwrap();
+
+            let records = parser::stream(num_of_threads, corpus.path(), &options)
+                .unwrap()
+                .count();
+
+            assert_eq!(records, sample_options.num_of_lines as usize);
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn missing_inputs_are_errors_of_every_strategy() {
+    let fixture = Fixture::new("missing");
+    let missing = PathBuf::from(fixture.path("missing.log"));
+    let options = parse_options();
+    for strategy in [
+        ParseStrategy::Single,
+        ParseStrategy::Channel,
+        ParseStrategy::Dashmap,
+        ParseStrategy::Sharded,
+    ] {
+        let err = parser::parse_file(strategy, 2, &missing, &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound, "{:?}", strategy);
+    }
+    let merged = parser::multi_thread_parser_merged(2, &missing, &options, MapBackend::BTreeMap);
+    assert_eq!(merged.unwrap_err().kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn subcommands_generate_parse_and_bench_the_same_file() {
+    let fixture = Fixture::new("cli");
+    let input = fixture.path("generated.log");
+    let run = |args: &[&str]| {
+        let output = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?} failed", args);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    run(&[
+        "generate",
+        "-o",
+        &input,
+        "--num-of-lines",
+        "300",
+        "--num-of-log-types",
+        "3",
+        "--seed",
+        "7",
+    ]);
+    let report = parser::single_thread_parser(&input, &ParseOptions::default()).unwrap();
+    assert_eq!(report.lines, 300);
+    assert_eq!(report.log_register.len(), 3);
+
+    let parsed = run(&["parse", "-i", &input, "-t", "2", "--color", "never"]);
+    for (log_type, register) in &report.log_register {
+        let row = parsed
+            .lines()
+            .find(|line| line.split_whitespace().next() == Some(log_type))
+            .unwrap();
+        assert_eq!(
+            row.split_whitespace().nth(1),
+            Some(&*register.counter.to_string())
+        );
+    }
+    assert!(parsed.contains("total (3 log_types)    300"), "{}", parsed);
+
+    // every strategy with every backend finds the same log types
+    let bench = run(&["bench", "-i", &input, "-t", "2"]);
+    let rows: Vec<Vec<&str>> = bench
+        .lines()
+        .skip(1)
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert!(rows.iter().any(|row| row[0] == "single_thread/serde"));
+    assert!(rows.iter().any(|row| row[0] == "multi_thread_sharded/scan"));
+    #[cfg(feature = "simd")]
+    assert!(rows.iter().any(|row| row[0] == "multi_thread_sharded/simd"));
+    assert!(rows.iter().all(|row| row[2] == "3"), "{}", bench);
+}
+
+#[test]
+fn report_levels_print_totals_rows_or_histograms() {
+    let fixture = Fixture::new("report-levels");
+    let input = fixture.lines(
+        "input.log",
+        [
+            r#"{"type":"a"}"#,
+            r#"{"type":"b","level":"error"}"#,
+            r#"{"type":"a","message":"a message of the second log of type a"}"#,
+        ],
+    );
+    let run = |report: &str| {
+        let output = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+            .args([
+                "parse", "-i", &input, "--color", "never", "--report", report,
+            ])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "--report {} failed", report);
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let first_words = |output: &str| -> Vec<String> {
+        output
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .map(str::to_string)
+            .collect()
+    };
+
+    let summary = run("summary");
+    assert_eq!(first_words(&summary), ["log_type", "total"]);
+    assert!(
+        summary.contains("total (2 log_types)      3"),
+        "{}",
+        summary
+    );
+    let standard = run("standard");
+    assert_eq!(first_words(&standard), ["log_type", "a", "b", "total"]);
+
+    // the histogram of every log type counts its lines
+    let full = run("full");
+    assert!(full.starts_with(&standard), "{}", full);
+    let histogram = full
+        .lines()
+        .skip_while(|line| !line.contains("line size histogram"))
+        .skip(1)
+        .take_while(|line| !line.is_empty());
+    let counts: Vec<(String, u64)> = histogram
+        .map(|row| {
+            let (log_type, ranges) = row.split_once(' ').unwrap();
+            let ranges = ranges.trim();
+            let count = ranges
+                .split(',')
+                .map(|range| range.rsplit(": ").next().unwrap().parse::<u64>().unwrap())
+                .sum();
+            (log_type.to_string(), count)
+        })
+        .collect();
+    assert_eq!(counts, [("a".to_string(), 2), ("b".to_string(), 1)]);
+
+    let invalid = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args(["parse", "-i", &input, "--report", "verbose"])
+        .output()
+        .unwrap();
+    assert!(!invalid.status.success());
+}
+
+/// Topics of librdkafka's mock cluster are consumed in report windows, and the offsets of reported
+/// windows are committed for the group.
+#[cfg(feature = "kafka")]
+#[test]
+fn kafka_topics_are_reported_per_window() {
+    use rdkafka::consumer::{BaseConsumer, Consumer};
+    use rdkafka::mocking::MockCluster;
+    use rdkafka::producer::{BaseProducer, BaseRecord, Producer};
+    use rdkafka::{ClientConfig, Offset, TopicPartitionList};
+
+    let cluster = MockCluster::new(1).unwrap();
+    cluster.create_topic("app-logs", 1, 1).unwrap();
+    let brokers = cluster.bootstrap_servers();
+    let producer: BaseProducer = ClientConfig::new()
+        .set("bootstrap.servers", &brokers)
+        .create()
+        .unwrap();
+    let mut produced = 0;
+    let mut produce = |payload: &str| {
+        producer
+            .send(BaseRecord::<(), str>::to("app-logs").payload(payload))
+            .unwrap();
+        producer.flush(Duration::from_secs(5)).unwrap();
+        produced += 1;
+    };
+
+    let mut parser = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args(["parse", "--kafka-brokers", &brokers])
+        .args(["--kafka-topic", "app-logs", "--kafka-group", "parsers"])
+        .args(["--kafka-window", "1s", "--color", "never"])
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let (rows, lines) = crossbeam_channel::unbounded();
+    let stdout = io::BufReader::new(parser.stdout.take().unwrap());
+    thread::spawn(move || {
+        stdout
+            .lines()
+            .map_while(Result::ok)
+            .try_for_each(|row| rows.send(row))
+    });
+    let mut window = vec![];
+    let mut next_window = |timeout: Duration| -> Vec<String> {
+        let deadline = Instant::now() + timeout;
+        while let Ok(row) = lines.recv_deadline(deadline) {
+            if row.starts_with("total") {
+                return std::mem::take(&mut window);
+            }
+            window.push(row);
+        }
+        panic!("No window reported in {:?}", timeout);
+    };
+    let count = |window: &[String], log_type: &str| -> Option<String> {
+        window.iter().find_map(|row| {
+            let mut columns = row.split_whitespace();
+            (columns.next() == Some(log_type)).then(|| columns.next().unwrap().to_string())
+        })
+    };
+
+    // a new group starts at the end of the topic, so pings are sent until one is consumed
+    let started = Instant::now();
+    loop {
+        assert!(
+            started.elapsed() < Duration::from_secs(30),
+            "Topic not consumed"
+        );
+        produce(r#"{"type":"ping"}"#);
+        if count(&next_window(Duration::from_secs(5)), "ping").is_some() {
+            break;
+        }
+    }
+    // every line of a message is a record
+    produce("{\"type\":\"a\"}\n{\"type\":\"b\"}\n\n{\"type\":\"a\"}");
+    loop {
+        let window = next_window(Duration::from_secs(5));
+        if let Some(lines) = count(&window, "a") {
+            assert_eq!(lines, "2", "{:?}", window);
+            assert_eq!(count(&window, "b").as_deref(), Some("1"), "{:?}", window);
+            break;
+        }
+    }
+    // offsets of reported windows are committed for the group
+    let reported = Instant::now();
+    let committed = loop {
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &brokers)
+            .set("group.id", "parsers")
+            .create()
+            .unwrap();
+        let mut partitions = TopicPartitionList::new();
+        partitions.add_partition("app-logs", 0);
+        let committed = consumer
+            .committed_offsets(partitions, Duration::from_secs(5))
+            .unwrap();
+        let offset = committed.find_partition("app-logs", 0).unwrap().offset();
+        if offset == Offset::Offset(produced) || reported.elapsed() > Duration::from_secs(10) {
+            break offset;
+        }
+        thread::sleep(Duration::from_millis(200));
+    };
+    parser.kill().unwrap();
+    parser.wait().unwrap();
+    assert_eq!(committed, Offset::Offset(produced));
+}
+
+#[cfg(not(feature = "kafka"))]
+#[test]
+fn kafka_topics_need_the_kafka_feature() {
+    let output = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args([
+            "parse",
+            "--kafka-brokers",
+            "kafka:9092",
+            "--kafka-topic",
+            "app-logs",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--features kafka"), "{}", stderr);
+}
+
+#[test]
+fn agents_forward_matching_lines_of_followed_files() {
+    let fixture = Fixture::new("agent");
+    let input = fixture.lines(
+        "app.log",
+        [
+            r#"{"type":"a","level":"info"}"#,
+            r#"{"type":"a","level":"debug"}"#,
+            "not json",
+        ],
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let uri = format!("tcp://{}", listener.local_addr().unwrap());
+    let mut agent = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args([
+            "agent",
+            "-f",
+            &input,
+            "--from-start",
+            "--poll-interval",
+            "50ms",
+        ])
+        .args(["--where", "level!=debug", "--forward", &uri])
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let (stream, _) = listener.accept().unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let mut forwarded = io::BufReader::new(stream).lines();
+    let mut next = || forwarded.next().unwrap().unwrap();
+    assert_eq!(next(), r#"{"type":"a","level":"info"}"#);
+
+    // appended lines are forwarded as they are written
+    let mut file = OpenOptions::new().append(true).open(&input).unwrap();
+    writeln!(file, r#"{{"type":"b","level":"debug"}}"#).unwrap();
+    writeln!(file, r#"{{"type":"b", "level":"error"}}"#).unwrap();
+    assert_eq!(next(), r#"{"type":"b", "level":"error"}"#);
+    agent.kill().unwrap();
+    agent.wait().unwrap();
+}
+
+/// The input is far too big to be parsed before the signal, so the run is still parsing when it's
+/// stopped.
+#[cfg(all(unix, feature = "gzip"))]
+#[test]
+fn stopped_runs_report_the_lines_parsed_so_far() {
+    let fixture = Fixture::new("stopped");
+    let member = fixture.lines("member.log", (0..10_000).map(|_| r#"{"type":"a"}"#));
+    let compressed = fixture.path("member.log.gz");
+    decompress::compress(decompress::Codec::Gzip, &member, &compressed).unwrap();
+    // concatenated gzip members are read as one stream
+    let total = 10_000 * 5_000;
+    let input = fixture.bytes("app.log.gz", &fs::read(&compressed).unwrap().repeat(5_000));
+
+    let parse = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args(["parse", "-i", &input, "--color", "never"])
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+    let killed = process::Command::new("kill")
+        .args(["-TERM", &parse.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    let output = parse.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(130));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("INCOMPLETE"), "{}", stderr);
+    let exit: Value = serde_json::from_str(stderr.lines().last().unwrap()).unwrap();
+    assert_eq!(exit["status"], "interrupted");
+    let lines = exit["lines"].as_u64().unwrap();
+    assert!(lines > 0 && lines < total, "{}", lines);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains(&format!("total (1 log_types)  {:>5}", lines)),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn multiple_inputs_are_reported_together_and_per_file() {
+    let fixture = Fixture::new("inputs");
+    let first = fixture.lines("app.log.1", [r#"{"type":"a"}"#, r#"{"type":"b"}"#]);
+    let second = fixture.lines(
+        "app.log",
+        [r#"{"type":"a"}"#, r#"{"type":"a"}"#, r#"{"type":"c"}"#],
+    );
+    let results = fixture.path("results.jsonl");
+    let parse = |args: &[&str]| {
+        process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+            .args([
+                "parse", "-t", "2", "-i", &first, "-i", &second, "--color", "never",
+            ])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let totals = |output: &process::Output| -> Vec<String> {
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| line.starts_with("total"))
+            .map(|line| {
+                line.split_whitespace()
+                    .take(4)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    };
+
+    let sink = format!("file://{}", results);
+    assert_eq!(
+        totals(&parse(&["--sink", &sink])),
+        ["total (3 log_types) 5"]
+    );
+    let run: Value = serde_json::from_str(
+        fs::read_to_string(&results)
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(run["input"], format!("{}, {}", first, second));
+    assert_eq!(run["lines"], 5);
+
+    // rows of every input come before the totals
+    assert_eq!(
+        totals(&parse(&["--per-file"])),
+        [
+            "total (2 log_types) 2",
+            "total (2 log_types) 3",
+            "total (3 log_types) 5"
+        ]
+    );
+    let bad_lines = fixture.path("bad.jsonl");
+    assert!(!parse(&["--bad-lines", &bad_lines]).status.success());
+}
+
+#[test]
+fn sampled_inputs_are_reported_with_estimates_in_a_single_table() {
+    let fixture = Fixture::new("sampled");
+    let lines = [
+        r#"{"type":"a"}"#,
+        r#"{"type":"b"}"#,
+        r#"{"type":"a"}"#,
+        r#"{"type":"b"}"#,
+    ];
+    let first = fixture.lines("app.log.1", lines);
+    let second = fixture.lines("app.log", lines);
+    let output = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args(["parse", "-i", &first, "-i", &second, "--head", "2"])
+        .args(["--per-file", "--color", "never"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // a table per input and the one of both, estimates are columns of their rows
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains('|'), "{}", stdout);
+    let headers = stdout
+        .lines()
+        .filter(|line| line.starts_with("log_type"))
+        .inspect(|header| assert!(header.ends_with("est. lines  est. bytes  est. errors")))
+        .count();
+    assert_eq!(headers, 3, "{}", stdout);
+    let totals: Vec<Vec<&str>> = stdout
+        .lines()
+        .filter(|line| line.starts_with("total"))
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert_eq!(totals.len(), 3);
+    for (total, (lines, bytes)) in totals.iter().zip([("2", "26"), ("2", "26"), ("4", "52")]) {
+        assert_eq!([total[3], total[5]], [lines, bytes], "{:?}", total);
+    }
+    let estimates: Vec<&[&str]> = totals
+        .iter()
+        .map(|total| &total[total.len() - 4..])
+        .collect();
+    assert_eq!(
+        estimates,
+        [
+            ["~4", "~52", "B", "~0"],
+            ["~4", "~52", "B", "~0"],
+            ["~8", "~104", "B", "~0"]
+        ]
+    );
+
+    // sizes of the inputs are logged, not those of the samples
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr
+            .matches("File size in bytes: 52 (26 sampled)")
+            .count(),
+        2
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn dashboards_of_agents_show_counters_and_recent_errors() {
+    let fixture = Fixture::new("dashboard");
+    let input = fixture.lines(
+        "app.log",
+        [
+            r#"{"type":"a"}"#,
+            "not json",
+            r#"{"type":"b"}"#,
+            r#"{"type":"a"}"#,
+        ],
+    );
+    let mut agent = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args([
+            "agent",
+            "-f",
+            &input,
+            "--from-start",
+            "--poll-interval",
+            "50ms",
+            "--tui",
+        ])
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::null())
+        .spawn()
+        .unwrap();
+    // the agent is stopped once it drew the error line
+    let mut stdout = agent.stdout.take().unwrap();
+    let mut screen = vec![];
+    let mut buffer = [0; 4096];
+    // only changed cells are drawn, word by word
+    while !String::from_utf8_lossy(&screen).contains("[syntax:") {
+        let read = stdout.read(&mut buffer).unwrap();
+        assert!(read > 0, "{:?}", String::from_utf8_lossy(&screen));
+        screen.extend_from_slice(&buffer[..read]);
+    }
+    let stopped = process::Command::new("kill")
+        .args(["-TERM", &agent.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(stopped.success());
+    stdout.read_to_end(&mut screen).unwrap();
+
+    // the alternate screen is restored once the agent stops
+    assert!(agent.wait().unwrap().success());
+    let screen = String::from_utf8(screen).unwrap();
+    assert!(screen.starts_with("\x1b[?1049h"), "{:?}", screen);
+    assert!(screen.ends_with("\x1b[?25h\x1b[?1049l"), "{:?}", screen);
+    assert!(screen.contains("app.log"), "{:?}", screen);
+
+    let forwarded = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args(["agent", "-f", &input, "--tui", "--forward", "-"])
+        .output()
+        .unwrap();
+    assert_eq!(forwarded.status.code(), Some(1));
+}
+
+#[test]
+fn dashboards_fit_counters_and_recent_errors_into_the_terminal() {
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH));
+    let mut dashboard = Dashboard::with_backend("agent", TestBackend::new(60, 16))
+        .unwrap()
+        .with_clock(clock.clone());
+    let options = ParseOptions::default();
+    let mut report = ParseReport::default();
+    for log_type in ["a", "b", "a", "c", "d", "e", "a", "b", "f"] {
+        report
+            .add_line(&format!(r#"{{"type":"{}"}}"#, log_type), &options)
+            .unwrap();
+    }
+    dashboard.update(ParseReport::default());
+    clock.advance(Duration::from_secs(2));
+    dashboard.update(report);
+    for idx in 0..10 {
+        let line = format!("not json {} {}", idx, "x".repeat(80));
+        let err = serde_json::from_str::<Value>(&line).unwrap_err();
+        dashboard.error("app.log", &line, &err);
+    }
+    dashboard.draw("matching lines: 9").unwrap();
+
+    let buffer = dashboard.backend().buffer();
+    let rows: Vec<String> = (0..buffer.area.height)
+        .map(|y| {
+            (0..buffer.area.width)
+                .map(|x| buffer[(x, y)].symbol())
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        })
+        .collect();
+    assert_eq!(
+        rows[0],
+        "agent - up 2s, lines: 9, failed lines: 0, 4.5 lines/s"
+    );
+    assert_eq!(rows[1], "matching lines: 9");
+    // half of the screen for log types, the ones which don't fit are summed up
+    assert!(rows[2].starts_with("┌ log types "), "{:?}", rows);
+    let counters: Vec<Vec<&str>> = rows[3..9]
+        .iter()
+        .map(|row| row.trim_matches('│').split_whitespace().collect())
+        .collect();
+    assert_eq!(counters[0], ["log", "type", "lines", "lines/s", "bytes"]);
+    assert_eq!(counters[1][..3], ["a", "3", "1.5"]);
+    assert_eq!(counters[2][..3], ["b", "2", "1.0"]);
+    assert_eq!(counters[3][..3], ["c", "1", "0.5"]);
+    assert_eq!(counters[4][..3], ["d", "1", "0.5"]);
+    assert_eq!(counters[5], ["...", "2", "more"]);
+    // the newest errors fill the rest, cut to the width of the terminal
+    assert!(
+        rows[10].starts_with("┌ recent errors (0 total) "),
+        "{:?}",
+        rows
+    );
+    assert!(
+        rows[11].starts_with("│00:00:02 app.log [syntax: expected ident] not json 6 xxx"),
+        "{:?}",
+        rows
+    );
+    assert!(
+        rows[14].starts_with("│00:00:02 app.log [syntax: expected ident] not json 9 xxx"),
+        "{:?}",
+        rows
+    );
+    assert!(rows[15].starts_with("└"), "{:?}", rows);
+    assert!(rows.iter().all(|row| row.chars().count() <= 60));
+}
+
+#[test]
+fn report_tables_are_aligned_and_colored_by_errors() {
+    let fixture = Fixture::new("colors");
+    let input = fixture.lines(
+        "input.log",
+        [
+            r#"{"type":"c"}"#,
+            r#"{"type":"a"}"#,
+            r#"{"type":"a","level":"error"}"#,
+            r#"{"type":"b","level":"error"}"#,
+            r#"{"type":"a"}"#,
+        ],
+    );
+    let parse = |color: &str| {
+        let output = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+            .args(["parse", "-i", &input, "--color", color])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    // the most frequent log types first, numbers aligned to the right of their headers
+    let plain = parse("never");
+    let rows: Vec<&str> = plain.lines().filter(|row| !row.is_empty()).collect();
+    let first_words: Vec<&str> = rows
+        .iter()
+        .map(|row| row.split_whitespace().next().unwrap())
+        .collect();
+    assert_eq!(first_words, ["log_type", "a", "b", "c", "total"]);
+    let lines_end = rows[0].find("lines").unwrap() + "lines".len();
+    let errors_end = rows[0].find("errors").unwrap() + "errors".len();
+    for row in &rows[1..] {
+        let row = row.as_bytes();
+        assert!(row[lines_end - 1].is_ascii_digit() && row[lines_end] == b' ');
+        assert!(row[errors_end - 1].is_ascii_digit() && row[errors_end] == b' ');
+    }
+    // stdout of the test isn't a terminal
+    assert_eq!(parse("auto"), plain);
+
+    // log types with errors are yellow, mostly errors red
+    let colored = parse("always");
+    let styles: Vec<&str> = colored
+        .lines()
+        .filter(|row| !row.is_empty())
+        .map(|row| match row.strip_prefix('\x1b') {
+            Some(styled) => &styled[..styled.find('m').unwrap() + 1],
+            None => "",
+        })
+        .collect();
+    assert_eq!(styles, ["[1m", "[33m", "[1;31m", "", "[1m"]);
+    let uncolored: String = colored.replace("\x1b[1;31m", "").replace("\x1b[33m", "");
+    assert_eq!(
+        uncolored.replace("\x1b[1m", "").replace("\x1b[0m", ""),
+        plain
+    );
+}
+
+#[test]
+fn user_errors_exit_without_diagnostics_bundle() {
+    let fixture = Fixture::new("user-error");
+    let input = fixture.lines("input.log", [r#"{"type":"a"}"#]);
+    // the retention rule is read once the input is parsed, so after the panic hook is installed
+    let output = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args(["parse", "-i", &input, "--retention", "bogus"])
+        .env("TMPDIR", fixture.dir())
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let exit: Value = serde_json::from_str(stderr.lines().last().unwrap()).unwrap();
+    assert_eq!(exit["status"], "failed");
+    assert_eq!(exit["exit_code"], 1);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    let bundles = fs::read_dir(fixture.dir())
+        .unwrap()
+        .filter(|entry| {
+            let name = entry.as_ref().unwrap().file_name();
+            name.to_string_lossy()
+                .starts_with("log-parser-diagnostics-")
+        })
+        .count();
+    assert_eq!(bundles, 0);
+}
+
+#[test]
+fn failed_runs_record_lines_read_before_failing() {
+    let fixture = Fixture::new("failed-run");
+    let input = fixture.lines(
+        "input.log",
+        [r#"{"type":"a"}"#, r#"{"type":"b"}"#, "not json"],
+    );
+    let output = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args(["parse", "-i", &input, "--retention", "bogus"])
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let exit: Value = serde_json::from_str(stderr.lines().last().unwrap()).unwrap();
+    assert_eq!(exit["status"], "failed");
+    assert_eq!(exit["lines"], 3);
+}
+
+#[test]
+fn strict_runs_exit_with_2_when_lines_fail() {
+    let fixture = Fixture::new("strict");
+    let input = fixture.lines(
+        "input.log",
+        [r#"{"type":"a"}"#, "not json", r#"{"type":"b"}"#, "{"],
+    );
+    let run = |args: &[&str]| {
+        let output = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+            .args(["parse", "-i", &input])
+            .args(args)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let exit: Value = serde_json::from_str(stderr.lines().last().unwrap()).unwrap();
+        (output.status.code(), exit)
+    };
+
+    let (code, exit) = run(&[]);
+    assert_eq!(code, Some(0));
+    assert_eq!(exit["failed_lines"], 2);
+    let (code, exit) = run(&["--strict"]);
+    assert_eq!(code, Some(2));
+    assert_eq!(exit["status"], "violations");
+    assert_eq!(run(&["--max-errors", "1"]).0, Some(2));
+    assert_eq!(run(&["--max-errors", "2"]).0, Some(0));
+}
+
+/// The async parser runs the parser on the blocking pool of the runtime, with the report of the
+/// channel parser and records received from an async channel.
+#[cfg(feature = "async")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn async_parser_runs_on_tokio() {
+    let options = parse_options();
+    let sample_options = sample(0);
+    let corpus = Corpus::generate("async", &sample_options);
+
+    let expected = parser::multi_thread_parser_channel(4, corpus.path(), &options).unwrap();
+    let report = async_parser::parse_async(4, corpus.path(), &options)
+        .await
+        .unwrap();
+    assert_same_report(&expected, &report, "async");
+
+    let mut records = async_parser::stream_async(4, corpus.path(), &options)
+        .await
+        .unwrap();
+    let mut received = 0;
+    while let Some(record) = records.recv().await {
+        record.expect("Generated lines are valid");
+        received += 1;
+    }
+    assert_eq!(received, sample_options.num_of_lines as usize);
+
+    // the receiver can be dropped before the end, the parser stops on its own
+    let mut records = async_parser::stream_async(4, corpus.path(), &options)
+        .await
+        .unwrap();
+    assert!(records.recv().await.is_some());
+    drop(records);
+
+    let missing = async_parser::parse_async(4, format!("{}.missing", corpus.path()), &options)
+        .await
+        .unwrap_err();
+    assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+}
+
+/// Objects are parsed as they're fetched, through the decompression tool if they're compressed,
+/// without a copy in the temp directory.
+#[cfg(feature = "s3")]
+#[test]
+fn remote_objects_are_parsed_as_they_are_fetched() {
+    let fixture = Fixture::new("s3");
+    let input = fixture.lines(
+        "app.log",
+        [r#"{"type":"a"}"#, r#"{"type":"b"}"#, "not json"],
+    );
+    let compressed = fixture.path("app.log.gz");
+    decompress::compress(decompress::Codec::Gzip, &input, &compressed).unwrap();
+    // bigger than a range, so it's fetched in two
+    let big = r#"{"type":"a"}"#.repeat(700_000).replace("}{", "}\n{") + "\n";
+    let gz_size = fs::metadata(&compressed).unwrap().len();
+    let objects = HashMap::from([
+        (
+            "/logs/app.log.gz".to_string(),
+            fs::read(&compressed).unwrap(),
+        ),
+        ("/logs/big.log".to_string(), big.into_bytes()),
+    ]);
+    let (endpoint, ranges) = s3_server(objects);
+    let temp_dir = fixture.path("tmp");
+    fs::create_dir(&temp_dir).unwrap();
+    let parse = |url: &str| {
+        process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+            .args(["parse", "-i", url])
+            .env("AWS_ENDPOINT", &endpoint)
+            .env("AWS_ALLOW_HTTP", "true")
+            .env("AWS_REGION", "us-east-1")
+            .env("AWS_ACCESS_KEY_ID", "key")
+            .env("AWS_SECRET_ACCESS_KEY", "secret")
+            .env("TMPDIR", &temp_dir)
+            .output()
+            .unwrap()
+    };
+
+    let output = parse("s3://logs/app.log.gz");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    let exit: Value = serde_json::from_str(stderr.lines().last().unwrap()).unwrap();
+    assert_eq!(exit["lines"], 3);
+    assert_eq!(exit["failed_lines"], 1);
+    assert_eq!(fs::read_dir(&temp_dir).unwrap().count(), 0);
+
+    let output = parse("s3://logs/big.log");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    let exit: Value = serde_json::from_str(stderr.lines().last().unwrap()).unwrap();
+    assert_eq!(exit["lines"], 700_000);
+    // objects are fetched once, the first range is continued by the parser
+    let size = 700_000 * 13;
+    assert_eq!(
+        *ranges.lock().unwrap(),
+        [
+            (
+                "/logs/app.log.gz".to_string(),
+                format!("bytes=0-{}", gz_size - 1)
+            ),
+            (
+                "/logs/big.log".to_string(),
+                format!("bytes=0-{}", remote::RANGE_SIZE - 1)
+            ),
+            (
+                "/logs/big.log".to_string(),
+                format!("bytes={}-{}", remote::RANGE_SIZE, size - 1)
+            ),
+        ]
+    );
+
+    let output = parse("s3://logs/missing.log");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("Can't fetch s3://logs/missing.log"),
+        "{}",
+        stderr
+    );
+}
+
+/// S3 compatible endpoint serving the objects (by path, e.g. `/bucket/key`) to path-style
+/// requests, with the ranges of GET requests by path.
+#[cfg(feature = "s3")]
+#[allow(clippy::type_complexity)]
+fn s3_server(objects: HashMap<String, Vec<u8>>) -> (String, Arc<Mutex<Vec<(String, String)>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let ranges = Arc::new(Mutex::new(vec![]));
+    let requested = ranges.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let mut range = None;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim_end().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.trim_end().split_once(": ") {
+                    if name.eq_ignore_ascii_case("range") {
+                        range = Some(value.to_string());
+                    }
+                }
+            }
+            let mut parts = request.split(' ');
+            let (method, path) = (parts.next().unwrap(), parts.next().unwrap());
+            let object = match objects.get(path) {
+                Some(object) => object,
+                None => {
+                    let body = "<Error><Code>NoSuchKey</Code></Error>";
+                    write!(
+                        stream,
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .unwrap();
+                    continue;
+                }
+            };
+            let headers =
+                "Last-Modified: Mon, 01 Jan 2024 00:00:00 GMT\r\nETag: \"1\"\r\nConnection: close";
+            let (status, body) = match (method, &range) {
+                ("GET", Some(range)) => {
+                    requested
+                        .lock()
+                        .unwrap()
+                        .push((path.to_string(), range.clone()));
+                    let (start, end) = range
+                        .strip_prefix("bytes=")
+                        .and_then(|range| range.split_once('-'))
+                        .unwrap();
+                    let (start, end): (usize, usize) =
+                        (start.parse().unwrap(), end.parse().unwrap());
+                    (
+                        format!(
+                            "206 Partial Content\r\nContent-Range: bytes {}-{}/{}",
+                            start,
+                            end,
+                            object.len()
+                        ),
+                        &object[start..=end],
+                    )
+                }
+                ("GET", None) => ("200 OK".to_string(), &object[..]),
+                _ => ("200 OK".to_string(), &[][..]),
+            };
+            let len = if method == "HEAD" {
+                object.len()
+            } else {
+                body.len()
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\n{}\r\nContent-Length: {}\r\n\r\n",
+                status, headers, len
+            )
+            .unwrap();
+            stream.write_all(body).unwrap();
+        }
+    });
+
+    (endpoint, ranges)
+}
+
+#[test]
+fn stream_reports_errors_as_items() {
+    let fixture = Fixture::new("stream-errors");
+    let options = parse_options();
+    let missing = PathBuf::from(fixture.path("missing.log"));
+    assert!(parser::stream(2, &missing, &options).is_err());
+
+    let path = fixture.lines(
+        "broken.log",
+        ["{\"type\":\"a\"}", "not json", "{\"type\":\"b\"}"].iter(),
+    );
+    let items: Vec<_> = parser::stream(1, Path::new(&path), &options)
+        .unwrap()
+        .collect();
+    assert_eq!(items.len(), 3);
+    let failed: Vec<_> = items
+        .iter()
+        .filter_map(|item| item.as_ref().err())
+        .collect();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(
+        ParseError::of(failed[0]).unwrap().line.trim_end(),
+        "not json"
+    );
+}
+
+/// Reads of a directory fail on Unix, the skipped bytes are streamed as an error.
+#[cfg(unix)]
+#[test]
+fn stream_reports_skipped_bytes() {
+    let fixture = Fixture::new("stream-unreadable");
+    let options = ParseOptions {
+        io_retries: Some(0),
+        skip_unreadable: true,
+        ..parse_options()
+    };
+
+    let errors: Vec<_> = parser::stream(1, fixture.dir(), &options)
+        .unwrap()
+        .map(|item| item.unwrap_err())
+        .collect();
+    assert_eq!(errors.len(), 1);
+    let skipped = errors[0]
+        .get_ref()
+        .and_then(|err| err.downcast_ref::<FailedRange>())
+        .expect("skipped range");
+    assert_eq!(skipped.attempts, 1);
+}
+
+#[test]
+fn final_line_without_new_line_is_parsed_once() {
+    let options = parse_options();
+    check(
+        (sample_options(), 1..20u32),
+        |(sample_options, num_of_lines)| {
+            let sample_options = SampleOptions {
+                num_of_lines,
+                ..sample_options
+            };
+            let corpus = Corpus::generate("final-line", &sample_options);
+            let content = fs::read(corpus.path()).unwrap();
+            let content = content.strip_suffix(b"\n").unwrap_or(&content);
+            fs::write(corpus.path(), content).unwrap();
+
+            let expected = parser::single_thread_parser(corpus.path(), &options).unwrap();
+            let total_bytes: u64 = expected
+                .log_register
+                .values()
+                .map(|register| register.num_of_bytes)
+                .sum();
+            assert_eq!(expected.lines, sample_options.num_of_lines as u64);
+            assert_eq!(total_bytes, content.len() as u64);
+            // more threads than bytes too
+            for num_of_threads in (1..=MAX_THREADS).chain([u8::MAX]) {
+                let channel =
+                    parser::multi_thread_parser_channel(num_of_threads, corpus.path(), &options)
+                        .unwrap();
+                let dashmap =
+                    parser::multi_thread_parser_dashmap(num_of_threads, corpus.path(), &options)
+                        .unwrap();
+                let records = parser::stream(num_of_threads, corpus.path(), &options)
+                    .unwrap()
+                    .count();
+
+                assert_same_report(&expected, &channel, "channel");
+                assert_same_report(&expected, &dashmap, "dashmap");
+                assert_eq!(records, sample_options.num_of_lines as usize);
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn custom_delimiters_give_the_same_records() {
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("delimited", &sample_options);
+            let expected = parser::single_thread_parser(corpus.path(), &parse_options()).unwrap();
+            let content = fs::read(corpus.path()).unwrap();
+
+            for (delimiter, escaped) in [(&b"\0"[..], "\\0"), (b"\r\n", "\\r\\n"), (b"<|>", "<|>")]
+            {
+                let delimited: Vec<u8> = content
+                    .split(|byte| *byte == b'\n')
+                    .filter(|line| !line.is_empty())
+                    .flat_map(|line| [line, delimiter].concat())
+                    .collect();
+                fs::write(corpus.path(), delimited).unwrap();
+                let options = ParseOptions {
+                    record_delimiter: Some(Delimiter::parse(escaped).unwrap()),
+                    ..parse_options()
+                };
+
+                let report =
+                    parser::multi_thread_parser_channel(num_of_threads, corpus.path(), &options)
+                        .unwrap();
+
+                assert_eq!(report.lines, expected.lines);
+                assert_eq!(report.failed_lines, 0);
+                for (log_type, register) in &expected.log_register {
+                    let actual = &report.log_register[log_type];
+                    let extra_bytes = (delimiter.len() as u64 - 1) * register.counter;
+                    assert_eq!(actual.counter, register.counter);
+                    assert_eq!(actual.num_of_bytes, register.num_of_bytes + extra_bytes);
+                }
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn overlapping_delimiters_split_the_same_in_every_part() {
+    let escaped = prop::sample::select(vec!["@@", "aba", "\\r\\n\\r\\n"]);
+    let content = prop::collection::vec(prop::sample::select(b"ab@\r\n".to_vec()), 1..2_000);
+    check((escaped, content), |(escaped, content)| {
+        let fixture = Fixture::new("overlapping");
+        let path = fixture.bytes("delimited.log", &content);
+        let options = ParseOptions {
+            record_delimiter: Some(Delimiter::parse(escaped).unwrap()),
+            ..parse_options()
+        };
+        // runs of the delimiter (e.g. `@@@` or `ababa`) split differently from their middle
+        let records = |num_of_threads| {
+            let mut records: Vec<(u64, u64)> = parser::stream(num_of_threads, &path, &options)
+                .unwrap()
+                .map(|record| match record {
+                    Ok(record) => (record.offset, record.num_of_bytes),
+                    Err(err) => (u64::MAX, ParseError::of(&err).unwrap().line.len() as u64),
+                })
+                .collect();
+            records.sort_unstable();
+            records
+        };
+
+        let expected = records(1);
+        for num_of_threads in 2..=MAX_THREADS * 4 {
+            assert_eq!(
+                records(num_of_threads),
+                expected,
+                "{} threads",
+                num_of_threads
+            );
+        }
+        Ok(())
+    });
+}
+
+#[test]
+fn reingested_quarantine_gives_the_report_of_fixed_lines() {
+    let options = StateOptions::new(&parse_options(), 0).parse_options();
+    let not_json = 0..5;
+    let renamed = prop::collection::vec(prop::bool::weighted(0.3), MAX_LINES + not_json.end);
+    check(
+        (sample_options(), threads(), not_json, renamed),
+        |(sample_options, num_of_threads, not_json, renamed)| {
+            let corpus = Corpus::generate("reingest", &sample_options);
+            let mut fixed = fs::read_to_string(corpus.path()).unwrap();
+            for _ in 0..not_json {
+                fixed.push_str("not json\n");
+            }
+            fs::write(corpus.path(), &fixed).unwrap();
+            let expected = parser::single_thread_parser(corpus.path(), &options).unwrap();
+
+            // producer sent some logs with `kind` instead of `type`
+            let broken: String = fixed
+                .split_inclusive('\n')
+                .zip(renamed)
+                .map(|(line, renamed)| match renamed {
+                    true => line.replacen("\"type\":", "\"kind\":", 1),
+                    false => line.to_string(),
+                })
+                .collect();
+            fs::write(corpus.path(), broken).unwrap();
+            let quarantine_path = format!("{}.quarantine", corpus.path());
+            let quarantine_options = ParseOptions {
+                bad_lines: Some(Quarantine::new(&quarantine_path)),
+                ..options.clone()
+            };
+            let report = parser::multi_thread_parser_sharded(
+                num_of_threads,
+                corpus.path(),
+                &quarantine_options,
+            )
+            .unwrap();
+            let mut saved = SavedState::new(corpus.path(), report, StateOptions::new(&options, 0));
+
+            let overrides = Overrides {
+                renames: vec![("kind".to_string(), "type".to_string())],
+                ..Overrides::default()
+            };
+            let quarantined = io::BufReader::new(fs::File::open(&quarantine_path).unwrap());
+            let mut failing = 0;
+            reingest::reingest(
+                &quarantine_path,
+                quarantined,
+                &mut saved,
+                &options,
+                &overrides,
+                |_| failing += 1,
+            )
+            .unwrap();
+            fs::remove_file(&quarantine_path).unwrap();
+
+            assert_eq!(failing, expected.failed_lines);
+            assert_same_report(&expected, &saved.report, "reingested");
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn resumed_run_gives_the_same_report() {
+    let options = parse_options();
+    check(
+        (sample_options(), any::<prop::sample::Index>()),
+        |(sample_options, lines_read)| {
+            let corpus = Corpus::generate("resumed", &sample_options);
+            let expected = parser::single_thread_parser(corpus.path(), &options).unwrap();
+
+            // checkpoint of a run interrupted after the first lines of the only part
+            let content = fs::read_to_string(corpus.path()).unwrap();
+            let lines_read = lines_read.index(sample_options.num_of_lines as usize + 1);
+            let bytes_read: usize = content
+                .split_inclusive('\n')
+                .take(lines_read)
+                .map(str::len)
+                .sum();
+            fs::write(corpus.path(), &content[..bytes_read]).unwrap();
+            let read = parser::single_thread_parser(corpus.path(), &options).unwrap();
+            fs::write(corpus.path(), &content).unwrap();
+            let checkpoint_path = PathBuf::from(format!("{}.checkpoint", corpus.path()));
+            let saved = json!({
+                "key": "resumed",
+                "parts": {"0": {
+                    "bytes_read": bytes_read,
+                    "lines": read.lines,
+                    "bad_lines": read.failed_lines,
+                    "done": false,
+                    "register": read.log_register,
+                }},
+            });
+            fs::write(&checkpoint_path, saved.to_string()).unwrap();
+            let options = ParseOptions {
+                checkpoint: Some(
+                    Checkpoint::open(&checkpoint_path, "resumed", Duration::ZERO).unwrap(),
+                ),
+                ..parse_options()
+            };
+
+            let resumed = parser::multi_thread_parser_dashmap(1, corpus.path(), &options).unwrap();
+
+            assert_same_report(&expected, &resumed, "resumed");
+            assert!(!checkpoint_path.exists());
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn merged_shard_states_give_the_same_report() {
+    let options = parse_options();
+    check(
+        (sample_options(), threads(), any::<prop::sample::Index>()),
+        |(sample_options, num_of_threads, split)| {
+            let corpus = Corpus::generate("shards", &sample_options);
+            let expected = parser::single_thread_parser(corpus.path(), &options).unwrap();
+
+            let content = fs::read_to_string(corpus.path()).unwrap();
+            let lines: Vec<&str> = content.split_inclusive('\n').collect();
+            let split = split.index(lines.len() + 1);
+            let mut states = vec![];
+            for (idx, shard) in [&lines[..split], &lines[split..]].iter().enumerate() {
+                let shard_path = format!("{}.shard{}", corpus.path(), idx);
+                fs::write(&shard_path, shard.concat()).unwrap();
+                let report =
+                    parser::multi_thread_parser_dashmap(num_of_threads, &shard_path, &options)
+                        .unwrap();
+                let state_path = format!("{}.agg", shard_path);
+                SavedState::new(&shard_path, report, StateOptions::new(&options, 0))
+                    .save(&state_path)
+                    .unwrap();
+                states.push(SavedState::load(&state_path).unwrap());
+                fs::remove_file(&shard_path).unwrap();
+                fs::remove_file(&state_path).unwrap();
+            }
+
+            let merged = state::merge(states).unwrap();
+
+            assert_same_report(&expected, &merged.report, "merged");
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn served_batches_give_the_same_report() {
+    let options = parse_options();
+    let credentials = Credentials::basic("agent:s3cr3t:x").unwrap();
+    let server = Server::bind("127.0.0.1:0", options.clone())
+        .unwrap()
+        .credentials(&[
+            Credentials::Bearer("token".to_string()),
+            credentials.clone(),
+        ]);
+    let server = Arc::new(server);
+    let url = http::Url::parse(&format!("http://{}/ingest", server.local_addr().unwrap())).unwrap();
+    let running = server.clone();
+    thread::spawn(move || running.run());
+
+    let body = b"{\"type\":\"a\"}\n";
+    let wrong = Credentials::Bearer("tokem".to_string()).header();
+    for headers in [vec![], vec![("Authorization", wrong.as_str())]] {
+        let response = http::post(&url, "application/x-ndjson", body, &headers).unwrap();
+        assert_eq!(response.status, 401, "batch without credentials");
+    }
+    // rejected before the body is read, so the server neither waits for it nor allocates it
+    let mut stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    write!(
+        stream,
+        "POST /ingest HTTP/1.1\r\nContent-Length: {}\r\nExpect: 100-continue\r\n\r\n",
+        serve::MAX_BODY_SIZE
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 401 Unauthorized\r\n"),
+        "{}",
+        response
+    );
+    let authorization = credentials.header();
+
+    let expected = RefCell::new(ParseReport::default());
+    check(
+        (sample_options(), any::<prop::sample::Index>()),
+        |(sample_options, split)| {
+            let corpus = Corpus::generate("served", &sample_options);
+            *expected.borrow_mut() +=
+                parser::single_thread_parser(corpus.path(), &options).unwrap();
+
+            let content = fs::read_to_string(corpus.path()).unwrap();
+            let lines: Vec<&str> = content.split_inclusive('\n').collect();
+            let split = split.index(lines.len() + 1);
+            for batch in [&lines[..split], &lines[split..]] {
+                let headers = [("Authorization", authorization.as_str())];
+                let response = http::post(
+                    &url,
+                    "application/x-ndjson",
+                    batch.concat().as_bytes(),
+                    &headers,
+                )
+                .unwrap();
+                assert!(response.is_success(), "batch rejected");
+            }
+            Ok(())
+        },
+    );
+
+    assert_same_report(&expected.borrow(), &server.report(), "served");
+
+    let page = http::Url::parse(&format!("http://{}/", server.local_addr().unwrap())).unwrap();
+    let headers = [("Authorization", authorization.as_str())];
+    let response = http::request("GET", &page, "text/plain", b"", &headers).unwrap();
+    assert_eq!(response.status, 200);
+    let page = String::from_utf8(response.body).unwrap();
+    assert!(page.starts_with("<!DOCTYPE html>") && page.contains("fetch(\"stats\""));
+}
+
+#[test]
+fn grafana_charts_served_counters_and_windows() {
+    let now = UNIX_EPOCH + Duration::from_secs(1_714_600_000);
+    let options = ParseOptions {
+        timestamp_field: Some("ts".to_string()),
+        rate_window: Some(60),
+        clock: Some(Arc::new(ManualClock::new(now))),
+        ..ParseOptions::default()
+    };
+    let server = Arc::new(Server::bind("127.0.0.1:0", options).unwrap());
+    let address = server.local_addr().unwrap();
+    let running = server.clone();
+    thread::spawn(move || running.run());
+    let call = |method: &str, path: &str, body: &str| {
+        let url = http::Url::parse(&format!("http://{}{}", address, path)).unwrap();
+        let response = http::request(method, &url, "application/json", body.as_bytes(), &[]);
+        let response = response.unwrap();
+        let body = serde_json::from_slice(&response.body).unwrap_or(Value::Null);
+        (response.status, body)
+    };
+
+    let records = [
+        r#"{"type":"api","level":"info","ts":"2024-05-01T00:00:10Z"}"#,
+        r#"{"type":"api","level":"error","ts":"2024-05-01T00:00:50Z"}"#,
+        r#"{"type":"api","level":"info","ts":"2024-05-01T00:02:00Z"}"#,
+        r#"{"type":"db","level":"info","ts":"2024-05-01T00:01:00Z"}"#,
+        r#"{"type":"jobs","level":"info"}"#,
+    ];
+    assert_eq!(call("POST", "/ingest", &records.join("\n")).0, 200);
+
+    assert_eq!(call("GET", "/grafana/", "").0, 200);
+    assert_eq!(
+        call("POST", "/grafana/search", r#"{"target":""}"#),
+        (200, json!(["api", "db", "jobs"]))
+    );
+    assert_eq!(
+        call("POST", "/grafana/search", r#"{"target":"b"}"#),
+        (200, json!(["db", "jobs"]))
+    );
+
+    // windows before the range are left out, types without windows give the live counter
+    let start = 1_714_521_600_000u64;
+    let query = json!({
+        "range": {"from": "2024-05-01T00:01:00Z", "to": "2024-05-01T01:00:00Z"},
+        "targets": [
+            {"target": "api"},
+            {"target": "api", "payload": {"metric": "errors"}},
+            {"target": "jobs"},
+            {"target": "missing"},
+            {"target": "*", "type": "table"},
+        ],
+    });
+    let (status, series) = call("POST", "/grafana/query", &query.to_string());
+    assert_eq!(status, 200);
+    assert_eq!(
+        series,
+        json!([
+            {"target": "api", "datapoints": [[1, start + 120_000]]},
+            {"target": "api", "datapoints": [[0, start + 120_000]]},
+            {"target": "jobs", "datapoints": [[1, 1_714_600_000_000u64]]},
+            {
+                "type": "table",
+                "columns": [
+                    {"text": "log_type", "type": "string"},
+                    {"text": "counter", "type": "number"},
+                    {"text": "num_of_bytes", "type": "number"},
+                    {"text": "errors", "type": "number"},
+                ],
+                "rows": [
+                    ["api", 3, records[..3].iter().map(|record| record.len() + 1).sum::<usize>(), 1],
+                    ["db", 1, records[3].len() + 1, 0],
+                    ["jobs", 1, records[4].len(), 0],
+                ],
+            },
+        ])
+    );
+    let (status, _) = call(
+        "POST",
+        "/grafana/query",
+        r#"{"targets":[{"target":"api","payload":{"metric":"bytes"}}]}"#,
+    );
+    assert_eq!(status, 400);
+    assert_eq!(call("POST", "/grafana/query", "{").0, 400);
+
+    let (status, counters) = call("GET", "/grafana/counters", "");
+    assert_eq!(status, 200);
+    assert_eq!(counters[0]["log_type"], "api");
+    assert_eq!(counters[0]["counter"], 3);
+    assert_eq!(counters[2]["log_type"], "jobs");
+    assert_eq!(
+        call("GET", "/grafana/buckets", ""),
+        (
+            200,
+            json!([
+                {"time": start, "log_type": "api", "counter": 2, "errors": 1},
+                {"time": start + 60_000, "log_type": "db", "counter": 1, "errors": 0},
+                {"time": start + 120_000, "log_type": "api", "counter": 1, "errors": 0},
+            ])
+        )
+    );
+    // records POSTed to other paths are still ingested
+    assert_eq!(server.report().lines, records.len() as u64);
+}
+
+#[test]
+fn served_over_tls_to_clients_with_certificates() {
+    let fixture = Fixture::new("tls");
+    let openssl = |args: &[&str]| {
+        let status = process::Command::new("openssl")
+            .args(args)
+            .current_dir(fixture.dir())
+            .stderr(process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "openssl {:?}", args);
+    };
+    let key = |name: &str| {
+        openssl(&[
+            "genpkey",
+            "-algorithm",
+            "EC",
+            "-pkeyopt",
+            "ec_paramgen_curve:P-256",
+            "-out",
+            name,
+        ])
+    };
+    key("ca.key");
+    openssl(&[
+        "req",
+        "-x509",
+        "-new",
+        "-key",
+        "ca.key",
+        "-subj",
+        "/CN=test ca",
+        "-days",
+        "1",
+        "-out",
+        "ca.pem",
+    ]);
+    let issue = |name: &str, extensions: &str| {
+        key(&format!("{}.key", name));
+        let csr = format!("{}.csr", name);
+        let subject = format!("/CN={}", name);
+        openssl(&[
+            "req",
+            "-new",
+            "-key",
+            &format!("{}.key", name),
+            "-subj",
+            &subject,
+            "-out",
+            &csr,
+        ]);
+        let extfile = fixture.lines(&format!("{}.ext", name), [extensions]);
+        openssl(&[
+            "x509",
+            "-req",
+            "-in",
+            &csr,
+            "-CA",
+            "ca.pem",
+            "-CAkey",
+            "ca.key",
+            "-CAcreateserial",
+            "-days",
+            "1",
+            "-extfile",
+            &extfile,
+            "-out",
+            &format!("{}.pem", name),
+        ]);
+    };
+    issue("localhost", "subjectAltName=DNS:localhost,IP:127.0.0.1");
+    issue("client", "extendedKeyUsage=clientAuth");
+    let path = |name: &str| PathBuf::from(fixture.path(name));
+
+    let server = Server::bind("127.0.0.1:0", ParseOptions::default())
+        .unwrap()
+        .tls(&ServerTls {
+            cert: path("localhost.pem"),
+            key: path("localhost.key"),
+            client_ca: Some(path("ca.pem")),
+        })
+        .unwrap();
+    let server = Arc::new(server);
+    let port = server.local_addr().unwrap().port();
+    let running = server.clone();
+    thread::spawn(move || running.run());
+    let url = http::Url::parse(&format!("https://localhost:{}/ingest", port)).unwrap();
+    let body = b"{\"type\":\"a\"}\n";
+
+    // the server isn't trusted without the CA, and doesn't serve clients without certificates
+    assert!(http::post(&url, "application/x-ndjson", body, &[]).is_err());
+    tls::set_client(&ClientTls {
+        ca: Some(path("ca.pem")),
+        ..ClientTls::default()
+    })
+    .unwrap();
+    assert!(http::post(&url, "application/x-ndjson", body, &[]).is_err());
+    assert_eq!(server.report().lines, 0);
+
+    tls::set_client(&ClientTls {
+        ca: Some(path("ca.pem")),
+        cert: Some(path("client.pem")),
+        key: Some(path("client.key")),
+    })
+    .unwrap();
+    let response = http::post(&url, "application/x-ndjson", body, &[]).unwrap();
+    assert!(response.is_success());
+    let mut sink = sink::open(&format!("https://127.0.0.1:{}/ingest", port)).unwrap();
+    sink.send("{\"type\":\"b\"}").unwrap();
+    sink.close().unwrap();
+    assert_eq!(server.report().lines, 2);
+    assert!(ClientTls {
+        cert: Some(path("client.pem")),
+        ..ClientTls::default()
+    }
+    .config()
+    .is_err());
+
+    // tls:// line sinks write lines over the same kind of connection
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let config = ServerTls {
+        cert: path("localhost.pem"),
+        key: path("localhost.key"),
+        client_ca: None,
+    }
+    .config()
+    .unwrap();
+    let received = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut lines = String::new();
+        Stream::accept(stream, Some(&config))
+            .unwrap()
+            .read_to_string(&mut lines)
+            .unwrap();
+        lines
+    });
+    let mut sink = sink::open(&format!("tls://localhost:{}", port)).unwrap();
+    sink.send("first").unwrap();
+    sink.send("second").unwrap();
+    sink.close().unwrap();
+    drop(sink);
+    assert_eq!(received.join().unwrap(), "first\nsecond\n");
+}
+
+/// Next command of a Redis client (RESP array of bulk strings), `None` once it disconnects.
+fn read_redis_command<R: BufRead>(reader: &mut R) -> Option<Vec<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).unwrap() == 0 {
+        return None;
+    }
+    let args: usize = line.trim_end().strip_prefix('*').unwrap().parse().unwrap();
+    let command = (0..args)
+        .map(|_| {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            let len: usize = line.trim_end().strip_prefix('$').unwrap().parse().unwrap();
+            let mut arg = vec![0; len + 2];
+            reader.read_exact(&mut arg).unwrap();
+            arg.truncate(len);
+            String::from_utf8(arg).unwrap()
+        })
+        .collect();
+    Some(command)
+}
+
+#[test]
+fn redis_replies_round_trip() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut replies = [
+            "+OK\r\n",
+            "+OK\r\n",
+            "$5\r\nhello\r\n",
+            "$-1\r\n",
+            "*2\r\n:1\r\n*1\r\n$1\r\nx\r\n",
+            ":42\r\n",
+            "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n",
+            "+OK\r\n",
+            "+PONG\r\n",
+        ]
+        .iter();
+        let mut commands = vec![];
+        while let Some(command) = read_redis_command(&mut reader) {
+            commands.push(command.join(" "));
+            writer
+                .write_all(replies.next().unwrap().as_bytes())
+                .unwrap();
+        }
+        commands
+    });
+
+    let url = RedisUrl::parse(&format!("redis://:secret@127.0.0.1:{}/2", port)).unwrap();
+    let mut connection = redis::Connection::connect(&url).unwrap();
+    let command = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    let replies = connection
+        .pipeline(&[
+            command(&["GET", "greeting"]),
+            command(&["GET", "missing"]),
+            command(&["LRANGE", "list", "0", "-1"]),
+            command(&["INCRBY", "counter", "42"]),
+        ])
+        .unwrap();
+    assert_eq!(
+        replies,
+        [
+            Reply::Bulk(Some(b"hello".to_vec())),
+            Reply::Bulk(None),
+            Reply::Array(Some(vec![
+                Reply::Integer(1),
+                Reply::Array(Some(vec![Reply::Bulk(Some(b"x".to_vec()))])),
+            ])),
+            Reply::Integer(42),
+        ]
+    );
+    // replies after an error are read too, so the next pipeline gets its own
+    let err = connection
+        .pipeline(&[command(&["INCR", "list"]), command(&["SET", "a", "b c"])])
+        .unwrap_err();
+    assert!(err.to_string().contains("WRONGTYPE"), "{}", err);
+    let replies = connection.pipeline(&[command(&["PING"])]).unwrap();
+    assert_eq!(replies, [Reply::Status("PONG".to_string())]);
+    drop(connection);
+
+    let commands = server.join().unwrap();
+    assert_eq!(
+        commands,
+        [
+            "AUTH secret",
+            "SELECT 2",
+            "GET greeting",
+            "GET missing",
+            "LRANGE list 0 -1",
+            "INCRBY counter 42",
+            "INCR list",
+            "SET a b c",
+            "PING",
+        ]
+    );
+}
+
+#[test]
+fn parquet_sinks_write_records_next_to_the_stats() {
+    let fixture = Fixture::new("parquet-records");
+    // more records than a batch, so they're written in several
+    let lines: Vec<String> = (0..20_000)
+        .map(|idx| match idx % 3 {
+            0 => json!({"type": "a", "level": "info", "size": idx}).to_string(),
+            1 => json!({"type": "b", "timestamp": "2024-01-01T00:00:00Z"}).to_string(),
+            _ => json!({"type": "a", "size": "big"}).to_string(),
+        })
+        .collect();
+    let input = fixture.lines("input.log", &lines);
+    let options = ParseOptions {
+        numeric_fields: vec!["size".to_string()],
+        timestamp_field: Some("timestamp".to_string()),
+        ..ParseOptions::default()
+    };
+    let report = parser::single_thread_parser(&input, &options).unwrap();
+    let path = fixture.path("results.parquet");
+    let mut sink = sink::open_sink(&format!("file://{}", path)).unwrap();
+    sink.write_report(&input, &report, &options).unwrap();
+    let records: Vec<ParsedRecord> = parser::stream(1, &input, &options)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    for record in &records {
+        sink.write_record(record, &options).unwrap();
+    }
+    sink.finish().unwrap();
+
+    let stats = parquet::read(&path).unwrap();
+    assert_eq!(
+        stats[0],
+        (
+            "log_type".to_string(),
+            Column::Utf8(vec!["a".to_string(), "b".to_string()])
+        )
+    );
+    let records_path = fixture.path("results.records.parquet");
+    assert_eq!(parquet::records_path(&path), records_path);
+    let columns = parquet::read(&records_path).unwrap();
+    let names: Vec<&str> = columns.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "offset",
+            "log_type",
+            "level",
+            "num_of_bytes",
+            "timestamp",
+            "fields"
+        ]
+    );
+    let mut offset = 0;
+    let mut expected = (vec![], vec![], vec![], vec![], vec![], vec![]);
+    for (idx, line) in lines.iter().enumerate() {
+        expected.0.push(offset);
+        offset += line.len() as i64 + 1;
+        expected.3.push(line.len() as i64 + 1);
+        match idx % 3 {
+            0 => {
+                expected.1.push("a".to_string());
+                expected.2.push(Some("info".to_string()));
+                expected.4.push(None);
+                expected.5.push(format!("{{\"size\":{:.1}}}", idx as f64));
+            }
+            1 => {
+                expected.1.push("b".to_string());
+                expected.2.push(None);
+                expected
+                    .4
+                    .push(Some("2024-01-01T00:00:00.000Z".to_string()));
+                expected.5.push("{}".to_string());
+            }
+            _ => {
+                expected.1.push("a".to_string());
+                expected.2.push(None);
+                expected.4.push(None);
+                expected.5.push("{}".to_string());
+            }
+        }
+    }
+    assert_eq!(columns[0].1, Column::Int64(expected.0));
+    assert_eq!(columns[1].1, Column::Utf8(expected.1));
+    assert_eq!(columns[2].1, Column::OptionalUtf8(expected.2));
+    assert_eq!(columns[3].1, Column::Int64(expected.3));
+    assert_eq!(columns[4].1, Column::OptionalUtf8(expected.4));
+    assert_eq!(columns[5].1, Column::Utf8(expected.5));
+
+    // no records, no records file
+    let path = fixture.path("stats.parquet");
+    let mut sink = sink::open_sink(&format!("file://{}", path)).unwrap();
+    sink.write_report(&input, &report, &options).unwrap();
+    sink.finish().unwrap();
+    assert!(Path::new(&path).exists());
+    assert!(!Path::new(&parquet::records_path(&path)).exists());
+}
+
+#[test]
+fn results_sinks_are_opened_by_uri() {
+    let fixture = Fixture::new("sinks");
+    let input = fixture.lines(
+        "input.log",
+        [r#"{"type":"a"}"#, r#"{"type":"b"}"#, r#"{"type":"a"}"#],
+    );
+    let options = ParseOptions::default();
+    let report = parser::single_thread_parser(&input, &options).unwrap();
+    let records: Vec<ParsedRecord> = parser::stream(1, &input, &options)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let write = |uri: &str| {
+        let mut sink = sink::open_sink(uri).unwrap();
+        sink.write_report(&input, &report, &options).unwrap();
+        for record in &records {
+            sink.write_record(record, &options).unwrap();
+        }
+        sink.finish().unwrap();
+    };
+    let kinds = |ndjson: &str| -> Vec<(String, String)> {
+        ndjson
+            .lines()
+            .map(|line| {
+                let line: Value = serde_json::from_str(line).unwrap();
+                let log_type = line["log_type"].as_str().unwrap_or_default();
+                (
+                    line["kind"].as_str().unwrap().to_string(),
+                    log_type.to_string(),
+                )
+            })
+            .collect()
+    };
+    let expected: Vec<(String, String)> = [
+        ("run", ""),
+        ("log_type", "a"),
+        ("log_type", "b"),
+        ("record", "a"),
+        ("record", "b"),
+        ("record", "a"),
+    ]
+    .iter()
+    .map(|(kind, log_type)| (kind.to_string(), log_type.to_string()))
+    .collect();
+
+    let path = fixture.path("results.jsonl");
+    write(&format!("file://{}", path));
+    assert_eq!(kinds(&fs::read_to_string(&path).unwrap()), expected);
+
+    // the report is POSTed right away, records once the sink finishes
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let uri = format!("http://{}/results", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let mut bodies = vec![];
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = io::BufReader::new(stream);
+            let (head, body) = read_http_request(&mut reader);
+            assert_eq!(head[0], "POST /results HTTP/1.1\r\n");
+            let response = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            bodies.push(String::from_utf8(body).unwrap());
+        }
+        bodies
+    });
+    write(&uri);
+    let bodies = server.join().unwrap();
+    assert_eq!(kinds(&bodies[0]), expected[..3]);
+    assert_eq!(kinds(&bodies[1]), expected[3..]);
+
+    // the report is a message, every record another one
+    #[cfg(feature = "kafka")]
+    {
+        use rdkafka::consumer::{BaseConsumer, Consumer};
+        use rdkafka::message::Message;
+        use rdkafka::mocking::MockCluster;
+        use rdkafka::{ClientConfig, Offset, TopicPartitionList};
+
+        let cluster = MockCluster::new(1).unwrap();
+        cluster.create_topic("results", 1, 1).unwrap();
+        write(&format!("kafka://{}/results", cluster.bootstrap_servers()));
+
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", cluster.bootstrap_servers())
+            .set("group.id", "results")
+            .create()
+            .unwrap();
+        let mut partitions = TopicPartitionList::new();
+        partitions
+            .add_partition_offset("results", 0, Offset::Beginning)
+            .unwrap();
+        consumer.assign(&partitions).unwrap();
+        let mut messages = vec![];
+        while messages.len() < 4 {
+            let message = consumer.poll(Duration::from_secs(10)).unwrap().unwrap();
+            messages.push(String::from_utf8(message.payload().unwrap().to_vec()).unwrap());
+        }
+        assert_eq!(kinds(&messages[0]), expected[..3]);
+        assert_eq!(kinds(&messages[1..].join("\n")), expected[3..]);
+    }
+    #[cfg(not(feature = "kafka"))]
+    assert!(sink::open_sink("kafka://broker/topic")
+        .err()
+        .unwrap()
+        .contains("--features kafka"));
+
+    for unknown in [
+        "ftp://host/results",
+        "results.jsonl",
+        "kafka://broker",
+        "kafka:///topic",
+    ] {
+        assert!(sink::open_sink(unknown).is_err(), "{}", unknown);
+    }
+}
+
+#[test]
+fn pretty_json_sink_holds_the_lines_of_the_jsonl_sink() {
+    let fixture = Fixture::new("pretty-sink");
+    let input = fixture.lines(
+        "input.log",
+        [
+            r#"{"type":"a","duration_ms":5}"#,
+            r#"{"type":"b","duration_ms":"7"}"#,
+            r#"{"type":"a","message":"line\nbreak"}"#,
+        ],
+    );
+    let options = ParseOptions {
+        numeric_fields: vec!["duration_ms".to_string()],
+        ..ParseOptions::default()
+    };
+    let report = parser::single_thread_parser(&input, &options).unwrap();
+    let records: Vec<ParsedRecord> = parser::stream(1, &input, &options)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let write = |name: &str, records: &[ParsedRecord]| {
+        let path = fixture.path(name);
+        let mut sink = sink::open_sink(&format!("file://{}", path)).unwrap();
+        sink.write_report(&input, &report, &options).unwrap();
+        for record in records {
+            sink.write_record(record, &options).unwrap();
+        }
+        sink.finish().unwrap();
+        fs::read_to_string(path).unwrap()
+    };
+    // the creation time is the only difference of runs
+    let without_time = |mut line: Value| {
+        line.as_object_mut().unwrap().remove("created_at");
+        line
+    };
+
+    for records in [&records[..], &[]] {
+        let lines: Vec<Value> = write("results.jsonl", records)
+            .lines()
+            .map(|line| {
+                let mut line: Value = serde_json::from_str(line).unwrap();
+                line.as_object_mut().unwrap().remove("kind");
+                line
+            })
+            .collect();
+        let pretty = write("results.json", records);
+        assert!(pretty.lines().count() > lines.len(), "{}", pretty);
+        let mut document: Value = serde_json::from_str(&pretty).unwrap();
+        let fields = document.as_object_mut().unwrap();
+        let log_types = fields.remove("log_types").unwrap();
+        let written = fields.remove("records");
+
+        assert_eq!(without_time(document), without_time(lines[0].clone()));
+        assert_eq!(log_types.as_array().unwrap()[..], lines[1..3]);
+        match records.is_empty() {
+            true => assert_eq!(written, None),
+            false => assert_eq!(written.unwrap().as_array().unwrap()[..], lines[3..]),
+        }
+    }
+}
+
+#[test]
+fn redis_sink_increments_counters_of_log_types() {
+    let fixture = Fixture::new("redis");
+    let input = fixture.lines(
+        "input.log",
+        [r#"{"type":"a"}"#, r#"{"type":"a"}"#, r#"{"type":"b"}"#],
+    );
+    let report = parser::single_thread_parser(&input, &ParseOptions::default()).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut commands = vec![];
+        while let Some(command) = read_redis_command(&mut reader) {
+            commands.push(command.join(" "));
+            writer.write_all(b":1\r\n").unwrap();
+        }
+        commands
+    });
+
+    let uri = format!("redis://127.0.0.1:{}?prefix=app&ttl=1m", port);
+    let mut sink = sink::open_sink(&uri).unwrap();
+    sink.write_report(&input, &report, &ParseOptions::default())
+        .unwrap();
+    sink.finish().unwrap();
+
+    let commands = server.join().unwrap();
+    for expected in [
+        "HINCRBY app:totals lines 3",
+        "HINCRBY app:totals failed_lines 0",
+        "HINCRBY app:counter a 2",
+        "HINCRBY app:counter b 1",
+        "HINCRBY app:bytes a 26",
+        "ZINCRBY app:by_counter 2 a",
+        "EXPIRE app:by_bytes 60",
+    ] {
+        assert!(
+            commands.iter().any(|command| command == expected),
+            "{} not in {:?}",
+            expected,
+            commands
+        );
+    }
+}
+
+/// Client end of a fake broker: the accepted connection, failing reads instead of hanging.
+#[cfg(any(feature = "nats", feature = "amqp", feature = "mqtt"))]
+fn accept_client(listener: &TcpListener) -> (io::BufReader<TcpStream>, TcpStream) {
+    let (stream, _) = listener.accept().unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    (io::BufReader::new(stream.try_clone().unwrap()), stream)
+}
+
+/// Next protocol line of a NATS client starting with the prefix, without `\r\n`.
+#[cfg(feature = "nats")]
+fn nats_line<R: BufRead>(reader: &mut R, prefix: &str) -> String {
+    loop {
+        let mut line = String::new();
+        assert!(
+            reader.read_line(&mut line).unwrap() > 0,
+            "{} not sent",
+            prefix
+        );
+        if line.starts_with(prefix) {
+            return line.trim_end().to_string();
+        }
+    }
+}
+
+#[cfg(feature = "nats")]
+#[test]
+fn nats_messages_round_trip() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut reader, mut writer) = accept_client(&listener);
+        writer
+            .write_all(b"INFO {\"server_id\":\"test\"}\r\n")
+            .unwrap();
+        assert!(nats_line(&mut reader, "CONNECT").contains("\"headers\":true"));
+        nats_line(&mut reader, "PING");
+        writer.write_all(b"+OK\r\nPONG\r\n").unwrap();
+        assert_eq!(nats_line(&mut reader, "SUB"), "SUB logs.> 1");
+        // pings of the server are answered while messages are read
+        writer
+            .write_all(b"PING\r\nMSG logs.api 1 reply.1 12\r\n{\"type\":\"a\"}\r\n")
+            .unwrap();
+        nats_line(&mut reader, "PONG");
+        writer
+            .write_all(b"HMSG logs.api 1 28 28\r\nNATS/1.0 404 No Messages\r\n\r\n\r\n")
+            .unwrap();
+        assert_eq!(nats_line(&mut reader, "PUB"), "PUB stats 2");
+        nats_line(&mut reader, "{}");
+    });
+
+    let mut connection = nats::Connection::connect("127.0.0.1", port).unwrap();
+    connection.subscribe("logs.>").unwrap();
+    let timeout = Duration::from_secs(10);
+    let message = connection.next_message(timeout).unwrap().unwrap();
+    assert_eq!(
+        message,
+        nats::Message {
+            subject: "logs.api".to_string(),
+            reply: Some("reply.1".to_string()),
+            status: None,
+            payload: b"{\"type\":\"a\"}".to_vec(),
+        }
+    );
+    let status = connection.next_message(timeout).unwrap().unwrap();
+    assert_eq!((status.status, status.payload), (Some(404), vec![]));
+    connection.publish("stats", b"{}").unwrap();
+    server.join().unwrap();
+    assert!(connection.next_message(timeout).is_err());
+}
+
+#[cfg(feature = "nats")]
+#[test]
+fn nats_messages_are_acked_once_their_snapshot_is_written() {
+    let fixture = Fixture::new("nats");
+    let snapshot = fixture.path("snapshot.jsonl");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("nats://127.0.0.1:{}", listener.local_addr().unwrap().port());
+    let mut parser = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args(["nats", "--url", &url, "--consumer", "LOGS/parser"])
+        .args(["--snapshot-interval", "200ms", "--sink"])
+        .arg(format!("file://{}", snapshot))
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let (mut reader, mut writer) = accept_client(&listener);
+    writer.write_all(b"INFO {}\r\n").unwrap();
+    nats_line(&mut reader, "PING");
+    writer.write_all(b"PONG\r\n").unwrap();
+    let inbox = nats_line(&mut reader, "SUB")
+        .split(' ')
+        .nth(1)
+        .unwrap()
+        .to_string();
+    let request = nats_line(&mut reader, "PUB $JS.API.CONSUMER.MSG.NEXT.LOGS.parser");
+    assert!(request.contains(&inbox), "{}", request);
+    writer
+        .write_all(
+            format!(
+                "MSG {} 1 $JS.ACK.LOGS.parser.1 12\r\n{{\"type\":\"a\"}}\r\n",
+                inbox
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    assert_eq!(
+        nats_line(&mut reader, "PUB $JS.ACK"),
+        "PUB $JS.ACK.LOGS.parser.1 4"
+    );
+    let written = fs::read_to_string(&snapshot).unwrap_or_default();
+    parser.kill().unwrap();
+    parser.wait().unwrap();
+    assert!(written.contains("\"log_type\":\"a\""), "{}", written);
+}
+
+#[cfg(feature = "amqp")]
+fn amqp_frame<W: Write>(writer: &mut W, kind: u8, channel: u16, payload: &[u8]) {
+    writer.write_all(&[kind]).unwrap();
+    writer.write_all(&channel.to_be_bytes()).unwrap();
+    writer
+        .write_all(&(payload.len() as u32).to_be_bytes())
+        .unwrap();
+    writer.write_all(payload).unwrap();
+    writer.write_all(&[0xce]).unwrap();
+}
+
+#[cfg(feature = "amqp")]
+fn amqp_method<W: Write>(writer: &mut W, channel: u16, (class, method): (u16, u16), args: &[u8]) {
+    let mut payload = [class.to_be_bytes(), method.to_be_bytes()].concat();
+    payload.extend(args);
+    amqp_frame(writer, 1, channel, &payload);
+}
+
+/// Next frame of an AMQP client as its kind, channel and payload.
+#[cfg(feature = "amqp")]
+fn read_amqp_frame<R: Read>(reader: &mut R) -> (u8, u16, Vec<u8>) {
+    let mut header = [0; 7];
+    reader.read_exact(&mut header).unwrap();
+    let size = u32::from_be_bytes([header[3], header[4], header[5], header[6]]) as usize;
+    let mut payload = vec![0; size + 1];
+    reader.read_exact(&mut payload).unwrap();
+    assert_eq!(payload.pop(), Some(0xce));
+    (
+        header[0],
+        u16::from_be_bytes([header[1], header[2]]),
+        payload,
+    )
+}
+
+/// Arguments of the next method of an AMQP client, which should be the expected one.
+#[cfg(feature = "amqp")]
+fn expect_amqp_method<R: Read>(reader: &mut R, channel: u16, expected: (u16, u16)) -> Vec<u8> {
+    let (kind, frame_channel, payload) = read_amqp_frame(reader);
+    let id = (
+        u16::from_be_bytes([payload[0], payload[1]]),
+        u16::from_be_bytes([payload[2], payload[3]]),
+    );
+    assert_eq!((kind, frame_channel, id), (1, channel, expected));
+    payload[4..].to_vec()
+}
+
+/// Fake broker accepting a consumer of the queue, with the connection it consumes on.
+#[cfg(feature = "amqp")]
+fn accept_amqp_consumer(
+    listener: &TcpListener,
+    queue: &str,
+    prefetch: u16,
+) -> (io::BufReader<TcpStream>, TcpStream) {
+    let (mut reader, mut writer) = accept_client(listener);
+    let mut protocol = [0; 8];
+    reader.read_exact(&mut protocol).unwrap();
+    assert_eq!(&protocol, b"AMQP\x00\x00\x09\x01");
+    amqp_method(&mut writer, 0, (10, 10), &[0, 9, 0, 0, 0, 0, 0, 0, 0, 0]);
+    let start_ok = expect_amqp_method(&mut reader, 0, (10, 11));
+    assert!(start_ok
+        .windows(12)
+        .any(|bytes| bytes == b"\x00guest\x00guest"));
+    let tune = [0, 0, 0, 2, 0, 0, 0, 60];
+    amqp_method(&mut writer, 0, (10, 30), &tune);
+    assert_eq!(expect_amqp_method(&mut reader, 0, (10, 31)), tune);
+    assert_eq!(expect_amqp_method(&mut reader, 0, (10, 40))[..2], [1, b'/']);
+    amqp_method(&mut writer, 0, (10, 41), &[0]);
+    expect_amqp_method(&mut reader, 1, (20, 10));
+    amqp_method(&mut writer, 1, (20, 11), &[0, 0, 0, 0]);
+    let qos = expect_amqp_method(&mut reader, 1, (60, 10));
+    assert_eq!(qos[4..6], prefetch.to_be_bytes());
+    amqp_method(&mut writer, 1, (60, 11), &[]);
+    let consume = expect_amqp_method(&mut reader, 1, (60, 20));
+    assert_eq!(consume[3..3 + queue.len()], *queue.as_bytes());
+    amqp_method(&mut writer, 1, (60, 21), b"\x04ctag");
+    (reader, writer)
+}
+
+/// Deliver message with the tag to the consumer: deliver method, content header and body.
+#[cfg(feature = "amqp")]
+fn amqp_deliver<W: Write>(writer: &mut W, tag: u64, redelivered: bool, body: &[u8]) {
+    let mut deliver = b"\x04ctag".to_vec();
+    deliver.extend(&tag.to_be_bytes());
+    deliver.push(redelivered as u8);
+    deliver.extend(b"\x00\x04logs");
+    amqp_method(writer, 1, (60, 60), &deliver);
+    let mut header = vec![0, 60, 0, 0];
+    header.extend(&(body.len() as u64).to_be_bytes());
+    header.extend(&[0, 0]);
+    amqp_frame(writer, 2, 1, &header);
+    if !body.is_empty() {
+        amqp_frame(writer, 3, 1, body);
+    }
+}
+
+#[cfg(feature = "amqp")]
+#[test]
+fn amqp_deliveries_round_trip() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut reader, mut writer) = accept_amqp_consumer(&listener, "app-logs", 10);
+        // heartbeats of the broker are answered
+        amqp_frame(&mut writer, 8, 0, &[]);
+        assert_eq!(read_amqp_frame(&mut reader), (8, 0, vec![]));
+        amqp_deliver(&mut writer, 7, true, b"{\"type\":\"a\"}");
+        amqp_deliver(&mut writer, 8, false, b"");
+        let ack = expect_amqp_method(&mut reader, 1, (60, 80));
+        assert_eq!(ack, [0, 0, 0, 0, 0, 0, 0, 7, 0]);
+        amqp_method(
+            &mut writer,
+            1,
+            (20, 40),
+            b"\x01\x94\x09NOT_FOUND\x00\x00\x00\x00",
+        );
+    });
+
+    let url = AmqpUrl::parse(&format!("amqp://127.0.0.1:{}", port)).unwrap();
+    let consumer = amqp::Consumer::connect(&url, "app-logs", 10).unwrap();
+    let timeout = Duration::from_secs(10);
+    let delivery = consumer.next_delivery(timeout).unwrap().unwrap();
+    assert_eq!(
+        delivery,
+        amqp::Delivery {
+            delivery_tag: 7,
+            routing_key: "logs".to_string(),
+            redelivered: true,
+            body: b"{\"type\":\"a\"}".to_vec(),
+        }
+    );
+    let empty = consumer.next_delivery(timeout).unwrap().unwrap();
+    assert_eq!((empty.delivery_tag, empty.body), (8, vec![]));
+    consumer.ack(&delivery).unwrap();
+    server.join().unwrap();
+    let err = consumer.next_delivery(timeout).unwrap_err();
+    assert!(err.to_string().contains("404 NOT_FOUND"), "{}", err);
+}
+
+#[cfg(feature = "amqp")]
+#[test]
+fn amqp_messages_are_acked_once_their_snapshot_is_written() {
+    let fixture = Fixture::new("amqp");
+    let snapshot = fixture.path("snapshot.jsonl");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("amqp://127.0.0.1:{}", listener.local_addr().unwrap().port());
+    let mut parser = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args([
+            "amqp",
+            "--url",
+            &url,
+            "--queue",
+            "app-logs",
+            "--prefetch",
+            "5",
+        ])
+        .args(["--snapshot-interval", "200ms", "--sink"])
+        .arg(format!("file://{}", snapshot))
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let (mut reader, mut writer) = accept_amqp_consumer(&listener, "app-logs", 5);
+    amqp_deliver(&mut writer, 1, false, b"{\"type\":\"a\"}");
+
+    let ack = expect_amqp_method(&mut reader, 1, (60, 80));
+    let written = fs::read_to_string(&snapshot).unwrap_or_default();
+    parser.kill().unwrap();
+    parser.wait().unwrap();
+    assert_eq!(ack, [0, 0, 0, 0, 0, 0, 0, 1, 0]);
+    assert!(written.contains("\"log_type\":\"a\""), "{}", written);
+}
+
+#[cfg(feature = "mqtt")]
+fn mqtt_packet<W: Write>(writer: &mut W, kind: u8, body: &[u8]) {
+    // remaining lengths of test packets fit a byte
+    assert!(body.len() < 128);
+    writer.write_all(&[kind, body.len() as u8]).unwrap();
+    writer.write_all(body).unwrap();
+}
+
+/// Next packet of an MQTT client as its first byte and body.
+#[cfg(feature = "mqtt")]
+fn read_mqtt_packet<R: Read>(reader: &mut R) -> (u8, Vec<u8>) {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header).unwrap();
+    assert!(header[1] < 128, "long packet {:?}", header);
+    let mut body = vec![0; header[1] as usize];
+    reader.read_exact(&mut body).unwrap();
+    (header[0], body)
+}
+
+/// Fake broker accepting a subscriber of the topic filter, with the connection it subscribed on.
+#[cfg(feature = "mqtt")]
+fn accept_mqtt_subscriber(
+    listener: &TcpListener,
+    topic_filter: &str,
+    qos: u8,
+) -> (io::BufReader<TcpStream>, TcpStream) {
+    let (mut reader, mut writer) = accept_client(listener);
+    let (kind, connect) = read_mqtt_packet(&mut reader);
+    assert_eq!((kind, &connect[..7]), (0x10, &b"\x00\x04MQTT\x04"[..]));
+    mqtt_packet(&mut writer, 0x20, &[0, 0]);
+    let mut subscribe = vec![0, 1, 0, topic_filter.len() as u8];
+    subscribe.extend(topic_filter.as_bytes());
+    subscribe.push(qos);
+    assert_eq!(read_mqtt_packet(&mut reader), (0x82, subscribe));
+    mqtt_packet(&mut writer, 0x90, &[0, 1, qos]);
+    (reader, writer)
+}
+
+/// Publish the payload to the subscriber, with a packet id unless it's QoS 0.
+#[cfg(feature = "mqtt")]
+fn mqtt_publish<W: Write>(writer: &mut W, qos: u8, packet_id: u16, payload: &[u8]) {
+    let mut body = b"\x00\x0edevices/1/logs".to_vec();
+    if qos > 0 {
+        body.extend(&packet_id.to_be_bytes());
+    }
+    body.extend(payload);
+    mqtt_packet(writer, 0x30 | qos << 1, &body);
+}
+
+#[cfg(feature = "mqtt")]
+#[test]
+fn mqtt_messages_round_trip() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut reader, mut writer) = accept_mqtt_subscriber(&listener, "devices/+/logs", 2);
+        mqtt_publish(&mut writer, 0, 0, b"{\"type\":\"a\"}");
+        mqtt_publish(&mut writer, 1, 5, b"{\"type\":\"b\"}");
+        mqtt_publish(&mut writer, 2, 6, b"");
+        assert_eq!(read_mqtt_packet(&mut reader), (0x40, vec![0, 5]));
+        assert_eq!(read_mqtt_packet(&mut reader), (0x50, vec![0, 6]));
+        // QoS 2 deliveries are completed
+        mqtt_packet(&mut writer, 0x62, &[0, 6]);
+        assert_eq!(read_mqtt_packet(&mut reader), (0x70, vec![0, 6]));
+
+        // refused connections are errors
+        let (mut reader, mut writer) = accept_client(&listener);
+        read_mqtt_packet(&mut reader);
+        mqtt_packet(&mut writer, 0x20, &[0, 5]);
+    });
+
+    let url = MqttUrl::parse(&format!("mqtt://127.0.0.1:{}", port)).unwrap();
+    let session = mqtt::Session {
+        client_id: "parser".to_string(),
+        clean: false,
+        keep_alive: Duration::ZERO,
+    };
+    let topic_filters = ["devices/+/logs".to_string()];
+    let subscriber = mqtt::Subscriber::connect(&url, &session, &topic_filters, 2).unwrap();
+    let timeout = Duration::from_secs(10);
+    let messages: Vec<_> = (0..3)
+        .map(|_| subscriber.next_message(timeout).unwrap().unwrap())
+        .collect();
+    assert_eq!(
+        messages[1],
+        mqtt::Message {
+            topic: "devices/1/logs".to_string(),
+            qos: 1,
+            packet_id: Some(5),
+            payload: b"{\"type\":\"b\"}".to_vec(),
+        }
+    );
+    let received: Vec<_> = messages
+        .iter()
+        .map(|message| (message.qos, message.packet_id, message.payload.len()))
+        .collect();
+    assert_eq!(received, [(0, None, 12), (1, Some(5), 12), (2, Some(6), 0)]);
+    for message in &messages {
+        subscriber.ack(message).unwrap();
+    }
+
+    let refused = mqtt::Subscriber::connect(&url, &session, &topic_filters, 2);
+    server.join().unwrap();
+    assert!(subscriber.next_message(timeout).is_err());
+    let err = refused.err().unwrap();
+    assert!(err.to_string().contains("not authorized"), "{}", err);
+}
+
+#[cfg(feature = "mqtt")]
+#[test]
+fn mqtt_messages_are_acked_once_their_snapshot_is_written() {
+    let fixture = Fixture::new("mqtt");
+    let snapshot = fixture.path("snapshot.jsonl");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("mqtt://127.0.0.1:{}", listener.local_addr().unwrap().port());
+    let mut parser = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args([
+            "mqtt",
+            "--url",
+            &url,
+            "--topic",
+            "devices/+/logs",
+            "--qos",
+            "1",
+        ])
+        .args(["--snapshot-interval", "200ms", "--sink"])
+        .arg(format!("file://{}", snapshot))
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let (mut reader, mut writer) = accept_mqtt_subscriber(&listener, "devices/+/logs", 1);
+    mqtt_publish(&mut writer, 1, 1, b"{\"type\":\"a\"}");
+
+    let ack = loop {
+        match read_mqtt_packet(&mut reader) {
+            // pings of the keep alive
+            (0xc0, _) => {}
+            packet => break packet,
+        }
+    };
+    let written = fs::read_to_string(&snapshot).unwrap_or_default();
+    parser.kill().unwrap();
+    parser.wait().unwrap();
+    assert_eq!(ack, (0x40, vec![0, 1]));
+    assert!(written.contains("\"log_type\":\"a\""), "{}", written);
+}
+
+/// Value of a numeric field: a number, a number written as text, text or no value.
+fn numeric_values() -> impl Strategy<Value = Vec<(u8, Option<Value>)>> {
+    let value = prop_oneof![
+        (-1000..1000i64).prop_map(|value| Some(json!(value))),
+        (-1000..1000i64).prop_map(|value| Some(json!(format!(" {}.5", value)))),
+        Just(Some(json!("n/a"))),
+        Just(None),
+    ];
+    prop::collection::vec((0..3u8, value), 0..200)
+}
+
+#[test]
+fn numeric_fields_sum_and_average_their_numbers() {
+    let options = ParseOptions {
+        numeric_fields: vec!["ms".to_string()],
+        ..ParseOptions::default()
+    };
+    check(
+        (numeric_values(), threads(), strategies()),
+        |(values, num_of_threads, strategy)| {
+            let fixture = Fixture::new("numeric");
+            let mut expected: HashMap<String, (f64, u64)> = HashMap::new();
+            let mut lines = vec![];
+            for (log_type, value) in values {
+                let mut log = json!({"type": log_type.to_string()});
+                let number = match &value {
+                    Some(Value::Number(number)) => number.as_f64(),
+                    Some(Value::String(text)) => text.trim().parse().ok(),
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    log["ms"] = value;
+                }
+                let (sum, count) = expected.entry(log_type.to_string()).or_default();
+                if let Some(number) = number {
+                    *sum += number;
+                    *count += 1;
+                }
+                lines.push(log.to_string());
+            }
+            let input = fixture.lines("input.log", lines);
+
+            // halves add up exactly in any order
+            let report = parser::parse_file(strategy, num_of_threads, &input, &options).unwrap();
+            for (log_type, (sum, count)) in &expected {
+                let field = &report.log_register[log_type].fields[0];
+                assert_eq!((field.sum, field.count), (*sum, *count));
+                if *count > 0 {
+                    assert_eq!(field.avg(), sum / *count as f64);
+                }
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn line_size_stats_are_exact_or_within_their_accuracy() {
+    let options = parse_options();
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("line-sizes", &sample_options);
+            let report =
+                parser::multi_thread_parser_sharded(num_of_threads, corpus.path(), &options)
+                    .unwrap();
+
+            let mut sizes: HashMap<String, Vec<u64>> = HashMap::new();
+            for line in fs::read_to_string(corpus.path()).unwrap().lines() {
+                let log: Value = serde_json::from_str(line).unwrap();
+                let log_type = log["type"].as_str().unwrap().to_string();
+                sizes
+                    .entry(log_type)
+                    .or_default()
+                    .push(line.len() as u64 + 1);
+            }
+            for (log_type, mut sizes) in sizes {
+                sizes.sort();
+                let stats = &report.log_register[&log_type].line_size;
+                assert_eq!(stats.count(), sizes.len() as u64);
+                assert_eq!(stats.min(), sizes[0]);
+                assert_eq!(stats.max(), sizes[sizes.len() - 1]);
+                let mean = sizes.iter().sum::<u64>() as f64 / sizes.len() as f64;
+                assert!((stats.mean() - mean).abs() < 1e-9);
+                for (quantile, percentile) in
+                    [(0.5, stats.p50()), (0.95, stats.p95()), (0.99, stats.p99())]
+                {
+                    let rank = (quantile * (sizes.len() - 1) as f64).round() as usize;
+                    let exact = sizes[rank] as f64;
+                    assert!((percentile as f64 - exact).abs() <= exact * 0.01 + 1.0);
+                }
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn sampling_rates_fit_the_target_without_dropping_errors() {
+    let options = parse_options();
+    check(
+        (sample_options(), 1..100_000u64),
+        |(sample_options, target_daily_bytes)| {
+            let corpus = Corpus::generate("sampling", &sample_options);
+            let report = parser::single_thread_parser(corpus.path(), &options).unwrap();
+            let registers = report
+                .log_register
+                .iter()
+                .map(|(log_type, register)| (log_type.as_str(), register));
+            // the input covers a day, so daily bytes are the bytes of the input
+            let day = Duration::from_secs(24 * 60 * 60);
+            let policy = sampling::recommend(registers, day, target_daily_bytes);
+
+            let observed: u64 = report.log_register.values().map(|r| r.num_of_bytes).sum();
+            assert_eq!(policy.observed_daily_bytes, observed);
+            let mut floor = 0.0;
+            for rate in &policy.rates {
+                let register = &report.log_register[&rate.log_type];
+                assert!(rate.sample_rate <= 1.0);
+                assert!(rate.sample_rate >= register.error_ratio());
+                floor += register.num_of_bytes as f64 * register.error_ratio();
+                if observed <= target_daily_bytes {
+                    assert_eq!(rate.sample_rate, 1.0);
+                }
+            }
+            // only error logs kept in full can make the target unreachable
+            if floor <= target_daily_bytes as f64 {
+                assert!(policy.target_reached, "{:?}", policy);
+                assert!(policy.sampled_daily_bytes <= target_daily_bytes + 1);
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn retention_keeps_the_newest_part_of_the_window() {
+    let corpus = Corpus::generate("retention", &sample(0));
+    let report = parser::single_thread_parser(corpus.path(), &parse_options()).unwrap();
+    let mut log_types: Vec<&String> = report.log_register.keys().collect();
+    log_types.sort();
+    let (kept, unmatched) = (log_types[0], log_types[1]);
+    let rules: Vec<RetentionRule> = [format!("{}=12h", kept), "*=1d".to_string()]
+        .iter()
+        .map(|rule| RetentionRule::parse(rule).unwrap())
+        .collect();
+    assert!(RetentionRule::parse("=1d").is_err());
+    assert!(RetentionRule::parse("debug").is_err());
+
+    // the input covers 2 days: 12h keep a quarter of the bytes, the default rule a half
+    let window = Duration::from_secs(2 * 24 * 60 * 60);
+    let registers = report
+        .log_register
+        .iter()
+        .map(|(log_type, register)| (log_type.as_str(), register));
+    let simulation = retention::simulate(registers, window, &rules);
+    for retained in &simulation.types {
+        let bytes = report.log_register[&retained.log_type].num_of_bytes;
+        let (retention, kept_part) = match &retained.log_type {
+            log_type if log_type == kept => (12 * 3600, 0.25),
+            _ => (24 * 3600, 0.5),
+        };
+        assert_eq!(retained.retention_secs, Some(retention));
+        assert_eq!(retained.num_of_bytes, bytes);
+        assert_eq!(retained.remaining_bytes, (bytes as f64 * kept_part) as u64);
+        assert_eq!(retained.saved_bytes, bytes - retained.remaining_bytes);
+        assert_eq!(retained.steady_state_bytes, Some(retained.remaining_bytes));
+    }
+    let total: u64 = report.log_register.values().map(|r| r.num_of_bytes).sum();
+    assert_eq!(simulation.num_of_bytes, total);
+    assert_eq!(simulation.remaining_bytes + simulation.saved_bytes, total);
+
+    // without the default rule other log types are kept forever
+    let registers = report
+        .log_register
+        .iter()
+        .map(|(log_type, register)| (log_type.as_str(), register));
+    let simulation = retention::simulate(registers, window, &rules[..1]);
+    let retained = simulation
+        .types
+        .iter()
+        .find(|retained| &retained.log_type == unmatched)
+        .unwrap();
+    assert_eq!(retained.retention_secs, None);
+    assert_eq!(retained.saved_bytes, 0);
+}
+
+#[test]
+fn drift_reports_fields_missing_from_the_schema_by_first_occurrence() {
+    let fixture = Fixture::new("drift");
+    let mut lines = vec![];
+    for idx in 0..300 {
+        let log_type = ["a", "b"][idx % 2];
+        let mut log = json!({"type": log_type, "ts": 1_700_000_000 + idx});
+        if idx >= 100 && idx % 10 == 0 {
+            log["user"] = json!("u");
+        }
+        if idx >= 40 && idx % 20 == 5 {
+            log["trace_id"] = json!(idx);
+        }
+        lines.push(log.to_string());
+    }
+    let input = fixture.lines("input.log", lines);
+    let schema = fixture.lines(
+        "schema.json",
+        [r#"{"properties": {"type": {}, "ts": {}, "message": {}}}"#],
+    );
+    let schema = ExpectedSchema::load(&schema).unwrap();
+    assert!(schema.contains("message") && !schema.contains("user"));
+    let array = fixture.lines("array.json", [r#"["type", 7]"#]);
+    assert!(ExpectedSchema::load(&array).is_err());
+
+    let options = ParseOptions {
+        timestamp_field: Some("ts".to_string()),
+        collect_field_names: true,
+        ..ParseOptions::default()
+    };
+    let mut detector = DriftDetector::new(schema);
+    for record in parser::stream(3, &input, &options).unwrap() {
+        detector.observe(&record.unwrap());
+    }
+    let unexpected = detector.report();
+
+    let fields: Vec<&str> = unexpected
+        .iter()
+        .map(|field| field.field.as_str())
+        .collect();
+    assert_eq!(fields, ["trace_id", "user"]);
+    let (trace_id, user) = (&unexpected[0], &unexpected[1]);
+    assert_eq!(trace_id.count, 13);
+    assert_eq!(trace_id.log_types, BTreeSet::from(["b".to_string()]));
+    assert_eq!(
+        trace_id.first_timestamp,
+        Some(UNIX_EPOCH + Duration::from_secs(1_700_000_045))
+    );
+    assert_eq!(user.count, 20);
+    assert_eq!(user.log_types, BTreeSet::from(["a".to_string()]));
+    let offset: usize = fs::read_to_string(&input)
+        .unwrap()
+        .lines()
+        .take(100)
+        .map(|line| line.len() + 1)
+        .sum();
+    assert_eq!(user.first_offset, offset as u64);
+}
+
+#[test]
+fn producers_deviating_from_the_fleet_are_flagged() {
+    let fixture = Fixture::new("producers");
+    let mut lines = vec![];
+    for idx in 0..200 {
+        let host = ["a", "b", "c", "d", "e"][idx % 5];
+        let mut log = json!({"type": "http", "host": host, "level": "info", "msg": "ok"});
+        // the last host logs errors with a field the rest of the fleet doesn't have
+        if host == "e" {
+            log["level"] = json!("error");
+            log["stack"] = json!("at main");
+        }
+        lines.push(log.to_string());
+    }
+    let input = fixture.lines("input.log", lines);
+    let options = ParseOptions {
+        string_fields: vec!["host".to_string()],
+        collect_field_names: true,
+        ..ParseOptions::default()
+    };
+
+    let mut fingerprinter = FleetFingerprinter::new();
+    for record in parser::stream(3, &input, &options).unwrap() {
+        fingerprinter.observe(&record.unwrap());
+    }
+    let fleet = fingerprinter.report();
+
+    assert_eq!(fleet.fleet_fingerprint, ["host", "level", "msg", "type"]);
+    let producers: Vec<&str> = fleet
+        .producers
+        .iter()
+        .map(|p| p.producer.as_str())
+        .collect();
+    assert_eq!(producers, ["a", "b", "c", "d", "e"]);
+    for producer in &fleet.producers[..4] {
+        assert_eq!(producer.counter, 40);
+        assert_eq!(producer.conformance, 1.0);
+        assert!(producer.outlier_reasons.is_empty(), "{:?}", producer);
+    }
+    let outlier = &fleet.producers[4];
+    assert_eq!((outlier.conformance, outlier.error_rate), (0.0, 1.0));
+    let reasons: Vec<&str> = outlier
+        .outlier_reasons
+        .iter()
+        .map(|reason| reason.split(' ').next().unwrap())
+        .collect();
+    assert_eq!(
+        reasons,
+        ["conformance", "error_rate", "avg_line_size", "volume"]
+    );
+}
+
+#[test]
+fn anonymized_values_get_consistent_pseudonyms() {
+    let fixture = Fixture::new("anonymize");
+    let input = fixture.lines(
+        "input.log",
+        [
+            r#"{"type":"a","ts":"2024-01-01T00:00:00Z","user_id":"alice","msg":"alice@corp.com from 192.168.1.7"}"#,
+            "not json",
+            r#"{"type":"a","ts":"2024-01-01T00:00:01Z","user_id":"bob","msg":"bob@corp.com from 192.168.1.7"}"#,
+            r#"{"type":"b","ts":"2024-01-01T00:00:02Z","user_id":"alice","msg":"alice@corp.com v1.2.3.4"}"#,
+        ],
+    );
+    let output = fixture.path("anonymized.log");
+
+    let mut anonymizer = Anonymizer::new(vec!["user_id".to_string()]);
+    let summary = anonymize::anonymize_file(&input, &output, &mut anonymizer).unwrap();
+
+    assert_eq!((summary.lines, summary.failed_lines), (4, 1));
+    let records: Vec<Value> = fs::read_to_string(&output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let field = |field: &str| -> Vec<&str> {
+        records
+            .iter()
+            .map(|record| record[field].as_str().unwrap())
+            .collect()
+    };
+    // structure and timestamps are kept, equal values get equal pseudonyms
+    assert_eq!(field("type"), ["a", "a", "b"]);
+    assert_eq!(
+        field("ts"),
+        [
+            "2024-01-01T00:00:00Z",
+            "2024-01-01T00:00:01Z",
+            "2024-01-01T00:00:02Z"
+        ]
+    );
+    assert_eq!(field("user_id"), ["user_id-1", "user_id-2", "user_id-1"]);
+    assert_eq!(
+        field("msg"),
+        [
+            "user1@example.com from 10.0.0.1",
+            "user2@example.com from 10.0.0.1",
+            "user1@example.com v1.2.3.4"
+        ]
+    );
+}
+
+#[test]
+fn replayed_records_reach_a_tcp_sink_at_their_pace() {
+    let fixture = Fixture::new("replay");
+    let input = fixture.lines(
+        "input.log",
+        [
+            r#"{"type":"a","ts":"2024-01-01T00:00:00Z"}"#,
+            "not json",
+            r#"{"type":"b","ts":"2024-01-01T00:01:40Z"}"#,
+            r#"{"type":"a"}"#,
+            r#"{"type":"b","ts":"2024-01-01T00:03:20Z"}"#,
+        ],
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let uri = format!("tcp://{}", listener.local_addr().unwrap());
+    let received = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        io::BufReader::new(stream)
+            .lines()
+            .collect::<io::Result<Vec<String>>>()
+            .unwrap()
+    });
+
+    let clock = ManualClock::new(UNIX_EPOCH);
+    let mut sink = sink::open(&uri).unwrap();
+    let mut budgets = BudgetTracker::new(vec![]);
+    let summary = replay::replay(&input, sink.as_mut(), "ts", 10.0, &mut budgets, &clock).unwrap();
+    drop(sink);
+
+    assert_eq!((summary.sent, summary.skipped), (4, 1));
+    // 200 seconds of records replayed 10 times faster
+    assert_eq!(summary.elapsed, Duration::from_secs(20));
+    let lines = fs::read_to_string(&input).unwrap();
+    let expected: Vec<&str> = lines.lines().filter(|line| *line != "not json").collect();
+    assert_eq!(received.join().unwrap(), expected);
+}
+
+/// Every replayed record is a message of librdkafka's mock cluster, sent no faster than the rate limit.
+#[cfg(feature = "kafka")]
+#[test]
+fn replayed_records_reach_a_kafka_topic_at_the_rate_limit() {
+    use rdkafka::consumer::{BaseConsumer, Consumer};
+    use rdkafka::message::Message;
+    use rdkafka::mocking::MockCluster;
+    use rdkafka::{ClientConfig, Offset, TopicPartitionList};
+
+    let fixture = Fixture::new("replay-kafka");
+    let lines: Vec<String> = (0..5)
+        .map(|idx| json!({"type": "a", "idx": idx}).to_string())
+        .collect();
+    let input = fixture.lines("input.log", &lines);
+    let cluster = MockCluster::new(1).unwrap();
+    cluster.create_topic("replay", 1, 1).unwrap();
+
+    let started = Instant::now();
+    let output = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args(["replay", "-i"])
+        .arg(&input)
+        .arg("--sink")
+        .arg(format!("kafka://{}/replay", cluster.bootstrap_servers()))
+        .args(["--timestamp-field", "ts", "--speed", "0"])
+        .args(["--rate-limit", "10", "--burst", "1"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // the first record takes the burst, every other one waits a tenth of a second
+    assert!(started.elapsed() >= Duration::from_millis(400));
+
+    let consumer: BaseConsumer = ClientConfig::new()
+        .set("bootstrap.servers", cluster.bootstrap_servers())
+        .set("group.id", "replay")
+        .create()
+        .unwrap();
+    let mut partitions = TopicPartitionList::new();
+    partitions
+        .add_partition_offset("replay", 0, Offset::Beginning)
+        .unwrap();
+    consumer.assign(&partitions).unwrap();
+    let mut messages = vec![];
+    while messages.len() < lines.len() {
+        let message = consumer.poll(Duration::from_secs(10)).unwrap().unwrap();
+        messages.push(String::from_utf8(message.payload().unwrap().to_vec()).unwrap());
+    }
+    assert_eq!(messages, lines);
+}
+
+#[cfg(not(feature = "kafka"))]
+#[test]
+fn kafka_line_sinks_need_the_kafka_feature() {
+    let err = sink::open("kafka://broker:9092/topic").err().unwrap();
+    assert!(err.contains("--features kafka"), "{}", err);
+}
+
+#[test]
+fn monthly_costs_of_log_types_add_up_to_the_total() {
+    // an hour of logs is 720 hours of the month
+    let estimator = CostEstimator::new(0.5, Duration::from_secs(3600));
+    assert_eq!(estimator.monthly_bytes(1_000_000), 720_000_000.0);
+    assert_eq!(estimator.monthly_cost(1_000_000), 0.36);
+
+    let corpus = Corpus::generate("cost", &sample(0));
+    let report = parser::single_thread_parser(corpus.path(), &parse_options()).unwrap();
+    let registers = report.log_register.values();
+    let total_bytes: u64 = registers
+        .clone()
+        .map(|register| register.num_of_bytes)
+        .sum();
+    let costs: f64 = registers
+        .map(|register| estimator.monthly_cost(register.num_of_bytes))
+        .sum();
+    assert!((costs - estimator.monthly_cost(total_bytes)).abs() < 1e-9);
+
+    // the table gets a column of the costs of the --window the input covers
+    let output = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args(["parse", "-i", corpus.path(), "--color", "never"])
+        .args(["--cost-per-gb", "0.5", "--window", "1h"])
+        .output()
+        .unwrap();
+    let table = String::from_utf8(output.stdout).unwrap();
+    assert!(table.lines().next().unwrap().ends_with("monthly cost"));
+    let total = table
+        .lines()
+        .find(|line| line.starts_with("total"))
+        .unwrap();
+    let expected = format!("{:.2}", estimator.monthly_cost(total_bytes));
+    assert!(total.ends_with(&expected), "{}", table);
+}
+
+#[test]
+fn burn_rates_alert_on_windows_spending_the_error_budget() {
+    let mut tracker = SloTracker::new(Slo {
+        objective: 0.99,
+        latency_threshold: Some(300.0),
+        period: Duration::from_secs(30 * 24 * 60 * 60),
+    });
+    let start = UNIX_EPOCH + Duration::from_secs(1_700_000_040);
+    let at = |minute: u64| Some(start + Duration::from_secs(minute * 60));
+    // 3 failed and 2 slow requests an hour ago, then the last 5 minutes are good
+    for status in [503.0, 503.0, 500.0, 200.0, 200.0, 200.0, 200.0, 200.0] {
+        tracker.observe(at(0), Some(100.0), Some(status));
+    }
+    tracker.observe(at(0), Some(450.0), None);
+    tracker.observe(at(0), Some(301.0), Some(200.0));
+    for minute in 55..60 {
+        for _ in 0..10 {
+            tracker.observe(at(minute), Some(100.0), Some(200.0));
+        }
+    }
+    // not an SLI event
+    tracker.observe(at(59), None, None);
+    tracker.observe(None, Some(450.0), Some(200.0));
+
+    let report = tracker.report(None);
+    assert_eq!(
+        (report.total, report.bad, report.without_timestamp),
+        (61, 6, 1)
+    );
+    assert_eq!(report.window_secs, 60 * 60);
+    assert!((report.compliance - 55.0 / 61.0).abs() < 1e-9);
+    let budget_consumed = 6.0 / 61.0 / 0.01 / (30.0 * 24.0);
+    assert!((report.budget_consumed - budget_consumed).abs() < 1e-9);
+    let burn_rates: Vec<(u64, u64, u64)> = report
+        .burn_rates
+        .iter()
+        .map(|rate| (rate.window_secs, rate.total, rate.bad))
+        .collect();
+    assert_eq!(
+        burn_rates,
+        [
+            (5 * 60, 50, 0),
+            (30 * 60, 50, 0),
+            (60 * 60, 60, 5),
+            (2 * 60 * 60, 60, 5),
+            (6 * 60 * 60, 60, 5),
+            (24 * 60 * 60, 60, 5),
+            (3 * 24 * 60 * 60, 60, 5)
+        ]
+    );
+    assert!((report.burn_rates[2].burn_rate - 5.0 / 60.0 / 0.01).abs() < 1e-9);
+    // fast burning page alerts need a bad short window too
+    assert_eq!(report.alerts.len(), 2, "{:?}", report.alerts);
+    assert!(report
+        .alerts
+        .iter()
+        .all(|alert| alert.starts_with("ticket")));
+}
+
+#[test]
+fn byte_budgets_flag_log_types_projected_over_them() {
+    let budgets = vec![
+        ByteBudget::parse("a=1KB/1h").unwrap(),
+        ByteBudget::parse("*=10MB").unwrap(),
+    ];
+    assert_eq!(
+        budgets[0].daily_bytes(),
+        24.0 * budgets[0].num_of_bytes as f64
+    );
+    assert_eq!(budgets[1].period, Duration::from_secs(24 * 60 * 60));
+    for invalid in ["a", "=1KB", "a=1KB/0s", "a=lots"] {
+        assert!(ByteBudget::parse(invalid).is_err(), "{}", invalid);
+    }
+
+    let fixture = Fixture::new("budget");
+    let input = fixture.lines(
+        "input.log",
+        [r#"{"type":"a"}"#, r#"{"type":"b"}"#, r#"{"type":"a"}"#],
+    );
+    let report = parser::single_thread_parser(&input, &ParseOptions::default()).unwrap();
+    let window = Duration::from_secs(60);
+    let statuses = budget::check(
+        report
+            .log_register
+            .iter()
+            .map(|(log_type, register)| (log_type.as_str(), register)),
+        window,
+        &budgets,
+    );
+    let statuses: Vec<(&str, u64, bool)> = statuses
+        .iter()
+        .map(|status| {
+            (
+                status.log_type.as_str(),
+                status.daily_bytes,
+                status.over_budget,
+            )
+        })
+        .collect();
+    // 26 and 13 bytes a minute
+    assert_eq!(statuses, [("a", 26 * 1440, true), ("b", 13 * 1440, false)]);
+
+    // streams are projected after a minute, once per log type
+    let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let at = |secs: u64| start + Duration::from_secs(secs);
+    let mut tracker = BudgetTracker::new(budgets);
+    assert_eq!(tracker.observe("a", 100, at(0)), None);
+    assert_eq!(tracker.observe("a", 100, at(30)), None);
+    assert_eq!(tracker.observe("b", 1000, at(120)), None);
+    let alert = tracker.observe("a", 100, at(120)).unwrap();
+    assert_eq!(alert.log_type, "a");
+    assert_eq!(alert.projected_daily_bytes, 300 * 720);
+    assert_eq!(tracker.observe("a", 100, at(180)), None);
+}
+
+#[test]
+fn alert_rules_fire_on_the_aggregates() {
+    let options = ParseOptions {
+        rate_window: Some(60),
+        ..parse_options()
+    };
+    check(sample_options(), |sample_options| {
+        let corpus = Corpus::generate("alert", &sample_options);
+        let report = parser::single_thread_parser(corpus.path(), &options).unwrap();
+        prop_assume!(report.lines > 0, "no log type to select");
+        let (log_type, register) = report.log_register.iter().next().unwrap();
+        let count = register.counter;
+        let errors: u64 = report.log_register.values().map(|elem| elem.errors).sum();
+        let max_window = register
+            .windows
+            .values()
+            .map(|count| count.lines)
+            .max()
+            .unwrap_or_default();
+
+        let fired = |rule: &str| {
+            let rule = AlertRule::parse(rule).unwrap();
+            rule.validate(&options).unwrap();
+            rule.evaluate(&report, &options)
+        };
+        let selected = format!("type={} count>{}", log_type, count);
+        assert!(fired(&selected).is_none());
+        let selected = format!("type={} count>={}", log_type, count);
+        assert_eq!(fired(&selected).unwrap().value, count as f64);
+        assert!(fired(&format!("errors>{}", errors)).is_none());
+        assert!(fired(&format!("errors=={}", errors)).is_some());
+        // nothing is selected, dead-man rules fire
+        assert!(fired("type=missing count<1").is_some());
+
+        let rule = format!("type={} window_count>={}", log_type, max_window);
+        match fired(&rule) {
+            Some(alert) => {
+                assert_eq!(alert.value, max_window as f64);
+                assert!(alert.window.is_some());
+            }
+            None => assert!(register.windows.is_empty()),
+        }
+        assert!(fired(&format!("type={} window_count>{}", log_type, max_window)).is_none());
+        Ok(())
+    });
+
+    let grouped = ParseOptions {
+        group_by: vec!["type".to_string(), "level".to_string()],
+        ..ParseOptions::default()
+    };
+    let rule = AlertRule::parse("level=error error_rate>=50%").unwrap();
+    assert_eq!(rule.threshold, 0.5);
+    rule.validate(&grouped).unwrap();
+    assert!(AlertRule::parse("host=a count>1")
+        .unwrap()
+        .validate(&grouped)
+        .is_err());
+    assert!(AlertRule::parse("window_errors>1")
+        .unwrap()
+        .validate(&ParseOptions::default())
+        .is_err());
+    assert!(AlertRule::parse("type=a").is_err());
+    assert!(AlertRule::parse("latency>1").is_err());
+    assert!(AlertRule::parse("bytes>1GB").unwrap().threshold > 1e9 - 1.0);
+
+    // alerts are POSTed as a single JSON line, which the server counts as a line
+    let server = Arc::new(Server::bind("127.0.0.1:0", ParseOptions::default()).unwrap());
+    let url = format!("http://{}/hook", server.local_addr().unwrap());
+    let running = server.clone();
+    thread::spawn(move || running.run());
+    let report = ParseReport::default();
+    let alerts = alert::evaluate(
+        &[AlertRule::parse("count<1").unwrap()],
+        &report,
+        &ParseOptions::default(),
+    );
+    assert_eq!(alerts.len(), 1);
+    alert::notify(&url, "app.log", &report, &alerts, &ParseOptions::default()).unwrap();
+    assert_eq!(server.report().lines, 1);
+}
+
+#[test]
+fn signed_reports_verify_until_changed() {
+    let fixture = Fixture::new("signing");
+    let dir = fixture.dir();
+    // PEM keys like `openssl genpkey -algorithm ed25519` and `openssl pkey -pubout` write them
+    let key = |name: &str, seed: u8| {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[seed; 32]);
+        let private = dir.join(format!("{}.key", name));
+        let public = dir.join(format!("{}.pub", name));
+        fs::write(
+            &private,
+            key.to_pkcs8_pem(LineEnding::LF).unwrap().as_bytes(),
+        )
+        .unwrap();
+        fs::write(
+            &public,
+            key.verifying_key()
+                .to_public_key_pem(LineEnding::LF)
+                .unwrap(),
+        )
+        .unwrap();
+        (private, public)
+    };
+    let (private, public) = key("signer", 1);
+    let (other_private, other_public) = key("other", 2);
+
+    let corpus = Corpus::generate("signed", &sample(0));
+    let report = parser::single_thread_parser(corpus.path(), &parse_options()).unwrap();
+    let path = dir.join("results.json");
+    fs::write(&path, serde_json::to_string(&report).unwrap()).unwrap();
+
+    let signature = signing::sign(&path, &SigningKey::File(private.clone())).unwrap();
+    assert_eq!(signature, dir.join("results.json.sig"));
+    assert!(signing::verify(&path, &public, &signature).unwrap());
+    assert!(!signing::verify(&path, &other_public, &signature).unwrap());
+
+    // the key can be given as PEM text too
+    let pem = fs::read_to_string(&other_private).unwrap();
+    let signature = signing::sign(&path, &SigningKey::Pem(pem)).unwrap();
+    assert!(signing::verify(&path, &other_public, &signature).unwrap());
+    assert!(!signing::verify(&path, &public, &signature).unwrap());
+
+    let mut content = fs::read(&path).unwrap();
+    content.push(b'\n');
+    fs::write(&path, content).unwrap();
+    assert!(!signing::verify(&path, &other_public, &signature).unwrap());
+    assert!(signing::verify(&dir.join("missing.json"), &public, &signature).is_err());
+
+    // keys which aren't Ed25519 PEM keys fail, unlike signatures which don't match
+    fs::write(&path, "report").unwrap();
+    fs::write(&signature, [0; 10]).unwrap();
+    assert!(!signing::verify(&path, &public, &signature).unwrap());
+    assert!(signing::verify(&path, &path, &signature).is_err());
+    assert!(signing::sign(&path, &SigningKey::Pem("not a key".to_string())).is_err());
+}
+
+#[test]
+fn keys_are_written_to_private_dirs_only() {
+    let private = PrivateDir::new().unwrap();
+    let path = private.path().to_path_buf();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+    let (key, mut file) = private.create("signing.pem").unwrap();
+    file.write_all(b"key").unwrap();
+    assert!(private.create("signing.pem").is_err());
+    assert_eq!(fs::read(&key).unwrap(), b"key");
+    drop(private);
+    assert!(!path.exists());
+}
+
+/// Head lines (with `\r\n`) and body of the next request of an HTTP client.
+fn read_http_request<R: BufRead>(reader: &mut R) -> (Vec<String>, Vec<u8>) {
+    let mut head = Vec::new();
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+            length = value.trim().parse().unwrap();
+        }
+        if line == "\r\n" {
+            break;
+        }
+        head.push(line);
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    (head, body)
+}
+
+#[test]
+fn comments_are_posted_without_temporary_files() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let api_url = format!("http://{}", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = io::BufReader::new(stream);
+        let (head, body) = read_http_request(&mut reader);
+        let response = r#"{"html_url":"https://github.com/owner/repo/pull/7#issuecomment-1"}"#;
+        write!(
+            reader.get_mut(),
+            "HTTP/1.1 201 Created\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.len(),
+            response
+        )
+        .unwrap();
+        (head, body)
+    });
+
+    let issue = IssueRef::parse("owner/repo#7").unwrap();
+    let body = "Lines: \"5000\"\nPath: C:\\logs\\app.log\t@home";
+    let url = github::post_comment(&api_url, &issue, "secret", body).unwrap();
+    assert_eq!(url, "https://github.com/owner/repo/pull/7#issuecomment-1");
+
+    let (head, payload) = server.join().unwrap();
+    assert_eq!(
+        head[0],
+        "POST /repos/owner/repo/issues/7/comments HTTP/1.1\r\n"
+    );
+    assert!(head.contains(&"Authorization: Bearer secret\r\n".to_string()));
+    let payload: Value = serde_json::from_slice(&payload).unwrap();
+    assert_eq!(payload, json!({ "body": body }));
+}
+
+#[test]
+fn markdown_summaries_are_posted_as_comments() {
+    for invalid in [
+        "owner/repo",
+        "repo#7",
+        "owner/#7",
+        "owner/repo#x",
+        "a/b/c#7",
+    ] {
+        assert!(IssueRef::parse(invalid).is_err(), "{}", invalid);
+    }
+
+    let fixture = Fixture::new("github");
+    let input = fixture.lines(
+        "input.log",
+        [
+            r#"{"type":"a"}"#,
+            r#"{"type":"a"}"#,
+            r#"{"type":"b"}"#,
+            "not json",
+        ],
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let api_url = format!("http://{}", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = io::BufReader::new(stream);
+        let (head, body) = read_http_request(&mut reader);
+        let response = r#"{"html_url":"https://github.com/owner/repo/issues/3#issuecomment-1"}"#;
+        write!(
+            reader.get_mut(),
+            "HTTP/1.1 201 Created\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.len(),
+            response
+        )
+        .unwrap();
+        (head, body)
+    });
+
+    let output = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args(["parse", "-i", &input, "--github-comment", "owner/repo#3"])
+        .args(["--github-api-url", &api_url])
+        .env("GITHUB_TOKEN", "secret")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+
+    let (head, payload) = server.join().unwrap();
+    assert_eq!(
+        head[0],
+        "POST /repos/owner/repo/issues/3/comments HTTP/1.1\r\n"
+    );
+    let payload: Value = serde_json::from_slice(&payload).unwrap();
+    let comment = payload["body"].as_str().unwrap();
+    assert!(comment.starts_with("### Log parser summary"), "{}", comment);
+    assert!(comment.contains("failed lines: 1"), "{}", comment);
+    assert!(comment.contains("| a "), "{}", comment);
+    assert!(comment.contains("| b "), "{}", comment);
+
+    // the token is required before anything is posted
+    let output = process::Command::new(env!("CARGO_BIN_EXE_log-parser"))
+        .args(["parse", "-i", &input, "--github-comment", "owner/repo#3"])
+        .env_remove("GITHUB_TOKEN")
+        .output()
+        .unwrap();
+    assert_ne!(output.status.code(), Some(0));
+}
+
+#[cfg(feature = "otlp")]
+#[test]
+fn otlp_metrics_of_consecutive_windows_are_exported() {
+    let fixture = Fixture::new("otlp");
+    let input = fixture.lines(
+        "input.log",
+        [r#"{"type":"a"}"#, r#"{"type":"a"}"#, "not json"],
+    );
+    let report = parser::single_thread_parser(&input, &ParseOptions::default()).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let uri = format!("otlp://{}", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        ["200 OK", "503 Service Unavailable"]
+            .iter()
+            .map(|status| {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = io::BufReader::new(stream);
+                let request = read_http_request(&mut reader);
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {}\r\nContent-Length: 10\r\nConnection: close\r\n\r\noverloaded",
+                    status
+                )
+                .unwrap();
+                request
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let url = otlp::endpoint(&uri).unwrap();
+    let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    otlp::start_window(start);
+    otlp::export(&url, "input.log", &report).unwrap();
+    let err = otlp::export(&url, "input.log", &report).unwrap_err();
+    assert!(
+        err.to_string().contains("status 503: overloaded"),
+        "{}",
+        err
+    );
+
+    let requests = server.join().unwrap();
+    let (head, _) = &requests[0];
+    assert_eq!(head[0], "POST /v1/metrics HTTP/1.1\r\n");
+    let exports: Vec<Value> = requests
+        .iter()
+        .map(|(_, body)| serde_json::from_slice(body).unwrap())
+        .collect();
+    let metric = |export: &Value, name: &str| {
+        export["resourceMetrics"][0]["scopeMetrics"][0]["metrics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|metric| metric["name"] == name)
+            .unwrap()["sum"]["dataPoints"]
+            .clone()
+    };
+    let logs = metric(&exports[0], "log_parser.logs");
+    assert_eq!(logs[0]["attributes"][0]["value"]["stringValue"], "a");
+    assert_eq!(logs[0]["asInt"], "2");
+    assert_eq!(logs[0]["startTimeUnixNano"], "1700000000000000000");
+    assert_eq!(
+        metric(&exports[0], "log_parser.failed_lines")[0]["asInt"],
+        "1"
+    );
+    // the window of an export starts where the previous one ended
+    let lines = metric(&exports[1], "log_parser.lines");
+    assert_eq!(lines[0]["asInt"], "3");
+    assert_eq!(lines[0]["startTimeUnixNano"], logs[0]["timeUnixNano"]);
+}
+
+#[test]
+fn spool_keeps_the_latest_lines_in_order_across_restarts() {
+    // lines of every batch and whether the spool is reopened after it
+    let batches = prop::collection::vec((1..10usize, prop::bool::weighted(0.2)), 1..20);
+    check((1..2000u64, batches), |(max_size, batches)| {
+        let fixture = Fixture::new("spool");
+        let dir = fixture.dir().join("spool");
+        let mut spool = Spool::open(&dir, max_size).unwrap();
+
+        let mut pushed = vec![];
+        let mut dropped = 0;
+        for (batch_idx, (num_of_lines, reopened)) in batches.into_iter().enumerate() {
+            let batch: Vec<String> = (0..num_of_lines)
+                .map(|idx| json!({"type": "a", "batch": batch_idx, "idx": idx}).to_string())
+                .collect();
+            pushed.extend(batch.clone());
+            dropped += spool.push(&batch).unwrap();
+            if reopened {
+                spool = Spool::open(&dir, max_size).unwrap();
+            }
+        }
+        let size = spool.size();
+
+        let (mut drained, mut batches) = (vec![], 0);
+        while let Some(batch) = spool.front().unwrap() {
+            drained.extend(batch);
+            batches += 1;
+            spool.pop().unwrap();
+        }
+        // the latest batch is kept even if it's larger than the maximum
+        assert!(size <= max_size || batches == 1, "spool size");
+        assert_eq!(
+            drained,
+            pushed[dropped as usize..].to_vec(),
+            "spooled lines"
+        );
+        assert_eq!(spool.size(), 0, "size of drained spool");
+        Ok(())
+    });
+}
+
+/// Line sink recording batches it was sent.
+struct RecordingSink(Arc<Mutex<Vec<Vec<String>>>>);
+
+impl LineSink for RecordingSink {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        self.0.lock().unwrap().push(vec![line.to_string()]);
+        Ok(())
+    }
+
+    fn send_batch(&mut self, lines: &[String]) -> io::Result<()> {
+        self.0.lock().unwrap().push(lines.to_vec());
+        Ok(())
+    }
+}
+
+#[test]
+fn batched_sink_sends_every_line_in_order_within_batch_limits() {
+    // whether the sink is flushed after every line
+    let flushes = prop::collection::vec(prop::bool::weighted(0.1), 0..100);
+    check(
+        (1..20usize, 1..500u64, flushes),
+        |(max_records, max_bytes, flushes)| {
+            let uri = format!(
+                "tcp://host:1?batch_records={}&batch_bytes={}",
+                max_records, max_bytes
+            );
+            let (base, policy) = BatchPolicy::from_uri(&uri).unwrap();
+            assert_eq!(base, "tcp://host:1");
+
+            let batches = Arc::new(Mutex::new(vec![]));
+            let mut sink = sink::batched(Box::new(RecordingSink(batches.clone())), policy);
+            let lines: Vec<String> = (0..flushes.len())
+                .map(|idx| json!({"type": "a", "idx": idx}).to_string())
+                .collect();
+            for (line, flushed) in lines.iter().zip(flushes) {
+                sink.send(line).unwrap();
+                if flushed {
+                    sink.flush().unwrap();
+                }
+            }
+            sink.close().unwrap();
+
+            let batches = batches.lock().unwrap();
+            for batch in batches.iter() {
+                let bytes: usize = batch.iter().map(|line| line.len() + 1).sum();
+                // the line which fills the batch is its last one
+                let last = batch.last().map_or(0, |line| line.len() + 1);
+                assert!(
+                    batch.len() <= max_records && bytes - last < max_bytes as usize,
+                    "batch limits"
+                );
+            }
+            assert_eq!(batches.concat(), lines, "batched lines");
+            Ok(())
+        },
+    );
+}
+
+/// Corpus with `\r\n` line endings, as written on Windows.
+fn crlf_corpus(name: &str, options: &SampleOptions) -> Corpus {
+    let corpus = Corpus::generate(name, options);
+    let content = fs::read_to_string(corpus.path()).unwrap();
+    fs::write(corpus.path(), content.replace('\n', "\r\n")).unwrap();
+    corpus
+}
+
+#[test]
+fn rate_limited_records_wait_for_tokens_beyond_the_burst() {
+    check(
+        (0.5..1000.0, 1..50u64, 0..200usize),
+        |(records_per_sec, burst, num_of_lines)| {
+            let limit = RateLimit::new(records_per_sec, Some(burst)).unwrap();
+            let lines: Vec<String> = (0..num_of_lines)
+                .map(|idx| json!({"type": "a", "idx": idx}).to_string())
+                .collect();
+            let expected =
+                lines.len().saturating_sub(limit.burst as usize) as f64 / limit.records_per_sec;
+
+            let clock = Arc::new(ManualClock::new(UNIX_EPOCH));
+            let mut writer = ThrottledWriter::new(vec![], TokenBucket::new(limit, clock.clone()));
+            for line in &lines {
+                writeln!(writer, "{}", line).unwrap();
+            }
+            let elapsed = clock.elapsed().as_secs_f64();
+            assert!((elapsed - expected).abs() < 1e-3);
+            assert_eq!(writer.bucket().waited(), clock.elapsed());
+
+            let clock = Arc::new(ManualClock::new(UNIX_EPOCH));
+            let sent = Arc::new(Mutex::new(vec![]));
+            let mut sink = sink::throttled(
+                Box::new(RecordingSink(sent.clone())),
+                TokenBucket::new(limit, clock.clone()),
+            );
+            for line in &lines {
+                sink.send(line).unwrap();
+            }
+            sink.close().unwrap();
+            let elapsed = clock.elapsed().as_secs_f64();
+            assert!((elapsed - expected).abs() < 1e-3);
+            let sent: Vec<String> = sent.lock().unwrap().concat();
+            assert_eq!(sent, lines);
+            Ok(())
+        },
+    );
+
+    // a paused stream gets its burst back, not more
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH));
+    let mut bucket = TokenBucket::new(RateLimit::new(10.0, None).unwrap(), clock.clone());
+    assert!((0..10).all(|_| bucket.try_take()));
+    assert!(!bucket.try_take());
+    clock.advance(Duration::from_secs(60));
+    assert!((0..10).all(|_| bucket.try_take()));
+    assert!(!bucket.try_take());
+    assert!(RateLimit::new(0.0, None).is_err());
+    assert!(RateLimit::new(1.0, Some(0)).is_err());
+}
+
+#[test]
+fn crlf_files_give_the_report_of_lf_files() {
+    let options = parse_options();
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let lf = Corpus::generate("lf", &sample_options);
+            let crlf = crlf_corpus("crlf", &sample_options);
+
+            let expected = parser::single_thread_parser(lf.path(), &options).unwrap();
+            let single = parser::single_thread_parser(crlf.path(), &options).unwrap();
+            let sharded =
+                parser::multi_thread_parser_sharded(num_of_threads, crlf.path(), &options).unwrap();
+            assert_same_report(&single, &sharded, "sharded");
+
+            assert_eq!(single.lines, expected.lines);
+            assert_eq!(single.failed_lines, 0);
+            for (log_type, expected) in &expected.log_register {
+                let actual = &single.log_register[log_type];
+                assert_eq!(actual.counter, expected.counter);
+                assert_eq!(actual.errors, expected.errors);
+            }
+            Ok(())
+        },
+    );
+}
+
+/// Rewrite the generated corpus as lines of the type and message, parsed with [`LINE_PATTERN`].
+fn to_pattern_lines(corpus: &Corpus) {
+    let mut text = String::new();
+    for line in fs::read_to_string(corpus.path()).unwrap().lines() {
+        let log: Value = serde_json::from_str(line).unwrap();
+        text.push_str(&format!(
+            "{} {}\n",
+            log["type"].as_str().unwrap_or_default(),
+            log["message"].as_str().unwrap_or_default()
+        ));
+    }
+    fs::write(corpus.path(), text).unwrap();
+}
+
+const LINE_PATTERN: &str = r"^(?P<type>\S*) (?P<message>.*)$";
+
+#[test]
+fn crlf_records_have_the_values_of_lf_records() {
+    check(
+        (sample_options(), 0..3u8, quoted_fields()),
+        |(sample_options, format, quoted)| {
+            let lf = Corpus::generate("values-lf", &sample_options);
+            let crlf = Corpus::generate("values-crlf", &sample_options);
+            let mut options = ParseOptions {
+                string_fields: vec!["type".to_string(), "message".to_string()],
+                ..ParseOptions::default()
+            };
+            match format {
+                0 => {}
+                1 => {
+                    let columns = ["id", "level", "message", "type"];
+                    to_csv(&lf, &quoted, ',', &columns, true, false);
+                    options.csv =
+                        Some(CsvFormat::new(",").unwrap().with_header(lf.path()).unwrap());
+                }
+                _ => {
+                    to_pattern_lines(&lf);
+                    options.pattern = Some(LinePattern::new(LINE_PATTERN).unwrap());
+                }
+            }
+            let content = fs::read_to_string(lf.path()).unwrap();
+            fs::write(crlf.path(), content.replace('\n', "\r\n")).unwrap();
+
+            let expected: Vec<ParsedRecord> = parser::stream(1, lf.path(), &options)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+            let records: Vec<ParsedRecord> = parser::stream(1, crlf.path(), &options)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(records.len(), expected.len());
+            for (record, expected) in records.iter().zip(&expected) {
+                assert_eq!(record.string_values, expected.string_values);
+                assert_eq!(record.num_of_bytes, expected.num_of_bytes + 1);
+            }
+            // the header line is the only one not counted
+            let header = match options.csv {
+                Some(_) => content.find('\n').unwrap() as u64 + 2,
+                None => 0,
+            };
+            let size = fs::metadata(crlf.path()).unwrap().len();
+            let bytes: u64 = records.iter().map(|record| record.num_of_bytes).sum();
+            assert_eq!(bytes + header, size);
+
+            let report = parser::single_thread_parser(crlf.path(), &options).unwrap();
+            let bytes: u64 = report.log_register.values().map(|r| r.num_of_bytes).sum();
+            assert_eq!(bytes + header, size);
+            Ok(())
+        },
+    );
+}
+
+/// File names aren't always valid UTF-8 on Unix, e.g. Latin-1 names of old log rotations.
+#[cfg(unix)]
+#[test]
+fn non_utf8_paths_are_parsed() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let options = sample(0);
+    let corpus = Corpus::generate("non-utf8", &options);
+    let fixture = Fixture::new("non-utf8");
+    let path = fixture.dir().join(OsStr::from_bytes(b"caf\xe9.log"));
+    fs::copy(corpus.path(), &path).unwrap();
+
+    let expected = parser::single_thread_parser(corpus.path(), &parse_options()).unwrap();
+    let single = parser::single_thread_parser(&path, &parse_options()).unwrap();
+    let sharded = parser::multi_thread_parser_sharded(4, &path, &parse_options()).unwrap();
+    let opened = decompress::open(&path).map(|file| file.source().to_string());
+    assert_eq!(single, expected);
+    assert_same_report(&single, &sharded, "sharded");
+    assert!(opened.unwrap().ends_with("caf\u{fffd}.log"));
+}
+
+/// Reader failing the first reads like a mount with a hiccup.
+struct FlakyReader {
+    failures: u32,
+    inner: io::Cursor<Vec<u8>>,
+}
+
+impl Read for FlakyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.failures > 0 {
+            self.failures -= 1;
+            return Err(io::Error::other("stale file handle"));
+        }
+        self.inner.read(buf)
+    }
+}
+
+#[test]
+fn failed_reads_are_retried_with_backoff() {
+    let delay = Duration::from_millis(100);
+    for failures in 0..5 {
+        let clock = ManualClock::new(UNIX_EPOCH);
+        let flaky = FlakyReader {
+            failures,
+            inner: io::Cursor::new(b"{\"type\":\"a\"}\n".to_vec()),
+        };
+        let mut reader = RetryReader::new(flaky, 3, delay, &clock);
+        let mut content = String::new();
+        let result = reader.read_to_string(&mut content);
+
+        match failures <= 3 {
+            true => {
+                result.unwrap();
+                assert_eq!(content, "{\"type\":\"a\"}\n");
+                assert_eq!(clock.elapsed(), delay * (2u32.pow(failures) - 1));
+            }
+            false => {
+                assert!(result.is_err());
+                assert_eq!(clock.elapsed(), delay * 7);
+            }
+        }
+    }
+}
+
+/// Reads of a directory fail on Unix, all of its bytes are skipped.
+#[cfg(unix)]
+#[test]
+fn unreadable_bytes_are_skipped() {
+    let fixture = Fixture::new("unreadable");
+    let dir = fixture.dir();
+    let size = fs::metadata(dir).unwrap().len();
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH));
+    let options = ParseOptions {
+        io_retries: Some(2),
+        io_retry_delay_ms: Some(50),
+        skip_unreadable: true,
+        clock: Some(clock.clone()),
+        ..parse_options()
+    };
+
+    let report = parser::single_thread_parser(dir, &options).unwrap();
+    let sharded = parser::multi_thread_parser_sharded(1, dir, &options).unwrap();
+    assert_eq!(report.lines, 0);
+    assert_eq!(report.failed_ranges.len(), 1);
+    let skipped = &report.failed_ranges[0];
+    assert_eq!((skipped.start_idx, skipped.end_idx), (0, size));
+    assert_eq!(skipped.attempts, 3);
+    assert_same_report(&report, &sharded, "sharded");
+    // both parsers retried a single read twice
+    assert_eq!(clock.elapsed(), Duration::from_millis(2 * (50 + 100)));
+}
+
+#[test]
+fn scanned_lines_are_bounded_and_cover_every_byte() {
+    let bytes = prop::collection::vec(
+        prop_oneof![Just(b'\n'), Just(0xff), Just(b'{'), any::<u8>()],
+        0..512,
+    );
+    check((bytes, 1..64usize), |(data, max_line_len)| {
+        let mut scanner = LineScanner::with_max_line_len(&data[..], max_line_len);
+        let mut total_bytes = 0;
+        while let Some(line) = scanner.next_line().unwrap() {
+            assert!(line.num_of_bytes > 0);
+            match line.error {
+                Some(LineError::TooLong(_)) => {
+                    assert!(line.num_of_bytes as usize > max_line_len)
+                }
+                _ => assert!(line.num_of_bytes as usize <= max_line_len),
+            }
+            total_bytes += line.num_of_bytes;
+        }
+        assert_eq!(total_bytes as usize, data.len());
+        Ok(())
+    });
+}
+
+#[test]
+fn pathological_lines_fail_or_parse_to_finite_values() {
+    let options = ParseOptions {
+        numeric_fields: vec!["duration_ms".to_string()],
+        ..ParseOptions::default()
+    };
+    let nested = format!("{}{}", "[".repeat(10_000), "]".repeat(10_000));
+    assert!(parser::parse_line(&nested, &options).is_err());
+    let nested = format!(
+        r#"{{"type":"a","x":{}1{}}}"#,
+        "[".repeat(500),
+        "]".repeat(500)
+    );
+    assert!(parser::parse_line(&nested, &options).is_err());
+
+    for duration in [r#""1e999""#, r#""-1e999""#, r#""NaN""#, r#""inf""#] {
+        let line = format!(r#"{{"type":"a","duration_ms":{}}}"#, duration);
+        let record = parser::parse_line(&line, &options).unwrap();
+        assert_eq!(record.numeric_values, [None], "{}", duration);
+    }
+    let record = parser::parse_line(r#"{"type":"a","duration_ms":" 12.5 "}"#, &options).unwrap();
+    assert_eq!(record.numeric_values, [Some(12.5)]);
+}
+
+/// Reads of a directory fail on Unix, every part of it fails all attempts and is reported.
+#[cfg(unix)]
+#[test]
+fn failing_parts_are_retried_and_reported_as_failed_ranges() {
+    let fixture = Fixture::new("failing-parts");
+    let dir = fixture.dir();
+    let size = fs::metadata(dir).unwrap().len();
+    // failed reads aren't retried within an attempt
+    let options = ParseOptions {
+        io_retries: Some(0),
+        ..parse_options()
+    };
+    for strategy in [
+        ParseStrategy::Channel,
+        ParseStrategy::Dashmap,
+        ParseStrategy::Sharded,
+    ] {
+        let report = parser::parse_file(strategy, 2, dir, &options).unwrap();
+        assert_eq!(report.lines, 0, "{:?}", strategy);
+        let ranges: Vec<(u64, u64)> = report
+            .failed_ranges
+            .iter()
+            .map(|range| (range.start_idx, range.end_idx))
+            .collect();
+        // the failed parts cover the whole file
+        assert!(!ranges.is_empty() && ranges.len() <= 2, "{:?}", ranges);
+        assert_eq!(ranges[0].0, 0);
+        assert_eq!(ranges[ranges.len() - 1].1, size);
+        assert!(ranges.windows(2).all(|pair| pair[0].1 == pair[1].0));
+        for range in &report.failed_ranges {
+            assert_eq!(range.attempts, chunk::MAX_ATTEMPTS);
+            assert!(!range.error.is_empty());
+        }
+    }
+}
+
+/// Whether records are pretty-printed and their separators, taken in turn by records.
+fn concatenated_layouts() -> impl Strategy<Value = Vec<(bool, &'static str)>> {
+    let separator = prop::sample::select(vec!["", " ", "\n", " \n\n"]);
+    prop::collection::vec((prop::bool::weighted(1.0 / 3.0), separator), 1..64)
+}
+
+/// Records of the corpus as concatenated JSON: some pretty-printed, separated by nothing, spaces
+/// or new lines.
+fn concatenated_corpus(name: &str, options: &SampleOptions, layouts: &[(bool, &str)]) -> Corpus {
+    let corpus = Corpus::generate(name, options);
+    let content = fs::read_to_string(corpus.path()).unwrap();
+    let mut concatenated = String::new();
+    for (line, (pretty, separator)) in content.lines().zip(layouts.iter().cycle()) {
+        let value: Value = serde_json::from_str(line).unwrap();
+        match pretty {
+            true => concatenated.push_str(&serde_json::to_string_pretty(&value).unwrap()),
+            false => concatenated.push_str(line),
+        }
+        concatenated.push_str(separator);
+    }
+    fs::write(corpus.path(), concatenated).unwrap();
+    corpus
+}
+
+#[test]
+fn concatenated_json_gives_the_report_of_json_lines() {
+    let options = parse_options();
+    let concatenated_options = ParseOptions {
+        multiline: Some(Multiline::ConcatenatedJson),
+        ..parse_options()
+    };
+    check(
+        (sample_options(), concatenated_layouts(), threads()),
+        |(sample_options, layouts, num_of_threads)| {
+            let lines = Corpus::generate("lines", &sample_options);
+            let concatenated = concatenated_corpus("concatenated", &sample_options, &layouts);
+
+            let expected = parser::single_thread_parser(lines.path(), &options).unwrap();
+            let single =
+                parser::single_thread_parser(concatenated.path(), &concatenated_options).unwrap();
+            for strategy in [ParseStrategy::Channel, ParseStrategy::Sharded] {
+                let report = parser::parse_file(
+                    strategy,
+                    num_of_threads,
+                    concatenated.path(),
+                    &concatenated_options,
+                )
+                .unwrap();
+                assert_same_report(&single, &report, &format!("{:?}", strategy));
+            }
+
+            assert_eq!(single.lines, expected.lines);
+            assert_eq!(single.failed_lines, 0);
+            for (log_type, expected) in &expected.log_register {
+                let actual = &single.log_register[log_type];
+                assert_eq!(actual.counter, expected.counter);
+                assert_eq!(actual.errors, expected.errors);
+            }
+            Ok(())
+        },
+    );
+}
+
+/// Path longer than `MAX_PATH` (260 characters), opened without the `\\?\` prefix.
+#[cfg(windows)]
+#[test]
+fn long_windows_paths_are_parsed() {
+    let fixture = Fixture::new("long");
+    let mut dir = fixture.dir().to_path_buf();
+    while dir.as_os_str().len() < 300 {
+        dir.push("a".repeat(50));
+    }
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("input.log");
+    let options = sample(0);
+    parser::prepare_sample_file(&options, path.to_str().unwrap());
+
+    let report = parser::multi_thread_parser_sharded(4, &path, &parse_options()).unwrap();
+    assert_eq!(report.lines, options.num_of_lines as u64);
+}
+
+/// Log files are read while the writing process still has them open (and may rename them).
+#[cfg(windows)]
+#[test]
+fn windows_files_open_for_writing_are_parsed() {
+    let options = sample(0);
+    let corpus = crlf_corpus("shared", &options);
+    let writer = OpenOptions::new().append(true).open(corpus.path()).unwrap();
+
+    let report = parser::multi_thread_parser_sharded(4, corpus.path(), &parse_options()).unwrap();
+    drop(writer);
+    assert_eq!(report.lines, options.num_of_lines as u64);
+    assert_eq!(report.failed_lines, 0);
+}
+
+#[test]
+fn auto_tuning_times_whole_lines_of_the_input_head() {
+    let options = parse_options();
+    check_cases(
+        CASES / 8,
+        (sample_options(), any::<prop::sample::Index>(), threads()),
+        |(sample_options, sample_bytes, max_threads)| {
+            let corpus = Corpus::generate("tune", &sample_options);
+            let size = fs::metadata(corpus.path()).unwrap().len();
+            let sample_bytes = sample_bytes.index(size as usize + 2) as u64;
+            let candidates = tune::candidates(max_threads);
+
+            let tuning = tune::tune(
+                ParseStrategy::Sharded,
+                corpus.path(),
+                sample_bytes,
+                &candidates,
+                &options,
+            )
+            .unwrap();
+            assert!(candidates.contains(&tuning.threads));
+            assert_eq!(tuning.timings.len(), candidates.len());
+            let head = subset::extract(corpus.path(), Subset::HeadBytes(sample_bytes), 0).unwrap();
+            assert_eq!(tuning.sample_bytes, head.bytes());
+            let content = fs::read_to_string(head.path()).unwrap();
+            assert!(content.is_empty() || content.ends_with('\n'));
+            assert!(head.bytes() < sample_bytes + 400);
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn locale_separators_keep_the_digits_of_numbers() {
+    let locales = ["C", "en_US.UTF-8", "de_DE", "fr_FR", "de_CH", "pt_BR"];
+    check(
+        (prop::sample::select(locales.to_vec()), -1e12..1e12),
+        |(locale, number)| {
+            let locale = Locale::from_name(locale).unwrap();
+            let number = format!("{:.2}", number);
+
+            let grouped = locale.group(&number);
+            let (integer, fraction) = grouped.rsplit_once(locale.decimal).unwrap();
+            let plain: String = integer
+                .split(|c| Some(c) == locale.grouping)
+                .collect::<Vec<_>>()
+                .join("");
+            assert_eq!(format!("{}.{}", plain, fraction), number);
+            // groups of three digits after the first one
+            let groups: Vec<&str> = integer
+                .trim_start_matches('-')
+                .split(|c| Some(c) == locale.grouping)
+                .collect();
+            assert!(
+                locale.grouping.is_none()
+                    || (1..=3).contains(&groups[0].len())
+                        && groups[1..].iter().all(|group| group.len() == 3),
+                "{}",
+                grouped
+            );
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn inputs_with_byte_order_marks_give_the_report_of_utf8_inputs() {
+    let options = parse_options();
+    check_cases(
+        CASES / 4,
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("utf8", &sample_options);
+            let fixture = Fixture::new("bom");
+            let content = fs::read_to_string(corpus.path()).unwrap();
+            let expected =
+                parser::multi_thread_parser_sharded(num_of_threads, corpus.path(), &options)
+                    .unwrap();
+
+            let encoded: [(Encoding, Vec<u8>); 3] = [
+                (
+                    Encoding::Utf8,
+                    [&[0xef, 0xbb, 0xbf], content.as_bytes()].concat(),
+                ),
+                (
+                    Encoding::Utf16Le,
+                    [0xff, 0xfe]
+                        .iter()
+                        .copied()
+                        .chain(content.encode_utf16().flat_map(u16::to_le_bytes))
+                        .collect(),
+                ),
+                (
+                    Encoding::Utf16Be,
+                    [0xfe, 0xff]
+                        .iter()
+                        .copied()
+                        .chain(content.encode_utf16().flat_map(u16::to_be_bytes))
+                        .collect(),
+                ),
+            ];
+            for (encoding, bytes) in encoded {
+                let encoded = fixture.bytes(&format!("{}.log", encoding), &bytes);
+
+                let input = decompress::open(&encoded).unwrap();
+                assert_eq!(input.encoding(), Some(encoding));
+                let report =
+                    parser::multi_thread_parser_sharded(num_of_threads, input.path(), &options)
+                        .unwrap();
+                assert_eq!(report, expected, "{} report", encoding);
+
+                // transcoded as it's parsed
+                let streamed = Input::open(&encoded).unwrap();
+                assert_eq!(streamed.encoding(), Some(encoding));
+                assert_eq!(streamed.file(), None);
+                let report = parser::parse_input(
+                    ParseStrategy::Sharded,
+                    num_of_threads,
+                    &streamed,
+                    &options,
+                )
+                .unwrap();
+                assert_eq!(report, expected, "{} streamed report", encoding);
+            }
+            Ok(())
+        },
+    );
+}
+
+/// Reader returning a byte at a time, like a slow pipe.
+struct ByteReader(io::Cursor<Vec<u8>>);
+
+impl Read for ByteReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(1);
+        self.0.read(&mut buf[..len])
+    }
+}
+
+#[test]
+fn transcoded_inputs_keep_characters_split_between_reads() {
+    let transcode = |encoding, bytes: Vec<u8>| {
+        let mut text = String::new();
+        Transcoder::new(encoding, 0, ByteReader(io::Cursor::new(bytes)))
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    };
+    let text = "{\"type\":\"caf\u{e9} \u{1f600}\"}\n";
+    let le: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let be: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    assert_eq!(transcode(Encoding::Utf16Le, le), text);
+    assert_eq!(transcode(Encoding::Utf16Be, be), text);
+
+    // an unpaired surrogate and an odd trailing byte are replaced
+    let unpaired = [&0xd83du16.to_le_bytes()[..], b"a\0", b"b"].concat();
+    assert_eq!(transcode(Encoding::Utf16Le, unpaired), "\u{fffd}a\u{fffd}");
+    // every Latin-1 byte is the character with its code, C1 controls included
+    assert_eq!(
+        transcode(Encoding::Latin1, b"caf\xe9 \x80".to_vec()),
+        "caf\u{e9} \u{80}"
+    );
+}
+
+#[test]
+fn formatted_durations_keep_their_length() {
+    check(0..10_000_000_000u64, |micros| {
+        let duration = Duration::from_micros(micros);
+
+        // `1h 2m 3.4s` is 3723.4 seconds
+        let human = units::format_duration(duration);
+        let secs: f64 = match human.strip_suffix("ms") {
+            Some(millis) => millis.parse::<f64>().unwrap() / 1000.0,
+            None => human
+                .split(' ')
+                .map(|part| {
+                    let (number, unit) = part.split_at(part.len() - 1);
+                    let multiplier = match unit {
+                        "h" => 3600.0,
+                        "m" => 60.0,
+                        _ => 1.0,
+                    };
+                    number.parse::<f64>().unwrap() * multiplier
+                })
+                .sum(),
+        };
+        // tenths are rounded, halfway with float error
+        assert!(
+            (secs - duration.as_secs_f64()).abs() <= 0.05 + 1e-9,
+            "{}",
+            human
+        );
+
+        let iso = units::iso_duration(duration);
+        let rest = iso.strip_prefix("PT").unwrap();
+        let (hours, rest) = rest.split_once('H').unwrap_or(("0", rest));
+        let (minutes, rest) = rest.split_once('M').unwrap_or(("0", rest));
+        let secs = rest.strip_suffix('S').unwrap_or("0");
+        let millis = hours.parse::<u128>().unwrap() * 3_600_000
+            + minutes.parse::<u128>().unwrap() * 60_000
+            + (secs.parse::<f64>().unwrap() * 1000.0).round() as u128;
+        assert_eq!(millis, duration.as_millis(), "{}", iso);
+        Ok(())
+    });
+}
+
+#[test]
+fn lines_longer_than_the_limit_are_counted_as_oversize() {
+    check(
+        (sample_options(), threads(), 50..400usize),
+        |(sample_options, num_of_threads, max_line_bytes)| {
+            let corpus = Corpus::generate("oversize", &sample_options);
+            let oversize = fs::read_to_string(corpus.path())
+                .unwrap()
+                // the limit includes the delimiter
+                .split_inclusive('\n')
+                .filter(|line| line.len() > max_line_bytes)
+                .count() as u64;
+            let options = ParseOptions {
+                max_line_bytes: Some(max_line_bytes),
+                ..parse_options()
+            };
+
+            let expected = parser::single_thread_parser(corpus.path(), &options).unwrap();
+            let dashmap =
+                parser::multi_thread_parser_dashmap(num_of_threads, corpus.path(), &options)
+                    .unwrap();
+            let sharded =
+                parser::multi_thread_parser_sharded(num_of_threads, corpus.path(), &options)
+                    .unwrap();
+
+            assert_eq!(expected.failed_lines, oversize);
+            assert_eq!(summary::oversize_lines(&expected), oversize);
+            assert_same_report(&expected, &dashmap, "dashmap");
+            assert_same_report(&expected, &sharded, "sharded");
+            Ok(())
+        },
+    );
+}
+
+/// Whether fields are quoted, taken in turn by fields.
+fn quoted_fields() -> impl Strategy<Value = Vec<bool>> {
+    prop::collection::vec(prop::bool::weighted(0.3), 1..64)
+}
+
+/// Rewrite the generated corpus as CSV with the columns (and a header), some values quoted.
+/// Quoted messages span two lines with `newlines`.
+fn to_csv(
+    corpus: &Corpus,
+    quoted: &[bool],
+    delimiter: char,
+    columns: &[&str],
+    header: bool,
+    newlines: bool,
+) {
+    let mut quoted = quoted.iter().cycle();
+    let mut csv = match header {
+        true => columns.join(&delimiter.to_string()) + "\n",
+        false => String::new(),
+    };
+    for line in fs::read_to_string(corpus.path()).unwrap().lines() {
+        let log: Value = serde_json::from_str(line).unwrap();
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|column| {
+                let value = log[column].as_str().unwrap_or_default();
+                // quoted values can hold delimiters, quotes and new lines
+                match (quoted.next().unwrap(), *column) {
+                    (true, "message") if newlines => {
+                        format!("\"{}\n{}{}\"\"\"", value, value, delimiter)
+                    }
+                    (true, "message") => format!("\"{}{}\"\"\"", value, delimiter),
+                    (true, _) => format!("\"{}\"", value),
+                    (false, _) => value.to_string(),
+                }
+            })
+            .collect();
+        csv.push_str(&fields.join(&delimiter.to_string()));
+        csv.push('\n');
+    }
+    fs::write(corpus.path(), csv).unwrap();
+}
+
+#[test]
+fn csv_inputs_give_the_counts_of_json_inputs() {
+    let options = parse_options();
+    let csv = (
+        prop::sample::select(vec![',', ';', '\t']),
+        0..4usize,
+        quoted_fields(),
+        any::<bool>(),
+    );
+    check(
+        (sample_options(), threads(), csv),
+        |(sample_options, num_of_threads, (delimiter, rotation, quoted, newlines))| {
+            let corpus = Corpus::generate("csv", &sample_options);
+            let expected = parser::single_thread_parser(corpus.path(), &options).unwrap();
+
+            let mut columns = ["id", "level", "message", "type"];
+            columns.rotate_left(rotation);
+            to_csv(&corpus, &quoted, delimiter, &columns, true, newlines);
+            let format = CsvFormat::new(&delimiter.to_string())
+                .unwrap()
+                .with_header(corpus.path())
+                .unwrap();
+            let csv_options = ParseOptions {
+                csv: Some(format),
+                ..options.clone()
+            };
+
+            let single = parser::single_thread_parser(corpus.path(), &csv_options).unwrap();
+            let sharded =
+                parser::multi_thread_parser_sharded(num_of_threads, corpus.path(), &csv_options)
+                    .unwrap();
+
+            assert_eq!(single.lines, expected.lines);
+            assert_eq!(single.failed_lines, 0);
+            for (log_type, register) in &expected.log_register {
+                let actual = &single.log_register[log_type];
+                assert_eq!(actual.counter, register.counter);
+                assert_eq!(actual.errors, register.errors);
+            }
+            assert_same_report(&single, &sharded, "sharded");
+            Ok(())
+        },
+    );
+
+    // quoted fields keep their new lines, an unterminated one runs to the end of the input
+    let fixture = Fixture::new("csv-newlines");
+    let path = fixture.path("audit.csv");
+    fs::write(
+        &path,
+        "id,message,type\r\n1,\"first\r\nsecond\",a\r\n2,plain,b\r\n3,\"open,c\r\n4,x,d\r\n",
+    )
+    .unwrap();
+    let csv_options = ParseOptions {
+        csv: Some(CsvFormat::default().with_header(&path).unwrap()),
+        ..ParseOptions::default()
+    };
+    let records = preview::records(&path, 10, &csv_options).unwrap();
+    let values: Vec<Option<&Value>> = records.iter().map(|record| record.value.as_ref()).collect();
+    assert_eq!(values.len(), 3);
+    assert_eq!(values[0].unwrap()["message"], "first\r\nsecond");
+    assert_eq!(values[1].unwrap()["type"], "b");
+    assert!(values[2].is_none());
+    let report = parser::single_thread_parser(&path, &csv_options).unwrap();
+    assert_eq!((report.lines, report.failed_lines), (3, 1));
+}
+
+#[test]
+fn previewed_inputs_have_their_format_detected() {
+    let options = ParseOptions::default();
+    let csv = (
+        prop::sample::select(vec![',', ';', '\t', '|']),
+        any::<bool>(),
+        quoted_fields(),
+    );
+    check(
+        (sample_options(), 1..50u32, 1..60usize, csv),
+        |(sample_options, num_of_lines, num_of_records, (delimiter, header, quoted))| {
+            let sample_options = SampleOptions {
+                num_of_lines,
+                ..sample_options
+            };
+            let corpus = Corpus::generate("preview", &sample_options);
+            assert_eq!(
+                preview::detect_format(corpus.path(), &options).unwrap(),
+                None
+            );
+            let records = preview::records(corpus.path(), num_of_records, &options).unwrap();
+            assert_eq!(
+                records.len(),
+                num_of_records.min(sample_options.num_of_lines as usize)
+            );
+            assert!(records.iter().all(|record| record.error.is_none()));
+
+            // the type of generated logs is a number, so the first line is a header only if given
+            let columns = ["type", "id", "level", "message"];
+            to_csv(&corpus, &quoted, delimiter, &columns, header, false);
+            let detected = preview::detect_format(corpus.path(), &options)
+                .unwrap()
+                .expect("CSV not detected");
+
+            assert_eq!(detected.delimiter, delimiter);
+            assert_eq!(detected.has_header, header);
+            let csv_options = ParseOptions {
+                csv: Some(detected),
+                ..options.clone()
+            };
+            let records = preview::records(corpus.path(), num_of_records, &csv_options).unwrap();
+            assert_eq!(
+                records.len(),
+                num_of_records.min(sample_options.num_of_lines as usize)
+            );
+            let detected = preview::detected_fields(&records);
+            assert_eq!(detected.get("type"), Some(&FieldRole::LogType));
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn last_records_are_the_newest_records_read_from_the_start() {
+    let options = ParseOptions::default();
+    let sample_options =
+        sample_options().prop_filter("no records", |options| options.num_of_lines > 0);
+    check(
+        (sample_options, 1..200usize, any::<prop::sample::Index>()),
+        |(sample_options, num_of_records, record)| {
+            let corpus = Corpus::generate("last", &sample_options);
+            let all = preview::records(corpus.path(), usize::MAX, &options).unwrap();
+            let log_type = all[record.index(all.len())].value.as_ref().unwrap()["type"].to_string();
+            let conditions = [Condition::parse(&format!("type={}", log_type)).unwrap()];
+
+            for conditions in [&[][..], &conditions[..]].iter().copied() {
+                let expected: Vec<&str> = all
+                    .iter()
+                    .rev()
+                    .filter(|record| {
+                        conditions.iter().all(|condition| {
+                            condition.matches(record.value.as_ref().unwrap().get(&condition.field))
+                        })
+                    })
+                    .take(num_of_records)
+                    .map(|record| record.line.trim_end())
+                    .collect();
+                let last =
+                    reverse::last_records(corpus.path(), num_of_records, &options, conditions)
+                        .unwrap();
+                let lines: Vec<&str> = last.iter().map(|record| record.line.as_str()).collect();
+                assert_eq!(lines, expected);
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn seek_finds_the_first_record_at_or_after_the_time() {
+    let options = ParseOptions::default();
+    // time-ordered records with repeated times and lines without a timestamp
+    let steps = prop::collection::vec(prop::option::weighted(0.9, 0..3u64), 0..3000);
+    check((steps, 0..4000u64, 0..5usize), |(steps, at, context)| {
+        let fixture = Fixture::new("seek");
+        let path = fixture.path("seek.log");
+        let mut content = String::new();
+        let mut offsets = vec![];
+        let mut time = 1_700_000_000u64;
+        for step in steps {
+            let step = match step {
+                Some(step) => step,
+                None => {
+                    content.push_str("  at stack trace line\n");
+                    continue;
+                }
+            };
+            time += step;
+            offsets.push((content.len() as u64, time));
+            content.push_str(&format!("{{\"type\":\"a\",\"timestamp\":{}}}\n", time));
+        }
+        fs::write(&path, &content).unwrap();
+        let at = 1_700_000_000 + at;
+
+        let position = seek::seek(
+            &path,
+            UNIX_EPOCH + Duration::from_secs(at),
+            "timestamp",
+            context,
+            &options,
+        )
+        .unwrap();
+        let expected = offsets
+            .iter()
+            .find(|(_, time)| *time >= at)
+            .map(|(offset, _)| *offset)
+            .unwrap_or(content.len() as u64);
+        assert_eq!(position.offset, expected);
+        assert!(position.before.len() <= context);
+        assert!(position.after.len() <= context + 1);
+        if let Some(first) = position
+            .after
+            .first()
+            .filter(|_| expected < content.len() as u64)
+        {
+            assert!(content[expected as usize..].starts_with(&first.line));
+        }
+        Ok(())
+    });
+}
+
+#[test]
+fn derived_fields_group_like_the_fields_they_are_computed_from() {
+    let record = json!({"status": "404", "path": "/api/users/1", "ms": 12.5});
+    for (expr, expected) in [
+        ("status / 100", json!(4)),
+        ("status % 100 * 2 + -1", json!(7)),
+        ("ms / 2", json!(6.25)),
+        ("split(path, \"/\", 1)", json!("api")),
+        ("upper(split(path, \"/\", -1))", json!("1")),
+        (
+            "concat(substr(path, 1, 3), \":\", round(ms))",
+            json!("api:13"),
+        ),
+        ("status / 0", Value::Null),
+        ("missing + 1", Value::Null),
+    ]
+    .iter()
+    {
+        let derived = Derived::parse(&format!("x = {}", expr)).unwrap();
+        assert_eq!(derived.expr.eval(&record), *expected, "{}", expr);
+    }
+
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("derive", &sample_options);
+            let options = ParseOptions {
+                derive: vec![Derived::parse("kind = concat(\"t\", type)").unwrap()],
+                group_by: vec!["kind".to_string()],
+                ..ParseOptions::default()
+            };
+
+            let expected =
+                parser::single_thread_parser(corpus.path(), &ParseOptions::default()).unwrap();
+            let derived =
+                parser::multi_thread_parser_sharded(num_of_threads, corpus.path(), &options)
+                    .unwrap();
+            let counters = |report: &ParseReport, prefix: &str| -> BTreeMap<String, u64> {
+                report
+                    .log_register
+                    .iter()
+                    .map(|(key, register)| (format!("{}{}", prefix, key), register.counter))
+                    .collect()
+            };
+            assert_eq!(counters(&derived, ""), counters(&expected, "t"));
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn month_name_timestamps_parse_in_their_locales() {
+    let de = [TimeLocale::German];
+    let fr_es = [TimeLocale::French, TimeLocale::Spanish];
+    for (text, locales, expected) in [
+        (
+            "14/Feb/2018:00:28:07 +0100",
+            &[][..],
+            "2018-02-13T23:28:07Z",
+        ),
+        ("Wed, 14 Feb 2018 00:28:07 GMT", &[], "2018-02-14T00:28:07Z"),
+        ("February 29 2024 12:00", &[], "2024-02-29T12:00:00Z"),
+        (
+            "14. Mär. 2018 00:28:07.250",
+            &de,
+            "2018-03-14T00:28:07.250Z",
+        ),
+        ("Okt 3 2021 10:00:00 +02:00", &de, "2021-10-03T08:00:00Z"),
+        ("1 août 2019 09:30:00", &fr_es, "2019-08-01T09:30:00Z"),
+        ("ene 31 2020 23:59:59", &fr_es, "2020-01-31T23:59:59Z"),
+    ] {
+        let expected = humantime::parse_rfc3339(expected).unwrap();
+        assert_eq!(
+            timestamp::parse_str_with(text, locales),
+            Some(expected),
+            "{}",
+            text
+        );
+    }
+    for (text, locales) in [
+        ("14. Mär. 2018 00:28:07", &[][..]),
+        ("Feb 30 2018 00:28:07", &[]),
+        ("Feb 14 2018", &[]),
+        ("Jui 14 2018 00:28:07", &fr_es),
+        ("Feb 14 2018 00:28:07 nonsense", &[]),
+    ] {
+        assert_eq!(timestamp::parse_str_with(text, locales), None, "{}", text);
+    }
+    assert!(TimeLocale::from_name("de_DE.UTF-8").is_ok());
+    assert!(TimeLocale::from_name("xx").is_err());
+
+    // syslog dates without year are never more than a day ahead
+    let now = SystemTime::now();
+    let today = timestamp::format(now);
+    for month in ["Jan", "Jun", "Dec"] {
+        let text = format!(
+            "{} 1 {}",
+            month,
+            &today["YYYY-MM-DDT".len().."YYYY-MM-DDThh:mm:ss".len()]
+        );
+        let time = timestamp::parse_str(&text).unwrap();
+        assert!(time <= now + Duration::from_secs(86_400), "{}", text);
+        assert!(time > now - Duration::from_secs(366 * 86_400), "{}", text);
+    }
+
+    let options = ParseOptions {
+        timestamp_field: Some("ts".to_string()),
+        timestamp_locales: vec![TimeLocale::German],
+        rate_window: Some(3600),
+        ..ParseOptions::default()
+    };
+    let fixture = Fixture::new("syslog");
+    let path = fixture.bytes(
+        "syslog.log",
+        b"{\"type\":\"cron\",\"ts\":\"Dez 24 2020 18:30:00\"}\n{\"type\":\"cron\",\"ts\":\"Dez 24 2020 19:10:00\"}\n",
+    );
+    let report = parser::single_thread_parser(&path, &options).unwrap();
+    let start = humantime::parse_rfc3339("2020-12-24T18:00:00Z").unwrap();
+    let start = start.duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let windows: Vec<u64> = report.log_register["cron"]
+        .windows
+        .keys()
+        .copied()
+        .collect();
+    assert_eq!(windows, vec![start, start + 3600]);
+}
+
+#[test]
+fn manual_clock_simulates_time_of_streaming_features() {
+    let start = humantime::parse_rfc3339("2020-03-01T00:00:00Z").unwrap();
+    let clock = Arc::new(ManualClock::new(start));
+    let fixture = Fixture::new("clock");
+    let path = fixture.lines(
+        "clock.log",
+        [
+            "{\"type\":\"cron\",\"ts\":\"Dec 31 23:00:00\"}",
+            "{\"type\":\"cron\",\"ts\":\"Mar 1 12:00:00\"}",
+            "{\"type\":\"cron\",\"ts\":\"Jan 1 00:00:00\"}",
+        ],
+    );
+
+    // dates without year are placed by the clock, not by the system time
+    let options = ParserOptions::builder()
+        .input(&path)
+        .timestamp_field("ts")
+        .clock(clock.clone())
+        .build()
+        .unwrap();
+    let report = run::run(&options).unwrap();
+    let register = &report.log_register["cron"];
+    let at = |text| Some(humantime::parse_rfc3339(text).unwrap());
+    assert_eq!(register.first_seen, at("2019-12-31T23:00:00Z"));
+    assert_eq!(register.last_seen, at("2020-03-01T12:00:00Z"));
+
+    // replay paced by the clock runs right away, the clock advances by the span of the records
+    let sent = Arc::new(Mutex::new(vec![]));
+    let mut budgets = BudgetTracker::new(vec![]);
+    let summary = replay::replay(
+        &path,
+        &mut RecordingSink(sent.clone()),
+        "ts",
+        2.0,
+        &mut budgets,
+        clock.as_ref(),
+    )
+    .unwrap();
+    assert_eq!(summary.sent, 3);
+    assert_eq!(sent.lock().unwrap().len(), 3);
+    // the third record is due before the second one, so it's sent right away
+    assert_eq!(
+        summary.elapsed,
+        Duration::from_secs(3_600 * (24 * 60 + 13) / 2)
+    );
+    assert_eq!(clock.elapsed(), summary.elapsed);
+}
+
+#[test]
+fn follow_mode_and_progress_run_on_the_clock() {
+    let fixture = Fixture::new("follow-clock");
+    let path = fixture.path("app.log");
+    fs::write(&path, "{\"type\":\"a\"}\n").unwrap();
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH));
+    let options = ParseOptions {
+        clock: Some(clock.clone()),
+        ..ParseOptions::default()
+    };
+
+    // a line is appended after every poll, polls and snapshots are paced by the clock only
+    let started = Instant::now();
+    let mut files = vec![FollowedFile::new(&path, true)];
+    let (mut lines, mut polls, mut snapshots) = (vec![], 0, vec![]);
+    follow::follow(
+        &mut files,
+        &options,
+        Duration::from_secs(1),
+        Duration::from_secs(5),
+        |event| {
+            match event {
+                Followed::Lines(_, appended) => lines.extend(appended),
+                Followed::Polled { snapshot } => {
+                    polls += 1;
+                    if snapshot {
+                        snapshots.push(clock.elapsed());
+                    }
+                    let mut file = OpenOptions::new().append(true).open(&path)?;
+                    writeln!(file, "{{\"type\":\"b\",\"poll\":{}}}", polls)?;
+                }
+            }
+            Ok(polls < 12)
+        },
+    )
+    .unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(clock.elapsed(), Duration::from_secs(11));
+    assert_eq!(
+        snapshots,
+        vec![Duration::from_secs(5), Duration::from_secs(10)]
+    );
+    assert_eq!(lines.len(), 12);
+    assert_eq!(lines[0], "{\"type\":\"a\"}");
+    assert_eq!(lines[11], "{\"type\":\"b\",\"poll\":11}");
+
+    // throughput and ETA are measured by the clock too
+    let handle = ProgressHandle::with_clock(100, clock.clone());
+    let counter = handle.progress().counter();
+    counter.add(50);
+    clock.advance(Duration::from_secs(10));
+    let snapshot = handle.poll();
+    assert_eq!(snapshot.elapsed, Duration::from_secs(10));
+    assert_eq!(snapshot.bytes_per_sec, 5.0);
+    assert_eq!(snapshot.eta, Some(Duration::from_secs(10)));
+    drop(counter);
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(
+        handle.progress().threads()[0].elapsed,
+        Duration::from_secs(10)
+    );
+}
+
+#[test]
+fn progress_bar_adds_up_the_counters_of_threads() {
+    let clock = Arc::new(ManualClock::new(UNIX_EPOCH));
+    let progress = Progress::new(4_000_000).with_clock(clock.clone());
+    assert_eq!(
+        progress.render(),
+        format!(
+            "[{}]   0% 0.00 B/4.00 MB, 0 lines/s, 0.0 MB/s, ETA -",
+            " ".repeat(30)
+        )
+    );
+
+    let (first, second) = (progress.counter(), progress.counter());
+    for _ in 0..3 {
+        first.add(500_000);
+    }
+    second.add(500_000);
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(progress.consumed(), (2_000_000, 4));
+    assert_eq!(
+        progress.render(),
+        format!(
+            "[{}{}]  50% 2.00 MB/4.00 MB, 2 lines/s, 1.0 MB/s, ETA 2s",
+            "=".repeat(15),
+            " ".repeat(15)
+        )
+    );
+    let lines: Vec<u64> = progress
+        .threads()
+        .iter()
+        .map(|thread| thread.lines)
+        .collect();
+    assert_eq!(lines, [3, 1]);
+
+    // chunk boundaries read twice don't go over the total
+    second.add(2_500_000);
+    assert!(progress
+        .render()
+        .starts_with(&format!("[{}] 100% 4.00 MB/4.00 MB", "=".repeat(30))));
+    assert!(progress.render().ends_with("ETA 0s"));
+}
+
+#[test]
+fn normalized_values_group_together() {
+    for (normalization, value, expected) in [
+        ("path=path-ids", "/users/123/orders", "/users/{id}/orders"),
+        (
+            "path=lower,path-ids",
+            "/Users/6F9619FF-8B86-D011-B42D-00CF4FC964FF?page=2",
+            "/users/{id}?page=2",
+        ),
+        ("path=path-ids", "/v2/users/me", "/v2/users/me"),
+        ("path=trim,lower", "  GET  ", "get"),
+    ]
+    .iter()
+    {
+        let normalization = Normalization::parse(normalization).unwrap();
+        assert_eq!(normalization.apply((*value).into()), *expected, "{}", value);
+    }
+    assert!(Normalization::parse("path=upper").is_err());
+    assert!(Normalization::parse("path").is_err());
+
+    check(
+        (sample_options(), threads()),
+        |(sample_options, num_of_threads)| {
+            let corpus = Corpus::generate("normalize", &sample_options);
+            let expected =
+                parser::single_thread_parser(corpus.path(), &ParseOptions::default()).unwrap();
+
+            // every log type but one is mapped to the same value
+            let mut log_types: Vec<&String> = expected.log_register.keys().collect();
+            log_types.sort();
+            let table = format!("{}.lookup.csv", corpus.path());
+            let lines: Vec<String> = log_types
+                .iter()
+                .skip(1)
+                .map(|log_type| format!("{},other", log_type))
+                .collect();
+            fs::write(&table, lines.join("\n")).unwrap();
+            let options = ParseOptions {
+                group_by: vec!["type".to_string()],
+                normalize: vec![Normalization::parse(&format!("type=lookup:{}", table)).unwrap()],
+                ..ParseOptions::default()
+            };
+            let normalized =
+                parser::multi_thread_parser_sharded(num_of_threads, corpus.path(), &options)
+                    .unwrap();
+
+            let counter = |log_type: &str| {
+                expected
+                    .log_register
+                    .get(log_type)
+                    .map_or(0, |register| register.counter)
+            };
+            let mut counters = BTreeMap::new();
+            if let Some(first) = log_types.first() {
+                counters.insert(first.to_string(), counter(first));
+            }
+            let other: u64 = log_types
+                .iter()
+                .skip(1)
+                .map(|log_type| counter(log_type))
+                .sum();
+            if other > 0 {
+                counters.insert("other".to_string(), other);
+            }
+            let actual: BTreeMap<String, u64> = normalized
+                .log_register
+                .iter()
+                .map(|(key, register)| (key.clone(), register.counter))
+                .collect();
+            assert_eq!(actual, counters);
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn served_records_are_grouped_by_reloaded_lookup_tables() {
+    let fixture = Fixture::new("lookup-reload");
+    let table = fixture.lines("services.csv", ["a,alpha"]);
+    let options = ParseOptions {
+        group_by: vec!["type".to_string()],
+        normalize: vec![Normalization::parse(&format!("type=lookup:{}", table)).unwrap()],
+        ..ParseOptions::default()
+    };
+    normalize::watch_lookup_tables(&options.normalize, Duration::from_millis(20));
+    let server = Arc::new(Server::bind("127.0.0.1:0", options.clone()).unwrap());
+    let url = http::Url::parse(&format!("http://{}/ingest", server.local_addr().unwrap())).unwrap();
+    let running = server.clone();
+    thread::spawn(move || running.run());
+    let post = || {
+        let response =
+            http::post(&url, "application/x-ndjson", b"{\"type\":\"a\"}\n", &[]).unwrap();
+        assert!(response.is_success(), "batch rejected");
+    };
+    let counters = || -> BTreeMap<String, u64> {
+        server
+            .report()
+            .log_register
+            .iter()
+            .map(|(key, register)| (key.clone(), register.counter))
+            .collect()
+    };
+
+    post();
+    fs::write(&table, "a,beta\n").unwrap();
+    // records posted until the watcher swaps the table are still mapped by the old one
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut posted = 1;
+    while !counters().contains_key("beta") {
+        assert!(Instant::now() < deadline, "lookup table wasn't reloaded");
+        thread::sleep(Duration::from_millis(20));
+        post();
+        posted += 1;
+    }
+    post();
+    let reloaded = counters();
+    assert_eq!(reloaded.values().sum::<u64>(), posted + 1);
+    assert!(reloaded["alpha"] >= 1, "{:?}", reloaded);
+    assert!(reloaded["beta"] >= 2, "{:?}", reloaded);
+    assert_eq!(reloaded.len(), 2, "{:?}", reloaded);
+
+    // a file which can't be loaded leaves the reloaded table in use
+    fs::write(&table, "a beta gamma\n").unwrap();
+    thread::sleep(Duration::from_millis(200));
+    let tables = normalize::lookup_tables(&options.normalize);
+    // failed by the watcher already or right now, it isn't swapped in either way
+    assert!(!tables[0].reload().unwrap_or(false));
+    post();
+    assert_eq!(counters()["beta"], reloaded["beta"] + 1);
+    assert_eq!(tables[0].current().get("a").unwrap(), "beta");
+}
+
+#[test]
+fn duplicates_are_ids_seen_before_by_any_thread() {
+    // redeliveries of random lines, anywhere in the file
+    let redeliveries = prop::collection::vec(
+        any::<(prop::sample::Index, prop::sample::Index)>(),
+        0..MAX_LINES,
+    );
+    check(
+        (
+            sample_options(),
+            redeliveries,
+            strategies(),
+            threads(),
+            any::<[bool; 2]>(),
+        ),
+        |(sample_options, redeliveries, strategy, num_of_threads, drops)| {
+            let corpus = Corpus::generate("duplicates", &sample_options);
+            let content = fs::read_to_string(corpus.path()).unwrap();
+            let mut lines: Vec<&str> = content.lines().collect();
+            for (line, at) in redeliveries.into_iter().take(lines.len()) {
+                let line = lines[line.index(lines.len())];
+                lines.insert(at.index(lines.len() + 1), line);
+            }
+            fs::write(corpus.path(), lines.join("\n") + "\n").unwrap();
+            let mut ids: Vec<String> = lines
+                .iter()
+                .map(|line| serde_json::from_str::<Value>(line).unwrap()["id"].to_string())
+                .collect();
+            ids.sort_unstable();
+            ids.dedup();
+            let expected = (lines.len() - ids.len()) as u64;
+
+            let modes = [DedupMode::Exact, DedupMode::Bloom];
+            for (mode, drop_duplicates) in modes.iter().copied().zip(drops) {
+                let options = ParseOptions {
+                    dedup_field: Some("id".to_string()),
+                    drop_duplicates,
+                    seen_ids: Some(Arc::new(SeenIds::new(mode, ids.len()))),
+                    ..ParseOptions::default()
+                };
+                let report =
+                    parser::parse_file(strategy, num_of_threads, corpus.path(), &options).unwrap();
+                let duplicates: u64 = report
+                    .log_register
+                    .values()
+                    .map(|register| register.duplicates)
+                    .sum();
+                let counter: u64 = report
+                    .log_register
+                    .values()
+                    .map(|register| register.counter)
+                    .sum();
+                match mode {
+                    DedupMode::Exact => assert_eq!(duplicates, expected),
+                    // a few new IDs are taken for duplicates
+                    DedupMode::Bloom => assert!(
+                        duplicates >= expected
+                            && duplicates <= expected + lines.len() as u64 / 20 + 1,
+                        "{} duplicates of {}",
+                        duplicates,
+                        expected
+                    ),
+                }
+                let kept = if drop_duplicates {
+                    lines.len() as u64 - duplicates
+                } else {
+                    lines.len() as u64
+                };
+                assert_eq!(counter, kept);
+            }
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn anomalies_start_at_the_spike_or_drop() {
+    let detector = AnomalyDetector {
+        trailing_windows: AnomalyDetector::DEFAULT_TRAILING_WINDOWS,
+        max_stddevs: None,
+        max_change: Some(0.8),
+    };
+    // lines within 10% of 100, ten times as many or none from the start index on
+    let lines = prop::collection::vec(90..111u64, 40);
+    check(
+        (
+            4..40usize,
+            any::<prop::sample::Index>(),
+            any::<bool>(),
+            lines,
+        ),
+        |(num_of_windows, start_idx, spike, lines)| {
+            let start_idx = 2 + start_idx.index(num_of_windows - 2);
+            let anomaly_lines: u64 = if spike { 1000 } else { 0 };
+            let mut series: Vec<WindowRate> = (0..num_of_windows)
+                .map(|idx| {
+                    let lines = match idx == start_idx {
+                        true => anomaly_lines,
+                        false => lines[idx],
+                    };
+                    WindowRate {
+                        start: idx as u64 * 60,
+                        lines,
+                        lines_per_sec: lines as f64 / 60.0,
+                        error_rate: 0.0,
+                        anomaly: None,
+                    }
+                })
+                .collect();
+
+            rate::flag_anomalies(&mut series, &detector);
+
+            let flagged: Vec<usize> = (0..num_of_windows)
+                .filter(|idx| series[*idx].anomaly.is_some())
+                .collect();
+            assert_eq!(flagged, vec![start_idx]);
+            Ok(())
+        },
+    );
+}
+
+#[test]
+fn group_keys_keep_values_of_every_field() {
+    let groups = (1..4usize).prop_flat_map(|num_of_fields| {
+        let values = prop::collection::vec(0..20u8, num_of_fields);
+        (Just(num_of_fields), prop::collection::vec(values, 1..200))
+    });
+    check(groups, |(num_of_fields, groups)| {
+        let group_keys = GroupKeys::default();
+        let groups: Vec<Vec<String>> = groups
+            .iter()
+            .map(|values| {
+                values
+                    .iter()
+                    .map(|value| format!("value-{}", value))
+                    .collect()
+            })
+            .collect();
+
+        let keys: Vec<_> = thread::scope(|scope| {
+            let threads: Vec<_> = groups
+                .chunks(50)
+                .map(|groups| {
+                    let group_keys = &group_keys;
+                    scope.spawn(move || {
+                        groups
+                            .iter()
+                            .map(|values| group_keys.key(values))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect()
+        });
+
+        for (key, values) in keys.iter().zip(&groups) {
+            let interned: Vec<String> = group_keys
+                .values(key)
+                .iter()
+                .map(|value| value.to_string())
+                .collect();
+            assert_eq!(interned, *values);
+            let name = group_keys.name(key);
+            assert_eq!(name, group::key(values));
+            assert_eq!(group::values(&name, num_of_fields), *values);
+        }
+        // every value once, however many groups it's part of
+        assert!(group_keys.len() <= 20);
+        Ok(())
+    });
+}
+
+#[test]
+fn overflowing_counters_saturate_and_are_counted() {
+    let options = SampleOptions {
+        num_of_lines: 100,
+        ..sample(0)
+    };
+    let corpus = Corpus::generate("overflow", &options);
+    let report = parser::single_thread_parser(corpus.path(), &parse_options()).unwrap();
+    assert_eq!(report.overflows(), 0);
+
+    let (log_type, mut register) = report.log_register.into_iter().next().unwrap();
+    register.counter = u64::MAX - 1;
+    register.num_of_bytes = u64::MAX;
+    let mut total = register.clone();
+    total += register;
+    assert_eq!(total.counter, u64::MAX, "{}", log_type);
+    assert_eq!(total.num_of_bytes, u64::MAX, "{}", log_type);
+    assert_eq!(total.overflows, 2, "{}", log_type);
+
+    let mut report = ParseReport::default();
+    report.log_register.insert(log_type.clone(), total.clone());
+    report
+        .log_register
+        .insert(format!("{}-copy", log_type), total);
+    assert_eq!(report.overflows(), 4);
+
+    // merged line sizes saturate too
+    let mut sizes = Stats::of(u64::MAX);
+    sizes += Stats::of(u64::MAX);
+    assert_eq!(sizes.count(), 2);
+    assert_eq!(sizes.max(), u64::MAX);
+    assert_eq!(sizes.mean(), u64::MAX as f64 / 2.0);
+}
+
+#[test]
+fn run_sums_up_reports_of_every_input() {
+    check(
+        (sample_options(), sample_options(), threads()),
+        |(first, second, num_of_threads)| {
+            let first = Corpus::generate("run_first", &first);
+            let second = Corpus::generate("run_second", &second);
+
+            let options = ParserOptions::builder()
+                .input(first.path())
+                .input(second.path())
+                .threads(num_of_threads)
+                .parse_options(parse_options())
+                .group_by("type")
+                .group_by("level")
+                .build()
+                .unwrap();
+            let report = run::run(&options).unwrap();
+
+            let mut expected =
+                parser::single_thread_parser(first.path(), options.parse_options()).unwrap();
+            expected +=
+                parser::single_thread_parser(second.path(), options.parse_options()).unwrap();
+            assert_same_report(&expected, &report, "run");
+            Ok(())
+        },
+    );
+
+    let missing = ParserOptions::builder()
+        .input("missing.log")
+        .build()
+        .unwrap();
+    assert!(matches!(run::run(&missing), Err(RunError::Open { .. })));
+    assert!(matches!(
+        ParserOptions::builder().build(),
+        Err(RunError::NoInput)
+    ));
+    assert!(matches!(
+        ParserOptions::builder()
+            .input("app.log")
+            .error_when("status")
+            .build(),
+        Err(RunError::InvalidOption(_))
+    ));
+}
@@ -0,0 +1,767 @@
+//! End-to-end tests of input and output formats against golden files in `tests/golden`
+//! (`UPDATE_GOLDEN=1 cargo test` rewrites them).
+
+use log_parser::decompress::{self, Codec, Input};
+use log_parser::parquet::{self, Column};
+use log_parser::parser::{
+    self, ParseOptions, ParseReport, SampleOptions, SampleTimestamps, Strategy,
+};
+use log_parser::pattern::LinePattern;
+use log_parser::projection::{self, Condition, Projection};
+use log_parser::quarantine::Quarantine;
+use log_parser::rollup::{self, Format, Period};
+use log_parser::scanner::{Multiline, StartPattern};
+use log_parser::sqlite::SqliteWriter;
+use log_parser::testing::{assert_golden, report_snapshot, Fixture, LogLine};
+use log_parser::validate::{self, JsonSchema};
+#[cfg(target_os = "linux")]
+use log_parser::watch::DirEvents;
+use log_parser::watch::{self, DirWatcher};
+use log_parser::{diff, group, sampling, summary, table};
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+const NUM_OF_THREADS: u8 = 3;
+
+fn golden(name: &str) -> String {
+    format!("tests/golden/{}", name)
+}
+
+/// Lines covering levels, numeric strings, missing fields and lines which can't be parsed.
+fn ndjson_lines() -> Vec<String> {
+    let mut lines = vec![];
+    for idx in 0..30 {
+        let level = ["info", "warn", "error"][idx % 3];
+        lines.push(
+            LogLine::new("http")
+                .level(level)
+                .field("duration_ms", idx * 10)
+                .field("user_id", format!("user-{}", idx % 7))
+                .field("status", 200 + (idx % 4) * 100)
+                .to_string(),
+        );
+    }
+    for idx in 0..10 {
+        lines.push(
+            LogLine::new("db")
+                .level(if idx == 0 { "FATAL" } else { "debug" })
+                .field("duration_ms", format!("{}.5", idx))
+                .field("user_id", "user-1")
+                .to_string(),
+        );
+    }
+    lines.push(LogLine::new("cron").to_string());
+    lines.push("{\"level\":\"info\"}".to_string());
+    lines.push("not json".to_string());
+    lines.push("{\"type\":\"http\",\"duration_ms\":".to_string());
+
+    lines
+}
+
+fn ndjson_options() -> ParseOptions {
+    ParseOptions {
+        numeric_fields: vec!["duration_ms".to_string(), "status".to_string()],
+        distinct_fields: vec!["user_id".to_string()],
+        ..ParseOptions::default()
+    }
+}
+
+/// Parse with every strategy, they have to agree.
+fn parse<P: AsRef<Path>>(input: P, options: &ParseOptions) -> ParseReport {
+    let input = input.as_ref();
+    let report = parser::single_thread_parser(input, options).unwrap();
+    assert_eq!(
+        report,
+        parser::multi_thread_parser_dashmap(NUM_OF_THREADS, input, options).unwrap()
+    );
+    assert_eq!(
+        report,
+        parser::multi_thread_parser_channel(NUM_OF_THREADS, input, options).unwrap()
+    );
+    assert_eq!(
+        report,
+        parser::multi_thread_parser_sharded(NUM_OF_THREADS, input, options).unwrap()
+    );
+    report
+}
+
+#[test]
+fn ndjson_report() {
+    let fixture = Fixture::new("ndjson");
+    let input = fixture.lines("input.log", ndjson_lines());
+    let options = ndjson_options();
+
+    let report = parse(&input, &options);
+
+    assert_golden(
+        golden("ndjson_report.txt"),
+        &report_snapshot(&report, &options),
+    );
+}
+
+#[test]
+fn invalid_utf8_long_and_wrong_type_lines() {
+    let fixture = Fixture::new("invalid");
+    let mut content = vec![];
+    content.extend_from_slice(b"{\"type\":\"a\"}\n{\"type\":\"\xff\"}\n");
+    content.extend_from_slice(format!("{{\"type\":\"{}\"}}\n", "x".repeat(2 << 20)).as_bytes());
+    content.extend_from_slice(b"{\"type\":\"b\"}\n{\"type\":7}\n");
+    let input = fixture.bytes("input.log", &content);
+    let options = ParseOptions::default();
+
+    let report = parse(&input, &options);
+
+    assert_golden(
+        golden("invalid_report.txt"),
+        &report_snapshot(&report, &options),
+    );
+}
+
+#[test]
+fn generated_report() {
+    let fixture = Fixture::new("generated");
+    let input = fixture.generated(
+        "input.log",
+        &SampleOptions {
+            num_of_lines: 500,
+            num_of_log_types: 4,
+            timestamps: Some(SampleTimestamps {
+                start: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+                range: Duration::from_secs(3600),
+                jitter: Duration::from_secs(0),
+            }),
+            levels: vec![("info".to_string(), 9), ("error".to_string(), 1)],
+            seed: Some(42),
+            ..SampleOptions::default()
+        },
+    );
+    let options = ParseOptions {
+        distinct_fields: vec!["level".to_string()],
+        ..ParseOptions::default()
+    };
+
+    let report = parse(&input, &options);
+
+    assert_golden(
+        golden("generated_report.txt"),
+        &report_snapshot(&report, &options),
+    );
+}
+
+/// Pretty-printed JSON records and records followed by stack traces.
+fn multiline_lines() -> (Vec<String>, Vec<String>) {
+    let mut pretty = vec![];
+    let mut traces = vec![];
+    for idx in 0..20 {
+        let line = LogLine::new(["app", "worker"][idx % 2])
+            .level(if idx % 5 == 0 { "error" } else { "info" })
+            .field("msg", "braces } and \"quotes {")
+            .field("attempts", vec![idx, idx + 1]);
+        let value: serde_json::Value = serde_json::from_str(&line.to_string()).unwrap();
+        pretty.push(serde_json::to_string_pretty(&value).unwrap());
+        traces.push(line.to_string());
+        for frame in 0..idx % 4 {
+            traces.push(format!("    at Worker.run(Worker.java:{})", frame));
+        }
+    }
+    // continuation lines of a record which can't be parsed
+    traces.push("not json".to_string());
+    traces.push("    at Worker.run(Worker.java:1)".to_string());
+
+    (pretty, traces)
+}
+
+#[test]
+fn multiline_records() {
+    let fixture = Fixture::new("multiline");
+    let (pretty, traces) = multiline_lines();
+    let json_options = ParseOptions {
+        multiline: Some(Multiline::Json),
+        distinct_fields: vec!["attempts".to_string()],
+        ..ParseOptions::default()
+    };
+    let pattern_options = ParseOptions {
+        multiline: Some(Multiline::StartPattern(StartPattern::new(r"^\S").unwrap())),
+        ..ParseOptions::default()
+    };
+
+    let json_report = parse(fixture.lines("pretty.log", pretty), &json_options);
+    let pattern_report = parse(fixture.lines("traces.log", traces), &pattern_options);
+
+    let snapshot = format!(
+        "{}\n{}",
+        report_snapshot(&json_report, &json_options),
+        report_snapshot(&pattern_report, &pattern_options)
+    );
+    assert_golden(golden("multiline_report.txt"), &snapshot);
+}
+
+#[test]
+fn pattern_fields() {
+    let fixture = Fixture::new("pattern");
+    let mut lines = vec![];
+    for idx in 0..30 {
+        let level = ["INFO", "WARN", "ERROR"][idx % 3];
+        let log_type = ["http", "db"][idx % 2];
+        lines.push(format!(
+            "2024-03-01T10:00:{:02}Z {} [{}] took {}ms",
+            idx,
+            level,
+            log_type,
+            idx * 10
+        ));
+    }
+    lines.push("no timestamp here".to_string());
+    // without `type` group
+    lines.push("2024-03-01T10:01:00Z INFO [] took 5ms".to_string());
+    let options = ParseOptions {
+        pattern: Some(
+            LinePattern::new(
+                r"^(?P<ts>\S+) (?P<level>\w+) \[(?P<type>\w+)?\] took (?P<latency_ms>\d+)ms$",
+            )
+            .unwrap(),
+        ),
+        numeric_fields: vec!["latency_ms".to_string()],
+        group_by: vec!["type".to_string(), "level".to_string()],
+        ..ParseOptions::default()
+    };
+
+    let report = parse(fixture.lines("input.log", lines), &options);
+
+    assert_golden(
+        golden("pattern_report.txt"),
+        &report_snapshot(&report, &options),
+    );
+}
+
+#[test]
+fn watched_files() {
+    let drop_dir = Fixture::new("watched");
+    let fixture = Fixture::new("watched-all");
+    let lines = ndjson_lines();
+    let (first, second) = lines.split_at(lines.len() / 2);
+    let mut watcher = DirWatcher::new(drop_dir.dir());
+
+    drop_dir.lines("a.log", first);
+    // files are ready once their size didn't change between two polls
+    assert!(watcher.poll().unwrap().is_empty());
+    assert_eq!(watcher.poll().unwrap(), vec![drop_dir.dir().join("a.log")]);
+    drop_dir.lines(".b.log.part", second);
+    assert!(watcher.poll().unwrap().is_empty());
+    assert!(watcher.poll().unwrap().is_empty());
+    fs::rename(drop_dir.path(".b.log.part"), drop_dir.path("b.log")).unwrap();
+
+    let options = ParseOptions::default();
+    let mut files = vec![];
+    let running = watch::watch(
+        NUM_OF_THREADS,
+        drop_dir.dir(),
+        &options,
+        Duration::from_millis(10),
+        |file, _| {
+            files.push(file.to_path_buf());
+            files.len() < 2
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        files,
+        vec![drop_dir.dir().join("a.log"), drop_dir.dir().join("b.log")]
+    );
+    assert_eq!(running, parse(fixture.lines("all.log", &lines), &options));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn watched_files_are_ready_once_closed_or_renamed() {
+    use std::io::Write as _;
+
+    let drop_dir = Fixture::new("watched-events");
+    let lines = ndjson_lines();
+    drop_dir.lines("a.log", &lines);
+    let mut events = DirEvents::new(drop_dir.dir()).unwrap();
+    let wait = |events: &mut DirEvents| events.wait(watch::DEBOUNCE * 5).unwrap();
+
+    // files already in the directory are ready right away
+    assert_eq!(wait(&mut events), vec![drop_dir.dir().join("a.log")]);
+
+    // a file is ready once it's closed, not while it's written
+    let mut file = fs::File::create(drop_dir.path("b.log")).unwrap();
+    writeln!(file, "{}", lines[0]).unwrap();
+    assert!(wait(&mut events).is_empty());
+    drop(file);
+    assert_eq!(wait(&mut events), vec![drop_dir.dir().join("b.log")]);
+
+    // or once it's renamed into place, hidden files and directories are skipped
+    drop_dir.lines(".c.log.part", &lines);
+    fs::create_dir(drop_dir.path("d")).unwrap();
+    assert!(wait(&mut events).is_empty());
+    fs::rename(drop_dir.path(".c.log.part"), drop_dir.path("c.log")).unwrap();
+    assert_eq!(wait(&mut events), vec![drop_dir.dir().join("c.log")]);
+    assert!(wait(&mut events).is_empty());
+}
+
+#[test]
+fn rate_windows() {
+    let fixture = Fixture::new("rates");
+    let mut lines = vec![];
+    for idx in 0..120u64 {
+        // a minute without logs
+        let secs = 1_700_000_000 + idx + if idx >= 90 { 60 } else { 0 };
+        lines.push(
+            LogLine::new(["http", "db"][idx as usize % 2])
+                .level("info")
+                .field("timestamp", secs)
+                .field("status", if idx % 6 == 0 { 503 } else { 200 })
+                .to_string(),
+        );
+    }
+    lines.push(LogLine::new("http").level("error").to_string());
+    let options = ParseOptions {
+        timestamp_field: Some("timestamp".to_string()),
+        rate_window: Some(30),
+        error_when: vec![Condition::parse("status=503").unwrap()],
+        ..ParseOptions::default()
+    };
+
+    let report = parse(fixture.lines("input.log", lines), &options);
+
+    assert_golden(
+        golden("rate_report.txt"),
+        &report_snapshot(&report, &options),
+    );
+}
+
+#[test]
+fn grouped_pivot() {
+    let fixture = Fixture::new("grouped");
+    let input = fixture.lines("input.log", ndjson_lines());
+    let options = ParseOptions {
+        group_by: vec!["type".to_string(), "level".to_string()],
+        ..ParseOptions::default()
+    };
+    let report = parse(&input, &options);
+
+    let (header, rows) = group::pivot(&report.log_register, "type", |elem| elem.counter);
+
+    assert_golden(
+        golden("grouped_pivot.md"),
+        &table::markdown_table(&header, &rows),
+    );
+}
+
+#[test]
+fn grouped_diff() {
+    let fixture = Fixture::new("diff");
+    let input_a = fixture.lines("a.log", ndjson_lines());
+    // cron is gone, auth is new and http errors doubled
+    let mut lines_b: Vec<String> = ndjson_lines()
+        .into_iter()
+        .filter(|line| !line.contains("cron"))
+        .collect();
+    lines_b.extend((0..10).map(|idx| {
+        LogLine::new("http")
+            .level("error")
+            .field("idx", idx)
+            .to_string()
+    }));
+    lines_b.push(LogLine::new("auth").level("warn").to_string());
+    let input_b = fixture.lines("b.log", lines_b);
+    let options = ParseOptions {
+        group_by: vec!["type".to_string(), "level".to_string()],
+        ..ParseOptions::default()
+    };
+
+    let diffs = diff::diff(&parse(&input_a, &options), &parse(&input_b, &options));
+
+    assert_golden(
+        golden("grouped_diff.md"),
+        &diff::markdown_table(&diffs, "group"),
+    );
+}
+
+#[test]
+fn markdown_summary() {
+    let fixture = Fixture::new("summary");
+    let input = fixture.lines("input.log", ndjson_lines());
+    let report = parse(&input, &ndjson_options());
+
+    let markdown = summary::markdown(&report, &["2 lines can't be parsed (max 1)".to_string()]);
+
+    assert_golden(golden("summary.md"), &markdown);
+}
+
+#[test]
+fn quarantine_file() {
+    let fixture = Fixture::new("quarantine");
+    let input = fixture.lines("input.log", ndjson_lines());
+    let options = ParseOptions {
+        bad_lines: Some(Quarantine::new(fixture.path("bad_lines.jsonl"))),
+        ..ParseOptions::default()
+    };
+
+    parser::single_thread_parser(&input, &options).unwrap();
+
+    let bad_lines = fs::read_to_string(fixture.path("bad_lines.jsonl")).unwrap();
+    assert_golden(golden("bad_lines.jsonl"), &fixture.normalize(&bad_lines));
+}
+
+#[test]
+fn rotated_quarantine_files() {
+    let fixture = Fixture::new("rotated");
+    let input = fixture.lines("input.log", ndjson_lines());
+    // every line fills a file
+    let options = ParseOptions {
+        bad_lines: Some(Quarantine::new(fixture.path("bad_lines.jsonl")).max_bytes(1)),
+        ..ParseOptions::default()
+    };
+
+    parser::single_thread_parser(&input, &options).unwrap();
+
+    let mut bad_lines = fs::read_to_string(fixture.path("bad_lines.jsonl")).unwrap();
+    for idx in 1.. {
+        match fs::read_to_string(fixture.path(&format!("bad_lines.{}.jsonl", idx))) {
+            Ok(rotated) => bad_lines.push_str(&rotated),
+            Err(_) => break,
+        }
+    }
+    assert_golden(golden("bad_lines.jsonl"), &fixture.normalize(&bad_lines));
+}
+
+#[test]
+fn selected_fields() {
+    let fixture = Fixture::new("select");
+    let input = fixture.lines("input.log", ndjson_lines());
+    let select = |format| {
+        let projection = Projection::new(
+            vec![
+                "type".to_string(),
+                "status".to_string(),
+                "user_id".to_string(),
+            ],
+            vec![
+                Condition::parse("level!=info").unwrap(),
+                Condition::parse("user_id=user-1").unwrap(),
+            ],
+            format,
+        );
+        let mut out = vec![];
+        let options = ParseOptions::default();
+        projection::project(
+            NUM_OF_THREADS,
+            &input,
+            &options,
+            &projection,
+            true,
+            &mut out,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    assert_golden(
+        golden("selected.jsonl"),
+        &select(projection::Format::Ndjson),
+    );
+    assert_golden(golden("selected.csv"), &select(projection::Format::Csv));
+}
+
+#[test]
+fn validation_errors() {
+    let fixture = Fixture::new("validate");
+    let input = fixture.lines("input.log", ndjson_lines());
+    let schema = JsonSchema::new(serde_json::json!({
+        "type": "object",
+        "required": ["type", "level"],
+        "properties": {
+            "type": {"enum": ["http", "db"]},
+            "level": {"$ref": "#/definitions/level"},
+            "duration_ms": {"type": "integer", "maximum": 250},
+            "status": {"type": "integer"},
+            "user_id": {"type": "string", "pattern": "^user-[0-6]$"}
+        },
+        "additionalProperties": false,
+        "definitions": {"level": {"enum": ["info", "warn", "error", "debug"]}}
+    }))
+    .unwrap();
+
+    let validation = validate::validate_file(
+        NUM_OF_THREADS,
+        &input,
+        Some(&schema),
+        100,
+        &ParseOptions::default(),
+    )
+    .unwrap();
+
+    let mut actual = format!(
+        "lines: {}, invalid lines: {}\n",
+        validation.lines, validation.invalid_lines
+    );
+    for invalid in &validation.invalid {
+        for error in &invalid.errors {
+            writeln!(
+                actual,
+                "{} (offset {}): {}",
+                invalid.line, invalid.offset, error
+            )
+            .unwrap();
+        }
+    }
+    assert_golden(golden("validation_errors.txt"), &actual);
+}
+
+/// Columns as text, one column per line.
+fn columns_snapshot(columns: &[(String, Column)]) -> String {
+    let mut snapshot = String::new();
+    for (name, column) in columns {
+        let values = match column {
+            Column::Int64(values) => format!("{:?}", values),
+            Column::Double(values) => format!("{:?}", values),
+            Column::Utf8(values) => format!("{:?}", values),
+            Column::OptionalUtf8(values) => format!("{:?}", values),
+        };
+        writeln!(snapshot, "{}: {}", name, values).unwrap();
+    }
+    snapshot
+}
+
+#[test]
+fn parquet_export() {
+    let fixture = Fixture::new("parquet");
+    let input = fixture.lines("input.log", ndjson_lines());
+    let options = ndjson_options();
+    let report = parse(&input, &options);
+    let output = fixture.path("results.parquet");
+
+    parquet::write_report(&output, &report, &options).unwrap();
+
+    let columns = parquet::read(&output).unwrap();
+    assert_golden(golden("parquet_columns.txt"), &columns_snapshot(&columns));
+}
+
+#[test]
+fn compressed_input() {
+    let fixture = Fixture::new("compressed");
+    let input = fixture.lines("input.log", ndjson_lines());
+    let options = ndjson_options();
+    let expected = parse(&input, &options);
+
+    assert_eq!(decompress::detect(&input).unwrap(), None);
+    for (codec, extension) in [
+        (Codec::Gzip, "gz"),
+        (Codec::Zstd, "zst"),
+        (Codec::Bzip2, "bz2"),
+        (Codec::Xz, "xz"),
+    ] {
+        if !codec.is_enabled() {
+            continue;
+        }
+        let compressed = format!("{}.{}", input, extension);
+        decompress::compress(codec, &input, &compressed).unwrap();
+
+        let decompressed = decompress::open(&compressed).unwrap();
+        assert_eq!(decompressed.codec(), Some(codec));
+        assert_eq!(decompressed.source(), compressed);
+        assert_eq!(parse(decompressed.path(), &options), expected);
+
+        let path = decompressed.path().to_path_buf();
+        drop(decompressed);
+        assert!(fs::metadata(path).is_err(), "decompressed copy is removed");
+
+        // parsed straight from the decoder
+        let streamed = Input::open(&compressed).unwrap();
+        assert_eq!(streamed.codec(), Some(codec));
+        assert_eq!(streamed.file(), None);
+        for strategy in [Strategy::Single, Strategy::Dashmap] {
+            let report =
+                parser::parse_input(strategy, NUM_OF_THREADS, &streamed, &options).unwrap();
+            assert_eq!(report, expected);
+        }
+        let records = parser::stream_input(NUM_OF_THREADS, &streamed, &options)
+            .unwrap()
+            .filter(Result::is_ok)
+            .count() as u64;
+        assert_eq!(records, expected.lines - expected.failed_lines);
+
+        // the decoder fails once it gets to the end of the truncated content
+        let bytes = fs::read(&compressed).unwrap();
+        let truncated = fixture.bytes(
+            &format!("truncated.log.{}", extension),
+            &bytes[..bytes.len() / 2],
+        );
+        let err = Input::open(&truncated)
+            .and_then(|input| {
+                parser::parse_input(Strategy::Single, NUM_OF_THREADS, &input, &options)
+            })
+            .unwrap_err();
+        assert!(
+            err.to_string().contains(&format!(
+                "Can't decompress {} ({})",
+                truncated,
+                codec.feature()
+            )),
+            "{}",
+            err
+        );
+    }
+}
+
+#[test]
+fn sqlite_export() {
+    let fixture = Fixture::new("sqlite");
+    let input = fixture.lines("input.log", ndjson_lines());
+    let options = ndjson_options();
+    let report = parse(&input, &options);
+    let output = fixture.path("results.db");
+
+    // two runs are appended to the same database
+    for _ in 0..2 {
+        let mut writer = SqliteWriter::open(&output).unwrap();
+        writer.write_report(&input, &report, &options).unwrap();
+        for record in parser::stream(NUM_OF_THREADS, &input, &options)
+            .unwrap()
+            .flatten()
+        {
+            writer.write_record(&record, &options).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    let rows = sqlite_rows(
+        &output,
+        &[
+            "SELECT id, lines, failed_lines FROM runs ORDER BY id",
+            "SELECT * FROM log_types ORDER BY run_id, log_type",
+            "SELECT * FROM fields ORDER BY run_id, log_type, field",
+            "SELECT run_id, offset, log_type, level, num_of_bytes, fields FROM records WHERE run_id = 1 ORDER BY offset",
+        ],
+    );
+    assert_golden(golden("sqlite_tables.txt"), &rows);
+}
+
+#[test]
+fn sqlite_export_of_non_finite_sums() {
+    let fixture = Fixture::new("sqlite-sums");
+    let lines = ["up", "down", "unknown"]
+        .iter()
+        .map(|log_type| LogLine::new(log_type).field("bytes", 1));
+    let input = fixture.lines("input.log", lines);
+    let options = ParseOptions {
+        numeric_fields: vec!["bytes".to_string()],
+        ..ParseOptions::default()
+    };
+    // parsed sums saturate, reports of other sources (e.g. merged states) can be out of range
+    let mut report = parse(&input, &options);
+    for (log_type, sum) in [
+        ("up", f64::INFINITY),
+        ("down", f64::NEG_INFINITY),
+        ("unknown", f64::NAN),
+    ] {
+        report.log_register.get_mut(log_type).unwrap().fields[0].sum = sum;
+    }
+    let output = fixture.path("results.db");
+
+    let mut writer = SqliteWriter::open(&output).unwrap();
+    writer.write_report(&input, &report, &options).unwrap();
+    writer.finish().unwrap();
+
+    let rows = sqlite_rows(
+        &output,
+        &["SELECT log_type, field, sum IS NULL, sum, count FROM fields ORDER BY log_type"],
+    );
+    assert_golden(golden("sqlite_sums.txt"), &rows);
+}
+
+/// Rows of the queries like the `sqlite3` shell lists them: values separated by `|`, NULL as an
+/// empty value and reals converted to text by SQLite.
+fn sqlite_rows(path: &str, queries: &[&str]) -> String {
+    let connection = Connection::open(path).unwrap();
+    let mut rows = String::new();
+    for query in queries {
+        let mut statement = connection.prepare(query).unwrap();
+        let columns = statement.column_count();
+        let mut result = statement.query([]).unwrap();
+        while let Some(row) = result.next().unwrap() {
+            let values: Vec<String> = (0..columns)
+                .map(|idx| match row.get_ref(idx).unwrap() {
+                    ValueRef::Null => String::new(),
+                    ValueRef::Integer(value) => value.to_string(),
+                    ValueRef::Real(value) => connection
+                        .query_row("SELECT CAST(?1 AS TEXT)", [value], |row| row.get(0))
+                        .unwrap(),
+                    ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned(),
+                    ValueRef::Blob(_) => unreachable!("No blobs are written"),
+                })
+                .collect();
+            writeln!(rows, "{}", values.join("|")).unwrap();
+        }
+    }
+    rows
+}
+
+/// Daily Parquet exports with volume growing every day.
+fn daily_exports(fixture: &Fixture) {
+    let options = ParseOptions::default();
+    for day in 1..=9 {
+        let mut lines = vec![];
+        for idx in 0..(10 * day) {
+            lines.push(LogLine::new("http").field("idx", idx).to_string());
+        }
+        for idx in 0..20 {
+            let level = if idx < day { "error" } else { "info" };
+            lines.push(LogLine::new("db").level(level).to_string());
+        }
+        let input = fixture.lines(&format!("day-{}.log", day), lines);
+        let report = parse(&input, &options);
+        let output = fixture.path(&format!("2024-04-{:02}.parquet", 25 + day));
+        parquet::write_report(output, &report, &options).unwrap();
+    }
+}
+
+#[test]
+fn rollup_reports() {
+    let fixture = Fixture::new("rollup");
+    daily_exports(&fixture);
+    let exports = rollup::load_dir(fixture.dir()).unwrap();
+
+    let weekly = rollup::rollup(&exports, Period::Week);
+    let monthly = rollup::rollup(&exports, Period::Month);
+
+    assert_golden(
+        golden("rollup_weekly.md"),
+        &rollup::render(&weekly, Format::Markdown),
+    );
+    assert_golden(
+        golden("rollup_monthly.html"),
+        &rollup::render(&monthly, Format::Html),
+    );
+}
+
+#[test]
+fn sampling_policy() {
+    let fixture = Fixture::new("sampling");
+    let input = fixture.lines("input.log", ndjson_lines());
+    let report = parse(&input, &ParseOptions::default());
+
+    // rates are in the order of the given log types
+    let mut log_types: Vec<_> = report.log_register.iter().collect();
+    log_types.sort_by_key(|(log_type, _)| *log_type);
+    let policy = sampling::recommend(
+        log_types
+            .into_iter()
+            .map(|(log_type, elem)| (log_type.as_str(), elem)),
+        Duration::from_secs(60),
+        2 * 1024 * 1024,
+    );
+
+    assert_golden(
+        golden("sampling_policy.json"),
+        &serde_json::to_string_pretty(&policy).unwrap(),
+    );
+}